@@ -0,0 +1,208 @@
+//! Files inside of zip and tar archives. A member of an archive is addressed in
+//! the registry as `<archive>!/<member>` (e.g., `photos.zip!/2021/img.jpg`).
+//! Extended attributes cannot be set on a member, so members are only tagged
+//! in the registry
+
+use anyhow::{Context, Result};
+use colored::{Color, Colorize};
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::util::fmt_path;
+
+/// Separates the path of the archive from the path of the member
+pub(crate) const MEMBER_SEPARATOR: &str = "!/";
+
+/// Formats of archives that can be looked inside of
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+}
+
+impl ArchiveKind {
+    /// The format of the archive at `path`, based on its extension
+    fn of(path: &Path) -> Option<Self> {
+        match path
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(str::to_ascii_lowercase)
+            .as_deref()
+        {
+            Some("zip") => Some(Self::Zip),
+            Some("tar") => Some(Self::Tar),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the file at `path` is an archive that can be looked inside of
+pub(crate) fn is_archive(path: &Path) -> bool {
+    ArchiveKind::of(path).is_some()
+}
+
+/// Path of a `member` of an `archive` in the registry
+pub(crate) fn member_path(archive: &Path, member: &str) -> PathBuf {
+    let mut path = archive.as_os_str().to_os_string();
+    path.push(MEMBER_SEPARATOR);
+    path.push(member);
+    PathBuf::from(path)
+}
+
+/// Split the path of a member into the path of the archive and the member. The
+/// path is split at the first separator that follows an archive's extension
+pub(crate) fn split_member(path: &Path) -> Option<(PathBuf, String)> {
+    let path = path.to_str()?;
+    path.match_indices(MEMBER_SEPARATOR)
+        .map(|(idx, _)| (&path[..idx], &path[idx + MEMBER_SEPARATOR.len()..]))
+        .find(|(archive, member)| !member.is_empty() && is_archive(Path::new(archive)))
+        .map(|(archive, member)| (PathBuf::from(archive), member.to_owned()))
+}
+
+/// Paths of the files inside of the `archive`
+pub(crate) fn list_members(archive: &Path) -> Result<Vec<String>> {
+    let file = File::open(archive)
+        .with_context(|| format!("unable to open archive: {}", archive.display()))?;
+    let mut members = Vec::new();
+
+    match ArchiveKind::of(archive) {
+        Some(ArchiveKind::Zip) => {
+            let mut zip = zip::ZipArchive::new(file).context("failed to read zip archive")?;
+            for idx in 0..zip.len() {
+                let member = zip.by_index(idx).context("failed to read zip archive")?;
+                if member.is_file() {
+                    members.push(member.name().to_owned());
+                }
+            }
+        },
+        Some(ArchiveKind::Tar) =>
+            for member in tar::Archive::new(file)
+                .entries()
+                .context("failed to read tar archive")?
+            {
+                let member = member.context("failed to read tar archive")?;
+                if member.header().entry_type().is_file() {
+                    members.push(member.path()?.to_string_lossy().into_owned());
+                }
+            },
+        None => {},
+    }
+
+    Ok(members)
+}
+
+/// Read the content of the `members` of the `archive`, keyed by member. A tar
+/// archive cannot be seeked into, so it is read once for all of the members
+/// instead of once for each. Members that are not in the archive are left out
+pub(crate) fn read_members(
+    archive: &Path,
+    members: &[String],
+) -> Result<BTreeMap<String, Vec<u8>>> {
+    let file = File::open(archive)
+        .with_context(|| format!("unable to open archive: {}", archive.display()))?;
+    let mut contents = BTreeMap::new();
+
+    match ArchiveKind::of(archive) {
+        Some(ArchiveKind::Zip) => {
+            let mut zip = zip::ZipArchive::new(file).context("failed to read zip archive")?;
+            for member in members {
+                if let Ok(mut entry) = zip.by_name(member) {
+                    let mut data = Vec::new();
+                    entry.read_to_end(&mut data)?;
+                    contents.insert(member.clone(), data);
+                }
+            }
+        },
+        Some(ArchiveKind::Tar) =>
+            for entry in tar::Archive::new(file)
+                .entries()
+                .context("failed to read tar archive")?
+            {
+                let mut entry = entry.context("failed to read tar archive")?;
+                let path = entry.path()?.to_string_lossy().into_owned();
+                if members.contains(&path) && !contents.contains_key(&path) {
+                    let mut data = Vec::new();
+                    entry.read_to_end(&mut data)?;
+                    contents.insert(path, data);
+                }
+            },
+        None => return Err(anyhow::anyhow!("not an archive: {}", archive.display())),
+    }
+
+    Ok(contents)
+}
+
+/// Format the path of a member, marking where the path of the archive ends.
+/// Returns `None` if the `path` is not a member of an archive
+pub(crate) fn fmt_member(path: &Path, base_color: Color, ls_colors: bool) -> Option<String> {
+    let (archive, member) = split_member(path)?;
+    Some(format!(
+        "{}{}{}",
+        fmt_path(archive, base_color, ls_colors),
+        MEMBER_SEPARATOR.magenta().bold(),
+        member.italic()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn splits_member_paths() {
+        let path = member_path(Path::new("/tmp/photos.zip"), "2021/img.jpg");
+        assert_eq!(path, PathBuf::from("/tmp/photos.zip!/2021/img.jpg"));
+        assert_eq!(
+            split_member(&path),
+            Some((PathBuf::from("/tmp/photos.zip"), "2021/img.jpg".to_owned()))
+        );
+
+        assert_eq!(split_member(Path::new("/tmp/not!/an/archive")), None);
+        assert_eq!(split_member(Path::new("/tmp/photos.zip")), None);
+        assert_eq!(split_member(Path::new("/tmp/photos.zip!/")), None);
+    }
+
+    #[test]
+    fn reads_zip_and_tar_members() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        let zip_path = dir.path().join("photos.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&zip_path)?);
+        zip.add_directory("2021/", zip::write::FileOptions::default())?;
+        zip.start_file("2021/img.jpg", zip::write::FileOptions::default())?;
+        zip.write_all(b"zipped")?;
+        zip.finish()?;
+
+        let tar_path = dir.path().join("photos.tar");
+        let mut tar = tar::Builder::new(File::create(&tar_path)?);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(6);
+        header.set_cksum();
+        tar.append_data(&mut header, "2021/img.jpg", &b"tarred"[..])?;
+        tar.finish()?;
+
+        assert_eq!(list_members(&zip_path)?, vec!["2021/img.jpg"]);
+        assert_eq!(list_members(&tar_path)?, vec!["2021/img.jpg"]);
+        let members = vec!["2021/img.jpg".to_owned(), "missing.jpg".to_owned()];
+        let zipped = read_members(&zip_path, &members)?;
+        let tarred = read_members(&tar_path, &members)?;
+        assert_eq!(
+            zipped.get("2021/img.jpg").map(Vec::as_slice),
+            Some(&b"zipped"[..])
+        );
+        assert_eq!(
+            tarred.get("2021/img.jpg").map(Vec::as_slice),
+            Some(&b"tarred"[..])
+        );
+        assert!(!zipped.contains_key("missing.jpg"));
+        assert!(!tarred.contains_key("missing.jpg"));
+
+        Ok(())
+    }
+}
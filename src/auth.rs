@@ -0,0 +1,69 @@
+//! Tokens of the registry server (`wutag serve`) that are kept in the keyring
+//! of the OS (secret-service on Linux, the keychain on macOS). The server keeps
+//! the hashes of the tokens it accepts in one entry, keyed by a name for each
+//! client. A client keeps the token for each server it uses in an entry named
+//! after the server's `host:port`
+
+use anyhow::{Context, Result};
+use keyring::Entry;
+use std::collections::BTreeMap;
+
+/// Service of the keyring entries
+const SERVICE: &str = "wutag";
+
+/// Keyring entry holding the hashes of the tokens the server accepts
+const SERVER_ENTRY: &str = "serve-tokens";
+
+/// Hashes of the tokens the server accepts, keyed by the name of each one
+pub(crate) type Tokens = BTreeMap<String, String>;
+
+/// The hashes of the tokens the server accepts
+pub(crate) fn server_tokens() -> Result<Tokens> {
+    match Entry::new(SERVICE, SERVER_ENTRY).get_password() {
+        Ok(json) => serde_json::from_str(&json).context("the tokens in the keyring are invalid"),
+        Err(keyring::Error::NoEntry) => Ok(Tokens::new()),
+        Err(e) => Err(e).context("failed to read the tokens from the keyring"),
+    }
+}
+
+/// Replace the hashes of the tokens the server accepts
+pub(crate) fn save_server_tokens(tokens: &Tokens) -> Result<()> {
+    let entry = Entry::new(SERVICE, SERVER_ENTRY);
+    if tokens.is_empty() {
+        return match entry.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e).context("failed to remove the tokens from the keyring"),
+        };
+    }
+
+    entry
+        .set_password(&serde_json::to_string(tokens)?)
+        .context("failed to save the tokens to the keyring")
+}
+
+/// The token that is sent to the server at `host`. The keyring not being
+/// available is the same as not having a token, since the server may not need
+/// one
+pub(crate) fn client_token(host: &str) -> Option<String> {
+    Entry::new(SERVICE, host)
+        .get_password()
+        .map_err(|e| log::debug!("no token for {}: {}", host, e))
+        .ok()
+}
+
+/// Save the `token` that is sent to the server at `host`
+pub(crate) fn login(host: &str, token: &str) -> Result<()> {
+    Entry::new(SERVICE, host)
+        .set_password(token)
+        .context("failed to save the token to the keyring")
+}
+
+/// Remove the token that is sent to the server at `host`, returning whether
+/// there was one
+pub(crate) fn logout(host: &str) -> Result<bool> {
+    match Entry::new(SERVICE, host).delete_password() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(e).context("failed to remove the token from the keyring"),
+    }
+}
@@ -94,10 +94,10 @@ _wutag_tags() {
     [[ $PREFIX = -* ]] && return 1
     integer ret=1
     local -a tags; wtags=(
-        ${(@f)$(_call_program commands wutag -g list -r tags -1cu)}
+        ${(@f)$(_call_program commands wutag complete tags --mru)}
     )
 
-    _describe -t wtags 'tags' wtags && ret=0
+    _describe -V -t wtags 'tags' wtags && ret=0
     return ret
 }"#,
     ),
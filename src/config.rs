@@ -8,19 +8,34 @@ use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use std::{
     borrow::Cow,
+    collections::BTreeMap,
     env,
     ffi::OsString,
-    fs,
+    fmt, fs,
     io::Write,
     path::{Path, PathBuf},
     str::FromStr,
 };
 use tui::layout::Alignment;
 
-use crate::{ui::event::Key, wutag_fatal};
-use wutag_core::color::TuiColor;
+use crate::{
+    glob::GlobEngine,
+    hashing::HashAlgorithm,
+    ui::{event::Key, preview::ImageProtocol},
+    wutag_fatal,
+};
+use wutag_core::{color::TuiColor, XattrLayout};
+
+pub(crate) const CONFIG_FILE: &str = "wutag.yml";
+const HISTORY_FILE: &str = "command.history";
+/// Name of the file in the configuration directory holding the layout of the
+/// TUI that was last chosen with its keybindings
+const LAYOUT_FILE: &str = "ui-layout.yml";
+pub(crate) const POLICY_FILE: &str = ".wutag.toml";
 
-const CONFIG_FILE: &str = "wutag.yml";
+/// Options of each subcommand that are used when they are not given, keyed by
+/// the name of the subcommand and then the long name of the option
+pub(crate) type Defaults = IndexMap<String, IndexMap<String, serde_yaml::Value>>;
 
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 #[serde(rename_all = "snake_case", default)]
@@ -28,20 +43,140 @@ pub(crate) struct Config {
     // TODO: Perhaps add these to a field of their own like cli or global
     /// Max depth a regex/glob with traverse
     #[serde(alias = "max-depth")]
-    pub(crate) max_depth:    Option<usize>,
+    pub(crate) max_depth:          Option<usize>,
     /// Base color that paths are displayed
     #[serde(alias = "base-color")]
-    pub(crate) base_color:   Option<String>,
+    pub(crate) base_color:         Option<String>,
     /// Border color used to display tags with border option
     #[serde(alias = "border-color")]
-    pub(crate) border_color: Option<String>,
+    pub(crate) border_color:       Option<String>,
+    /// Style of the part of a path or tag that matched a search, such as
+    /// `underline` (default), `bold red`, or `reverse #FF5813`. Use `none` to
+    /// not highlight anything
+    pub(crate) highlight:          Option<String>,
     /// Array of colors to use as tags
-    pub(crate) colors:       Option<Vec<String>>,
+    pub(crate) colors:             Option<Vec<String>>,
     #[serde(alias = "ignore")]
     /// Array of file patterns to ignore tagging
-    pub(crate) ignores:      Option<Vec<String>>,
+    pub(crate) ignores:            Option<Vec<String>>,
     /// Format the file is in when using `view` subcommand
-    pub(crate) format:       Option<String>,
+    pub(crate) format:             Option<String>,
+    /// Tags that are set on every file when using the `set` subcommand
+    #[serde(alias = "default-tags")]
+    pub(crate) default_tags:       Option<Vec<String>>,
+    /// Tags with a value that are set on every file when using the `set`
+    /// subcommand. The value `now()` is expanded to the current date
+    #[serde(alias = "default-values")]
+    pub(crate) default_values:     Option<IndexMap<String, String>>,
+    /// Tag that replaces a tag whose `expires=<date>` value has passed when
+    /// using the `expire` subcommand. If not set, the tag is only removed
+    #[serde(alias = "expire-replacement")]
+    pub(crate) expire_replacement: Option<String>,
+    /// Groups of tags that are mutually exclusive. Setting one tag of a group
+    /// removes the others in the group from the file
+    #[serde(alias = "exclusive-groups")]
+    pub(crate) exclusive_groups:   Option<IndexMap<String, Vec<String>>>,
+    /// Named registries that are searched with `search --all-registries`
+    pub(crate) registries:         Option<IndexMap<String, PathBuf>>,
+    /// Registry to use instead of the default. This can be the URL of a
+    /// registry shared with `wutag serve` (e.g., `http://host:7373`)
+    pub(crate) registry:           Option<String>,
+    /// Registries of filesystems, by their UUID. A registry is used for the
+    /// files of its filesystem instead of `registry` and the default, and a
+    /// relative path is relative to the mount point, so that a removable drive
+    /// can carry its own. The ones that are mounted are searched with `search
+    /// --all-registries`
+    #[serde(alias = "filesystem-registries")]
+    pub(crate) filesystem_registries: Option<IndexMap<String, PathBuf>>,
+    /// Places that the same share is mounted at on different machines (e.g.,
+    /// `["/mnt/nas/"]`). Paths under them are stored without the prefix and
+    /// read back under the one mounted here, so that registries created on
+    /// different mounts of the share can be used together
+    #[serde(alias = "strip-prefix")]
+    pub(crate) strip_prefix:       Option<Vec<PathBuf>>,
+    /// Number of snapshots created with `snapshot create` that are kept. The
+    /// oldest are removed once there are more
+    #[serde(alias = "max-snapshots")]
+    pub(crate) max_snapshots:      Option<usize>,
+    /// Number of tags a file can have before `lint --quota-check` warns about
+    /// it (default: 32)
+    #[serde(alias = "max-tags-per-file")]
+    pub(crate) max_tags_per_file:  Option<usize>,
+    /// Number of files a tag can be on before `lint --quota-check` warns about
+    /// it (default: 10000)
+    #[serde(alias = "max-files-per-tag")]
+    pub(crate) max_files_per_tag:  Option<usize>,
+    /// Number of days after which a use of a tag counts for half as much when
+    /// ordering tags with `complete tags --mru` (default: 7)
+    #[serde(alias = "mru-half-life")]
+    pub(crate) mru_half_life:      Option<f64>,
+    /// Algorithm used to hash the content of files: `blake3` (default), `xxh3`,
+    /// or `sha256`
+    #[serde(alias = "hash-algorithm")]
+    pub(crate) hash_algorithm:     Option<HashAlgorithm>,
+    /// Only hash the start and end of files along with their size (same as
+    /// `--quick-hash`)
+    #[serde(alias = "quick-hash")]
+    pub(crate) quick_hash:         bool,
+    /// Number of KiB hashed at both the start and end of a file with a quick
+    /// hash (default: 64)
+    #[serde(alias = "quick-hash-size")]
+    pub(crate) quick_hash_size:    Option<u64>,
+    /// Icons (e.g., nerd-font glyphs) displayed before the name of a tag. An
+    /// icon set with `edit --icon` takes precedence
+    pub(crate) icons:              Option<IndexMap<String, String>>,
+    /// Do not display the icons of tags (same as `--no-icons`)
+    #[serde(alias = "no-icons")]
+    pub(crate) no_icons:           bool,
+    /// Keep tags in a namespace of the current user (same as `--per-user`)
+    #[serde(alias = "per-user")]
+    pub(crate) per_user:           bool,
+    /// Use each path of a file that is reached through several paths (e.g.,
+    /// symlinks or hard links), instead of only the first one (same as
+    /// `--show-duplicates`)
+    #[serde(alias = "show-duplicates")]
+    pub(crate) show_duplicates:    bool,
+    /// Walk into symlinked directories and read and write the tags of a
+    /// symlink on its target instead of on the symlink (default: false). `set`
+    /// and `search` can override it with `--follow` or `--no-follow`
+    #[serde(alias = "follow-symlinks")]
+    pub(crate) follow_symlinks:    Option<bool>,
+    /// Rules glob patterns are matched with: `globset` (default), where `*`
+    /// also matches `/`, or `literal-separator`, where only `**` crosses
+    /// directories
+    #[serde(alias = "glob-engine")]
+    pub(crate) glob_engine:        Option<GlobEngine>,
+    /// Read each tag back after `set` writes it, to detect filesystems that
+    /// silently drop extended attributes
+    #[serde(alias = "verify-writes")]
+    pub(crate) verify_writes:      bool,
+    /// Layout of the extended attributes that tags are written in: `legacy`
+    /// (default), with an attribute per tag, `single`, with every tag of a
+    /// file in one attribute, or `auto`, which keeps the layout of files that
+    /// already have legacy tags. Tags are read from both
+    #[serde(alias = "xattr-layout")]
+    pub(crate) xattr_layout:       Option<XattrLayout>,
+    /// Warn when `set` creates a tag that only differs in number from an
+    /// existing one (e.g., `photos` and `photo`), and offer to use that one
+    #[serde(alias = "check-plurals")]
+    pub(crate) check_plurals:      bool,
+    /// Language of the error messages, which are read from
+    /// `messages/<language>.yml` in the configuration directory (default: the
+    /// language of the locale)
+    pub(crate) language:           Option<String>,
+    /// Order that files and tags are listed in: `name` (default), `count`, or
+    /// `registry`
+    #[serde(alias = "default-sort")]
+    pub(crate) default_sort:       Option<SortOrder>,
+    /// Options that are used for each subcommand when they are not given on
+    /// the command line (e.g., `search: { raw: true }`)
+    pub(crate) defaults:           Option<Defaults>,
+    /// Tags that the clients of `wutag serve` cannot change, or that only
+    /// some of them can
+    pub(crate) serve:              ServeConfig,
+    /// Changes made by the service that maintains the registry (`wutag service
+    /// install`) that a desktop notification is sent for
+    pub(crate) notify:             NotifyConfig,
 
     /// Configuration dealing with keys
     #[cfg(feature = "ui")]
@@ -59,6 +194,43 @@ pub(crate) struct Config {
     pub(crate) encryption: EncryptConfig,
 }
 
+/// Tagging policy of a directory, which applies to the directory the policy
+/// file is found in and all of its subdirectories
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case", default)]
+pub(crate) struct DirPolicy {
+    /// Tags that every file is required to have
+    pub(crate) require: Vec<String>,
+}
+
+/// Access control of the tags of a registry shared with `wutag serve`, which
+/// is checked each time a client sends the registry back
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case", default)]
+pub(crate) struct ServeConfig {
+    /// Tags that no client can add to or remove from files, or otherwise change
+    #[serde(alias = "read-only-tags")]
+    pub(crate) read_only_tags: Vec<String>,
+    /// Tags that only the tokens with the given names (see `wutag auth`) can
+    /// change
+    #[serde(alias = "tag-writers")]
+    pub(crate) tag_writers:    BTreeMap<String, Vec<String>>,
+}
+
+/// Kinds of change that the service that maintains the registry sends a
+/// desktop notification for. No notification is sent by default
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case", default)]
+pub(crate) struct NotifyConfig {
+    /// Tags that were removed (or replaced) because they expired
+    pub(crate) expired:  bool,
+    /// Files that changed since they were tagged, whose entries were updated
+    pub(crate) modified: bool,
+    /// Files that no longer exist (e.g., they were moved), which `repair
+    /// --remove` removes from the registry
+    pub(crate) missing:  bool,
+}
+
 /// Encryption section of configuration file
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 #[serde(rename_all = "snake_case", default)]
@@ -68,11 +240,46 @@ pub(crate) struct EncryptConfig {
     pub(crate) public_key: Option<String>,
     /// Whether the database/yaml file should actually be encrypted
     #[serde(alias = "to-encrypt")]
-    pub(crate) to_encrypt: bool,
-    // TODO: Check and make sure works
+    pub(crate) to_encrypt:    bool,
     /// Use a `TTY` to ask for password input
-    #[serde(alias = "TTY")]
-    pub(crate) tty:        bool,
+    #[serde(alias = "TTY", alias = "gpg_tty", alias = "gpg-tty")]
+    pub(crate) tty:           bool,
+    /// How `gpg-agent` asks for the passphrase of a key
+    #[serde(alias = "pinentry-mode")]
+    pub(crate) pinentry_mode: Option<PinentryMode>,
+}
+
+/// How `gpg-agent` asks for the passphrase of a key (see `gpg
+/// --pinentry-mode`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PinentryMode {
+    /// Whatever `gpg-agent` is configured to do
+    Default,
+    /// Always run the pinentry program
+    Ask,
+    /// Never ask, as if the pinentry program was cancelled
+    Cancel,
+    /// Never ask, and fail if a passphrase is needed
+    Error,
+    /// Give the passphrase through wutag instead of a pinentry program, which
+    /// is read from `WUTAG_PASSPHRASE_FD`
+    Loopback,
+}
+
+impl FromStr for PinentryMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().trim() {
+            "default" => Ok(Self::Default),
+            "ask" => Ok(Self::Ask),
+            "cancel" => Ok(Self::Cancel),
+            "error" => Ok(Self::Error),
+            "loopback" => Ok(Self::Loopback),
+            _ => Err(format!("invalid pinentry mode: {}", s)),
+        }
+    }
 }
 
 /// UI general configuration
@@ -82,6 +289,10 @@ pub(crate) struct UiConfig {
     /// Whether the UI is colored
     #[serde(alias = "colored-ui")]
     pub(crate) colored_ui:           bool,
+    /// Whether to use a theme of basic colors with a high contrast instead of
+    /// `theme`, without anything that blinks or flashes
+    #[serde(alias = "high-contrast")]
+    pub(crate) high_contrast:        bool,
     // Whether the list should wrap back around to opposite side when reaching end
     pub(crate) looping:              bool,
     /// Refresh rate of application
@@ -96,6 +307,15 @@ pub(crate) struct UiConfig {
     /// Height of preview window (out of 100)
     #[serde(alias = "preview-height")]
     pub(crate) preview_height:       u16,
+    /// Graphics protocol that images are drawn with in the preview
+    #[serde(alias = "image-protocol")]
+    pub(crate) image_protocol:       ImageProtocol,
+    /// Order of the rows of the table
+    #[serde(alias = "sort-by")]
+    pub(crate) sort_by:              TableSort,
+    /// Width of the tags column (out of 100), or 0 to fit it to the tags
+    #[serde(alias = "tags-width")]
+    pub(crate) tags_width:           u16,
     /// Whether history should be enabled
     pub(crate) history:              bool,
     #[serde(alias = "history-filepath")]
@@ -156,6 +376,9 @@ pub(crate) struct UiConfig {
     /// Underline header
     #[serde(alias = "header-underline")]
     pub(crate) header_underline: bool,
+
+    /// Colors of the parts of the TUI
+    pub(crate) theme: UiTheme,
 }
 
 /// UI Key configuration
@@ -167,6 +390,9 @@ pub(crate) struct KeyConfig {
     pub(crate) help:    Key,
     pub(crate) refresh: Key,
     pub(crate) preview: Key,
+    pub(crate) details: Key,
+    pub(crate) sort:    Key,
+    pub(crate) palette: Key,
 
     // == Movement ==
     pub(crate) up:           Key,
@@ -184,9 +410,19 @@ pub(crate) struct KeyConfig {
     pub(crate) select:       Key,
     #[serde(alias = "preview-down")]
     pub(crate) preview_down: Key,
-    #[serde(alias = "preview-down")]
+    #[serde(alias = "preview-up")]
     pub(crate) preview_up:   Key,
 
+    // == Layout ==
+    #[serde(alias = "grow-tags")]
+    pub(crate) grow_tags:      Key,
+    #[serde(alias = "shrink-tags")]
+    pub(crate) shrink_tags:    Key,
+    #[serde(alias = "grow-preview")]
+    pub(crate) grow_preview:   Key,
+    #[serde(alias = "shrink-preview")]
+    pub(crate) shrink_preview: Key,
+
     // == Actions to tags ==
     pub(crate) add:    Key,
     pub(crate) set:    Key,
@@ -208,6 +444,9 @@ impl Default for KeyConfig {
             help:    Key::Char('?'),
             refresh: Key::Char('r'),
             preview: Key::Char('P'),
+            details: Key::Char('i'),
+            sort:    Key::Char('S'),
+            palette: Key::Ctrl('p'),
 
             up:           Key::Char('k'),
             down:         Key::Char('j'),
@@ -220,6 +459,11 @@ impl Default for KeyConfig {
             select:       Key::Char('v'),
             select_all:   Key::Char('V'),
 
+            grow_tags:      Key::Char('>'),
+            shrink_tags:    Key::Char('<'),
+            grow_preview:   Key::Char('+'),
+            shrink_preview: Key::Char('-'),
+
             add:    Key::Char('a'),
             set:    Key::Char('s'),
             clear:  Key::Char('D'),
@@ -236,21 +480,16 @@ impl Default for UiConfig {
     fn default() -> Self {
         Self {
             colored_ui:           true,
+            high_contrast:        false,
             looping:              true,
             flashy:               true,
             history:              true,
-            history_filepath:     get_config_path()
-                .unwrap_or_else(|_| {
-                    dirs::home_dir().map_or_else(
-                        || PathBuf::from(format!("{}/.config/wutag", env!("HOME"))),
-                        |p| p.join(".config").join("wutag"),
-                    )
-                })
-                .join("command.history")
-                .display()
-                .to_string(),
+            history_filepath:     get_history_path().display().to_string(),
             preview_scroll_lines: 1_u16,
             preview_height:       60_u16,
+            image_protocol:       ImageProtocol::default(),
+            sort_by:              TableSort::default(),
+            tags_width:           0_u16,
             default_alias:        true,
             alias_hash:           IndexMap::new(),
             tick_rate:            250_u64,
@@ -269,6 +508,7 @@ impl Default for UiConfig {
             selection_indicator:  String::from("\u{2022}"),
             header_alignment:     String::from("center"),
             header_underline:     true,
+            theme:                UiTheme::default(),
         }
     }
 }
@@ -330,6 +570,36 @@ impl Config {
     }
 }
 
+impl DirPolicy {
+    /// Loads the closest [`POLICY_FILE`] found in `dir` or any of its parents
+    pub(crate) fn load<P: AsRef<Path>>(dir: P) -> Result<Option<Self>> {
+        for parent in dir.as_ref().ancestors() {
+            let path = parent.join(POLICY_FILE);
+            if !path.is_file() {
+                continue;
+            }
+
+            let file = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read policy file: '{}'", path.display()))?;
+            let policy = toml::from_str(&file).with_context(|| {
+                format!("failed to deserialize policy file: '{}'", path.display())
+            })?;
+
+            return Ok(Some(policy));
+        }
+
+        Ok(None)
+    }
+
+    /// Return the required tags that are missing from `tags`
+    pub(crate) fn missing<S: AsRef<str>>(&self, tags: &[S]) -> Vec<&String> {
+        self.require
+            .iter()
+            .filter(|req| !tags.iter().any(|t| t.as_ref() == req.as_str()))
+            .collect()
+    }
+}
+
 impl KeyConfig {
     // TODO: Use with ui::command
     /// Return the field name as a string for the generation of keybindings
@@ -340,6 +610,9 @@ impl KeyConfig {
             s if s == self.help => "help",
             s if s == self.refresh => "refresh",
             s if s == self.preview => "preview",
+            s if s == self.details => "details",
+            s if s == self.sort => "sort",
+            s if s == self.palette => "command palette",
             //
             s if s == self.up => "up",
             s if s == self.down => "down",
@@ -352,6 +625,11 @@ impl KeyConfig {
             s if s == self.select_all => "select all",
             s if s == self.select => "select",
             //
+            s if s == self.grow_tags => "grow tags",
+            s if s == self.shrink_tags => "shrink tags",
+            s if s == self.grow_preview => "grow preview",
+            s if s == self.shrink_preview => "shrink preview",
+            //
             s if s == self.add => "add",
             s if s == self.set => "set",
             s if s == self.clear => "clear",
@@ -367,6 +645,14 @@ impl KeyConfig {
 }
 
 impl UiConfig {
+    /// Use the high-contrast theme, and turn off the selection blinking and the
+    /// colors flashing, which are hard to follow with a screen magnifier
+    pub(crate) fn use_high_contrast(&mut self) {
+        self.theme = UiTheme::high_contrast();
+        self.selection_blink = false;
+        self.flashy = false;
+    }
+
     /// Create the default alias hash. `IndexMap` is needed to keep track of the
     /// order the user adds the variables. If one variable is `$XDG_CONFIG_HOME`
     /// which is `$HOME/.config`, and `$HOME` is also a variable, the longer and
@@ -523,6 +809,203 @@ impl UiConfig {
     }
 }
 
+/// Order that files and tags are listed in when it is not given on the command
+/// line. Files or tags that are equal are kept in the order they were added to
+/// the registry, so the output is the same every time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SortOrder {
+    /// Files by path and tags by name
+    Name,
+    /// Files by path and tags by the number of files they are on
+    Count,
+    /// Files and tags in the order they were added to the registry
+    Registry,
+}
+
+impl Default for SortOrder {
+    fn default() -> Self {
+        Self::Name
+    }
+}
+
+/// Order of the rows of the table in the TUI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TableSort {
+    /// By path
+    Path,
+    /// By the number of tags, most first
+    #[serde(alias = "tag-count")]
+    TagCount,
+    /// By the modification time in the registry, newest first
+    Mtime,
+}
+
+impl TableSort {
+    /// The order that follows this one when cycling through them
+    pub(crate) fn next(self) -> Self {
+        match self {
+            Self::Path => Self::TagCount,
+            Self::TagCount => Self::Mtime,
+            Self::Mtime => Self::Path,
+        }
+    }
+}
+
+impl Default for TableSort {
+    fn default() -> Self {
+        Self::Path
+    }
+}
+
+impl fmt::Display for TableSort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Self::Path => "path",
+            Self::TagCount => "tag count",
+            Self::Mtime => "mtime",
+        })
+    }
+}
+
+/// A color of the theme of the TUI
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum ThemeColor {
+    /// A hex color, the name of a basic color, or one of the 256 colors. A hex
+    /// color is displayed as the nearest of the 256 colors when the terminal
+    /// does not display truecolor
+    Color(String),
+    /// A color, and the one of the 256 colors that is displayed instead when
+    /// the terminal does not display truecolor
+    Fallback { color: String, fallback: u8 },
+}
+
+impl From<&str> for ThemeColor {
+    fn from(color: &str) -> Self {
+        Self::Color(color.to_owned())
+    }
+}
+
+/// Colors of each part of the TUI
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", default)]
+pub(crate) struct UiTheme {
+    /// Whether the terminal displays truecolor. It is detected from
+    /// `$COLORTERM` when it is not given
+    pub(crate) truecolor:    Option<bool>,
+    /// Titles of the table, the panes, and the command prompt
+    pub(crate) header:       ThemeColor,
+    /// Text of the panes and the completions
+    pub(crate) text:         ThemeColor,
+    /// Current mode, highlighted completion, and path in the preview
+    pub(crate) selection:    ThemeColor,
+    /// Header of the table
+    #[serde(alias = "table-header")]
+    pub(crate) table_header: ThemeColor,
+    /// Paths in the table, which falls back to `paths_color` when not given
+    pub(crate) path:         Option<ThemeColor>,
+    /// Tags whose color cannot be displayed, and flashing text when there are
+    /// no `colors` in the configuration
+    #[serde(alias = "tag-fallback")]
+    pub(crate) tag_fallback: ThemeColor,
+    /// Title of errors in the command prompt
+    pub(crate) error:        ThemeColor,
+    /// Borders between panes
+    pub(crate) border:       ThemeColor,
+    /// Keys in the help menu
+    pub(crate) key:          ThemeColor,
+    /// Actions in the help menu, and the banner
+    pub(crate) help:         ThemeColor,
+    /// Commands in the descriptions of the help menu, and the current context
+    pub(crate) command:      ThemeColor,
+    /// Progress of changes to marked files, and the position in the preview
+    pub(crate) info:         ThemeColor,
+}
+
+impl Default for UiTheme {
+    fn default() -> Self {
+        Self {
+            truecolor:    None,
+            header:       ThemeColor::from("#EF1D55"),
+            text:         ThemeColor::from("#E8C097"),
+            selection:    ThemeColor::from("#FF5813"),
+            table_header: ThemeColor::from("#98676A"),
+            path:         None,
+            tag_fallback: ThemeColor::from("blue"),
+            error:        ThemeColor::from("red"),
+            border:       ThemeColor::from("darkgray"),
+            key:          ThemeColor::from("#FF9500"),
+            help:         ThemeColor::from("#A06469"),
+            command:      ThemeColor::from("#4C96A8"),
+            info:         ThemeColor::from("#819C3B"),
+        }
+    }
+}
+
+impl UiTheme {
+    /// A theme of the basic colors, which the terminal displays with its own
+    /// palette, chosen to stand out from a dark background and each other
+    pub(crate) fn high_contrast() -> Self {
+        Self {
+            truecolor:    None,
+            header:       ThemeColor::from("white"),
+            text:         ThemeColor::from("white"),
+            selection:    ThemeColor::from("lightyellow"),
+            table_header: ThemeColor::from("white"),
+            path:         Some(ThemeColor::from("lightcyan")),
+            tag_fallback: ThemeColor::from("white"),
+            error:        ThemeColor::from("lightred"),
+            border:       ThemeColor::from("white"),
+            key:          ThemeColor::from("lightyellow"),
+            help:         ThemeColor::from("white"),
+            command:      ThemeColor::from("lightcyan"),
+            info:         ThemeColor::from("lightgreen"),
+        }
+    }
+}
+
+/// Layout of the TUI that is changed with its keybindings. It is saved when the
+/// TUI exits and takes precedence over the `ui` section of the configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct UiLayout {
+    pub(crate) sort_by:        TableSort,
+    pub(crate) tags_width:     u16,
+    pub(crate) preview_height: u16,
+}
+
+impl UiLayout {
+    /// The layout of `ui`
+    pub(crate) fn of(ui: &UiConfig) -> Self {
+        Self {
+            sort_by:        ui.sort_by,
+            tags_width:     ui.tags_width,
+            preview_height: ui.preview_height,
+        }
+    }
+
+    /// Set the layout of `ui` to this one
+    pub(crate) fn apply(self, ui: &mut UiConfig) {
+        ui.sort_by = self.sort_by;
+        ui.tags_width = self.tags_width.min(100);
+        ui.preview_height = self.preview_height.min(100);
+    }
+
+    /// Load the layout that was saved last, if there is one
+    pub(crate) fn load() -> Option<Self> {
+        let path = get_config_path().ok()?.join(LAYOUT_FILE);
+        serde_yaml::from_slice(&fs::read(path).ok()?).ok()
+    }
+
+    /// Save the layout so that it is used the next time the TUI is opened
+    pub(crate) fn save(self) -> Result<()> {
+        let path = get_config_path()?.join(LAYOUT_FILE);
+        fs::write(&path, serde_yaml::to_vec(&self)?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
 /// Wrapper around [`Alignment`](tui::layout::Alignment) to provide
 /// serialization for the user configuration
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
@@ -554,9 +1037,21 @@ impl FromStr for HeaderAlignment {
     }
 }
 
+/// Get a directory from the environment variable `var` if it is an absolute
+/// path
+fn get_env_path(var: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+}
+
 /// Get the configuration file's dirname ($XDG_CONFIG_HOME/wutag) on both
-/// `macOS` and Linux
+/// `macOS` and Linux. Overridden by `WUTAG_CONFIG_DIR`
 pub(crate) fn get_config_path() -> Result<PathBuf> {
+    if let Some(dir) = get_env_path("WUTAG_CONFIG_DIR") {
+        return Ok(dir);
+    }
+
     #[cfg(target_os = "macos")]
     let conf_dir_og = std::env::var_os("XDG_CONFIG_HOME")
         .map(PathBuf::from)
@@ -571,3 +1066,40 @@ pub(crate) fn get_config_path() -> Result<PathBuf> {
         .map(|p| p.join("wutag"))
         .context("unable to join config path")
 }
+
+/// Get the directory holding the default registry and the TUI's history
+/// ($XDG_DATA_HOME/wutag) on both `macOS` and Linux. Overridden by
+/// `WUTAG_DATA_DIR`
+pub(crate) fn get_data_path() -> Result<PathBuf> {
+    if let Some(dir) = get_env_path("WUTAG_DATA_DIR") {
+        return Ok(dir);
+    }
+
+    #[cfg(target_os = "macos")]
+    let data_dir_og = get_env_path("XDG_DATA_HOME")
+        .or_else(|| dirs::home_dir().map(|d| d.join(".local").join("share")));
+
+    #[cfg(not(target_os = "macos"))]
+    let data_dir_og = dirs::data_local_dir();
+
+    data_dir_og
+        .map(|p| p.join("wutag"))
+        .context("unable to join data path")
+}
+
+/// Get the directory temporary files are created in. Overridden by
+/// `WUTAG_CACHE_DIR`
+pub(crate) fn get_cache_path() -> PathBuf {
+    get_env_path("WUTAG_CACHE_DIR").unwrap_or_else(env::temp_dir)
+}
+
+/// Get the default path of the TUI's history. The history used to be kept in
+/// the configuration directory, which is still used if the file is there
+pub(crate) fn get_history_path() -> PathBuf {
+    get_config_path()
+        .map(|p| p.join(HISTORY_FILE))
+        .ok()
+        .filter(|p| p.exists())
+        .or_else(|| get_data_path().map(|p| p.join(HISTORY_FILE)).ok())
+        .unwrap_or_else(|| PathBuf::from(HISTORY_FILE))
+}
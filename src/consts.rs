@@ -24,8 +24,18 @@ pub(crate) const APP_NAME: &str = "wutag";
 pub(crate) const DEFAULT_BASE_COLOR: Color = Blue;
 /// Default border color for printing formatted tags
 pub(crate) const DEFAULT_BORDER_COLOR: cli_table::Color = cli_table::Color::White;
+/// Default style of the part of a search result that matched
+pub(crate) const DEFAULT_HIGHLIGHT: &str = "underline";
 /// Default max depth passed to [WalkParallel](ignore::WalkParallel)
 pub(crate) const DEFAULT_MAX_DEPTH: usize = 2;
+/// Default number of registry snapshots that are kept
+pub(crate) const DEFAULT_MAX_SNAPSHOTS: usize = 10;
+/// Default number of tags a file can have before `lint --quota-check` warns
+pub(crate) const DEFAULT_MAX_TAGS_PER_FILE: usize = 32;
+/// Default number of files a tag can be on before `lint --quota-check` warns
+pub(crate) const DEFAULT_MAX_FILES_PER_TAG: usize = 10_000;
+/// Default number of days after which a use of a tag counts for half as much
+pub(crate) const DEFAULT_MRU_HALF_LIFE: f64 = 7.0;
 /// Default colors used for tags
 pub(crate) const DEFAULT_COLORS: &[Color] = &[
     Red,
@@ -86,7 +96,8 @@ pub(crate) static EXEC_BATCH_EXPL: Lazy<String> = Lazy::new(|| {
         \t  e.g., {}wutag search '*.rs' -x {{@s}} new_tag{} (only applies to -x/--exec)\n  \
        '{}{{@r}}{}': shorthand to remove tag (only applies to -x/--exec)\n  \
        '{}{{@c}}{}': shorthand to clear tag, no other arg required (only applies to -x/--exec)\n  \
-        \t  e.g., {}wutag search '*.rs' -x {{@c}}{}",
+        \t  e.g., {}wutag search '*.rs' -x {{@c}}{}\n  \
+        See 'wutag tokens' for all of the tokens and the arguments they take",
        GREEN, RES, GREEN, RES,
        GREEN, RES, GREEN, RES,
        GREEN, RES, GREEN, RES,
@@ -0,0 +1,230 @@
+//! Reports of crashes, so that a panic outside of the TUI leaves something to
+//! attach to a bug report. The report is written to the cache directory and
+//! only holds what is needed to find the bug: the arguments with every path
+//! replaced by its hash, the version of the registry that was loaded, the last
+//! SQL statement that was run, and the backtrace. Paths in the message of the
+//! panic are hashed as well, so that the names of files are not leaked
+//!
+//! The TUI sets a panic hook of its own, which restores the terminal first
+
+use crate::{config::get_cache_path, wutag_error};
+use colored::Colorize;
+use once_cell::sync::Lazy;
+use std::{
+    backtrace::Backtrace,
+    env,
+    fmt::Write as _,
+    fs, panic,
+    path::{Path, MAIN_SEPARATOR},
+    sync::Mutex,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Version of the registry that was loaded, which is the version of `wutag`
+/// that last saved it
+static REGISTRY_VERSION: Lazy<Mutex<Option<String>>> = Lazy::new(Mutex::default);
+
+/// The last SQL statement that was run, such as on the database of a library
+/// that tags are imported from
+static LAST_SQL: Lazy<Mutex<Option<String>>> = Lazy::new(Mutex::default);
+
+/// Number of hexadecimal digits of the hash that replaces a path
+const HASH_LEN: usize = 12;
+
+/// Characters that a path in the message of a panic is quoted with
+const QUOTES: &[char] = &['"', '\'', '`', '(', ')', '[', ']'];
+
+/// Characters that can follow a path in the message of a panic
+const PUNCTUATION: &[char] = &[',', ':', ';', '.'];
+
+/// Remember the `version` of the registry that was loaded
+pub(crate) fn set_registry_version(version: Option<&str>) {
+    if let Ok(mut registry_version) = REGISTRY_VERSION.lock() {
+        *registry_version = version.map(ToOwned::to_owned);
+    }
+}
+
+/// Remember the `sql` statement that is about to be run
+pub(crate) fn set_last_sql(sql: &str) {
+    if let Ok(mut last_sql) = LAST_SQL.lock() {
+        *last_sql = Some(sql.to_owned());
+    }
+}
+
+/// Replace the `path` with its hash. The same path always has the same hash,
+/// so that the arguments of a report can still be compared
+fn hash_path(path: &str) -> String {
+    format!(
+        "<path:{}>",
+        &blake3::hash(path.as_bytes()).to_hex()[..HASH_LEN]
+    )
+}
+
+/// Whether the `word` is likely to be a path
+fn is_path(word: &str) -> bool {
+    word.contains(MAIN_SEPARATOR) || word.starts_with('~') || Path::new(word).exists()
+}
+
+/// The argument `arg` with the path it is or is the value of hashed. Flags,
+/// names of tags, and patterns are kept
+fn redact_arg(arg: &str) -> String {
+    if let Some((flag, value)) = arg.strip_prefix("--").and_then(|a| a.split_once('=')) {
+        return format!("--{}={}", flag, redact_arg(value));
+    }
+
+    match arg {
+        _ if arg.starts_with('-') && !arg.contains(MAIN_SEPARATOR) => arg.to_owned(),
+        _ if is_path(arg) => hash_path(arg),
+        _ => arg.to_owned(),
+    }
+}
+
+/// The `message` of a panic with the paths in it hashed. The quotes around a
+/// word and the punctuation after it are kept, so that they are not hashed
+fn redact_message(message: &str) -> String {
+    message
+        .split(' ')
+        .map(|word| {
+            let start = word.len() - word.trim_start_matches(QUOTES).len();
+            let end = word
+                .trim_end_matches(|c| QUOTES.contains(&c) || PUNCTUATION.contains(&c))
+                .len();
+            match word.get(start..end) {
+                Some(path) if !path.is_empty() && is_path(path) =>
+                    format!("{}{}{}", &word[..start], hash_path(path), &word[end..]),
+                _ => word.to_owned(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Create the report of a panic with the `message` at the `location`
+fn report(args: &[String], message: &str, location: Option<&str>) -> String {
+    let lock = |slot: &Mutex<Option<String>>| {
+        slot.lock()
+            .ok()
+            .and_then(|s| s.clone())
+            .unwrap_or_else(|| String::from("none"))
+    };
+
+    let mut report = String::new();
+    let _ = writeln!(report, "wutag: {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(report, "os: {} {}", env::consts::OS, env::consts::ARCH);
+    let _ = writeln!(
+        report,
+        "args: wutag {}",
+        args.iter()
+            .map(|arg| redact_arg(arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    let _ = writeln!(report, "registry_version: {}", lock(&REGISTRY_VERSION));
+    let _ = writeln!(report, "last_sql: {}", lock(&LAST_SQL));
+    let _ = writeln!(
+        report,
+        "thread: {}",
+        thread::current().name().unwrap_or("unnamed")
+    );
+    let _ = writeln!(report, "panic: {}", redact_message(message));
+    let _ = writeln!(report, "location: {}", location.unwrap_or("unknown"));
+    let _ = writeln!(report, "\n{}", Backtrace::force_capture());
+
+    report
+}
+
+/// Write a report of every panic to the cache directory after the message of
+/// the panic is displayed
+pub(crate) fn install() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| (*s).to_owned())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        let location = info.location().map(ToString::to_string);
+        let args = env::args_os()
+            .skip(1)
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let path = get_cache_path().join(format!("wutag-crash-{}.txt", secs));
+        match fs::write(&path, report(&args, &message, location.as_deref())) {
+            Ok(()) => wutag_error!(
+                "wutag crashed. A report without the names of your files was written to {}, \
+                 please attach it to a bug report",
+                path.display()
+            ),
+            Err(e) => wutag_error!(
+                "wutag crashed and its report could not be written to {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_paths_in_arguments() {
+        let hashed = redact_arg("/home/user/secret.txt");
+        assert!(hashed.starts_with("<path:"));
+        assert!(!hashed.contains("secret"));
+        assert_eq!(hashed, redact_arg("/home/user/secret.txt"));
+
+        assert_eq!(redact_arg("set"), "set");
+        assert_eq!(redact_arg("*.txt"), "*.txt");
+        assert_eq!(redact_arg("--max-depth"), "--max-depth");
+        assert_eq!(
+            redact_arg("--registry=~/reg.yml"),
+            format!("--registry={}", hash_path("~/reg.yml"))
+        );
+        assert!(redact_arg("-R/tmp/reg.yml").starts_with("<path:"));
+    }
+
+    #[test]
+    fn reports_without_file_names() {
+        set_registry_version(Some("0.7.1"));
+        set_last_sql("SELECT path FROM items");
+
+        let report = report(
+            &[
+                "set".to_owned(),
+                "/home/user/diary.md".to_owned(),
+                "private".to_owned(),
+            ],
+            "unable to read /home/user/diary.md: denied",
+            Some("src/registry.rs:10:5"),
+        );
+        assert!(!report.contains("diary"));
+        assert!(report.contains("args: wutag set <path:"));
+        assert!(report.contains("registry_version: 0.7.1"));
+        assert!(report.contains("last_sql: SELECT path FROM items"));
+        assert!(report.contains("panic: unable to read <path:"));
+        assert!(report.contains("location: src/registry.rs:10:5"));
+    }
+
+    #[test]
+    fn hashes_every_path_in_messages() {
+        assert_eq!(
+            redact_message("no such file: '~/notes.md'."),
+            format!("no such file: '{}'.", hash_path("~/notes.md"))
+        );
+        assert_eq!(
+            redact_message("failed to read \"README.md\":"),
+            format!("failed to read \"{}\":", hash_path("README.md"))
+        );
+        assert_eq!(redact_message("a 'quoted' word."), "a 'quoted' word.");
+    }
+}
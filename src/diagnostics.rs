@@ -0,0 +1,202 @@
+//! Problems with a single file (e.g., an unreadable entry or a filesystem that
+//! does not support extended attributes) are warnings. They are displayed and
+//! the command continues with the next file. Warnings are counted so that
+//! `--strict` can make the command fail when there were any
+//!
+//! The files that a command matched are counted as well, so that scripts can
+//! use `--fail-on` to tell from the exit code alone whether anything matched
+//! or whether some of the files failed
+//!
+//! A command that cannot continue exits with [`exit`], which a command run
+//! from the TUI turns into an error instead of ending the TUI along with it
+
+use std::{
+    fmt,
+    panic::{self, AssertUnwindSafe, PanicInfo},
+    process,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use crate::wutag_error;
+use colored::Colorize;
+
+/// Exit code of a command that matched nothing with `--fail-on no-matches`
+pub(crate) const EXIT_NO_MATCHES: i32 = 3;
+
+/// Exit code of a command with warnings with `--fail-on partial`
+pub(crate) const EXIT_PARTIAL: i32 = 4;
+
+/// Number of warnings displayed so far
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether warnings make the command fail
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// Whether the command matches files against a pattern or query
+static MATCHING: AtomicBool = AtomicBool::new(false);
+
+/// Number of files that the command matched
+static MATCHES: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether matching no files makes the command fail
+static FAIL_ON_NO_MATCHES: AtomicBool = AtomicBool::new(false);
+
+/// Whether warnings make the command fail with [`EXIT_PARTIAL`]
+static FAIL_ON_PARTIAL: AtomicBool = AtomicBool::new(false);
+
+/// Whether [`exit`] unwinds to [`catch_exit`] instead of ending the process
+static CATCH_EXIT: AtomicBool = AtomicBool::new(false);
+
+/// Whether the command run by [`catch_exit`] exited, and with which code
+static EXITED: AtomicBool = AtomicBool::new(false);
+static EXIT_CODE: AtomicI32 = AtomicI32::new(0);
+
+/// When a command that did not have an error exits with a non-zero code
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FailOn {
+    /// Nothing matched the pattern or query of the command
+    NoMatches,
+    /// Some of the files could not be read or tagged, which are warnings
+    Partial,
+    /// Only errors make the command fail
+    Never,
+}
+
+impl FromStr for FailOn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().trim() {
+            "no-matches" | "no_matches" => Ok(Self::NoMatches),
+            "partial" => Ok(Self::Partial),
+            "never" => Ok(Self::Never),
+            v => Err(format!("unsupported condition: {}", v)),
+        }
+    }
+}
+
+impl fmt::Display for FailOn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoMatches => f.write_str("no-matches"),
+            Self::Partial => f.write_str("partial"),
+            Self::Never => f.write_str("never"),
+        }
+    }
+}
+
+/// Make any warning result in a non-zero exit code
+pub(crate) fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+/// Set the conditions of `--fail-on`. `never` overrides the others
+pub(crate) fn set_fail_on(fail_on: &[FailOn]) {
+    let never = fail_on.contains(&FailOn::Never);
+    FAIL_ON_NO_MATCHES.store(
+        !never && fail_on.contains(&FailOn::NoMatches),
+        Ordering::Relaxed,
+    );
+    FAIL_ON_PARTIAL.store(
+        !never && fail_on.contains(&FailOn::Partial),
+        Ordering::Relaxed,
+    );
+}
+
+/// Count a warning
+pub(crate) fn warn() {
+    WARNINGS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count `count` files that matched the pattern or query of the command. This
+/// is called with 0 before matching starts, so that a command that matched
+/// nothing can be told apart from one that does not match files
+pub(crate) fn matched(count: usize) {
+    MATCHING.store(true, Ordering::Relaxed);
+    MATCHES.fetch_add(count, Ordering::Relaxed);
+}
+
+/// Exit code once the command has finished. Warnings are only a failure when
+/// using `--strict` or `--fail-on partial`, and matching nothing when using
+/// `--fail-on no-matches`
+pub(crate) fn exit_code() -> i32 {
+    let warnings = WARNINGS.load(Ordering::Relaxed);
+    if warnings > 0 && STRICT.load(Ordering::Relaxed) {
+        wutag_error!(
+            "{} warning(s) were treated as errors ({})",
+            warnings,
+            "--strict".bold()
+        );
+        1
+    } else if warnings > 0 && FAIL_ON_PARTIAL.load(Ordering::Relaxed) {
+        EXIT_PARTIAL
+    } else if MATCHING.load(Ordering::Relaxed)
+        && MATCHES.load(Ordering::Relaxed) == 0
+        && FAIL_ON_NO_MATCHES.load(Ordering::Relaxed)
+    {
+        log::debug!("nothing matched ('--fail-on no-matches')");
+        EXIT_NO_MATCHES
+    } else {
+        0
+    }
+}
+
+/// End the command with the exit `code`. A command run by [`catch_exit`]
+/// unwinds to it instead of ending the process
+pub(crate) fn exit(code: i32) -> ! {
+    if CATCH_EXIT.load(Ordering::SeqCst) {
+        EXIT_CODE.store(code, Ordering::SeqCst);
+        EXITED.store(true, Ordering::SeqCst);
+        panic::resume_unwind(Box::new(code));
+    }
+    process::exit(code)
+}
+
+/// Run `f` without letting it end the process, returning the code that it
+/// exited with if it called [`exit`]. An exit from one of the threads walking
+/// the directory makes the thread that started them panic in turn, so no panic
+/// is displayed once the command exited
+pub(crate) fn catch_exit<R, F: FnOnce() -> R>(f: F) -> Result<R, i32> {
+    let hook: Arc<dyn Fn(&PanicInfo<'_>) + Send + Sync> = Arc::from(panic::take_hook());
+    let prev = Arc::clone(&hook);
+    panic::set_hook(Box::new(move |info| {
+        if !EXITED.load(Ordering::SeqCst) {
+            prev(info);
+        }
+    }));
+    EXITED.store(false, Ordering::SeqCst);
+    CATCH_EXIT.store(true, Ordering::SeqCst);
+
+    let res = panic::catch_unwind(AssertUnwindSafe(f));
+
+    CATCH_EXIT.store(false, Ordering::SeqCst);
+    drop(panic::take_hook());
+    panic::set_hook(Box::new(move |info| hook(info)));
+
+    match res {
+        Ok(res) => Ok(res),
+        Err(_) if EXITED.swap(false, Ordering::SeqCst) => Err(EXIT_CODE.load(Ordering::SeqCst)),
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn catches_exits() {
+        assert_eq!(catch_exit(|| 5), Ok(5));
+        assert_eq!(catch_exit(|| exit(3)), Err::<(), _>(3));
+        assert_eq!(
+            catch_exit(|| thread::spawn(|| exit(2)).join().unwrap()),
+            Err::<(), _>(2)
+        );
+        assert!(!CATCH_EXIT.load(Ordering::SeqCst));
+    }
+}
@@ -1,14 +1,17 @@
 //! Provides GPGME binary context adapter.
 
 use anyhow::Result;
-use gpgme::{Context as GpgmeContext, PinentryMode, Protocol};
+use gpgme::{Context as GpgmeContext, PassphraseRequest, PinentryMode, Protocol};
 use thiserror::Error;
 
 use super::raw;
-use crate::encryption::{
-    protocol, util, Ciphertext, EncryptConfig, InnerCtx, Key, Plaintext, Proto, Recipients,
+use crate::{
+    config,
+    encryption::{
+        protocol, util, Ciphertext, EncryptConfig, InnerCtx, Key, Plaintext, Proto, Recipients,
+    },
 };
-use std::env;
+use std::{env, io::Write};
 
 /// Protocol to use for Gpg/Pgp
 const PROTO: Protocol = Protocol::OpenPgp;
@@ -24,27 +27,72 @@ pub(crate) fn context(config: &EncryptConfig) -> Result<Context, Error> {
 
     let mut context = gpgme::Context::from_protocol(PROTO).map_err(Error::Context)?;
 
-    // Set pinentry mode when using GPG TTY
-    if config.gpg_tty {
-        context
-            .set_pinentry_mode(PinentryMode::Loopback)
-            .map_err(Error::Context)?;
+    // A passphrase that is given to wutag can only be passed on in loopback mode
+    let mode = match (config.pinentry_mode, &config.passphrase) {
+        (Some(config::PinentryMode::Loopback) | None, Some(_)) => Some(PinentryMode::Loopback),
+        (Some(config::PinentryMode::Loopback), None) => return Err(Error::NoPassphrase),
+        (Some(mode), _) => Some(pinentry_mode(mode)),
+        (None, None) => None,
+    };
+    if let Some(mode) = mode {
+        context.set_pinentry_mode(mode).map_err(Error::Context)?;
     }
 
     context.set_armor(true);
 
-    Ok(Context::from(context))
+    Ok(Context::from(context).with_passphrase(config.passphrase.clone()))
+}
+
+/// Convert the pinentry mode of the configuration to the one of `gpgme`
+fn pinentry_mode(mode: config::PinentryMode) -> PinentryMode {
+    match mode {
+        config::PinentryMode::Default => PinentryMode::Default,
+        config::PinentryMode::Ask => PinentryMode::Ask,
+        config::PinentryMode::Cancel => PinentryMode::Cancel,
+        config::PinentryMode::Error => PinentryMode::Error,
+        config::PinentryMode::Loopback => PinentryMode::Loopback,
+    }
 }
 
 /// GPGME crypto context.
 pub(crate) struct Context {
     /// GPGME crytpography context.
-    context: GpgmeContext,
+    context:    GpgmeContext,
+    /// Passphrase given to `gpg-agent` in loopback mode
+    passphrase: Option<Plaintext>,
 }
 
 impl Context {
     pub(crate) fn from(context: GpgmeContext) -> Self {
-        Self { context }
+        Self {
+            context,
+            passphrase: None,
+        }
+    }
+
+    /// Answer requests for a passphrase with `passphrase` instead of a
+    /// pinentry program
+    pub(crate) fn with_passphrase(mut self, passphrase: Option<Plaintext>) -> Self {
+        self.passphrase = passphrase;
+        self
+    }
+
+    /// Run `f` with the context, giving it the passphrase if there is one
+    fn unlocked<T>(&mut self, f: impl FnOnce(&mut GpgmeContext) -> T) -> T {
+        match &self.passphrase {
+            Some(passphrase) => {
+                let passphrase = passphrase.clone();
+                self.context.with_passphrase_provider(
+                    |_: PassphraseRequest<'_>, out: &mut dyn Write| {
+                        out.write_all(passphrase.unsecure_ref())?;
+                        out.write_all(b"\n")?;
+                        Ok(())
+                    },
+                    f,
+                )
+            },
+            None => f(&mut self.context),
+        }
     }
 }
 
@@ -61,11 +109,11 @@ impl InnerCtx for Context {
     }
 
     fn decrypt(&mut self, ciphertext: Ciphertext) -> Result<Plaintext> {
-        raw::decrypt(&mut self.context, &ciphertext)
+        self.unlocked(|context| raw::decrypt(context, &ciphertext))
     }
 
     fn can_decrypt(&mut self, ciphertext: Ciphertext) -> Result<bool> {
-        raw::can_decrypt(&mut self.context, &ciphertext)
+        self.unlocked(|context| raw::can_decrypt(context, &ciphertext))
     }
 
     fn keys_public(&mut self) -> Result<Vec<Key>> {
@@ -106,4 +154,10 @@ impl InnerCtx for Context {
 pub(crate) enum Error {
     #[error("failed to obtain GPGME cryptography context")]
     Context(#[source] gpgme::Error),
+
+    #[error(
+        "pinentry mode 'loopback' needs a passphrase, give it with 'WUTAG_PASSPHRASE_FD' (e.g., \
+         'WUTAG_PASSPHRASE_FD=3 wutag list 3<file')"
+    )]
+    NoPassphrase,
 }
@@ -1,12 +1,13 @@
 //! Optional encryption interface the `wutag` crate
 
 pub(crate) mod backend;
+pub(crate) mod payload;
 pub(crate) mod protocol;
 pub(crate) mod recipients;
 pub(crate) mod types;
 pub(crate) mod util;
 
-use crate::consts::encrypt::REGISTRY_UMASK;
+use crate::{config::PinentryMode, consts::encrypt::REGISTRY_UMASK};
 pub(crate) use recipients::Recipients;
 pub(crate) use types::{Ciphertext, Plaintext};
 
@@ -42,6 +43,13 @@ pub(crate) struct EncryptConfig {
 
     /// Whether to use TTY or pinentry
     pub(crate) gpg_tty: bool,
+
+    /// How `gpg-agent` asks for passphrases, which is left to it if `None`
+    pub(crate) pinentry_mode: Option<PinentryMode>,
+
+    /// Passphrase read from `WUTAG_PASSPHRASE_FD`, which is given to
+    /// `gpg-agent` in loopback mode
+    pub(crate) passphrase: Option<Plaintext>,
 }
 
 impl EncryptConfig {
@@ -50,6 +58,8 @@ impl EncryptConfig {
         Self {
             proto,
             gpg_tty: false,
+            pinentry_mode: None,
+            passphrase: None,
         }
     }
 }
@@ -274,6 +284,9 @@ pub(crate) enum Error {
     #[error("fingerprint does not match public key in keychain")]
     #[allow(dead_code)]
     UnknownFingerprint,
+
+    #[error("failed to read the passphrase from 'WUTAG_PASSPHRASE_FD'")]
+    Passphrase(#[source] anyhow::Error),
 }
 
 /// Prelude for common crypto traits.
@@ -0,0 +1,301 @@
+//! Tags that are encrypted to the recipients of the configuration before they
+//! are written to a file (`set --encrypt`). The name of a tag is part of the
+//! key of its extended attribute, so an encrypted tag is instead kept in the
+//! value of an attribute whose key only has an id (see
+//! [`wutag_core::locked_key`]).
+//!
+//! The value is a header followed by the ciphertext, separated by `;`:
+//! `wutag-locked;v1;<key id>,<key id>;<base64 of the ciphertext>`. The version
+//! allows the format to change, and the short fingerprints of the keys the tag
+//! was encrypted to tell whether one of the private keys can decrypt it before
+//! asking for a passphrase
+
+use super::{prelude::*, util, Context, Plaintext, Recipients};
+use crate::config::EncryptConfig;
+use anyhow::{Context as _, Result};
+use once_cell::{sync::Lazy, unsync::OnceCell};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    path::Path,
+    str::FromStr,
+    sync::Mutex,
+};
+use thiserror::Error;
+use wutag_core::{
+    locked_key,
+    tag::{list_locked, Tag},
+    xattr::set_xattr,
+};
+
+/// Start of the value of an attribute that holds an encrypted tag
+const MAGIC: &str = "wutag-locked";
+
+/// Version of the format that tags are encrypted with
+pub(crate) const PAYLOAD_VERSION: u8 = 1;
+
+/// Length of the id in the key of the attribute
+const ID_LEN: usize = 16;
+
+thread_local! {
+    /// Decrypts the tags of the files that are listed by this thread, which is
+    /// created on the first encrypted tag
+    static UNLOCKER: OnceCell<Option<RefCell<Unlocker>>> = OnceCell::new();
+}
+
+/// The tags that were decrypted by any thread, by the id of their payload, so
+/// that a tag that `search` matched is not decrypted again to be displayed
+static OPENED: Lazy<Mutex<HashMap<String, Option<Tag>>>> = Lazy::new(Mutex::default);
+
+/// An encrypted tag as it is stored in an extended attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Payload {
+    /// Version of the format
+    pub(crate) version:    u8,
+    /// Short fingerprints of the keys the tag was encrypted to
+    pub(crate) key_ids:    Vec<String>,
+    /// The tag encrypted with `gpg`
+    pub(crate) ciphertext: Vec<u8>,
+}
+
+impl Payload {
+    /// Encrypt the `tag` to the `recipients`
+    pub(crate) fn seal(ctx: &mut Context, recipients: &Recipients, tag: &Tag) -> Result<Self> {
+        let plaintext = Plaintext::from(serde_cbor::to_vec(tag)?);
+        let ciphertext = ctx
+            .encrypt(recipients, plaintext)
+            .context("failed to encrypt tag")?;
+
+        Ok(Self {
+            version:    PAYLOAD_VERSION,
+            key_ids:    recipients
+                .keys()
+                .iter()
+                .map(|key| key.fingerprint(true))
+                .collect(),
+            ciphertext: ciphertext.unsecure_ref().to_vec(),
+        })
+    }
+
+    /// Decrypt the tag, which may ask for the passphrase of the key
+    pub(crate) fn open(&self, ctx: &mut Context) -> Result<Tag> {
+        let plaintext = ctx
+            .decrypt(self.ciphertext.clone().into())
+            .context("failed to decrypt tag")?;
+        serde_cbor::from_slice(plaintext.unsecure_ref()).context("decrypted tag is invalid")
+    }
+
+    /// Id of the payload in the key of its attribute, so that each encrypted
+    /// tag of a file is kept in its own attribute
+    pub(crate) fn id(&self) -> String {
+        blake3::hash(&self.ciphertext).to_hex().as_str()[..ID_LEN].to_owned()
+    }
+
+    /// Write the payload to the file at `path`
+    pub(crate) fn write_to(&self, path: &Path) -> wutag_core::Result<()> {
+        set_xattr(
+            path,
+            locked_key(&self.id()).as_str(),
+            self.to_string().as_str(),
+        )
+    }
+}
+
+impl fmt::Display for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{};v{};{};{}",
+            MAGIC,
+            self.version,
+            self.key_ids.join(","),
+            base64::encode(&self.ciphertext)
+        )
+    }
+}
+
+/// Error reading the value of an attribute as an encrypted tag
+#[derive(Debug, Error, PartialEq)]
+pub(crate) enum PayloadError {
+    #[error("the attribute does not hold an encrypted tag")]
+    NotAPayload,
+
+    #[error("version {0} of encrypted tags is not supported by this version of wutag")]
+    UnsupportedVersion(u8),
+
+    #[error("the encrypted tag is malformed")]
+    Malformed,
+}
+
+impl FromStr for Payload {
+    type Err = PayloadError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(4, ';');
+        if parts.next() != Some(MAGIC) {
+            return Err(PayloadError::NotAPayload);
+        }
+
+        let version = parts
+            .next()
+            .and_then(|v| v.strip_prefix('v'))
+            .and_then(|v| v.parse::<u8>().ok())
+            .ok_or(PayloadError::Malformed)?;
+        if version == 0 || version > PAYLOAD_VERSION {
+            return Err(PayloadError::UnsupportedVersion(version));
+        }
+
+        let key_ids = parts
+            .next()
+            .ok_or(PayloadError::Malformed)?
+            .split(',')
+            .filter(|id| !id.is_empty())
+            .map(util::format_fingerprint)
+            .collect();
+        let ciphertext = parts
+            .next()
+            .and_then(|c| base64::decode(c).ok())
+            .filter(|c| !c.is_empty())
+            .ok_or(PayloadError::Malformed)?;
+
+        Ok(Self {
+            version,
+            key_ids,
+            ciphertext,
+        })
+    }
+}
+
+/// Decrypts the tags that one of the private keys was given
+struct Unlocker {
+    ctx:        Context,
+    /// Short fingerprints of the private keys
+    secret_ids: Vec<String>,
+}
+
+impl Unlocker {
+    fn new(config: &EncryptConfig) -> Option<Self> {
+        let mut ctx = util::context(config)
+            .map_err(|e| log::warn!("unable to decrypt tags: {:#}", anyhow::Error::from(e)))
+            .ok()?;
+        let secret_ids = ctx
+            .keys_private()
+            .unwrap_or_default()
+            .iter()
+            .map(|key| key.fingerprint(true))
+            .collect();
+
+        Some(Self { ctx, secret_ids })
+    }
+
+    /// The tag of the `payload`, or `None` if none of the private keys can
+    /// decrypt it
+    fn open(&mut self, payload: &Payload) -> Option<Tag> {
+        if !payload
+            .key_ids
+            .iter()
+            .any(|id| self.secret_ids.contains(id))
+        {
+            return None;
+        }
+
+        payload
+            .open(&mut self.ctx)
+            .map_err(|e| log::debug!("{:#}", e))
+            .ok()
+    }
+}
+
+/// The encrypted tags of the file at `path`, with `None` for each one that
+/// cannot be decrypted. `config` is how the passphrase is asked for
+pub(crate) fn locked_tags(path: &Path, config: &EncryptConfig) -> Vec<Option<Tag>> {
+    let payloads = list_locked(path)
+        .unwrap_or_default()
+        .iter()
+        .map(|xattr| xattr.val().parse::<Payload>())
+        .collect::<Vec<_>>();
+    if payloads.is_empty() {
+        return vec![];
+    }
+
+    UNLOCKER.with(|cell| {
+        let unlocker = cell.get_or_init(|| Unlocker::new(config).map(RefCell::new));
+        payloads
+            .iter()
+            .map(|payload| match (payload, unlocker) {
+                (Ok(payload), Some(unlocker)) =>
+                    open_once(payload, || unlocker.borrow_mut().open(payload)),
+                (Err(e), _) => {
+                    log::debug!("{}: {}", path.display(), e);
+                    None
+                },
+                _ => None,
+            })
+            .collect()
+    })
+}
+
+/// The tag of the `payload`, which is decrypted with `open` unless a thread
+/// already did. The lock is held while decrypting, so that only one thread asks
+/// for a passphrase at a time and the others use the one `gpg-agent` cached
+fn open_once<F: FnOnce() -> Option<Tag>>(payload: &Payload, open: F) -> Option<Tag> {
+    let mut opened = OPENED.lock().unwrap();
+    opened.entry(payload.id()).or_insert_with(open).clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_payloads() {
+        let payload = Payload {
+            version:    PAYLOAD_VERSION,
+            key_ids:    vec!["C011CBEF6628B679".to_owned(), "0123456789ABCDEF".to_owned()],
+            ciphertext: b"-----BEGIN PGP MESSAGE-----\n...".to_vec(),
+        };
+
+        let value = payload.to_string();
+        assert!(value.starts_with("wutag-locked;v1;C011CBEF6628B679,0123456789ABCDEF;"));
+        assert_eq!(value.parse::<Payload>(), Ok(payload.clone()));
+        assert_eq!(payload.id().len(), ID_LEN);
+    }
+
+    #[test]
+    fn rejects_other_values() {
+        assert_eq!("".parse::<Payload>(), Err(PayloadError::NotAPayload));
+        assert_eq!(
+            "wutag-locked;v9;C011CBEF6628B679;YWJj".parse::<Payload>(),
+            Err(PayloadError::UnsupportedVersion(9))
+        );
+        assert_eq!(
+            "wutag-locked;1;C011CBEF6628B679;YWJj".parse::<Payload>(),
+            Err(PayloadError::Malformed)
+        );
+        assert_eq!(
+            "wutag-locked;v1;C011CBEF6628B679;not base64!".parse::<Payload>(),
+            Err(PayloadError::Malformed)
+        );
+        assert_eq!(
+            "wutag-locked;v1;c011cbef6628b679;YWJj"
+                .parse::<Payload>()
+                .map(|p| p.key_ids),
+            Ok(vec!["C011CBEF6628B679".to_owned()])
+        );
+    }
+
+    #[test]
+    fn opens_each_payload_once() {
+        let payload = Payload {
+            version:    PAYLOAD_VERSION,
+            key_ids:    vec!["C011CBEF6628B679".to_owned()],
+            ciphertext: b"opens_each_payload_once".to_vec(),
+        };
+        let tag = Tag::new("secret", colored::Color::Black);
+
+        assert_eq!(open_once(&payload, || Some(tag.clone())), Some(tag.clone()));
+        // The tag is not decrypted again
+        assert_eq!(open_once(&payload, || None), Some(tag));
+    }
+}
@@ -1,8 +1,22 @@
 //! Provides interface for crypto recipients.
+//!
+//! The recipients of a registry are kept in a file next to it with one full
+//! fingerprint per line (see `wutag recipients`). The registry and the
+//! encrypted tags of its files are encrypted to them and to the `public_key` of
+//! the configuration, so that the user can always decrypt them
 
-use super::Key;
+use super::{prelude::*, util, Context, Key, Plaintext};
 use crate::wutag_fatal;
+use anyhow::{anyhow, Context as _, Result};
 use colored::Colorize;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Extension added to the name of the registry to get the name of the file
+/// that lists its recipients
+const RECIPIENTS_EXTENSION: &str = "recipients";
 
 /// A list of recipients
 ///
@@ -26,6 +40,134 @@ impl Recipients {
     pub(crate) fn keys(&self) -> &[Key] {
         &self.keys
     }
+
+    /// The public keys with the given `fingerprints`, failing if one of them
+    /// is not in the keychain
+    pub(crate) fn find(ctx: &mut Context, fingerprints: &[String]) -> Result<Self> {
+        let keys = ctx
+            .keys_public()
+            .context("failed to list the public keys")?;
+        let found = fingerprints
+            .iter()
+            .map(|fingerprint| {
+                keys.iter()
+                    .find(|key| util::fingerprints_equal(key.fingerprint(false), fingerprint))
+                    .cloned()
+                    .ok_or_else(|| anyhow!("no public key has the fingerprint {}", fingerprint))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self::from(found))
+    }
+
+    /// The `public_key` of the configuration followed by the recipients of the
+    /// registry at `registry`
+    pub(crate) fn configured(
+        ctx: &mut Context,
+        registry: &Path,
+        public_key: Option<&str>,
+    ) -> Result<Self> {
+        let fingerprints = read_fingerprints(registry).context("failed to read the recipients")?;
+        let mut keys = Self::find(ctx, &fingerprints)?.keys;
+
+        if let Some(wanted) = public_key.filter(|key| !key.trim().is_empty()) {
+            let key = find_key(ctx, wanted)?;
+            keys.retain(|k| k.fingerprint(false) != key.fingerprint(false));
+            keys.insert(0, key);
+        }
+
+        if keys.is_empty() {
+            return Err(anyhow!(
+                "no key to encrypt to, set 'public_key' in the 'encryption' configuration or add \
+                 one with 'wutag recipients add'"
+            ));
+        }
+        Ok(Self::from(keys))
+    }
+
+    /// Check that each of the keys can be encrypted to, e.g., that it has not
+    /// expired or been revoked, and that it is trusted
+    pub(crate) fn check_usable(&self, ctx: &mut Context) -> Result<()> {
+        for key in &self.keys {
+            ctx.encrypt(&Self::from(vec![key.clone()]), Plaintext::from("wutag"))
+                .with_context(|| format!("the key {} cannot be encrypted to", key))?;
+        }
+        Ok(())
+    }
+}
+
+/// The public key given as an email, a short fingerprint, or a full
+/// fingerprint (optionally starting with `0x`)
+pub(crate) fn find_key(ctx: &mut Context, wanted: &str) -> Result<Key> {
+    let wanted = wanted.trim();
+    let wanted = wanted.strip_prefix("0x").unwrap_or(wanted).to_uppercase();
+
+    let matches = |key: &Key| {
+        wanted == key.fingerprint(false)
+            || wanted == key.fingerprint(true)
+            || (wanted.contains('@')
+                && key
+                    .display()
+                    .to_uppercase()
+                    .contains(&format!("<{}>", wanted)))
+    };
+
+    ctx.keys_public()
+        .context("failed to list the public keys")?
+        .into_iter()
+        .find(matches)
+        .ok_or_else(|| anyhow!("no public key matches '{}'", wanted))
+}
+
+/// The path of the file that lists the recipients of the registry at
+/// `registry`
+pub(crate) fn recipients_path(registry: &Path) -> PathBuf {
+    let mut name = registry.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(RECIPIENTS_EXTENSION);
+    registry.with_file_name(name)
+}
+
+/// The fingerprints of the recipients of the registry at `registry`, which
+/// are empty if it has none. Blank lines and lines starting with `#` are
+/// skipped
+pub(crate) fn read_fingerprints(registry: &Path) -> io::Result<Vec<String>> {
+    match fs::read_to_string(recipients_path(registry)) {
+        Ok(content) => Ok(parse_fingerprints(&content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e),
+    }
+}
+
+/// Replace the recipients of the registry at `registry`, removing the file
+/// when there are none
+pub(crate) fn write_fingerprints(registry: &Path, fingerprints: &[String]) -> io::Result<()> {
+    let path = recipients_path(registry);
+    if fingerprints.is_empty() {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+
+    let mut content = fingerprints.join("\n");
+    content.push('\n');
+    fs::write(path, content)
+}
+
+/// The fingerprints in the content of a recipients file
+fn parse_fingerprints(content: &str) -> Vec<String> {
+    let mut fingerprints = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fingerprint = util::format_fingerprint(line);
+        if !fingerprints.contains(&fingerprint) {
+            fingerprints.push(fingerprint);
+        }
+    }
+    fingerprints
 }
 
 /// Check if given keys all use same protocol
@@ -39,3 +181,37 @@ fn keys_same_proto(keys: &[Key]) -> bool {
         keys[1..].iter().all(|k| k.proto() == proto)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_recipients_files() -> io::Result<()> {
+        assert_eq!(
+            parse_fingerprints(
+                "# work\ne93accaaaeb024788c106edec011cbef6628b679\n\n  ABCD  \nabcd\n"
+            ),
+            vec![
+                "E93ACCAAAEB024788C106EDEC011CBEF6628B679".to_owned(),
+                "ABCD".to_owned()
+            ]
+        );
+
+        let dir = tempfile::tempdir()?;
+        let registry = dir.path().join("wutag.registry");
+        assert_eq!(
+            recipients_path(&registry),
+            dir.path().join("wutag.registry.recipients")
+        );
+        assert!(read_fingerprints(&registry)?.is_empty());
+
+        let fingerprints = vec!["ABCD".to_owned(), "EF01".to_owned()];
+        write_fingerprints(&registry, &fingerprints)?;
+        assert_eq!(read_fingerprints(&registry)?, fingerprints);
+
+        write_fingerprints(&registry, &[])?;
+        assert!(!recipients_path(&registry).exists());
+        Ok(())
+    }
+}
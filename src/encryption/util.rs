@@ -1,18 +1,27 @@
 //! Common cryptography utilities
 
-use super::{prelude::*, Context, EncryptConfig, Key, Proto};
-use crate::wutag_fatal;
-use anyhow::Result;
+use super::{prelude::*, Context, EncryptConfig, Key, Plaintext, Proto};
+use crate::{config, wutag_fatal};
+use anyhow::{anyhow, Context as _, Result};
 use colored::Colorize;
+use once_cell::sync::OnceCell;
 use std::{
-    env,
+    env, fs,
     path::{Path, PathBuf},
 };
+use zeroize::Zeroize;
 
 /// Local TTY path.
 const LOCAL_TTY_PATH: &str = "/dev/stdin";
 /// Max depth to traverse symlinks
 const SYMLINK_MAX_DEPTH: u8 = 31;
+/// Environment variable holding the file descriptor to read the passphrase
+/// from (e.g., `WUTAG_PASSPHRASE_FD=3 wutag list 3<file`)
+pub(crate) const PASSPHRASE_FD_ENV: &str = "WUTAG_PASSPHRASE_FD";
+
+/// The passphrase from [`PASSPHRASE_FD_ENV`], which is read once since the
+/// descriptor is usually a pipe
+static PASSPHRASE: OnceCell<Option<Result<Plaintext, String>>> = OnceCell::new();
 
 /// Format fingerprint in consistent format
 #[allow(dead_code)]
@@ -77,16 +86,49 @@ pub(crate) fn get_tty() -> Option<PathBuf> {
     resolve_symlink(&path, 0)
 }
 
+/// The passphrase from the file descriptor in [`PASSPHRASE_FD_ENV`], or `None`
+/// if it is not set
+pub(crate) fn passphrase() -> Result<Option<Plaintext>> {
+    PASSPHRASE
+        .get_or_init(|| {
+            let fd = env::var(PASSPHRASE_FD_ENV).ok()?;
+            Some(read_passphrase(&fd).map_err(|e| format!("{:#}", e)))
+        })
+        .clone()
+        .transpose()
+        .map_err(|e| anyhow!(e))
+}
+
+/// Read the passphrase from the file descriptor `fd`, which is its first line
+/// like `gpg --passphrase-fd`. The descriptor is read until it is closed
+fn read_passphrase(fd: &str) -> Result<Plaintext> {
+    let fd = fd
+        .trim()
+        .parse::<u32>()
+        .with_context(|| format!("'{}' is not a file descriptor", fd))?;
+    let mut passphrase = fs::read(format!("/dev/fd/{}", fd))
+        .with_context(|| format!("failed to read file descriptor {}", fd))?;
+
+    if let Some(end) = passphrase.iter().position(|&b| b == b'\n') {
+        passphrase[end..].zeroize();
+        passphrase.truncate(end);
+    }
+
+    Ok(Plaintext::from(passphrase))
+}
+
 /// Construct crypto config, respect CLI arguments.
-pub(crate) fn config(tty: bool) -> EncryptConfig {
+pub(crate) fn config(config: &config::EncryptConfig) -> Result<EncryptConfig, super::Error> {
     // Change if age gets introduced
     let mut encrypt_config = EncryptConfig::from(Proto::Gpg);
-    encrypt_config.gpg_tty = tty;
-    encrypt_config
+    encrypt_config.gpg_tty = config.tty;
+    encrypt_config.pinentry_mode = config.pinentry_mode;
+    encrypt_config.passphrase = passphrase().map_err(super::Error::Passphrase)?;
+    Ok(encrypt_config)
 }
 
 /// Construct crypto context based on `wutag.yml`
-/// [`EncryptConfig`](crate::config::EncryptConfig)
-pub(crate) fn context(tty: bool) -> Result<Context, super::Error> {
-    super::context(&self::config(tty))
+/// [`EncryptConfig`](crate::config::EncryptConfig) and the command line
+pub(crate) fn context(config: &config::EncryptConfig) -> Result<Context, super::Error> {
+    super::context(&self::config(config)?)
 }
@@ -1,27 +1,59 @@
-use std::{io, io::Write, process::Command, sync::Mutex};
+use std::{
+    io::{self, Write},
+    path::Path,
+    process::Command,
+    sync::Mutex,
+};
 
 use super::exits::ExitCode;
 use crate::wutag_error;
 use colored::Colorize;
 
-pub(crate) fn execute_command(mut cmd: Command, out_perm: &Mutex<()>) -> ExitCode {
+/// Write `data` to `out`, starting each line with `prefix` if it is given
+fn write_output<W: Write>(mut out: W, data: &[u8], prefix: Option<&str>) -> io::Result<()> {
+    match prefix {
+        Some(prefix) =>
+            for line in data.split_inclusive(|b| *b == b'\n') {
+                out.write_all(prefix.as_bytes())?;
+                out.write_all(line)?;
+            },
+        None => out.write_all(data)?,
+    }
+    out.flush()
+}
+
+/// Execute the command. When `buffered`, its output is captured and written
+/// all at once when it exits, so that the output of commands that run at the
+/// same time is not mixed together. Each line then starts with `prefix` if it
+/// is given (e.g., the path the command was run on)
+pub(crate) fn execute_command(
+    mut cmd: Command,
+    out_perm: &Mutex<()>,
+    buffered: bool,
+    prefix: Option<&Path>,
+) -> ExitCode {
     // Spawn the supplied command.
-    let output = cmd.output();
+    let output = if buffered {
+        cmd.output().map(|output| (output.status, Some(output)))
+    } else {
+        cmd.status().map(|status| (status, None))
+    };
 
     // Then wait for the command to exit, if it was spawned.
     match output {
-        Ok(output) => {
-            // While this lock is active, this thread will be the only thread allowed
-            // to write its outputs.
-            let _lock = out_perm.lock().unwrap();
+        Ok((status, output)) => {
+            if let Some(output) = output {
+                let prefix = prefix.map(|p| format!("{}: ", p.display().to_string().magenta()));
 
-            let stdout = io::stdout();
-            let stderr = io::stderr();
+                // While this lock is active, this thread will be the only thread allowed
+                // to write its outputs.
+                let _lock = out_perm.lock().unwrap();
 
-            let _drop = stdout.lock().write_all(&output.stdout);
-            let _drop = stderr.lock().write_all(&output.stderr);
+                let _drop = write_output(io::stdout().lock(), &output.stdout, prefix.as_deref());
+                let _drop = write_output(io::stderr().lock(), &output.stderr, prefix.as_deref());
+            }
 
-            if output.status.code() == Some(0) {
+            if status.code() == Some(0) {
                 ExitCode::Success
             } else {
                 ExitCode::GeneralError
@@ -1,3 +1,4 @@
+use super::TokenArgs;
 use std::{
     ffi::{OsStr, OsString},
     path::{Path, PathBuf},
@@ -27,21 +28,34 @@ pub(crate) fn wutag_colored_dir(path: &Path) -> OsString {
     wutag
 }
 
-pub(crate) fn wutag_set_tag(path: &Path) -> OsString {
+/// Append the arguments of a token to the command
+fn push_args(wutag: &mut OsString, args: &TokenArgs) {
+    for arg in &args.args {
+        wutag.push(" ");
+        wutag.push(arg);
+    }
+}
+
+pub(crate) fn wutag_set_tag(path: &Path, args: &TokenArgs) -> OsString {
     let mut wutag = OsString::new();
     wutag.push("wutag --color=always -d ");
     wutag.push(dirname(path));
     wutag.push(" set ");
+    if let Some(ref color) = args.color {
+        wutag.push(format!("-C {} ", color));
+    }
     wutag.push(basename(path));
+    push_args(&mut wutag, args);
     wutag
 }
 
-pub(crate) fn wutag_remove_tag(path: &Path) -> OsString {
+pub(crate) fn wutag_remove_tag(path: &Path, args: &TokenArgs) -> OsString {
     let mut wutag = OsString::new();
     wutag.push("wutag --color=always -d ");
     wutag.push(dirname(path));
     wutag.push(" remove ");
     wutag.push(basename(path));
+    push_args(&mut wutag, args);
     wutag
     // wutag.push(format!("wutag --color=always -d {}", dir));
 }
@@ -56,12 +70,13 @@ pub(crate) fn wutag_clear_tag(path: &Path) -> OsString {
     // wutag.push(format!("wutag --color=always -d {}", dir));
 }
 
-pub(crate) fn wutag_cp_tag(path: &Path) -> OsString {
+pub(crate) fn wutag_cp_tag(path: &Path, args: &TokenArgs) -> OsString {
     let mut wutag = OsString::new();
     wutag.push("wutag --color=always -d ");
     wutag.push(dirname(path));
     wutag.push(" cp ");
     wutag.push(path);
+    push_args(&mut wutag, args);
     wutag
     // wutag.push(format!("wutag --color=always -d {}", dir));
 }
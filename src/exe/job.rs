@@ -1,18 +1,22 @@
 //! Execute a search for tags asynchronously. Optionally execute a
 //! command on each result. Outline came from [fd](https://github.com/sharkdp/fd)
 use std::{
-    borrow::Cow,
-    ffi::OsStr,
     path::PathBuf,
     sync::{Arc, Mutex},
 };
 
 pub(crate) use crate::{
-    filesystem::{contained_path, osstr_to_bytes},
+    filesystem::SeenFiles,
     global_opts,
-    subcommand::{search::SearchOpts, App},
+    subcommand::{
+        search::{SearchFilter, SearchOpts},
+        App,
+    },
     ternary,
-    util::{fmt_local_path, fmt_path, fmt_tag, raw_local_path, regex_builder},
+    util::{
+        fmt_highlighted_path, fmt_local_path, fmt_path, fmt_tag_highlight, fmt_tag_icon,
+        raw_local_path, regex_builder,
+    },
     wutag_error,
 };
 
@@ -84,6 +88,8 @@ pub(crate) fn receiver(
                                     },
                                     Err(_) => break,
                                 };
+                                // Other threads receive the next results while this one runs
+                                drop(lock);
 
                                 inner.push(
                                     command.generate_and_execute(&value, &Arc::clone(&out_perm)),
@@ -103,9 +109,45 @@ pub(crate) fn receiver(
                 generalize_exitcodes(exits)
             }
         } else {
+            // The part of the path that matched, and the tags that were searched for, are
+            // highlighted. `LS_COLORS` already styles each component of the path
+            let highlight = app.highlight.filter(|_| !opts.raw);
+            let highlight_re = opts
+                .highlight
+                .as_ref()
+                .filter(|_| highlight.is_some() && !app.ls_colors)
+                .map(|p| regex_builder(p, app.case_insensitive, app.case_sensitive));
+            let searched = |name: &str| {
+                opts.tags
+                    .iter()
+                    .chain(opts.tag_sets.any_of.iter())
+                    .chain(opts.tag_sets.all_of.iter())
+                    .any(|t| t == name)
+            };
+
             for result in rx {
                 match result {
                     WorkerResult::Entry((entry, id)) => {
+                        if let Some(format) = app.porcelain {
+                            let path = entry.display().to_string();
+                            let tags = app.registry.list_entry_tags(id).unwrap_or_default();
+                            let tags =
+                                app.styled_tags(id, tags.into_iter().filter(|_| !opts.only_files));
+                            println!(
+                                "{}",
+                                format.file_record(opts.registry_name.as_deref(), &path, &tags)
+                            );
+                            continue;
+                        }
+
+                        if let Some(ref name) = opts.registry_name {
+                            if opts.raw {
+                                print!("{} ", name);
+                            } else {
+                                print!("{} ", name.purple().bold());
+                            }
+                        }
+
                         if opts.raw {
                             global_opts!(
                                 raw_local_path(
@@ -116,6 +158,26 @@ pub(crate) fn receiver(
                                 app,
                                 opts.garrulous
                             );
+                        } else if let (Some(re), Some(style)) = (&highlight_re, highlight) {
+                            global_opts!(
+                                fmt_highlighted_path(
+                                    &raw_local_path(
+                                        entry.display().to_string(),
+                                        app.base_dir.display().to_string(),
+                                    ),
+                                    re,
+                                    app.base_color,
+                                    style,
+                                ),
+                                fmt_highlighted_path(
+                                    &entry.display().to_string(),
+                                    re,
+                                    app.base_color,
+                                    style,
+                                ),
+                                app,
+                                opts.garrulous
+                            );
                         } else {
                             global_opts!(
                                 fmt_local_path(
@@ -133,20 +195,20 @@ pub(crate) fn receiver(
                         if opts.only_files && !app.quiet {
                             println!();
                         } else {
-                            let tags = app
+                            let mut tags = app
                                 .registry
                                 .list_entry_tags(id)
                                 .unwrap_or_default()
                                 .iter()
-                                .map(|t| {
-                                    if opts.raw {
-                                        t.name().to_owned()
-                                    } else {
-                                        fmt_tag(t).to_string()
-                                    }
+                                .map(|t| match highlight {
+                                    _ if opts.raw => t.name().to_owned(),
+                                    Some(style) if searched(t.name()) =>
+                                        fmt_tag_highlight(t, app.tag_icon(t), style),
+                                    _ => fmt_tag_icon(t, app.tag_icon(t)),
                                 })
-                                .collect::<Vec<_>>()
-                                .join(" ");
+                                .collect::<Vec<_>>();
+                            tags.extend(app.fmt_locked_tags(&entry, opts.raw));
+                            let tags = tags.join(" ");
 
                             if opts.garrulous && !app.quiet {
                                 println!("\t{}", tags);
@@ -166,88 +228,45 @@ pub(crate) fn receiver(
 }
 
 /// Spawn a sender channel that filters results and `sends` them to
-/// [receiver](self::receiver)
+/// [receiver](self::receiver). Returns the number of results that were sent
 pub(crate) fn sender(
     app: &Arc<App>,
     opts: &Arc<SearchOpts>,
     re: &Arc<Regex>,
     tx: Sender<WorkerResult>,
-) {
+) -> usize {
     let app = Arc::clone(app);
     let opts = Arc::clone(opts);
     let re = Arc::clone(re);
 
-    let exclude_pattern = regex_builder(
-        app.exclude.join("|").as_str(),
-        app.case_insensitive,
-        app.case_sensitive,
-    );
-
     thread::scope(move |s| {
         let tx_thread = tx.clone();
         s.spawn(move |_| {
+            let filter = SearchFilter::new(&app, &opts, &re);
+            let mut sent = 0_usize;
+            let mut seen = SeenFiles::default();
             // Repeated code from calling function to run on multiple threads
-            for (&id, entry) in app.registry.list_entries_and_ids() {
-                if !app.global && !contained_path(entry.path(), &app.base_dir) {
-                    continue;
-                }
-
-                let search_str: Cow<OsStr> = Cow::Owned(entry.path().as_os_str().to_os_string());
-                let search_bytes = osstr_to_bytes(search_str.as_ref());
-
-                if !app.exclude.is_empty() && exclude_pattern.is_match(&search_bytes) {
-                    continue;
-                }
-
-                if let Some(ref ext) = app.extension {
-                    if !ext.is_match(&search_bytes) {
-                        continue;
-                    }
+            for (id, entry) in app.paged_entries() {
+                if opts.max_results.map_or(false, |max| sent >= max) {
+                    break;
                 }
 
-                if let Some(ref file_types) = app.file_type {
-                    if file_types.should_ignore(&entry.path()) {
-                        log::debug!("Ignoring: {}", entry.path().display());
-                        continue;
-                    }
-                }
-
-                if re.is_match(&search_bytes) {
-                    // Additional tag search
-                    // !(opts.tags.is_empty() || opts.only_all && opts.all &&
-                    // app.registry.entry_has_any_tags(id, &opts.tags))
-
-                    // only !all !has_only
-                    // !only all !has_all
-                    // !only !all !has_any
-
-                    if !opts.tags.is_empty()
-                        && ((opts.only_all
-                            && !opts.all
-                            && !app.registry.entry_has_only_all_tags(id, &opts.tags))
-                            || (!opts.only_all
-                                && opts.all
-                                && !app.registry.entry_has_all_tags(id, &opts.tags))
-                            || (!opts.only_all
-                                && !opts.all
-                                && !app.registry.entry_has_any_tags(id, &opts.tags)))
-                    {
-                        continue;
-                    }
-
-                    if !opts.tags.is_empty()
-                        && opts.all
-                        && !app.registry.entry_has_all_tags(id, &opts.tags)
-                    {
+                if filter.is_match(id, entry.path()) {
+                    // The same file may be in the registry under several paths
+                    if !app.show_duplicates && seen.is_duplicate(entry.path()) {
                         continue;
                     }
 
                     tx_thread
                         .send(WorkerResult::Entry((entry.path().to_owned(), id)))
                         .unwrap();
+                    sent += 1;
                 }
             }
-        });
+            sent
+        })
+        .join()
+        .unwrap()
     })
-    .unwrap();
+    .unwrap()
 }
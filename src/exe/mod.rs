@@ -5,7 +5,8 @@ pub(crate) mod job;
 pub(crate) mod token;
 
 use std::{
-    ffi::OsString,
+    ffi::{OsStr, OsString},
+    os::unix::ffi::OsStrExt,
     path::{Path, PathBuf},
     process::{Command, Stdio},
     sync::{Arc, Mutex},
@@ -22,7 +23,7 @@ pub(crate) use self::{
         basename, dirname, remove_extension, strip_current_dir, wutag_clear_tag, wutag_colored_dir,
         wutag_cp_tag, wutag_dir, wutag_remove_tag, wutag_set_tag,
     },
-    token::Token,
+    token::{Token, TokenArgs},
 };
 
 /// Execution mode of the command
@@ -41,8 +42,12 @@ pub(crate) enum ExecutionMode {
 /// command and execute it.
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct CommandTemplate {
-    args: Vec<ArgumentTemplate>,
-    mode: ExecutionMode,
+    args:     Vec<ArgumentTemplate>,
+    mode:     ExecutionMode,
+    /// Capture the output of each command and write it at once
+    buffered: bool,
+    /// Start each line of the output with the path the command was run on
+    prefixed: bool,
 }
 
 impl CommandTemplate {
@@ -77,7 +82,7 @@ impl CommandTemplate {
         S: AsRef<str>,
     {
         static PLACEHOLDER_PATTERN: Lazy<Regex> =
-            Lazy::new(|| Regex::new(r"\{(/?\.?.?|//|@?[srxc]?)\}").unwrap());
+            Lazy::new(|| Regex::new(r"\{(/?\.?.?|//|@?[srxc]?|@[src]:[^}]*)\}").unwrap());
 
         let mut args = Vec::new();
         let mut has_placeholder = false;
@@ -104,11 +109,20 @@ impl CommandTemplate {
                     "{/.}" => tokens.push(Token::BasenameNoExt),
                     "{..}" => tokens.push(Token::Wutag),
                     "{@}" => tokens.push(Token::WutagColored),
-                    "{@s}" => tokens.push(Token::WutagSet),
-                    "{@r}" => tokens.push(Token::WutagRemove),
+                    "{@s}" => tokens.push(Token::WutagSet(TokenArgs::default())),
+                    "{@r}" => tokens.push(Token::WutagRemove(TokenArgs::default())),
                     "{@x}" => tokens.push(Token::WutagClear),
-                    "{@c}" => tokens.push(Token::WutagCp),
-                    _ => unreachable!("Unhandled placeholder"),
+                    "{@c}" => tokens.push(Token::WutagCp(TokenArgs::default())),
+                    p => {
+                        // A token with arguments, e.g., `{@s:tag=value@red}`
+                        let args = &p[4..p.len() - 1];
+                        tokens.push(match &p[..3] {
+                            "{@s" => Token::WutagSet(TokenArgs::parse(args, true)),
+                            "{@r" => Token::WutagRemove(TokenArgs::parse(args, false)),
+                            "{@c" => Token::WutagCp(TokenArgs::parse(args, false)),
+                            _ => unreachable!("Unhandled placeholder"),
+                        });
+                    },
                 }
 
                 has_placeholder = true;
@@ -116,7 +130,7 @@ impl CommandTemplate {
 
             // Without a placeholder, the argument is just fixed text.
             if tokens.is_empty() {
-                args.push(ArgumentTemplate::Text(arg.to_owned()));
+                args.push(ArgumentTemplate::Text(OsString::from(arg)));
                 continue;
             }
 
@@ -134,7 +148,21 @@ impl CommandTemplate {
             args.push(ArgumentTemplate::Tokens(vec![Token::Placeholder]));
         }
 
-        CommandTemplate { args, mode }
+        CommandTemplate {
+            args,
+            mode,
+            buffered: true,
+            prefixed: false,
+        }
+    }
+
+    /// Set whether the output of each command is captured and written once it
+    /// exits (`buffered`), and whether each line then starts with the path
+    /// the command was run on (`prefixed`). Does not apply to batch commands
+    pub(crate) fn with_output(mut self, buffered: bool, prefixed: bool) -> Self {
+        self.buffered = buffered;
+        self.prefixed = prefixed && buffered;
+        self
     }
 
     fn number_of_tokens(&self) -> usize {
@@ -148,14 +176,13 @@ impl CommandTemplate {
         cloned_args.remove(0);
         log::debug!("Cloned args: removed arg: {:?}", cloned_args);
 
+        // Split as bytes, since the path does not have to be valid UTF-8
         let mut new_args = self.args[0]
             .clone()
             .generate(input)
-            .to_str()
-            .unwrap()
-            .split(' ')
-            .map(String::from)
-            .map(ArgumentTemplate::Text)
+            .as_bytes()
+            .split(|b| *b == b' ')
+            .map(|arg| ArgumentTemplate::Text(OsStr::from_bytes(arg).to_os_string()))
             .collect::<Vec<ArgumentTemplate>>();
 
         log::debug!("New args: {:?}", new_args);
@@ -186,7 +213,8 @@ impl CommandTemplate {
         }
 
         log::debug!("=== Final command ===: {:#?}", cmd);
-        execute_command(cmd, out_perm)
+        let prefix = if self.prefixed { Some(input) } else { None };
+        execute_command(cmd, out_perm, self.buffered, prefix)
     }
 
     pub(crate) fn in_batch_mode(&self) -> bool {
@@ -222,7 +250,7 @@ impl CommandTemplate {
         }
 
         if has_path {
-            execute_command(cmd, &Mutex::new(()))
+            execute_command(cmd, &Mutex::new(()), false, None)
         } else {
             ExitCode::Success
         }
@@ -236,7 +264,7 @@ impl CommandTemplate {
 #[derive(Clone, Debug, PartialEq)]
 enum ArgumentTemplate {
     Tokens(Vec<Token>),
-    Text(String),
+    Text(OsString),
 }
 
 impl ArgumentTemplate {
@@ -246,12 +274,7 @@ impl ArgumentTemplate {
 
     pub(crate) fn contains_wutag(&self) -> bool {
         if let ArgumentTemplate::Tokens(ref tokens) = *self {
-            tokens[0] == Token::Wutag
-                || tokens[0] == Token::WutagColored
-                || tokens[0] == Token::WutagSet
-                || tokens[0] == Token::WutagRemove
-                || tokens[0] == Token::WutagClear
-                || tokens[0] == Token::WutagCp
+            tokens[0].is_wutag()
         } else {
             false
         }
@@ -280,16 +303,16 @@ impl ArgumentTemplate {
                         Placeholder => s.push(path),
                         Wutag => s.push(&wutag_dir(path)),
                         WutagColored => s.push(&wutag_colored_dir(path)),
-                        WutagSet => s.push(&wutag_set_tag(path)),
-                        WutagRemove => s.push(&wutag_remove_tag(path)),
+                        WutagSet(ref args) => s.push(&wutag_set_tag(path, args)),
+                        WutagRemove(ref args) => s.push(&wutag_remove_tag(path, args)),
                         WutagClear => s.push(&wutag_clear_tag(path)),
-                        WutagCp => s.push(&wutag_cp_tag(path)),
+                        WutagCp(ref args) => s.push(&wutag_cp_tag(path, args)),
                         Text(ref string) => s.push(string),
                     }
                 }
                 s
             },
-            ArgumentTemplate::Text(ref text) => OsString::from(text),
+            ArgumentTemplate::Text(ref text) => text.clone(),
         }
     }
 }
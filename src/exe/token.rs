@@ -1,5 +1,75 @@
 use std::fmt::{self, Display, Formatter};
 
+/// The tokens that can be given to `-x|--exec` and `-X|--exec-batch`, along
+/// with what they expand to. Displayed by `wutag tokens`
+#[rustfmt::skip]
+pub(crate) const TOKENS: &[(&str, &str)] = &[
+    ("{}", "path of the match"),
+    ("{/}", "basename of the match"),
+    ("{//}", "parent directory of the match"),
+    ("{.}", "path of the match without its extension"),
+    ("{/.}", "basename of the match without its extension"),
+    ("{..}", "wutag -d <parent_of_match>"),
+    ("{@}", "same as above, but with colored output"),
+    ("{@s}", "set the tags that follow on the match"),
+    (
+        "{@s:<tags>[@<color>]}",
+        "set the comma-separated tags, which can have values (e.g., '{@s:due=fri,a@#ff0000}')",
+    ),
+    ("{@r}", "remove the tags that follow from the match"),
+    ("{@r:<tags>}", "remove the comma-separated tags (e.g., '{@r:inbox,todo}')"),
+    ("{@x}", "clear the tags of the match"),
+    ("{@c}", "copy the tags of the match to the pattern that follows"),
+    ("{@c:<pattern>}", "copy the tags of the match to the pattern (e.g., '{@c:*.toml}')"),
+];
+
+/// Arguments given to a `{@...}` token after a colon, which are added to the
+/// `wutag` command it expands to
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct TokenArgs {
+    /// Tags (which can have a value, e.g., `tag=value`), or a pattern
+    pub(crate) args:  Vec<String>,
+    /// Color of the tags that are set
+    pub(crate) color: Option<String>,
+}
+
+impl TokenArgs {
+    /// Parse the arguments of a token, e.g., `due=friday,work@red` from
+    /// `{@s:due=friday,work@red}`. A color is only taken when `with_color` is
+    /// true, since it is only used when setting tags
+    pub(crate) fn parse(s: &str, with_color: bool) -> Self {
+        let (args, color) = match s.rsplit_once('@') {
+            Some((args, color)) if with_color && !color.is_empty() =>
+                (args, Some(color.to_owned())),
+            _ => (s, None),
+        };
+
+        Self {
+            args: args
+                .split(',')
+                .filter(|a| !a.is_empty())
+                .map(ToOwned::to_owned)
+                .collect(),
+            color,
+        }
+    }
+
+    /// Whether there are no arguments
+    pub(crate) fn is_empty(&self) -> bool {
+        self.args.is_empty() && self.color.is_none()
+    }
+}
+
+impl Display for TokenArgs {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.args.join(","))?;
+        if let Some(ref color) = self.color {
+            write!(f, "@{}", color)?;
+        }
+        Ok(())
+    }
+}
+
 /// Designates what should be written to a buffer
 ///
 /// Each `Token` contains either text, or a placeholder variant, which will be
@@ -14,15 +84,38 @@ pub(crate) enum Token {
     BasenameNoExt,
     Wutag,
     WutagColored,
-    WutagSet,
-    WutagRemove,
+    WutagSet(TokenArgs),
+    WutagRemove(TokenArgs),
     WutagClear,
-    WutagCp,
+    WutagCp(TokenArgs),
     Text(String),
 }
 
+impl Token {
+    /// Whether the token expands to a `wutag` command
+    pub(crate) fn is_wutag(&self) -> bool {
+        matches!(
+            self,
+            Token::Wutag
+                | Token::WutagColored
+                | Token::WutagSet(_)
+                | Token::WutagRemove(_)
+                | Token::WutagClear
+                | Token::WutagCp(_)
+        )
+    }
+}
+
 impl Display for Token {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let with_args = |f: &mut Formatter, name: &str, args: &TokenArgs| {
+            if args.is_empty() {
+                write!(f, "{{@{}}}", name)
+            } else {
+                write!(f, "{{@{}:{}}}", name, args)
+            }
+        };
+
         match *self {
             Token::Placeholder => f.write_str("{}")?,
             Token::Basename => f.write_str("{/}")?,
@@ -31,12 +124,35 @@ impl Display for Token {
             Token::BasenameNoExt => f.write_str("{/.}")?,
             Token::Wutag => f.write_str("{..}")?,
             Token::WutagColored => f.write_str("{@}")?,
-            Token::WutagSet => f.write_str("{@s}")?,
-            Token::WutagRemove => f.write_str("{@r}")?,
+            Token::WutagSet(ref args) => with_args(f, "s", args)?,
+            Token::WutagRemove(ref args) => with_args(f, "r", args)?,
             Token::WutagClear => f.write_str("{@x}")?,
-            Token::WutagCp => f.write_str("{@c}")?,
+            Token::WutagCp(ref args) => with_args(f, "c", args)?,
             Token::Text(ref string) => f.write_str(string)?,
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_token_args() {
+        let args = TokenArgs::parse("due=friday,work@red", true);
+        assert_eq!(args.args, vec!["due=friday", "work"]);
+        assert_eq!(args.color.as_deref(), Some("red"));
+        assert_eq!(
+            Token::WutagSet(args).to_string(),
+            "{@s:due=friday,work@red}"
+        );
+
+        let args = TokenArgs::parse("user@host", false);
+        assert_eq!(args.args, vec!["user@host"]);
+        assert_eq!(args.color, None);
+
+        assert!(TokenArgs::parse("", true).is_empty());
+        assert_eq!(Token::WutagRemove(TokenArgs::default()).to_string(), "{@r}");
+    }
+}
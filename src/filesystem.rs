@@ -1,19 +1,22 @@
 use std::{
     borrow::Cow,
-    env,
+    collections::HashSet,
     ffi::OsStr,
     fs::{self, File, Metadata},
     io::{self, Write},
     path::{Path, PathBuf},
+    str::FromStr,
 };
 
+use once_cell::sync::OnceCell;
 use rand::{distributions::Alphanumeric, Rng};
-use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
 
 use colored::Colorize;
 use thiserror::Error;
 
-use crate::wutag_error;
+use crate::{config::get_cache_path, diagnostics, wutag_error};
+use wutag_core::xattr::follow_symlinks;
 
 /// FileTypes to filter against when searching (taken from `fd`)
 #[derive(Debug, Clone, Default)]
@@ -75,19 +78,22 @@ impl FileInfo for &Path {
     }
 
     fn file_type(&self) -> Option<fs::FileType> {
-        let metadata = fs::metadata(self.path()).unwrap();
-        Some(metadata.file_type())
+        self.meta().ok().map(|meta| meta.file_type())
     }
 
+    /// The metadata of the file, or of the symlink itself when symlinks are not
+    /// followed
     fn meta(&self) -> FileInfoResult<Metadata> {
-        match self.metadata() {
-            Ok(meta) => Ok(meta),
-            Err(e) => Err(Error::Metadata(e.to_string())),
-        }
+        let metadata = if follow_symlinks() {
+            self.metadata()
+        } else {
+            self.symlink_metadata()
+        };
+        metadata.map_err(|e| Error::Metadata(e.to_string()))
     }
 
     fn is_executable(&self) -> bool {
-        self.metadata()
+        self.meta()
             .map(|m| &m.permissions().mode() & 0o111 != 0)
             .unwrap_or(false)
     }
@@ -138,9 +144,324 @@ pub(crate) fn is_empty(entry: &impl FileInfo) -> bool {
     }
 }
 
-/// Create a path to a temporary file
+/// Remembers the files that were seen by their device and inode, so that a file
+/// reached through several paths (e.g., a symlink and its target, or hard
+/// links) is only used once
+#[derive(Debug, Default)]
+pub(crate) struct SeenFiles(HashSet<(u64, u64)>);
+
+impl SeenFiles {
+    /// Whether the file at `path`, after following symlinks, was already seen
+    /// through another path. A file that cannot be read is never a duplicate
+    pub(crate) fn is_duplicate(&mut self, path: &Path) -> bool {
+        fs::metadata(path).map_or(false, |meta| !self.0.insert((meta.dev(), meta.ino())))
+    }
+}
+
+/// Number of bytes in each unit of `--size`, which is case-insensitive
+const SIZE_UNITS: &[(&str, u64)] = &[
+    ("b", 1),
+    ("k", 1000),
+    ("kb", 1000),
+    ("ki", 1024),
+    ("kib", 1024),
+    ("m", 1000 * 1000),
+    ("mb", 1000 * 1000),
+    ("mi", 1024 * 1024),
+    ("mib", 1024 * 1024),
+    ("g", 1000 * 1000 * 1000),
+    ("gb", 1000 * 1000 * 1000),
+    ("gi", 1024 * 1024 * 1024),
+    ("gib", 1024 * 1024 * 1024),
+    ("t", 1000 * 1000 * 1000 * 1000),
+    ("tb", 1000 * 1000 * 1000 * 1000),
+    ("ti", 1024 * 1024 * 1024 * 1024),
+    ("tib", 1024 * 1024 * 1024 * 1024),
+];
+
+/// Error parsing a filter on the metadata of files
+#[derive(Debug, Error, PartialEq)]
+pub(crate) enum FilterError {
+    #[error("invalid size '{0}', expected e.g., '+10M', '-1GiB' or '512k'")]
+    Size(String),
+    #[error("invalid owner '{0}', expected 'user', ':group' or 'user:group'")]
+    Owner(String),
+    #[error("unknown user '{0}'")]
+    UnknownUser(String),
+    #[error("unknown group '{0}'")]
+    UnknownGroup(String),
+    #[error("invalid permissions '{0}', expected e.g., '644', '-u+x' or '/u+w,g+w'")]
+    Perm(String),
+}
+
+/// Filter on the size of files (`--size`, taken from `fd`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SizeFilter {
+    /// At least the number of bytes (`+10M`)
+    Min(u64),
+    /// At most the number of bytes (`-10M`)
+    Max(u64),
+    /// Exactly the number of bytes (`10M`)
+    Equals(u64),
+}
+
+impl SizeFilter {
+    /// Whether a file of `size` bytes passes the filter
+    pub(crate) fn is_within(self, size: u64) -> bool {
+        match self {
+            Self::Min(min) => size >= min,
+            Self::Max(max) => size <= max,
+            Self::Equals(bytes) => size == bytes,
+        }
+    }
+}
+
+impl FromStr for SizeFilter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || FilterError::Size(s.to_owned());
+        let (filter, rest): (fn(u64) -> Self, &str) = if let Some(rest) = s.strip_prefix('+') {
+            (Self::Min, rest)
+        } else if let Some(rest) = s.strip_prefix('-') {
+            (Self::Max, rest)
+        } else {
+            (Self::Equals, s)
+        };
+
+        let (num, unit) = rest.split_at(
+            rest.find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len()),
+        );
+        let num = num.parse::<u64>().map_err(|_| err())?;
+        let unit = unit.to_ascii_lowercase();
+        let bytes = if unit.is_empty() {
+            1
+        } else {
+            SIZE_UNITS
+                .iter()
+                .find(|(name, _)| *name == unit)
+                .map(|(_, bytes)| *bytes)
+                .ok_or_else(err)?
+        };
+
+        num.checked_mul(bytes).map(filter).ok_or_else(err)
+    }
+}
+
+/// Check of the user or group of a file, where `!` matches any other id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IdCheck {
+    Equal(u32),
+    NotEqual(u32),
+    Ignore,
+}
+
+impl IdCheck {
+    fn parse(
+        s: &str,
+        resolve: impl Fn(&str) -> Option<u32>,
+        unknown: fn(String) -> FilterError,
+    ) -> Result<Self, FilterError> {
+        if s.is_empty() {
+            return Ok(Self::Ignore);
+        }
+
+        let (negate, name) = match s.strip_prefix('!') {
+            Some(name) => (true, name),
+            None => (false, s),
+        };
+        let id = name
+            .parse::<u32>()
+            .ok()
+            .or_else(|| resolve(name))
+            .ok_or_else(|| unknown(name.to_owned()))?;
+
+        Ok(if negate {
+            Self::NotEqual(id)
+        } else {
+            Self::Equal(id)
+        })
+    }
+
+    fn matches(self, id: u32) -> bool {
+        match self {
+            Self::Equal(expected) => id == expected,
+            Self::NotEqual(other) => id != other,
+            Self::Ignore => true,
+        }
+    }
+}
+
+/// Filter on the user and group that own files (`--owner`, taken from `fd`).
+/// Either one can be a name or an id, and a leading `!` matches files that are
+/// not owned by it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct OwnerFilter {
+    uid: IdCheck,
+    gid: IdCheck,
+}
+
+impl OwnerFilter {
+    /// Whether a file owned by `uid` and `gid` passes the filter
+    pub(crate) fn matches(self, uid: u32, gid: u32) -> bool {
+        self.uid.matches(uid) && self.gid.matches(gid)
+    }
+}
+
+impl FromStr for OwnerFilter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (user, group) = s.split_once(':').unwrap_or((s, ""));
+        let filter = Self {
+            uid: IdCheck::parse(
+                user,
+                |name| users::get_user_by_name(name).map(|u| u.uid()),
+                FilterError::UnknownUser,
+            )?,
+            gid: IdCheck::parse(
+                group,
+                |name| users::get_group_by_name(name).map(|g| g.gid()),
+                FilterError::UnknownGroup,
+            )?,
+        };
+
+        if filter.uid == IdCheck::Ignore && filter.gid == IdCheck::Ignore {
+            return Err(FilterError::Owner(s.to_owned()));
+        }
+
+        Ok(filter)
+    }
+}
+
+/// Filter on the permission bits of files (`--perm`, like `find -perm`). The
+/// mode is octal (`644`) or symbolic (`u=rw,go=r`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PermFilter {
+    /// Exactly the bits (`644`)
+    Exact(u32),
+    /// All of the bits are set (`-u+x`)
+    All(u32),
+    /// Any of the bits is set (`/u+w,g+w`)
+    Any(u32),
+}
+
+impl PermFilter {
+    /// Whether a file with the `mode` passes the filter
+    pub(crate) fn matches(self, mode: u32) -> bool {
+        let mode = mode & 0o7777;
+        match self {
+            Self::Exact(bits) => mode == bits,
+            Self::All(bits) => mode & bits == bits,
+            Self::Any(bits) => bits == 0 || mode & bits != 0,
+        }
+    }
+}
+
+/// Parse an octal or symbolic mode into its permission bits
+fn parse_mode(s: &str) -> Option<u32> {
+    if !s.is_empty() && s.chars().all(|c| c.is_digit(8)) {
+        return u32::from_str_radix(s, 8)
+            .ok()
+            .filter(|mode| *mode <= 0o7777);
+    }
+
+    s.split(',').try_fold(0, |mode, clause| {
+        let (who, perms) = clause.split_at(clause.find(|c| c == '+' || c == '=')?);
+        let (mut users, mut special) = (0, 0);
+        for c in who.chars() {
+            match c {
+                'u' => {
+                    users |= 0o700;
+                    special |= 0o4000;
+                },
+                'g' => {
+                    users |= 0o070;
+                    special |= 0o2000;
+                },
+                'o' => users |= 0o007,
+                'a' => {
+                    users |= 0o777;
+                    special |= 0o6000;
+                },
+                _ => return None,
+            }
+        }
+        if who.is_empty() {
+            users = 0o777;
+            special = 0o6000;
+        }
+
+        perms[1..].chars().try_fold(mode, |mode, c| match c {
+            'r' => Some(mode | (0o444 & users)),
+            'w' => Some(mode | (0o222 & users)),
+            'x' => Some(mode | (0o111 & users)),
+            's' => Some(mode | special),
+            't' => Some(mode | 0o1000),
+            _ => None,
+        })
+    })
+}
+
+impl FromStr for PermFilter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (filter, mode): (fn(u32) -> Self, &str) = if let Some(mode) = s.strip_prefix('-') {
+            (Self::All, mode)
+        } else if let Some(mode) = s.strip_prefix('/') {
+            (Self::Any, mode)
+        } else {
+            (Self::Exact, s)
+        };
+
+        parse_mode(mode)
+            .map(filter)
+            .ok_or_else(|| FilterError::Perm(s.to_owned()))
+    }
+}
+
+/// Filters on the metadata of files (`--size`, `--owner` and `--perm`), which
+/// are applied while walking directories and searching the registry
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MetadataFilter {
+    pub(crate) sizes: Vec<SizeFilter>,
+    pub(crate) owner: Option<OwnerFilter>,
+    pub(crate) perm:  Option<PermFilter>,
+}
+
+impl MetadataFilter {
+    /// Whether there are no filters
+    pub(crate) fn is_empty(&self) -> bool {
+        self.sizes.is_empty() && self.owner.is_none() && self.perm.is_none()
+    }
+
+    /// Whether the file passes all of the filters. Only regular files have a
+    /// size, and a file whose metadata cannot be read never passes
+    pub(crate) fn is_match(&self, entry: &impl FileInfo) -> bool {
+        let meta = match entry.meta() {
+            Ok(meta) => meta,
+            Err(_) => return false,
+        };
+
+        (self.sizes.is_empty()
+            || (meta.is_file() && self.sizes.iter().all(|size| size.is_within(meta.len()))))
+            && self
+                .owner
+                .map_or(true, |owner| owner.matches(meta.uid(), meta.gid()))
+            && self.perm.map_or(true, |perm| perm.matches(meta.mode()))
+    }
+}
+
+/// Create a path to a temporary file within the cache directory
 pub(crate) fn create_temp_path() -> String {
-    let mut tmp_path = env::temp_dir();
+    let mut tmp_path = get_cache_path();
+    if !tmp_path.exists() {
+        if let Err(e) = fs::create_dir_all(&tmp_path) {
+            wutag_error!("unable to create cache directory: {}", e);
+        }
+    }
     tmp_path.push(format!(
         "{}-{}",
         env!("CARGO_PKG_NAME"),
@@ -184,7 +505,7 @@ pub(crate) fn create_temp_ignore(content: &dyn Fn(&mut File) -> io::Result<()>)
         Ok(tmp) => return tmp.display().to_string(),
         Err(e) => {
             wutag_error!("unable to create temporary ignore file: {} {}", tmp, e);
-            std::process::exit(1);
+            diagnostics::exit(1);
         },
     }
 }
@@ -219,12 +540,12 @@ pub(crate) fn delete_file<P: AsRef<Path>>(file: P) {
     }
 }
 
-/// Determine whether file (path) contains path and if so, return true
+/// Determine whether file (path) contains path and if so, return true. The
+/// paths are compared as bytes, so neither has to be valid UTF-8
 pub(crate) fn contained_path<P: AsRef<Path>>(file: P, path: P) -> bool {
-    file.as_ref()
-        .display()
-        .to_string()
-        .contains(path.as_ref().to_str().unwrap())
+    let file = osstr_to_bytes(file.as_ref().as_os_str());
+    let path = osstr_to_bytes(path.as_ref().as_os_str());
+    path.is_empty() || file.windows(path.len()).any(|w| w == &*path)
 }
 
 /// Convert an OsStr to bytes for RegexBuilder
@@ -232,3 +553,360 @@ pub(crate) fn osstr_to_bytes(input: &OsStr) -> Cow<[u8]> {
     use std::os::unix::ffi::OsStrExt;
     Cow::Borrowed(input.as_bytes())
 }
+
+/// Prefixes that are stripped from paths before they are stored, set once from
+/// the configuration
+static STRIP_PREFIXES: OnceCell<StripPrefixes> = OnceCell::new();
+
+/// The places that the same share (e.g., a NAS) is mounted at on different
+/// machines (`strip_prefix`). A path under any of them is stored relative to
+/// it, and is read back under the one that is mounted on this machine, so that
+/// registries created on different mounts of the share can be used together
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct StripPrefixes {
+    prefixes: Vec<PathBuf>,
+    /// The prefix that stored paths are read back under
+    local:    Option<PathBuf>,
+}
+
+impl StripPrefixes {
+    /// Create the prefixes. Paths are read back under the first one that
+    /// exists, or the first one if none do
+    pub(crate) fn new(prefixes: Vec<PathBuf>) -> Self {
+        let local = prefixes
+            .iter()
+            .find(|p| p.exists())
+            .or_else(|| prefixes.first())
+            .cloned();
+
+        Self { prefixes, local }
+    }
+
+    /// Set the prefixes that are used when (de)serializing paths. Only the
+    /// first call has an effect
+    pub(crate) fn init(self) {
+        if STRIP_PREFIXES.set(self).is_err() {
+            log::debug!("prefixes to strip were already set");
+        }
+    }
+
+    /// The prefixes that are used when (de)serializing paths
+    pub(crate) fn current() -> &'static Self {
+        static EMPTY: StripPrefixes = StripPrefixes {
+            prefixes: Vec::new(),
+            local:    None,
+        };
+        STRIP_PREFIXES.get().unwrap_or(&EMPTY)
+    }
+
+    /// The `path` as it is stored, without the prefix it is under
+    pub(crate) fn stored<'a>(&self, path: &'a Path) -> &'a Path {
+        self.prefixes
+            .iter()
+            .filter_map(|prefix| path.strip_prefix(prefix).ok())
+            .find(|rest| !rest.as_os_str().is_empty())
+            .unwrap_or(path)
+    }
+
+    /// The stored `path` as it is on this machine, under the local prefix
+    pub(crate) fn restore<'a>(&self, path: &'a Path) -> Cow<'a, Path> {
+        match &self.local {
+            Some(local) if path.is_relative() => Cow::Owned(local.join(path)),
+            _ => Cow::Borrowed(path),
+        }
+    }
+
+    /// The absolute `path` as it is on this machine, which is under the local
+    /// prefix if it is under any of the others
+    pub(crate) fn local<'a>(&self, path: &'a Path) -> Cow<'a, Path> {
+        if path.is_relative() {
+            return Cow::Borrowed(path);
+        }
+
+        self.restore(self.stored(path))
+    }
+}
+
+/// (De)serialize a path without losing the paths that are not valid UTF-8,
+/// which serde refuses to write. Paths that are valid UTF-8 are written as a
+/// string like before, and any other path as its bytes (`{ bytes: [...] }`)
+pub(crate) mod os_path {
+    use super::StripPrefixes;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::{
+        borrow::Cow,
+        ffi::OsString,
+        os::unix::ffi::{OsStrExt, OsStringExt},
+        path::{Path, PathBuf},
+    };
+
+    /// How a path is written
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    enum Repr<'a> {
+        Utf8(&'a str),
+        Bytes { bytes: &'a [u8] },
+    }
+
+    /// How a path is read
+    #[derive(Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+    #[serde(untagged)]
+    enum OwnedRepr {
+        Utf8(String),
+        Bytes { bytes: Vec<u8> },
+    }
+
+    impl<'a> From<&'a Path> for Repr<'a> {
+        fn from(path: &'a Path) -> Self {
+            let path = StripPrefixes::current().stored(path);
+            path.to_str().map_or_else(
+                || Self::Bytes {
+                    bytes: path.as_os_str().as_bytes(),
+                },
+                Self::Utf8,
+            )
+        }
+    }
+
+    impl From<OwnedRepr> for PathBuf {
+        fn from(repr: OwnedRepr) -> Self {
+            let path = match repr {
+                OwnedRepr::Utf8(s) => Self::from(s),
+                OwnedRepr::Bytes { bytes } => Self::from(OsString::from_vec(bytes)),
+            };
+
+            let prefixes = StripPrefixes::current();
+            match prefixes.restore(prefixes.stored(&path)) {
+                Cow::Owned(local) => local,
+                Cow::Borrowed(_) => path,
+            }
+        }
+    }
+
+    pub(crate) fn serialize<P: AsRef<Path>, S: Serializer>(
+        path: &P,
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        Repr::from(path.as_ref()).serialize(s)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<PathBuf, D::Error> {
+        OwnedRepr::deserialize(d).map(PathBuf::from)
+    }
+
+    /// (De)serialize a map keyed by paths, in the same way as a path
+    pub(crate) mod keys {
+        use super::{OwnedRepr, Repr};
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+        use std::{collections::BTreeMap, path::PathBuf};
+
+        pub(crate) fn serialize<V: Serialize, S: Serializer>(
+            map: &BTreeMap<PathBuf, V>,
+            s: S,
+        ) -> Result<S::Ok, S::Error> {
+            s.collect_map(map.iter().map(|(k, v)| (Repr::from(k.as_path()), v)))
+        }
+
+        pub(crate) fn deserialize<'de, V: Deserialize<'de>, D: Deserializer<'de>>(
+            d: D,
+        ) -> Result<BTreeMap<PathBuf, V>, D::Error> {
+            BTreeMap::<OwnedRepr, V>::deserialize(d)
+                .map(|map| map.into_iter().map(|(k, v)| (k.into(), v)).collect())
+        }
+    }
+
+    /// (De)serialize a map of maps keyed by paths, such as the stashes of the
+    /// registry
+    pub(crate) mod nested_keys {
+        use super::{OwnedRepr, Repr};
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+        use std::{collections::BTreeMap, path::PathBuf};
+
+        /// A map keyed by paths that is serialized like [`keys`](super::keys)
+        struct Inner<'a, V>(&'a BTreeMap<PathBuf, V>);
+
+        impl<V: Serialize> Serialize for Inner<'_, V> {
+            fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                s.collect_map(self.0.iter().map(|(k, v)| (Repr::from(k.as_path()), v)))
+            }
+        }
+
+        pub(crate) fn serialize<V: Serialize, S: Serializer>(
+            map: &BTreeMap<String, BTreeMap<PathBuf, V>>,
+            s: S,
+        ) -> Result<S::Ok, S::Error> {
+            s.collect_map(map.iter().map(|(k, inner)| (k, Inner(inner))))
+        }
+
+        pub(crate) fn deserialize<'de, V: Deserialize<'de>, D: Deserializer<'de>>(
+            d: D,
+        ) -> Result<BTreeMap<String, BTreeMap<PathBuf, V>>, D::Error> {
+            BTreeMap::<String, BTreeMap<OwnedRepr, V>>::deserialize(d).map(|map| {
+                map.into_iter()
+                    .map(|(k, inner)| {
+                        (k, inner.into_iter().map(|(p, v)| (p.into(), v)).collect())
+                    })
+                    .collect()
+            })
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use std::{collections::BTreeMap, os::unix::ffi::OsStrExt};
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Paths {
+        #[serde(with = "os_path")]
+        path:  PathBuf,
+        #[serde(with = "os_path::keys")]
+        paths: BTreeMap<PathBuf, usize>,
+    }
+
+    #[test]
+    fn round_trips_invalid_utf8_paths() {
+        let invalid = PathBuf::from(OsStr::from_bytes(b"/tmp/caf\xe9"));
+        let paths = Paths {
+            path:  invalid.clone(),
+            paths: vec![(invalid, 1), (PathBuf::from("/tmp/valid"), 2)]
+                .into_iter()
+                .collect(),
+        };
+
+        let yaml = serde_yaml::to_string(&paths).unwrap();
+        assert!(yaml.contains("/tmp/valid"));
+        assert_eq!(serde_yaml::from_str::<Paths>(&yaml).unwrap(), paths);
+    }
+
+    #[test]
+    fn contains_invalid_utf8_paths() {
+        let dir = Path::new(OsStr::from_bytes(b"/tmp/caf\xe9"));
+        assert!(contained_path(dir.join("a").as_path(), dir));
+        assert!(!contained_path(Path::new("/tmp/other"), dir));
+    }
+
+    #[test]
+    fn strips_and_restores_prefixes() {
+        let mounted = tempfile::tempdir().unwrap();
+        let prefixes = StripPrefixes::new(vec![
+            PathBuf::from("/nonexistent/nas/"),
+            mounted.path().to_path_buf(),
+        ]);
+        let stored = |path: &Path| prefixes.stored(path).to_path_buf();
+        let restore = |path: &str| prefixes.restore(Path::new(path)).into_owned();
+        let local = |path: &str| prefixes.local(Path::new(path)).into_owned();
+
+        assert_eq!(stored(Path::new("/nonexistent/nas/a.mp3")), Path::new("a.mp3"));
+        assert_eq!(stored(&mounted.path().join("a.mp3")), Path::new("a.mp3"));
+        assert_eq!(stored(Path::new("/nonexistent/nas")), Path::new("/nonexistent/nas"));
+        assert_eq!(stored(Path::new("/srv/a.mp3")), Path::new("/srv/a.mp3"));
+
+        // The prefix that exists is the local one
+        assert_eq!(restore("music/a.mp3"), mounted.path().join("music/a.mp3"));
+        assert_eq!(restore("/srv/a.mp3"), Path::new("/srv/a.mp3"));
+        assert_eq!(local("/nonexistent/nas/a.mp3"), mounted.path().join("a.mp3"));
+        assert_eq!(local("/srv/a.mp3"), Path::new("/srv/a.mp3"));
+        assert_eq!(local("a.mp3"), Path::new("a.mp3"));
+
+        let none = StripPrefixes::new(vec![]);
+        assert_eq!(none.stored(Path::new("/tmp/a")), Path::new("/tmp/a"));
+        assert_eq!(none.restore(Path::new("a")), Path::new("a"));
+    }
+
+    #[test]
+    fn detects_files_reached_through_several_paths() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("file");
+        let other = dir.path().join("other");
+        fs::write(&file, "")?;
+        fs::write(&other, "")?;
+        std::os::unix::fs::symlink(&file, dir.path().join("symlink"))?;
+        fs::hard_link(&file, dir.path().join("hard_link"))?;
+
+        let mut seen = SeenFiles::default();
+        assert!(!seen.is_duplicate(&file));
+        assert!(!seen.is_duplicate(&other));
+        assert!(seen.is_duplicate(&dir.path().join("symlink")));
+        assert!(seen.is_duplicate(&dir.path().join("hard_link")));
+        assert!(!seen.is_duplicate(&dir.path().join("missing")));
+        assert!(!seen.is_duplicate(&dir.path().join("missing")));
+        Ok(())
+    }
+
+    #[test]
+    fn parses_size_filters() {
+        assert_eq!("+10M".parse(), Ok(SizeFilter::Min(10_000_000)));
+        assert_eq!("-1GiB".parse(), Ok(SizeFilter::Max(1024 * 1024 * 1024)));
+        assert_eq!("512k".parse(), Ok(SizeFilter::Equals(512_000)));
+        assert_eq!("42".parse(), Ok(SizeFilter::Equals(42)));
+        assert!("+10X".parse::<SizeFilter>().is_err());
+        assert!("M".parse::<SizeFilter>().is_err());
+        assert!("99999999999T".parse::<SizeFilter>().is_err());
+
+        assert!(SizeFilter::Min(10).is_within(10));
+        assert!(!SizeFilter::Max(10).is_within(11));
+    }
+
+    #[test]
+    fn parses_owner_filters() {
+        let owner = "root".parse::<OwnerFilter>().unwrap();
+        assert!(owner.matches(0, 100));
+        assert!(!owner.matches(1000, 0));
+
+        let owner = "!0:100".parse::<OwnerFilter>().unwrap();
+        assert!(owner.matches(1000, 100));
+        assert!(!owner.matches(0, 100));
+        assert!(":100".parse::<OwnerFilter>().unwrap().matches(0, 100));
+
+        assert_eq!(
+            ":".parse::<OwnerFilter>(),
+            Err(FilterError::Owner(":".to_owned()))
+        );
+        assert_eq!(
+            "no-such-user-wutag".parse::<OwnerFilter>(),
+            Err(FilterError::UnknownUser("no-such-user-wutag".to_owned()))
+        );
+    }
+
+    #[test]
+    fn parses_perm_filters() {
+        assert_eq!("644".parse(), Ok(PermFilter::Exact(0o644)));
+        assert_eq!("u=rw,go=r".parse(), Ok(PermFilter::Exact(0o644)));
+        assert_eq!("-u+x".parse(), Ok(PermFilter::All(0o100)));
+        assert_eq!("/u+w,g+w".parse(), Ok(PermFilter::Any(0o220)));
+        assert_eq!("-a+x".parse(), Ok(PermFilter::All(0o111)));
+        assert_eq!("/+s".parse(), Ok(PermFilter::Any(0o6000)));
+        assert!("888".parse::<PermFilter>().is_err());
+        assert!("u+q".parse::<PermFilter>().is_err());
+        assert!("/".parse::<PermFilter>().is_err());
+
+        assert!(PermFilter::All(0o100).matches(0o100_755));
+        assert!(!PermFilter::Any(0o022).matches(0o100_644));
+        assert!(PermFilter::Exact(0o644).matches(0o100_644));
+    }
+
+    #[test]
+    fn filters_files_by_metadata() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("file");
+        fs::write(&file, "0123456789")?;
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640))?;
+
+        let filter = |sizes: &[&str], perm: &str| MetadataFilter {
+            sizes: sizes.iter().map(|s| s.parse().unwrap()).collect(),
+            owner: None,
+            perm:  Some(perm.parse().unwrap()),
+        };
+        assert!(filter(&["+10b", "-1k"], "640").is_match(&file.as_path()));
+        assert!(!filter(&["+11b"], "640").is_match(&file.as_path()));
+        assert!(!filter(&[], "/o+r").is_match(&file.as_path()));
+        assert!(!filter(&["-1k"], "-u+w").is_match(&dir.path()));
+        assert!(!filter(&[], "640").is_match(&dir.path().join("missing").as_path()));
+        assert!(MetadataFilter::default().is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+//! Translation of the glob patterns given to `set`, `search`, `rm`, the REPL,
+//! etc. into the regular expressions that paths are matched with. Every code
+//! path goes through the engine chosen with `glob_engine`, so that a pattern
+//! matches the same files whether the directories are walked or the registry
+//! is searched
+
+use globset::{Glob, GlobBuilder};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, fmt};
+
+/// Engine used for glob patterns, set once from the configuration
+static GLOB_ENGINE: OnceCell<GlobEngine> = OnceCell::new();
+
+/// POSIX character classes that can be used inside of a bracket expression
+/// (e.g., `[[:digit:]_]`), with the ranges they expand to
+const CHAR_CLASSES: &[(&str, &str)] = &[
+    ("alnum", "a-zA-Z0-9"),
+    ("alpha", "a-zA-Z"),
+    ("blank", " \t"),
+    ("digit", "0-9"),
+    ("lower", "a-z"),
+    ("space", " \t\n\r\x0b\x0c"),
+    ("upper", "A-Z"),
+    ("word", "a-zA-Z0-9_"),
+    ("xdigit", "0-9a-fA-F"),
+];
+
+/// How glob patterns are matched against paths. Both support `?`, `*`, `**`,
+/// alternatives (`{a,b}`), bracket expressions (`[a-z]`, `[!a-z]`), and POSIX
+/// character classes inside of them (`[[:alpha:]]`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum GlobEngine {
+    /// The rules of `globset`, where `*` and `?` also match the separator, so
+    /// `*.rs` matches `src/main.rs`
+    Globset,
+    /// The rules of `globset` with its literal separator, where `*` and `?`
+    /// never match the separator and only `**` crosses directories. A pattern
+    /// without a separator matches the file name, so `*.rs` matches
+    /// `src/main.rs` but `src/*.rs` does not match `src/bin/main.rs`
+    #[serde(alias = "literal_separator")]
+    LiteralSeparator,
+}
+
+impl Default for GlobEngine {
+    fn default() -> Self {
+        Self::Globset
+    }
+}
+
+impl fmt::Display for GlobEngine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Globset => write!(f, "globset"),
+            Self::LiteralSeparator => write!(f, "literal-separator"),
+        }
+    }
+}
+
+impl GlobEngine {
+    /// Set the engine used for glob patterns. Only the first call has an
+    /// effect
+    pub(crate) fn init(self) {
+        if GLOB_ENGINE.set(self).is_err() {
+            log::debug!("glob engine was already set");
+        }
+    }
+
+    /// The engine used for glob patterns
+    pub(crate) fn current() -> Self {
+        GLOB_ENGINE.get().copied().unwrap_or_default()
+    }
+
+    /// Compile the glob `pattern` as it is, with the rules of the engine
+    pub(crate) fn glob(self, pattern: &str) -> Result<Glob, globset::Error> {
+        GlobBuilder::new(&expand_char_classes(pattern))
+            .literal_separator(self == Self::LiteralSeparator)
+            .build()
+    }
+
+    /// The regular expression that matches the same paths as the glob
+    /// `pattern`. The walker matches it against file names and a search of the
+    /// registry against whole paths, so with `literal-separator` a pattern
+    /// without a separator is allowed to follow any directory
+    pub(crate) fn regex(self, pattern: &str) -> Result<String, globset::Error> {
+        let pattern = match self {
+            Self::LiteralSeparator if !pattern.contains('/') =>
+                Cow::Owned(format!("**/{}", pattern)),
+            _ => Cow::Borrowed(pattern),
+        };
+
+        self.glob(&pattern).map(|glob| glob.regex().to_owned())
+    }
+}
+
+/// Replace the POSIX character classes inside of the bracket expressions of
+/// `pattern` with the ranges they stand for. Unknown classes are left alone
+fn expand_char_classes(pattern: &str) -> Cow<'_, str> {
+    if !pattern.contains("[:") {
+        return Cow::Borrowed(pattern);
+    }
+
+    let mut expanded = String::with_capacity(pattern.len());
+    let mut in_brackets = false;
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        if c == '\\' {
+            let escaped = rest.chars().nth(1).map_or(1, |e| 1 + e.len_utf8());
+            expanded.push_str(&rest[..escaped]);
+            rest = &rest[escaped..];
+            continue;
+        }
+
+        if in_brackets {
+            if let Some((name, range)) = CHAR_CLASSES
+                .iter()
+                .find(|(name, _)| rest.starts_with(&format!("[:{}:]", name)))
+            {
+                expanded.push_str(range);
+                rest = &rest[name.len() + 4..];
+                continue;
+            }
+            in_brackets = c != ']';
+        } else if c == '[' {
+            // A `]` right after the opening bracket (or its negation) is a
+            // literal, not the end of the expression
+            in_brackets = true;
+            let start = ["[!]", "[^]", "[!", "[^", "[]", "["]
+                .iter()
+                .find(|start| rest.starts_with(**start))
+                .map_or(1, |start| start.len());
+            expanded.push_str(&rest[..start]);
+            rest = &rest[start..];
+            continue;
+        }
+
+        expanded.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    Cow::Owned(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn is_match(engine: GlobEngine, pattern: &str, path: &str) -> bool {
+        Regex::new(&engine.regex(pattern).unwrap())
+            .unwrap()
+            .is_match(path)
+    }
+
+    #[test]
+    fn expands_char_classes() {
+        assert_eq!(expand_char_classes("*.rs"), "*.rs");
+        assert_eq!(expand_char_classes("[[:digit:]_]*"), "[0-9_]*");
+        assert_eq!(expand_char_classes("[![:upper:]]"), "[!A-Z]");
+        assert_eq!(expand_char_classes("[]][:alpha:]]"), "[]a-zA-Z]");
+        assert_eq!(expand_char_classes("[:alpha:]"), "[:alpha:]");
+        assert_eq!(expand_char_classes("\\[[:alpha:]]"), "\\[[:alpha:]]");
+        assert_eq!(expand_char_classes("[[:nope:]]"), "[[:nope:]]");
+    }
+
+    #[test]
+    fn matches_extended_globs() {
+        for engine in &[GlobEngine::Globset, GlobEngine::LiteralSeparator] {
+            assert!(is_match(*engine, "*.{rs,go}", "main.go"));
+            assert!(is_match(*engine, "[!a-m]*.rs", "zebra.rs"));
+            assert!(!is_match(*engine, "[!a-m]*.rs", "main.rs"));
+            assert!(is_match(*engine, "file[[:digit:]].txt", "file1.txt"));
+            assert!(!is_match(*engine, "file[[:digit:]].txt", "filea.txt"));
+            assert!(is_match(*engine, "/src/**/*.rs", "/src/bin/main.rs"));
+        }
+    }
+
+    #[test]
+    fn matches_separators_by_engine() {
+        // The walker matches file names and a search whole paths
+        assert!(is_match(GlobEngine::Globset, "*.rs", "/src/bin/main.rs"));
+        assert!(is_match(GlobEngine::LiteralSeparator, "*.rs", "/src/bin/main.rs"));
+        assert!(is_match(GlobEngine::LiteralSeparator, "*.rs", "main.rs"));
+
+        assert!(is_match(GlobEngine::Globset, "/src/*.rs", "/src/a/main.rs"));
+        assert!(!is_match(GlobEngine::LiteralSeparator, "/src/*.rs", "/src/a/main.rs"));
+        assert!(is_match(GlobEngine::LiteralSeparator, "/src/*.rs", "/src/main.rs"));
+    }
+}
@@ -0,0 +1,228 @@
+//! Hashing of the content of files that are added to the registry. Each hash is
+//! stored with the algorithm that created it (e.g., `xxh3-quick64:<digest>`), so
+//! that hashes are only compared when they were created the same way. Hashes
+//! without a prefix are full `blake3` hashes from before this was configurable
+
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt, fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+use xxhash_rust::xxh3::Xxh3;
+
+/// Default number of KiB hashed at the start and end of a file with a quick
+/// hash
+pub(crate) const DEFAULT_QUICK_HASH_SIZE: u64 = 64;
+
+/// Hashing that is used for new hashes, set once from the configuration
+static HASHING: OnceCell<Hashing> = OnceCell::new();
+
+/// Algorithm used to hash the content of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum HashAlgorithm {
+    Blake3,
+    Xxh3,
+    Sha256,
+}
+
+impl Default for HashAlgorithm {
+    fn default() -> Self {
+        Self::Blake3
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Blake3 => write!(f, "blake3"),
+            Self::Xxh3 => write!(f, "xxh3"),
+            Self::Sha256 => write!(f, "sha256"),
+        }
+    }
+}
+
+/// How the content of a file is hashed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Hashing {
+    /// Algorithm used to hash the file
+    pub(crate) algorithm: HashAlgorithm,
+    /// Only hash this many KiB at the start and at the end of the file, along
+    /// with the file's size
+    pub(crate) quick:     Option<u64>,
+}
+
+impl Hashing {
+    /// Set the hashing used for new hashes. Only the first call has an effect
+    pub(crate) fn init(self) {
+        if HASHING.set(self).is_err() {
+            log::debug!("hashing was already set");
+        }
+    }
+
+    /// The hashing used for new hashes
+    pub(crate) fn current() -> Self {
+        HASHING.get().copied().unwrap_or_default()
+    }
+
+    /// The hashing that created a stored `hash`
+    #[allow(dead_code)]
+    pub(crate) fn of(hash: &str) -> Self {
+        let prefix = match hash.split_once(':') {
+            Some((prefix, _)) => prefix,
+            None => return Self::default(),
+        };
+        let (name, quick) = match prefix.split_once("-quick") {
+            Some((name, kib)) => (name, kib.parse().ok()),
+            None => (prefix, None),
+        };
+        let algorithm = match name {
+            "xxh3" => HashAlgorithm::Xxh3,
+            "sha256" => HashAlgorithm::Sha256,
+            _ => HashAlgorithm::Blake3,
+        };
+
+        Self { algorithm, quick }
+    }
+
+    /// Hash the content of the file at `path`, prefixed with the algorithm
+    pub(crate) fn hash_file<P: AsRef<Path>>(&self, path: P) -> Result<String> {
+        let path = path.as_ref();
+        let mut file = fs::File::open(path)
+            .with_context(|| format!("unable to open file to hash: {}", path.display()))?;
+        let size = file.metadata()?.len();
+        let mut hasher = Hasher::new(self.algorithm);
+
+        match self.quick.map(|kib| kib * 1024) {
+            Some(part) if size > part * 2 => {
+                hasher.update_from((&mut file).take(part))?;
+                file.seek(SeekFrom::End(-(part as i64)))?;
+                hasher.update_from(file)?;
+                hasher.update(&size.to_le_bytes());
+
+                Ok(format!(
+                    "{}-quick{}:{}",
+                    self.algorithm,
+                    part / 1024,
+                    hasher.finalize()
+                ))
+            },
+            _ => {
+                hasher.update_from(file)?;
+
+                Ok(format!("{}:{}", self.algorithm, hasher.finalize()))
+            },
+        }
+    }
+
+    /// Hash all of `data`, prefixed with the algorithm. Used for the members of
+    /// an archive, which cannot be read from a file
+    #[cfg(feature = "archives")]
+    pub(crate) fn hash_bytes(&self, data: &[u8]) -> String {
+        let mut hasher = Hasher::new(self.algorithm);
+        hasher.update(data);
+
+        format!("{}:{}", self.algorithm, hasher.finalize())
+    }
+}
+
+/// A hasher of any of the supported algorithms
+enum Hasher {
+    Blake3(Box<blake3::Hasher>),
+    Xxh3(Box<Xxh3>),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Blake3 => Self::Blake3(Box::new(blake3::Hasher::new())),
+            HashAlgorithm::Xxh3 => Self::Xxh3(Box::new(Xxh3::new())),
+            HashAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Blake3(hasher) => {
+                hasher.update(data);
+            },
+            Self::Xxh3(hasher) => hasher.update(data),
+            Self::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Read everything from `reader` into the hasher
+    fn update_from<R: Read>(&mut self, mut reader: R) -> io::Result<()> {
+        let mut buf = [0; 65536];
+        loop {
+            match reader.read(&mut buf)? {
+                0 => return Ok(()),
+                n => self.update(&buf[..n]),
+            }
+        }
+    }
+
+    /// The digest as a hex string
+    fn finalize(self) -> String {
+        match self {
+            Self::Blake3(hasher) => hasher.finalize().to_string(),
+            Self::Xxh3(hasher) => format!("{:016x}", hasher.digest()),
+            Self::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn hashes_with_algorithm_prefix() -> Result<()> {
+        let mut file = tempfile::NamedTempFile::new()?;
+        file.write_all(&[1; 4096])?;
+
+        for algorithm in &[
+            HashAlgorithm::Blake3,
+            HashAlgorithm::Xxh3,
+            HashAlgorithm::Sha256,
+        ] {
+            let hashing = Hashing {
+                algorithm: *algorithm,
+                quick:     None,
+            };
+            let hash = hashing.hash_file(file.path())?;
+            assert!(hash.starts_with(&format!("{}:", algorithm)));
+            assert_eq!(Hashing::of(&hash), hashing);
+        }
+
+        // The file is small enough to be hashed fully
+        let quick = Hashing {
+            algorithm: HashAlgorithm::Xxh3,
+            quick:     Some(4),
+        };
+        assert!(quick.hash_file(file.path())?.starts_with("xxh3:"));
+
+        // Only the start and end are hashed, so a change in the middle is missed
+        let quick = Hashing {
+            algorithm: HashAlgorithm::Xxh3,
+            quick:     Some(1),
+        };
+        let hash = quick.hash_file(file.path())?;
+        assert!(hash.starts_with("xxh3-quick1:"));
+        assert_eq!(Hashing::of(&hash), quick);
+
+        file.as_file_mut().seek(SeekFrom::Start(2048))?;
+        file.write_all(&[2; 16])?;
+        assert_eq!(quick.hash_file(file.path())?, hash);
+
+        assert_eq!(Hashing::of("abcdef"), Hashing::default());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+//! Write-ahead journal of the tags that `set` is about to write. Before the
+//! extended attributes of the files of a batch are changed, the tags they have
+//! and the tags that will be written are appended to a file next to the
+//! registry, which is synced to disk once. The registry is only saved once the
+//! whole batch is done, so if the batch is interrupted (e.g., by a power loss)
+//! the files and the registry disagree. The journal is then still there, and
+//! `wutag recover` either replays it or rolls the files back to the tags they
+//! had before.
+//!
+//! Each process writes its own journal, named after its id, so that a batch
+//! that is running is not mistaken for one that was interrupted. Each line of
+//! the journal is a JSON object, so that a line that was only partially written
+//! when the batch was interrupted is skipped
+
+use crate::filesystem::os_path;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+};
+use wutag_core::{process_running, tag::Tag};
+
+/// Extension added to the name of the registry to get the name of its journal
+const JOURNAL_EXTENSION: &str = "pending";
+
+/// The changes that are about to be made to the tags of a file
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub(crate) struct PendingOp {
+    /// The file that is tagged
+    #[serde(with = "os_path")]
+    pub(crate) path:     PathBuf,
+    /// The tags the file had before the batch
+    pub(crate) before:   Vec<Tag>,
+    /// Whether the tags of the file are cleared first
+    pub(crate) clear:    bool,
+    /// The tags that are written
+    pub(crate) tags:     Vec<Tag>,
+    /// The default tags, which are only written if the file does not have them
+    pub(crate) defaults: Vec<Tag>,
+    /// The values of the tags that are set in the registry
+    pub(crate) values:   BTreeMap<String, String>,
+}
+
+/// The path of the journal that the process `pid` writes for the registry at
+/// `registry`
+fn journal_path(registry: &Path, pid: u32) -> PathBuf {
+    let mut name = registry.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.{}", pid, JOURNAL_EXTENSION));
+    registry.with_file_name(name)
+}
+
+/// The journals of the registry at `registry` whose process is no longer
+/// running, which are the batches that were interrupted
+fn interrupted(registry: &Path) -> Vec<PathBuf> {
+    let prefix = format!("{}.", registry.file_name().unwrap_or_default().to_string_lossy());
+    let suffix = format!(".{}", JOURNAL_EXTENSION);
+    let dir = registry.parent().unwrap_or_else(|| Path::new("."));
+
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter(|entry| {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    name.strip_prefix(&prefix)
+                        .and_then(|n| n.strip_suffix(&suffix))
+                        .and_then(|pid| pid.parse::<u32>().ok())
+                        .map_or(false, |pid| !process_running(pid))
+                })
+                .map(|entry| entry.path())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The complete records of the journal at `path`
+fn read_ops(path: &Path) -> Vec<PendingOp> {
+    File::open(path)
+        .map(|file| {
+            BufReader::new(file)
+                .lines()
+                .filter_map(io::Result::ok)
+                .filter_map(|line| serde_json::from_str(&line).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The changes of the batches that did not finish, which are empty if there
+/// are none. A journal without a complete record is ignored
+pub(crate) fn pending(registry: &Path) -> Vec<PendingOp> {
+    interrupted(registry)
+        .iter()
+        .flat_map(|path| read_ops(path))
+        .collect()
+}
+
+/// Remove the journals of the batches of the registry at `registry` that did
+/// not finish
+pub(crate) fn discard(registry: &Path) -> io::Result<()> {
+    interrupted(registry).iter().try_for_each(|path| remove(path))
+}
+
+/// Remove the journal at `path`, which may already be gone
+fn remove(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// A journal that changes are appended to
+#[derive(Debug)]
+pub(crate) struct Journal {
+    file: BufWriter<File>,
+    path: PathBuf,
+}
+
+impl Journal {
+    /// Start the journal of a batch of this process
+    pub(crate) fn begin(registry: &Path) -> io::Result<Self> {
+        let path = journal_path(registry, process::id());
+        Ok(Self {
+            file: BufWriter::new(File::create(&path)?),
+            path,
+        })
+    }
+
+    /// Append a change to the journal. It is only on the disk after `sync`
+    pub(crate) fn record(&mut self, op: &PendingOp) -> io::Result<()> {
+        let line = serde_json::to_string(op)?;
+        writeln!(self.file, "{}", line)
+    }
+
+    /// Write the changes to the disk, which is done once for a batch before
+    /// any of its files are changed
+    pub(crate) fn sync(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file.get_ref().sync_data()
+    }
+
+    /// Remove the journal once the registry has been saved
+    pub(crate) fn finish(self) -> io::Result<()> {
+        remove(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use colored::Color;
+
+    #[test]
+    fn reads_back_complete_records() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let registry = dir.path().join("wutag.registry");
+        let op = PendingOp {
+            path:     PathBuf::from("/tmp/a"),
+            before:   vec![Tag::new("old", Color::Red)],
+            clear:    true,
+            tags:     vec![Tag::new("new", Color::Blue)],
+            defaults: vec![],
+            values:   BTreeMap::new(),
+        };
+
+        let mut journal = Journal::begin(&registry)?;
+        journal.record(&op)?;
+        // A record that was cut off is skipped
+        write!(journal.file, "{{\"path\":\"/tmp/b\"")?;
+        journal.sync()?;
+        assert_eq!(read_ops(&journal.path), vec![op.clone()]);
+
+        // The journal of a batch that is still running is not pending
+        assert!(pending(&registry).is_empty());
+
+        // A journal whose process is gone is, unless it is empty
+        let gone = journal_path(&registry, i32::MAX as u32);
+        fs::rename(&journal.path, &gone)?;
+        File::create(journal_path(&registry, i32::MAX as u32 - 1))?;
+        assert_eq!(pending(&registry), vec![op]);
+
+        discard(&registry)?;
+        assert!(!gone.exists());
+        assert_eq!(fs::read_dir(dir.path())?.count(), 0);
+        journal.finish()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+//! Libraries of other programs that tags are imported from, which are read
+//! from their sqlite databases. Their categories become tags and values on the
+//! files the libraries point to:
+//!
+//! - beets: the genres of a track become tags, and its `album`, `artist`, and
+//!   `year` become values
+//! - calibre: the tags of a book stay tags, and its `series` and first `author`
+//!   become values
+//!
+//! The databases are only opened to be read, so a library can be imported
+//! while the program that owns it is running
+
+use crate::crash;
+use anyhow::{Context, Result};
+use rusqlite::{types::ValueRef, Connection, OpenFlags};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    ffi::OsStr,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+/// Name of the database of a calibre library
+const CALIBRE_DB: &str = "metadata.db";
+
+/// A file of a library with the tags and values it is given
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LibraryItem {
+    pub(crate) path:   PathBuf,
+    pub(crate) tags:   Vec<String>,
+    pub(crate) values: BTreeMap<String, String>,
+}
+
+impl LibraryItem {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            tags: vec![],
+            values: BTreeMap::new(),
+        }
+    }
+
+    /// Add the tag `name` unless it is empty or was already added
+    fn tag(&mut self, name: &str) {
+        let name = name.trim();
+        if !name.is_empty() && !self.tags.iter().any(|t| t == name) {
+            self.tags.push(name.to_owned());
+        }
+    }
+
+    /// Add the tag `name` with the `value`, unless the value is empty
+    fn value(&mut self, name: &str, value: &str) {
+        let value = value.trim();
+        if value.is_empty() {
+            return;
+        }
+        self.tag(name);
+        self.values.insert(name.to_owned(), value.to_owned());
+    }
+}
+
+/// Open the database at `path` to be read
+fn open(path: &Path) -> Result<Connection> {
+    Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .with_context(|| format!("unable to open {}", path.display()))
+}
+
+/// The database of beets, which is `$BEETSDIR/library.db` or else
+/// `~/.config/beets/library.db`
+pub(crate) fn default_beets_db() -> Option<PathBuf> {
+    env::var_os("BEETSDIR")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config/beets")))
+        .map(|dir| dir.join("library.db"))
+}
+
+/// The library of calibre, which is `~/Calibre Library` unless it was moved
+pub(crate) fn default_calibre_library() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join("Calibre Library"))
+}
+
+/// The items of the beets database at `db`. Beets keeps paths as bytes, which
+/// may also have been written as text by older versions
+pub(crate) fn beets_items(db: &Path) -> Result<Vec<LibraryItem>> {
+    let conn = open(db)?;
+    let sql = "SELECT path, genre, album, albumartist, artist, year FROM items";
+    crash::set_last_sql(sql);
+    let mut stmt = conn.prepare(sql).context("not a beets library")?;
+
+    let items = stmt
+        .query_map([], |row| {
+            let path = match row.get_ref(0)? {
+                ValueRef::Text(bytes) | ValueRef::Blob(bytes) =>
+                    PathBuf::from(OsStr::from_bytes(bytes)),
+                _ => PathBuf::new(),
+            };
+            let text = |idx: usize| {
+                row.get::<_, Option<String>>(idx)
+                    .map(Option::unwrap_or_default)
+            };
+
+            let mut item = LibraryItem::new(path);
+            for genre in text(1)?.split(&[',', ';'][..]) {
+                item.tag(genre);
+            }
+            item.value("album", &text(2)?);
+            let artist = match text(3)? {
+                artist if artist.is_empty() => text(4)?,
+                artist => artist,
+            };
+            item.value("artist", &artist);
+            if let Some(year) = row.get::<_, Option<i64>>(5)?.filter(|y| *y > 0) {
+                item.value("year", &year.to_string());
+            }
+            Ok(item)
+        })?
+        .filter(|item| item.as_ref().map_or(true, |i| i.path.is_absolute()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(items)
+}
+
+/// Map each book of a calibre database to the names of a category, like its
+/// tags or authors, in the order they were linked
+fn calibre_links(conn: &Connection, sql: &str) -> Result<HashMap<i64, Vec<String>>> {
+    crash::set_last_sql(sql);
+    let mut stmt = conn.prepare(sql).context("not a calibre library")?;
+    let mut links = HashMap::<i64, Vec<String>>::new();
+    for link in stmt.query_map([], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+    })? {
+        let (book, name) = link?;
+        links.entry(book).or_default().push(name);
+    }
+
+    Ok(links)
+}
+
+/// The items of the calibre library at `library`, which is either its
+/// directory or its `metadata.db`. Each format of a book is its own file
+pub(crate) fn calibre_items(library: &Path) -> Result<Vec<LibraryItem>> {
+    let (dir, db) = if library.is_dir() {
+        (library, library.join(CALIBRE_DB))
+    } else {
+        (library.parent().unwrap_or(library), library.to_path_buf())
+    };
+    let conn = open(&db)?;
+
+    let tags = calibre_links(
+        &conn,
+        "SELECT l.book, t.name FROM books_tags_link l JOIN tags t ON t.id = l.tag ORDER BY l.id",
+    )?;
+    let series = calibre_links(
+        &conn,
+        "SELECT l.book, s.name FROM books_series_link l JOIN series s ON s.id = l.series",
+    )?;
+    let authors = calibre_links(
+        &conn,
+        "SELECT l.book, a.name FROM books_authors_link l JOIN authors a ON a.id = l.author \
+         ORDER BY l.id",
+    )?;
+
+    let sql = "SELECT b.id, b.path, d.name, d.format FROM books b JOIN data d ON d.book = b.id";
+    crash::set_last_sql(sql);
+    let mut stmt = conn.prepare(sql).context("not a calibre library")?;
+    let items = stmt
+        .query_map([], |row| {
+            let book: i64 = row.get(0)?;
+            let path = dir.join(row.get::<_, String>(1)?).join(format!(
+                "{}.{}",
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?.to_ascii_lowercase()
+            ));
+
+            let mut item = LibraryItem::new(path);
+            for tag in tags.get(&book).into_iter().flatten() {
+                item.tag(tag);
+            }
+            if let Some(series) = series.get(&book).and_then(|s| s.first()) {
+                item.value("series", series);
+            }
+            if let Some(author) = authors.get(&book).and_then(|a| a.first()) {
+                item.value("author", author);
+            }
+            Ok(item)
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_beets_items() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let db = dir.path().join("library.db");
+        Connection::open(&db)?.execute_batch(
+            "CREATE TABLE items (id INTEGER PRIMARY KEY, path BLOB, genre TEXT, album TEXT, \
+             albumartist TEXT, artist TEXT, year INTEGER);
+             INSERT INTO items VALUES (1, CAST('/music/a.flac' AS BLOB), 'Rock, Blues', \
+             'Abbey Road', '', 'The Beatles', 1969);
+             INSERT INTO items VALUES (2, '/music/b.mp3', '', '', 'Various', 'Someone', 0);
+             INSERT INTO items VALUES (3, 'relative.mp3', 'Pop', '', '', '', 0);",
+        )?;
+
+        let items = beets_items(&db)?;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].path, PathBuf::from("/music/a.flac"));
+        assert_eq!(items[0].tags, vec!["Rock", "Blues", "album", "artist", "year"]);
+        assert_eq!(items[0].values["album"], "Abbey Road");
+        assert_eq!(items[0].values["artist"], "The Beatles");
+        assert_eq!(items[0].values["year"], "1969");
+        assert_eq!(items[1].tags, vec!["artist"]);
+        assert_eq!(items[1].values["artist"], "Various");
+
+        assert!(beets_items(&dir.path().join("missing.db")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reads_calibre_items() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        Connection::open(dir.path().join(CALIBRE_DB))?.execute_batch(
+            "CREATE TABLE books (id INTEGER PRIMARY KEY, path TEXT);
+             CREATE TABLE data (id INTEGER PRIMARY KEY, book INTEGER, format TEXT, name TEXT);
+             CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE books_tags_link (id INTEGER PRIMARY KEY, book INTEGER, tag INTEGER);
+             CREATE TABLE series (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE books_series_link (id INTEGER PRIMARY KEY, book INTEGER, series \
+             INTEGER);
+             CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE books_authors_link (id INTEGER PRIMARY KEY, book INTEGER, author \
+             INTEGER);
+             INSERT INTO books VALUES (1, 'Frank Herbert/Dune (1)');
+             INSERT INTO data VALUES (1, 1, 'EPUB', 'Dune - Frank Herbert');
+             INSERT INTO data VALUES (2, 1, 'PDF', 'Dune - Frank Herbert');
+             INSERT INTO tags VALUES (1, 'Fiction'), (2, 'Science Fiction');
+             INSERT INTO books_tags_link VALUES (1, 1, 2), (2, 1, 1);
+             INSERT INTO series VALUES (1, 'Dune');
+             INSERT INTO books_series_link VALUES (1, 1, 1);
+             INSERT INTO authors VALUES (1, 'Frank Herbert');
+             INSERT INTO books_authors_link VALUES (1, 1, 1);",
+        )?;
+
+        let items = calibre_items(dir.path())?;
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[0].path,
+            dir.path()
+                .join("Frank Herbert/Dune (1)/Dune - Frank Herbert.epub")
+        );
+        assert_eq!(items[0].tags, vec![
+            "Science Fiction",
+            "Fiction",
+            "series",
+            "author"
+        ]);
+        assert_eq!(items[0].values["series"], "Dune");
+        assert_eq!(items[0].values["author"], "Frank Herbert");
+        assert_eq!(items[0].tags, items[1].tags);
+
+        assert_eq!(calibre_items(&dir.path().join(CALIBRE_DB))?, items);
+        Ok(())
+    }
+}
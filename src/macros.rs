@@ -1,10 +1,11 @@
-/// Format errors
+/// Format errors with a single entry, which are counted as warnings
 #[macro_export]
 macro_rules! err {
     ($err:ident, $entry:ident) => {
         err!("", $err, $entry);
     };
     ($prefix:expr, $err:ident, $entry:ident) => {{
+        $crate::diagnostics::warn();
         let err = fmt_err($err);
         eprintln!(
             "{}{} - {}",
@@ -49,12 +50,22 @@ macro_rules! wutag_error {
     })
 }
 
+/// Expand to a warning message, which does not stop the command unless using
+/// `--strict`
+#[macro_export]
+macro_rules! wutag_warning {
+    ($($err:tt)*) => ({
+        $crate::diagnostics::warn();
+        eprintln!("{}: {}", "[wutag warning]".magenta().bold(), format!($($err)*));
+    })
+}
+
 /// Expand to a fatal message
 #[macro_export]
 macro_rules! wutag_fatal {
     ($($err:tt)*) => ({
         eprintln!("{}: {}", "[wutag fatal]".yellow().bold(), format!($($err)*));
-        std::process::exit(1);
+        $crate::diagnostics::exit(1);
     })
 }
 
@@ -56,32 +56,62 @@
     clippy::upper_case_acronyms
 )]
 
+#[cfg(feature = "archives")]
+mod archive;
+#[cfg(feature = "auth")]
+mod auth;
 mod comp_helper;
 mod config;
 mod consts;
+mod crash;
+mod diagnostics;
 #[cfg(feature = "encrypt-gpgme")]
 mod encryption;
 mod exe;
 mod filesystem;
+mod glob;
+mod hashing;
+mod journal;
+#[cfg(feature = "media")]
+mod library;
 mod macros;
+mod messages;
+mod mounts;
 mod opt;
+mod porcelain;
 mod registry;
+mod remote;
+mod storage;
 mod subcommand;
 #[cfg(feature = "ui")]
 mod ui;
 mod util;
+#[cfg(feature = "media")]
+mod xmp;
 
 use colored::Colorize;
 use config::Config;
 use opt::Opts;
+use std::process;
 use subcommand::App;
 
 fn main() {
+    crash::install();
     let config = Config::load_default_location().unwrap_or_default();
-    let args = Opts::get_args();
+    let args = Opts::get_args(&config);
+    util::set_color_override(util::resolve_color_when(
+        args.color_when.as_deref(),
+        args.porcelain.is_some() || args.plain,
+    ));
     util::initialize_logging(&args);
+    diagnostics::set_strict(args.strict);
+    diagnostics::set_fail_on(args.fail_on.as_deref().unwrap_or_default());
+    messages::init(args.verbose_errors, config.language.as_deref());
 
     if let Err(e) = App::run(args, &config) {
         wutag_error!("{}", e);
+        process::exit(1);
     }
+
+    process::exit(diagnostics::exit_code());
 }
@@ -0,0 +1,179 @@
+//! Catalog of the error messages that need an explanation. Each message has a
+//! short form that is always displayed, and a long form that explains the cause
+//! and is only displayed with `--verbose-errors`, so that the failing path is
+//! not lost in the explanation.
+//!
+//! Messages can be translated by placing a file named after the language (e.g.,
+//! `de.yml`) in the `messages` directory of the configuration directory. It
+//! maps the key of a message to its `short` and `long` forms, and any message
+//! that is missing falls back to the English one
+
+use crate::config::get_config_path;
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    env, fs,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use wutag_core::Error;
+
+/// Name of the directory in the configuration directory holding translations
+const MESSAGES_DIR: &str = "messages";
+
+/// Whether the long form of a message is displayed
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Messages read from the translation of the current language
+static CATALOG: OnceCell<HashMap<String, Entry>> = OnceCell::new();
+
+/// A message in a translation file
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+struct Entry {
+    short: String,
+    #[serde(default)]
+    long:  Option<String>,
+}
+
+/// A message in the catalog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Message {
+    XattrsUnsupported,
+    XattrsNotPermitted,
+    XattrTooLarge,
+    XattrDropped,
+    InvalidFormat,
+}
+
+impl Message {
+    /// Key of the message in a translation file
+    fn key(self) -> &'static str {
+        match self {
+            Self::XattrsUnsupported => "xattrs_unsupported",
+            Self::XattrsNotPermitted => "xattrs_not_permitted",
+            Self::XattrTooLarge => "xattr_too_large",
+            Self::XattrDropped => "xattr_dropped",
+            Self::InvalidFormat => "invalid_format",
+        }
+    }
+
+    /// The short and long forms in English
+    fn english(self) -> (&'static str, &'static str) {
+        match self {
+            Self::XattrsUnsupported => (
+                "the filesystem does not support extended attributes",
+                "Tags are kept in the 'user' extended attributes of a file. Filesystems such as \
+                 vfat and exfat never support them, some network filesystems only do when the \
+                 server allows it, and ext2/3/4 needed to be mounted with 'user_xattr' on older \
+                 kernels. Move the file to another filesystem or remount this one with support \
+                 for them",
+            ),
+            Self::XattrsNotPermitted => (
+                "extended attributes are not permitted on this file",
+                "Linux does not allow 'user' extended attributes on symlinks, device files, \
+                 sockets, or FIFOs, and no file can be changed when it is immutable ('chattr \
+                 +i'). A symlink is tagged by tagging the file it points to instead",
+            ),
+            Self::XattrTooLarge => (
+                "the tag is too large for the filesystem",
+                "Some filesystems limit the size of an extended attribute, or the size of all of \
+                 them on a single file (e.g., 4 KiB on ext4). A tag with a shorter name, or \
+                 fewer tags on the file, fits",
+            ),
+            Self::XattrDropped => (
+                "the filesystem did not keep the tag",
+                "The tag was written without an error, but it was not there when it was read \
+                 back. Some filesystems (e.g., FUSE or network mounts) silently drop extended \
+                 attributes. 'wutag info --filesystems' lists the mount points that are known \
+                 to do this",
+            ),
+            Self::InvalidFormat => (
+                "invalid format in the configuration, using the default: toml",
+                "The 'format' option of the configuration is the format of the files that \
+                 'edit' and 'view' open in an editor. Valid values are: toml, yaml, yml, json",
+            ),
+        }
+    }
+
+    /// The message for an error that is in the catalog
+    pub(crate) fn for_error(err: &Error) -> Option<Self> {
+        match err {
+            Error::XattrsUnsupported => Some(Self::XattrsUnsupported),
+            Error::XattrsNotPermitted => Some(Self::XattrsNotPermitted),
+            Error::XattrTooLarge => Some(Self::XattrTooLarge),
+            Error::XattrDropped => Some(Self::XattrDropped),
+            _ => None,
+        }
+    }
+}
+
+/// The language of a locale such as `de_DE.UTF-8`
+fn language(locale: &str) -> Option<&str> {
+    let lang = locale
+        .split(|c| c == '_' || c == '.' || c == '@')
+        .next()
+        .filter(|l| !l.is_empty())?;
+    if lang == "C" || lang == "POSIX" {
+        None
+    } else {
+        Some(lang)
+    }
+}
+
+/// Display the long form of messages, and read the translation of `lang` (or
+/// the language of the locale if it is not given)
+pub(crate) fn init(verbose: bool, lang: Option<&str>) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .find_map(|var| env::var(var).ok().filter(|v| !v.is_empty()));
+    let lang = match lang.or_else(|| locale.as_deref().and_then(language)) {
+        Some(lang) if lang != "en" => lang,
+        _ => return,
+    };
+
+    let catalog = get_config_path()
+        .map(|dir| dir.join(MESSAGES_DIR).join(format!("{}.yml", lang)))
+        .ok()
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|data| serde_yaml::from_slice(&data).ok());
+
+    if let Some(catalog) = catalog {
+        if CATALOG.set(catalog).is_err() {
+            log::debug!("message catalog was already loaded");
+        }
+    }
+}
+
+/// The text of a message, including its long form with `--verbose-errors`
+pub(crate) fn text(msg: Message) -> String {
+    let (short, long) = msg.english();
+    let entry = CATALOG.get().and_then(|c| c.get(msg.key()));
+    let short = entry.map_or(short, |e| e.short.as_str());
+    let long = entry.and_then(|e| e.long.as_deref()).unwrap_or(long);
+
+    if VERBOSE.load(Ordering::Relaxed) {
+        format!("{}\n    {}", short, long)
+    } else {
+        short.to_owned()
+    }
+}
+
+/// The text of an error, which is taken from the catalog when it is in it
+pub(crate) fn error_text(err: &Error) -> String {
+    Message::for_error(err).map_or_else(|| err.to_string(), text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_locale_language() {
+        assert_eq!(language("de_DE.UTF-8"), Some("de"));
+        assert_eq!(language("fr"), Some("fr"));
+        assert_eq!(language("C.UTF-8"), None);
+        assert_eq!(language(""), None);
+    }
+}
@@ -0,0 +1,316 @@
+//! Whether the filesystem of each mount point keeps the user extended
+//! attributes that tags are written to. Some filesystems (e.g., `tmpfs` on
+//! older kernels, `vfat`, or network mounts) reject them or silently drop them,
+//! so what was learned while tagging is kept in the cache directory and used in
+//! error messages and `wutag info --filesystems`.
+//!
+//! Filesystems are also told apart by their UUID, so that a removable drive can
+//! carry its own registry (see `filesystem_registries`) wherever it is mounted
+
+use crate::{config::get_cache_path, messages, wutag_error};
+use colored::Colorize;
+use indexmap::IndexMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use wutag_core::{
+    tag::{list_tags, Tag},
+    Error,
+};
+
+/// Name of the file in the cache directory holding the table
+const XATTR_SUPPORT_FILE: &str = "wutag-xattr-support.yml";
+
+/// Directory with a link to the device of each filesystem, named after its UUID
+const UUID_DIR: &str = "/dev/disk/by-uuid";
+
+/// File listing the mounted filesystems
+const MOUNTS_FILE: &str = "/proc/self/mounts";
+
+/// Escape sequences in the mount points of `MOUNTS_FILE`, with the characters
+/// they stand for. The backslash is last so that it is not unescaped twice
+const MOUNT_ESCAPES: &[(&str, &str)] = &[
+    ("\\040", " "),
+    ("\\011", "\t"),
+    ("\\012", "\n"),
+    ("\\134", "\\"),
+];
+
+/// Whether a filesystem keeps user extended attributes
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum XattrSupport {
+    /// Tags are kept
+    Yes,
+    /// Tags are rejected or dropped
+    No,
+    /// Small tags are kept, but tags with long names or colors were too large
+    SizeLimit,
+}
+
+impl fmt::Display for XattrSupport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Self::Yes => "yes".green(),
+            Self::No => "no".red(),
+            Self::SizeLimit => "size limit".yellow(),
+        })
+    }
+}
+
+/// The table of mount points, which is read from the cache when first used
+static SUPPORT: Lazy<Mutex<BTreeMap<PathBuf, XattrSupport>>> = Lazy::new(|| {
+    let table = fs::read(get_cache_path().join(XATTR_SUPPORT_FILE))
+        .ok()
+        .and_then(|data| serde_yaml::from_slice(&data).ok())
+        .unwrap_or_default();
+    Mutex::new(table)
+});
+
+/// The mount point of the filesystem that `path` is on, which is the last
+/// ancestor that is on the same device
+pub(crate) fn mount_point(path: &Path) -> PathBuf {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dev = match fs::metadata(&path) {
+        Ok(meta) => meta.dev(),
+        Err(_) => return path,
+    };
+
+    path.ancestors()
+        .take_while(|p| fs::metadata(p).map_or(false, |meta| meta.dev() == dev))
+        .last()
+        .map_or_else(|| path.clone(), Path::to_path_buf)
+}
+
+/// The UUID of the filesystem that `path` is on, which is found by comparing
+/// its device with the devices in `/dev/disk/by-uuid`
+pub(crate) fn filesystem_uuid(path: &Path) -> Option<String> {
+    let dev = fs::metadata(path).ok()?.dev();
+    fs::read_dir(UUID_DIR).ok()?.flatten().find_map(|entry| {
+        (fs::metadata(entry.path()).ok()?.rdev() == dev)
+            .then(|| entry.file_name().to_string_lossy().into_owned())
+    })
+}
+
+/// The mount point of the filesystem with the `uuid`, or `None` if it is not
+/// mounted
+pub(crate) fn uuid_mount_point(uuid: &str) -> Option<PathBuf> {
+    let dev = fs::metadata(Path::new(UUID_DIR).join(uuid)).ok()?.rdev();
+    fs::read_to_string(MOUNTS_FILE)
+        .ok()?
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(|mount| PathBuf::from(unescape_mount(mount)))
+        .find(|mount| fs::metadata(mount).map_or(false, |meta| meta.dev() == dev))
+}
+
+/// The registry that `registries` (the `filesystem_registries` of the
+/// configuration) gives to the filesystem that `path` is on. A relative path
+/// is relative to the mount point of the filesystem
+pub(crate) fn filesystem_registry(
+    path: &Path,
+    registries: &IndexMap<String, PathBuf>,
+) -> Option<PathBuf> {
+    if registries.is_empty() {
+        return None;
+    }
+
+    let uuid = filesystem_uuid(path)?;
+    registries
+        .iter()
+        .find(|(id, _)| id.eq_ignore_ascii_case(&uuid))
+        .map(|(_, registry)| mount_point(path).join(registry))
+}
+
+/// The registries of `filesystem_registries` that are on a mounted filesystem,
+/// named after the UUID of the filesystem
+pub(crate) fn mounted_registries(
+    registries: &IndexMap<String, PathBuf>,
+) -> impl Iterator<Item = (String, PathBuf)> + '_ {
+    registries.iter().filter_map(|(uuid, registry)| {
+        uuid_mount_point(uuid).map(|mount| (uuid.clone(), mount.join(registry)))
+    })
+}
+
+/// The registries of `filesystem_registries` that are on a mounted filesystem,
+/// with the mount point and the device of the filesystem
+pub(crate) fn mounted_devices(
+    registries: &IndexMap<String, PathBuf>,
+) -> Vec<(PathBuf, PathBuf, u64)> {
+    registries
+        .iter()
+        .filter_map(|(uuid, registry)| {
+            let dev = fs::metadata(Path::new(UUID_DIR).join(uuid)).ok()?.rdev();
+            let mount = uuid_mount_point(uuid)?;
+            Some((mount.join(registry), mount, dev))
+        })
+        .collect()
+}
+
+/// The files that a run of a command changes when the files are routed to the
+/// registries of their filesystems (see `filesystem_registries`)
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Filesystems {
+    /// Every file
+    All,
+    /// The files on the device
+    Only(u64),
+    /// The files on none of the devices, which have their own registries
+    Except(Vec<u64>),
+}
+
+impl Default for Filesystems {
+    fn default() -> Self {
+        Self::All
+    }
+}
+
+impl Filesystems {
+    /// Whether the file at `path` is changed by this run. A file that does not
+    /// exist (e.g., a member of an archive) is on the filesystem of its closest
+    /// ancestor that does
+    pub(crate) fn contains(&self, path: &Path) -> bool {
+        if *self == Self::All {
+            return true;
+        }
+
+        let dev = path
+            .ancestors()
+            .find_map(|p| fs::symlink_metadata(p).ok())
+            .map(|meta| meta.dev());
+        match (self, dev) {
+            (Self::Only(only), Some(dev)) => *only == dev,
+            (Self::Except(devs), Some(dev)) => !devs.contains(&dev),
+            (Self::Only(_), None) => false,
+            _ => true,
+        }
+    }
+}
+
+/// Undo the escaping of the spaces, tabs, newlines, and backslashes in a mount
+/// point of `/proc/self/mounts` (e.g., `\040` is a space)
+fn unescape_mount(mount: &str) -> String {
+    MOUNT_ESCAPES
+        .iter()
+        .fold(mount.to_owned(), |mount, (from, to)| {
+            mount.replace(from, to)
+        })
+}
+
+/// Record that the filesystem of `path` has the given `support`, returning its
+/// mount point. The table is saved when it changes
+pub(crate) fn record(path: &Path, support: XattrSupport) -> PathBuf {
+    let mount = mount_point(path);
+    let mut table = match SUPPORT.lock() {
+        Ok(table) => table,
+        Err(_) => return mount,
+    };
+
+    // A tag that fit does not mean that every tag will
+    if support == XattrSupport::Yes && table.get(&mount) == Some(&XattrSupport::SizeLimit) {
+        return mount;
+    }
+
+    if table.insert(mount.clone(), support) != Some(support) {
+        let file = get_cache_path().join(XATTR_SUPPORT_FILE);
+        if let Err(e) = serde_yaml::to_vec(&*table)
+            .map_err(|e| e.to_string())
+            .and_then(|data| fs::write(&file, data).map_err(|e| e.to_string()))
+        {
+            wutag_error!("failed to write {}: {}", file.display(), e);
+        }
+    }
+
+    mount
+}
+
+/// The mount points that are known, and whether they keep tags
+pub(crate) fn table() -> BTreeMap<PathBuf, XattrSupport> {
+    SUPPORT.lock().map(|t| t.clone()).unwrap_or_default()
+}
+
+/// Read the `tag` back from `path` after it was written, since some
+/// filesystems accept an extended attribute without keeping it
+pub(crate) fn verify_write(path: &Path, tag: &Tag) -> wutag_core::Result<()> {
+    if list_tags(path)?.iter().any(|t| t.name() == tag.name()) {
+        record(path, XattrSupport::Yes);
+        Ok(())
+    } else {
+        record(path, XattrSupport::No);
+        Err(Error::XattrDropped)
+    }
+}
+
+/// What an error that happened while tagging a file says about whether its
+/// filesystem keeps tags, or `None` if the error is not caused by it
+fn support_of(err: &Error) -> Option<XattrSupport> {
+    match err {
+        Error::XattrsUnsupported | Error::XattrDropped => Some(XattrSupport::No),
+        Error::XattrTooLarge => Some(XattrSupport::SizeLimit),
+        _ => None,
+    }
+}
+
+/// Record the support of the filesystem of `path` when an error that happened
+/// while tagging it is caused by the filesystem. This is done whether or not
+/// the error is displayed, so that `--quiet` still fills the table
+pub(crate) fn record_error(path: &Path, err: &Error) {
+    if let Some(support) = support_of(err) {
+        record(path, support);
+    }
+}
+
+/// Describe an error that happened while tagging `path`, naming the mount
+/// point when the error is caused by its filesystem
+pub(crate) fn describe(path: &Path, err: &Error) -> String {
+    if support_of(err).is_none() {
+        return messages::error_text(err);
+    }
+
+    let mount = mount_point(path);
+    let text = messages::error_text(err);
+    match text.split_once('\n') {
+        Some((short, long)) => format!(
+            "{} (mounted at {})\n{}",
+            short,
+            mount.display().to_string().bold(),
+            long
+        ),
+        None => format!("{} (mounted at {})", text, mount.display().to_string().bold()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescapes_mount_points() {
+        assert_eq!(unescape_mount("/media/usb"), "/media/usb");
+        assert_eq!(unescape_mount("/media/My\\040Drive"), "/media/My Drive");
+        assert_eq!(unescape_mount("/media/a\\134040b"), "/media/a\\040b");
+    }
+
+    #[test]
+    fn skips_unmapped_filesystems() {
+        assert_eq!(filesystem_registry(Path::new("/"), &IndexMap::new()), None);
+        assert_eq!(uuid_mount_point("not-a-uuid"), None);
+        assert!(mounted_devices(&IndexMap::new()).is_empty());
+    }
+
+    #[test]
+    fn routes_files_by_device() {
+        let dev = fs::metadata("/").unwrap().dev();
+        assert!(Filesystems::All.contains(Path::new("/")));
+        assert!(Filesystems::Only(dev).contains(Path::new("/")));
+        assert!(!Filesystems::Except(vec![dev]).contains(Path::new("/")));
+        assert!(Filesystems::Only(dev).contains(Path::new("/no-such-file.zip/member")));
+        assert!(Filesystems::Except(vec![dev + 1]).contains(Path::new("/")));
+    }
+}
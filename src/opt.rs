@@ -1,24 +1,58 @@
 //! Options used by the main executable
-use clap::{crate_version, AppSettings, ArgSettings, Parser, Subcommand, ValueHint};
-use std::{env, fs, path::PathBuf};
+use clap::{crate_version, AppSettings, Arg, ArgSettings, IntoApp, Parser, Subcommand, ValueHint};
+use colored::Colorize;
+use serde_yaml::Value;
+use std::{env, ffi::OsString, fs, path::PathBuf};
 
 use crate::{
+    config::{Config, Defaults},
     consts::{AFTER_HELP, APP_ABOUT, APP_AUTHORS, DEFAULT_EDITOR, FILE_TYPE, OVERRIDE_HELP},
+    diagnostics::FailOn,
+    filesystem::{OwnerFilter, PermFilter, SizeFilter},
     subcommand::{
+        clean_cache::CleanCacheOpts,
         clear::ClearOpts,
+        complete::CompleteOpts,
         cp::CpOpts,
+        debug::DebugOpts,
+        dirs::DirsOpts,
         edit::EditOpts,
+        env::EnvOpts,
+        expire::ExpireOpts,
+        explain_config::ExplainConfigOpts,
+        git_sync::GitSyncOpts,
+        hook::{CheckStagedOpts, HookOpts},
         info::InfoOpts,
+        lint::LintOpts,
         list::{ListObject, ListOpts},
+        migrate_xattrs::MigrateXattrsOpts,
+        preserve::PreserveOpts,
         print_completions::CompletionsOpts,
+        recover::RecoverOpts,
+        reg::RegistryOpts,
         repair::RepairOpts,
         rm::RmOpts,
         search::SearchOpts,
+        serve::ServeOpts,
+        service::ServiceOpts,
         set::SetOpts,
+        snapshot::SnapshotOpts,
+        stash::StashOpts,
+        sync::SyncOpts,
+        top::TopOpts,
         view::ViewOpts,
+        which::WhichOpts,
     },
+    wutag_error,
 };
 
+#[cfg(feature = "auth")]
+use crate::subcommand::auth::AuthOpts;
+#[cfg(feature = "encrypt-gpgme")]
+use crate::{config::PinentryMode, subcommand::recipients::RecipientsOpts};
+#[cfg(feature = "media")]
+use crate::subcommand::media::{ExportOpts, ImportOpts};
+
 // INFO: The fully qualified path is needed after adding 'notify-rust' to
 // dependencies
 #[derive(Parser, Default, Clone, Debug, PartialEq)]
@@ -124,7 +158,8 @@ pub(crate) struct Opts {
                       'search', 'list', 'rm', and 'clear'."
     )]
     pub(crate) global:           bool,
-    /// Respect 'LS_COLORS' environment variable when coloring the output
+    /// Respect 'LS_COLORS' environment variable when coloring the output (unless
+    /// color is disabled)
     #[clap(long, short = 'l', conflicts_with = "color")]
     pub(crate) ls_colors:        bool,
     /// When to colorize output
@@ -134,7 +169,9 @@ pub(crate) struct Opts {
         possible_values = &["never", "auto", "always"],
         long_about = "\
         When to colorize output (usually meant for piping). Valid values are: always, \
-        auto, never. The always selection only applies to the path as of now."
+        auto, never. Without this flag, color is disabled when the 'NO_COLOR' environment \
+        variable is set and forced when 'CLICOLOR_FORCE' is set. Otherwise output is colored \
+        only on a terminal. Porcelain output is never colored."
     )]
     pub(crate) color_when:       Option<String>,
     /// File-type(s) to filter by: f|file, d|directory, l|symlink, e|empty
@@ -164,6 +201,49 @@ pub(crate) struct Opts {
     )]
     /// Filter results by file extension
     pub(crate) extension:        Option<Vec<String>>,
+    /// Filter results by file size: e.g., +10M, -1G
+    #[clap(
+        long = "size",
+        number_of_values = 1,
+        multiple_occurrences = true,
+        takes_value = true,
+        value_name = "size",
+        allow_hyphen_values = true,
+        long_about = "\
+        Filter results by the size of regular files (taken from 'fd'). '+' matches files of at \
+                      least, '-' of at most, and no sign of exactly the size. Units are 'b', 'k', \
+                      'm', 'g' and 't' (powers of 1000) or 'ki', 'mi', 'gi' and 'ti' (powers of \
+                      1024), e.g., 'wutag --size +1G set . big'. Can be used multiple times: e.g., \
+                      --size +1k --size -10M
+        "
+    )]
+    pub(crate) size:             Option<Vec<SizeFilter>>,
+    /// Filter results by owner: user, :group, user:group
+    #[clap(
+        long = "owner",
+        takes_value = true,
+        value_name = "user:group",
+        long_about = "\
+        Filter results by the user and/or group that own them (taken from 'fd'). Either one can \
+                      be a name or an id, and a leading '!' matches files not owned by it, e.g., \
+                      --owner john, --owner :staff, --owner '!root:wheel'
+        "
+    )]
+    pub(crate) owner:            Option<OwnerFilter>,
+    /// Filter results by permissions: 644, -u+x, /u+w,g+w
+    #[clap(
+        long = "perm",
+        takes_value = true,
+        value_name = "mode",
+        allow_hyphen_values = true,
+        long_about = "\
+        Filter results by their permission bits (like 'find -perm'). The mode is octal (644) or \
+                      symbolic (u=rw,go=r). Without a prefix the bits must be exactly the mode, \
+                      with '-' all of the bits of the mode must be set, and with '/' any of them, \
+                      e.g., --perm /u+w,g+w
+        "
+    )]
+    pub(crate) perm:             Option<PermFilter>,
     #[clap(
         long = "exclude", short = 'E',
         number_of_values = 1,
@@ -188,15 +268,184 @@ pub(crate) struct Opts {
                       available to users"
     )]
     pub(crate) quiet:            bool,
+    /// Exit with an error if there were any warnings
+    #[clap(
+        long = "strict",
+        long_about = "Problems with a single file, such as a file that cannot be read or tagged, \
+                      are warnings. They are displayed and the command continues with the next \
+                      file. With this flag the command exits with an error if there were any \
+                      warnings"
+    )]
+    pub(crate) strict:           bool,
+    /// Exit with an error when nothing matched or some files failed
+    #[clap(
+        long = "fail-on",
+        number_of_values = 1,
+        multiple_occurrences = true,
+        takes_value = true,
+        value_name = "when",
+        possible_values = &["no-matches", "partial", "never"],
+        long_about = "\
+        Exit with an error although the command itself succeeded, so that scripts can branch on \
+                      the exit code instead of parsing the output. With 'no-matches' it exits \
+                      with 3 when the pattern or query matched no files (e.g., a 'search' without \
+                      results), and with 'partial' it exits with 4 when some of the files could \
+                      not be read or tagged. 'never' (default) only exits with an error on \
+                      errors. Can be used multiple times: e.g., --fail-on no-matches --fail-on \
+                      partial"
+    )]
+    pub(crate) fail_on:          Option<Vec<FailOn>>,
+    /// Explain the cause of errors
+    #[clap(
+        long = "verbose-errors",
+        long_about = "Display a long explanation of the cause after errors that have one, such \
+                      as a filesystem that does not support extended attributes. Only the short \
+                      form is displayed by default so that the failing path is easy to find"
+    )]
+    pub(crate) verbose_errors:   bool,
+    /// Only hash the start and end of files along with their size
+    #[clap(
+        long = "quick-hash",
+        long_about = "\
+        Only hash the first and last 'quick_hash_size' KiB (default: 64) of a file along with its \
+                      size when adding it to the registry, instead of the entire file. This is \
+                      much faster for large media files. Can also be set with 'quick_hash' in \
+                      the configuration file"
+    )]
+    pub(crate) quick_hash:       bool,
+    /// Do not display the icons of tags
+    #[clap(
+        long = "no-icons",
+        long_about = "Do not display the icons that are set with 'edit --icon' or the 'icons' \
+                      map of the configuration file before tags. Meant for terminals without a \
+                      font that has the glyphs. Can also be set with 'no_icons' in the \
+                      configuration file"
+    )]
+    pub(crate) no_icons:         bool,
+    /// Keep tags in a namespace of the current user
+    #[clap(
+        long = "per-user",
+        long_about = "\
+        Read and write tags in the namespace of the current user ('user.wutag.<uid>') instead of \
+                      the namespace shared by every user, so that users of a shared directory \
+                      have their own tags. Can also be set with 'per_user' in the configuration \
+                      file"
+    )]
+    pub(crate) per_user:         bool,
+    /// Use every path of a file that is reached through several paths
+    #[clap(
+        long = "show-duplicates",
+        long_about = "\
+        A file that is reached through several paths, such as a symlink and its target or two \
+                      hard links, is only matched and listed once by default, using the path \
+                      that is reached first. This uses every path instead. Can also be set with \
+                      'show_duplicates' in the configuration file"
+    )]
+    pub(crate) show_duplicates:  bool,
+    /// Display output in a stable, machine-readable format
+    #[clap(
+        long = "porcelain",
+        value_name = "version",
+        min_values = 0,
+        require_equals = true,
+        default_missing_value = "v1",
+        possible_values = &["v1", "json"],
+        group = "machine-readable",
+        conflicts_with = "color",
+        long_about = "\
+        Display output in a stable, versioned, machine-readable format (default: v1). Fields are \
+                      tab-separated, paths are absolute, and tabs/newlines inside of a field are \
+                      escaped. Fields are guaranteed to not be reordered within a version. With \
+                      'json', each record is a JSON object on its own line. Only applies to \
+                      'list' and 'search'."
+    )]
+    pub(crate) porcelain:        Option<String>,
+    /// Display output in a machine-readable format (same as '--porcelain')
+    #[clap(
+        long = "output",
+        value_name = "format",
+        possible_values = &["v1", "json"],
+        group = "machine-readable",
+        conflicts_with = "color",
+        long_about = "\
+        The same as '--porcelain', but the format is required and can be given as the next \
+                      argument (e.g., '--output json')"
+    )]
+    pub(crate) output:           Option<String>,
+    /// Include the color and effects of each tag in JSON output
+    #[clap(
+        long = "with-style",
+        requires = "machine-readable",
+        long_about = "\
+        Include the color (in hex notation), the effects (e.g., bold), and the icon of each tag \
+                      in the output of '--porcelain=json' or '--output json', so that a frontend \
+                      can display tags like the terminal does without knowing how wutag picks \
+                      their style"
+    )]
+    pub(crate) with_style:       bool,
+    /// Display output that is easy to follow with a screen reader
+    #[clap(
+        long = "plain",
+        conflicts_with_all = &["machine-readable", "color"],
+        long_about = "\
+        Display output that reads well with a screen reader: no color, icons, box-drawing \
+                      characters, or columns that are padded to line up. Files are displayed \
+                      as 'path: tag, tag (tag=value)' and tags as 'tag: count files'. Only \
+                      applies to 'list' and 'search'; other commands only lose their color"
+    )]
+    pub(crate) plain:            bool,
+    /// Tag files on another machine over SSH (e.g., user@host)
+    #[clap(
+        long = "remote",
+        value_name = "user@host",
+        long_about = "\
+        Set the tags of files on another machine by running 'getfattr' and 'setfattr' on it \
+                      over SSH, while the registry stays on this machine. The files are read \
+                      from stdin as absolute paths (e.g., 'ssh host find ~/photos | wutag \
+                      --remote host set --stdin <tag>'), and are added to the registry as \
+                      '<host>:<path>'. Only applies to 'set --stdin'"
+    )]
+    pub(crate) remote:           Option<String>,
+    /// Ask for passphrases on the current TTY
+    #[cfg(feature = "encrypt-gpgme")]
+    #[clap(
+        long = "gpg-tty",
+        long_about = "\
+        Set 'GPG_TTY' to the current TTY when it is not set, so that a curses pinentry program \
+                      asks for the passphrase of the key that decrypts the registry on this \
+                      terminal. Can also be set with 'tty' in the 'encryption' configuration"
+    )]
+    pub(crate) gpg_tty:          bool,
+    /// How gpg-agent asks for passphrases
+    #[cfg(feature = "encrypt-gpgme")]
+    #[clap(
+        long = "pinentry-mode",
+        value_name = "mode",
+        possible_values = &["default", "ask", "cancel", "error", "loopback"],
+        long_about = "\
+        How gpg-agent asks for the passphrase of a key. With 'loopback', no pinentry program is \
+                      run and the passphrase is read from the file descriptor in \
+                      'WUTAG_PASSPHRASE_FD' (e.g., 'WUTAG_PASSPHRASE_FD=3 wutag list 3<file'), \
+                      which is meant for scripts and cron jobs. 'loopback' is used when \
+                      'WUTAG_PASSPHRASE_FD' is set and this is not. 'error' fails instead of \
+                      waiting for a passphrase. Can also be set with 'pinentry_mode' in the \
+                      'encryption' configuration"
+    )]
+    pub(crate) pinentry_mode:    Option<PinentryMode>,
     #[clap(subcommand)]
     pub(crate) cmd:              Command,
 }
 
 impl Opts {
-    /// Allows a default command to run if no arguments are passed
-    pub(crate) fn get_args() -> Self {
+    /// Allows a default command to run if no arguments are passed. The
+    /// `defaults` of the subcommand in the configuration are used for the
+    /// options that are not given
+    pub(crate) fn get_args(config: &Config) -> Self {
         if env::args_os().len() > 1 {
-            Self::parse()
+            match &config.defaults {
+                Some(defaults) => Self::parse_from(with_defaults(env::args_os(), defaults)),
+                None => Self::parse(),
+            }
         } else {
             Self {
                 // May switch to global
@@ -223,14 +472,129 @@ impl Opts {
     }
 }
 
+/// Convert an option from the `defaults` of the configuration to arguments.
+/// `true` is a flag, a list is given once per item, and anything else is the
+/// option's value
+fn default_args(long: &str, value: &Value) -> Vec<OsString> {
+    let arg = |v: &str| OsString::from(format!("--{}={}", long, v));
+    match value {
+        Value::Bool(true) => vec![OsString::from(format!("--{}", long))],
+        Value::Bool(false) | Value::Null => vec![],
+        Value::String(s) => vec![arg(s)],
+        Value::Number(n) => vec![arg(&n.to_string())],
+        Value::Sequence(seq) => seq.iter().flat_map(|v| default_args(long, v)).collect(),
+        Value::Mapping(_) => {
+            wutag_error!("invalid value in the defaults: {}", long);
+            vec![]
+        },
+    }
+}
+
+/// Insert the options from the `defaults` of the configuration right after the
+/// subcommand in `args`. An option that is already given is left out, so that
+/// the command line always takes precedence
+fn with_defaults<I>(args: I, defaults: &Defaults) -> Vec<OsString>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let mut args = args.into_iter().collect::<Vec<_>>();
+    let app = Opts::into_app();
+    let takes_value = |a: &Arg<'_>| a.is_set(ArgSettings::TakesValue);
+
+    // Whether a global option is followed by its value as the next argument. In
+    // a group of short flags (e.g., `-dc`), the rest of the group after the
+    // first one that takes a value is that value
+    let skips_next = |given: &str| match given.strip_prefix("--") {
+        Some(long) =>
+            !long.contains('=')
+                && app
+                    .get_arguments()
+                    .any(|a| a.get_long() == Some(long) && takes_value(a)),
+        None => {
+            let shorts = given[1..].chars().collect::<Vec<_>>();
+            shorts
+                .iter()
+                .position(|c| {
+                    app.get_arguments()
+                        .any(|a| a.get_short() == Some(*c) && takes_value(a))
+                })
+                .map_or(false, |pos| pos + 1 == shorts.len())
+        },
+    };
+
+    // Find the subcommand, skipping the values of the global options before it
+    let mut found = None;
+    let mut skip = false;
+    for (idx, arg) in args.iter().enumerate().skip(1) {
+        let arg = arg.to_string_lossy();
+        if skip {
+            skip = false;
+        } else if arg.starts_with('-') {
+            skip = skips_next(&arg);
+        } else {
+            found = app
+                .get_subcommands()
+                .find(|s| s.get_name() == arg || s.get_all_aliases().any(|a| a == arg))
+                .map(|s| (idx, s));
+            break;
+        }
+    }
+    let (idx, sub) = match found {
+        Some(found) => found,
+        None => return args,
+    };
+    let options = match defaults.get(sub.get_name()) {
+        Some(options) => options,
+        None => return args,
+    };
+
+    // The options that are given are the ones clap finds, which handles groups
+    // of short flags and the values that look like flags. Arguments that do not
+    // parse are reported by clap afterwards, when every default is used
+    let matches = app.clone().try_get_matches_from(&args).ok();
+    let given = matches
+        .as_ref()
+        .and_then(|m| m.subcommand_matches(sub.get_name()));
+
+    let mut inserted = Vec::new();
+    for (key, value) in options {
+        let long = key.replace('_', "-");
+        let found = sub
+            .get_arguments()
+            .find(|a| a.get_long() == Some(long.as_str()));
+        let arg = match found {
+            Some(arg) => arg,
+            None => {
+                wutag_error!(
+                    "unknown option in the defaults of {}: {}",
+                    sub.get_name().bold(),
+                    key
+                );
+                continue;
+            },
+        };
+
+        let is_given = given.map_or(false, |m| m.occurrences_of(arg.get_name()) > 0);
+        if !is_given {
+            inserted.extend(default_args(&long, value));
+        }
+    }
+
+    args.splice(idx + 1..idx + 1, inserted);
+    args
+}
+
 impl Default for Command {
     fn default() -> Self {
         Self::List(ListOpts {
             object: ListObject::Files {
-                with_tags: true,
-                formatted: true,
-                border:    false,
-                garrulous: false,
+                with_tags:   true,
+                formatted:   true,
+                border:      false,
+                garrulous:   false,
+                show_others: false,
+                group_by:    None,
+                collapse:    false,
             },
             raw:    false,
         })
@@ -284,8 +648,33 @@ pub(crate) enum Command {
     #[clap(override_usage = "wutag [FLAG/OPTIONS] search [FLAG/OPTIONS] <pattern>")]
     Search(SearchOpts),
     /// Copies tags from the specified file to files that match a pattern
-    #[clap(override_usage = "wutag [FLAG/OPTIONS] cp [FLAG/OPTIONS] <input_path> <pattern>")]
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] cp [FLAG/OPTIONS] <input_path> <pattern> | \
+                          --from-query <query> --to <pattern>"
+    )]
     Cp(CpOpts),
+    /// Run a command like 'cp' and copy the tags of the sources to the files it wrote
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] preserve -- <command>...",
+        long_about = "\
+        Run a command that copies or moves files without preserving extended attributes, such as \
+        'cp', 'mv', or 'install', and then copy the tags of each source to its destination. The \
+        destinations are read from the command's verbose output ('src' -> 'dst') when there is \
+        one, and are otherwise inferred from its arguments, e.g., 'wutag preserve -- cp -r src \
+        dst'. Exits with the status of the command"
+    )]
+    Preserve(PreserveOpts),
+    /// Rewrite the tags of files in another layout of extended attributes
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] migrate-xattrs [--to <legacy|single>] <pattern>",
+        long_about = "\
+        Rewrite the tags of the files that match the pattern in another layout of extended \
+        attributes: 'legacy', with an attribute per tag ('user.wutag.<tag>'), which older \
+        versions of wutag read, or 'single', with every tag of a file in one attribute \
+        ('user.wutag.tags'). Tags are read from both layouts, and 'xattr_layout' in the \
+        configuration chooses the one new tags are written in"
+    )]
+    MigrateXattrs(MigrateXattrsOpts),
     /// View the results in an editor (optional pattern)
     #[clap(override_usage = "wutag [FLAG/OPTIONS] view [FLAG/OPTIONS] -p [<pattern>]")]
     View(ViewOpts),
@@ -294,6 +683,26 @@ pub(crate) enum Command {
     Edit(EditOpts),
     /// Display information about the wutag environment
     Info(InfoOpts),
+    /// Display the shell, variables, and completions `wutag` is used with
+    #[clap(
+        override_usage = "wutag env [--shell <shell>] [--install]",
+        long_about = "\
+        Display the shell detected from '$SHELL', the environment variables that change where \
+        'wutag' keeps its files, the locations of those files, and whether the completions of \
+        the shell are installed and complete the names of tags. '--install' writes the \
+        completions to the directory the shell loads them from"
+    )]
+    Env(EnvOpts),
+    /// Display every setting with where it comes from
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] explain-config [<key>]",
+        long_about = "\
+        Display every setting that is in effect and where it comes from: the default, the line \
+        of the configuration file that sets it, an environment variable, or a flag given on the \
+        command-line (e.g., 'wutag --max-depth 4 explain-config max_depth'). A key such as 'tui' \
+        only displays the settings under it"
+    )]
+    ExplainConfig(ExplainConfigOpts),
     /// Repair broken/missing/modified files in the registry
     #[clap(
         aliases = &["fix", "rep", "repa", "repai"],
@@ -303,6 +712,219 @@ pub(crate) enum Command {
         Alias: fix"
     )]
     Repair(RepairOpts),
+    /// Replay or undo a batch of 'set' that was interrupted
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] recover [--rollback] [--dry-run]",
+        long_about = "\
+        Before 'set' changes the tags of a file, it writes them to a journal next to the \
+        registry, which is removed once the registry is saved. If the batch was interrupted \
+        (e.g., by a power loss), the journal is left behind and this replays it. With \
+        '--rollback', the files are given back the tags they had before the batch instead"
+    )]
+    Recover(RecoverOpts),
+    /// Add, remove, or list the keys that the registry is encrypted to
+    #[cfg(feature = "encrypt-gpgme")]
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] recipients <SUBCOMMAND> [<key>...]",
+        long_about = "\
+        Manage the keys that the registry and the tags written with 'set --encrypt' are \
+        encrypted to, in addition to 'public_key'. A key is given as an email, a short \
+        fingerprint, or a full fingerprint, and is kept in a file next to the registry. Every \
+        key is checked to be usable before anything is changed, then the encrypted tags of \
+        the files in the registry are re-encrypted to the new set of keys"
+    )]
+    Recipients(RecipientsOpts),
+    /// Remove tags whose `expires=<date>` value has passed
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] expire [FLAG/OPTIONS]",
+        long_about = "\
+        Remove tags that have a value of 'expires=<date>' (e.g., set with \
+        'wutag set <pattern> toread=expires=2024-07-01' or 'wutag set --temp 2d') once \
+        the date has passed. Optionally replace them with another tag. 'search' already \
+        leaves out the files of tags that expired before they are removed"
+    )]
+    Expire(ExpireOpts),
+    /// Tag files with their XMP keywords or from a beets or calibre library
+    #[cfg(feature = "media")]
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] import --from <xmp|beets|calibre> [<pattern>]",
+        long_about = "\
+        Tag the files that match the pattern with the keywords ('dc:subject') of their XMP \
+        metadata, which photo managers like Darktable and digiKam keep tags in. The keywords are \
+        read from a sidecar next to the file ('photo.jpg.xmp' or 'photo.xmp'), or else from the \
+        XMP embedded in the file itself. '--from beets' and '--from calibre' instead tag the \
+        files of those libraries: the genres of a track become tags and its album, artist, and \
+        year become values, and the tags of a book stay tags and its series and author become \
+        values. A pattern then only filters the files of the library"
+    )]
+    Import(ImportOpts),
+    /// Write the tags of files to XMP sidecars
+    #[cfg(feature = "media")]
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] export --to xmp [--replace] <pattern>",
+        long_about = "\
+        Write the tags of the files that match the pattern as the keywords ('dc:subject') of \
+        their XMP sidecars, which are created as 'photo.jpg.xmp' if they do not exist. The \
+        keywords a sidecar already has are kept unless '--replace' is given, and so is the rest \
+        of it, such as the history of edits that Darktable keeps there"
+    )]
+    Export(ExportOpts),
+    /// Report files that are missing the tags required by a directory's policy
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] lint [--quota-check] [<dir>]",
+        long_about = "\
+        Report the files that are missing any of the tags that are required by the closest \
+        '.wutag.toml' policy file (e.g., 'require = [\"project\", \"status\"]'). Exits with a \
+        non-zero status if any files are missing tags. '--quota-check' also warns about files \
+        with too many tags and tags on too many files"
+    )]
+    Lint(LintOpts),
+    /// Share the registry with other machines over the network
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] serve [--http[=<addr>]]",
+        long_about = "\
+        Serve the registry over HTTP so that it can be shared between machines. Clients use it \
+        by setting 'registry' in the configuration file, or '--registry', to 'http://<addr>'. \
+        The whole registry is sent when a client saves, so the last client to save wins"
+    )]
+    Serve(ServeOpts),
+    /// Manage the tokens that clients of 'serve' authenticate with
+    #[cfg(feature = "auth")]
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] auth <SUBCOMMAND> [<name|url>]",
+        long_about = "\
+        Manage the tokens of 'serve', which are kept in the keyring of the OS. On the server, \
+        'add <name>' creates a token and prints it, 'remove <name>' stops accepting it, and \
+        'list' lists their names. Once there is a token, the server refuses requests without \
+        one. On a client, 'login <url>' saves the token read from stdin for the server at \
+        <url>, and 'logout <url>' removes it. 'WUTAG_TOKEN' takes precedence over a saved \
+        token"
+    )]
+    Auth(AuthOpts),
+    /// Maintain the registry on a schedule with systemd
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] service <SUBCOMMAND> [--maintain <when>]",
+        long_about = "\
+        Write a user-level systemd service and timer that expire tags and update the hashes of \
+        modified files in the registry. 'install --maintain daily|weekly|monthly' writes the \
+        units to '~/.config/systemd/user' and enables the timer, 'uninstall' removes them, and \
+        'status' displays when the timer last ran and will run next"
+    )]
+    Service(ServiceOpts),
+    /// Merge the tags of another registry into the current one
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] sync [FLAG/OPTIONS] <registry>",
+        long_about = "\
+        Merge the tags and values of another registry (a path or 'http://<addr>') into the \
+        current one, so that two machines tagging the same synced tree can converge. Tags are \
+        unioned, and when both registries have a different value for a tag, the value from the \
+        entry with the newest modification time is kept. Run it on both machines to converge"
+    )]
+    Sync(SyncOpts),
+    /// Move the tags of files that git renamed
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] git-sync [FLAG/OPTIONS]",
+        long_about = "\
+        Inside of a git repository, find the files that were renamed between the last synced \
+        commit and HEAD with 'git diff --find-renames', then move their entries in the registry \
+        and set their tags again on the renamed files. The first run only records HEAD"
+    )]
+    GitSync(GitSyncOpts),
+    /// Print a hook script that lets tags enforce a repository's policies
+    #[clap(
+        override_usage = "wutag hook git pre-commit --deny-tag <tag>...",
+        long_about = "\
+        Print a git hook script that blocks commits of staged files that have any of the denied \
+        tags. Install it with 'wutag hook git pre-commit --deny-tag confidential > \
+        .git/hooks/pre-commit && chmod +x .git/hooks/pre-commit'"
+    )]
+    Hook(HookOpts),
+    /// Fail if a file staged in git has a denied tag (used by 'hook')
+    #[clap(hide = true, override_usage = "wutag check-staged --deny-tag <tag>...")]
+    CheckStaged(CheckStagedOpts),
+    /// Commands that help with developing wutag, such as creating test fixtures
+    #[clap(
+        hide = true,
+        override_usage = "wutag [FLAG/OPTIONS] debug generate-fixture [--files <N>] [--tags <M>] \
+                          [--seed <S>] <dir>"
+    )]
+    Debug(DebugOpts),
+    /// Save, restore, list, or compare copies of the registry
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] snapshot <SUBCOMMAND> [<name|label>]",
+        long_about = "\
+        Keep point-in-time copies of the registry next to it in 'snapshots/', e.g., before a \
+        risky bulk operation. 'create [label]' saves a copy, 'list' lists them, 'diff <name>' \
+        displays what has changed since, and 'restore <name>' replaces the registry and re-tags \
+        the files to match. Only the newest 'max_snapshots' (default: 10) are kept"
+    )]
+    Snapshot(SnapshotOpts),
+    /// Maintain the registry itself, such as moving the files under a directory
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] registry rebase <old> <new>",
+        long_about = "\
+        'rebase <old> <new>' moves every file under the directory <old> in the registry to the \
+        same place under <new>, keeping its tags and values, e.g., after a share was mounted \
+        somewhere else. The files themselves are not touched. To use a share from several \
+        mounts at once, set 'strip_prefix' in the configuration instead"
+    )]
+    Registry(RegistryOpts),
+    /// Remove the tags from files and keep them in the registry to put back later
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] stash [--name <name>] <pattern> | pop <name> | list",
+        long_about = "\
+        Remove every tag from the files matching the pattern, and keep them in the registry under \
+        a named stash (default: the current date and time), e.g., before sharing a directory or \
+        running a tool that does not handle extended attributes. 'pop <name>' puts the tags and \
+        their values back and removes the stash, and 'list' lists the stashes"
+    )]
+    Stash(StashOpts),
+    /// Print candidates for shell completion, such as the names of tags
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] complete tags [--mru]",
+        long_about = "\
+        Print the names of the tags in the registry one per line, for use in shell completion. \
+        With '--mru', the tags that were used most often and most recently are printed first"
+    )]
+    Complete(CompleteOpts),
+    /// Print the directories that files were tagged in
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] dirs [--frecent [--score]] [--init <shell>]",
+        long_about = "\
+        Print the directories that files were tagged in one per line without any color. With \
+        '--frecent', the directories where files were tagged most often and most recently are \
+        printed first. '--init <shell>' prints a widget that changes to one of them with 'fzf' \
+        on Alt-d, e.g., `eval \"$(wutag dirs --init zsh)\"`"
+    )]
+    Dirs(DirsOpts),
+    /// Print the files that have a tag, like 'command -v'
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] which [-1] <tag>",
+        long_about = "\
+        Print the paths of the files that have the exact tag, sorted and one per line without any \
+        color. With '-1', only the first is printed. Exits with a non-zero status if no file has \
+        the tag, e.g., `vim \"$(wutag -g which -1 todo)\"`"
+    )]
+    Which(WhichOpts),
+    /// Display the tokens that can be used with 'search --exec'
+    #[clap(
+        override_usage = "wutag tokens",
+        long_about = "\
+        Display the tokens that 'search -x|--exec' and '-X|--exec-batch' replace, such as '{}' for \
+        the path of the match. The '{@s}', '{@r}', and '{@c}' tokens take arguments after a colon, \
+        e.g., 'wutag search \'*.rs\' -x {@s:lang=rust,code@#ff0000}'"
+    )]
+    Tokens,
+    /// Display the changes to the registry as they happen
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] top [-n <secs>] [-l <num>]",
+        long_about = "\
+        Display the files that are tagged and untagged by other processes, such as the 'service' \
+        or a script, along with the number of files and tags and how often the registry is \
+        written. The view is updated in place every '-n' seconds (default: 1) until it is \
+        interrupted"
+    )]
+    Top(TopOpts),
     /// Prints completions for the specified shell to dir or stdout
     #[clap(
         display_order = 1000,
@@ -310,8 +932,8 @@ pub(crate) enum Command {
     )]
     PrintCompletions(CompletionsOpts),
     /// Clean the cached tag registry
-    #[clap(override_usage = "wutag [FLAG/OPTIONS] clean-cache")]
-    CleanCache,
+    #[clap(override_usage = "wutag [FLAG/OPTIONS] clean-cache [FLAG/OPTIONS]")]
+    CleanCache(CleanCacheOpts),
     /// Open a TUI to manage tags
     #[clap(
         aliases = &["tui"],
@@ -320,4 +942,87 @@ pub(crate) enum Command {
         Start the TUI to manage the registry interactively. Alias: tui"
     )]
     Ui,
+    /// Narrow down search results interactively
+    #[clap(
+        override_usage = "wutag [FLAG/OPTIONS] repl",
+        long_about = "\
+        Open a prompt where each query narrows the files that matched the previous one, e.g., \
+        'tag(photo)' followed by 'and after(2023)'. Queries can also start with 'or' or 'not'. \
+        Type ':help' at the prompt for the meta-commands, such as ':list', ':exec <cmd>', and \
+        ':save <file>'"
+    )]
+    Repl,
+}
+
+impl Command {
+    /// Whether the command can change the registry. The registry of any other
+    /// command is loaded read-only, so that it is never saved over the changes
+    /// of a command running at the same time
+    pub(crate) fn mutates_registry(&self) -> bool {
+        #[cfg(feature = "media")]
+        if matches!(self, Self::Export(_)) {
+            return false;
+        }
+
+        !matches!(
+            self,
+            Self::List(_)
+                | Self::Search(_)
+                | Self::Info(_)
+                | Self::Env(_)
+                | Self::ExplainConfig(_)
+                | Self::Lint(_)
+                | Self::MigrateXattrs(_)
+                | Self::Hook(_)
+                | Self::CheckStaged(_)
+                | Self::Complete(_)
+                | Self::Dirs(_)
+                | Self::Which(_)
+                | Self::Tokens
+                | Self::Top(_)
+                | Self::PrintCompletions(_)
+                | Self::Repl
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn inserts_config_defaults() {
+        let defaults: Defaults =
+            serde_yaml::from_str("list: { raw: true }\nsearch: { raw: true, any_of: [a, b] }")
+                .unwrap();
+
+        assert_eq!(
+            with_defaults(args(&["wutag", "-d", "list", "ls", "tags"]), &defaults),
+            args(&["wutag", "-d", "list", "ls", "--raw", "tags"])
+        );
+        assert_eq!(
+            with_defaults(args(&["wutag", "search", "-r", "*"]), &defaults),
+            args(&["wutag", "search", "--any-of=a", "--any-of=b", "-r", "*"])
+        );
+        assert_eq!(
+            with_defaults(args(&["wutag", "search", "--any-of=c", "*"]), &defaults),
+            args(&["wutag", "search", "--raw", "--any-of=c", "*"])
+        );
+        assert_eq!(
+            with_defaults(args(&["wutag", "-id", "search", "search", "-Gr", "*"]), &defaults),
+            args(&["wutag", "-id", "search", "search", "--any-of=a", "--any-of=b", "-Gr", "*"])
+        );
+        assert_eq!(
+            with_defaults(args(&["wutag", "-dsearch", "search", "*"]), &defaults),
+            args(&["wutag", "-dsearch", "search", "--raw", "--any-of=a", "--any-of=b", "*"])
+        );
+        assert_eq!(
+            with_defaults(args(&["wutag", "set", "*", "tag"]), &defaults),
+            args(&["wutag", "set", "*", "tag"])
+        );
+    }
 }
@@ -0,0 +1,286 @@
+//! Stable, versioned machine-readable output. Unlike the human readable output,
+//! which is colored and may change between releases, the porcelain formats are
+//! a contract that scripts can rely on.
+//!
+//! Version 1 (`--porcelain=v1`):
+//!   - One record per line, fields separated by a single tab (`\t`)
+//!   - Paths are always absolute and never colored
+//!   - Backslashes, tabs, carriage returns and newlines inside of a field are
+//!     escaped as `\\`, `\t`, `\r` and `\n` respectively
+//!   - Fields are never reordered. New fields may only be appended to the end of
+//!     a record
+//!
+//! Records:
+//!   - `list files`: `<path>\t<tag>\t<tag>...`
+//!   - `list tags`:  `<tag>\t<count>`
+//!   - `search`:     `<path>\t<tag>\t<tag>...`
+//!   - `search --all-registries`: `<registry>\t<path>\t<tag>\t<tag>...`
+//!
+//! JSON (`--porcelain=json` or `--output json`):
+//!   - One JSON object per line, with the same records as version 1
+//!   - Files are `{"path": ..., "tags": [...]}`, with a `registry` field for
+//!     `search --all-registries`, and tags are `{"tag": ..., "count": ...}`
+//!   - Each tag is an object with its `name`. With `--with-style`, it also has
+//!     the `color` in hex notation, the `effects` it is displayed with, and its
+//!     `icon` when it has one, so that a frontend displays it like the terminal
+//!   - New fields may be added to an object
+//!
+//! Plain (`--plain`):
+//!   - Meant to be read aloud by a screen reader instead of parsed, so it is
+//!     not a contract and may change between releases
+//!   - No color, icons, borders, or columns that are padded to line up
+//!   - Files are `<path>: <tag>, <tag> (<tag>=<value>)`, with `<registry>: `
+//!     before them for `search --all-registries`
+//!   - Tags are `<tag>: <count> files`
+
+use serde_json::{json, Value};
+use std::{borrow::Cow, fmt, str::FromStr};
+use wutag_core::{color::color_hex, tag::Tag};
+
+/// Separator used between fields of a record
+pub(crate) const FIELD_SEPARATOR: char = '\t';
+
+/// Version of the porcelain output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Porcelain {
+    /// Tab-separated output, see module level documentation
+    V1,
+    /// JSON Lines output, see module level documentation
+    Json,
+    /// Output for screen readers, see module level documentation
+    Plain,
+}
+
+/// A tag in a record, along with how it is displayed when `--with-style` is
+/// given
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StyledTag<'a> {
+    pub(crate) tag:   &'a Tag,
+    /// Whether the color, effects, and icon are included
+    pub(crate) style: bool,
+    pub(crate) icon:  Option<&'a str>,
+    /// The value of the tag on the file of the record
+    pub(crate) value: Option<&'a str>,
+}
+
+impl StyledTag<'_> {
+    /// The tag as a JSON object
+    fn to_json(self) -> Value {
+        let mut value = json!({ "name": self.tag.name() });
+        if self.style {
+            value["color"] = json!(color_hex(*self.tag.color()));
+            // Tags are always displayed in bold (see `fmt_tag`)
+            value["effects"] = json!(["bold"]);
+            if let Some(icon) = self.icon {
+                value["icon"] = json!(icon);
+            }
+        }
+        value
+    }
+}
+
+impl Porcelain {
+    /// A record of a file and its tags, in the registry named `registry` when
+    /// searching all of them
+    pub(crate) fn file_record(
+        self,
+        registry: Option<&str>,
+        path: &str,
+        tags: &[StyledTag],
+    ) -> String {
+        match self {
+            Self::V1 => record(
+                registry
+                    .into_iter()
+                    .chain(std::iter::once(path))
+                    .chain(tags.iter().map(|t| t.tag.name())),
+            ),
+            Self::Json => {
+                let mut value = json!({
+                    "path": path,
+                    "tags": tags.iter().map(|t| t.to_json()).collect::<Vec<_>>(),
+                });
+                if let Some(registry) = registry {
+                    value["registry"] = json!(registry);
+                }
+                value.to_string()
+            },
+            Self::Plain => plain_file_record(registry, path, tags),
+        }
+    }
+
+    /// A record of a tag and the number of files it is on
+    pub(crate) fn tag_record(self, tag: StyledTag, count: usize) -> String {
+        match self {
+            Self::V1 => record(&[tag.tag.name(), count.to_string().as_str()]),
+            Self::Json => json!({ "tag": tag.to_json(), "count": count }).to_string(),
+            Self::Plain => format!("{}: {}", tag.tag.name(), plain_count(count)),
+        }
+    }
+}
+
+impl FromStr for Porcelain {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().trim() {
+            "v1" | "1" => Ok(Self::V1),
+            "json" => Ok(Self::Json),
+            v => Err(format!("unsupported porcelain version: {}", v)),
+        }
+    }
+}
+
+impl fmt::Display for Porcelain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::V1 => f.write_str("v1"),
+            Self::Json => f.write_str("json"),
+            Self::Plain => f.write_str("plain"),
+        }
+    }
+}
+
+/// A number of files in the plain format (e.g., `1 file` or `3 files`)
+pub(crate) fn plain_count(count: usize) -> String {
+    format!("{} {}", count, if count == 1 { "file" } else { "files" })
+}
+
+/// A record of a file in the plain format: its path, followed by its tags and
+/// then the values of the tags that have one
+fn plain_file_record(registry: Option<&str>, path: &str, tags: &[StyledTag]) -> String {
+    let mut record = registry.map_or_else(String::new, |name| format!("{}: ", name));
+    record.push_str(path);
+    if tags.is_empty() {
+        return record;
+    }
+
+    let names = tags.iter().map(|t| t.tag.name()).collect::<Vec<_>>();
+    record.push_str(&format!(": {}", names.join(", ")));
+
+    let values = tags
+        .iter()
+        .filter_map(|t| t.value.map(|value| format!("{}={}", t.tag.name(), value)))
+        .collect::<Vec<_>>();
+    if !values.is_empty() {
+        record.push_str(&format!(" ({})", values.join(", ")));
+    }
+
+    record
+}
+
+/// Escape a single field so that it never contains a raw tab or newline
+pub(crate) fn escape(field: &str) -> Cow<str> {
+    if !field.contains(&['\\', '\t', '\n', '\r'][..]) {
+        return Cow::Borrowed(field);
+    }
+
+    let mut escaped = String::with_capacity(field.len() + 4);
+    for ch in field.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+
+    Cow::Owned(escaped)
+}
+
+/// Build a single record out of the given fields
+pub(crate) fn record<I, S>(fields: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    fields
+        .into_iter()
+        .map(|f| escape(f.as_ref()).into_owned())
+        .collect::<Vec<_>>()
+        .join(&FIELD_SEPARATOR.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_special_characters() {
+        assert_eq!(escape("plain"), "plain");
+        assert_eq!(escape("a\tb"), "a\\tb");
+        assert_eq!(escape("a\nb\r"), "a\\nb\\r");
+        assert_eq!(escape("back\\slash"), "back\\\\slash");
+    }
+
+    #[test]
+    fn builds_records() {
+        assert_eq!(record(&["/tmp/a b", "tag", "tab\tbed"]), "/tmp/a b\ttag\ttab\\tbed");
+        assert_eq!("v1".parse::<Porcelain>(), Ok(Porcelain::V1));
+        assert_eq!("JSON".parse::<Porcelain>(), Ok(Porcelain::Json));
+        assert!("v2".parse::<Porcelain>().is_err());
+    }
+
+    #[test]
+    fn builds_json_records() {
+        let tag = Tag::new("code", colored::Color::TrueColor { r: 255, g: 0, b: 0 });
+        let plain = StyledTag {
+            tag:   &tag,
+            style: false,
+            icon:  Some("x"),
+            value: None,
+        };
+        let styled = StyledTag { style: true, ..plain };
+
+        let parse = |record: String| serde_json::from_str::<Value>(&record).unwrap();
+
+        assert_eq!(
+            parse(Porcelain::Json.file_record(None, "/tmp/a", &[plain])),
+            json!({ "path": "/tmp/a", "tags": [{ "name": "code" }] })
+        );
+        assert_eq!(
+            parse(Porcelain::Json.tag_record(styled, 2)),
+            json!({
+                "tag": { "name": "code", "color": "#ff0000", "effects": ["bold"], "icon": "x" },
+                "count": 2
+            })
+        );
+        assert_eq!(
+            Porcelain::V1.file_record(Some("work"), "/tmp/a", &[styled]),
+            "work\t/tmp/a\tcode"
+        );
+    }
+
+    #[test]
+    fn builds_plain_records() {
+        let code = Tag::new("code", colored::Color::Red);
+        let todo = Tag::new("todo", colored::Color::Blue);
+        let tags = [
+            StyledTag {
+                tag:   &code,
+                style: true,
+                icon:  Some("x"),
+                value: None,
+            },
+            StyledTag {
+                tag:   &todo,
+                style: true,
+                icon:  None,
+                value: Some("urgent"),
+            },
+        ];
+
+        assert_eq!(
+            Porcelain::Plain.file_record(None, "/tmp/a", &tags),
+            "/tmp/a: code, todo (todo=urgent)"
+        );
+        assert_eq!(
+            Porcelain::Plain.file_record(Some("work"), "/tmp/a", &tags[..1]),
+            "work: /tmp/a: code"
+        );
+        assert_eq!(Porcelain::Plain.file_record(None, "/tmp/a", &[]), "/tmp/a");
+        assert_eq!(Porcelain::Plain.tag_record(tags[0], 1), "code: 1 file");
+        assert_eq!(Porcelain::Plain.tag_record(tags[1], 3), "todo: 3 files");
+    }
+}
@@ -1,21 +1,43 @@
 // #![allow(dead_code)]
 
 // TODO: look into using an actual database
-
+// TODO: when the registry moves to sqlite, create these indexes in `init` so
+//       that searches over many files are not table scans, and add a
+//       `--profile` flag that prints the time taken by each statement:
+//         - file(hash)
+//         - filetag(tag_id, file_id)
+//         - filetag(value_id)
+//         - tag(name COLLATE unicase)
+
+#[cfg(feature = "archives")]
+use crate::archive;
 use crate::{
-    config::EncryptConfig,
-    encryption::{util, InnerCtx, Plaintext, Recipients},
-    filesystem::contained_path,
+    config::{get_data_path, EncryptConfig},
+    crash,
+    encryption::{recipients, util, InnerCtx, Plaintext, Recipients},
+    filesystem::{contained_path, os_path, StripPrefixes},
+    hashing::Hashing,
     opt::Opts,
+    remote,
+    storage::{self, FileStorage, MemoryStorage, Storage},
+    util::parse_expiry,
     wutag_error, wutag_fatal, wutag_info,
 };
 use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDateTime};
 use colored::{Color, Colorize};
 use once_cell::sync::{Lazy, OnceCell};
 use rayon::prelude::*;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 use shellexpand::LookupError;
-use wutag_core::tag::Tag;
+use wutag_core::{
+    event::{ChangeEvent, Observers},
+    tag::Tag,
+    xattr::{follow_symlinks, ssh},
+    XattrNamespace,
+};
+#[cfg(feature = "write-through")]
+use wutag_core::TagStore;
 
 // use rusqlite::{
 //     self as rsq, params,
@@ -25,15 +47,31 @@ use wutag_core::tag::Tag;
 
 use std::{
     borrow::Cow,
-    collections::BTreeMap,
-    env, fs, io,
+    collections::{BTreeMap, BTreeSet},
+    env, fs, iter, mem,
+    ops::Bound,
     path::{Path, PathBuf},
-    sync::atomic::{AtomicBool, Ordering},
-    time::SystemTime,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 /// Name of registry file
-const REGISTRY_FILE: &str = "wutag.registry";
+pub(crate) const REGISTRY_FILE: &str = "wutag.registry";
+/// Number of entries that `--global` listing and searching walk at once
+pub(crate) const ENTRY_PAGE_SIZE: usize = 1024;
+/// Version of `wutag` that is recorded in the registries it saves
+pub(crate) const REGISTRY_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Environment variable that keeps the registry in memory instead of where
+/// `--registry` or the configuration point, so that tests never touch a
+/// registry on disk
+pub(crate) const IN_MEMORY_ENV: &str = "WUTAG_IN_MEMORY";
+/// Minimum Jaro-Winkler similarity of a tag to a name for it to be suggested
+const SIMILAR_TAG_THRESHOLD: f64 = 0.8;
+/// Number of tags that are suggested for a name that is not a tag
+const SIMILAR_TAG_LIMIT: usize = 3;
 /// Only print 'matching key info' once
 static KEY_INFO: Lazy<AtomicBool> = Lazy::new(|| AtomicBool::new(true));
 // static KEY_INFO: Lazy<AtomicUsize> = Lazy::new(|| AtomicUsize::new(0));
@@ -44,8 +82,9 @@ static ENCRYPTION: OnceCell<Result<()>> = OnceCell::new();
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub(crate) struct EntryData {
     /// Path of the file entry with tags
+    #[serde(with = "os_path")]
     path:    PathBuf,
-    /// Blake3 hashsum of the file
+    /// Hashsum of the file, prefixed with how it was hashed (see `Hashing`)
     hash:    String,
     /// File modification time
     modtime: SystemTime,
@@ -64,13 +103,10 @@ impl Default for EntryData {
 impl EntryData {
     /// Generate a new `EntryData` instance
     pub(crate) fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let path = path.as_ref();
-
-        let mut file = fs::File::open(&path)
-            .unwrap_or_else(|_| wutag_fatal!("unable to open the new entry: {}", path.display()));
-        let mut hasher = blake3::Hasher::new();
+        let target = resolve_symlink(path.as_ref());
+        let path = target.as_deref().unwrap_or_else(|| path.as_ref());
 
-        io::copy(&mut file, &mut hasher)?;
+        let hash = Hashing::current().hash_file(path)?;
 
         let modtime = if let Some(modified) = fs::metadata(&path)
             .map(|m| m.modified().ok())
@@ -82,12 +118,72 @@ impl EntryData {
         };
 
         Ok(Self {
-            path: path.to_path_buf(),
-            hash: hasher.finalize().to_string(),
+            path: StripPrefixes::current().local(path).into_owned(),
+            hash,
+            modtime,
+        })
+    }
+
+    /// Generate a new `EntryData` instance for a `member` of an `archive`, with
+    /// the content `data` read by [`archive::read_members`]. The member has the
+    /// modification time of the archive
+    #[cfg(feature = "archives")]
+    pub(crate) fn new_member<P: AsRef<Path>>(
+        archive: P,
+        member: &str,
+        data: &[u8],
+    ) -> Result<Self> {
+        let archive = archive.as_ref();
+
+        let hash = Hashing::current().hash_bytes(data);
+        let modtime = fs::metadata(archive)
+            .and_then(|m| m.modified())
+            .unwrap_or_else(|_| SystemTime::now());
+
+        Ok(Self {
+            path: archive::member_path(archive, member),
+            hash,
+            modtime,
+        })
+    }
+
+    /// Generate a new `EntryData` instance for a file on another machine that
+    /// is tagged with `--remote`. The path is stored as `<host>:<path>`
+    pub(crate) fn new_remote<P: AsRef<Path>>(host: &str, path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = path
+            .to_str()
+            .with_context(|| format!("path is not valid UTF-8: {}", path.display()))?;
+
+        let sum = ssh::run(host, &["sha256sum", "--", file])?;
+        let hash = format!(
+            "sha256:{}",
+            sum.split_whitespace()
+                .next()
+                .context("invalid output of sha256sum")?
+        );
+
+        let modtime = ssh::run(host, &["stat", "-c", "%Y", "--", file])?
+            .trim()
+            .parse()
+            .map_or_else(
+                |_| SystemTime::now(),
+                |secs| UNIX_EPOCH + Duration::from_secs(secs),
+            );
+
+        Ok(Self {
+            path: Self::remote_path(host, path),
+            hash,
             modtime,
         })
     }
 
+    /// The path that a file on another machine is stored under in the
+    /// registry (see [`EntryData::new_remote`])
+    pub(crate) fn remote_path<P: AsRef<Path>>(host: &str, path: P) -> PathBuf {
+        PathBuf::from(format!("{}:{}", host, path.as_ref().display()))
+    }
+
     /// Check whether the file has been modified since it was added to the
     /// registry
     pub(crate) fn changed_since(&self) -> Result<bool> {
@@ -101,16 +197,12 @@ impl EntryData {
         }
     }
 
-    /// Recalculate the file's hash
+    /// Recalculate the file's hash with the configured hashing
     pub(crate) fn recalculate_hash(&mut self) -> Result<()> {
-        let mut hasher = blake3::Hasher::new();
-
-        if let Ok(mut file) = fs::File::open(&self.path) {
-            io::copy(&mut file, &mut hasher)?;
+        if self.path.is_file() {
+            self.hash = Hashing::current().hash_file(&self.path)?;
         }
 
-        self.hash = hasher.finalize().to_string();
-
         Ok(())
     }
 
@@ -156,22 +248,237 @@ impl EntryData {
     pub(crate) fn modtime(&self) -> &SystemTime {
         &self.modtime
     }
+
+    /// Return the hash of the file, prefixed with how it was hashed
+    pub(crate) fn hash(&self) -> &str {
+        &self.hash
+    }
+}
+
+/// The target of the symlink at `path` when symlinks are followed, which is
+/// where its tags are, so that a file tagged through a symlink and directly has
+/// a single entry
+fn resolve_symlink(path: &Path) -> Option<PathBuf> {
+    if !follow_symlinks() {
+        return None;
+    }
+
+    fs::symlink_metadata(path)
+        .ok()
+        .filter(|meta| meta.file_type().is_symlink())
+        .and_then(|_| fs::canonicalize(path).ok())
+}
+
+/// The singular of the tag `name`, lowercased, for finding the tags that only
+/// differ in number (e.g., `photos` and `photo`). This is only a simple
+/// stemming of English plurals
+fn singular(name: &str) -> String {
+    let name = name.to_lowercase();
+
+    if let Some(stem) = name.strip_suffix("ies").filter(|stem| stem.len() > 1) {
+        format!("{}y", stem)
+    } else if let Some(stem) = ["sses", "xes", "ches", "shes", "zzes"]
+        .iter()
+        .find(|end| name.ends_with(*end))
+        .and_then(|_| name.strip_suffix("es"))
+    {
+        stem.to_owned()
+    } else if ["ss", "us", "is"].iter().any(|end| name.ends_with(end)) {
+        name
+    } else {
+        name.strip_suffix('s')
+            .filter(|stem| stem.len() > 2)
+            .map_or_else(|| name.clone(), str::to_owned)
+    }
 }
 
 /// Alias to `usize`, which is a hashed timestamp written to the files extended
 /// attributes
 pub(crate) type EntryId = usize;
 
+/// A change that is made to a file when syncing with another registry
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum SyncChange {
+    /// The tag is added to the file
+    Tag(Tag),
+    /// The value of the tag with the given name is set, replacing the old value
+    /// if there was one
+    Value {
+        tag: String,
+        old: Option<String>,
+        new: String,
+    },
+}
+
+/// How often and how recently a tag was added to a file
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+pub(crate) struct TagUsage {
+    /// Number of times the tag was added to a file
+    pub(crate) count:     u64,
+    /// Last time the tag was added to a file
+    pub(crate) last_used: SystemTime,
+}
+
+impl TagUsage {
+    /// The `count`, halved every `half_life` days since the tag was last used
+    pub(crate) fn score(&self, half_life: f64) -> f64 {
+        let days = SystemTime::now()
+            .duration_since(self.last_used)
+            .unwrap_or_default()
+            .as_secs_f64()
+            / 86400.0;
+
+        self.count as f64 * 0.5_f64.powf(days / half_life.max(f64::EPSILON))
+    }
+}
+
+/// The tags and values of a file that were removed with `wutag stash`, so that
+/// they can be put back
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub(crate) struct StashedEntry {
+    /// The tags that were on the file
+    pub(crate) tags:   Vec<Tag>,
+    /// The values of the tags, keyed by the `Tag`'s name
+    #[serde(default)]
+    pub(crate) values: BTreeMap<String, String>,
+}
+
+/// How many files a value is attached to, and which tags it is attached to
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ValueCount<'a> {
+    /// Number of files that have the value on any of their tags
+    pub(crate) files: usize,
+    /// Names of the tags that the value is attached to
+    pub(crate) tags:  BTreeSet<&'a str>,
+}
+
+/// A change to the registry that is applied along with others by
+/// [`TagRegistry::apply`]. Files are identified by their path
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Op {
+    /// Add the tag to the file, adding the file to the registry if it is not in
+    /// it yet
+    AddTag { path: PathBuf, tag: Tag },
+    /// Remove the tag with the given name from the file
+    RemoveTag { path: PathBuf, tag: String },
+    /// Set the value of a tag that is on the file, replacing the old value if
+    /// there was one
+    SetValue {
+        path:  PathBuf,
+        tag:   String,
+        value: String,
+    },
+    /// Change the path of the file after it was moved, keeping its tags and
+    /// values
+    MoveFile { from: PathBuf, to: PathBuf },
+}
+
+/// How to revert an [`Op`] that [`TagRegistry::apply`] applied, once another
+/// one in the batch failed
+#[derive(Clone, Debug)]
+enum Undo {
+    /// Remove the file that was added to the registry
+    RemoveEntry(EntryId),
+    /// Remove the tag that was added to the file
+    Untag { tag: Tag, id: EntryId },
+    /// Add back the tag that was removed from the file, along with its value
+    /// and the file itself if it was removed with its last tag
+    Retag {
+        tag:   Tag,
+        id:    EntryId,
+        value: Option<String>,
+        entry: Option<EntryData>,
+    },
+    /// Put back the value that was replaced, or remove it if there was none
+    Value {
+        tag:   Tag,
+        id:    EntryId,
+        value: Option<String>,
+    },
+    /// Move the file back to its previous path
+    Move { id: EntryId, from: PathBuf },
+}
+
+/// What happened to an [`Op`] given to [`TagRegistry::apply`]
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum OpStatus {
+    /// The registry was changed
+    Applied,
+    /// The registry already had the change, e.g., the file already had the tag
+    Unchanged,
+    /// The operation could not be applied, so none of the batch was
+    Failed(String),
+    /// The operation could be applied, but another one in the batch failed
+    RolledBack,
+}
+
+/// The status of an [`Op`] given to [`TagRegistry::apply`]
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct OpResult {
+    pub(crate) op:     Op,
+    pub(crate) status: OpStatus,
+}
+
+/// Report of [`TagRegistry::apply`], with a result for each operation in the
+/// order they were given
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ApplyReport {
+    /// Whether the operations changed the registry, which is only the case if
+    /// none of them failed
+    pub(crate) committed: bool,
+    pub(crate) results:   Vec<OpResult>,
+}
+
 /// Representation of the entire registry
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub(crate) struct TagRegistry {
     /// Path to the `TagRegistry`
-    pub(crate) path:    PathBuf,
+    pub(crate) path:       PathBuf,
     /// Hash of the `Tag` name and the file id (`EntryId`) in which these tags
     /// are associated with
-    pub(crate) tags:    BTreeMap<Tag, Vec<EntryId>>,
+    pub(crate) tags:       BTreeMap<Tag, Vec<EntryId>>,
     /// Hash of the file id (`EntryId`) and the entries data (`EntryData`)
-    pub(crate) entries: BTreeMap<EntryId, EntryData>,
+    pub(crate) entries:    BTreeMap<EntryId, EntryData>,
+    /// Hash of the file id (`EntryId`) and the values attached to the entry's
+    /// tags, which are keyed by the `Tag`'s name
+    #[serde(default)]
+    pub(crate) values:     BTreeMap<EntryId, BTreeMap<String, String>>,
+    /// Hash of the `Tag` name and the icon displayed before it
+    #[serde(default)]
+    pub(crate) icons:      BTreeMap<String, String>,
+    /// Hash of the `Tag` name and how often and how recently it was used
+    #[serde(default)]
+    pub(crate) usage:      BTreeMap<String, TagUsage>,
+    /// Hash of a directory and how often and how recently files in it were
+    /// tagged
+    #[serde(default, with = "os_path::keys")]
+    pub(crate) dir_usage:  BTreeMap<PathBuf, TagUsage>,
+    /// Hash of the name of a stash and the tags that were removed from each
+    /// file with `wutag stash`
+    #[serde(default, with = "os_path::nested_keys")]
+    pub(crate) stashes:    BTreeMap<String, BTreeMap<PathBuf, StashedEntry>>,
+    /// Version of `wutag` that last saved the registry. It is `None` for
+    /// registries saved before the version was recorded
+    #[serde(default, serialize_with = "serialize_version")]
+    pub(crate) version:    Option<String>,
+    /// The last time the registry was repaired or its orphans were removed
+    #[serde(default)]
+    pub(crate) maintained: Option<DateTime<Local>>,
+    /// Namespace of the extended attributes that the tags of its files are
+    /// kept in, which is chosen with `set --namespace`
+    #[serde(default)]
+    pub(crate) namespace:  XattrNamespace,
+    /// Where the registry was loaded from and is saved to. A registry that was
+    /// deserialized has none until whoever loaded it attaches one
+    #[serde(skip, default = "storage::detached")]
+    storage:               Arc<dyn Storage>,
+    /// Whether the registry was loaded for a command that does not change it,
+    /// which never saves it
+    #[serde(skip)]
+    read_only:             bool,
+    /// Callbacks that are called with each change to the registry
+    #[serde(skip)]
+    observers:             Observers,
     /* /// The connection to the database
      * pub(crate) connection: rsq::Connection, */
 }
@@ -179,19 +486,7 @@ pub(crate) struct TagRegistry {
 impl Default for TagRegistry {
     fn default() -> Self {
         let state_file = {
-            #[cfg(target_os = "macos")]
-            let data_dir_og = env::var_os("XDG_DATA_HOME")
-                .map(PathBuf::from)
-                .filter(|p| p.is_absolute())
-                .or_else(|| dirs::home_dir().map(|d| d.join(".local").join("share")))
-                .context("Invalid data directory");
-
-            #[cfg(not(target_os = "macos"))]
-            let data_dir_og = dirs::data_local_dir();
-
-            let data_dir = data_dir_og
-                .map(|p| p.join("wutag"))
-                .expect("unable to join registry path");
+            let data_dir = get_data_path().unwrap_or_else(|e| wutag_fatal!("{}", e));
 
             if !data_dir.exists() {
                 fs::create_dir_all(&data_dir).unwrap_or_else(|_| {
@@ -205,11 +500,7 @@ impl Default for TagRegistry {
             data_dir.join(REGISTRY_FILE)
         };
 
-        Self {
-            path:    state_file,
-            tags:    BTreeMap::new(),
-            entries: BTreeMap::new(),
-        }
+        Self::new(state_file)
     }
 }
 
@@ -217,8 +508,30 @@ impl TagRegistry {
     /// Creates a new instance of `TagRegistry` with a `path` without loading
     pub(crate) fn new<P: AsRef<Path>>(path: P) -> Self {
         Self {
-            path: path.as_ref().to_path_buf(),
-            ..Self::default()
+            path:       path.as_ref().to_path_buf(),
+            tags:       BTreeMap::new(),
+            entries:    BTreeMap::new(),
+            values:     BTreeMap::new(),
+            icons:      BTreeMap::new(),
+            usage:      BTreeMap::new(),
+            dir_usage:  BTreeMap::new(),
+            stashes:    BTreeMap::new(),
+            version:    Some(REGISTRY_VERSION.to_owned()),
+            maintained: None,
+            namespace:  XattrNamespace::default(),
+            storage:    Arc::new(FileStorage::new(path.as_ref(), &EncryptConfig::default())),
+            read_only:  false,
+            observers:  Observers::default(),
+        }
+    }
+
+    /// Creates an empty `TagRegistry` that is only kept in memory. Nothing is
+    /// written to disk, not even when it is saved, so tests can use as many as
+    /// they need at once
+    pub(crate) fn new_in_memory() -> Self {
+        Self {
+            storage: Arc::new(MemoryStorage::default()),
+            ..Self::new("")
         }
     }
 
@@ -255,20 +568,64 @@ impl TagRegistry {
         }
 
         let data = fs::read(path).context("failed to read saved registry")?;
-        serde_yaml::from_slice(&data).context("failed to deserialize tag registry")
+        let mut registry: Self =
+            serde_yaml::from_slice(&data).context("failed to deserialize tag registry")?;
+        registry.set_storage(Arc::new(FileStorage::new(path, config)));
+        crash::set_registry_version(registry.version.as_deref());
+
+        Ok(registry)
     }
 
-    /// Saves the registry serialized to the path from which it was loaded.
+    /// Saves the registry to the [`Storage`] it was loaded from (e.g., its file
+    /// or the server it was fetched from)
     pub(crate) fn save(&self) -> Result<()> {
-        let serialized = serde_yaml::to_vec(&self).context("failed to serialize tag registry")?;
+        if self.read_only {
+            log::debug!("not saving the registry, it was loaded read-only");
+            return Ok(());
+        }
+
+        self.storage.save(self)
+    }
+
+    /// Where the registry is saved to
+    pub(crate) fn storage(&self) -> Arc<dyn Storage> {
+        Arc::clone(&self.storage)
+    }
+
+    /// Save the registry to `storage` from now on
+    pub(crate) fn set_storage(&mut self, storage: Arc<dyn Storage>) {
+        self.storage = storage;
+    }
+
+    /// URL of the server the registry was fetched from, if it is shared over
+    /// the network with `wutag serve`
+    pub(crate) fn remote(&self) -> Option<&str> {
+        self.storage.url()
+    }
+
+    /// Never save the registry, since the command it was loaded for does not
+    /// change it
+    pub(crate) fn set_read_only(&mut self) {
+        self.read_only = true;
+    }
 
-        fs::write(&self.path, &serialized).context("failed to save registry")
+    /// Calls `f` with each change to the registry from now on, such as a tag
+    /// being added to a file. Clones of the registry share the callbacks
+    #[cfg_attr(not(feature = "ui"), allow(dead_code))]
+    pub(crate) fn on_change<F>(&self, f: F)
+    where
+        F: FnMut(&ChangeEvent) + Send + 'static,
+    {
+        self.observers.subscribe(f);
     }
 
     /// Clears this tag registry by removing all entries and tags.
     pub(crate) fn clear(&mut self) {
         self.tags.clear();
         self.entries.clear();
+        self.values.clear();
+        self.icons.clear();
+        self.observers.emit(&ChangeEvent::Cleared);
     }
 
     /// Updates the entry's modificiation time and hash, based on the EntryId
@@ -283,14 +640,18 @@ impl TagRegistry {
 
         // *e = entry;
 
+        self.observers.emit(&ChangeEvent::FileUpdated(id));
         Ok(())
     }
 
-    /// Updates the entry or adds it if it is not present.
+    /// Updates the entry or adds it if it is not present. Entries are the same
+    /// when their paths are, so that a file whose content, modification time,
+    /// or way of hashing (`hash_algorithm`, `--quick-hash`) changed keeps its
+    /// entry
     pub(crate) fn add_or_update_entry(&mut self, entry: EntryData) -> EntryId {
         let pos = self
             .list_entries_and_ids()
-            .find(|(_, e)| **e == entry)
+            .find(|(_, e)| e.path() == entry.path())
             .map(|(idx, _)| *idx);
 
         if let Some(pos) = pos {
@@ -302,6 +663,7 @@ impl TagRegistry {
             });
 
             *e = entry;
+            self.observers.emit(&ChangeEvent::FileUpdated(pos));
             pos
         } else {
             let timestamp = chrono::Utc::now().timestamp_nanos();
@@ -313,6 +675,7 @@ impl TagRegistry {
                 }
             } as usize;
             self.entries.insert(timestamp, entry);
+            self.observers.emit(&ChangeEvent::FileAdded(timestamp));
             timestamp
         }
     }
@@ -337,6 +700,44 @@ impl TagRegistry {
         }
         entries.push(entry);
 
+        self.observers.emit(&ChangeEvent::Tagged {
+            tag:  tag.name().to_owned(),
+            file: entry,
+        });
+        None
+    }
+
+    /// Adds the `tag` to an entry like [`tag_entry`](Self::tag_entry), and
+    /// counts it as a use of the tag and of the directory of the entry when it
+    /// was added. Only the tags that the user sets are counted, so that the
+    /// registry being repaired, synced, or restored does not change the order
+    /// of [`list_tags_by_usage`](Self::list_tags_by_usage)
+    pub(crate) fn use_tag(&mut self, tag: &Tag, entry: EntryId) -> Option<EntryId> {
+        if let Some(entry) = self.tag_entry(tag, entry) {
+            return Some(entry);
+        }
+
+        let usage = self.usage.entry(tag.name().to_owned()).or_insert(TagUsage {
+            count:     0,
+            last_used: UNIX_EPOCH,
+        });
+        usage.count += 1;
+        usage.last_used = SystemTime::now();
+
+        if let Some(dir) = self
+            .entries
+            .get(&entry)
+            .and_then(|e| e.path().parent())
+            .map(Path::to_path_buf)
+        {
+            let usage = self.dir_usage.entry(dir).or_insert(TagUsage {
+                count:     0,
+                last_used: UNIX_EPOCH,
+            });
+            usage.count += 1;
+            usage.last_used = SystemTime::now();
+        }
+
         None
     }
 
@@ -361,8 +762,15 @@ impl TagRegistry {
             let entry = entries.remove(pos);
 
             self.clean_tag_if_no_entries(tag);
+            self.remove_value(tag, entry);
+            self.observers.emit(&ChangeEvent::Untagged {
+                tag:  tag.name().to_owned(),
+                file: entry,
+            });
 
             if self.list_entry_tags(entry).is_none() {
+                self.values.remove(&entry);
+                self.observers.emit(&ChangeEvent::FileRemoved(entry));
                 return self.entries.remove(&entry);
             }
         }
@@ -393,15 +801,183 @@ impl TagRegistry {
             self.tags.remove(&tag);
         }
 
-        self.entries.remove(&entry);
+        self.values.remove(&entry);
+        if self.entries.remove(&entry).is_some() {
+            self.observers.emit(&ChangeEvent::FileRemoved(entry));
+        }
     }
 
-    /// Finds the entry by a `path`. Returns the id of the entry if found.
-    pub(crate) fn find_entry<P: AsRef<Path>>(&self, path: P) -> Option<EntryId> {
-        self.entries
+    /// Attaches a `value` to the `tag` that is on the `entry`, replacing the
+    /// previous value if there was one
+    pub(crate) fn set_value<S: Into<String>>(&mut self, tag: &Tag, entry: EntryId, value: S) {
+        let value = value.into();
+        self.values
+            .entry(entry)
+            .or_insert_with(BTreeMap::new)
+            .insert(tag.name().to_owned(), value.clone());
+
+        self.observers.emit(&ChangeEvent::ValueSet {
+            tag: tag.name().to_owned(),
+            file: entry,
+            value,
+        });
+    }
+
+    /// Returns the value attached to the tag with the name `tag` on the `entry`
+    /// if such value exists
+    #[allow(dead_code)]
+    pub(crate) fn get_value<T: AsRef<str>>(&self, tag: T, entry: EntryId) -> Option<&str> {
+        self.values
+            .get(&entry)
+            .and_then(|values| values.get(tag.as_ref()))
+            .map(String::as_str)
+    }
+
+    /// Detaches the value from the `tag` on the `entry`, returning it if it
+    /// existed
+    pub(crate) fn remove_value(&mut self, tag: &Tag, entry: EntryId) -> Option<String> {
+        let values = self.values.get_mut(&entry)?;
+        let value = values.remove(tag.name());
+
+        if values.is_empty() {
+            self.values.remove(&entry);
+        }
+
+        if value.is_some() {
+            self.observers.emit(&ChangeEvent::ValueRemoved {
+                tag:  tag.name().to_owned(),
+                file: entry,
+            });
+        }
+        value
+    }
+
+    /// Lists the tags of each entry whose `expires=<date>` value has passed at
+    /// `now`
+    pub(crate) fn expired_tags(&self, now: NaiveDateTime) -> Vec<(EntryId, Vec<Tag>)> {
+        self.values
+            .iter()
+            .filter_map(|(id, values)| {
+                let expired = values
+                    .iter()
+                    .filter(|(_, value)| parse_expiry(value).map_or(false, |time| time <= now))
+                    .filter_map(|(name, _)| self.get_tag(name).cloned())
+                    .collect::<Vec<_>>();
+                (!expired.is_empty()).then(|| (*id, expired))
+            })
+            .collect()
+    }
+
+    /// Lists the tag names and values that are attached to the `entry`
+    #[allow(dead_code)]
+    pub(crate) fn list_entry_values(&self, entry: EntryId) -> Option<&BTreeMap<String, String>> {
+        self.values.get(&entry)
+    }
+
+    /// Counts the files that each distinct value is attached to, along with the
+    /// tags it is attached to. Only the values of the tag named `tag` are
+    /// counted when it is given, and only on the entries that `keep` returns
+    /// `true` for
+    pub(crate) fn count_values<F: Fn(EntryId) -> bool>(
+        &self,
+        tag: Option<&str>,
+        keep: F,
+    ) -> BTreeMap<&str, ValueCount<'_>> {
+        let mut counts: BTreeMap<&str, ValueCount<'_>> = BTreeMap::new();
+
+        for (_, values) in self.values.iter().filter(|(id, _)| keep(**id)) {
+            // A file is counted once for a value, even if several of its tags have it
+            let mut seen = BTreeSet::new();
+            for (name, value) in values {
+                if tag.map_or(false, |t| t != name) {
+                    continue;
+                }
+                let count = counts.entry(value.as_str()).or_default();
+                count.tags.insert(name.as_str());
+                if seen.insert(value.as_str()) {
+                    count.files += 1;
+                }
+            }
+        }
+
+        counts
+    }
+
+    /// Lists the tags that are not attached to any existing entry
+    pub(crate) fn list_unused_tags(&self) -> Vec<&Tag> {
+        self.tags
+            .iter()
+            .filter(|(_, entries)| !entries.iter().any(|id| self.entries.contains_key(id)))
+            .map(|(tag, _)| tag)
+            .collect()
+    }
+
+    /// Lists the values that are attached to a tag that is no longer on the
+    /// entry, or to an entry that no longer exists
+    pub(crate) fn list_orphaned_values(&self) -> Vec<(EntryId, &String)> {
+        self.values
             .iter()
-            .find(|(_, entry)| entry.path == path.as_ref())
-            .map(|(idx, _)| *idx)
+            .flat_map(|(&id, values)| values.keys().map(move |name| (id, name)))
+            .filter(|(id, name)| {
+                !self.tags.iter().any(|(tag, entries)| tag.name() == *name && entries.contains(id))
+            })
+            .collect()
+    }
+
+    /// Removes unused tags, orphaned values, references to entries that no
+    /// longer exist and entries that have no tags. Returns the number of tags
+    /// and values that were removed
+    pub(crate) fn remove_orphans(&mut self) -> (usize, usize) {
+        let values = self
+            .list_orphaned_values()
+            .into_iter()
+            .map(|(id, name)| (id, name.clone()))
+            .collect::<Vec<_>>();
+
+        for (id, name) in &values {
+            if let Some(entry_values) = self.values.get_mut(id) {
+                entry_values.remove(name);
+                if entry_values.is_empty() {
+                    self.values.remove(id);
+                }
+            }
+        }
+
+        let entries = &self.entries;
+        for ids in self.tags.values_mut() {
+            ids.retain(|id| entries.contains_key(id));
+        }
+
+        let tags = self.tags.len();
+        self.tags.retain(|_, ids| !ids.is_empty());
+        let tags = tags - self.tags.len();
+
+        let tagged = self.tags.values().flatten().copied().collect::<BTreeSet<_>>();
+        self.entries.retain(|id, _| tagged.contains(id));
+
+        self.mark_maintained();
+        (tags, values.len())
+    }
+
+    /// Records that the registry was maintained (e.g., repaired) just now
+    pub(crate) fn mark_maintained(&mut self) {
+        self.maintained = Some(Local::now());
+    }
+
+    /// Finds the entry by a `path`. Returns the id of the entry if found. When
+    /// symlinks are followed, a symlink also finds the entry of its target. A
+    /// path under one of the `strip_prefix` mounts finds the entry under the
+    /// local one
+    pub(crate) fn find_entry<P: AsRef<Path>>(&self, path: P) -> Option<EntryId> {
+        let path = StripPrefixes::current().local(path.as_ref());
+        let find = |path: &Path| {
+            self.entries
+                .iter()
+                .find(|(_, entry)| entry.path == path)
+                .map(|(idx, _)| *idx)
+        };
+
+        find(path.as_ref()).or_else(|| resolve_symlink(path.as_ref()).and_then(|t| find(&t)))
     }
 
     /// Lists tags of the `entry` if such entry exists.
@@ -442,6 +1018,7 @@ impl TagRegistry {
     }
 
     /// Check if the file entry has all and only all specified tags
+    #[allow(dead_code)]
     pub(crate) fn entry_has_only_all_tags(&self, id: EntryId, tags: &[String]) -> bool {
         use std::collections::HashSet;
 
@@ -592,21 +1169,429 @@ impl TagRegistry {
         self.entries.iter()
     }
 
+    /// The first `limit` entries whose id is greater than `after` (or the first
+    /// entries if it is `None`), ordered by id
+    pub(crate) fn entries_after(
+        &self,
+        after: Option<EntryId>,
+        limit: usize,
+    ) -> Vec<(EntryId, &EntryData)> {
+        let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+        self.entries
+            .range((start, Bound::Unbounded))
+            .take(limit)
+            .map(|(id, entry)| (*id, entry))
+            .collect()
+    }
+
+    /// Walks the entries ordered by id, `limit` at a time. Each page starts
+    /// after the last id of the previous one, so entries that are removed or
+    /// added in between do not shift the pages
+    pub(crate) fn entry_pages(
+        &self,
+        limit: usize,
+    ) -> impl Iterator<Item = Vec<(EntryId, &EntryData)>> {
+        let mut after = None;
+        iter::from_fn(move || {
+            let page = self.entries_after(after, limit);
+            after = Some(page.last()?.0);
+            Some(page)
+        })
+    }
+
     /// Lists available tags.
     pub(crate) fn list_tags(&self) -> impl Iterator<Item = &Tag> {
         self.tags.keys()
     }
 
+    /// Lists the tags ordered by how often and how recently they were used,
+    /// where the uses count for half as much every `half_life` days
+    pub(crate) fn list_tags_by_usage(&self, half_life: f64) -> Vec<&Tag> {
+        let score = |t: &Tag| self.usage.get(t.name()).map_or(0.0, |u| u.score(half_life));
+
+        let mut tags = self.tags.keys().collect::<Vec<_>>();
+        tags.sort_by(|a, b| {
+            score(b)
+                .partial_cmp(&score(a))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        tags
+    }
+
+    /// Lists the directories that files were tagged in along with their score,
+    /// ordered by how often and how recently files in them were tagged. The
+    /// uses count for half as much every `half_life` days
+    pub(crate) fn list_dirs_by_usage(&self, half_life: f64) -> Vec<(&Path, f64)> {
+        let mut dirs = self
+            .dir_usage
+            .iter()
+            .map(|(dir, usage)| (dir.as_path(), usage.score(half_life)))
+            .collect::<Vec<_>>();
+        dirs.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        dirs
+    }
+
+    /// Finds an existing tag that `name` only differs from in number (e.g.,
+    /// `photo` for `photos`), which is likely what was meant when `name` is not
+    /// a tag yet
+    pub(crate) fn inflected_tag<T: AsRef<str>>(&self, name: T) -> Option<&Tag> {
+        let name = name.as_ref();
+        if self.get_tag(name).is_some() {
+            return None;
+        }
+
+        let stem = singular(name);
+        self.tags.keys().find(|tag| singular(tag.name()) == stem)
+    }
+
+    /// Lists the tags whose names are closest to `name`, most similar first,
+    /// to suggest a correction when `name` is not a tag
+    pub(crate) fn similar_tags<T: AsRef<str>>(&self, name: T) -> Vec<&Tag> {
+        let name = name.as_ref();
+        let mut tags = self
+            .tags
+            .keys()
+            .map(|tag| (strsim::jaro_winkler(name, tag.name()), tag))
+            .filter(|(score, _)| *score >= SIMILAR_TAG_THRESHOLD)
+            .collect::<Vec<_>>();
+        tags.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        tags.into_iter()
+            .take(SIMILAR_TAG_LIMIT)
+            .map(|(_, tag)| tag)
+            .collect()
+    }
+
+    /// Records the tags and values of the entry under the stash `name`, and
+    /// then removes the entry. Returns `false` if the entry has no tags
+    pub(crate) fn stash_entry<S: AsRef<str>>(&mut self, name: S, id: EntryId) -> bool {
+        let path = match self.get_entry(id) {
+            Some(entry) => entry.path().to_path_buf(),
+            None => return false,
+        };
+        let tags = self
+            .list_entry_tags(id)
+            .unwrap_or_default()
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if tags.is_empty() {
+            return false;
+        }
+
+        let values = self.values.get(&id).cloned().unwrap_or_default();
+        self.stashes
+            .entry(name.as_ref().to_owned())
+            .or_insert_with(BTreeMap::new)
+            .insert(path, StashedEntry { tags, values });
+        self.clear_entry(id);
+
+        true
+    }
+
+    /// Removes the file at `path` from the stash `name`, returning its tags and
+    /// values. The stash is removed once it has no files left
+    pub(crate) fn unstash_entry<S: AsRef<str>, P: AsRef<Path>>(
+        &mut self,
+        name: S,
+        path: P,
+    ) -> Option<StashedEntry> {
+        let stash = self.stashes.get_mut(name.as_ref())?;
+        let stashed = stash.remove(path.as_ref());
+        if stash.is_empty() {
+            self.stashes.remove(name.as_ref());
+        }
+        stashed
+    }
+
     /// Returns data of the entry with `id` if such entry exists.
     pub(crate) fn get_entry(&self, id: EntryId) -> Option<&EntryData> {
         self.entries.get(&id)
     }
 
+    /// Changes the path of the entry with `id` after its file was moved,
+    /// keeping its tags and values. Returns `false` if there is no such entry
+    pub(crate) fn update_entry_path<P: AsRef<Path>>(&mut self, id: EntryId, path: P) -> bool {
+        match self.entries.get_mut(&id) {
+            Some(entry) => {
+                entry.path = path.as_ref().to_path_buf();
+                self.observers.emit(&ChangeEvent::FileUpdated(id));
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Moves every path under `old` to the same place under `new`, such as
+    /// after a share was mounted somewhere else, keeping the tags and values.
+    /// Returns the number of files that were moved
+    pub(crate) fn rebase<P: AsRef<Path>>(&mut self, old: P, new: P) -> usize {
+        let (old, new) = (old.as_ref(), new.as_ref());
+        let rebase = |path: &Path| {
+            path.strip_prefix(old).ok().map(|rest| {
+                if rest.as_os_str().is_empty() {
+                    new.to_path_buf()
+                } else {
+                    new.join(rest)
+                }
+            })
+        };
+
+        let moved = self
+            .entries
+            .iter_mut()
+            .filter_map(|(id, entry)| {
+                rebase(&entry.path).map(|path| {
+                    entry.path = path;
+                    *id
+                })
+            })
+            .collect::<Vec<_>>();
+        for id in &moved {
+            self.observers.emit(&ChangeEvent::FileUpdated(*id));
+        }
+
+        for (dir, usage) in mem::take(&mut self.dir_usage) {
+            let dir = rebase(&dir).unwrap_or(dir);
+            match self.dir_usage.get_mut(&dir) {
+                Some(merged) => {
+                    merged.count += usage.count;
+                    merged.last_used = merged.last_used.max(usage.last_used);
+                },
+                None => {
+                    self.dir_usage.insert(dir, usage);
+                },
+            }
+        }
+
+        for stash in self.stashes.values_mut() {
+            *stash = mem::take(stash)
+                .into_iter()
+                .map(|(path, stashed)| (rebase(&path).unwrap_or(path), stashed))
+                .collect();
+        }
+
+        moved.len()
+    }
+
+    /// Applies the `ops` in order as a single transaction: either every one of
+    /// them changes the registry or, if any of them fails, none do. Later
+    /// operations see the changes of the earlier ones, and the callbacks only
+    /// see the changes once all of them succeeded. The registry is not saved
+    pub(crate) fn apply(&mut self, ops: Vec<Op>) -> ApplyReport {
+        // The events are recorded instead of going to the callbacks of this
+        // registry, which only get them if the batch is committed
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let observers = mem::take(&mut self.observers);
+        let recorded = Arc::clone(&events);
+        self.on_change(move |event| {
+            if let Ok(mut events) = recorded.lock() {
+                events.push(event.clone());
+            }
+        });
+
+        let mut log = Vec::new();
+        let mut results = ops
+            .into_iter()
+            .map(|op| {
+                let status = match self.apply_op(&op) {
+                    Ok(undo) if undo.is_empty() => OpStatus::Unchanged,
+                    Ok(undo) => {
+                        log.extend(undo);
+                        OpStatus::Applied
+                    },
+                    Err(e) => OpStatus::Failed(e),
+                };
+                OpResult { op, status }
+            })
+            .collect::<Vec<_>>();
+
+        let committed = !results
+            .iter()
+            .any(|result| matches!(result.status, OpStatus::Failed(_)));
+        if !committed {
+            for undo in log.into_iter().rev() {
+                self.undo(undo);
+            }
+            for result in &mut results {
+                if !matches!(result.status, OpStatus::Failed(_)) {
+                    result.status = OpStatus::RolledBack;
+                }
+            }
+        }
+
+        self.observers = observers;
+        if committed {
+            let events = events
+                .lock()
+                .map(|mut events| mem::take(&mut *events))
+                .unwrap_or_default();
+            for event in &events {
+                self.observers.emit(event);
+            }
+        }
+
+        ApplyReport { committed, results }
+    }
+
+    /// Applies a single operation of [`TagRegistry::apply`], returning how to
+    /// undo it, which is nothing if the registry did not change
+    fn apply_op(&mut self, op: &Op) -> Result<Vec<Undo>, String> {
+        let find = |registry: &Self, path: &Path| {
+            registry
+                .find_entry(path)
+                .ok_or_else(|| format!("{} is not in the registry", path.display()))
+        };
+        let entry_tag = |registry: &Self, id: EntryId, name: &str| {
+            registry
+                .list_entry_tags(id)
+                .and_then(|tags| tags.into_iter().find(|t| t.name() == name).cloned())
+        };
+
+        match op {
+            Op::AddTag { path, tag } => {
+                let mut undo = vec![];
+                let id = match self.find_entry(path) {
+                    Some(id) => id,
+                    None => {
+                        let data = EntryData::new(path)
+                            .map_err(|e| format!("{}: {:#}", path.display(), e))?;
+                        let id = self.add_or_update_entry(data);
+                        undo.push(Undo::RemoveEntry(id));
+                        id
+                    },
+                };
+                if self.tag_entry(tag, id).is_none() {
+                    undo.push(Undo::Untag {
+                        tag: tag.clone(),
+                        id,
+                    });
+                }
+                Ok(undo)
+            },
+            Op::RemoveTag { path, tag } => {
+                let id = find(self, path)?;
+                let tag = match entry_tag(self, id, tag) {
+                    Some(tag) => tag,
+                    None => return Ok(vec![]),
+                };
+                let value = self.get_value(tag.name(), id).map(String::from);
+                let entry = self.untag_entry(&tag, id);
+                Ok(vec![Undo::Retag {
+                    tag,
+                    id,
+                    value,
+                    entry,
+                }])
+            },
+            Op::SetValue { path, tag, value } => {
+                let id = find(self, path)?;
+                let tag = entry_tag(self, id, tag)
+                    .ok_or_else(|| format!("{} does not have the tag {}", path.display(), tag))?;
+                let old = self.get_value(tag.name(), id).map(String::from);
+                if old.as_ref() == Some(value) {
+                    return Ok(vec![]);
+                }
+                self.set_value(&tag, id, value.clone());
+                Ok(vec![Undo::Value {
+                    tag,
+                    id,
+                    value: old,
+                }])
+            },
+            Op::MoveFile { from, to } => {
+                let id = find(self, from)?;
+                if from == to {
+                    return Ok(vec![]);
+                }
+                if self.find_entry(to).is_some() {
+                    return Err(format!("{} is already in the registry", to.display()));
+                }
+                self.update_entry_path(id, to);
+                Ok(vec![Undo::Move {
+                    id,
+                    from: from.clone(),
+                }])
+            },
+        }
+    }
+
+    /// Reverts a change that [`TagRegistry::apply`] made before another
+    /// operation of the batch failed. The callbacks are not told, since they
+    /// were not told about the change either
+    fn undo(&mut self, undo: Undo) {
+        match undo {
+            Undo::RemoveEntry(id) => {
+                self.entries.remove(&id);
+            },
+            Undo::Untag { tag, id } => {
+                if let Some(entries) = self.tags.get_mut(&tag) {
+                    entries.retain(|e| *e != id);
+                }
+                self.clean_tag_if_no_entries(&tag);
+            },
+            Undo::Retag {
+                tag,
+                id,
+                value,
+                entry,
+            } => {
+                if let Some(entry) = entry {
+                    self.entries.insert(id, entry);
+                }
+                self.mut_tag_entries(&tag).push(id);
+                if let Some(value) = value {
+                    self.values
+                        .entry(id)
+                        .or_insert_with(BTreeMap::new)
+                        .insert(tag.name().to_owned(), value);
+                }
+            },
+            Undo::Value { tag, id, value } => match value {
+                Some(value) => {
+                    self.values
+                        .entry(id)
+                        .or_insert_with(BTreeMap::new)
+                        .insert(tag.name().to_owned(), value);
+                },
+                None => {
+                    if let Some(values) = self.values.get_mut(&id) {
+                        values.remove(tag.name());
+                        if values.is_empty() {
+                            self.values.remove(&id);
+                        }
+                    }
+                },
+            },
+            Undo::Move { id, from } =>
+                if let Some(entry) = self.entries.get_mut(&id) {
+                    entry.path = from;
+                },
+        }
+    }
+
     /// Returns the tag with the name `tag` if it exists.
     pub(crate) fn get_tag<T: AsRef<str>>(&self, tag: T) -> Option<&Tag> {
         self.tags.keys().find(|t| t.name() == tag.as_ref())
     }
 
+    /// Returns the icon displayed before the tag with the name `tag` if it has
+    /// one
+    pub(crate) fn get_tag_icon<T: AsRef<str>>(&self, tag: T) -> Option<&str> {
+        self.icons.get(tag.as_ref()).map(String::as_str)
+    }
+
+    /// Sets the icon displayed before the `tag`, replacing the previous icon
+    pub(crate) fn set_tag_icon<T: AsRef<str>, S: Into<String>>(&mut self, tag: T, icon: S) {
+        self.icons.insert(tag.as_ref().to_owned(), icon.into());
+    }
+
+    /// Removes the icon of the `tag`, returning it if it existed
+    pub(crate) fn remove_tag_icon<T: AsRef<str>>(&mut self, tag: T) -> Option<String> {
+        self.icons.remove(tag.as_ref())
+    }
+
     /// Updates the color of the `tag`. Returns `true` if the tag was found and
     /// updated and `false` otherwise.
     pub(crate) fn update_tag_color<T: AsRef<str>>(&mut self, tag: T, color: Color) -> bool {
@@ -618,27 +1603,102 @@ impl TagRegistry {
 
             t.set_color(&color);
             self.tags.insert(t, data);
+            self.observers.emit(&ChangeEvent::TagRecolored(tag.as_ref().to_owned()));
             true
         } else {
             false
         }
     }
 
-    /// Update / rename the name of the tag
+    /// Update / rename the name of the tag. If a tag with the new name already
+    /// exists the two are merged. A value attached to the tag is moved to the new
+    /// name, unless the entry already has a value for the new name. The same
+    /// goes for the tag's icon
     pub(crate) fn update_tag_name<T: AsRef<str>>(&mut self, tag: T, rename: T) -> bool {
-        if let Some(mut t) = self.tags.keys().find(|t| t.name() == tag.as_ref()).cloned() {
+        let (tag, rename) = (tag.as_ref(), rename.as_ref());
+        if let Some(mut t) = self.tags.keys().find(|t| t.name() == tag).cloned() {
             let data = self
                 .tags
                 .remove(&t)
                 .unwrap_or_else(|| wutag_fatal!("failure to remove tag: {}", t));
 
-            t.set_name(&rename);
-            self.tags.insert(t, data);
-            true
-        } else {
-            false
-        }
-    }
+            for entry in &data {
+                if let Some(values) = self.values.get_mut(entry) {
+                    if let Some(value) = values.remove(tag) {
+                        values.entry(rename.to_owned()).or_insert(value);
+                    }
+                }
+            }
+
+            if let Some(icon) = self.icons.remove(tag) {
+                self.icons.entry(rename.to_owned()).or_insert(icon);
+            }
+
+            if let Some(usage) = self.usage.remove(tag) {
+                self.usage.entry(rename.to_owned()).or_insert(usage);
+            }
+
+            t.set_name(rename);
+            let entries = self.tags.entry(t).or_insert_with(Vec::new);
+            for entry in data {
+                if !entries.contains(&entry) {
+                    entries.push(entry);
+                }
+            }
+
+            self.observers.emit(&ChangeEvent::TagRenamed {
+                from: tag.to_owned(),
+                to:   rename.to_owned(),
+            });
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Lists the changes needed to merge `other` into this registry, keyed by
+    /// the path of the file. Tags are unioned, and a conflicting value is only
+    /// taken from `other` when its entry has a newer modification time
+    pub(crate) fn sync_changes(&self, other: &Self) -> BTreeMap<PathBuf, Vec<SyncChange>> {
+        let mut changes = BTreeMap::new();
+
+        for (other_id, other_entry) in other.list_entries_and_ids() {
+            let id = self.find_entry(other_entry.path());
+            let newer = id
+                .and_then(|id| self.get_entry(id))
+                .map_or(true, |entry| other_entry.modtime() > entry.modtime());
+            let tags = id.and_then(|id| self.list_entry_tags(id)).unwrap_or_default();
+
+            let mut entry_changes = Vec::new();
+            for tag in other.list_entry_tags(*other_id).unwrap_or_default() {
+                if !tags.contains(&tag) {
+                    // Keep the color of the tag if it is already in this registry
+                    let tag = self.get_tag(tag.name()).unwrap_or(tag);
+                    entry_changes.push(SyncChange::Tag(tag.clone()));
+                }
+
+                let new = match other.get_value(tag.name(), *other_id) {
+                    Some(new) => new,
+                    None => continue,
+                };
+                let old = id.and_then(|id| self.get_value(tag.name(), id));
+
+                if old.is_none() || (old != Some(new) && newer) {
+                    entry_changes.push(SyncChange::Value {
+                        tag: tag.name().to_owned(),
+                        old: old.map(ToOwned::to_owned),
+                        new: new.to_owned(),
+                    });
+                }
+            }
+
+            if !entry_changes.is_empty() {
+                changes.insert(other_entry.path().to_path_buf(), entry_changes);
+            }
+        }
+
+        changes
+    }
 
     /// Encrypt or decrypt the registry
     #[cfg(feature = "encrypt-gpgme")]
@@ -655,8 +1715,7 @@ impl TagRegistry {
                 .unwrap_or_else(|| public.trim())
                 .to_uppercase();
 
-            let mut ctx =
-                util::context(config.tty).context("failure to get cryptography context")?;
+            let mut ctx = util::context(config).context("failure to get cryptography context")?;
             let all_recipients =
                 Recipients::from(ctx.keys_private().context("no private keys were found")?);
 
@@ -700,9 +1759,19 @@ Use an (1) email, (2) short fingerprint, or (3) full fingerprint"#,
                     log::debug!("decrypting registry");
 
                     // 1. Decrypt file
-                    let plaintext = ctx
-                        .decrypt_file(path)
-                        .context("failure to decrypt registry")?;
+                    let plaintext = ctx.decrypt_file(path).with_context(|| {
+                        if atty::isnt(atty::Stream::Stdin)
+                            && env::var_os(util::PASSPHRASE_FD_ENV).is_none()
+                        {
+                            format!(
+                                "failure to decrypt registry without a terminal, give the \
+                                 passphrase with '{}'",
+                                util::PASSPHRASE_FD_ENV
+                            )
+                        } else {
+                            String::from("failure to decrypt registry")
+                        }
+                    })?;
 
                     // 2. Serialize the decrypted string to a registry
                     let yaml: TagRegistry = serde_yaml::from_slice(plaintext.unsecure_ref())
@@ -727,8 +1796,18 @@ Use an (1) email, (2) short fingerprint, or (3) full fingerprint"#,
 
                     log::debug!("encrypting registry");
 
-                    // 3. Encrypt and write the file
-                    ctx.encrypt_file(&Recipients::from(vec![found.clone()]), plaintext, path)
+                    // 3. Encrypt it to the key and to the recipients of the registry
+                    let mut keys = vec![found.clone()];
+                    let fingerprints = recipients::read_fingerprints(path)
+                        .context("failed to read the recipients")?;
+                    for key in Recipients::find(&mut ctx, &fingerprints)?.keys() {
+                        if key.fingerprint(false) != found.fingerprint(false) {
+                            keys.push(key.clone());
+                        }
+                    }
+
+                    // 4. Encrypt and write the file
+                    ctx.encrypt_file(&Recipients::from(keys), plaintext, path)
                         .context("failure to encrypt registry")?;
 
                     // self.encrypted = true;
@@ -744,6 +1823,12 @@ Use an (1) email, (2) short fingerprint, or (3) full fingerprint"#,
     }
 }
 
+/// Always record the version of the binary that saves the registry, instead of
+/// the one it was loaded with
+fn serialize_version<S: Serializer>(_: &Option<String>, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_some(REGISTRY_VERSION)
+}
+
 #[cfg(feature = "encrypt-gpgme")]
 pub(crate) fn is_encrypted<P: AsRef<Path>>(path: P) -> bool {
     let path = path.as_ref();
@@ -758,13 +1843,39 @@ pub(crate) fn is_encrypted<P: AsRef<Path>>(path: P) -> bool {
     content.contains("-----BEGIN PGP MESSAGE-----") && content.contains("-----END PGP MESSAGE-----")
 }
 
+/// Load the registry at `path`, or create a new one if it cannot be read. An
+/// encrypted registry that cannot be decrypted (e.g., without a terminal to ask
+/// for the passphrase) is an error instead of being replaced with an empty one
+fn load_or_new(path: &Path, config: &EncryptConfig) -> Result<TagRegistry> {
+    match Arc::new(FileStorage::new(path, config)).load() {
+        Ok(registry) => Ok(registry),
+        #[cfg(feature = "encrypt-gpgme")]
+        Err(e) if is_encrypted(path) => Err(e),
+        Err(e) => {
+            log::debug!("creating a new registry at {}: {:#}", path.display(), e);
+            Ok(TagRegistry::new(path))
+        },
+    }
+}
+
 /// Load the `TagRegistry`
 pub(crate) fn load_registry(opts: &Opts, config: &EncryptConfig) -> Result<TagRegistry> {
     // Default location of registry
     let def_registry = TagRegistry::default();
     let state_file = def_registry.path;
 
-    let registry = if let Some(opt_reg) = &opts.reg {
+    let in_memory = env::var_os(IN_MEMORY_ENV).map_or(false, |v| !v.is_empty() && v != "0");
+    let mut registry = if in_memory {
+        log::debug!("using a registry in memory ({})", IN_MEMORY_ENV);
+        TagRegistry::new_in_memory()
+    } else if let Some(location) = opts
+        .reg
+        .as_ref()
+        .map(|r| r.display().to_string())
+        .filter(|r| !storage::is_path(r))
+    {
+        storage::open(&location, config).load()?
+    } else if let Some(opt_reg) = &opts.reg {
         // Expand both tlide '~' and environment variables in 'WUTAG_REGISTRY' env var
         let registry = &PathBuf::from(
             shellexpand::full(&opt_reg.display().to_string())
@@ -782,7 +1893,7 @@ pub(crate) fn load_registry(opts: &Opts, config: &EncryptConfig) -> Result<TagRe
 
         if registry.is_file() && registry.file_name().is_some() {
             log::debug!("using a non-default registry: {}", registry.display());
-            TagRegistry::load(&registry, config).unwrap_or_else(|_| TagRegistry::new(&registry))
+            load_or_new(registry, config)?
             //\\
         } else if registry.is_dir() && registry.file_name().is_some() {
             wutag_error!(
@@ -790,7 +1901,7 @@ pub(crate) fn load_registry(opts: &Opts, config: &EncryptConfig) -> Result<TagRe
                 registry.display().to_string().green(),
                 state_file.display().to_string().green(),
             );
-            TagRegistry::load(&state_file, config).unwrap_or_else(|_| TagRegistry::new(&state_file))
+            load_or_new(&state_file, config)?
             //\\
         } else if registry.display().to_string().ends_with('/') {
             wutag_error!(
@@ -798,7 +1909,7 @@ pub(crate) fn load_registry(opts: &Opts, config: &EncryptConfig) -> Result<TagRe
                 registry.display().to_string().green(),
                 state_file.display().to_string().green(),
             );
-            TagRegistry::load(&state_file, config).unwrap_or_else(|_| TagRegistry::new(&state_file))
+            load_or_new(&state_file, config)?
             //\\
         } else {
             log::debug!("using a non-default registry: {}", registry.display());
@@ -814,33 +1925,66 @@ pub(crate) fn load_registry(opts: &Opts, config: &EncryptConfig) -> Result<TagRe
                 )
             })?;
 
-            TagRegistry::load(&registry, config).unwrap_or_else(|_| {
-                log::debug!("creating a non-default registry");
-                TagRegistry::new(&registry)
-            })
+            load_or_new(registry, config)?
         }
     } else {
         log::debug!("using default registry");
-        TagRegistry::load(&state_file, config).unwrap_or_else(|_| {
-            log::debug!("creating default registry");
-            TagRegistry::new(&state_file)
-        })
+        load_or_new(&state_file, config)?
     };
 
+    if !opts.cmd.mutates_registry() {
+        log::debug!("loading the registry read-only");
+        registry.set_read_only();
+    }
+
     Ok(registry)
 }
 
+/// Keeps the registry up to date when files are tagged with the `*_with`
+/// methods of `DirEntryExt`
+#[cfg(feature = "write-through")]
+impl TagStore for TagRegistry {
+    fn tagged(&mut self, path: &Path, tag: &Tag) -> wutag_core::Result<()> {
+        let id = match self.find_entry(path) {
+            Some(id) => id,
+            None => self.add_or_update_entry(
+                EntryData::new(path).map_err(|e| wutag_core::Error::Other(e.to_string()))?,
+            ),
+        };
+        self.tag_entry(tag, id);
+
+        Ok(())
+    }
+
+    fn untagged(&mut self, path: &Path, tag: &Tag) -> wutag_core::Result<()> {
+        if let Some(id) = self.find_entry(path) {
+            self.untag_entry(tag, id);
+        }
+
+        Ok(())
+    }
+
+    fn cleared(&mut self, path: &Path) -> wutag_core::Result<()> {
+        if let Some(id) = self.find_entry(path) {
+            self.clear_entry(id);
+        }
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::consts::DEFAULT_COLORS;
     use colored::Color::{Black, Red};
+    use proptest::prelude::*;
 
     #[test]
     fn adds_and_tags_entry() -> Result<()> {
         let path = PathBuf::from("/tmp");
         let entry = EntryData::new(path.clone())?;
-        let mut registry = TagRegistry::default();
+        let mut registry = TagRegistry::new_in_memory();
         registry.add_or_update_entry(entry.clone());
         let id = registry.find_entry(&path).unwrap();
 
@@ -866,7 +2010,7 @@ mod tests {
 
     #[test]
     fn adds_multiple_entries() -> Result<()> {
-        let mut registry = TagRegistry::default();
+        let mut registry = TagRegistry::new_in_memory();
 
         let entry = EntryData::new("/tmp")?;
         let fst_id = registry.add_or_update_entry(entry.clone());
@@ -882,11 +2026,424 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn walks_entries_in_pages() {
+        let mut registry = TagRegistry::new_in_memory();
+        for id in [0, 1, 2, 5, 8] {
+            registry.entries.insert(id, EntryData::default());
+        }
+
+        let ids = |page: Vec<(EntryId, &EntryData)>| -> Vec<EntryId> {
+            page.iter().map(|(id, _)| *id).collect()
+        };
+        assert_eq!(ids(registry.entries_after(None, 2)), vec![0, 1]);
+        assert_eq!(ids(registry.entries_after(Some(2), 2)), vec![5, 8]);
+        assert_eq!(ids(registry.entries_after(Some(3), 10)), vec![5, 8]);
+        assert!(registry.entries_after(Some(8), 2).is_empty());
+
+        let pages = registry.entry_pages(2).map(ids).collect::<Vec<_>>();
+        assert_eq!(pages, vec![vec![0, 1], vec![2, 5], vec![8]]);
+        assert_eq!(registry.entry_pages(5).count(), 1);
+    }
+
+    #[test]
+    fn updates_entry_path() -> Result<()> {
+        let mut registry = TagRegistry::new_in_memory();
+        let id = registry.add_or_update_entry(EntryData::new("/tmp")?);
+        let tag = Tag::new("moved", Black);
+        registry.tag_entry(&tag, id);
+        registry.set_value(&tag, id, "1");
+
+        assert!(registry.update_entry_path(id, "/tmp/moved"));
+        assert!(registry.find_entry("/tmp").is_none());
+        assert_eq!(registry.find_entry("/tmp/moved"), Some(id));
+        assert_eq!(registry.list_entry_tags(id), Some(vec![&tag]));
+        assert_eq!(registry.get_value("moved", id), Some("1"));
+        assert!(!registry.update_entry_path(id + 1, "/tmp"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn finds_entries_through_symlinks() -> Result<()> {
+        wutag_core::xattr::set_follow_symlinks(true);
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().canonicalize()?.join("file");
+        let link = dir.path().join("link");
+        fs::write(&file, "")?;
+        std::os::unix::fs::symlink(&file, &link)?;
+
+        let mut registry = TagRegistry::new_in_memory();
+        let id = registry.add_or_update_entry(EntryData::new(&link)?);
+        assert_eq!(
+            registry.get_entry(id).map(EntryData::path),
+            Some(file.as_path())
+        );
+        assert_eq!(registry.find_entry(&file), Some(id));
+        assert_eq!(registry.find_entry(&link), Some(id));
+        assert_eq!(registry.add_or_update_entry(EntryData::new(&file)?), id);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rebases_paths() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("a.mp3");
+        fs::write(&file, "")?;
+
+        let mut registry = TagRegistry::new_in_memory();
+        let tag = Tag::new("music", Black);
+        let id = registry.add_or_update_entry(EntryData::new(&file)?);
+        registry.use_tag(&tag, id);
+        registry.stash_entry("stash", id);
+        let id = registry.add_or_update_entry(EntryData::new(&file)?);
+        registry.use_tag(&tag, id);
+
+        let nas = Path::new("/mnt/nas");
+        assert_eq!(registry.rebase(Path::new("/nonexistent"), nas), 0);
+        assert_eq!(registry.rebase(dir.path(), nas), 1);
+        assert_eq!(registry.find_entry(nas.join("a.mp3")), Some(id));
+        assert_eq!(registry.list_entries_with_tags(vec![tag.name()]), vec![id]);
+        assert!(registry.stashes["stash"].contains_key(&nas.join("a.mp3")));
+        assert_eq!(registry.dir_usage[nas].count, 2);
+        assert!(!registry.dir_usage.contains_key(dir.path()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn applies_ops_as_one_transaction() -> Result<()> {
+        let mut registry = TagRegistry::new_in_memory();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&events);
+        registry.on_change(move |e| seen.lock().unwrap().push(e.clone()));
+
+        let tag = Tag::new("batch", Black);
+        let statuses = |report: &ApplyReport| {
+            report
+                .results
+                .iter()
+                .map(|r| r.status.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let report = registry.apply(vec![
+            Op::AddTag {
+                path: PathBuf::from("/tmp"),
+                tag:  tag.clone(),
+            },
+            Op::AddTag {
+                path: PathBuf::from("/tmp"),
+                tag:  tag.clone(),
+            },
+            Op::SetValue {
+                path:  PathBuf::from("/tmp"),
+                tag:   "batch".to_owned(),
+                value: "1".to_owned(),
+            },
+        ]);
+        assert!(report.committed);
+        assert_eq!(
+            statuses(&report),
+            vec![OpStatus::Applied, OpStatus::Unchanged, OpStatus::Applied]
+        );
+        let id = registry.find_entry("/tmp").unwrap();
+        assert_eq!(registry.get_value("batch", id), Some("1"));
+        assert_eq!(events.lock().unwrap().len(), 3);
+
+        // A failing operation leaves the registry and the callbacks untouched
+        let report = registry.apply(vec![
+            Op::MoveFile {
+                from: PathBuf::from("/tmp"),
+                to:   PathBuf::from("/tmp/moved"),
+            },
+            Op::RemoveTag {
+                path: PathBuf::from("/tmp/moved"),
+                tag:  "batch".to_owned(),
+            },
+            Op::SetValue {
+                path:  PathBuf::from("/tmp/missing"),
+                tag:   "batch".to_owned(),
+                value: "2".to_owned(),
+            },
+        ]);
+        assert!(!report.committed);
+        assert_eq!(statuses(&report), vec![
+            OpStatus::RolledBack,
+            OpStatus::RolledBack,
+            OpStatus::Failed("/tmp/missing is not in the registry".to_owned()),
+        ]);
+        assert_eq!(registry.find_entry("/tmp"), Some(id));
+        assert_eq!(registry.list_entry_tags(id), Some(vec![&tag]));
+        assert_eq!(registry.get_value("batch", id), Some("1"));
+        assert_eq!(events.lock().unwrap().len(), 3);
+
+        let report = registry.apply(vec![
+            Op::MoveFile {
+                from: PathBuf::from("/tmp"),
+                to:   PathBuf::from("/tmp/moved"),
+            },
+            Op::RemoveTag {
+                path: PathBuf::from("/tmp/moved"),
+                tag:  "batch".to_owned(),
+            },
+        ]);
+        assert!(report.committed);
+        assert!(registry.find_entry("/tmp/moved").is_none());
+        assert!(registry.list_tags().next().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn emits_change_events() -> Result<()> {
+        let mut registry = TagRegistry::new_in_memory();
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = std::sync::Arc::clone(&events);
+        registry.on_change(move |e| seen.lock().unwrap().push(e.clone()));
+
+        let id = registry.add_or_update_entry(EntryData::new("/tmp")?);
+        let tag = Tag::new("event", Black);
+        registry.tag_entry(&tag, id);
+        registry.set_value(&tag, id, "1");
+        registry.update_tag_name("event", "renamed");
+        registry.clear_entry(id);
+
+        assert_eq!(*events.lock().unwrap(), vec![
+            ChangeEvent::FileAdded(id),
+            ChangeEvent::Tagged {
+                tag:  "event".to_owned(),
+                file: id,
+            },
+            ChangeEvent::ValueSet {
+                tag:   "event".to_owned(),
+                file:  id,
+                value: "1".to_owned(),
+            },
+            ChangeEvent::TagRenamed {
+                from: "event".to_owned(),
+                to:   "renamed".to_owned(),
+            },
+            ChangeEvent::FileRemoved(id),
+        ]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_entry_when_hashing_changes() -> Result<()> {
+        let mut registry = TagRegistry::new_in_memory();
+        let entry = EntryData::new("/tmp")?;
+        let id = registry.add_or_update_entry(entry.clone());
+
+        let rehashed = EntryData {
+            hash: format!("xxh3-quick64:{}", "0".repeat(16)),
+            ..entry
+        };
+        assert_eq!(registry.add_or_update_entry(rehashed.clone()), id);
+        assert_eq!(registry.list_entries().count(), 1);
+        assert_eq!(registry.get_entry(id), Some(&rehashed));
+
+        Ok(())
+    }
+
+    #[test]
+    fn saves_by_replacing_the_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(REGISTRY_FILE);
+        let mut registry = TagRegistry::new(&path);
+        let id = registry.add_or_update_entry(EntryData::new(dir.path())?);
+        registry.tag_entry(&Tag::new("saved", Black), id);
+        registry.save()?;
+
+        let names = fs::read_dir(dir.path())?
+            .map(|e| e.map(|e| e.file_name()))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(names, vec![REGISTRY_FILE]);
+
+        let mut loaded = TagRegistry::load(&path, &EncryptConfig::default())?;
+        assert!(loaded.get_tag("saved").is_some());
+
+        loaded.set_read_only();
+        loaded.clear();
+        loaded.save()?;
+        let reloaded = TagRegistry::load(&path, &EncryptConfig::default())?;
+        assert!(reloaded.get_tag("saved").is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn records_version_of_binary() -> Result<()> {
+        let mut registry = TagRegistry::new_in_memory();
+        registry.version = None;
+        assert!(registry.maintained.is_none());
+        registry.remove_orphans();
+        assert!(registry.maintained.is_some());
+
+        let saved: TagRegistry = serde_yaml::from_slice(&serde_yaml::to_vec(&registry)?)?;
+        assert_eq!(saved.version.as_deref(), Some(REGISTRY_VERSION));
+        assert_eq!(saved.maintained, registry.maintained);
+
+        let old: TagRegistry = serde_yaml::from_str("path: ''\ntags: {}\nentries: {}\n")?;
+        assert_eq!(old.version, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn stashes_and_pops_entries() -> Result<()> {
+        let mut registry = TagRegistry::new_in_memory();
+        let id = registry.add_or_update_entry(EntryData::new("/tmp")?);
+        let untagged = registry.add_or_update_entry(EntryData::new("/tmp/123")?);
+        let tag = Tag::new("stashed", Black);
+        registry.tag_entry(&tag, id);
+        registry.set_value(&tag, id, "1");
+
+        assert!(registry.stash_entry("share", id));
+        assert!(!registry.stash_entry("share", untagged));
+        assert_eq!(registry.get_entry(id), None);
+        assert_eq!(registry.get_tag("stashed"), None);
+
+        assert_eq!(registry.stashes["share"].len(), 1);
+        let stashed = registry.unstash_entry("share", "/tmp").unwrap();
+        assert_eq!(stashed.tags, vec![tag.clone()]);
+        assert_eq!(stashed.values.get("stashed").map(String::as_str), Some("1"));
+        assert!(registry.stashes.is_empty());
+
+        let fst = registry.add_or_update_entry(EntryData::new("/tmp")?);
+        let snd = registry.add_or_update_entry(EntryData::new("/tmp/123")?);
+        registry.tag_entry(&tag, fst);
+        registry.tag_entry(&tag, snd);
+        registry.stash_entry("share", fst);
+        registry.stash_entry("share", snd);
+        assert!(registry.unstash_entry("share", "/tmp").is_some());
+        assert!(registry.unstash_entry("share", "/tmp").is_none());
+        assert_eq!(registry.stashes["share"].len(), 1);
+        assert!(registry.unstash_entry("share", "/tmp/123").is_some());
+        assert!(registry.stashes.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn orders_tags_by_usage() -> Result<()> {
+        let mut registry = TagRegistry::new_in_memory();
+        let fst = registry.add_or_update_entry(EntryData::new("/tmp")?);
+        let snd = registry.add_or_update_entry(EntryData::new("/tmp/123")?);
+        let (daily, old, unused) = (
+            Tag::new("daily", Black),
+            Tag::new("old", Black),
+            Tag::new("unused", Black),
+        );
+
+        // Tags that the user did not set are not counted
+        registry.tag_entry(&unused, fst);
+        assert!(!registry.usage.contains_key("unused"));
+        for id in &[fst, snd] {
+            registry.use_tag(&daily, *id);
+            registry.use_tag(&old, *id);
+        }
+        registry.use_tag(&daily, fst);
+        assert_eq!(registry.usage["daily"].count, 2);
+
+        registry.usage.get_mut("old").unwrap().last_used =
+            SystemTime::now() - Duration::from_secs(30 * 86400);
+        registry.update_tag_name("daily", "today");
+
+        assert_eq!(registry.list_tags_by_usage(7.0), vec![
+            registry.get_tag("today").unwrap(),
+            &old,
+            &unused
+        ]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn orders_dirs_by_usage() -> Result<()> {
+        let mut registry = TagRegistry::new_in_memory();
+        let fst = registry.add_or_update_entry(EntryData::new("/tmp")?);
+        let snd = registry.add_or_update_entry(EntryData::new("/tmp/123")?);
+        let (tag, other) = (Tag::new("tag", Black), Tag::new("other", Black));
+
+        registry.use_tag(&tag, fst);
+        registry.use_tag(&tag, snd);
+        registry.use_tag(&other, snd);
+        // Tagging a file with a tag it has does not count
+        registry.use_tag(&other, snd);
+        assert_eq!(registry.dir_usage[Path::new("/tmp")].count, 2);
+
+        let dirs = |registry: &TagRegistry| {
+            registry
+                .list_dirs_by_usage(7.0)
+                .into_iter()
+                .map(|(dir, _)| dir.to_path_buf())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(dirs(&registry), vec![Path::new("/tmp"), Path::new("/")]);
+
+        // A directory used more often falls behind once it has not been used
+        // for a few half lives
+        registry.dir_usage.get_mut(Path::new("/tmp")).unwrap().last_used =
+            SystemTime::now() - Duration::from_secs(30 * 86400);
+        assert_eq!(dirs(&registry), vec![Path::new("/"), Path::new("/tmp")]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn suggests_similar_tags() -> Result<()> {
+        let mut registry = TagRegistry::new_in_memory();
+        let id = registry.add_or_update_entry(EntryData::new("/tmp")?);
+        for name in &["work", "workout", "music", "photos"] {
+            registry.tag_entry(&Tag::new(*name, Black), id);
+        }
+
+        let names = |name: &str| {
+            registry
+                .similar_tags(name)
+                .iter()
+                .map(|t| t.name().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("wrok"), vec!["work"]);
+        assert_eq!(names("workou"), vec!["workout", "work"]);
+        assert_eq!(names("photo"), vec!["photos"]);
+        assert!(names("xyz").is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn finds_tags_differing_in_number() -> Result<()> {
+        assert_eq!(singular("Photos"), "photo");
+        assert_eq!(singular("stories"), "story");
+        assert_eq!(singular("glasses"), "glass");
+        assert_eq!(singular("boxes"), "box");
+        assert_eq!(singular("status"), "status");
+        assert_eq!(singular("bus"), "bus");
+
+        let mut registry = TagRegistry::new_in_memory();
+        let id = registry.add_or_update_entry(EntryData::new("/tmp")?);
+        for name in &["photo", "stories"] {
+            registry.tag_entry(&Tag::new(*name, Black), id);
+        }
+
+        assert_eq!(registry.inflected_tag("photos").map(Tag::name), Some("photo"));
+        assert_eq!(registry.inflected_tag("story").map(Tag::name), Some("stories"));
+        assert_eq!(registry.inflected_tag("photo"), None);
+        assert_eq!(registry.inflected_tag("photography"), None);
+
+        Ok(())
+    }
+
     #[test]
     fn updates_tag_color() -> Result<()> {
         let entry = EntryData::new("/tmp")?;
 
-        let mut registry = TagRegistry::default();
+        let mut registry = TagRegistry::new_in_memory();
         let id = registry.add_or_update_entry(entry);
 
         let tag = Tag::new("test", Black);
@@ -902,7 +2459,7 @@ mod tests {
     fn removes_an_entry_when_no_tags_left() -> Result<()> {
         let entry = EntryData::new("/tmp")?;
 
-        let mut registry = TagRegistry::default();
+        let mut registry = TagRegistry::new_in_memory();
         let id = registry.add_or_update_entry(entry.clone());
 
         let tag1 = Tag::new("test", Black);
@@ -939,7 +2496,7 @@ mod tests {
 
     #[test]
     fn lists_entry_tags() -> Result<()> {
-        let mut registry = TagRegistry::default();
+        let mut registry = TagRegistry::new_in_memory();
 
         let tag1 = Tag::new("src", Black);
         let tag2 = Tag::new("code", Red);
@@ -960,7 +2517,7 @@ mod tests {
 
     #[test]
     fn lists_entries_with_tags() -> Result<()> {
-        let mut registry = TagRegistry::default();
+        let mut registry = TagRegistry::new_in_memory();
 
         let tag1 = Tag::new("src", Black);
         let tag2 = Tag::new("code", Red);
@@ -1001,6 +2558,179 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sets_and_removes_values() -> Result<()> {
+        let mut registry = TagRegistry::new_in_memory();
+
+        let tag1 = Tag::new("added", Black);
+        let tag2 = Tag::new("rating", Red);
+
+        let id = registry.add_or_update_entry(EntryData::new("/tmp")?);
+        registry.tag_entry(&tag1, id);
+        registry.tag_entry(&tag2, id);
+
+        registry.set_value(&tag1, id, "2021-11-24");
+        registry.set_value(&tag2, id, "5");
+        assert_eq!(registry.get_value("added", id), Some("2021-11-24"));
+        assert_eq!(registry.list_entry_values(id).map(BTreeMap::len), Some(2));
+
+        registry.untag_entry(&tag1, id);
+        assert_eq!(registry.get_value("added", id), None);
+        assert_eq!(registry.get_value("rating", id), Some("5"));
+
+        registry.clear_entry(id);
+        assert!(registry.values.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn counts_distinct_values() -> Result<()> {
+        let mut registry = TagRegistry::new_in_memory();
+        let (rating, score) = (Tag::new("rating", Red), Tag::new("score", Black));
+
+        let fst = registry.add_or_update_entry(EntryData::new("/tmp")?);
+        let snd = registry.add_or_update_entry(EntryData::new("/tmp/123")?);
+        for id in &[fst, snd] {
+            registry.tag_entry(&rating, *id);
+            registry.tag_entry(&score, *id);
+            registry.set_value(&rating, *id, "5");
+        }
+        registry.set_value(&score, fst, "5");
+        registry.set_value(&score, snd, "3");
+
+        let counts = registry.count_values(None, |_| true);
+        assert_eq!(counts.keys().collect::<Vec<_>>(), vec![&"3", &"5"]);
+        assert_eq!(counts["5"].files, 2);
+        assert_eq!(
+            counts["5"].tags.iter().collect::<Vec<_>>(),
+            vec![&"rating", &"score"]
+        );
+
+        let counts = registry.count_values(Some("score"), |id| id == snd);
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts["3"].files, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn finds_expired_tags() -> Result<()> {
+        let mut registry = TagRegistry::new_in_memory();
+
+        let temp = Tag::new("review", Black);
+        let dated = Tag::new("toread", Red);
+
+        let id = registry.add_or_update_entry(EntryData::new("/tmp")?);
+        registry.tag_entry(&temp, id);
+        registry.tag_entry(&dated, id);
+        registry.set_value(&temp, id, "expires=2021-12-01T12:00:00");
+        registry.set_value(&dated, id, "expires=2021-12-01");
+
+        let expired = |s: &str| {
+            registry.expired_tags(NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S").unwrap())
+        };
+        assert!(expired("2021-12-01T11:59:59").is_empty());
+        assert_eq!(expired("2021-12-01T12:00:00"), vec![(id, vec![temp.clone()])]);
+        assert_eq!(expired("2021-12-02T00:00:00"), vec![(id, vec![temp, dated])]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn renames_tag_with_values() -> Result<()> {
+        let mut registry = TagRegistry::new_in_memory();
+
+        let tag = Tag::new("toread", Black);
+
+        let id = registry.add_or_update_entry(EntryData::new("/tmp")?);
+        registry.tag_entry(&tag, id);
+        registry.set_value(&tag, id, "expires=2021-12-01");
+        registry.set_tag_icon("toread", "\u{f02d}");
+
+        assert!(registry.update_tag_name("toread", "later"));
+        assert!(registry.get_tag("toread").is_none());
+        assert_eq!(registry.list_entries_with_tags(vec!["later"]), vec![id]);
+        assert_eq!(registry.get_value("toread", id), None);
+        assert_eq!(registry.get_value("later", id), Some("expires=2021-12-01"));
+        assert!(registry.list_orphaned_values().is_empty());
+        assert_eq!(registry.get_tag_icon("toread"), None);
+        assert_eq!(registry.get_tag_icon("later"), Some("\u{f02d}"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn merges_tags_with_shared_values() -> Result<()> {
+        let mut registry = TagRegistry::new_in_memory();
+
+        let tag1 = Tag::new("todo", Black);
+        let tag2 = Tag::new("task", Red);
+
+        let id1 = registry.add_or_update_entry(EntryData::new("/tmp")?);
+        let id2 = registry.add_or_update_entry(EntryData::new("/tmp/1")?);
+        let id3 = registry.add_or_update_entry(EntryData::new("/tmp/2")?);
+
+        // Both tags with a value on the first entry, one tag on each of the others
+        registry.tag_entry(&tag1, id1);
+        registry.tag_entry(&tag2, id1);
+        registry.set_value(&tag1, id1, "1");
+        registry.set_value(&tag2, id1, "2");
+        registry.tag_entry(&tag1, id2);
+        registry.set_value(&tag1, id2, "3");
+        registry.tag_entry(&tag2, id3);
+        registry.set_value(&tag2, id3, "4");
+
+        assert!(registry.update_tag_name("todo", "task"));
+        assert!(registry.get_tag("todo").is_none());
+        assert_eq!(registry.list_tags().count(), 1);
+
+        let entries = registry.list_entries_with_tags(vec!["task"]);
+        assert_eq!(entries.len(), 3);
+        assert!(entries.contains(&id1));
+        assert!(entries.contains(&id2));
+        assert!(entries.contains(&id3));
+
+        // The value of the tag being merged into is kept
+        assert_eq!(registry.get_value("task", id1), Some("2"));
+        assert_eq!(registry.get_value("task", id2), Some("3"));
+        assert_eq!(registry.get_value("task", id3), Some("4"));
+        assert!(registry.list_orphaned_values().is_empty());
+
+        registry.untag_by_name("task", id2);
+        assert_eq!(registry.get_value("task", id2), None);
+        assert_eq!(registry.get_value("task", id1), Some("2"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn removes_orphans() -> Result<()> {
+        let mut registry = TagRegistry::new_in_memory();
+
+        let tag1 = Tag::new("used", Black);
+        let tag2 = Tag::new("unused", Red);
+
+        let id = registry.add_or_update_entry(EntryData::new("/tmp")?);
+        registry.tag_entry(&tag1, id);
+        registry.set_value(&tag1, id, "1");
+
+        // Reference an entry that does not exist and a tag not on the entry
+        registry.tag_entry(&tag2, id + 1);
+        registry.set_value(&tag2, id, "2");
+
+        assert_eq!(registry.list_unused_tags(), vec![&tag2]);
+        assert_eq!(registry.list_orphaned_values(), vec![(id, &"unused".to_owned())]);
+
+        assert_eq!(registry.remove_orphans(), (1, 1));
+        assert!(registry.list_unused_tags().is_empty());
+        assert!(registry.list_orphaned_values().is_empty());
+        assert_eq!(registry.get_value("used", id), Some("1"));
+        assert_eq!(registry.list_entries().count(), 1);
+
+        Ok(())
+    }
+
     #[test]
     fn saves_and_loads() -> Result<()> {
         let tmp_dir = tempfile::tempdir().unwrap();
@@ -1026,4 +2756,156 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn saves_and_loads_invalid_utf8_paths() -> Result<()> {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let tmp_dir = tempfile::tempdir()?;
+        let registry_path = tmp_dir.path().join("wutag.registry");
+        let path = tmp_dir.path().join(OsStr::from_bytes(b"caf\xe9.txt"));
+        // The filesystem may only allow names that are valid UTF-8
+        if fs::write(&path, "").is_err() {
+            return Ok(());
+        }
+
+        let mut registry = TagRegistry::new(&registry_path);
+        let tag = Tag::new("src", Black);
+        let id = registry.add_or_update_entry(EntryData::new(&path)?);
+        registry.tag_entry(&tag, id);
+        registry.stash_entry("stash", id);
+        let id = registry.add_or_update_entry(EntryData::new(&path)?);
+        registry.tag_entry(&tag, id);
+        registry.save()?;
+
+        let registry = TagRegistry::load(registry_path, &EncryptConfig::default())?;
+        assert_eq!(registry.find_entry(&path), Some(id));
+        assert!(registry.stashes["stash"].contains_key(&path));
+        assert!(registry.dir_usage.contains_key(tmp_dir.path()));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "write-through")]
+    #[test]
+    fn writes_tags_through_to_registry() -> Result<()> {
+        use wutag_core::DirEntryExt;
+
+        let tmp_dir = tempfile::tempdir()?;
+        let path = tmp_dir.path().join("file");
+        fs::write(&path, "")?;
+
+        let mut registry = TagRegistry::new_in_memory();
+        let tag = Tag::new("through", Red);
+
+        // The filesystem may not support extended attributes
+        if path.as_path().tag_with(&tag, &mut registry).is_err() {
+            assert!(registry.find_entry(&path).is_none());
+            return Ok(());
+        }
+
+        let id = registry.find_entry(&path).unwrap();
+        assert_eq!(registry.list_entry_tags(id), Some(vec![&tag]));
+
+        path.as_path().untag_with(&tag, &mut registry)?;
+        assert!(registry.find_entry(&path).is_none());
+        assert!(!path.as_path().has_tags()?);
+
+        path.as_path().tag_with(&tag, &mut registry)?;
+        path.as_path().clear_tags_with(&mut registry)?;
+        assert!(registry.find_entry(&path).is_none());
+
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn round_trips_tags_and_values(
+            tags in prop::collection::btree_map(
+                "\\p{L}[\\p{L}\\p{N}_.=-]{0,15}",
+                ("\\p{L}[\\p{L}\\p{N}_.=-]{0,15}", 0..DEFAULT_COLORS.len()),
+                1..8,
+            )
+        ) {
+            let mut registry = TagRegistry::new_in_memory();
+            let id = registry.add_or_update_entry(EntryData::new("/tmp").unwrap());
+            for (name, (value, color)) in &tags {
+                let tag = Tag::new(name.as_str(), DEFAULT_COLORS[*color]);
+                registry.tag_entry(&tag, id);
+                registry.set_value(&tag, id, value.as_str());
+            }
+
+            let serialized = serde_yaml::to_vec(&registry).unwrap();
+            let loaded: TagRegistry = serde_yaml::from_slice(&serialized).unwrap();
+            prop_assert_eq!(&serde_yaml::to_vec(&loaded).unwrap(), &serialized);
+            prop_assert_eq!(loaded.list_entry_values(id), registry.list_entry_values(id));
+
+            // Tags are written to the extended attributes of a file as CBOR
+            for tag in loaded.list_tags() {
+                let original = registry.get_tag(tag.name()).unwrap();
+                prop_assert_eq!(
+                    serde_cbor::to_vec(tag).unwrap(),
+                    serde_cbor::to_vec(original).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lists_sync_changes() -> Result<()> {
+        let mut local = TagRegistry::new_in_memory();
+        let mut other = TagRegistry::new_in_memory();
+
+        let todo = Tag::new("todo", Black);
+        let other_todo = Tag::new("todo", Red);
+        let done = Tag::new("done", Red);
+
+        // The entry in the other registry is older, so its values do not win
+        let mut older = EntryData::new("/tmp")?;
+        older.modtime = SystemTime::UNIX_EPOCH;
+        let id = local.add_or_update_entry(EntryData::new("/tmp")?);
+        let other_id = other.add_or_update_entry(older);
+
+        local.tag_entry(&todo, id);
+        local.set_value(&todo, id, "1");
+        other.tag_entry(&other_todo, other_id);
+        other.set_value(&other_todo, other_id, "2");
+        other.tag_entry(&done, other_id);
+        other.set_value(&done, other_id, "3");
+
+        let changes = local.sync_changes(&other);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[&PathBuf::from("/tmp")], vec![
+            SyncChange::Tag(done.clone()),
+            SyncChange::Value {
+                tag: "done".to_owned(),
+                old: None,
+                new: "3".to_owned(),
+            },
+        ]);
+
+        // Once the other entry is newer, its conflicting value is taken
+        let mut local = TagRegistry::new_in_memory();
+        let mut other = TagRegistry::new_in_memory();
+        let mut older = EntryData::new("/tmp")?;
+        older.modtime = SystemTime::UNIX_EPOCH;
+        let id = local.add_or_update_entry(older);
+        let other_id = other.add_or_update_entry(EntryData::new("/tmp")?);
+
+        local.tag_entry(&todo, id);
+        local.set_value(&todo, id, "1");
+        other.tag_entry(&other_todo, other_id);
+        other.set_value(&other_todo, other_id, "2");
+
+        let changes = local.sync_changes(&other);
+        assert_eq!(changes[&PathBuf::from("/tmp")], vec![SyncChange::Value {
+            tag: "todo".to_owned(),
+            old: Some("1".to_owned()),
+            new: "2".to_owned(),
+        }]);
+
+        assert!(local.sync_changes(&local).is_empty());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,654 @@
+//! A registry that is shared over the network. The server (`wutag serve`) holds
+//! the registry file, and clients that use a registry like `http://host:port`
+//! fetch the entire registry when starting and send it back when saving.
+//!
+//! Protocol:
+//!   - `GET /registry`: returns the registry serialized as YAML
+//!   - `PUT /registry`: replaces the registry with the YAML in the body
+//!
+//! The last client to save wins. The registry file on the server cannot be
+//! encrypted. A registry larger than 64 MiB is refused with `413 Payload Too
+//! Large`, a request line and headers larger than 16 KiB or with more than 100
+//! headers with `431 Request Header Fields Too Large`, and a client that stops
+//! sending for 30 seconds is dropped
+//!
+//! When the server has tokens (see `wutag auth`), each request must have an
+//! `Authorization: Bearer <token>` header with one of them, or it is answered
+//! with `401 Unauthorized`. The server only knows the hashes of the tokens
+//!
+//! The `serve` section of the configuration of the server can protect tags
+//! from clients: a `PUT` that adds a read-only tag to or removes it from a
+//! file, changes its value or color, or does the same to a tag that only other
+//! tokens can change (`tag_writers`), is answered with `403 Forbidden` and the
+//! registry is left as it was
+
+use anyhow::{anyhow, Context, Result};
+use colored::Color;
+use rand::{distributions::Alphanumeric, Rng};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    config::{EncryptConfig, ServeConfig},
+    registry::TagRegistry,
+    storage::{FileStorage, Storage},
+    wutag_error, wutag_info,
+};
+
+/// Default address the server listens on
+pub(crate) const DEFAULT_ADDRESS: &str = "127.0.0.1:7373";
+
+/// The only endpoint of the server
+const ENDPOINT: &str = "/registry";
+
+/// Environment variable holding the token that is sent to the server, which
+/// takes precedence over the one saved with `wutag auth login`
+pub(crate) const TOKEN_ENV: &str = "WUTAG_TOKEN";
+
+/// Length of a generated token
+const TOKEN_LEN: usize = 40;
+
+/// Largest registry a client can send, so that a request cannot make the
+/// server allocate any amount of memory
+const MAX_BODY_LEN: usize = 64 * 1024 * 1024;
+
+/// Largest request line and headers a client can send, which are read before
+/// the length of the body is known
+const MAX_HEAD_LEN: u64 = 16 * 1024;
+
+/// Most headers a request can have
+const MAX_HEADERS: usize = 100;
+
+/// How long the server waits on a client that stopped sending or reading,
+/// since it only handles one connection at a time
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Whether the registry given is served over the network
+pub(crate) fn is_remote<S: AsRef<str>>(registry: S) -> bool {
+    registry.as_ref().starts_with("http://")
+}
+
+/// The `host:port` of the registry server at `url`
+pub(crate) fn host(url: &str) -> Result<&str> {
+    url.strip_prefix("http://")
+        .and_then(|u| u.split('/').next())
+        .filter(|h| !h.is_empty())
+        .with_context(|| format!("invalid registry url: {}", url))
+}
+
+/// Generate a new token for clients to authenticate with
+pub(crate) fn generate_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LEN)
+        .map(char::from)
+        .collect()
+}
+
+/// Hash of a token, which is what the server keeps
+pub(crate) fn hash_token(token: &str) -> String {
+    blake3::hash(token.trim().as_bytes()).to_hex().to_string()
+}
+
+/// Who sent a request to the server
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Client<'a> {
+    /// Anyone who can connect, since the server has no tokens
+    Anyone,
+    /// The holder of the token with this name
+    Token(&'a str),
+}
+
+/// The client whose `Authorization` header is allowed by the server with the
+/// `tokens`, which are the hashes of the tokens keyed by their names. Any
+/// request is allowed if there are none
+fn authorize<'a>(tokens: &'a BTreeMap<String, String>, header: Option<&str>) -> Option<Client<'a>> {
+    if tokens.is_empty() {
+        return Some(Client::Anyone);
+    }
+
+    let token = hash_token(header.and_then(|h| h.trim().strip_prefix("Bearer "))?);
+
+    // Every hash is compared in full so the time taken does not tell how much
+    // of a token was right
+    tokens.iter().fold(None, |found, (name, hash)| {
+        let same = hash.len() == token.len()
+            && hash
+                .bytes()
+                .zip(token.bytes())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0;
+        found.or_else(|| same.then(|| Client::Token(name.as_str())))
+    })
+}
+
+/// The color of each tag of the `registry`, along with the files it is on and
+/// its value on each of them. Files are keyed by their path, since ids are not
+/// the same in the registry of a client
+fn tag_states(registry: &TagRegistry) -> BTreeMap<&str, (Color, BTreeMap<PathBuf, Option<&str>>)> {
+    registry
+        .tags
+        .iter()
+        .map(|(tag, ids)| {
+            let files = ids
+                .iter()
+                .filter_map(|id| {
+                    let entry = registry.entries.get(id)?;
+                    Some((
+                        entry.path().to_path_buf(),
+                        registry.get_value(tag.name(), *id),
+                    ))
+                })
+                .collect();
+            (tag.name(), (*tag.color(), files))
+        })
+        .collect()
+}
+
+/// The names of the tags that were added to or removed from a file, or whose
+/// color or values were changed, between the registries `old` and `new`
+fn changed_tags(old: &TagRegistry, new: &TagRegistry) -> BTreeSet<String> {
+    let (old, new) = (tag_states(old), tag_states(new));
+    old.keys()
+        .chain(new.keys())
+        .filter(|tag| old.get(*tag) != new.get(*tag))
+        .map(|tag| (*tag).to_owned())
+        .collect()
+}
+
+/// Whether the `client` may change the `tag` under the access control `acl`
+fn may_change(acl: &ServeConfig, tag: &str, client: Client) -> bool {
+    if acl.read_only_tags.iter().any(|t| t == tag) {
+        return false;
+    }
+
+    match (acl.tag_writers.get(tag), client) {
+        (None, _) => true,
+        (Some(names), Client::Token(name)) => names.iter().any(|n| n == name),
+        (Some(_), Client::Anyone) => false,
+    }
+}
+
+/// The tags that the `client` is not allowed to change but that differ in the
+/// registry it sent, `new`, from the registry at `path`
+fn denied_tags(
+    path: &Path,
+    new: &TagRegistry,
+    acl: &ServeConfig,
+    client: Client,
+) -> Result<Vec<String>> {
+    if acl.read_only_tags.is_empty() && acl.tag_writers.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let old = if path.is_file() {
+        serde_yaml::from_slice(&fs::read(path)?).context("failed to deserialize tag registry")?
+    } else {
+        TagRegistry::new(path)
+    };
+
+    Ok(changed_tags(&old, new)
+        .into_iter()
+        .filter(|tag| !may_change(acl, tag, client))
+        .collect())
+}
+
+/// The token that is sent to the server at `host`, if there is one
+fn client_token(host: &str) -> Option<String> {
+    if let Some(token) = env::var(TOKEN_ENV).ok().filter(|t| !t.trim().is_empty()) {
+        return Some(token.trim().to_owned());
+    }
+
+    #[cfg(feature = "auth")]
+    {
+        crate::auth::client_token(host)
+    }
+    #[cfg(not(feature = "auth"))]
+    {
+        let _ = host;
+        None
+    }
+}
+
+/// Send a request to the registry server, returning the body of the response
+fn request(url: &str, method: &str, body: &[u8]) -> Result<Vec<u8>> {
+    send(url, method, body, client_token(host(url)?).as_deref())
+}
+
+/// Send a request to the registry server with the `token`, returning the body
+/// of the response
+fn send(url: &str, method: &str, body: &[u8], token: Option<&str>) -> Result<Vec<u8>> {
+    let host = host(url)?;
+    let mut stream = TcpStream::connect(host)
+        .with_context(|| format!("failed to connect to registry: {}", url))?;
+
+    let auth = token.map_or_else(String::new, |t| format!("Authorization: Bearer {}\r\n", t));
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+        method,
+        ENDPOINT,
+        host,
+        auth,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()?;
+
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
+        .context("failed to read response from registry server")?;
+
+    let pos = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .context("invalid response from registry server")?;
+    let head = String::from_utf8_lossy(&response[..pos]);
+    let status = head.lines().next().unwrap_or_default();
+    let code = status.split_whitespace().nth(1).unwrap_or_default();
+
+    let body = &response[pos + 4..];
+    if code == "401" {
+        return Err(anyhow!(
+            "registry server responded with: {}. Save a token for it with 'wutag auth login {}' \
+             or set '{}'",
+            status,
+            url,
+            TOKEN_ENV
+        ));
+    } else if code == "403" {
+        return Err(anyhow!(
+            "registry server responded with: {}. {}",
+            status,
+            String::from_utf8_lossy(body)
+        ));
+    } else if !code.starts_with('2') {
+        return Err(anyhow!("registry server responded with: {}", status));
+    }
+
+    Ok(body.to_vec())
+}
+
+/// A registry served by `wutag serve` at `url`. It is fetched whole when it is
+/// loaded and sent back whole when it is saved
+#[derive(Debug, Clone)]
+pub(crate) struct RemoteStorage {
+    url: String,
+}
+
+impl RemoteStorage {
+    pub(crate) fn new<S: AsRef<str>>(url: S) -> Self {
+        Self {
+            url: url.as_ref().to_owned(),
+        }
+    }
+}
+
+impl Storage for RemoteStorage {
+    /// Fetch the registry from the server
+    fn load(self: Arc<Self>) -> Result<TagRegistry> {
+        let body = request(&self.url, "GET", &[])?;
+        let mut registry: TagRegistry =
+            serde_yaml::from_slice(&body).context("failed to deserialize remote tag registry")?;
+        registry.set_storage(self);
+
+        Ok(registry)
+    }
+
+    /// Send the registry to the server, replacing the registry there
+    fn save(&self, registry: &TagRegistry) -> Result<()> {
+        let serialized =
+            serde_yaml::to_vec(registry).context("failed to serialize tag registry")?;
+        request(&self.url, "PUT", &serialized).map(|_| ())
+    }
+
+    fn url(&self) -> Option<&str> {
+        Some(&self.url)
+    }
+}
+
+/// Handle a single request to the server, which only allows the `tokens` and
+/// only lets them change the tags that the `acl` allows
+fn handle(
+    stream: &mut TcpStream,
+    path: &Path,
+    tokens: &BTreeMap<String, String>,
+    acl: &ServeConfig,
+) -> Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    // A line without its end cannot make the server read past the budget
+    let mut head = (&mut reader).take(MAX_HEAD_LEN);
+    let mut request_line = String::new();
+    head.read_line(&mut request_line)?;
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    let mut headers = 0_usize;
+    loop {
+        let mut line = String::new();
+        if head.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        headers += 1;
+        if headers > MAX_HEADERS {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            if key.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().context("invalid content length")?;
+            } else if key.trim().eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_owned());
+            }
+        }
+    }
+
+    let head_too_large = head.limit() == 0 || headers > MAX_HEADERS;
+
+    let client = authorize(tokens, authorization.as_deref());
+    let too_large = content_length > MAX_BODY_LEN;
+    let len = if client.is_some() && !too_large && !head_too_large {
+        content_length
+    } else {
+        0
+    };
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+
+    let mut parts = request_line.split_whitespace();
+    let (status, response) = match (parts.next(), parts.next(), client) {
+        _ if head_too_large => ("431 Request Header Fields Too Large", Vec::new()),
+        (_, _, None) => ("401 Unauthorized", Vec::new()),
+        _ if too_large => ("413 Payload Too Large", Vec::new()),
+        (Some("GET"), Some(ENDPOINT), _) =>
+            if path.is_file() {
+                ("200 OK", fs::read(path)?)
+            } else {
+                ("200 OK", serde_yaml::to_vec(&TagRegistry::new(path))?)
+            },
+        (Some("PUT"), Some(ENDPOINT), Some(client)) =>
+            match serde_yaml::from_slice::<TagRegistry>(&body) {
+                Ok(mut registry) => match denied_tags(path, &registry, acl, client)? {
+                    denied if denied.is_empty() => {
+                        registry.path = path.to_path_buf();
+                        registry.set_storage(Arc::new(FileStorage::new(
+                            path,
+                            &EncryptConfig::default(),
+                        )));
+                        registry.save()?;
+                        ("204 No Content", Vec::new())
+                    },
+                    denied => {
+                        log::debug!("{:?} cannot change the tags {:?}", client, denied);
+                        let message =
+                            format!("not allowed to change the tags: {}", denied.join(", "));
+                        ("403 Forbidden", message.into_bytes())
+                    },
+                },
+                Err(e) => ("400 Bad Request", e.to_string().into_bytes()),
+            },
+        _ => ("404 Not Found", Vec::new()),
+    };
+
+    log::debug!("{} => {}", request_line.trim_end(), status);
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/x-yaml\r\nContent-Length: {}\r\nConnection: \
+         close\r\n\r\n",
+        status,
+        response.len()
+    )?;
+    stream.write_all(&response)?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+/// Serve the registry at `path` on `addr` to the clients with one of the
+/// `tokens` (their hashes keyed by name), which can change the tags that the
+/// `acl` allows. Requests are handled one at a time
+pub(crate) fn serve<A: ToSocketAddrs>(
+    addr: A,
+    path: &Path,
+    tokens: &BTreeMap<String, String>,
+    acl: &ServeConfig,
+) -> Result<()> {
+    let listener = TcpListener::bind(addr).context("failed to bind the registry server")?;
+    wutag_info!(
+        "serving {} on http://{}",
+        path.display(),
+        listener.local_addr()?
+    );
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(mut stream) =>
+                if let Err(e) = handle(&mut stream, path, tokens, acl) {
+                    wutag_error!("{}", e);
+                },
+            Err(e) => wutag_error!("{}", e),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::EntryData;
+    use colored::Color::Black;
+    use wutag_core::tag::Tag;
+
+    #[test]
+    fn pushes_and_fetches_registry() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let path = tmp_dir.path().join("wutag.registry");
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}", listener.local_addr()?);
+
+        let server_path = path.clone();
+        let server = std::thread::spawn(move || {
+            for stream in listener.incoming().take(3) {
+                handle(
+                    &mut stream.unwrap(),
+                    &server_path,
+                    &BTreeMap::new(),
+                    &ServeConfig::default(),
+                )
+                .unwrap();
+            }
+        });
+
+        let fetch = || Arc::new(RemoteStorage::new(&url)).load();
+        let mut registry = fetch()?;
+        assert_eq!(registry.remote(), Some(url.as_str()));
+        assert_eq!(registry.list_entries().count(), 0);
+
+        let tag = Tag::new("remote", Black);
+        let id = registry.add_or_update_entry(EntryData::new("/tmp")?);
+        registry.tag_entry(&tag, id);
+        registry.save()?;
+
+        let registry = fetch()?;
+        assert_eq!(registry.list_entries_with_tags(vec!["remote"]), vec![id]);
+        assert!(path.is_file());
+
+        server.join().unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn requires_token() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let path = tmp_dir.path().join("wutag.registry");
+        let token = generate_token();
+        let tokens = BTreeMap::from([(String::from("laptop"), hash_token(&token))]);
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}", listener.local_addr()?);
+
+        let server = std::thread::spawn(move || {
+            for stream in listener.incoming().take(3) {
+                handle(
+                    &mut stream.unwrap(),
+                    &path,
+                    &tokens,
+                    &ServeConfig::default(),
+                )
+                .unwrap();
+            }
+        });
+
+        let err = send(&url, "GET", &[], None).unwrap_err().to_string();
+        assert!(err.contains("401 Unauthorized"));
+        assert!(send(&url, "GET", &[], Some("wrong")).is_err());
+        assert!(send(&url, "GET", &[], Some(&token)).is_ok());
+
+        server.join().unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_large_bodies() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let path = tmp_dir.path().join("wutag.registry");
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            handle(&mut stream, &path, &BTreeMap::new(), &ServeConfig::default()).unwrap();
+        });
+
+        let mut stream = TcpStream::connect(addr)?;
+        write!(
+            stream,
+            "PUT {} HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            ENDPOINT,
+            MAX_BODY_LEN + 1
+        )?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        assert!(response.starts_with("HTTP/1.1 413 Payload Too Large"));
+
+        server.join().unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_large_heads() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let path = tmp_dir.path().join("wutag.registry");
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let server = std::thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                handle(
+                    &mut stream.unwrap(),
+                    &path,
+                    &BTreeMap::new(),
+                    &ServeConfig::default(),
+                )
+                .unwrap();
+            }
+        });
+
+        let request = |head: String| -> Result<String> {
+            let mut stream = TcpStream::connect(addr)?;
+            stream.write_all(head.as_bytes())?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            Ok(response)
+        };
+
+        // A request line that never ends
+        let line = format!("GET /{}", "a".repeat(MAX_HEAD_LEN as usize - 5));
+        assert!(request(line)?.starts_with("HTTP/1.1 431 Request Header Fields Too Large"));
+
+        let headers = "X-Tag: a\r\n".repeat(MAX_HEADERS + 1);
+        let head = format!("GET {} HTTP/1.1\r\n{}\r\n", ENDPOINT, headers);
+        assert!(request(head)?.starts_with("HTTP/1.1 431 Request Header Fields Too Large"));
+
+        server.join().unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn checks_authorization_header() {
+        let token = generate_token();
+        assert_eq!(token.len(), TOKEN_LEN);
+
+        let tokens = BTreeMap::from([
+            (String::from("desktop"), hash_token("other")),
+            (String::from("laptop"), hash_token(&token)),
+        ]);
+        assert_eq!(
+            authorize(&tokens, Some(&format!("Bearer {}", token))),
+            Some(Client::Token("laptop"))
+        );
+        assert_eq!(authorize(&tokens, Some(&format!("Basic {}", token))), None);
+        assert_eq!(authorize(&tokens, Some("Bearer wrong")), None);
+        assert_eq!(authorize(&tokens, None), None);
+        assert_eq!(authorize(&BTreeMap::new(), None), Some(Client::Anyone));
+    }
+
+    #[test]
+    fn enforces_tag_access() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let path = tmp_dir.path().join("wutag.registry");
+        let acl = ServeConfig {
+            read_only_tags: vec![String::from("archived")],
+            tag_writers:    BTreeMap::from([(String::from("work"), vec![String::from("laptop")])]),
+        };
+
+        let mut old = TagRegistry::new(&path);
+        let id = old.add_or_update_entry(EntryData::new("/tmp")?);
+        old.tag_entry(&Tag::new("archived", Black), id);
+        old.save()?;
+
+        let mut new = old.clone();
+        new.tag_entry(&Tag::new("work", Black), id);
+        new.tag_entry(&Tag::new("misc", Black), id);
+        assert_eq!(
+            changed_tags(&old, &new).into_iter().collect::<Vec<_>>(),
+            vec!["misc", "work"]
+        );
+        assert!(denied_tags(&path, &new, &acl, Client::Token("laptop"))?.is_empty());
+        assert_eq!(
+            denied_tags(&path, &new, &acl, Client::Token("desktop"))?,
+            vec!["work"]
+        );
+        assert_eq!(
+            denied_tags(&path, &new, &acl, Client::Anyone)?,
+            vec!["work"]
+        );
+
+        let mut new = old.clone();
+        new.clear_entry(id);
+        assert_eq!(
+            denied_tags(&path, &new, &acl, Client::Token("laptop"))?,
+            vec!["archived"]
+        );
+        assert!(denied_tags(&path, &new, &ServeConfig::default(), Client::Anyone)?.is_empty());
+
+        Ok(())
+    }
+}
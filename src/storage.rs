@@ -0,0 +1,217 @@
+//! Where the registry is kept. Each place is a [`Storage`], which is chosen
+//! when the registry is loaded from the value of `registry` in the
+//! configuration (or `-R|--registry`):
+//!
+//! - [`FileStorage`]: a YAML file on this machine, which is the default
+//! - [`RemoteStorage`]: a registry shared with `wutag serve`, for a value like
+//!   `http://host:port`
+//! - [`MemoryStorage`]: a registry that is never written anywhere, for the
+//!   value `memory:`. Tests use it to not touch the disk
+//!
+//! A loaded registry keeps its storage, so that saving it writes it back to
+//! where it came from. Subcommands only see the [`TagRegistry`], so another
+//! backend only needs another implementation of [`Storage`]
+
+use crate::{
+    config::EncryptConfig,
+    registry::TagRegistry,
+    remote::{self, RemoteStorage},
+};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    process,
+    sync::{Arc, Mutex},
+};
+
+/// Location of a registry that is only kept in memory
+pub(crate) const MEMORY: &str = "memory:";
+
+/// A place the registry can be loaded from and saved to
+pub(crate) trait Storage: fmt::Debug + Send + Sync {
+    /// Read the registry, which keeps this storage to be saved to
+    fn load(self: Arc<Self>) -> Result<TagRegistry>;
+
+    /// Replace the registry that is kept in the storage with `registry`
+    fn save(&self, registry: &TagRegistry) -> Result<()>;
+
+    /// URL of the server that holds the registry, if it is not on this machine
+    fn url(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Whether the registry at `location` is kept in a file
+pub(crate) fn is_path(location: &str) -> bool {
+    location != MEMORY && !remote::is_remote(location)
+}
+
+/// The storage of the registry at `location`, which is a path, the URL of a
+/// registry served with `wutag serve`, or [`MEMORY`]
+pub(crate) fn open(location: &str, config: &EncryptConfig) -> Arc<dyn Storage> {
+    if location == MEMORY {
+        log::debug!("using a registry in memory");
+        Arc::new(MemoryStorage::default())
+    } else if remote::is_remote(location) {
+        log::debug!("using a remote registry: {}", location);
+        Arc::new(RemoteStorage::new(location))
+    } else {
+        Arc::new(FileStorage::new(location, config))
+    }
+}
+
+/// The storage of a registry that was deserialized, which cannot be saved
+/// until it is given a storage of its own
+pub(crate) fn detached() -> Arc<dyn Storage> {
+    Arc::new(FileStorage::new("", &EncryptConfig::default()))
+}
+
+/// A registry kept in a YAML file, which can be encrypted with `gpg`
+#[derive(Debug, Clone)]
+pub(crate) struct FileStorage {
+    path:    PathBuf,
+    encrypt: EncryptConfig,
+}
+
+impl FileStorage {
+    pub(crate) fn new<P: AsRef<Path>>(path: P, config: &EncryptConfig) -> Self {
+        Self {
+            path:    path.as_ref().to_path_buf(),
+            encrypt: config.clone(),
+        }
+    }
+}
+
+impl Storage for FileStorage {
+    fn load(self: Arc<Self>) -> Result<TagRegistry> {
+        TagRegistry::load(&self.path, &self.encrypt)
+    }
+
+    /// The registry is written to a temporary file next to it that is renamed
+    /// over it, so that a command reading it at the same time (e.g., `list`
+    /// while the `service` is repairing it) reads either the old or the new
+    /// registry instead of a partial one, without waiting on a lock
+    fn save(&self, registry: &TagRegistry) -> Result<()> {
+        if self.path.as_os_str().is_empty() {
+            return Err(anyhow!("the registry has no file to be saved to"));
+        }
+
+        let serialized =
+            serde_yaml::to_vec(registry).context("failed to serialize tag registry")?;
+
+        // A symlink to the registry is kept by replacing its target
+        let path = fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone());
+        let tmp = path.with_file_name(format!(
+            ".{}.{}.tmp",
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            process::id()
+        ));
+
+        fs::write(&tmp, &serialized)
+            .and_then(|_| match fs::metadata(&path) {
+                Ok(meta) => fs::set_permissions(&tmp, meta.permissions()),
+                Err(_) => Ok(()),
+            })
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|e| {
+                let _ = fs::remove_file(&tmp);
+                e
+            })
+            .context("failed to save registry")
+    }
+}
+
+/// A registry that is only kept in memory. Saving it keeps a serialized copy
+/// that it is loaded from, and nothing is written to disk, so tests can use as
+/// many as they need at once
+#[derive(Debug, Default)]
+pub(crate) struct MemoryStorage {
+    saved: Mutex<Option<Vec<u8>>>,
+}
+
+impl Storage for MemoryStorage {
+    fn load(self: Arc<Self>) -> Result<TagRegistry> {
+        let saved = self
+            .saved
+            .lock()
+            .map_err(|_| anyhow!("registry in memory is poisoned"))?
+            .clone();
+        let mut registry = match saved {
+            Some(data) =>
+                serde_yaml::from_slice(&data).context("failed to deserialize tag registry")?,
+            None => TagRegistry::new(""),
+        };
+        registry.set_storage(self);
+        Ok(registry)
+    }
+
+    fn save(&self, registry: &TagRegistry) -> Result<()> {
+        let serialized =
+            serde_yaml::to_vec(registry).context("failed to serialize tag registry")?;
+        *self
+            .saved
+            .lock()
+            .map_err(|_| anyhow!("registry in memory is poisoned"))? = Some(serialized);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::EntryData;
+    use colored::Color::Black;
+    use wutag_core::tag::Tag;
+
+    #[test]
+    fn keeps_registry_in_memory() -> Result<()> {
+        let storage = Arc::new(MemoryStorage::default());
+        let mut registry = Arc::clone(&storage).load()?;
+        assert_eq!(registry.list_entries().count(), 0);
+
+        let id = registry.add_or_update_entry(EntryData::new("/tmp")?);
+        registry.tag_entry(&Tag::new("kept", Black), id);
+        registry.save()?;
+
+        let loaded = Arc::clone(&storage).load()?;
+        assert_eq!(loaded.list_entries_with_tags(vec!["kept"]), vec![id]);
+        assert!(loaded.remote().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn opens_storage_by_location() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("wutag.registry");
+        let config = EncryptConfig::default();
+
+        assert!(open(&path.display().to_string(), &config).load().is_err());
+
+        let mut registry = TagRegistry::new(&path);
+        let id = registry.add_or_update_entry(EntryData::new(dir.path())?);
+        registry.tag_entry(&Tag::new("file", Black), id);
+        registry.save()?;
+
+        let storage = open(&path.display().to_string(), &config);
+        assert!(storage.url().is_none());
+        assert!(storage.load()?.get_tag("file").is_some());
+        assert_eq!(
+            open("http://localhost:7373", &config).url(),
+            Some("http://localhost:7373")
+        );
+
+        let mut registry = open(MEMORY, &config).load()?;
+        let id = registry.add_or_update_entry(EntryData::new(dir.path())?);
+        registry.tag_entry(&Tag::new("memory", Black), id);
+        registry.save()?;
+        assert!(!is_path(MEMORY));
+        assert_eq!(fs::read_dir(dir.path())?.count(), 1);
+
+        let detached: TagRegistry = serde_yaml::from_slice(&serde_yaml::to_vec(&registry)?)?;
+        assert!(detached.save().is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+//! `auth` - Manage the tokens that clients of the registry server authenticate
+//! with, which are kept in the keyring of the OS
+
+use super::{
+    uses::{
+        io, remote, wutag_error, wutag_fatal, wutag_warning, Args, BufRead, Colorize, Context,
+        Result, Subcommand,
+    },
+    App,
+};
+use crate::auth;
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub(crate) enum AuthObject {
+    /// Create a token that the server accepts and print it
+    Add {
+        /// Name of the token, such as the client that uses it
+        name: String,
+    },
+    /// Stop the server from accepting a token
+    Remove {
+        /// Name of the token (see 'auth list')
+        name: String,
+    },
+    /// List the names of the tokens the server accepts
+    List,
+    /// Save the token that is sent to a server, which is read from stdin
+    Login {
+        /// Url of the server (e.g., 'http://host:7373')
+        url: String,
+    },
+    /// Remove the token that is sent to a server
+    Logout {
+        /// Url of the server (e.g., 'http://host:7373')
+        url: String,
+    },
+}
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct AuthOpts {
+    /// The action to take. Valid values are: 'add', 'remove', 'list', 'login',
+    /// 'logout'
+    #[clap(subcommand)]
+    pub(crate) object: AuthObject,
+}
+
+impl App {
+    /// Manage the tokens of the registry server
+    pub(crate) fn auth(&self, opts: &AuthOpts) {
+        log::debug!("AuthOpts: {:#?}", opts);
+
+        let res = match &opts.object {
+            AuthObject::Add { name } => self.add_token(name),
+            AuthObject::Remove { name } => self.remove_token(name),
+            AuthObject::List => auth::server_tokens().map(|tokens| {
+                for name in tokens.keys() {
+                    println!("{}", name.green().bold());
+                }
+            }),
+            AuthObject::Login { url } => self.login(url),
+            AuthObject::Logout { url } => remote::host(url).and_then(|host| {
+                if !auth::logout(host)? {
+                    wutag_warning!("there is no token for {}", url);
+                }
+                Ok(())
+            }),
+        };
+
+        if let Err(e) = res {
+            wutag_error!("{:#}", e);
+        }
+    }
+
+    /// Create the token `name`, which is only displayed this once
+    fn add_token(&self, name: &str) -> Result<()> {
+        let mut tokens = auth::server_tokens()?;
+        if tokens.contains_key(name) {
+            wutag_fatal!(
+                "a token named {} already exists, remove it first to replace it",
+                name
+            );
+        }
+
+        let token = remote::generate_token();
+        tokens.insert(name.to_owned(), remote::hash_token(&token));
+        auth::save_server_tokens(&tokens)?;
+
+        if self.quiet {
+            println!("{}", token);
+        } else {
+            println!(
+                "{} {}: {}\n{}",
+                "\u{2714}".green().bold(),
+                "TOKEN CREATED".red().bold(),
+                name.green().bold(),
+                token
+            );
+            println!(
+                "Give it to the client with 'wutag auth login <url>', it is not displayed again"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Stop accepting the token `name`
+    fn remove_token(&self, name: &str) -> Result<()> {
+        let mut tokens = auth::server_tokens()?;
+        if tokens.remove(name).is_none() {
+            wutag_fatal!("there is no token named {}", name);
+        }
+        auth::save_server_tokens(&tokens)?;
+
+        if !self.quiet {
+            println!(
+                "{} {}: {}",
+                "\u{2718}".red().bold(),
+                "TOKEN REMOVED".red().bold(),
+                name.green().bold()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Save the token read from stdin for the server at `url`
+    fn login(&self, url: &str) -> Result<()> {
+        let host = remote::host(url)?;
+        let mut token = String::new();
+        io::stdin()
+            .lock()
+            .read_line(&mut token)
+            .context("failed to read the token from stdin")?;
+
+        let token = token.trim();
+        if token.is_empty() {
+            wutag_fatal!("no token was given on stdin");
+        }
+        auth::login(host, token)?;
+
+        if !self.quiet {
+            println!(
+                "{} {}: {}",
+                "\u{2714}".green().bold(),
+                "TOKEN SAVED".red().bold(),
+                host.green().bold()
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -1,10 +1,77 @@
 use super::{
-    uses::{io, wutag_error, wutag_fatal, wutag_info, BufRead, BufReader, Colorize, Write},
+    uses::{
+        fmt_path, fmt_tag, io, wutag_error, wutag_fatal, wutag_info, Args, BufRead, BufReader,
+        Colorize, Write,
+    },
     App,
 };
 
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct CleanCacheOpts {
+    /// Only remove unused tags and orphaned values instead of the whole registry
+    #[clap(long, short = 'o')]
+    pub(crate) orphans: bool,
+    /// Display what would be removed without modifying the registry
+    #[clap(long = "dry-run", short = 'd', requires = "orphans")]
+    pub(crate) dry_run: bool,
+    /// Delete the registry without saving a snapshot of it first
+    #[clap(
+        long,
+        conflicts_with = "orphans",
+        long_about = "Delete the registry without saving a snapshot of it first. Otherwise, a \
+                      snapshot labeled 'clean-cache' is saved, which can be restored with 'wutag \
+                      snapshot restore <name>'"
+    )]
+    pub(crate) purge:   bool,
+}
+
 impl App {
-    pub(crate) fn clean_cache(&mut self) {
+    /// Remove the tags that are not attached to any file and the values that
+    /// are attached to a tag no longer on a file
+    fn clean_orphans(&mut self, dry_run: bool) {
+        if !self.quiet {
+            for tag in self.registry.list_unused_tags() {
+                println!("{} {}", "X".bold().red(), fmt_tag(tag));
+            }
+            for (id, name) in self.registry.list_orphaned_values() {
+                if let Some(entry) = self.registry.get_entry(id) {
+                    println!(
+                        "{} {}: {}",
+                        "X".bold().red(),
+                        fmt_path(entry.path(), self.base_color, self.ls_colors),
+                        name.bold()
+                    );
+                } else {
+                    println!("{} {}", "X".bold().red(), name.bold());
+                }
+            }
+        }
+
+        if dry_run {
+            return;
+        }
+
+        let (tags, values) = self.registry.remove_orphans();
+        if let Err(e) = self.registry.save() {
+            wutag_error!("{:?}", e);
+        } else if !self.quiet {
+            println!(
+                "{} {}: {} tag(s), {} value(s)",
+                "\u{2714}".green().bold(),
+                "ORPHANS REMOVED".red().bold(),
+                tags.to_string().green().bold(),
+                values.to_string().green().bold()
+            );
+        }
+    }
+
+    pub(crate) fn clean_cache(&mut self, opts: &CleanCacheOpts) {
+        log::debug!("CleanCacheOpts: {:#?}", opts);
+        if opts.orphans {
+            self.clean_orphans(opts.dry_run);
+            return;
+        }
+
         macro_rules! prompt {
             ($dis:ident, $path:expr) => {
                 $dis!(
@@ -49,6 +116,19 @@ impl App {
         };
 
         if prompt {
+            if !opts.purge {
+                match self.create_snapshot(Some("clean-cache")) {
+                    Ok(name) =>
+                        if !self.quiet {
+                            wutag_info!(
+                                "saved snapshot {}, restore it with 'wutag snapshot restore'",
+                                name.green().bold()
+                            );
+                        },
+                    Err(e) => wutag_fatal!("{}, use --purge to clean anyway", e),
+                }
+            }
+
             self.registry.clear();
             if let Err(e) = self.registry.save() {
                 wutag_error!("{:?}", e);
@@ -1,7 +1,7 @@
 use super::{
     uses::{
-        clear_tags, err, fmt_err, fmt_ok, fmt_path, glob_builder, has_tags, osstr_to_bytes, reg_ok,
-        regex_builder, Arc, Args, Colorize, Cow, DirEntryExt, OsStr,
+        clear_tags, diagnostics, err, fmt_err, fmt_ok, fmt_path, glob_builder, has_tags,
+        osstr_to_bytes, reg_ok, regex_builder, Arc, Args, Colorize, Cow, DirEntryExt, OsStr,
     },
     App,
 };
@@ -30,6 +30,7 @@ impl App {
                 self.case_insensitive,
                 self.case_sensitive,
             );
+            diagnostics::matched(0);
             for (&id, entry) in self.registry.clone().list_entries_and_ids() {
                 let search_str: Cow<OsStr> = Cow::Owned(entry.path().as_os_str().to_os_string());
                 let search_bytes = &osstr_to_bytes(search_str.as_ref());
@@ -44,6 +45,7 @@ impl App {
                 }
 
                 if re.is_match(search_bytes) {
+                    diagnostics::matched(1);
                     self.registry.clear_entry(id);
                     match has_tags(entry.path()) {
                         Ok(has_tags) =>
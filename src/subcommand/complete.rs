@@ -0,0 +1,48 @@
+//! Candidates for shell completion, printed one per line without any color
+
+use super::{
+    uses::{Args, Subcommand},
+    App,
+};
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub(crate) enum CompleteObject {
+    /// Print the names of the tags in the registry
+    Tags {
+        /// Order the tags by how often and how recently they were used
+        #[clap(
+            long,
+            long_about = "Order the tags by how often and how recently they were added to a \
+                          file, so that the tags used daily are first. A use counts for half as \
+                          much after 'mru_half_life' days (default: 7)"
+        )]
+        mru: bool,
+    },
+}
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct CompleteOpts {
+    /// The candidates to print. Valid values are: 'tags'
+    #[clap(subcommand)]
+    pub(crate) object: CompleteObject,
+}
+
+impl App {
+    pub(crate) fn complete(&self, opts: &CompleteOpts) {
+        log::debug!("CompleteOpts: {:#?}", opts);
+
+        match opts.object {
+            CompleteObject::Tags { mru } => {
+                let tags = if mru {
+                    self.registry.list_tags_by_usage(self.mru_half_life)
+                } else {
+                    self.registry.list_tags().collect()
+                };
+
+                for tag in tags {
+                    println!("{}", tag.name());
+                }
+            },
+        }
+    }
+}
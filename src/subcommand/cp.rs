@@ -2,13 +2,16 @@
 
 use super::{
     uses::{
-        err, fmt_err, fmt_path, fmt_tag, glob_builder, list_tags, osstr_to_bytes, parse_path,
-        reg_ok, regex_builder, wutag_error, wutag_fatal, Arc, Args, Colorize, Cow, DirEntryExt,
-        EntryData, OsStr, PathBuf, Result, ValueHint,
+        diagnostics, err, fmt_err, fmt_path, fmt_tag, glob_builder, list_tags, osstr_to_bytes,
+        parse_path, reg_ok, regex_builder, wutag_error, wutag_fatal, Arc, Args, Colorize, Cow,
+        DirEntryExt, EntryData, OsStr, PathBuf, Result, Tag, ValueHint, XattrNamespace,
     },
     App,
 };
 
+use regex::bytes::Regex;
+use std::collections::BTreeSet;
+
 #[derive(Args, Debug, Clone, PartialEq)]
 pub(crate) struct CpOpts {
     /// Use a glob to match files (must be global)
@@ -22,31 +25,202 @@ pub(crate) struct CpOpts {
                       no longer used"
     )]
     pub(crate) glob:       bool,
+    /// Copy the tags of the files matching a query instead of a single file
+    #[clap(
+        name = "from-query",
+        long = "from-query",
+        value_name = "query",
+        number_of_values = 1,
+        multiple_occurrences = true,
+        long_about = "Copy the tags of the files in the registry that match a query, such as \
+                      'tag(template)', instead of the tags of a single file. The queries are the \
+                      same as the ones in 'wutag repl', and are applied in order when given more \
+                      than once. The files to copy them to are given with '--to' instead of the \
+                      positional arguments"
+    )]
+    pub(crate) from_query: Vec<String>,
+    /// Only copy the tags that all files matching the query have
+    #[clap(
+        long,
+        requires = "from-query",
+        long_about = "Only copy the tags that all of the files matching the query have, instead \
+                      of the tags that any of them have"
+    )]
+    pub(crate) intersect:  bool,
+    /// A glob pattern of the files to copy the tags of '--from-query' to
+    #[clap(
+        long,
+        value_name = "pattern",
+        requires = "from-query",
+        required_unless_present = "input_path"
+    )]
+    pub(crate) to:         Option<String>,
+    /// Namespace of the extended attributes to keep the tags in
+    #[clap(
+        long,
+        value_name = "namespace",
+        possible_values = &["user", "trusted", "security"],
+        long_about = "Keep the tags in the extended attributes of this namespace (e.g., \
+                      'trusted.wutag') instead of 'user'. Only root can use 'trusted', which can \
+                      tag files that 'user' does not allow, such as symlinks. The namespace is \
+                      recorded in the registry, so every command reading the tags of its files \
+                      uses it"
+    )]
+    pub(crate) namespace:  Option<XattrNamespace>,
     /// Path to the file from which to copy tags from
     #[clap(
+        name = "input_path",
         value_name = "input_path",
         value_hint = ValueHint::FilePath,
+        required_unless_present = "from-query",
+        conflicts_with = "from-query",
         // validator = |t| parse_path(t) // Would be nice to be aware of other options
     )]
-    pub(crate) input_path: PathBuf,
+    pub(crate) input_path: Option<PathBuf>,
     /// A glob pattern like "*.png".
-    #[clap(value_name = "pattern")]
-    pub(crate) pattern:    String,
+    #[clap(value_name = "pattern", required_unless_present = "from-query")]
+    pub(crate) pattern:    Option<String>,
 }
 
 impl App {
+    /// The tags of the files that match the `queries`. These are the tags that
+    /// any of the files have, or that all of them have if `intersect` is used
+    fn query_tags(&self, queries: &[String], intersect: bool) -> Result<Vec<Tag>> {
+        let mut sources = self.query_entries(queries)?.into_iter().map(|id| {
+            self.registry
+                .list_entry_tags(id)
+                .unwrap_or_default()
+                .into_iter()
+                .cloned()
+                .collect::<BTreeSet<Tag>>()
+        });
+
+        let tags = if intersect {
+            sources
+                .next()
+                .map(|first| sources.fold(first, |acc, tags| &acc & &tags))
+                .unwrap_or_default()
+        } else {
+            sources.flatten().collect()
+        };
+
+        Ok(tags.into_iter().collect())
+    }
+
+    /// Copy the `tags` to the files in the local directory that match `re`
+    fn cp_tags(&mut self, re: Regex, tags: &[Tag]) {
+        reg_ok(
+            &Arc::new(re),
+            &Arc::new(self.clone()),
+            |entry: &ignore::DirEntry| {
+                if !self.quiet {
+                    println!(
+                        "{}:",
+                        fmt_path(entry.path(), self.base_color, self.ls_colors)
+                    );
+                }
+                for tag in tags {
+                    if let Err(e) = entry.tag(tag) {
+                        err!('\t', e, entry);
+                    } else {
+                        let entry = match EntryData::new(entry.path()) {
+                            Ok(data) => data,
+                            Err(e) => {
+                                err!('\t', e, entry);
+                                continue;
+                            },
+                        };
+                        let id = self.registry.add_or_update_entry(entry);
+                        self.registry.use_tag(tag, id);
+                        if !self.quiet {
+                            println!("\t{} {}", "+".bold().green(), fmt_tag(tag));
+                        }
+                    }
+                }
+            },
+        );
+        log::debug!("Saving registry...");
+        self.save_registry();
+    }
+
+    /// Copy the tags of the files matching `--from-query` to the files that
+    /// match `pattern`, either in the local directory or in the registry
+    fn cp_from_query(&mut self, opts: &CpOpts, re: Regex) -> Result<()> {
+        let tags = self.query_tags(&opts.from_query, opts.intersect)?;
+        if tags.is_empty() {
+            wutag_error!("no tags to copy from the files matching the query");
+            return Ok(());
+        }
+
+        if !self.global {
+            self.cp_tags(re, &tags);
+            return Ok(());
+        }
+
+        for (_, entry) in self.registry.clone().list_entries_and_ids() {
+            if !re.is_match(&osstr_to_bytes(entry.path().as_os_str())) {
+                continue;
+            }
+
+            if !self.quiet {
+                println!(
+                    "{}:",
+                    fmt_path(entry.path(), self.base_color, self.ls_colors)
+                );
+            }
+            for tag in &tags {
+                if let Err(e) = entry.path().tag(tag) {
+                    err!('\t', e, entry);
+                } else {
+                    let data = match EntryData::new(entry.path()) {
+                        Ok(data) => data,
+                        Err(e) => {
+                            err!('\t', e, entry);
+                            continue;
+                        },
+                    };
+                    let id = self.registry.add_or_update_entry(data);
+                    self.registry.use_tag(tag, id);
+                    if !self.quiet {
+                        println!("\t{} {}", "+".bold().green(), fmt_tag(tag));
+                    }
+                }
+            }
+        }
+        log::debug!("Saving registry...");
+        self.save_registry();
+
+        Ok(())
+    }
+
     pub(crate) fn cp(&mut self, opts: &CpOpts) -> Result<()> {
         log::debug!("CpOpts: {:#?}", opts);
         log::debug!("Using registry: {}", self.registry.path.display());
 
+        // With `--from-query` the files are given with `--to`
+        let pattern = match (&opts.pattern, &opts.to) {
+            (Some(pattern), None) | (None, Some(pattern)) => pattern.clone(),
+            (Some(_), Some(_)) => wutag_fatal!(
+                "{} takes the pattern of the files instead of a positional argument",
+                "--to".bold()
+            ),
+            (None, None) => wutag_fatal!("a pattern is required"),
+        };
+
         let pat = if self.pat_regex {
-            String::from(&opts.pattern)
+            pattern
         } else {
-            glob_builder(&opts.pattern)
+            glob_builder(&pattern)
         };
 
         let re = regex_builder(&pat, self.case_insensitive, self.case_sensitive);
-        let path = opts.input_path.as_path();
+        if !opts.from_query.is_empty() {
+            return self.cp_from_query(opts, re);
+        }
+        let path = match opts.input_path {
+            Some(ref path) => path.as_path(),
+            None => wutag_fatal!("a file to copy the tags from is required"),
+        };
 
         // FIX: Manage both globs for input and output
         // To do this, a selection menu or something would have to popup to have the
@@ -80,19 +254,28 @@ impl App {
                                 if let Err(e) = entry_path.tag(tag) {
                                     err!('\t', e, entry);
                                 } else {
-                                    let entry = EntryData::new(entry.path())?;
+                                    let entry = match EntryData::new(entry.path()) {
+                                        Ok(data) => data,
+                                        Err(e) => {
+                                            err!('\t', e, entry);
+                                            continue;
+                                        },
+                                    };
                                     let id = self.registry.add_or_update_entry(entry);
-                                    self.registry.tag_entry(tag, id);
+                                    self.registry.use_tag(tag, id);
                                     if !self.quiet {
                                         println!("\t{} {}", "+".bold().green(), fmt_tag(tag));
                                     }
                                 }
                             },
-                        Err(e) => wutag_error!(
-                            "failed to get source tags from `{}` - {}",
-                            path.display(),
-                            e
-                        ),
+                        Err(e) => {
+                            diagnostics::warn();
+                            wutag_error!(
+                                "failed to get source tags from `{}` - {}",
+                                path.display(),
+                                e
+                            );
+                        },
                     }
                 }
                 log::debug!("Saving registry...");
@@ -100,50 +283,20 @@ impl App {
             }
         } else {
             if let Err(e) = parse_path(path) {
+                diagnostics::warn();
                 wutag_error!("{}: {}", e, path.display());
             }
 
             match list_tags(path) {
-                Ok(tags) => {
-                    reg_ok(
-                        &Arc::new(re),
-                        &Arc::new(self.clone()),
-                        |entry: &ignore::DirEntry| {
-                            if !self.quiet {
-                                println!(
-                                    "{}:",
-                                    fmt_path(entry.path(), self.base_color, self.ls_colors)
-                                );
-                            }
-                            for tag in &tags {
-                                if let Err(e) = entry.tag(tag) {
-                                    err!('\t', e, entry);
-                                } else {
-                                    let entry = if let Ok(data) = EntryData::new(entry.path()) {
-                                        data
-                                    } else {
-                                        wutag_fatal!(
-                                            "unable to create new entry: {}",
-                                            entry.path().display()
-                                        );
-                                    };
-                                    let id = self.registry.add_or_update_entry(entry);
-                                    self.registry.tag_entry(tag, id);
-                                    if !self.quiet {
-                                        println!("\t{} {}", "+".bold().green(), fmt_tag(tag));
-                                    }
-                                }
-                            }
-                        },
+                Ok(tags) => self.cp_tags(re, &tags),
+                Err(e) => {
+                    diagnostics::warn();
+                    wutag_error!(
+                        "failed to get source tags from `{}` - {}",
+                        path.display(),
+                        e
                     );
-                    log::debug!("Saving registry...");
-                    self.save_registry();
                 },
-                Err(e) => wutag_error!(
-                    "failed to get source tags from `{}` - {}",
-                    path.display(),
-                    e
-                ),
             }
         }
 
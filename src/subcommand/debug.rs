@@ -0,0 +1,248 @@
+//! Commands that help with developing `wutag`, which are hidden from the help.
+//! `debug generate-fixture` creates a tree of tagged files and adds them to the
+//! registry. The same seed always creates the same files, tags, and values, so
+//! tests, benchmarks, and bug reports can recreate a fixture instead of
+//! shipping it
+
+use super::{
+    uses::{
+        fs, wutag_error, wutag_fatal, Args, Colorize, EntryData, PathBuf, Subcommand, Tag,
+        ValueHint, DEFAULT_COLORS,
+    },
+    App,
+};
+
+use std::path::Path;
+
+/// Words that the tags of a fixture are named after
+const TAG_WORDS: &[&str] = &[
+    "work", "music", "photo", "todo", "draft", "src", "docs", "old", "shared", "backup",
+];
+
+/// Extensions of the files of a fixture
+const EXTENSIONS: &[&str] = &["rs", "txt", "md", "png", "mp3", "toml", "sh", "c"];
+
+/// Maximum number of tags on a file of a fixture
+const MAX_FILE_TAGS: usize = 3;
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub(crate) enum DebugObject {
+    /// Create a tree of tagged files and add them to the registry
+    GenerateFixture {
+        /// Number of files to create
+        #[clap(long, value_name = "N", default_value = "100")]
+        files: usize,
+        /// Number of distinct tags to give the files
+        #[clap(long, value_name = "M", default_value = "10")]
+        tags:  usize,
+        /// Seed that the files, tags, and values are chosen with
+        #[clap(long, value_name = "S", default_value = "0")]
+        seed:  u64,
+        /// Directory to create the files in, which must be empty
+        #[clap(value_name = "dir", value_hint = ValueHint::DirPath)]
+        dir:   PathBuf,
+    },
+}
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct DebugOpts {
+    /// The action to take. Valid values are: 'generate-fixture'
+    #[clap(subcommand)]
+    pub(crate) object: DebugObject,
+}
+
+/// A generator of pseudo-random numbers (SplitMix64). It is used instead of
+/// `rand` so that a seed creates the same fixture with every version
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number below `n`, which cannot be 0
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// A file of a fixture
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FixtureFile {
+    /// Path of the file, relative to the directory of the fixture
+    pub(crate) path:    PathBuf,
+    pub(crate) content: String,
+    /// The tags of the file, as indexes into the tags of the fixture, with
+    /// their values
+    pub(crate) tags:    Vec<(usize, Option<String>)>,
+}
+
+/// The files and tags that `debug generate-fixture` creates
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Fixture {
+    pub(crate) tags:  Vec<Tag>,
+    pub(crate) files: Vec<FixtureFile>,
+}
+
+impl Fixture {
+    /// Choose the `files` and `tags` of the fixture for the `seed`. Files are
+    /// up to three directories deep and each has at least one tag, unless
+    /// there are none
+    pub(crate) fn plan(files: usize, tags: usize, seed: u64) -> Self {
+        let mut rng = SplitMix64(seed);
+
+        let tags = (0..tags)
+            .map(|i| {
+                let word = TAG_WORDS[i % TAG_WORDS.len()];
+                let name = match i / TAG_WORDS.len() {
+                    0 => word.to_owned(),
+                    n => format!("{}{}", word, n),
+                };
+                Tag::new(name, DEFAULT_COLORS[rng.below(DEFAULT_COLORS.len())])
+            })
+            .collect::<Vec<_>>();
+
+        let files = (0..files)
+            .map(|i| {
+                let mut path = (0..=rng.below(3))
+                    .map(|_| format!("d{}", rng.below(4)))
+                    .collect::<PathBuf>();
+                path.push(format!(
+                    "file{:04}.{}",
+                    i,
+                    EXTENSIONS[rng.below(EXTENSIONS.len())]
+                ));
+
+                let content = format!("wutag fixture {} {}\n", seed, i).repeat(1 + rng.below(8));
+
+                let mut file_tags: Vec<(usize, Option<String>)> = vec![];
+                if !tags.is_empty() {
+                    for _ in 0..=rng.below(MAX_FILE_TAGS.min(tags.len())) {
+                        let tag = rng.below(tags.len());
+                        let value = (rng.below(4) == 0).then(|| format!("v{}", rng.below(10)));
+                        if file_tags.iter().all(|(t, _)| *t != tag) {
+                            file_tags.push((tag, value));
+                        }
+                    }
+                }
+
+                FixtureFile {
+                    path,
+                    content,
+                    tags: file_tags,
+                }
+            })
+            .collect();
+
+        Self { tags, files }
+    }
+}
+
+impl App {
+    /// Create the files of the `fixture` in `dir`, tag them, and add them to
+    /// the registry
+    fn generate_fixture(&mut self, dir: &Path, fixture: &Fixture) {
+        if fs::read_dir(dir).map_or(false, |mut d| d.next().is_some()) {
+            wutag_fatal!(
+                "the directory of a fixture must be empty: {}",
+                dir.display()
+            );
+        }
+        let dir = fs::create_dir_all(dir)
+            .and_then(|_| fs::canonicalize(dir))
+            .unwrap_or_else(|e| wutag_fatal!("unable to create {}: {}", dir.display(), e));
+
+        for file in &fixture.files {
+            let path = dir.join(&file.path);
+            if let Err(e) = path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(&path, &file.content))
+            {
+                wutag_fatal!("unable to create {}: {}", path.display(), e);
+            }
+            if file.tags.is_empty() {
+                continue;
+            }
+
+            let entry = match EntryData::new(&path) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    wutag_error!("{} {}", e, path.display());
+                    continue;
+                },
+            };
+            let id = self.registry.add_or_update_entry(entry);
+
+            for (tag, value) in &file.tags {
+                let tag = &fixture.tags[*tag];
+                if let Err(e) = tag.save_to(&path) {
+                    wutag_error!("{} {}", e, path.display());
+                }
+                self.registry.tag_entry(tag, id);
+                if let Some(value) = value {
+                    self.registry.set_value(tag, id, value.clone());
+                }
+            }
+        }
+
+        self.save_registry();
+
+        if !self.quiet {
+            println!(
+                "{} {}: {} files with {} tags in {}",
+                "\u{2714}".green().bold(),
+                "FIXTURE CREATED".red().bold(),
+                fixture.files.len(),
+                fixture.tags.len(),
+                dir.display().to_string().green().bold()
+            );
+        }
+    }
+
+    pub(crate) fn debug(&mut self, opts: &DebugOpts) {
+        log::debug!("DebugOpts: {:#?}", opts);
+        log::debug!("Using registry: {}", self.registry.path.display());
+
+        match &opts.object {
+            DebugObject::GenerateFixture {
+                files,
+                tags,
+                seed,
+                dir,
+            } => self.generate_fixture(dir, &Fixture::plan(*files, *tags, *seed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plans_the_same_fixture_for_a_seed() {
+        let fixture = Fixture::plan(50, 12, 7);
+        assert_eq!(fixture, Fixture::plan(50, 12, 7));
+        assert_ne!(fixture, Fixture::plan(50, 12, 8));
+
+        assert_eq!(fixture.files.len(), 50);
+        assert_eq!(fixture.tags.len(), 12);
+        assert_eq!(fixture.tags[10].name(), "work1");
+        for file in &fixture.files {
+            assert!((1..=MAX_FILE_TAGS).contains(&file.tags.len()));
+            assert!(file.path.is_relative());
+            assert!((2..=4).contains(&file.path.components().count()));
+        }
+    }
+
+    #[test]
+    fn plans_files_without_tags() {
+        let fixture = Fixture::plan(5, 0, 0);
+        assert!(fixture.tags.is_empty());
+        assert!(fixture.files.iter().all(|f| f.tags.is_empty()));
+    }
+}
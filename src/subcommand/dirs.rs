@@ -0,0 +1,113 @@
+//! The directories that files were tagged in, one per line without any color.
+//! With `--frecent` they are ordered by how often and how recently files in
+//! them were tagged, so that the directories that are being curated can be
+//! jumped back to, e.g., with the widget printed by `--init` or by passing them
+//! to `zoxide add`
+
+use super::{
+    uses::{contained_path, Args, PathBuf},
+    App,
+};
+
+use std::collections::BTreeSet;
+
+/// Widget for zsh that picks a frecent directory with `fzf` on Alt-d
+const ZSH_WIDGET: &str = r#"wutag-cd-widget() {
+    local dir
+    dir="$(wutag -g --color=never dirs --frecent | fzf --height 40% --reverse --no-sort)"
+    if [[ -n "$dir" ]]; then
+        cd -- "$dir"
+    fi
+    zle reset-prompt
+}
+zle -N wutag-cd-widget
+bindkey '\ed' wutag-cd-widget"#;
+
+/// Widget for bash that picks a frecent directory with `fzf` on Alt-d
+const BASH_WIDGET: &str = r#"__wutag_cd() {
+    local dir
+    dir="$(wutag -g --color=never dirs --frecent | fzf --height 40% --reverse --no-sort)"
+    [[ -n "$dir" ]] && cd -- "$dir"
+}
+bind -x '"\ed": __wutag_cd'"#;
+
+/// Widget for fish that picks a frecent directory with `fzf` on Alt-d
+const FISH_WIDGET: &str = r#"function __wutag_cd
+    set -l dir (wutag -g --color=never dirs --frecent | fzf --height 40% --reverse --no-sort)
+    and cd -- $dir
+    commandline -f repaint
+end
+bind \ed __wutag_cd"#;
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct DirsOpts {
+    /// Order the directories by how often and how recently files were tagged
+    #[clap(
+        long,
+        short,
+        long_about = "Order the directories by how often and how recently files in them were \
+                      tagged, so that the directories where files are tagged daily are first. \
+                      A use counts for half as much after 'mru_half_life' days (default: 7). \
+                      Directories that no longer exist are skipped"
+    )]
+    pub(crate) frecent: bool,
+    /// Display the score of each directory before it
+    #[clap(long, short, requires = "frecent")]
+    pub(crate) score:   bool,
+    /// Print a widget for the shell that changes to a frecent directory
+    #[clap(
+        long,
+        value_name = "shell",
+        possible_values = &["bash", "zsh", "fish"],
+        conflicts_with_all = &["frecent", "score"],
+        long_about = "Print a widget for the shell that picks one of the frecent directories \
+                      with 'fzf' and changes to it, which is bound to Alt-d. Add 'eval \
+                      \"$(wutag dirs --init zsh)\"' to the configuration of the shell"
+    )]
+    pub(crate) init:    Option<String>,
+}
+
+impl App {
+    pub(crate) fn dirs(&self, opts: &DirsOpts) {
+        log::debug!("DirsOpts: {:#?}", opts);
+        log::debug!("Using registry: {}", self.registry.path.display());
+
+        if let Some(shell) = &opts.init {
+            println!("{}", match shell.as_str() {
+                "bash" => BASH_WIDGET,
+                "fish" => FISH_WIDGET,
+                _ => ZSH_WIDGET,
+            });
+            return;
+        }
+
+        let keep = |dir: &PathBuf| self.global || contained_path(dir, &self.base_dir);
+
+        if opts.frecent {
+            for (dir, score) in self.registry.list_dirs_by_usage(self.mru_half_life) {
+                let dir = dir.to_path_buf();
+                if !keep(&dir) || !dir.is_dir() {
+                    continue;
+                }
+                if opts.score {
+                    println!("{:.2}\t{}", score, dir.display());
+                } else {
+                    println!("{}", dir.display());
+                }
+            }
+            return;
+        }
+
+        let dirs = self
+            .registry
+            .entries
+            .values()
+            .filter_map(|entry| entry.path().parent().map(PathBuf::from))
+            .filter(keep)
+            .collect::<BTreeSet<_>>();
+
+        for dir in dirs {
+            println!("{}", dir.display());
+        }
+    }
+}
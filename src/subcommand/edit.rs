@@ -1,5 +1,8 @@
 use super::{
-    uses::{fmt_tag, parse_color, print_stdout, Args, Border, Cell, Justify, Separator, Table},
+    uses::{
+        fmt_tag, fmt_tag_icon, parse_color, print_stdout, Args, Border, Cell, Justify, Separator,
+        Table,
+    },
     App,
 };
 
@@ -19,11 +22,23 @@ pub(crate) struct EditOpts {
     )]
     pub(crate) color: Option<String>,
 
+    /// Set the icon displayed before the tag (e.g., a nerd-font glyph)
+    #[clap(
+        name = "icon",
+        long,
+        short,
+        long_about = "Set the icon that is displayed before the tag in the output of 'list', \
+                      'search', and the TUI. This is usually a glyph from a nerd-font. An empty \
+                      string removes the icon. Icons can also be set with the 'icons' map in the \
+                      configuration file, though an icon set here takes precedence"
+    )]
+    pub(crate) icon: Option<String>,
+
     #[clap(
         name = "rename",
         long,
         short,
-        required_unless_present = "color",
+        required_unless_present_any = &["color", "icon"],
         long_about = "Rename a tag. If both color and rename are present, the rename is carried \
                       out first"
     )]
@@ -86,12 +101,33 @@ impl App {
             update_color!(&opts.tag, *col);
         }
 
+        if let Some(icon) = &opts.icon {
+            let name = opts.rename.as_ref().unwrap_or(&opts.tag);
+            if let Some(tag) = self.registry.get_tag(name).cloned() {
+                let old_tag = fmt_tag_icon(&tag, self.tag_icon(&tag));
+                if icon.is_empty() {
+                    self.registry.remove_tag_icon(name);
+                } else {
+                    self.registry.set_tag_icon(name, icon);
+                }
+
+                table.push(vec![
+                    old_tag.cell().justify(Justify::Right),
+                    "==>".cell().justify(Justify::Center),
+                    fmt_tag_icon(&tag, self.tag_icon(&tag))
+                        .cell()
+                        .justify(Justify::Left),
+                ]);
+            }
+        }
+
         if !self.quiet {
             print_stdout(
                 table
                     .table()
                     .border(Border::builder().build())
-                    .separator(Separator::builder().build()),
+                    .separator(Separator::builder().build())
+                    .color_choice(self.color_choice()),
             )
             .expect("unable to print table");
         }
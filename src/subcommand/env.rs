@@ -0,0 +1,282 @@
+//! Display the environment `wutag` runs in: the shell it was started from, the
+//! variables that change where its files are, and whether the completions of
+//! the shell are installed. `env --install` writes the completions to the
+//! directory the shell loads them from, instead of printing them to stdout
+//! like `print-completions`
+
+use super::{
+    print_completions::completion_script,
+    uses::{env, fs, wutag_fatal, wutag_info, Args, Colorize, Config, PathBuf},
+    App,
+};
+
+use clap_generate::Shell;
+use std::path::Path;
+
+/// Environment variables that change where `wutag` keeps its files, which
+/// registry it uses and how, or how its output is colored
+const ENV_VARS: &[&str] = &[
+    "SHELL",
+    "WUTAG_CONFIG_DIR",
+    "WUTAG_DATA_DIR",
+    "WUTAG_CACHE_DIR",
+    "WUTAG_REGISTRY",
+    "WUTAG_IN_MEMORY",
+    "WUTAG_TOKEN",
+    "WUTAG_PASSPHRASE_FD",
+    "XDG_CONFIG_HOME",
+    "XDG_DATA_HOME",
+    "NO_COLOR",
+    "CLICOLOR_FORCE",
+];
+
+/// Environment variables whose values are secrets, which are only displayed as
+/// being set
+const SECRET_VARS: &[&str] = &["WUTAG_TOKEN"];
+
+/// Shells that completions are generated for
+const SHELLS: &[Shell] = &[
+    Shell::Bash,
+    Shell::Elvish,
+    Shell::Fish,
+    Shell::PowerShell,
+    Shell::Zsh,
+];
+
+/// Command that completions run to complete the names of the tags in the
+/// registry
+const DYNAMIC_COMPLETION: &str = "wutag complete";
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct EnvOpts {
+    /// Shell to display or install the completions of, instead of `$SHELL`
+    #[clap(
+        long,
+        short,
+        possible_values = &["bash", "zsh", "powershell", "elvish", "fish"]
+    )]
+    pub(crate) shell:   Option<Shell>,
+    /// Install the completions of the shell
+    #[clap(
+        long,
+        short,
+        long_about = "Write the completions of the shell to the directory it loads them from, \
+                      replacing the ones that are there. Bash and fish load them without any \
+                      configuration, zsh needs the directory in '$fpath', and elvish needs 'use \
+                      wutag' in 'rc.elv'. PowerShell has no such directory"
+    )]
+    pub(crate) install: bool,
+}
+
+/// The shell of the executable at `path`, e.g., `/usr/bin/zsh`
+fn shell_of(path: &Path) -> Option<Shell> {
+    match path.file_name()?.to_str()? {
+        "bash" => Some(Shell::Bash),
+        "elvish" => Some(Shell::Elvish),
+        "fish" => Some(Shell::Fish),
+        "pwsh" | "powershell" => Some(Shell::PowerShell),
+        "zsh" => Some(Shell::Zsh),
+        _ => None,
+    }
+}
+
+/// The shell named by `$SHELL`
+fn detect_shell() -> Option<Shell> {
+    env::var_os("SHELL").and_then(|shell| shell_of(Path::new(&shell)))
+}
+
+/// A base directory of the XDG specification from the variable `var`, or
+/// `join` in the home directory when it is not set
+fn xdg_dir(var: &str, join: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| dirs::home_dir().map(|home| home.join(join)))
+}
+
+/// Path that the completions of the `shell` are installed to, or `None` when
+/// the shell does not load completions from a directory
+fn completion_path(shell: Shell) -> Option<PathBuf> {
+    let (base, path) = match shell {
+        Shell::Bash => (
+            xdg_dir("XDG_DATA_HOME", ".local/share"),
+            "bash-completion/completions/wutag",
+        ),
+        Shell::Elvish => (
+            xdg_dir("XDG_CONFIG_HOME", ".config"),
+            "elvish/lib/wutag.elv",
+        ),
+        Shell::Fish => (
+            xdg_dir("XDG_CONFIG_HOME", ".config"),
+            "fish/completions/wutag.fish",
+        ),
+        Shell::Zsh => (
+            xdg_dir("XDG_DATA_HOME", ".local/share"),
+            "zsh/site-functions/_wutag",
+        ),
+        _ => return None,
+    };
+
+    base.map(|base| base.join(path))
+}
+
+/// Describe the `installed` completions compared to the `script` of this
+/// version, and whether they complete the names of tags
+fn install_state(installed: Option<&str>, script: &str) -> (&'static str, &'static str) {
+    let state = match installed {
+        None => "no",
+        Some(installed) if installed == script => "yes",
+        Some(_) => "yes, but they differ from this version (see '--install')",
+    };
+    let dynamic = match installed {
+        _ if !script.contains(DYNAMIC_COMPLETION) => "not supported by this shell",
+        Some(installed) if installed.contains(DYNAMIC_COMPLETION) => "yes",
+        _ => "no",
+    };
+
+    (state, dynamic)
+}
+
+impl App {
+    /// Display the completions of the `shell` and whether they are installed
+    fn env_completions(shell: Shell) {
+        let script = completion_script(shell);
+
+        println!("{}:", shell.to_string().magenta());
+        match completion_path(shell) {
+            Some(path) => {
+                let installed = fs::read_to_string(&path).ok();
+                let (state, dynamic) = install_state(installed.as_deref(), &script);
+                println!(
+                    "  {}: {}",
+                    "Path".magenta(),
+                    path.display().to_string().green()
+                );
+                println!("  {}: {}", "Installed".magenta(), state.green());
+                println!("  {}: {}", "Tag completion".magenta(), dynamic.green());
+            },
+            None => println!(
+                "  {}: {}",
+                "Path".magenta(),
+                format!(
+                    "none, add 'wutag print-completions --shell {}' to $PROFILE",
+                    shell
+                )
+                .yellow()
+            ),
+        }
+    }
+
+    /// Write the completions of the `shell` to the directory it loads them
+    /// from
+    fn install_completions(&self, shell: Shell) {
+        let path = completion_path(shell).unwrap_or_else(|| {
+            wutag_fatal!(
+                "{} does not load completions from a directory, add 'wutag print-completions \
+                 --shell {}' to $PROFILE instead",
+                shell,
+                shell
+            )
+        });
+
+        if let Err(e) = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, completion_script(shell)))
+        {
+            wutag_fatal!("unable to write {}: {}", path.display(), e);
+        }
+
+        if !self.quiet {
+            println!(
+                "{} {}: {} completions to {}",
+                "\u{2714}".green().bold(),
+                "INSTALLED".red().bold(),
+                shell,
+                path.display().to_string().green().bold()
+            );
+            match shell {
+                Shell::Zsh => wutag_info!(
+                    "{} must be in $fpath before 'compinit' is called",
+                    path.parent().unwrap_or(&path).display()
+                ),
+                Shell::Elvish => wutag_info!("add 'use wutag' to rc.elv to load them"),
+                _ => {},
+            }
+        }
+    }
+
+    pub(crate) fn env(&self, opts: &EnvOpts, config: &Config) {
+        log::debug!("EnvOpts: {:#?}", opts);
+
+        let detected = detect_shell();
+        let shell = opts.shell.or(detected);
+
+        if opts.install {
+            let shell = shell.unwrap_or_else(|| {
+                wutag_fatal!("unable to detect the shell from $SHELL, pass it with '--shell'")
+            });
+            self.install_completions(shell);
+            return;
+        }
+
+        println!(
+            "{}: {}",
+            "Shell".magenta(),
+            detected.map_or_else(
+                || "unknown".yellow().to_string(),
+                |shell| shell.to_string().green().to_string()
+            )
+        );
+        for var in ENV_VARS {
+            println!(
+                "{}: {}",
+                var.magenta(),
+                env::var(var).map_or_else(
+                    |_| "unset".yellow().to_string(),
+                    |value| if SECRET_VARS.contains(var) {
+                        "set".green().to_string()
+                    } else {
+                        value.green().to_string()
+                    }
+                )
+            );
+        }
+
+        self.info_paths(config);
+
+        match shell {
+            Some(shell) => Self::env_completions(shell),
+            None =>
+                for shell in SHELLS {
+                    Self::env_completions(*shell);
+                },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_shells_from_paths() {
+        assert_eq!(shell_of(Path::new("/usr/bin/zsh")), Some(Shell::Zsh));
+        assert_eq!(shell_of(Path::new("/bin/bash")), Some(Shell::Bash));
+        assert_eq!(shell_of(Path::new("pwsh")), Some(Shell::PowerShell));
+        assert_eq!(shell_of(Path::new("/bin/sh")), None);
+        assert_eq!(shell_of(Path::new("/")), None);
+    }
+
+    #[test]
+    fn compares_installed_completions() {
+        let script = "_wutag() { wutag complete tags --mru }";
+        assert_eq!(install_state(None, script), ("no", "no"));
+        assert_eq!(install_state(Some(script), script), ("yes", "yes"));
+        assert_eq!(install_state(Some("_wutag() {}"), script).1, "no");
+        assert_eq!(
+            install_state(Some("complete -c wutag"), "complete -c wutag"),
+            ("yes", "not supported by this shell")
+        );
+    }
+}
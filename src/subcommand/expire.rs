@@ -0,0 +1,118 @@
+use super::{
+    service,
+    uses::{contained_path, fmt_path, fmt_tag, wutag_error, Args, Colorize, Tag},
+    App,
+};
+
+use chrono::Local;
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct ExpireOpts {
+    /// Do not actually remove the expired tags
+    #[clap(short = 'd', long = "dry-run")]
+    pub(crate) dry_run: bool,
+    /// Tag to set in place of the expired tag (overrides the configuration)
+    #[clap(short = 'r', long = "replace", takes_value = true)]
+    pub(crate) replace: Option<String>,
+    /// Send a desktop notification of the expired tags when `notify.expired` is
+    /// set in the configuration (used by `wutag service install`)
+    #[clap(short = 'n', long = "notify")]
+    pub(crate) notify:  bool,
+}
+
+impl App {
+    /// Remove the tags that have expired from the registry in memory without
+    /// touching the files, so that they are not found before `expire` runs
+    pub(crate) fn hide_expired(&mut self) {
+        for (id, tags) in self.registry.expired_tags(Local::now().naive_local()) {
+            for tag in &tags {
+                self.registry.untag_entry(tag, id);
+            }
+        }
+    }
+
+    /// Remove all tags that have a value of `expires=<date>` where the date has
+    /// already passed
+    pub(crate) fn expire(&mut self, opts: &ExpireOpts) {
+        log::debug!("ExpireOpts: {:#?}", opts);
+        log::debug!("Using registry: {}", self.registry.path.display());
+
+        let replacement = opts
+            .replace
+            .as_ref()
+            .or_else(|| self.expire_replacement.as_ref());
+        let mut removed = 0_usize;
+        let mut files = 0_usize;
+
+        for (id, expired) in self.registry.expired_tags(Local::now().naive_local()) {
+            let entry = match self.registry.get_entry(id) {
+                Some(entry) => entry.clone(),
+                None => continue,
+            };
+
+            if !self.global && !contained_path(entry.path(), &self.base_dir) {
+                continue;
+            }
+
+            if !self.quiet {
+                println!(
+                    "{}:",
+                    fmt_path(entry.path(), self.base_color, self.ls_colors)
+                );
+            }
+
+            for tag in &expired {
+                if !opts.dry_run {
+                    if let Err(e) = tag.remove_from(entry.path()) {
+                        wutag_error!("{} {}", e, entry.path().display());
+                        continue;
+                    }
+                    self.registry.untag_entry(tag, id);
+                    removed += 1;
+                }
+                if !self.quiet {
+                    print!("\t{} {}", "X".bold().red(), fmt_tag(tag));
+                }
+            }
+
+            if let Some(name) = replacement {
+                let tag = self
+                    .registry
+                    .get_tag(name)
+                    .cloned()
+                    .unwrap_or_else(|| Tag::random(name, &self.colors));
+
+                if !opts.dry_run {
+                    if let Err(e) = tag.save_to(entry.path()) {
+                        wutag_error!("{} {}", e, entry.path().display());
+                    } else {
+                        // Removing the last expired tag removes the entry as an
+                        // orphan, so it is added back before it is tagged
+                        let id = self.registry.add_or_update_entry(entry.clone());
+                        self.registry.tag_entry(&tag, id);
+                    }
+                }
+                if !self.quiet {
+                    print!("\t{} {}", "+".bold().green(), fmt_tag(&tag));
+                }
+            }
+
+            if !self.quiet {
+                println!();
+            }
+            files += 1;
+        }
+
+        if !opts.dry_run {
+            log::debug!("Saving registry...");
+            self.save_registry();
+
+            if opts.notify && self.notify.expired && removed > 0 {
+                service::notify(
+                    "Expired tags",
+                    &format!("removed {} expired tags from {} files", removed, files),
+                );
+            }
+        }
+    }
+}
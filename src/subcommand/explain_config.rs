@@ -0,0 +1,607 @@
+//! Display every setting that is in effect and where it comes from: the
+//! built-in default, a line of the configuration file, an environment variable,
+//! or a flag given on the command-line. The configuration is explained before
+//! the registry is loaded, so that it can be checked when the registry it
+//! points to cannot be opened
+
+use super::uses::{
+    env, fs, get_cache_path, get_config_path, get_data_path, resolve_color_when, wutag_fatal,
+    wutag_warning, Args, Colorize, Config, HashMap, Opts, PathBuf, CONFIG_FILE,
+};
+
+use crate::{mounts, registry::REGISTRY_FILE};
+use serde_yaml::Value;
+use std::{collections::HashSet, fmt};
+
+/// Aliases of the keys of the configuration, by the section they are in. They
+/// are the `alias`es of the `#[serde]` attributes of the fields in `config.rs`,
+/// which `lists_the_aliases_of_the_config_types` checks
+const ALIASES: &[(&str, &str, &str)] = &[
+    ("", "max-depth", "max_depth"),
+    ("", "base-color", "base_color"),
+    ("", "border-color", "border_color"),
+    ("", "ignore", "ignores"),
+    ("", "default-tags", "default_tags"),
+    ("", "default-values", "default_values"),
+    ("", "expire-replacement", "expire_replacement"),
+    ("", "exclusive-groups", "exclusive_groups"),
+    ("", "filesystem-registries", "filesystem_registries"),
+    ("", "strip-prefix", "strip_prefix"),
+    ("", "max-snapshots", "max_snapshots"),
+    ("", "max-tags-per-file", "max_tags_per_file"),
+    ("", "max-files-per-tag", "max_files_per_tag"),
+    ("", "mru-half-life", "mru_half_life"),
+    ("", "hash-algorithm", "hash_algorithm"),
+    ("", "quick-hash", "quick_hash"),
+    ("", "quick-hash-size", "quick_hash_size"),
+    ("", "no-icons", "no_icons"),
+    ("", "per-user", "per_user"),
+    ("", "show-duplicates", "show_duplicates"),
+    ("", "follow-symlinks", "follow_symlinks"),
+    ("", "glob-engine", "glob_engine"),
+    ("", "verify-writes", "verify_writes"),
+    ("", "xattr-layout", "xattr_layout"),
+    ("", "check-plurals", "check_plurals"),
+    ("", "default-sort", "default_sort"),
+    ("", "Keys", "keys"),
+    ("", "ui", "tui"),
+    ("", "UI", "tui"),
+    ("", "TUI", "tui"),
+    ("", "Encryption", "encryption"),
+    ("serve", "read-only-tags", "read_only_tags"),
+    ("serve", "tag-writers", "tag_writers"),
+    ("encryption", "public-key", "public_key"),
+    ("encryption", "to-encrypt", "to_encrypt"),
+    ("encryption", "TTY", "tty"),
+    ("encryption", "gpg_tty", "tty"),
+    ("encryption", "gpg-tty", "tty"),
+    ("encryption", "pinentry-mode", "pinentry_mode"),
+    ("tui", "colored-ui", "colored_ui"),
+    ("tui", "high-contrast", "high_contrast"),
+    ("tui", "tick-rate", "tick_rate"),
+    ("tui", "startup-cmd", "startup_cmd"),
+    ("tui", "startup-command", "startup_cmd"),
+    ("tui", "preview-scroll-lines", "preview_scroll_lines"),
+    ("tui", "preview-height", "preview_height"),
+    ("tui", "image-protocol", "image_protocol"),
+    ("tui", "sort-by", "sort_by"),
+    ("tui", "tags-width", "tags_width"),
+    ("tui", "history-filepath", "history_filepath"),
+    ("tui", "flash", "flashy"),
+    ("tui", "default-shorten", "default_alias"),
+    ("tui", "shorten-hash", "alias_hash"),
+    ("tui", "tags-bold", "tags_bold"),
+    ("tui", "bold-tags", "tags_bold"),
+    ("tui", "paths-bold", "paths_bold"),
+    ("tui", "bold-paths", "paths_bold"),
+    ("tui", "paths-color", "paths_color"),
+    ("tui", "color-paths", "paths_color"),
+    ("tui", "completion-color", "completion_color"),
+    ("tui", "unmark-indicator", "unmark_indicator"),
+    ("tui", "selection-indicator", "selection_indicator"),
+    ("tui", "mark-indicator", "mark_indicator"),
+    ("tui", "selection-tags", "selection_tags"),
+    ("tui", "tag-selections", "selection_tags"),
+    ("tui", "selection-blink", "selection_blink"),
+    ("tui", "selection-bold", "selection_bold"),
+    ("tui", "selection-dim", "selection_dim"),
+    ("tui", "selection-italic", "selection_italic"),
+    ("tui", "header-alignment", "header_alignment"),
+    ("tui", "header-underline", "header_underline"),
+    ("keys", "go-to-top", "go_to_top"),
+    ("keys", "goto-top", "go_to_top"),
+    ("keys", "go-to-bottom", "go_to_bottom"),
+    ("keys", "goto-bottom", "go_to_bottom"),
+    ("keys", "page-up", "page_up"),
+    ("keys", "page-down", "page_down"),
+    ("keys", "select-all", "select_all"),
+    ("keys", "preview-down", "preview_down"),
+    ("keys", "preview-up", "preview_up"),
+    ("keys", "grow-tags", "grow_tags"),
+    ("keys", "shrink-tags", "shrink_tags"),
+    ("keys", "grow-preview", "grow_preview"),
+    ("keys", "shrink-preview", "shrink_preview"),
+    ("tui.theme", "table-header", "table_header"),
+    ("tui.theme", "tag-fallback", "tag_fallback"),
+];
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct ExplainConfigOpts {
+    /// Only explain this setting or section (e.g., `tui` or `tui.tick_rate`)
+    #[clap(value_name = "key")]
+    pub(crate) key: Option<String>,
+}
+
+/// Where the value of a setting comes from
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    /// The value `wutag` uses when nothing else sets it
+    Default,
+    /// The configuration file, with the line of the setting when it is found
+    File(Option<usize>),
+    /// An environment variable
+    Env(&'static str),
+    /// A flag given on the command-line
+    Flag(&'static str),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => f.write_str("default"),
+            Self::File(Some(line)) => write!(f, "{}:{}", CONFIG_FILE, line),
+            Self::File(None) => f.write_str(CONFIG_FILE),
+            Self::Env(var) => write!(f, "env ${}", var),
+            Self::Flag(flag) => write!(f, "flag {}", flag),
+        }
+    }
+}
+
+/// A setting that is in effect, keyed by its dotted path (e.g.,
+/// `tui.tick_rate`)
+#[derive(Debug, Clone, PartialEq)]
+struct Setting {
+    key:    String,
+    value:  String,
+    source: Source,
+}
+
+/// Join the `key` to the dotted path of its `parent`
+fn join(parent: &str, key: &str) -> String {
+    if parent.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", parent, key)
+    }
+}
+
+/// The name of the setting that `key` of the section `parent` is written for,
+/// which differs when it is one of the [`ALIASES`]. Keys that are chosen by the
+/// user, such as the names of tags, are left as they are
+fn normalize_key(parent: &str, key: &str) -> String {
+    ALIASES
+        .iter()
+        .find(|(section, alias, _)| *section == parent && *alias == key)
+        .map_or(key, |(_, _, name)| *name)
+        .to_owned()
+}
+
+/// Display a value of the configuration on a single line
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::Null => String::from("unset"),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        Value::Sequence(seq) => format!(
+            "[{}]",
+            seq.iter().map(scalar).collect::<Vec<_>>().join(", ")
+        ),
+        Value::Mapping(map) => format!(
+            "{{{}}}",
+            map.iter()
+                .map(|(k, v)| format!("{}: {}", scalar(k), scalar(v)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Flatten the `value` of the setting `key` into the settings it is made of
+fn flatten(key: &str, value: &Value, out: &mut Vec<(String, String)>) {
+    match value {
+        Value::Mapping(map) if !map.is_empty() =>
+            for (k, v) in map {
+                flatten(&join(key, &scalar(k)), v, out);
+            },
+        value => out.push((key.to_owned(), scalar(value))),
+    }
+}
+
+/// Collect the dotted path of every key that the configuration file sets
+fn file_keys(parent: &str, value: &Value, keys: &mut HashSet<String>) {
+    if let Value::Mapping(map) = value {
+        for (k, v) in map {
+            let path = join(parent, &normalize_key(parent, &scalar(k)));
+            file_keys(&path, v, keys);
+            keys.insert(path);
+        }
+    }
+}
+
+/// The line of each key of the configuration file `text`, keyed like
+/// [`file_keys`]. Only keys written in block style have a line of their own
+fn key_lines(text: &str) -> HashMap<String, usize> {
+    let mut lines = HashMap::new();
+    // Indentation and path of the keys that contain the current line
+    let mut parents: Vec<(usize, String)> = vec![];
+    for (idx, line) in text.lines().enumerate() {
+        let content = line.trim_start();
+        if content.is_empty() || content.starts_with('#') || content.starts_with('-') {
+            continue;
+        }
+        let key = match content.split_once(':') {
+            Some((key, _)) => key.trim().trim_matches(|c| c == '"' || c == '\''),
+            None => continue,
+        };
+
+        let indent = line.len() - content.len();
+        while parents.last().map_or(false, |(i, _)| *i >= indent) {
+            parents.pop();
+        }
+        let parent = parents.last().map_or("", |(_, path)| path.as_str());
+        let path = join(parent, &normalize_key(parent, key));
+
+        lines.entry(path.clone()).or_insert(idx + 1);
+        parents.push((indent, path));
+    }
+
+    lines
+}
+
+/// The line of the setting `key`, or of the closest section that contains it
+/// when it is written in flow style (e.g., `tui: {tick_rate: 100}`)
+fn line_of(lines: &HashMap<String, usize>, key: &str) -> Option<usize> {
+    let mut key = key;
+    loop {
+        if let Some(line) = lines.get(key) {
+            return Some(*line);
+        }
+        key = &key[..key.rfind('.')?];
+    }
+}
+
+/// The settings of the `config` that was loaded from the file `text`, which
+/// come from either the file or the defaults
+fn file_settings(config: &Config, text: &str) -> Vec<Setting> {
+    let mut keys = HashSet::new();
+    if let Ok(raw) = serde_yaml::from_str::<Value>(text) {
+        file_keys("", &raw, &mut keys);
+    }
+    let lines = key_lines(text);
+
+    let mut flat = vec![];
+    match serde_yaml::to_value(config) {
+        Ok(value) => flatten("", &value, &mut flat),
+        Err(e) => wutag_fatal!("unable to serialize the configuration: {}", e),
+    }
+
+    flat.into_iter()
+        .map(|(key, value)| {
+            let source = if keys.contains(&key) {
+                Source::File(line_of(&lines, &key))
+            } else {
+                Source::Default
+            };
+            Setting { key, value, source }
+        })
+        .collect()
+}
+
+/// Replace the value of the setting `key` with the one that `source` gives it
+fn override_setting(settings: &mut Vec<Setting>, key: &str, value: String, source: Source) {
+    match settings.iter_mut().find(|s| s.key == key) {
+        Some(setting) => {
+            setting.value = value;
+            setting.source = source;
+        },
+        None => settings.push(Setting {
+            key: key.to_owned(),
+            value,
+            source,
+        }),
+    }
+}
+
+/// Apply the flags of the command-line that take precedence over the
+/// configuration
+fn apply_flags(settings: &mut Vec<Setting>, opts: &Opts) {
+    if let Some(depth) = opts.max_depth {
+        override_setting(
+            settings,
+            "max_depth",
+            depth.to_string(),
+            Source::Flag("--max-depth"),
+        );
+    }
+
+    for &(given, key, flag) in &[
+        (opts.no_icons, "no_icons", "--no-icons"),
+        (opts.plain, "no_icons", "--plain"),
+        (opts.per_user, "per_user", "--per-user"),
+        (opts.show_duplicates, "show_duplicates", "--show-duplicates"),
+        (opts.quick_hash, "quick_hash", "--quick-hash"),
+    ] {
+        if given {
+            override_setting(settings, key, String::from("true"), Source::Flag(flag));
+        }
+    }
+
+    #[cfg(feature = "encrypt-gpgme")]
+    {
+        if opts.gpg_tty {
+            override_setting(
+                settings,
+                "encryption.tty",
+                String::from("true"),
+                Source::Flag("--gpg-tty"),
+            );
+        }
+        if let Some(mode) = opts.pinentry_mode {
+            override_setting(
+                settings,
+                "encryption.pinentry_mode",
+                serde_yaml::to_value(mode).map_or_else(|_| format!("{:?}", mode), |v| scalar(&v)),
+                Source::Flag("--pinentry-mode"),
+            );
+        }
+    }
+}
+
+/// The variable `var` when it is set, or else the default
+fn env_source(var: &'static str) -> Source {
+    if env::var_os(var).map_or(false, |v| !v.is_empty()) {
+        Source::Env(var)
+    } else {
+        Source::Default
+    }
+}
+
+/// A directory of `wutag`, which can be moved with the variable `var`
+fn dir_setting(key: &str, var: &'static str, dir: Option<PathBuf>) -> Setting {
+    Setting {
+        key:    key.to_owned(),
+        value:  dir.map_or_else(|| String::from("unset"), |d| d.display().to_string()),
+        source: env_source(var),
+    }
+}
+
+/// The registry that is used, which is chosen like [`App::run`] does
+///
+/// [`App::run`]: super::App::run
+fn registry_setting(settings: &[Setting], opts: &Opts, config: &Config) -> (String, Source) {
+    let file_source = |key: &str| {
+        settings
+            .iter()
+            .find(|s| s.key == key)
+            .map_or(Source::File(None), |s| s.source.clone())
+    };
+
+    if let Some(reg) = &opts.reg {
+        let from_env = env::var_os("WUTAG_REGISTRY").map_or(false, |v| v == reg.as_os_str());
+        let source = if from_env {
+            Source::Env("WUTAG_REGISTRY")
+        } else {
+            Source::Flag("--registry")
+        };
+        return (reg.display().to_string(), source);
+    }
+
+    if let Some(reg) = config
+        .filesystem_registries
+        .as_ref()
+        .and_then(|registries| {
+            let dir = opts.dir.clone().or_else(|| env::current_dir().ok())?;
+            mounts::filesystem_registry(&dir, registries)
+        })
+    {
+        return (
+            reg.display().to_string(),
+            file_source("filesystem_registries"),
+        );
+    }
+
+    if let Some(reg) = &config.registry {
+        return (reg.clone(), file_source("registry"));
+    }
+
+    let value = get_data_path().map_or_else(
+        |_| String::from("unset"),
+        |dir| dir.join(REGISTRY_FILE).display().to_string(),
+    );
+    (value, env_source("WUTAG_DATA_DIR"))
+}
+
+/// Whether output is colored, which is chosen like [`resolve_color_when`]
+fn color_setting(opts: &Opts) -> Setting {
+    let env_set = |var: &str| env::var_os(var).map_or(false, |v| !v.is_empty() && v != "0");
+    let machine = opts.porcelain.is_some() || opts.output.is_some();
+    let source = match opts.color_when.as_deref() {
+        _ if opts.porcelain.is_some() => Source::Flag("--porcelain"),
+        _ if opts.output.is_some() => Source::Flag("--output"),
+        Some("always") | Some("never") => Source::Flag("--color"),
+        _ if env::var_os("NO_COLOR").map_or(false, |v| !v.is_empty()) => Source::Env("NO_COLOR"),
+        _ if env_set("CLICOLOR_FORCE") => Source::Env("CLICOLOR_FORCE"),
+        _ => Source::Default,
+    };
+
+    Setting {
+        key: String::from("color"),
+        value: resolve_color_when(opts.color_when.as_deref(), machine).to_owned(),
+        source,
+    }
+}
+
+/// Display the settings that are in effect and where they come from, optionally
+/// only those under the setting `key`
+pub(crate) fn explain_config(explain: &ExplainConfigOpts, opts: &Opts, config: &Config) {
+    log::debug!("ExplainConfigOpts: {:#?}", explain);
+
+    let path = get_config_path().ok().map(|dir| dir.join(CONFIG_FILE));
+    let text = path
+        .as_ref()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .unwrap_or_default();
+    if let Err(e) = serde_yaml::from_str::<Config>(&text) {
+        wutag_warning!(
+            "{} is invalid, the defaults are used instead: {}",
+            CONFIG_FILE,
+            e
+        );
+    }
+
+    let mut settings = file_settings(config, &text);
+    apply_flags(&mut settings, opts);
+    let (registry, source) = registry_setting(&settings, opts, config);
+    override_setting(&mut settings, "registry", registry, source);
+
+    let mut all = vec![
+        dir_setting("config_file", "WUTAG_CONFIG_DIR", path),
+        dir_setting("data_dir", "WUTAG_DATA_DIR", get_data_path().ok()),
+        dir_setting("cache_dir", "WUTAG_CACHE_DIR", Some(get_cache_path())),
+        color_setting(opts),
+    ];
+    all.append(&mut settings);
+
+    let all = match &explain.key {
+        Some(key) => {
+            let section = format!("{}.", key);
+            let matched = all
+                .into_iter()
+                .filter(|s| s.key == *key || s.key.starts_with(&section))
+                .collect::<Vec<_>>();
+            if matched.is_empty() {
+                wutag_fatal!("there is no setting named '{}'", key);
+            }
+            matched
+        },
+        None => all,
+    };
+
+    for setting in all {
+        let source = format!("({})", setting.source);
+        println!(
+            "{}: {} {}",
+            setting.key.magenta(),
+            setting.value.green(),
+            match setting.source {
+                Source::Default => source.dimmed(),
+                _ => source.yellow(),
+            }
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+# Comment: not a key
+max-depth: 3
+colors: ['red', 'green']
+ignore:
+  - '*.log'
+ui:
+  tick-rate: 100
+  flash: false
+exclusive_groups: {status: [todo, done]}
+defaults:
+  search:
+    only-files: true
+";
+
+    #[test]
+    fn normalizes_aliases_of_fields() {
+        assert_eq!(normalize_key("", "max-depth"), "max_depth");
+        assert_eq!(normalize_key("", "UI"), "tui");
+        assert_eq!(normalize_key("tui", "bold-tags"), "tags_bold");
+        assert_eq!(normalize_key("encryption", "gpg-tty"), "tty");
+        assert_eq!(normalize_key("keys", "goto_top"), "goto_top");
+        assert_eq!(normalize_key("icons", "my-tag"), "my-tag");
+        assert_eq!(normalize_key("defaults.search", "only-files"), "only-files");
+    }
+
+    #[test]
+    fn lists_the_aliases_of_the_config_types() {
+        let sections = [
+            ("Config", ""),
+            ("UiConfig", "tui"),
+            ("UiTheme", "tui.theme"),
+            ("KeyConfig", "keys"),
+            ("EncryptConfig", "encryption"),
+            ("ServeConfig", "serve"),
+            ("NotifyConfig", "notify"),
+        ];
+
+        // The aliases of the `#[serde]` attribute before each field of the structs
+        let mut found = HashSet::new();
+        let mut section = None;
+        let mut rename = None;
+        let mut aliases = vec![];
+        for line in include_str!("../config.rs").lines() {
+            let trimmed = line.trim();
+            if line == "}" {
+                section = None;
+                rename = None;
+                aliases.clear();
+            } else if let Some(name) = trimmed.strip_prefix("pub(crate) struct ") {
+                let name = name.trim_end_matches(" {");
+                section = sections
+                    .iter()
+                    .find(|(s, _)| *s == name)
+                    .map(|(_, sec)| *sec);
+            } else if let Some(attr) = trimmed.strip_prefix("#[serde(") {
+                for (key, value) in attr.trim_end_matches(")]").split(", ").flat_map(|a| {
+                    a.split_once(" = ")
+                        .map(|(key, value)| (key, value.trim_matches('"')))
+                }) {
+                    match key {
+                        "alias" => aliases.push(value),
+                        "rename" => rename = Some(value),
+                        _ => {},
+                    }
+                }
+            } else if let Some((field, _)) = trimmed
+                .strip_prefix("pub(crate) ")
+                .and_then(|field| field.split_once(':'))
+            {
+                let name = rename.take().unwrap_or_else(|| field.trim());
+                for alias in aliases.drain(..) {
+                    if let Some(section) = section {
+                        found.insert((section, alias, name));
+                    }
+                }
+            }
+        }
+
+        let listed = ALIASES.iter().copied().collect::<HashSet<_>>();
+        assert_eq!(listed.len(), ALIASES.len());
+        assert_eq!(listed, found);
+    }
+
+    #[test]
+    fn finds_the_lines_of_keys() {
+        let lines = key_lines(CONFIG);
+        assert_eq!(lines["max_depth"], 2);
+        assert_eq!(lines["ignores"], 4);
+        assert_eq!(lines["tui"], 6);
+        assert_eq!(lines["tui.tick_rate"], 7);
+        assert_eq!(lines["tui.flashy"], 8);
+        assert_eq!(lines["defaults.search.only-files"], 12);
+        assert!(!lines.contains_key("Comment"));
+
+        assert_eq!(line_of(&lines, "exclusive_groups.status"), Some(9));
+        assert_eq!(line_of(&lines, "registry"), None);
+    }
+
+    #[test]
+    fn explains_where_settings_come_from() {
+        let config: Config = serde_yaml::from_str(CONFIG).unwrap();
+        let settings = file_settings(&config, CONFIG);
+        let get = |key: &str| settings.iter().find(|s| s.key == key).cloned().unwrap();
+
+        assert_eq!(get("max_depth").value, "3");
+        assert_eq!(get("max_depth").source, Source::File(Some(2)));
+        assert_eq!(get("colors").value, "[red, green]");
+        assert_eq!(get("ignores").source, Source::File(Some(4)));
+        assert_eq!(get("tui.tick_rate").value, "100");
+        assert_eq!(get("tui.tick_rate").source, Source::File(Some(7)));
+        assert_eq!(get("exclusive_groups.status").value, "[todo, done]");
+        assert_eq!(get("exclusive_groups.status").source, Source::File(Some(9)));
+        assert_eq!(get("defaults.search.only-files").value, "true");
+        assert_eq!(get("tui.looping").source, Source::Default);
+        assert_eq!(get("registry").value, "unset");
+        assert_eq!(get("registry").source, Source::Default);
+    }
+}
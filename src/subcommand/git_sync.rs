@@ -0,0 +1,197 @@
+//! Tags follow the files that git renamed. The renames between the last commit
+//! that was synced and `HEAD` are found with `git diff --find-renames`, and the
+//! last synced commit is kept in the repository's git directory
+
+use super::{
+    uses::{
+        fmt_path, fs, process, wutag_fatal, wutag_info, wutag_warning, Args, Colorize, Context,
+        PathBuf, Result, Tag,
+    },
+    App,
+};
+
+use anyhow::anyhow;
+use std::path::Path;
+
+/// Name of the file in the git directory holding the last synced commit
+const GIT_SYNC_FILE: &str = "wutag-sync";
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct GitSyncOpts {
+    /// Display the renames without modifying any files or the registry
+    #[clap(long = "dry-run", short = 'd')]
+    pub(crate) dry_run: bool,
+
+    /// Find the renames since this commit instead of the last synced commit
+    #[clap(long, value_name = "commit")]
+    pub(crate) since: Option<String>,
+}
+
+/// Run git in the directory `dir`, returning what it printed
+pub(crate) fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("failed to run git")?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Parse the renamed files (old path, new path) out of the output of `git diff
+/// --name-status -z`
+fn parse_renames(output: &str) -> Vec<(String, String)> {
+    let mut fields = output.split('\0');
+    let mut renames = Vec::new();
+
+    while let Some(status) = fields.next() {
+        match status.chars().next() {
+            Some('R') =>
+                if let (Some(old), Some(new)) = (fields.next(), fields.next()) {
+                    renames.push((old.to_owned(), new.to_owned()));
+                },
+            // Copies also have two paths
+            Some('C') => {
+                fields.nth(1);
+            },
+            Some(_) => {
+                fields.next();
+            },
+            None => {},
+        }
+    }
+
+    renames
+}
+
+impl App {
+    pub(crate) fn git_sync(&mut self, opts: &GitSyncOpts) {
+        log::debug!("GitSyncOpts: {:#?}", opts);
+        log::debug!("Using registry: {}", self.registry.path.display());
+
+        let rev_parse = |dir: &Path, arg: &str| {
+            git(dir, &["rev-parse", arg])
+                .map(|out| out.trim().to_owned())
+                .unwrap_or_else(|e| wutag_fatal!("{}", e))
+        };
+
+        let top = PathBuf::from(rev_parse(&self.base_dir, "--show-toplevel"));
+        let sync_file = PathBuf::from(rev_parse(&top, "--absolute-git-dir")).join(GIT_SYNC_FILE);
+        let head = rev_parse(&top, "HEAD");
+
+        let since = match opts.since.clone().or_else(|| {
+            fs::read_to_string(&sync_file)
+                .ok()
+                .map(|s| s.trim().to_owned())
+        }) {
+            Some(since) => since,
+            None => {
+                if !opts.dry_run {
+                    fs::write(&sync_file, &head)
+                        .unwrap_or_else(|e| wutag_fatal!("{}: {}", sync_file.display(), e));
+                }
+                if !self.quiet {
+                    wutag_info!(
+                        "recorded {}, files renamed after it will be synced",
+                        head.bold()
+                    );
+                }
+                return;
+            },
+        };
+
+        let diff = git(&top, &[
+            "diff",
+            "--find-renames",
+            "--name-status",
+            "-z",
+            &since,
+            &head,
+        ])
+        .unwrap_or_else(|e| wutag_fatal!("{}", e));
+
+        let mut moved = 0_usize;
+        for (old, new) in parse_renames(&diff) {
+            let (old, new) = (top.join(old), top.join(new));
+            let id = match self.registry.find_entry(&old) {
+                Some(id) => id,
+                None => continue,
+            };
+
+            if !self.quiet {
+                println!(
+                    "{} {} {}",
+                    fmt_path(&old, self.base_color, self.ls_colors),
+                    "=>".yellow().bold(),
+                    fmt_path(&new, self.base_color, self.ls_colors)
+                );
+            }
+            moved += 1;
+
+            if opts.dry_run {
+                continue;
+            }
+
+            let tags = self
+                .registry
+                .list_entry_tags(id)
+                .unwrap_or_default()
+                .into_iter()
+                .cloned()
+                .collect::<Vec<Tag>>();
+
+            // The file git wrote has none of the old file's extended attributes
+            for tag in &tags {
+                match tag.save_to(&new) {
+                    Ok(()) | Err(wutag_core::Error::TagExists(_)) => {},
+                    Err(e) => wutag_warning!("{} {}", e, new.display()),
+                }
+            }
+
+            self.registry.update_entry_path(id, &new);
+            if let Err(e) = self.registry.repair_registry(id) {
+                wutag_warning!("{} {}", e, new.display());
+            }
+        }
+
+        if !opts.dry_run {
+            fs::write(&sync_file, &head)
+                .unwrap_or_else(|e| wutag_fatal!("{}: {}", sync_file.display(), e));
+            log::debug!("Saving registry...");
+            self.save_registry();
+        }
+
+        if !self.quiet {
+            println!(
+                "{} {} tagged file(s) renamed since {}",
+                "\u{2714}".green().bold(),
+                moved,
+                since.bold()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_renames() {
+        let output = "M\0src/main.rs\0R087\0src/old.rs\0src/new.rs\0C100\0a.rs\0b.rs\0D\0gone.rs\0";
+        assert_eq!(parse_renames(output), vec![(
+            "src/old.rs".to_owned(),
+            "src/new.rs".to_owned()
+        )]);
+        assert!(parse_renames("").is_empty());
+    }
+}
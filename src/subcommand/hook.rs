@@ -0,0 +1,130 @@
+//! Hooks that let tags enforce the policies of a repository. `hook git
+//! pre-commit` prints a hook script that calls `check-staged`, which fails when
+//! a staged file has a denied tag (e.g., `confidential`)
+
+use super::{
+    git_sync::git,
+    uses::{
+        diagnostics, fmt_path, list_tags, wutag_error, wutag_fatal, Args, Colorize, PathBuf,
+        Subcommand,
+    },
+    App,
+};
+use wutag_core::xattr::ssh;
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub(crate) enum HookObject {
+    /// Print a git hook script that calls wutag
+    Git {
+        /// The hook to print. Valid values are: 'pre-commit'
+        #[clap(possible_values = &["pre-commit"])]
+        hook:      String,
+        /// Block commits of files with this tag. Can be used multiple times
+        #[clap(
+            long = "deny-tag",
+            value_name = "tag",
+            number_of_values = 1,
+            multiple_occurrences = true,
+            required = true
+        )]
+        deny_tags: Vec<String>,
+    },
+}
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct HookOpts {
+    /// The kind of hook. Valid values are: 'git'
+    #[clap(subcommand)]
+    pub(crate) object: HookObject,
+}
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct CheckStagedOpts {
+    /// Fail if a staged file has this tag. Can be used multiple times
+    #[clap(
+        long = "deny-tag",
+        value_name = "tag",
+        number_of_values = 1,
+        multiple_occurrences = true,
+        required = true
+    )]
+    pub(crate) deny_tags: Vec<String>,
+}
+
+impl App {
+    pub(crate) fn hook(&self, opts: &HookOpts) {
+        log::debug!("HookOpts: {:#?}", opts);
+
+        match &opts.object {
+            HookObject::Git { hook, deny_tags } => {
+                let args = deny_tags
+                    .iter()
+                    .map(|t| format!(" --deny-tag {}", ssh::quote(t)))
+                    .collect::<String>();
+
+                println!("#!/bin/sh");
+                println!("# Generated by: wutag hook git {}{}", hook, args);
+                println!("exec wutag check-staged{}", args);
+            },
+        }
+    }
+
+    /// Fail if any file that is staged in the git repository has a denied tag
+    pub(crate) fn check_staged(&self, opts: &CheckStagedOpts) {
+        log::debug!("CheckStagedOpts: {:#?}", opts);
+
+        let top = git(&self.base_dir, &["rev-parse", "--show-toplevel"])
+            .map(|out| PathBuf::from(out.trim()))
+            .unwrap_or_else(|e| wutag_fatal!("{}", e));
+        let staged = git(&top, &[
+            "diff",
+            "--cached",
+            "--name-only",
+            "-z",
+            "--diff-filter=ACMR",
+        ])
+        .unwrap_or_else(|e| wutag_fatal!("{}", e));
+
+        let mut denied = 0_usize;
+        for path in staged.split('\0').filter(|p| !p.is_empty()).map(|p| top.join(p)) {
+            // A tag may only be in the registry when extended attributes were lost
+            let mut tags = list_tags(&path)
+                .unwrap_or_default()
+                .iter()
+                .map(|t| t.name().to_owned())
+                .collect::<Vec<_>>();
+            if let Some(id) = self.registry.find_entry(&path) {
+                tags.extend(
+                    self.registry
+                        .list_entry_tags(id)
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|t| t.name().to_owned()),
+                );
+            }
+
+            let found = opts
+                .deny_tags
+                .iter()
+                .filter(|t| tags.contains(t))
+                .map(|t| t.bold().to_string())
+                .collect::<Vec<_>>();
+
+            if !found.is_empty() {
+                denied += 1;
+                if !self.quiet {
+                    println!(
+                        "{}: tagged {}",
+                        fmt_path(&path, self.base_color, self.ls_colors),
+                        found.join(", ")
+                    );
+                }
+            }
+        }
+
+        if denied > 0 {
+            wutag_error!("{} staged file(s) have a denied tag", denied);
+            diagnostics::exit(1);
+        }
+    }
+}
@@ -1,60 +1,230 @@
-// TODO: Entire subcommand
+//! Display the state of the registry, like `git status` for the tags, as well
+//! as the locations of the files `wutag` uses
+//!
+//! The registry is a single YAML file that is rewritten when it is saved, so
+//! unlike a database it has no write-ahead log or schema migrations. A registry
+//! saved by another version of `wutag` is displayed instead, since it is
+//! converted the next time it is saved
+
+// TODO: display the WAL status and pending migrations once the registry is
+//       stored in sqlite
 
-#![allow(unused)]
 use super::{
     uses::{
-        fmt_tag, Args, Border, Cell, ColorChoice, Colorize, HashMap, Justify, Separator, Style,
-        Subcommand, Table,
+        fs, get_cache_path, get_config_path, get_data_path, Args, BTreeMap, Colorize, Config,
+        CONFIG_FILE,
     },
     App,
 };
 
+use crate::{mounts, registry::REGISTRY_VERSION};
+
 #[derive(Args, Debug, Clone, PartialEq)]
 pub(crate) struct InfoOpts {
-    /// TO BE IMPLEMENTED
     /// Do not use color in output
     #[clap(long, short)]
-    pub(crate) raw: bool,
+    pub(crate) raw:         bool,
+    /// Display the locations of the files and directories used
+    #[clap(
+        long,
+        short,
+        long_about = "Display the locations of the configuration, data (registry and TUI \
+                      history), snapshot, and cache directories. These can be changed with \
+                      'WUTAG_CONFIG_DIR', 'WUTAG_DATA_DIR', and 'WUTAG_CACHE_DIR'"
+    )]
+    pub(crate) paths:       bool,
+    /// Display whether the filesystems tags were written to keep them
+    #[clap(
+        long,
+        short,
+        conflicts_with = "paths",
+        long_about = "Display the mount points that tags were written to and whether their \
+                      filesystem keeps extended attributes: 'yes', 'no', or 'size limit' when \
+                      only small tags fit. Filesystems that silently drop them are only found \
+                      with 'verify_writes' in the configuration"
+    )]
+    pub(crate) filesystems: bool,
+}
+
+/// Format a number of bytes with a binary unit (e.g., `1.5 KiB`)
+fn fmt_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB"];
+
+    #[allow(clippy::cast_precision_loss)]
+    let mut size = bytes as f64;
+    if size < 1024.0 {
+        return format!("{} B", bytes);
+    }
+
+    let mut unit = UNITS[0];
+    for u in UNITS {
+        unit = u;
+        size /= 1024.0;
+        if size < 1024.0 {
+            break;
+        }
+    }
+    format!("{:.1} {}", size, unit)
 }
 
 impl App {
-    pub(crate) fn info(&mut self, opts: &InfoOpts) {
+    /// Display the state of the registry: its size, the version of `wutag`
+    /// that saved it, how many of each item it has, and when it was last
+    /// repaired
+    fn info_registry(&self, raw: bool) {
+        let field = |name: &str, value: String| {
+            if raw {
+                println!("{}: {}", name, value);
+            } else {
+                println!("{}: {}", name.magenta(), value);
+            }
+        };
+        let color = |value: String, good: bool| {
+            if raw {
+                value
+            } else if good {
+                value.green().to_string()
+            } else {
+                value.yellow().to_string()
+            }
+        };
+        let registry = &self.registry;
+
+        field("Registry", color(registry.path.display().to_string(), true));
+        if let Some(url) = registry.remote() {
+            field("Remote", color(url.to_owned(), true));
+        } else {
+            field(
+                "Size",
+                fs::metadata(&registry.path).map_or_else(
+                    |e| color(e.to_string(), false),
+                    |meta| color(fmt_size(meta.len()), true),
+                ),
+            );
+        }
+
+        field(
+            "Version",
+            match registry.version.as_deref() {
+                Some(REGISTRY_VERSION) => color(REGISTRY_VERSION.to_owned(), true),
+                Some(version) => color(
+                    format!(
+                        "{} (this is {}, it is converted when next saved)",
+                        version, REGISTRY_VERSION
+                    ),
+                    false,
+                ),
+                None => color(
+                    format!(
+                        "unknown (saved before {}, it is converted when next saved)",
+                        REGISTRY_VERSION
+                    ),
+                    false,
+                ),
+            },
+        );
+
+        field(
+            "Namespace",
+            color(format!("{}.wutag", registry.namespace), true),
+        );
+
+        #[cfg(feature = "encrypt-gpgme")]
+        let encryption = match (
+            crate::registry::is_encrypted(&registry.path),
+            self.encrypt.to_encrypt,
+        ) {
+            (true, true) => color("encrypted".to_owned(), true),
+            (false, false) => color("not encrypted".to_owned(), true),
+            (true, false) => color("encrypted, decrypted when next saved".to_owned(), false),
+            (false, true) => color("not encrypted, encrypted when next saved".to_owned(), false),
+        };
+        #[cfg(not(feature = "encrypt-gpgme"))]
+        let encryption = color("not supported by this build".to_owned(), true);
+        field("Encryption", encryption);
+
+        field(
+            "Last maintenance",
+            registry.maintained.map_or_else(
+                || color("never (see 'repair')".to_owned(), false),
+                |time| color(time.format("%Y-%m-%d %H:%M:%S").to_string(), true),
+            ),
+        );
+
+        let values = registry.values.values().map(BTreeMap::len).sum::<usize>();
+        let unused = registry.list_unused_tags().len();
+        let orphans = registry.list_orphaned_values().len();
+        for (name, count, problem) in [
+            ("Files", registry.entries.len(), false),
+            ("Tags", registry.tags.len(), false),
+            ("Values", values, false),
+            ("Icons", registry.icons.len(), false),
+            ("Stashes", registry.stashes.len(), false),
+            ("Snapshots", self.list_snapshots().len(), false),
+            ("Unused tags", unused, unused > 0),
+            ("Orphaned values", orphans, orphans > 0),
+        ] {
+            field(name, color(count.to_string(), !problem));
+        }
+    }
+
+    /// Display the effective locations of the files and directories used
+    pub(crate) fn info_paths(&self, config: &Config) {
+        let unknown = |e: anyhow::Error| e.to_string();
+        let config_dir = get_config_path().map_err(unknown);
+
+        let mut paths = vec![
+            (
+                "Config directory",
+                config_dir.as_ref().map(|p| p.display().to_string()),
+            ),
+            (
+                "Config file",
+                config_dir
+                    .as_ref()
+                    .map(|p| p.join(CONFIG_FILE).display().to_string()),
+            ),
+            (
+                "Data directory",
+                get_data_path()
+                    .map(|p| p.display().to_string())
+                    .map_err(unknown),
+            ),
+            ("Registry", Ok(self.registry.path.display().to_string())),
+            ("Snapshots", Ok(self.snapshot_dir().display().to_string())),
+            (
+                "Cache directory",
+                Ok(get_cache_path().display().to_string()),
+            ),
+        ];
+
+        #[cfg(feature = "ui")]
+        paths.push(("History", Ok(config.ui.history_filepath.clone())));
+
+        for (name, path) in paths {
+            match path {
+                Ok(path) => println!("{}: {}", name.magenta(), path.green()),
+                Err(e) => println!("{}: {}", name.magenta(), e.red()),
+            }
+        }
+    }
+
+    pub(crate) fn info(&mut self, opts: &InfoOpts, config: &Config) {
         log::debug!("InfoOpts: {:#?}", opts);
         log::debug!("Using registry: {}", self.registry.path.display());
 
-        println!(
-            "{}: {}",
-            "Registry".magenta(),
-            self.registry.path.display().to_string().green()
-        );
+        if opts.paths {
+            self.info_paths(config);
+            return;
+        }
+
+        if opts.filesystems {
+            for (mount, support) in mounts::table() {
+                println!("{}: {}", mount.display().to_string().magenta(), support);
+            }
+            return;
+        }
 
-        // let (w, _) = crossterm::terminal::size().unwrap_or((80, 40));
-        //
-        // let mut table = vec![];
-        //
-        // for tag in self.registry.list_tags() {
-        //     let mut row = vec![];
-        //     let mut cumulative = 0;
-        //
-        //     cumulative += tag.name().len();
-        //
-        //     if cumulative < ((w - 4) / 2) as usize {
-        //         row.push(tag);
-        //     } else {
-        //         table.push(row.clone().iter().map(|t|
-        // t.cell().justify(Justify::Left)));         row.clear();
-        //     }
-        // }
-
-        self.registry
-            .list_tags()
-            .map(|tag| {
-                if opts.raw {
-                    tag.name().white()
-                } else {
-                    fmt_tag(tag)
-                }
-            })
-            .for_each(|t| println!("{}", t));
+        self.info_registry(opts.raw);
     }
 }
@@ -0,0 +1,143 @@
+use super::{
+    uses::{
+        contained_path, diagnostics, fmt_path, fmt_tag, glob_builder, parse_path, reg_ok,
+        regex_builder, wutag_error, wutag_warning, Arc, Args, Colorize, DirEntryExt, DirPolicy,
+        HashMap, PathBuf, ValueHint, POLICY_FILE,
+    },
+    App,
+};
+
+use itertools::Itertools;
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct LintOpts {
+    /// Directory to check. Defaults to the current directory or the path given
+    /// with -d
+    #[clap(
+        value_hint = ValueHint::DirPath,
+        validator = |t| parse_path(t),
+    )]
+    pub(crate) dir:         Option<PathBuf>,
+    /// Also warn about files with too many tags and tags on too many files
+    #[clap(
+        long = "quota-check",
+        short = 'Q',
+        long_about = "Also warn about the files in the directory that have more tags than \
+                      'max_tags_per_file' (default: 32), and the tags that are on more files \
+                      in the directory than 'max_files_per_tag' (default: 10000), which are \
+                      usually caused by a script or rule that went wrong. With '--global' the \
+                      whole registry is checked. These are warnings, so they only cause a \
+                      failure status with '--strict'"
+    )]
+    pub(crate) quota_check: bool,
+}
+
+impl App {
+    /// Warn about the files that have more tags than `max_tags_per_file`, and
+    /// the tags that are on more files than `max_files_per_tag`. Only the files
+    /// in the directory being linted are counted, unless `--global` is given
+    fn check_quotas(&self) {
+        let linted = |path: &std::path::Path| self.global || contained_path(path, &self.base_dir);
+
+        for (id, entry) in self.sorted_entries() {
+            if !linted(entry.path()) {
+                continue;
+            }
+
+            let count = self.registry.list_entry_tags(*id).map_or(0, |tags| tags.len());
+            if count > self.max_tags_per_file {
+                wutag_warning!(
+                    "{} has {} tags (quota: {})",
+                    fmt_path(entry.path(), self.base_color, self.ls_colors),
+                    count,
+                    self.max_tags_per_file
+                );
+            }
+        }
+
+        for (tag, ids) in &self.registry.tags {
+            let count = ids
+                .iter()
+                .filter_map(|id| self.registry.get_entry(*id))
+                .filter(|entry| linted(entry.path()))
+                .count();
+            if count > self.max_files_per_tag {
+                wutag_warning!(
+                    "{} is on {} files (quota: {})",
+                    fmt_tag(tag),
+                    count,
+                    self.max_files_per_tag
+                );
+            }
+        }
+    }
+
+    /// Report the files that are missing any of the tags required by the
+    /// closest policy file. Exits with a failure status if any are found
+    pub(crate) fn lint(&mut self, opts: &LintOpts) {
+        log::debug!("LintOpts: {:#?}", opts);
+
+        if let Some(dir) = &opts.dir {
+            self.base_dir = self.base_dir.join(dir);
+        }
+        log::debug!("Linting: {}", self.base_dir.display());
+
+        let re = regex_builder(&glob_builder("*"), false, false);
+        let mut policies: HashMap<PathBuf, Option<DirPolicy>> = HashMap::new();
+        let mut failed = 0_usize;
+
+        reg_ok(
+            &Arc::new(re),
+            &Arc::new(self.clone()),
+            |entry: &ignore::DirEntry| {
+                if !entry.file_type().map_or(false, |ft| ft.is_file())
+                    || entry.file_name() == POLICY_FILE
+                {
+                    return;
+                }
+
+                let dir = match entry.path().parent() {
+                    Some(dir) => dir.to_path_buf(),
+                    None => return,
+                };
+
+                let policy = policies.entry(dir).or_insert_with_key(|dir| {
+                    DirPolicy::load(dir).unwrap_or_else(|e| {
+                        wutag_error!("{}", e);
+                        None
+                    })
+                });
+
+                if let Some(policy) = policy {
+                    let tags = entry
+                        .list_tags()
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|t| t.name().to_owned())
+                        .collect::<Vec<_>>();
+                    let missing = policy.missing(&tags);
+
+                    if !missing.is_empty() {
+                        failed += 1;
+                        if !self.quiet {
+                            println!(
+                                "{}: missing {}",
+                                fmt_path(entry.path(), self.base_color, self.ls_colors),
+                                missing.iter().map(|t| t.bold()).join(", ")
+                            );
+                        }
+                    }
+                }
+            },
+        );
+
+        if opts.quota_check {
+            self.check_quotas();
+        }
+
+        if failed > 0 {
+            wutag_error!("{} file(s) are missing required tags", failed);
+            diagnostics::exit(1);
+        }
+    }
+}
@@ -2,13 +2,46 @@
 
 use super::{
     uses::{
-        contained_path, fmt_local_path, fmt_path, fmt_tag, global_opts, print_stdout,
-        raw_local_path, ternary, Args, Border, Cell, ColorChoice, Colorize, HashMap, Justify,
-        Separator, Style, Subcommand, Table,
+        color_matches, contained_path, fmt_local_path, fmt_path, fmt_tag_icon, global_opts,
+        print_stdout, raw_local_path, ternary, Args, BTreeMap, Border, Cell, Colorize, IndexMap,
+        Justify, Porcelain, SeenFiles, Separator, SortOrder, Style, Subcommand, Table, Tag,
     },
     App,
 };
+use crate::{
+    porcelain::plain_count,
+    registry::{EntryData, EntryId},
+};
 use itertools::Itertools;
+use std::{path::Path, str::FromStr};
+use wutag_core::tag::list_tags_by_owner;
+
+/// Name of the group of files that do not have an extension
+const NO_EXTENSION: &str = "(none)";
+
+/// What the files are grouped by with `list files --group-by`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum GroupBy {
+    /// Each tag, so a file is in the group of every tag it has
+    Tag,
+    /// The directory the file is in
+    Dir,
+    /// The extension of the file
+    Ext,
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().trim() {
+            "tag" | "tags" => Ok(Self::Tag),
+            "dir" | "directory" => Ok(Self::Dir),
+            "ext" | "extension" => Ok(Self::Ext),
+            _ => Err(format!("invalid group: {}", s)),
+        }
+    }
+}
 
 #[derive(Subcommand, Debug, Clone, PartialEq)]
 pub(crate) enum ListObject {
@@ -28,7 +61,14 @@ pub(crate) enum ListObject {
         unique: bool,
 
         /// Sort the output alphabetically (no-count), numerically otherwise
-        #[clap(long, short = 's')]
+        #[clap(
+            long,
+            short = 's',
+            long_about = "Sort the output alphabetically when used with '--no-count', and by the \
+                          number of files otherwise. Tags with the same count are ordered by \
+                          name. Without this option tags are listed in the order of the \
+                          'default_sort' configuration option (by name by default)"
+        )]
         sort: bool,
 
         /// Display one tag per line instead of tags on files
@@ -52,11 +92,30 @@ pub(crate) enum ListObject {
                           lines."
         )]
         border: bool,
+
+        /// Only display tags that are not attached to any file
+        #[clap(
+            long = "unused",
+            short = 'U',
+            long_about = "Only display tags that are not attached to any file in the registry. \
+                          These can be removed with 'wutag clean-cache --orphans'"
+        )]
+        unused: bool,
+
+        /// Only display tags with the given color
+        #[clap(
+            long = "color-filter",
+            short = 'C',
+            value_name = "color",
+            long_about = "Only display tags with the given color. The color is either in hex \
+                          notation or the name of the closest basic color (e.g., 'red')"
+        )]
+        color_filter: Option<String>,
     },
     Files {
         /// Display tags along with the files
         #[clap(name = "with_tags", long = "with-tags", short = 't')]
-        with_tags: bool,
+        with_tags:   bool,
         /// Format the tags and files output into columns
         #[clap(
             name = "formatted",
@@ -66,7 +125,7 @@ pub(crate) enum ListObject {
             requires = "with_tags",
             long_about = "Format the tags and files output into columns. Requires '--with-tags'"
         )]
-        formatted: bool,
+        formatted:   bool,
         /// Use border separators when formatting output
         #[clap(
             long,
@@ -76,7 +135,7 @@ pub(crate) enum ListObject {
             Use a border around the perimeter of the formatted output, as well as in-between the \
                           lines."
         )]
-        border:    bool,
+        border:      bool,
         /// Display tags and files on separate lines
         #[clap(
             name = "garrulous",
@@ -85,7 +144,49 @@ pub(crate) enum ListObject {
             conflicts_with = "formatted",
             requires = "with_tags"
         )]
-        garrulous: bool,
+        garrulous:   bool,
+        /// Also display the tags that other users set on the files
+        #[clap(
+            name = "show-others",
+            long = "show-others",
+            requires = "with_tags",
+            long_about = "Also display the tags that are in the namespaces of other users, or in \
+                          the shared namespace when '--per-user' is used, after the file's own \
+                          tags. These are read-only and are displayed as 'tag@<uid>', or \
+                          'tag@shared' for the shared namespace"
+        )]
+        show_others: bool,
+        /// Group the files by tag, directory, or extension
+        #[clap(
+            name = "group-by",
+            long = "group-by",
+            value_name = "key",
+            possible_values = &["tag", "dir", "ext"],
+            conflicts_with = "formatted",
+            long_about = "Display the files in groups, each with a header holding the number of \
+                          files in it. Files are grouped by each of their tags ('tag'), the \
+                          directory they are in ('dir'), or their extension ('ext'). Groups are \
+                          ordered by name, or by the number of files when 'default_sort' is \
+                          'count'"
+        )]
+        group_by:    Option<GroupBy>,
+        /// Only display the header of each group
+        #[clap(long, requires = "group-by")]
+        collapse:    bool,
+    },
+    Values {
+        /// Only display the values of this tag
+        #[clap(long = "for-tag", short = 't', value_name = "tag")]
+        for_tag: Option<String>,
+        /// Sort the values by the number of files they are on
+        #[clap(
+            long,
+            short = 's',
+            long_about = "Sort the values by the number of files that they are attached to. \
+                          Values with the same count are ordered by name. Without this option \
+                          values are listed by name unless 'default_sort' is 'count'"
+        )]
+        sort:    bool,
     },
 }
 
@@ -100,17 +201,29 @@ pub(crate) struct ListOpts {
     pub(crate) raw:    bool,
 }
 
+/// The text of a formatted tag without colors, in lowercase, used to sort tags
+/// alphabetically
+fn plain_text(s: &str) -> String {
+    String::from_utf8(strip_ansi_escapes::strip(s.as_bytes()).unwrap_or_default())
+        .expect("invalid UTF-8")
+        .to_ascii_lowercase()
+}
+
+/// The extension of a file in lowercase, used as the name of its group
+fn extension_group(path: &Path) -> String {
+    path.extension().map_or_else(
+        || String::from(NO_EXTENSION),
+        |ext| ext.to_string_lossy().to_lowercase(),
+    )
+}
+
 impl App {
     pub(crate) fn list(&self, opts: &ListOpts) {
         log::debug!("ListOpts: {:#?}", opts);
         log::debug!("Using registry: {}", self.registry.path.display());
 
         let mut table = vec![];
-        let colorchoice = match self.color_when.as_ref() {
-            "always" => ColorChoice::Always,
-            "never" => ColorChoice::Never,
-            _ => ColorChoice::Auto,
-        };
+        let colorchoice = self.color_choice();
 
         match opts.object {
             ListObject::Files {
@@ -118,8 +231,24 @@ impl App {
                 formatted,
                 border,
                 garrulous,
+                show_others,
+                group_by,
+                collapse,
             } => {
-                for (id, file) in self.registry.list_entries_and_ids() {
+                if let Some(by) = group_by.filter(|_| self.porcelain.is_none()) {
+                    self.list_files_grouped(
+                        opts.raw,
+                        by,
+                        collapse,
+                        with_tags,
+                        show_others,
+                        garrulous,
+                    );
+                    return;
+                }
+
+                let mut seen = SeenFiles::default();
+                for (id, file) in self.paged_entries() {
                     // Skips paths that are not contained within current directory to respect the
                     // `-d` flag. Global is just another way to specify -d=~
                     // (list files locally by default, i.e., no subcommand is given)
@@ -127,6 +256,18 @@ impl App {
                         continue;
                     }
 
+                    if !self.show_duplicates && seen.is_duplicate(file.path()) {
+                        continue;
+                    }
+
+                    if let Some(format) = self.porcelain {
+                        let path = file.path().display().to_string();
+                        let tags = self.registry.list_entry_tags(id).unwrap_or_default();
+                        let tags = self.styled_tags(id, tags.into_iter().filter(|_| with_tags));
+                        println!("{}", format.file_record(None, &path, &tags));
+                        continue;
+                    }
+
                     if opts.raw {
                         global_opts!(
                             raw_local_path(file.path(), &self.base_dir),
@@ -149,20 +290,7 @@ impl App {
                     }
 
                     if with_tags {
-                        let tags = self
-                            .registry
-                            .list_entry_tags(*id)
-                            .unwrap_or_default()
-                            .iter()
-                            .map(|t| {
-                                if opts.raw {
-                                    t.name().to_owned()
-                                } else {
-                                    fmt_tag(t).to_string()
-                                }
-                            })
-                            .collect::<Vec<_>>()
-                            .join(" ");
+                        let tags = self.entry_tags_text(id, file.path(), opts.raw, show_others);
 
                         if formatted {
                             table.push(vec![
@@ -203,15 +331,107 @@ impl App {
                     .expect("Unable to print table");
                 }
             },
+            ListObject::Values {
+                ref for_tag,
+                sort,
+            } => {
+                let counts = self.registry.count_values(for_tag.as_deref(), |id| {
+                    self.global
+                        || self
+                            .registry
+                            .get_entry(id)
+                            .map_or(false, |e| contained_path(e.path(), &self.base_dir))
+                });
+
+                let mut counts = counts.into_iter().collect::<Vec<_>>();
+                if sort || self.default_sort == SortOrder::Count {
+                    counts.sort_by(|a, b| b.1.files.cmp(&a.1.files));
+                }
+
+                if self.porcelain == Some(Porcelain::Plain) {
+                    for (value, count) in counts {
+                        println!(
+                            "{}: {} ({})",
+                            value,
+                            plain_count(count.files),
+                            count.tags.iter().join(", ")
+                        );
+                    }
+                    return;
+                }
+
+                for (value, count) in counts {
+                    let tags = count
+                        .tags
+                        .iter()
+                        .map(|name| match self.registry.get_tag(name) {
+                            Some(tag) if !opts.raw => fmt_tag_icon(tag, self.tag_icon(tag)),
+                            _ => (*name).to_owned(),
+                        })
+                        .join(" ");
+                    table.push(vec![
+                        value.cell(),
+                        ternary!(
+                            opts.raw,
+                            count.files.to_string().white(),
+                            count.files.to_string().green().bold()
+                        )
+                        .cell()
+                        .justify(Justify::Right),
+                        tags.cell(),
+                    ]);
+                }
+
+                print_stdout(
+                    table
+                        .table()
+                        .border(Border::builder().build())
+                        .separator(Separator::builder().build()),
+                )
+                .expect("Unable to print table");
+            },
             ListObject::Tags {
                 no_count,
                 border,
                 one_per_line,
                 unique,
                 sort,
+                unused,
+                ref color_filter,
             } => {
+                let keep = |t: &Tag| {
+                    color_filter
+                        .as_ref()
+                        .map_or(true, |c| color_matches(*t.color(), c))
+                };
+
+                if unused {
+                    for tag in self
+                        .registry
+                        .list_unused_tags()
+                        .into_iter()
+                        .filter(|t| keep(*t))
+                    {
+                        println!(
+                            "{}",
+                            ternary!(
+                                opts.raw,
+                                tag.name().white().to_string(),
+                                fmt_tag_icon(tag, self.tag_icon(tag))
+                            )
+                        );
+                    }
+                    return;
+                }
+
+                if let Some(format) = self.porcelain {
+                    self.list_tags_porcelain(format, keep);
+                    return;
+                }
+
+                let sort = sort || self.default_sort == SortOrder::Count;
                 let mut utags = Vec::new();
-                for (&id, file) in self.registry.list_entries_and_ids() {
+                for (&id, file) in self.sorted_entries() {
                     if !self.global && !contained_path(file.path(), &self.base_dir) {
                         continue;
                     }
@@ -219,47 +439,57 @@ impl App {
                     macro_rules! raw {
                         ($t:ident) => {
                             if opts.raw {
-                                $t.name().white()
+                                $t.name().white().to_string()
                             } else {
-                                fmt_tag($t)
+                                fmt_tag_icon($t, self.tag_icon($t))
                             }
                         };
                     }
 
                     if one_per_line {
                         self.registry.list_entry_tags(id).iter().for_each(|tags| {
-                            tags.iter().for_each(|t| utags.push(format!("{}", raw!(t))));
+                            tags.iter()
+                                .filter(|t| keep(*t))
+                                .for_each(|t| utags.push(format!("{}", raw!(t))));
                         });
                     } else {
                         let tags = self
                             .registry
                             .list_entry_tags(id)
                             .map(|tags| {
-                                tags.iter().fold(String::new(), |mut acc, t| {
-                                    acc.push_str(&format!("{} ", raw!(t)));
-                                    acc
-                                })
+                                tags.iter()
+                                    .filter(|t| keep(*t))
+                                    .fold(String::new(), |mut acc, t| {
+                                        acc.push_str(&format!("{} ", raw!(t)));
+                                        acc
+                                    })
                             })
                             .unwrap_or_default()
                             .clone();
 
-                        utags.push(tags);
+                        if !tags.is_empty() {
+                            utags.push(tags);
+                        }
                     }
                 }
 
+                // Ordered by name so that tags with the same count are always in the same
+                // order, unless they are listed in the order they were added
                 let mut vec = utags
                     .iter()
-                    .fold(HashMap::new(), |mut acc, t| {
+                    .fold(IndexMap::new(), |mut acc, t| {
                         *acc.entry(t.clone()).or_insert(0) += 1;
                         acc
                     })
-                    .iter()
-                    .map(|(s, i)| (s.clone(), *i))
+                    .into_iter()
                     .collect::<Vec<(String, i32)>>();
+                if self.default_sort != SortOrder::Registry {
+                    vec.sort_by_cached_key(|(s, _)| plain_text(s));
+                }
 
                 // Sort numerically if count is included
                 if sort {
-                    vec = vec.iter().sorted_by_key(|a| -a.1).cloned().collect();
+                    vec.sort_by_key(|a| -a.1);
                 }
 
                 for (tag, count) in vec {
@@ -281,25 +511,7 @@ impl App {
                     }
                     // Sort alphabetically if no count
                     if sort {
-                        utags = utags
-                            .iter()
-                            .sorted_unstable_by(|a, b| {
-                                macro_rules! strip_ansi {
-                                    ($cmp:ident) => {
-                                        &String::from_utf8(
-                                            strip_ansi_escapes::strip($cmp.as_bytes())
-                                                .unwrap_or_default(),
-                                        )
-                                        .expect("invalid UTF-8")
-                                        .to_ascii_lowercase()
-                                    };
-                                }
-
-                                Ord::cmp(strip_ansi!(b), strip_ansi!(a))
-                            })
-                            .rev()
-                            .cloned()
-                            .collect_vec();
+                        utags.sort_by_cached_key(|t| plain_text(t));
                     }
                     for tag in utags {
                         println!("{}", tag);
@@ -321,4 +533,194 @@ impl App {
             },
         }
     }
+
+    /// The tags of a file separated by spaces, followed by the tags other users
+    /// set on it when using `show_others`
+    fn entry_tags_text(&self, id: EntryId, path: &Path, raw: bool, show_others: bool) -> String {
+        let mut tags = self
+            .registry
+            .list_entry_tags(id)
+            .unwrap_or_default()
+            .iter()
+            .map(|t| {
+                if raw {
+                    t.name().to_owned()
+                } else {
+                    fmt_tag_icon(t, self.tag_icon(t))
+                }
+            })
+            .collect::<Vec<_>>();
+        tags.extend(self.fmt_locked_tags(path, raw));
+
+        if show_others {
+            // Tags in the namespace that is read and written are not another user's
+            let own = self.per_user.then(wutag_core::current_uid);
+            for (uid, tag) in list_tags_by_owner(path)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|(uid, _)| *uid != own)
+            {
+                let owner = uid.map_or_else(|| "shared".to_owned(), |u| u.to_string());
+                tags.push(ternary!(
+                    raw,
+                    format!("{}@{}", tag.name(), owner),
+                    format!("{}@{}", tag.name(), owner).dimmed().to_string()
+                ));
+            }
+        }
+
+        tags.join(" ")
+    }
+
+    /// The name of the directory a file is in, relative to the base directory
+    /// unless using `--global`
+    fn dir_group(&self, path: &Path) -> String {
+        let dir = path.parent().unwrap_or(path);
+        if self.global {
+            dir.display().to_string()
+        } else if dir == self.base_dir {
+            String::from(".")
+        } else {
+            raw_local_path(dir, &self.base_dir)
+        }
+    }
+
+    /// List the files in groups that share a tag, a directory, or an extension
+    fn list_files_grouped(
+        &self,
+        raw: bool,
+        by: GroupBy,
+        collapse: bool,
+        with_tags: bool,
+        show_others: bool,
+        garrulous: bool,
+    ) {
+        // The name of each group, along with its header and files
+        let mut groups: BTreeMap<String, (String, Vec<(EntryId, &EntryData)>)> = BTreeMap::new();
+
+        let mut seen = SeenFiles::default();
+        for (id, file) in self.sorted_entries() {
+            if !self.global && !contained_path(file.path(), &self.base_dir) {
+                continue;
+            }
+            if !self.show_duplicates && seen.is_duplicate(file.path()) {
+                continue;
+            }
+
+            let names = match by {
+                GroupBy::Tag => self
+                    .registry
+                    .list_entry_tags(*id)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|t| {
+                        let header =
+                            ternary!(raw, t.name().to_owned(), fmt_tag_icon(t, self.tag_icon(t)));
+                        (t.name().to_owned(), header)
+                    })
+                    .collect::<Vec<_>>(),
+                GroupBy::Dir | GroupBy::Ext => {
+                    let name = ternary!(
+                        by == GroupBy::Dir,
+                        self.dir_group(file.path()),
+                        extension_group(file.path())
+                    );
+                    let header = ternary!(
+                        raw,
+                        name.clone(),
+                        name.color(self.base_color).bold().to_string()
+                    );
+                    vec![(name, header)]
+                },
+            };
+
+            for (name, header) in names {
+                groups
+                    .entry(name)
+                    .or_insert_with(|| (header, Vec::new()))
+                    .1
+                    .push((*id, file));
+            }
+        }
+
+        let mut groups = groups.into_iter().map(|(_, g)| g).collect::<Vec<_>>();
+        if self.default_sort == SortOrder::Count {
+            groups.sort_by(|a, b| b.1.len().cmp(&a.1.len()));
+        }
+
+        for (header, files) in groups {
+            let count = files.len().to_string();
+            println!(
+                "{} ({})",
+                header,
+                ternary!(raw, count.normal(), count.green().bold())
+            );
+            if collapse {
+                continue;
+            }
+
+            for (id, file) in files {
+                let path = match (raw, self.global) {
+                    (true, true) => file.path().display().to_string(),
+                    (true, false) => raw_local_path(file.path(), &self.base_dir),
+                    (false, true) => fmt_path(file.path(), self.base_color, self.ls_colors),
+                    (false, false) => fmt_local_path(
+                        file.path(),
+                        &self.base_dir,
+                        self.base_color,
+                        self.ls_colors,
+                    ),
+                };
+                if with_tags {
+                    let tags = self.entry_tags_text(id, file.path(), raw, show_others);
+                    if garrulous {
+                        println!("\t{}\n\t\t{}", path, tags);
+                    } else {
+                        println!("\t{}: {}", path, tags);
+                    }
+                } else {
+                    println!("\t{}", path);
+                }
+            }
+        }
+    }
+
+    /// Print each tag along with the number of files it is on using the
+    /// porcelain format
+    fn list_tags_porcelain<F: Fn(&Tag) -> bool>(&self, format: Porcelain, keep: F) {
+        let mut counts: BTreeMap<&Tag, usize> = BTreeMap::new();
+        for (&id, file) in self.registry.list_entries_and_ids() {
+            if !self.global && !contained_path(file.path(), &self.base_dir) {
+                continue;
+            }
+
+            for tag in self.registry.list_entry_tags(id).unwrap_or_default() {
+                if !keep(tag) {
+                    continue;
+                }
+                *counts.entry(tag).or_insert(0) += 1;
+            }
+        }
+
+        for (tag, count) in counts {
+            println!("{}", format.tag_record(self.styled_tag(tag), count));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_groups() {
+        assert_eq!("tag".parse::<GroupBy>(), Ok(GroupBy::Tag));
+        assert_eq!("Directory".parse::<GroupBy>(), Ok(GroupBy::Dir));
+        assert_eq!("ext".parse::<GroupBy>(), Ok(GroupBy::Ext));
+        assert!("size".parse::<GroupBy>().is_err());
+
+        assert_eq!(extension_group(Path::new("/a/photo.JPG")), "jpg");
+        assert_eq!(extension_group(Path::new("/a/Makefile")), NO_EXTENSION);
+        assert_eq!(extension_group(Path::new("/a/.bashrc")), NO_EXTENSION);
+    }
 }
@@ -0,0 +1,342 @@
+//! Tags shared with photo managers such as Darktable, which keep them as the
+//! keywords of XMP metadata (see [`crate::xmp`]). `import --from xmp` tags
+//! files with the keywords of their sidecars or embedded XMP, and `export --to
+//! xmp` writes the tags of files to their sidecars
+//!
+//! `import --from beets` and `--from calibre` tag the files of those libraries
+//! with the genres, albums, series, etc. kept in their databases (see
+//! [`crate::library`])
+
+use super::{
+    uses::{
+        bold_entry, diagnostics, fmt_path, fmt_tag, glob_builder, list_tags, osstr_to_bytes,
+        reg_ok, regex_builder, wutag_error, wutag_fatal, wutag_warning, Arc, Args, Colorize,
+        EntryData, PathBuf, Tag, ValueHint,
+    },
+    App,
+};
+
+use crate::{
+    library::{self, LibraryItem},
+    mounts,
+    registry::{Op, OpStatus},
+    xmp,
+};
+use std::{fmt, str::FromStr};
+
+/// Formats that tags are imported from and exported to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum MediaFormat {
+    /// The `dc:subject` keywords of XMP sidecars or of the XMP embedded in a
+    /// file
+    Xmp,
+    /// The database of the music library of beets
+    Beets,
+    /// The database of a calibre library of books
+    Calibre,
+}
+
+impl FromStr for MediaFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().trim() {
+            "xmp" => Ok(Self::Xmp),
+            "beets" => Ok(Self::Beets),
+            "calibre" => Ok(Self::Calibre),
+            v => Err(format!("unsupported format: {}", v)),
+        }
+    }
+}
+
+impl fmt::Display for MediaFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Xmp => f.write_str("xmp"),
+            Self::Beets => f.write_str("beets"),
+            Self::Calibre => f.write_str("calibre"),
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct ImportOpts {
+    /// Format to read the tags from
+    #[clap(long, value_name = "format", possible_values = &["xmp", "beets", "calibre"])]
+    pub(crate) from:    MediaFormat,
+    /// Database of beets or directory of a calibre library
+    #[clap(
+        long,
+        short,
+        value_name = "path",
+        value_hint = ValueHint::AnyPath,
+        long_about = "The database of beets (default: '$BEETSDIR/library.db' or \
+                      '~/.config/beets/library.db'), or the directory of a calibre library or \
+                      its 'metadata.db' (default: '~/Calibre Library')"
+    )]
+    pub(crate) library: Option<PathBuf>,
+    /// A glob pattern like "*.jpg" (or regex), which only filters the files of
+    /// a library
+    #[clap(required_if_eq("from", "xmp"))]
+    pub(crate) pattern: Option<String>,
+}
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct ExportOpts {
+    /// Format to write the tags to
+    #[clap(long, value_name = "format", possible_values = &["xmp"])]
+    pub(crate) to:      MediaFormat,
+    /// Replace the keywords of existing sidecars instead of adding to them
+    #[clap(
+        long,
+        short,
+        long_about = "Replace the keywords that existing sidecars have with the tags of the \
+                      file, instead of adding the tags that are missing. Other metadata in the \
+                      sidecar, such as the history of edits, is always kept"
+    )]
+    pub(crate) replace: bool,
+    /// A glob pattern like "*.jpg" (or regex)
+    pub(crate) pattern: String,
+}
+
+impl App {
+    /// Tag the files that match the `pattern` with the keywords of their XMP
+    /// metadata
+    fn import_xmp(&mut self, pattern: &str) {
+        let pat = if self.pat_regex {
+            String::from(pattern)
+        } else {
+            glob_builder(pattern)
+        };
+
+        let re = regex_builder(&pat, self.case_insensitive, self.case_sensitive);
+        log::debug!("Compiled pattern: {}", re);
+        reg_ok(
+            &Arc::new(re),
+            &Arc::new(self.clone()),
+            |entry: &ignore::DirEntry| {
+                let path = entry.path();
+                if xmp::is_sidecar(path) {
+                    return;
+                }
+
+                let keywords = match xmp::read_subjects(path) {
+                    Ok(keywords) if keywords.is_empty() => return,
+                    Ok(keywords) => keywords,
+                    Err(e) => {
+                        diagnostics::warn();
+                        wutag_error!("{:#} {}", e, bold_entry!(path));
+                        return;
+                    },
+                };
+
+                if !self.quiet {
+                    println!("{}:", fmt_path(path, self.base_color, self.ls_colors));
+                }
+                for keyword in &keywords {
+                    let tag = self.resolve_tag(keyword, &None);
+                    if let Err(e) = self.write_tag(entry, &tag) {
+                        wutag_warning!("{} {}", mounts::describe(path, &e), bold_entry!(path));
+                        continue;
+                    }
+
+                    let data = match EntryData::new(path) {
+                        Ok(data) => data,
+                        Err(e) => {
+                            wutag_warning!("{} {}", e, bold_entry!(path));
+                            continue;
+                        },
+                    };
+                    let id = self.registry.add_or_update_entry(data);
+                    self.registry.tag_entry(&tag, id);
+                    if !self.quiet {
+                        print!("\t{} {}", "+".bold().green(), fmt_tag(&tag));
+                    }
+                }
+                if !self.quiet {
+                    println!();
+                }
+            },
+        );
+
+        log::debug!("Saving registry...");
+        self.save_registry();
+    }
+
+    /// Tag the files of a library with the tags and values of its `items`,
+    /// only keeping those that match the `pattern` if there is one
+    fn import_library(&mut self, items: Vec<LibraryItem>, pattern: Option<&str>) {
+        let re = pattern.map(|pattern| {
+            let pat = if self.pat_regex {
+                String::from(pattern)
+            } else {
+                glob_builder(pattern)
+            };
+            regex_builder(&pat, self.case_insensitive, self.case_sensitive)
+        });
+
+        let (items, missing): (Vec<_>, Vec<_>) = items
+            .into_iter()
+            .filter(|item| {
+                re.as_ref().map_or(true, |re| {
+                    re.is_match(&osstr_to_bytes(item.path.as_os_str()))
+                })
+            })
+            .partition(|item| item.path.exists());
+        if !missing.is_empty() {
+            wutag_warning!(
+                "{} file(s) of the library do not exist and were skipped",
+                missing.len()
+            );
+            for item in &missing {
+                log::debug!("missing: {}", item.path.display());
+            }
+        }
+
+        let items = items
+            .iter()
+            .map(|item| {
+                let tags = item
+                    .tags
+                    .iter()
+                    .map(|name| self.resolve_tag(name, &None))
+                    .collect::<Vec<Tag>>();
+                (item, tags)
+            })
+            .collect::<Vec<_>>();
+
+        let mut journal = self.begin_journal();
+        for (item, tags) in &items {
+            self.journal_op(&mut journal, &item.path, false, tags, &[], &item.values);
+        }
+        self.commit_journal(&mut journal);
+
+        for (item, tags) in &items {
+            let path = &item.path;
+            if !self.quiet {
+                println!("{}:", fmt_path(path, self.base_color, self.ls_colors));
+            }
+
+            let mut ops = vec![];
+            for tag in tags {
+                if let Err(e) = self.write_tag(&path, tag) {
+                    wutag_warning!("{} {}", mounts::describe(path, &e), bold_entry!(path));
+                    continue;
+                }
+
+                ops.push(Op::AddTag {
+                    path: path.clone(),
+                    tag:  tag.clone(),
+                });
+                if let Some(value) = item.values.get(tag.name()) {
+                    ops.push(Op::SetValue {
+                        path:  path.clone(),
+                        tag:   tag.name().to_owned(),
+                        value: value.clone(),
+                    });
+                }
+                if !self.quiet {
+                    print!("\t{} {}", "+".bold().green(), fmt_tag(tag));
+                }
+            }
+            if !self.quiet {
+                println!();
+            }
+
+            // A file is only in the registry with all of its tags and values
+            let report = self.registry.apply(ops);
+            for result in &report.results {
+                log::debug!("{:?}: {:?}", result.op, result.status);
+                if let OpStatus::Failed(e) = &result.status {
+                    diagnostics::warn();
+                    wutag_error!("{}", e);
+                }
+            }
+            if !report.committed {
+                wutag_warning!("the registry was not changed {}", bold_entry!(path));
+            }
+        }
+
+        log::debug!("Saving registry...");
+        self.save_registry();
+        self.finish_journal(journal);
+    }
+
+    /// Tag files with the tags kept by another program
+    pub(crate) fn import(&mut self, opts: &ImportOpts) {
+        log::debug!("ImportOpts: {:#?}", opts);
+        log::debug!("Using registry: {}", self.registry.path.display());
+
+        let library_path = |default: Option<PathBuf>| {
+            opts.library.clone().or(default).unwrap_or_else(|| {
+                wutag_fatal!("unable to find the library, pass it with '--library'")
+            })
+        };
+        let items = match opts.from {
+            MediaFormat::Xmp => {
+                self.import_xmp(opts.pattern.as_deref().unwrap_or_default());
+                return;
+            },
+            MediaFormat::Beets => library::beets_items(&library_path(library::default_beets_db())),
+            MediaFormat::Calibre =>
+                library::calibre_items(&library_path(library::default_calibre_library())),
+        };
+
+        match items {
+            Ok(items) => self.import_library(items, opts.pattern.as_deref()),
+            Err(e) => wutag_fatal!("{:#}", e),
+        }
+    }
+
+    /// Write the tags of the files that match the pattern to XMP sidecars
+    pub(crate) fn export(&self, opts: &ExportOpts) {
+        log::debug!("ExportOpts: {:#?}", opts);
+
+        let pat = if self.pat_regex {
+            String::from(&opts.pattern)
+        } else {
+            glob_builder(&opts.pattern)
+        };
+
+        let re = regex_builder(&pat, self.case_insensitive, self.case_sensitive);
+        log::debug!("Compiled pattern: {}", re);
+        reg_ok(
+            &Arc::new(re),
+            &Arc::new(self.clone()),
+            |entry: &ignore::DirEntry| {
+                let path = entry.path();
+                if xmp::is_sidecar(path) {
+                    return;
+                }
+
+                let tags = match list_tags(path) {
+                    Ok(tags) if !tags.is_empty() => tags
+                        .iter()
+                        .map(|tag| tag.name().to_owned())
+                        .collect::<Vec<_>>(),
+                    _ => return,
+                };
+
+                let written = match opts.to {
+                    MediaFormat::Xmp => xmp::write_sidecar(path, &tags, opts.replace),
+                    format => wutag_fatal!("tags cannot be exported to {}", format),
+                };
+                match written {
+                    Ok(sidecar) =>
+                        if !self.quiet {
+                            println!(
+                                "{} {} {}",
+                                fmt_path(path, self.base_color, self.ls_colors),
+                                "=>".bold(),
+                                sidecar.display().to_string().green()
+                            );
+                        },
+                    Err(e) => {
+                        diagnostics::warn();
+                        wutag_error!("{:#} {}", e, bold_entry!(path));
+                    },
+                }
+            },
+        );
+    }
+}
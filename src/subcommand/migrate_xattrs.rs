@@ -0,0 +1,75 @@
+//! Rewrite the tags of files in another layout of extended attributes (see
+//! [`XattrLayout`]). Tags are read from both layouts, so this is only needed to
+//! let older versions of `wutag`, which only read the legacy layout, see tags
+//! written in the single layout, or to free the names of attributes taken by
+//! the legacy layout
+
+use super::{
+    uses::{
+        bold_entry, fmt_path, glob_builder, reg_ok, regex_builder, wutag_warning, Arc, Args,
+        Colorize, XattrLayout,
+    },
+    App,
+};
+
+use crate::mounts;
+use wutag_core::tag::convert_layout;
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct MigrateXattrsOpts {
+    /// Layout to rewrite the tags in (default: 'xattr_layout')
+    #[clap(
+        long,
+        value_name = "layout",
+        possible_values = &["legacy", "single"],
+        long_about = "Layout to rewrite the tags in: 'legacy', with an attribute per tag, or \
+                      'single', with every tag of a file in one attribute. Defaults to \
+                      'xattr_layout' in the configuration, where 'auto' is 'single'"
+    )]
+    pub(crate) to:      Option<XattrLayout>,
+    /// A glob pattern like "*.png" (or regex)
+    pub(crate) pattern: String,
+}
+
+impl App {
+    /// Rewrite the tags of the files that match the pattern in another layout
+    pub(crate) fn migrate_xattrs(&self, opts: &MigrateXattrsOpts) {
+        log::debug!("MigrateXattrsOpts: {:#?}", opts);
+
+        let layout = match opts.to.unwrap_or(self.xattr_layout) {
+            XattrLayout::Auto => XattrLayout::Single,
+            layout => layout,
+        };
+        let pat = if self.pat_regex {
+            String::from(&opts.pattern)
+        } else {
+            glob_builder(&opts.pattern)
+        };
+
+        let re = regex_builder(&pat, self.case_insensitive, self.case_sensitive);
+        log::debug!("Compiled pattern: {}", re);
+        reg_ok(
+            &Arc::new(re),
+            &Arc::new(self.clone()),
+            |entry: &ignore::DirEntry| {
+                let path = entry.path();
+                match convert_layout(path, layout) {
+                    Ok(true) =>
+                        if !self.quiet {
+                            println!(
+                                "{} {} {}",
+                                fmt_path(path, self.base_color, self.ls_colors),
+                                "=>".bold(),
+                                layout.to_string().green()
+                            );
+                        },
+                    Ok(false) => log::debug!("no tags to move: {}", path.display()),
+                    Err(e) => {
+                        mounts::record_error(path, &e);
+                        wutag_warning!("{} {}", mounts::describe(path, &e), bold_entry!(path));
+                    },
+                }
+            },
+        );
+    }
+}
@@ -1,52 +1,125 @@
+#[cfg(feature = "auth")]
+pub(crate) mod auth;
 pub(crate) mod clean_cache;
 pub(crate) mod clear;
+pub(crate) mod complete;
 pub(crate) mod cp;
+pub(crate) mod debug;
+pub(crate) mod dirs;
 pub(crate) mod edit;
+pub(crate) mod env;
+pub(crate) mod expire;
+pub(crate) mod explain_config;
+pub(crate) mod git_sync;
+pub(crate) mod hook;
 pub(crate) mod info;
+pub(crate) mod lint;
 pub(crate) mod list;
+#[cfg(feature = "media")]
+pub(crate) mod media;
+pub(crate) mod migrate_xattrs;
+pub(crate) mod preserve;
 pub(crate) mod print_completions;
+#[cfg(feature = "encrypt-gpgme")]
+pub(crate) mod recipients;
+pub(crate) mod recover;
+pub(crate) mod reg;
 pub(crate) mod repair;
+pub(crate) mod repl;
 pub(crate) mod rm;
 pub(crate) mod search;
+pub(crate) mod serve;
+pub(crate) mod service;
 pub(crate) mod set;
+pub(crate) mod snapshot;
+pub(crate) mod stash;
+pub(crate) mod sync;
+pub(crate) mod tokens;
+pub(crate) mod top;
 pub(crate) mod uses;
 pub(crate) mod view;
+pub(crate) mod which;
 
 // TODO: Virtual filesystem
 // TODO: Repair database
 // TODO: Finish info command
 // TODO: imply tags
+//   - `imply graph` to audit the implications: `--dot` for Graphviz and an
+//     ASCII tree otherwise, including the transitive closure of each tag and
+//     the cycles that were rejected when adding an implication
+//   - Scoped implications that only apply under a path prefix or to files
+//     matching a pattern (e.g., under `~/music`, `flac` implies `lossless`).
+//     Store the scope with the implication and check it against the file's
+//     path when resolving its implied tags
 // TODO: rename tags
 // TODO: merge tags
 // TODO: tag value attributes
 
 use uses::{
-    env, parse_color, parse_color_cli_table, registry, ui, wutag_error, wutag_fatal, Color,
-    Colorize, Command, Config, Context, EncryptConfig, FileTypes, Opts, PathBuf, RegexSet,
-    RegexSetBuilder, Result, Stream, TagRegistry, DEFAULT_BASE_COLOR, DEFAULT_BORDER_COLOR,
-    DEFAULT_COLORS,
+    fmt_tag_icon, parse_color, parse_color_cli_table, registry, resolve_color_when,
+    set_color_override, ui, wutag_error, wutag_fatal, wutag_info, wutag_warning, xattr, Color,
+    ColorChoice, Colorize, Command, Config, Context, EncryptConfig, EntryData, EntryId, FileTypes,
+    GlobEngine, Hashing, Highlight, IndexMap, MetadataFilter, NotifyConfig, Opts, PathBuf,
+    Porcelain, RegexSet, RegexSetBuilder, Result, SortOrder, StripPrefixes, StyledTag, Tag,
+    TagRegistry, XattrLayout, XattrNamespace, DEFAULT_BASE_COLOR, DEFAULT_BORDER_COLOR,
+    DEFAULT_COLORS, DEFAULT_HIGHLIGHT, DEFAULT_MAX_FILES_PER_TAG, DEFAULT_MAX_SNAPSHOTS,
+    DEFAULT_MAX_TAGS_PER_FILE, DEFAULT_MRU_HALF_LIFE, DEFAULT_QUICK_HASH_SIZE,
 };
 
+use crate::{
+    journal,
+    messages::{self, Message},
+    mounts,
+    registry::ENTRY_PAGE_SIZE,
+};
+use std::{collections::BTreeSet, path::Path};
+
 #[derive(Clone, Debug)]
 pub(crate) struct App {
-    pub(crate) base_color:       Color,
-    pub(crate) base_dir:         PathBuf,
-    pub(crate) border_color:     cli_table::Color,
-    pub(crate) case_insensitive: bool,
-    pub(crate) case_sensitive:   bool,
-    pub(crate) color_when:       String,
-    pub(crate) colors:           Vec<Color>,
-    pub(crate) exclude:          Vec<String>,
-    pub(crate) extension:        Option<RegexSet>,
-    pub(crate) file_type:        Option<FileTypes>,
-    pub(crate) format:           String,
-    pub(crate) global:           bool,
-    pub(crate) ignores:          Option<Vec<String>>,
-    pub(crate) ls_colors:        bool,
-    pub(crate) max_depth:        Option<usize>,
-    pub(crate) quiet:            bool,
-    pub(crate) pat_regex:        bool,
-    pub(crate) registry:         TagRegistry,
+    pub(crate) base_color:         Color,
+    pub(crate) base_dir:           PathBuf,
+    pub(crate) border_color:       cli_table::Color,
+    pub(crate) case_insensitive:   bool,
+    pub(crate) case_sensitive:     bool,
+    pub(crate) check_plurals:      bool,
+    pub(crate) color_when:         String,
+    pub(crate) colors:             Vec<Color>,
+    pub(crate) default_sort:       SortOrder,
+    pub(crate) default_tags:       Vec<String>,
+    pub(crate) default_values:     IndexMap<String, String>,
+    pub(crate) exclude:            Vec<String>,
+    pub(crate) exclusive_groups:   IndexMap<String, Vec<String>>,
+    pub(crate) expire_replacement: Option<String>,
+    pub(crate) extension:          Option<RegexSet>,
+    pub(crate) file_type:          Option<FileTypes>,
+    pub(crate) filesystems:        mounts::Filesystems,
+    pub(crate) follow_symlinks:    bool,
+    pub(crate) format:             String,
+    pub(crate) global:             bool,
+    pub(crate) highlight:          Option<Highlight>,
+    pub(crate) icons:              IndexMap<String, String>,
+    pub(crate) ignores:            Option<Vec<String>>,
+    pub(crate) ls_colors:          bool,
+    pub(crate) max_depth:          Option<usize>,
+    pub(crate) max_files_per_tag:  usize,
+    pub(crate) max_snapshots:      usize,
+    pub(crate) max_tags_per_file:  usize,
+    pub(crate) metadata_filter:    Option<MetadataFilter>,
+    pub(crate) mru_half_life:      f64,
+    pub(crate) no_icons:           bool,
+    pub(crate) notify:             NotifyConfig,
+    pub(crate) quiet:              bool,
+    pub(crate) pat_regex:          bool,
+    pub(crate) per_user:           bool,
+    pub(crate) porcelain:          Option<Porcelain>,
+    pub(crate) registry:           TagRegistry,
+    pub(crate) registries:         IndexMap<String, PathBuf>,
+    pub(crate) remote_host:        Option<String>,
+    pub(crate) show_duplicates:    bool,
+    pub(crate) verbose:            bool,
+    pub(crate) verify_writes:      bool,
+    pub(crate) with_style:         bool,
+    pub(crate) xattr_layout:       XattrLayout,
 
     #[cfg(feature = "encrypt-gpgme")]
     pub(crate) encrypt: EncryptConfig,
@@ -54,7 +127,54 @@ pub(crate) struct App {
 
 impl App {
     /// Run the application
-    pub(crate) fn run(opts: Opts, config: &Config) -> Result<()> {
+    pub(crate) fn run(mut opts: Opts, config: &Config) -> Result<()> {
+        // The configuration is explained before anything is loaded, so that it
+        // can be checked when the registry cannot be opened
+        if let Command::ExplainConfig(ref explain) = opts.cmd {
+            explain_config::explain_config(explain, &opts, config);
+            return Ok(());
+        }
+
+        // The files that `set`, `rm` and `clear` change are routed to the
+        // registries of the drives they are on
+        let drives = match opts.cmd {
+            _ if opts.reg.is_some() => Vec::new(),
+            Command::Set(_) | Command::Rm(_) | Command::Clear(_) => config
+                .filesystem_registries
+                .as_ref()
+                .map(mounts::mounted_devices)
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        };
+
+        // The other commands use the registry of the filesystem of the directory
+        // before the one from the configuration
+        if opts.reg.is_none() && drives.is_empty() {
+            opts.reg = config
+                .filesystem_registries
+                .as_ref()
+                .and_then(|registries| {
+                    let dir = opts.dir.clone().or_else(|| std::env::current_dir().ok())?;
+                    mounts::filesystem_registry(&dir, registries)
+                })
+                .or_else(|| config.registry.as_ref().map(PathBuf::from));
+        }
+
+        // The encryption flags take precedence over the configuration
+        #[cfg(feature = "encrypt-gpgme")]
+        let config = &Config {
+            encryption: EncryptConfig {
+                tty: config.encryption.tty || opts.gpg_tty,
+                pinentry_mode: opts.pinentry_mode.or(config.encryption.pinentry_mode),
+                ..config.encryption.clone()
+            },
+            ..config.clone()
+        };
+
+        if !drives.is_empty() {
+            return Self::run_routed(opts, config, drives);
+        }
+
         let mut app = Self::new(&opts, config.clone())?;
         log::trace!("CONFIGURATION FILE: {:#?}", config);
         log::trace!("CONFIGURATION RESULT: {:#?}", app);
@@ -63,6 +183,50 @@ impl App {
         Ok(())
     }
 
+    /// Run the command once for the files on each of the mounted `drives`
+    /// (registry, mount point and device) with the registry of the drive, and
+    /// once for the remaining files with the registry of the configuration. A
+    /// drive is skipped when the command cannot reach its files
+    fn run_routed(opts: Opts, config: &Config, drives: Vec<(PathBuf, PathBuf, u64)>) -> Result<()> {
+        let dir = match opts.dir {
+            Some(ref dir) => dir.clone(),
+            None =>
+                std::env::current_dir().context("failed to determine current working directory")?,
+        };
+        let dir = std::fs::canonicalize(&dir).unwrap_or(dir);
+        let anywhere = opts.global
+            || matches!(
+                opts.cmd,
+                Command::Set(ref set) if set.from_csv.is_some() || set::paths_given(set)
+            );
+
+        let mut runs = vec![(
+            config.registry.as_ref().map(PathBuf::from),
+            mounts::Filesystems::Except(drives.iter().map(|(_, _, dev)| *dev).collect()),
+        )];
+        runs.extend(
+            drives
+                .into_iter()
+                .filter(|(_, mount, _)| {
+                    anywhere || mount.starts_with(&dir) || dir.starts_with(mount)
+                })
+                .map(|(registry, _, dev)| (Some(registry), mounts::Filesystems::Only(dev))),
+        );
+
+        for (registry, filesystems) in runs {
+            let opts = Opts {
+                reg: registry,
+                ..opts.clone()
+            };
+            let mut app = Self::new(&opts, config.clone())?;
+            app.filesystems = filesystems;
+            log::debug!("Routing {:?} to {}", app.filesystems, app.registry.path.display());
+            app.run_command(opts, config)?;
+        }
+
+        Ok(())
+    }
+
     /// Create a new instance of the application
     pub(crate) fn new(opts: &Opts, config: Config) -> Result<App> {
         let base_dir = if let Some(base_dir) = &opts.dir {
@@ -91,32 +255,36 @@ impl App {
             .transpose()?
             .unwrap_or(DEFAULT_BASE_COLOR);
 
+        let highlight = match config.highlight.as_deref().unwrap_or(DEFAULT_HIGHLIGHT) {
+            "none" => None,
+            style => Some(style.parse::<Highlight>().map_err(|e| anyhow::anyhow!(e))?),
+        };
+
         let border_color = config
             .border_color
             .map(parse_color_cli_table)
             .transpose()?
             .unwrap_or(DEFAULT_BORDER_COLOR);
 
-        let color_when = match opts.color_when {
-            Some(ref s) if s == "always" => "always",
-            Some(ref s) if s == "never" => "never",
-            _ =>
-                if env::var_os("NO_COLOR").is_none() && atty::is(Stream::Stdout) {
-                    "auto"
-                } else {
-                    "never"
-                },
+        let porcelain = if opts.plain {
+            Some(Porcelain::Plain)
+        } else {
+            opts.porcelain
+                .as_ref()
+                .or_else(|| opts.output.as_ref())
+                .map(|p| p.parse::<Porcelain>())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!(e))?
         };
 
+        let color_when = resolve_color_when(opts.color_when.as_deref(), porcelain.is_some());
+
         let format = if let Some(format_) = config.format {
             {
                 match format_.as_ref() {
                     f @ ("toml" | "yaml" | "yml" | "json") => f,
                     _ => {
-                        wutag_error!(
-                            "invalid format found as your configuration. Valid values: toml, \
-                             yaml, yml, json. Using the default: toml"
-                        );
+                        wutag_error!("{}", messages::text(Message::InvalidFormat));
                         "toml"
                     },
                 }
@@ -126,6 +294,15 @@ impl App {
             "toml".to_string()
         };
 
+        Hashing {
+            algorithm: config.hash_algorithm.unwrap_or_default(),
+            quick:     (opts.quick_hash || config.quick_hash)
+                .then(|| config.quick_hash_size.unwrap_or(DEFAULT_QUICK_HASH_SIZE)),
+        }
+        .init();
+        config.glob_engine.unwrap_or_default().init();
+        StripPrefixes::new(config.strip_prefix.clone().unwrap_or_default()).init();
+
         let registry = registry::load_registry(opts, &config.encryption)?;
 
         let extensions = opts
@@ -174,35 +351,121 @@ impl App {
         });
         log::debug!("FileTypes: {:#?}", file_types);
 
+        let metadata_filter = Some(MetadataFilter {
+            sizes: opts.size.clone().unwrap_or_default(),
+            owner: opts.owner,
+            perm:  opts.perm,
+        })
+        .filter(|filter| !filter.is_empty());
+
+        // The registries of the drives that are mounted are searched along with
+        // the others
+        let mut registries = config.registries.unwrap_or_default();
+        for (uuid, path) in config
+            .filesystem_registries
+            .as_ref()
+            .map(mounts::mounted_registries)
+            .into_iter()
+            .flatten()
+        {
+            registries.entry(uuid).or_insert(path);
+        }
+
         Ok(App {
             base_color,
             base_dir,
             border_color,
             case_insensitive: opts.case_insensitive,
             case_sensitive: opts.case_sensitive,
+            check_plurals: config.check_plurals,
             color_when: color_when.to_string(),
             colors,
+            default_sort: config.default_sort.unwrap_or_default(),
+            default_tags: config.default_tags.unwrap_or_default(),
+            default_values: config.default_values.unwrap_or_default(),
             exclude: excludes,
+            exclusive_groups: config.exclusive_groups.unwrap_or_default(),
+            expire_replacement: config.expire_replacement,
             extension: extensions,
             file_type: file_types,
+            filesystems: mounts::Filesystems::All,
+            follow_symlinks: config.follow_symlinks.unwrap_or(false),
             format,
             global: opts.global,
+            highlight,
+            icons: config.icons.unwrap_or_default(),
             ignores: config.ignores,
-            ls_colors: opts.ls_colors,
+            ls_colors: opts.ls_colors && color_when != "never",
             max_depth: if opts.max_depth.is_some() {
                 opts.max_depth
             } else {
                 config.max_depth
             },
+            max_files_per_tag: config
+                .max_files_per_tag
+                .unwrap_or(DEFAULT_MAX_FILES_PER_TAG),
+            max_snapshots: config.max_snapshots.unwrap_or(DEFAULT_MAX_SNAPSHOTS),
+            max_tags_per_file: config
+                .max_tags_per_file
+                .unwrap_or(DEFAULT_MAX_TAGS_PER_FILE),
+            metadata_filter,
+            mru_half_life: config.mru_half_life.unwrap_or(DEFAULT_MRU_HALF_LIFE),
+            no_icons: opts.no_icons || opts.plain || config.no_icons,
+            notify: config.notify,
             pat_regex: opts.regex,
+            per_user: opts.per_user || config.per_user,
+            porcelain,
             quiet: opts.quiet,
             registry,
+            registries,
+            remote_host: opts.remote.clone(),
+            show_duplicates: opts.show_duplicates || config.show_duplicates,
+            verbose: opts.verbose > 0,
+            verify_writes: config.verify_writes,
+            with_style: opts.with_style,
+            xattr_layout: config.xattr_layout.unwrap_or_default(),
 
             #[cfg(any(feature = "encrypt-gpgme"))]
             encrypt: config.encryption,
         })
     }
 
+    /// Return the tags that are mutually exclusive with the given tag
+    pub(crate) fn exclusive_tags(&self, tag: &str) -> Vec<&String> {
+        self.exclusive_groups
+            .values()
+            .filter(|group| group.iter().any(|t| t == tag))
+            .flatten()
+            .filter(|t| *t != tag)
+            .collect()
+    }
+
+    /// The entries of the registry ordered by path, unless `default_sort` is
+    /// `registry`. Entries with the same path stay in the order of their ids
+    pub(crate) fn sorted_entries(&self) -> Vec<(&EntryId, &EntryData)> {
+        let mut entries = self.registry.list_entries_and_ids().collect::<Vec<_>>();
+        if self.default_sort != SortOrder::Registry {
+            entries.sort_by(|a, b| a.1.path().cmp(b.1.path()));
+        }
+        entries
+    }
+
+    /// The entries in the same order as [`sorted_entries`](Self::sorted_entries)
+    /// without collecting them when they are kept in the order of the registry,
+    /// which walks it a page of [`ENTRY_PAGE_SIZE`] entries at a time. Ordering
+    /// by path needs every entry, though only references to them are collected
+    pub(crate) fn paged_entries(&self) -> Box<dyn Iterator<Item = (EntryId, &EntryData)> + '_> {
+        if self.default_sort == SortOrder::Registry {
+            Box::new(self.registry.entry_pages(ENTRY_PAGE_SIZE).flatten())
+        } else {
+            Box::new(
+                self.sorted_entries()
+                    .into_iter()
+                    .map(|(id, entry)| (*id, entry)),
+            )
+        }
+    }
+
     /// Save the `TagRegistry` after modifications
     pub(crate) fn save_registry(&mut self) {
         if let Err(e) = self.registry.save() {
@@ -210,35 +473,284 @@ impl App {
         }
     }
 
+    /// The tags of the file at `path` that were set with `set --encrypt`, with
+    /// `None` for each one that none of the private keys can decrypt
+    pub(crate) fn locked_tags(&self, path: &Path) -> Vec<Option<Tag>> {
+        #[cfg(feature = "encrypt-gpgme")]
+        {
+            crate::encryption::payload::locked_tags(path, &self.encrypt)
+        }
+        #[cfg(not(feature = "encrypt-gpgme"))]
+        {
+            let _ = path;
+            Vec::new()
+        }
+    }
+
+    /// Names of the tags of the file with `id` at `path`, including the ones set
+    /// with `set --encrypt` that one of the private keys can decrypt
+    pub(crate) fn tag_names(&self, id: EntryId, path: &Path) -> BTreeSet<String> {
+        self.registry
+            .list_entry_tags(id)
+            .unwrap_or_default()
+            .into_iter()
+            .chain(self.locked_tags(path).iter().flatten())
+            .map(|tag| tag.name().to_owned())
+            .collect()
+    }
+
+    /// The encrypted tags of the file at `path` as they are displayed after its
+    /// other tags, which is `[locked]` for each one that cannot be decrypted
+    pub(crate) fn fmt_locked_tags(&self, path: &Path, raw: bool) -> Vec<String> {
+        self.locked_tags(path)
+            .iter()
+            .map(|tag| match tag {
+                Some(tag) if raw => tag.name().to_owned(),
+                Some(tag) => fmt_tag_icon(tag, self.tag_icon(tag)),
+                None if raw => String::from("[locked]"),
+                None => "[locked]".dimmed().to_string(),
+            })
+            .collect()
+    }
+
+    /// Return the icon displayed before the `tag`. An icon set with `edit
+    /// --icon` takes precedence over one from the configuration
+    pub(crate) fn tag_icon(&self, tag: &Tag) -> Option<&str> {
+        if self.no_icons {
+            return None;
+        }
+
+        self.registry
+            .get_tag_icon(tag.name())
+            .or_else(|| self.icons.get(tag.name()).map(String::as_str))
+    }
+
+    /// Warn about each of the `tags` given on the command line that is not a tag
+    /// but is similar to some. The names are kept as they were typed, since a
+    /// tag set with `set --encrypt` is only on the files
+    pub(crate) fn warn_similar_tags(&self, tags: &[String]) {
+        for name in tags {
+            if self.registry.get_tag(name).is_some() {
+                continue;
+            }
+
+            let similar = self.registry.similar_tags(name);
+            if !similar.is_empty() {
+                wutag_warning!(
+                    "{} is not a tag, did you mean {}?",
+                    name.bold(),
+                    similar
+                        .iter()
+                        .map(|tag| tag.name().bold().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
+    }
+
+    /// The `tags` given on the command line, with each name that is not a tag
+    /// replaced by the most similar tag when `fuzzy` is given. Otherwise, a
+    /// name that is not a tag but is similar to some is an error that suggests
+    /// them
+    pub(crate) fn correct_tags(&self, tags: &[String], fuzzy: bool) -> Vec<String> {
+        tags.iter()
+            .map(|name| {
+                if self.registry.get_tag(name).is_some() {
+                    return name.clone();
+                }
+
+                let similar = self.registry.similar_tags(name);
+                match similar.first() {
+                    Some(tag) if fuzzy => {
+                        if !self.quiet {
+                            wutag_info!(
+                                "{} is not a tag, using {}",
+                                name.bold(),
+                                tag.name().bold()
+                            );
+                        }
+                        tag.name().to_owned()
+                    },
+                    Some(_) => wutag_fatal!(
+                        "{} is not a tag, did you mean {}? ('--fuzzy' uses the closest one)",
+                        name.bold(),
+                        similar
+                            .iter()
+                            .map(|tag| tag.name().bold().to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                    None => name.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// The `tag` in a record of the porcelain output, which is displayed with
+    /// its style when `--with-style` is given
+    pub(crate) fn styled_tag<'a>(&'a self, tag: &'a Tag) -> StyledTag<'a> {
+        StyledTag {
+            tag,
+            style: self.with_style,
+            icon: self.tag_icon(tag),
+            value: None,
+        }
+    }
+
+    /// The `tags` of the entry `id` in a record of the porcelain output, like
+    /// [`App::styled_tag`] along with their values
+    pub(crate) fn styled_tags<'a, I>(&'a self, id: EntryId, tags: I) -> Vec<StyledTag<'a>>
+    where
+        I: IntoIterator<Item = &'a Tag>,
+    {
+        tags.into_iter()
+            .map(|tag| StyledTag {
+                value: self.registry.get_value(tag.name(), id),
+                ..self.styled_tag(tag)
+            })
+            .collect()
+    }
+
+    /// How tables are colored, following `--color`
+    pub(crate) fn color_choice(&self) -> ColorChoice {
+        match self.color_when.as_ref() {
+            "always" => ColorChoice::Always,
+            "never" => ColorChoice::Never,
+            _ => ColorChoice::Auto,
+        }
+    }
+
+    /// Keep tags in the namespace of extended attributes that was `requested`,
+    /// or else in the one recorded in the registry. A registry keeps the tags
+    /// of all of its files in a single namespace
+    fn select_namespace(&mut self, requested: Option<XattrNamespace>) {
+        let recorded = self.registry.namespace;
+        let namespace = requested.unwrap_or(recorded);
+        if namespace == XattrNamespace::Trusted && wutag_core::current_uid() != 0 {
+            if requested.is_some() {
+                wutag_fatal!("only root can keep tags in the 'trusted' namespace");
+            }
+            wutag_warning!(
+                "the tags of this registry are kept in the 'trusted' namespace, which only root \
+                 can read"
+            );
+        }
+
+        if namespace != recorded {
+            if !self.registry.entries.is_empty() {
+                wutag_fatal!(
+                    "the registry keeps tags in the '{}' namespace, use another registry \
+                     ('--registry') for the '{}' namespace",
+                    recorded,
+                    namespace
+                );
+            }
+            self.registry.namespace = namespace;
+        }
+        log::debug!("Using xattr namespace: {}", namespace);
+        wutag_core::set_xattr_namespace(namespace);
+    }
+
     /// Run the subcommand from the command-line
     pub(crate) fn run_command(&mut self, opts: Opts, config: &Config) -> Result<()> {
-        if self.color_when == "never" {
-            colored::control::SHOULD_COLORIZE.set_override(false);
-        } else if self.color_when == "always" {
-            colored::control::SHOULD_COLORIZE.set_override(true);
+        set_color_override(&self.color_when);
+
+        // The files on the other machine can only be given on stdin, since the
+        // pattern and the other ways to give files find them on this one
+        if let Some(ref host) = self.remote_host {
+            if !matches!(
+                opts.cmd,
+                Command::Set(ref set) if set.stdin && set.from_csv.is_none() && !set.git_modified
+            ) {
+                wutag_fatal!("{} only applies to 'set --stdin'", "--remote".bold());
+            }
+            xattr::set_remote_host(host);
+        }
+
+        // Only `set` and `cp` can choose the namespace that tags are kept in
+        match opts.cmd {
+            Command::Set(ref opts) => self.select_namespace(opts.namespace),
+            Command::Cp(ref opts) => self.select_namespace(opts.namespace),
+            _ => self.select_namespace(None),
+        }
+        if self.per_user {
+            wutag_core::set_user_namespace(wutag_core::current_uid());
         }
+        wutag_core::set_xattr_layout(self.xattr_layout);
 
+        // `set` and `search` can override whether symlinks are followed
         match opts.cmd {
-            Command::CleanCache => self.clean_cache(),
+            Command::Set(ref opts) if opts.follow || opts.no_follow =>
+                self.follow_symlinks = opts.follow,
+            Command::Search(ref opts) if opts.follow || opts.no_follow =>
+                self.follow_symlinks = opts.follow,
+            _ => {},
+        }
+        xattr::set_follow_symlinks(self.follow_symlinks);
+
+        if !matches!(opts.cmd, Command::Recover(_))
+            && !journal::pending(&self.registry.path).is_empty()
+        {
+            wutag_warning!(
+                "a previous batch did not finish, the tags of its files may not match the \
+                 registry. Use 'wutag recover' to replay it or 'wutag recover --rollback' to undo \
+                 it"
+            );
+        }
+
+        match opts.cmd {
+            #[cfg(feature = "auth")]
+            Command::Auth(ref opts) => self.auth(opts),
+            Command::CleanCache(ref opts) => self.clean_cache(opts),
+            Command::CheckStaged(ref opts) => self.check_staged(opts),
             Command::Clear(ref opts) => self.clear(opts),
+            Command::Complete(ref opts) => self.complete(opts),
             Command::Cp(ref opts) => self.cp(opts)?,
+            Command::Debug(ref opts) => self.debug(opts),
+            Command::Dirs(ref opts) => self.dirs(opts),
             Command::Edit(ref opts) => self.edit(opts),
-            Command::Info(ref opts) => self.info(opts),
+            Command::Env(ref opts) => self.env(opts, config),
+            Command::Expire(ref opts) => self.expire(opts),
+            Command::ExplainConfig(_) => unreachable!("explained in `App::run`"),
+            #[cfg(feature = "media")]
+            Command::Export(ref opts) => self.export(opts),
+            Command::GitSync(ref opts) => self.git_sync(opts),
+            Command::Hook(ref opts) => self.hook(opts),
+            #[cfg(feature = "media")]
+            Command::Import(ref opts) => self.import(opts),
+            Command::Info(ref opts) => self.info(opts, config),
+            Command::Lint(ref opts) => self.lint(opts),
             Command::List(ref opts) => self.list(opts),
+            Command::MigrateXattrs(ref opts) => self.migrate_xattrs(opts),
+            Command::Preserve(ref opts) => self.preserve(opts),
             Command::PrintCompletions(ref opts) => self.print_completions(opts),
+            #[cfg(feature = "encrypt-gpgme")]
+            Command::Recipients(ref opts) => self.recipients(opts),
+            Command::Recover(ref opts) => self.recover(opts),
+            Command::Registry(ref opts) => self.manage_registry(opts),
             Command::Repair(ref opts) => self.repair(opts)?,
+            Command::Repl => self.repl(),
             Command::Rm(ref opts) => self.rm(opts),
             Command::Search(ref opts) => self.search(opts),
+            Command::Serve(ref opts) => self.serve(opts, config),
+            Command::Service(ref opts) => self.service(opts),
+            Command::Sync(ref opts) => self.sync(opts),
             Command::Set(opts) => self.set(&opts)?,
+            Command::Snapshot(ref opts) => self.snapshot(opts),
+            Command::Stash(ref opts) => self.stash(opts),
+            Command::Tokens => self.tokens(),
+            Command::Top(ref opts) => self.top(opts),
             Command::View(ref opts) => self.view(opts)?,
+            Command::Which(ref opts) => self.which(opts),
             Command::Ui => {
                 better_panic::install();
-                if let Err(e) = ui::start_ui(
-                    &self.clone(),
-                    config.clone(),
-                    registry::load_registry(&opts, &config.encryption)
-                        .expect("unable to get tag registry"),
-                ) {
+                let registry = registry::load_registry(&opts, &config.encryption)
+                    .expect("unable to get tag registry");
+                let mut config = config.clone();
+                config.no_icons |= self.no_icons;
+
+                if let Err(e) = ui::start_ui(&self.clone(), config, registry) {
                     ui::destruct_terminal();
                     wutag_fatal!("{}", e);
                 }
@@ -253,7 +765,10 @@ impl App {
 
     /// Encryption command to run after every subcommand
     pub(crate) fn handle_encryption(&self) {
-        if self.encrypt.to_encrypt && !registry::is_encrypted(&self.registry.path) {
+        if self.encrypt.to_encrypt
+            && self.registry.remote().is_none()
+            && !registry::is_encrypted(&self.registry.path)
+        {
             log::debug!("Attempting to encrypt registry");
             if let Err(e) = TagRegistry::crypt_registry(&self.registry.path, &self.encrypt, true) {
                 wutag_fatal!("{}", e);
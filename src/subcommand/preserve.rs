@@ -0,0 +1,271 @@
+//! Run a command that copies or moves files, such as `cp` or `install`, and
+//! then copy the tags of each source to its destination, since most of these
+//! tools do not preserve extended attributes. The destinations are taken from
+//! the verbose output of the command (`'src' -> 'dst'`) when there is one, and
+//! are otherwise inferred from its arguments
+
+// TODO: detect the files the command writes with fanotify, so that commands
+//       whose arguments and output cannot be parsed are supported as well
+
+use super::{
+    uses::{
+        diagnostics, fmt_path, fmt_tag, fs, io, list_tags, process, wutag_error, wutag_fatal,
+        wutag_warning, Args, BTreeMap, BufRead, BufReader, Colorize, EntryData, Lexiclean, PathBuf,
+        Regex, Tag,
+    },
+    App,
+};
+
+use once_cell::sync::Lazy;
+use std::path::Path;
+
+/// Options of `cp`, `mv`, and `install` that take a value as the next argument
+const VALUE_FLAGS: &[&str] = &[
+    "-S", "--suffix", "-m", "--mode", "-o", "--owner", "-g", "--group",
+];
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct PreserveOpts {
+    /// The command to run, given after '--' (e.g., 'cp -r src dst')
+    #[clap(
+        last = true,
+        required = true,
+        value_name = "command",
+        allow_hyphen_values = true
+    )]
+    pub(crate) command: Vec<String>,
+}
+
+/// Parse a line of verbose output, such as `'src' -> 'dst'` from `cp -v` or
+/// `renamed 'src' -> 'dst'` from `mv -v`
+fn parse_verbose_line(line: &str) -> Option<(PathBuf, PathBuf)> {
+    static VERBOSE_PATTERN: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"'([^']+)' -> '([^']+)'").unwrap());
+
+    VERBOSE_PATTERN
+        .captures(line)
+        .map(|caps| (PathBuf::from(&caps[1]), PathBuf::from(&caps[2])))
+}
+
+/// Split the arguments of a command like `cp` into the sources and the
+/// destination. The destination is a directory that the sources are copied
+/// into when it is given with `-t`, which is returned as `true`
+fn parse_copy_args(args: &[String]) -> (Vec<PathBuf>, Option<(PathBuf, bool)>) {
+    let mut paths = Vec::new();
+    let mut target = None;
+    let mut options = true;
+    let mut args = args.iter().skip(1);
+
+    while let Some(arg) = args.next() {
+        if !options || !arg.starts_with('-') || arg == "-" {
+            paths.push(PathBuf::from(arg));
+        } else if arg == "--" {
+            options = false;
+        } else if arg == "-t" || arg == "--target-directory" {
+            target = args.next().map(|dir| (PathBuf::from(dir), true));
+        } else if let Some(dir) = arg.strip_prefix("--target-directory=") {
+            target = Some((PathBuf::from(dir), true));
+        } else if VALUE_FLAGS.contains(&arg.as_str()) {
+            args.next();
+        }
+    }
+
+    if target.is_none() && paths.len() > 1 {
+        target = paths.pop().map(|dest| (dest, false));
+    }
+
+    (paths, target)
+}
+
+/// The absolute path of `path`, which does not have to exist
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| {
+        std::env::current_dir()
+            .map_or_else(|_| path.to_path_buf(), |cwd| cwd.join(path))
+            .lexiclean()
+    })
+}
+
+impl App {
+    /// The tags of the files that are the `sources` or are inside of them
+    fn source_tags(&self, sources: &[PathBuf]) -> BTreeMap<PathBuf, Vec<Tag>> {
+        let mut found = BTreeMap::new();
+
+        for source in sources {
+            for (id, entry) in self.registry.list_entries_and_ids() {
+                if entry.path().starts_with(source) {
+                    let tags = self.registry.list_entry_tags(*id).unwrap_or_default();
+                    found.insert(
+                        entry.path().to_path_buf(),
+                        tags.into_iter().cloned().collect(),
+                    );
+                }
+            }
+
+            if !found.contains_key(source) {
+                if let Some(tags) = list_tags(source).ok().filter(|t| !t.is_empty()) {
+                    found.insert(source.clone(), tags);
+                }
+            }
+        }
+
+        found
+    }
+
+    /// Copy the tags of `source` (and its values in the registry) to `dest`
+    fn preserve_tags(&mut self, source: &Path, dest: &Path, tags: &[Tag]) {
+        let entry = match EntryData::new(dest) {
+            Ok(data) => data,
+            Err(e) => {
+                wutag_warning!("{} {}", e, dest.display());
+                return;
+            },
+        };
+        let source_id = self.registry.find_entry(source);
+        let id = self.registry.add_or_update_entry(entry);
+
+        if !self.quiet {
+            println!("{}:", fmt_path(dest, self.base_color, self.ls_colors));
+        }
+
+        for tag in tags {
+            match tag.save_to(dest) {
+                Ok(()) | Err(wutag_core::Error::TagExists(_)) => {},
+                Err(e) => {
+                    wutag_error!("{} {}", e, dest.display());
+                    continue;
+                },
+            }
+            self.registry.tag_entry(tag, id);
+
+            let value = source_id
+                .and_then(|sid| self.registry.get_value(tag.name(), sid))
+                .map(ToOwned::to_owned);
+            if let Some(value) = value {
+                self.registry.set_value(tag, id, value);
+            }
+            if !self.quiet {
+                print!("\t{} {}", "+".bold().green(), fmt_tag(tag));
+            }
+        }
+
+        if !self.quiet {
+            println!();
+        }
+
+        // The source was moved instead of copied
+        if let Some(sid) = source_id {
+            if !source.exists() {
+                self.registry.clear_entry(sid);
+            }
+        }
+    }
+
+    pub(crate) fn preserve(&mut self, opts: &PreserveOpts) {
+        log::debug!("PreserveOpts: {:#?}", opts);
+        log::debug!("Using registry: {}", self.registry.path.display());
+
+        let (sources, target) = parse_copy_args(&opts.command);
+        let sources = sources.iter().map(|s| absolute(s)).collect::<Vec<_>>();
+        let target = target.map(|(dest, into)| {
+            let into = into || (sources.len() > 1 || dest.is_dir());
+            (absolute(&dest), into)
+        });
+        let tagged = self.source_tags(&sources);
+
+        let mut child = process::Command::new(&opts.command[0])
+            .args(&opts.command[1..])
+            .stdout(process::Stdio::piped())
+            .spawn()
+            .unwrap_or_else(|e| wutag_fatal!("{}: {}", opts.command[0].bold(), e));
+
+        // Forward the output while looking for the files that were written
+        let mut moves = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            for line in BufReader::new(stdout).lines().filter_map(io::Result::ok) {
+                println!("{}", line);
+                if let Some((src, dst)) = parse_verbose_line(&line) {
+                    moves.push((absolute(&src), absolute(&dst)));
+                }
+            }
+        }
+
+        let status = child
+            .wait()
+            .unwrap_or_else(|e| wutag_fatal!("{}: {}", opts.command[0].bold(), e));
+
+        if moves.is_empty() {
+            if let Some((dest, into)) = &target {
+                for source in &sources {
+                    let dst = match source.file_name() {
+                        Some(name) if *into => dest.join(name),
+                        _ => dest.clone(),
+                    };
+                    moves.push((source.clone(), dst));
+                }
+            }
+        }
+
+        for (src, dst) in &moves {
+            for (path, tags) in tagged.iter().filter(|(p, _)| p.starts_with(src)) {
+                let dest = match path.strip_prefix(src) {
+                    Ok(rel) if rel.as_os_str().is_empty() => dst.clone(),
+                    Ok(rel) => dst.join(rel),
+                    Err(_) => continue,
+                };
+                if dest.exists() {
+                    self.preserve_tags(path, &dest, tags);
+                }
+            }
+        }
+
+        log::debug!("Saving registry...");
+        self.save_registry();
+
+        if !status.success() {
+            diagnostics::exit(status.code().unwrap_or(1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_copy_args() {
+        assert_eq!(
+            parse_copy_args(&args(&["cp", "-r", "a", "b", "dir"])),
+            (
+                vec![PathBuf::from("a"), PathBuf::from("b")],
+                Some((PathBuf::from("dir"), false))
+            )
+        );
+        assert_eq!(
+            parse_copy_args(&args(&["install", "-m", "644", "-t", "dir", "a"])),
+            (vec![PathBuf::from("a")], Some((PathBuf::from("dir"), true)))
+        );
+        assert_eq!(
+            parse_copy_args(&args(&["cp", "--", "-a", "b"])),
+            (vec![PathBuf::from("-a")], Some((PathBuf::from("b"), false)))
+        );
+        assert_eq!(parse_copy_args(&args(&["cp", "a"])).1, None);
+    }
+
+    #[test]
+    fn parses_verbose_lines() {
+        assert_eq!(
+            parse_verbose_line("renamed 'a b' -> 'dir/a b'"),
+            Some((PathBuf::from("a b"), PathBuf::from("dir/a b")))
+        );
+        assert_eq!(
+            parse_verbose_line("'a' -> 'b'"),
+            Some((PathBuf::from("a"), PathBuf::from("b")))
+        );
+        assert_eq!(parse_verbose_line("removed 'a'"), None);
+    }
+}
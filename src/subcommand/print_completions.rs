@@ -37,42 +37,50 @@ pub(crate) struct CompletionsOpts {
     pub(crate) dir:   Option<PathBuf>,
 }
 
+/// The completion script of `wutag` for the `shell`
+pub(crate) fn completion_script(shell: Shell) -> String {
+    let mut app = Opts::into_app();
+
+    let buffer = Vec::new();
+    let mut cursor = io::Cursor::new(buffer);
+
+    match shell {
+        Shell::Bash => gen_completions(Bash, &mut app, &mut cursor),
+        Shell::Elvish => gen_completions(Elvish, &mut app, &mut cursor),
+        Shell::Fish => gen_completions(Fish, &mut app, &mut cursor),
+        Shell::PowerShell => gen_completions(PowerShell, &mut app, &mut cursor),
+        Shell::Zsh => gen_completions(Zsh, &mut app, &mut cursor),
+        _ => (),
+    }
+
+    let buffer = cursor.into_inner();
+    let mut script = String::from_utf8(buffer).expect("Clap completion not UTF-8");
+
+    // Replace Zsh completion output to make it better
+    // I may work on other shells, but am very familiar with Zsh, so that is why it
+    // is the only one so far
+    if shell == Shell::Zsh {
+        for (needle, replacement) in comp_helper::ZSH_COMPLETION_REP {
+            replace(&mut script, needle, replacement)
+                .expect("Failed to replace completion script");
+        }
+    }
+
+    let mut out = script.trim().to_owned();
+    out.push('\n');
+    out
+}
+
 impl App {
     // TODO: Refactor
     #[allow(clippy::unused_self)]
     pub(crate) fn print_completions(&self, opts: &CompletionsOpts) {
         log::debug!("CompletionOpts: {:#?}", opts);
-        let mut app = Opts::into_app();
 
-        let buffer = Vec::new();
-        let mut cursor = io::Cursor::new(buffer);
-
-        match opts.shell {
-            Shell::Bash => gen_completions(Bash, &mut app, &mut cursor),
-            Shell::Elvish => gen_completions(Elvish, &mut app, &mut cursor),
-            Shell::Fish => gen_completions(Fish, &mut app, &mut cursor),
-            Shell::PowerShell => gen_completions(PowerShell, &mut app, &mut cursor),
-            Shell::Zsh => gen_completions(Zsh, &mut app, &mut cursor),
-            _ => (),
+        if opts.shell != Shell::Zsh {
+            println!();
         }
-
-        let buffer = cursor.into_inner();
-        let mut script = String::from_utf8(buffer).expect("Clap completion not UTF-8");
-
-        // Replace Zsh completion output to make it better
-        // I may work on other shells, but am very familiar with Zsh, so that is why it
-        // is the only one so far
-        match opts.shell {
-            Shell::Zsh =>
-                for (needle, replacement) in comp_helper::ZSH_COMPLETION_REP {
-                    replace(&mut script, needle, replacement)
-                        .expect("Failed to replace completion script");
-                },
-            _ => println!(),
-        }
-
-        let mut out = script.trim().to_owned();
-        out.push('\n');
+        let out = completion_script(opts.shell);
 
         if let Some(dir) = &opts.dir {
             let filename = match opts.shell {
@@ -0,0 +1,187 @@
+//! `recipients` - Manage the keys that the registry and the encrypted tags are
+//! encrypted to
+
+use super::{
+    uses::{wutag_error, wutag_fatal, wutag_warning, Args, Colorize, Context, Result, Subcommand},
+    App,
+};
+
+use crate::encryption::{
+    payload::Payload,
+    prelude::*,
+    recipients::{find_key, read_fingerprints, write_fingerprints},
+    util, Context as CryptContext, Recipients,
+};
+use wutag_core::{tag::list_locked, xattr::remove_xattr};
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub(crate) enum RecipientsObject {
+    /// Add keys to the recipients and re-encrypt to them
+    Add {
+        /// Email, short fingerprint, or full fingerprint of each key
+        #[clap(required = true)]
+        keys: Vec<String>,
+    },
+    /// Remove keys from the recipients and re-encrypt without them
+    Remove {
+        /// Email, short fingerprint, or full fingerprint of each key
+        #[clap(required = true)]
+        keys: Vec<String>,
+    },
+    /// List the recipients
+    List,
+}
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct RecipientsOpts {
+    /// The action to take. Valid values are: 'add', 'remove', 'list'
+    #[clap(subcommand)]
+    pub(crate) object: RecipientsObject,
+}
+
+impl App {
+    /// Manage the recipients of the registry
+    pub(crate) fn recipients(&mut self, opts: &RecipientsOpts) {
+        log::debug!("RecipientsOpts: {:#?}", opts);
+        log::debug!("Using registry: {}", self.registry.path.display());
+
+        if self.registry.remote().is_some() {
+            wutag_fatal!("the recipients of a remote registry must be managed where it is served");
+        }
+
+        let mut ctx = util::context(&self.encrypt)
+            .unwrap_or_else(|e| wutag_fatal!("failed to get cryptography context: {}", e));
+
+        let res = match &opts.object {
+            RecipientsObject::Add { keys } => self.change_recipients(&mut ctx, keys, true),
+            RecipientsObject::Remove { keys } => self.change_recipients(&mut ctx, keys, false),
+            RecipientsObject::List => self.list_recipients(&mut ctx),
+        };
+
+        if let Err(e) = res {
+            wutag_error!("{:#}", e);
+        }
+    }
+
+    /// Print each recipient, and whether its key is missing from the keychain
+    fn list_recipients(&self, ctx: &mut CryptContext) -> Result<()> {
+        let fingerprints =
+            read_fingerprints(&self.registry.path).context("failed to read the recipients")?;
+        if fingerprints.is_empty() {
+            if !self.quiet {
+                println!(
+                    "no recipients, encrypting to 'public_key' ({})",
+                    self.encrypt.public_key.as_deref().unwrap_or("unset")
+                );
+            }
+            return Ok(());
+        }
+
+        let keys = ctx
+            .keys_public()
+            .context("failed to list the public keys")?;
+        for fingerprint in &fingerprints {
+            match keys
+                .iter()
+                .find(|key| util::fingerprints_equal(key.fingerprint(false), fingerprint))
+            {
+                Some(key) => println!("{} {}", "+".green().bold(), key),
+                None => println!(
+                    "{} {} {}",
+                    "-".red().bold(),
+                    fingerprint,
+                    "(not in the keychain)".red()
+                ),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add or remove the `keys` from the recipients, then re-encrypt the
+    /// encrypted tags of the files in the registry. The registry itself is
+    /// re-encrypted to the new recipients after the command
+    fn change_recipients(&self, ctx: &mut CryptContext, keys: &[String], add: bool) -> Result<()> {
+        let mut fingerprints =
+            read_fingerprints(&self.registry.path).context("failed to read the recipients")?;
+        let before = fingerprints.clone();
+
+        for wanted in keys {
+            let fingerprint = if add {
+                find_key(ctx, wanted)?.fingerprint(false)
+            } else {
+                // Allow removing keys that are no longer in the keychain
+                match find_key(ctx, wanted) {
+                    Ok(key) => key.fingerprint(false),
+                    Err(_) => util::format_fingerprint(wanted.trim_start_matches("0x")),
+                }
+            };
+
+            if add && !fingerprints.contains(&fingerprint) {
+                fingerprints.push(fingerprint);
+            } else if !add {
+                let len = fingerprints.len();
+                fingerprints.retain(|f| f != &fingerprint && !f.ends_with(&fingerprint));
+                if fingerprints.len() == len {
+                    wutag_warning!("{} is not a recipient", wanted);
+                }
+            }
+        }
+
+        if fingerprints == before {
+            return Ok(());
+        }
+
+        Recipients::find(ctx, &fingerprints)?
+            .check_usable(ctx)
+            .context("the recipients were not changed")?;
+        write_fingerprints(&self.registry.path, &fingerprints)
+            .context("failed to write the recipients")?;
+
+        let recipients =
+            Recipients::configured(ctx, &self.registry.path, self.encrypt.public_key.as_deref())?;
+        let count = self.reencrypt_tags(ctx, &recipients);
+
+        if !self.quiet {
+            for key in recipients.keys() {
+                println!("{} {}", "+".green().bold(), key);
+            }
+            println!(
+                "{}: {} encrypted tags",
+                "RE-ENCRYPTED".red().bold(),
+                count.to_string().green().bold()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Encrypt the encrypted tags of every file in the registry to the
+    /// `recipients`, returning how many were re-encrypted. Tags that cannot be
+    /// decrypted are left as they are
+    fn reencrypt_tags(&self, ctx: &mut CryptContext, recipients: &Recipients) -> usize {
+        let mut count = 0;
+        for entry in self.registry.list_entries() {
+            let path = entry.path();
+            for xattr in list_locked(path).unwrap_or_default() {
+                let res = xattr
+                    .val()
+                    .parse::<Payload>()
+                    .map_err(anyhow::Error::from)
+                    .and_then(|payload| payload.open(ctx))
+                    .and_then(|tag| Payload::seal(ctx, recipients, &tag))
+                    .and_then(|payload| {
+                        payload.write_to(path)?;
+                        remove_xattr(path, xattr.key())?;
+                        Ok(())
+                    });
+
+                match res {
+                    Ok(()) => count += 1,
+                    Err(e) => wutag_warning!("{}: {:#}", path.display(), e),
+                }
+            }
+        }
+        count
+    }
+}
@@ -0,0 +1,302 @@
+//! Finish or undo a batch of `set` that was interrupted, using the journal that
+//! is written before the tags of each file are changed (see [`crate::journal`])
+
+use super::{
+    uses::{
+        bold_entry, fmt_path, fmt_tag, wutag_error, wutag_fatal, wutag_info, wutag_warning, Args,
+        BTreeMap, Colorize, DirEntryExt, EntryData, Tag,
+    },
+    App,
+};
+
+use crate::journal::{self, Journal, PendingOp};
+use std::path::Path;
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct RecoverOpts {
+    /// Put back the tags the files had before the batch instead
+    #[clap(long, short)]
+    pub(crate) rollback: bool,
+    /// Only display the changes of the batch that did not finish
+    #[clap(long, short = 'd')]
+    pub(crate) dry_run:  bool,
+}
+
+/// Whether `path` is a file inside of an archive, which cannot have extended
+/// attributes
+#[cfg(feature = "archives")]
+fn is_member(path: &Path) -> bool {
+    crate::archive::split_member(path).is_some()
+}
+
+#[cfg(not(feature = "archives"))]
+fn is_member(_: &Path) -> bool {
+    false
+}
+
+impl App {
+    /// Start the journal of a batch of changes to the tags of files. There is
+    /// no journal for files on another machine, since they cannot be recovered
+    /// from this one
+    pub(crate) fn begin_journal(&self) -> Option<Journal> {
+        if self.remote_host.is_some() {
+            return None;
+        }
+        if !journal::pending(&self.registry.path).is_empty() {
+            wutag_fatal!(
+                "a previous batch did not finish, use 'wutag recover' to replay it or 'wutag \
+                 recover --rollback' to undo it"
+            );
+        }
+
+        match Journal::begin(&self.registry.path) {
+            Ok(journal) => Some(journal),
+            Err(e) => {
+                wutag_warning!("unable to start the journal, continuing without it: {}", e);
+                None
+            },
+        }
+    }
+
+    /// The change of the tags of `path` that is about to be made, along with the
+    /// tags it has now. The tags of a file inside of an archive are only in the
+    /// registry
+    pub(crate) fn pending_op(
+        &self,
+        path: &Path,
+        clear: bool,
+        tags: &[Tag],
+        defaults: &[Tag],
+        values: &BTreeMap<String, String>,
+    ) -> PendingOp {
+        let before = if is_member(path) {
+            self.registry
+                .find_entry(path)
+                .and_then(|id| self.registry.list_entry_tags(id))
+                .unwrap_or_default()
+                .into_iter()
+                .cloned()
+                .collect()
+        } else {
+            path.list_tags().unwrap_or_default()
+        };
+
+        PendingOp {
+            path: path.to_path_buf(),
+            before,
+            clear,
+            tags: tags.to_vec(),
+            defaults: defaults.to_vec(),
+            values: values.clone(),
+        }
+    }
+
+    /// Record a change in the journal. It is only on the disk once the journal
+    /// is committed
+    pub(crate) fn record_op(&self, journal: &mut Option<Journal>, op: &PendingOp) {
+        if let Some(Err(e)) = journal.as_mut().map(|j| j.record(op)) {
+            wutag_warning!("unable to write the journal, continuing without it: {}", e);
+            self.finish_journal(journal.take());
+        }
+    }
+
+    /// Record the tags that are about to be written to `path` in the journal,
+    /// along with the tags it has now
+    pub(crate) fn journal_op(
+        &self,
+        journal: &mut Option<Journal>,
+        path: &Path,
+        clear: bool,
+        tags: &[Tag],
+        defaults: &[Tag],
+        values: &BTreeMap<String, String>,
+    ) {
+        if journal.is_some() {
+            self.record_op(journal, &self.pending_op(path, clear, tags, defaults, values));
+        }
+    }
+
+    /// Write the changes recorded in the journal to the disk, which is done
+    /// once for a batch before any of its files are changed
+    pub(crate) fn commit_journal(&self, journal: &mut Option<Journal>) {
+        if let Some(Err(e)) = journal.as_mut().map(Journal::sync) {
+            wutag_warning!("unable to write the journal, continuing without it: {}", e);
+            self.finish_journal(journal.take());
+        }
+    }
+
+    /// Remove the journal once the registry has been saved
+    pub(crate) fn finish_journal(&self, journal: Option<Journal>) {
+        if let Some(Err(e)) = journal.map(Journal::finish) {
+            wutag_error!("unable to remove the journal: {}", e);
+        }
+    }
+
+    /// Write the tags of `op` to its file and the registry, like `set` would
+    /// have
+    fn replay_op(&mut self, op: &PendingOp) {
+        let path = &op.path;
+        #[cfg(feature = "archives")]
+        if let Some((archive, member)) = crate::archive::split_member(path) {
+            match crate::archive::read_members(&archive, &[member.clone()]) {
+                Ok(contents) => match contents.get(&member) {
+                    Some(data) => self.tag_member(op, data, false),
+                    None => wutag_warning!(
+                        "{} is not in the archive {}",
+                        member,
+                        bold_entry!(archive)
+                    ),
+                },
+                Err(e) => wutag_warning!("{} {}", e, bold_entry!(archive)),
+            }
+            return;
+        }
+
+        if op.clear {
+            if let Some(id) = self.registry.find_entry(path) {
+                self.registry.clear_entry(id);
+            }
+            if let Err(e) = path.clear_tags() {
+                wutag_error!("\t{} {}", e, bold_entry!(path));
+            }
+        }
+
+        let missing = op
+            .defaults
+            .iter()
+            .filter(|t| path.get_tag(t.name()).is_err())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for tag in op.tags.iter().chain(missing.iter()) {
+            // The tag was written before the batch was interrupted
+            match self.write_tag(&path, tag) {
+                Ok(()) | Err(wutag_core::Error::TagExists(_)) => {},
+                Err(e) => {
+                    wutag_warning!("{} {}", e, bold_entry!(path));
+                    continue;
+                },
+            }
+
+            let removed = self.remove_exclusive(&path, tag);
+            let entry = match EntryData::new(path) {
+                Ok(data) => data,
+                Err(e) => {
+                    wutag_warning!("{} {}", e, bold_entry!(path));
+                    continue;
+                },
+            };
+            let id = self.registry.add_or_update_entry(entry);
+            for other in &removed {
+                self.registry.untag_entry(other, id);
+            }
+            self.registry.tag_entry(tag, id);
+            if let Some(value) = op.values.get(tag.name()) {
+                self.registry.set_value(tag, id, value.clone());
+            }
+            if !self.quiet {
+                print!("\t{} {}", "+".bold().green(), fmt_tag(tag));
+            }
+        }
+    }
+
+    /// Put back the tags that the file of `op` had before the batch. The
+    /// registry is changed as well, in case it was saved before the journal
+    /// was removed
+    pub(crate) fn rollback_op(&mut self, op: &PendingOp) {
+        let path = &op.path;
+        let member = is_member(path);
+        if !member {
+            if let Err(e) = path.clear_tags() {
+                wutag_error!("\t{} {}", e, bold_entry!(path));
+                return;
+            }
+        }
+
+        let id = self.registry.find_entry(path);
+        for tag in op.tags.iter().chain(op.defaults.iter()) {
+            if op.before.contains(tag) {
+                continue;
+            }
+            if let Some(id) = id {
+                self.registry.untag_entry(tag, id);
+            }
+            if !self.quiet {
+                print!("\t{} {}", "X".bold().red(), fmt_tag(tag));
+            }
+        }
+
+        for tag in &op.before {
+            if !member {
+                if let Err(e) = path.tag(tag) {
+                    wutag_warning!("{} {}", e, bold_entry!(path));
+                    continue;
+                }
+            }
+            if let Some(id) = id {
+                self.registry.tag_entry(tag, id);
+            }
+        }
+    }
+
+    pub(crate) fn recover(&mut self, opts: &RecoverOpts) {
+        log::debug!("RecoverOpts: {:#?}", opts);
+        log::debug!("Using registry: {}", self.registry.path.display());
+
+        let pending = journal::pending(&self.registry.path);
+        if pending.is_empty() {
+            if !opts.dry_run {
+                if let Err(e) = journal::discard(&self.registry.path) {
+                    wutag_fatal!("unable to remove the journal: {}", e);
+                }
+            }
+            wutag_info!("there is no batch to recover");
+            return;
+        }
+
+        for op in &pending {
+            if !self.quiet || opts.dry_run {
+                println!("{}:", fmt_path(&op.path, self.base_color, self.ls_colors));
+            }
+
+            if opts.dry_run {
+                let (sign, tags) = if opts.rollback {
+                    ("=".bold().blue(), &op.before)
+                } else {
+                    ("+".bold().green(), &op.tags)
+                };
+                for tag in tags {
+                    print!("\t{} {}", sign, fmt_tag(tag));
+                }
+            } else if opts.rollback {
+                self.rollback_op(op);
+            } else {
+                self.replay_op(op);
+            }
+
+            if !self.quiet || opts.dry_run {
+                println!();
+            }
+        }
+
+        if opts.dry_run {
+            return;
+        }
+
+        log::debug!("Saving registry...");
+        self.save_registry();
+        if let Err(e) = journal::discard(&self.registry.path) {
+            wutag_error!("unable to remove the journal: {}", e);
+        }
+
+        if !self.quiet {
+            let done = if opts.rollback { "ROLLED BACK" } else { "REPLAYED" };
+            println!(
+                "{} {}: {} file(s)",
+                "\u{2714}".green().bold(),
+                done.red().bold(),
+                pending.len()
+            );
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! Maintenance of the registry itself, as opposed to the tags on the files in
+//! it. `registry rebase <old> <new>` moves the files under a directory to
+//! another, such as after a share was mounted somewhere else (see
+//! `strip_prefix` to use a share from several mounts at once)
+
+use super::{
+    uses::{wutag_fatal, Args, Colorize, PathBuf, Subcommand, ValueHint},
+    App,
+};
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub(crate) enum RegistryObject {
+    /// Move the files under a directory to the same place under another
+    Rebase {
+        /// Directory the files are under in the registry
+        #[clap(value_name = "old", value_hint = ValueHint::DirPath)]
+        old: PathBuf,
+        /// Directory the files are moved to
+        #[clap(value_name = "new", value_hint = ValueHint::DirPath)]
+        new: PathBuf,
+    },
+}
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct RegistryOpts {
+    /// The action to take. Valid values are: 'rebase'
+    #[clap(subcommand)]
+    pub(crate) object: RegistryObject,
+}
+
+impl App {
+    pub(crate) fn manage_registry(&mut self, opts: &RegistryOpts) {
+        log::debug!("RegistryOpts: {:#?}", opts);
+        log::debug!("Using registry: {}", self.registry.path.display());
+
+        match &opts.object {
+            RegistryObject::Rebase { old, new } => {
+                if !old.is_absolute() || !new.is_absolute() {
+                    wutag_fatal!("the directories to rebase must be absolute paths");
+                }
+
+                let moved = self.registry.rebase(old, new);
+                self.save_registry();
+
+                if !self.quiet {
+                    println!(
+                        "{} {}: {} {} {} ({} files)",
+                        "\u{2714}".green().bold(),
+                        "REBASED".red().bold(),
+                        old.display().to_string().green().bold(),
+                        "=>".bold(),
+                        new.display().to_string().green().bold(),
+                        moved
+                    );
+                }
+            },
+        }
+    }
+}
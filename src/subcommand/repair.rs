@@ -2,6 +2,7 @@
 // TODO: confirm all options work
 
 use super::{
+    service,
     uses::{
         contained_path, fmt_local_path, fmt_path, fs, print_stdout, systemtime_to_datetime, Args,
         Border, Cell, Colorize, Context, Justify, Result, Separator, Table, ValueHint,
@@ -40,6 +41,11 @@ pub(crate) struct RepairOpts {
     /// Update the hashsum of all files, including unmodified files
     #[clap(short = 'u', long = "unmodified", takes_value = true)]
     pub(crate) unmodified: bool,
+    /// Send a desktop notification of the updated and missing files when
+    /// `notify.modified` or `notify.missing` is set in the configuration (used
+    /// by `wutag service install`)
+    #[clap(short = 'n', long = "notify")]
+    pub(crate) notify:     bool,
 }
 
 impl App {
@@ -47,7 +53,8 @@ impl App {
         log::debug!("RepairOpts: {:#?}", opts);
 
         let mut table = vec![];
-        let mut removed = false;
+        let mut removed = 0_usize;
+        let mut missing = 0_usize;
 
         for (id, entry) in self
             .registry
@@ -59,6 +66,15 @@ impl App {
                 continue;
             }
 
+            // Members of an archive are only repaired by removing them once the
+            // archive is gone
+            #[cfg(feature = "archives")]
+            if let Some((archive, _)) = crate::archive::split_member(entry.path()) {
+                if archive.exists() {
+                    continue;
+                }
+            }
+
             let exists = entry.path().lexiclean().exists();
 
             if exists && (entry.changed_since()? || opts.unmodified) {
@@ -87,6 +103,10 @@ impl App {
                 }
             }
 
+            if !exists {
+                missing += 1;
+            }
+
             if !exists && opts.remove {
                 if !opts.dry_run {
                     self.registry.clear_entry(id);
@@ -99,25 +119,53 @@ impl App {
                         fmt_path(entry.path(), self.base_color, self.ls_colors),
                     );
                 }
-                removed = true;
+                removed += 1;
             }
         }
 
         if !self.quiet {
-            if removed && !table.is_empty() {
+            if removed > 0 && !table.is_empty() {
                 println!("\n{}:", "Updated".purple().bold());
             }
             print_stdout(
                 table
                     .table()
                     .border(Border::builder().build())
-                    .separator(Separator::builder().build()),
+                    .separator(Separator::builder().build())
+                    .color_choice(self.color_choice()),
             )
             .context("failed to print table")?;
         }
 
+        if !opts.dry_run {
+            self.registry.mark_maintained();
+        }
+
         log::debug!("Saving registry...");
         self.save_registry();
+
+        if opts.notify && !opts.dry_run {
+            if self.notify.modified && !table.is_empty() {
+                service::notify(
+                    "Modified files",
+                    &format!(
+                        "updated {} files that changed since they were tagged",
+                        table.len()
+                    ),
+                );
+            }
+            if self.notify.missing && missing > 0 {
+                service::notify(
+                    "Missing files",
+                    &if opts.remove {
+                        format!("removed {} files that no longer exist", removed)
+                    } else {
+                        format!("{} files in the registry no longer exist", missing)
+                    },
+                );
+            }
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,425 @@
+//! An interactive prompt where each query narrows the files that matched the
+//! previous one. A query is a filter that is optionally preceded by how it is
+//! combined with the current results:
+//!   - `and <filter>`: keep the results that match (the default)
+//!   - `or <filter>`: add the files in the registry that match
+//!   - `not <filter>`: remove the results that match
+//!
+//! Filters:
+//!   - `tag(<glob>)`: files with a tag matching the glob
+//!   - `path(<glob>)`: files whose path matches the glob
+//!   - `after(<date>)`: files modified on or after the date
+//!   - `before(<date>)`: files modified before the date
+//!   - `mtime(<cmp><date>)`: files whose modification time compares to the
+//!     date, where `<cmp>` is one of `<`, `<=`, `=`, `>=`, `>` (e.g.,
+//!     `mtime(>=2023-06)`)
+//!   - `ctime(<cmp><date>)`: the same with the time the file's status (e.g.,
+//!     its permissions or extended attributes) last changed
+//!   - `atime(<cmp><date>)`: the same with the time the file was last accessed
+//!
+//! Dates are `YYYY`, `YYYY-MM`, or `YYYY-MM-DD`. The times are read from the
+//! file, and the modification time falls back to the one in the registry if it
+//! cannot be read. Lines starting with `:` are meta-commands (see `:help`)
+
+use super::{
+    uses::{
+        fmt_path, fmt_tag_icon, fs, osstr_to_bytes, regex_builder, wutag_error, Arc, Colorize,
+        CommandTemplate, GlobEngine, PathBuf, Result,
+    },
+    App,
+};
+
+use crate::registry::EntryId;
+use anyhow::anyhow;
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use regex::bytes::Regex;
+use rustyline::{error::ReadlineError, Editor};
+use std::{
+    cmp::Ordering, collections::BTreeSet, convert::TryFrom, os::unix::fs::MetadataExt, path::Path,
+    sync::Mutex,
+};
+
+/// Help displayed with `:help`
+const REPL_HELP: &str = "\
+Queries:    [and|or|not] tag(<glob>) | path(<glob>) | after(<date>) | before(<date>)
+                         | mtime(<cmp><date>) | ctime(<cmp><date>) | atime(<cmp><date>)
+            where <cmp> is one of <, <=, =, >=, >
+Commands:
+  :list           display the files in the results and their tags
+  :exec <cmd>     execute a command on each file (see 'wutag search --exec')
+  :save <file>    write the paths of the results to a file, one per line
+  :reset          start over with every file in the registry
+  :help           display this message
+  :quit           exit (also Ctrl-D)";
+
+/// How a query is combined with the current results
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combine {
+    And,
+    Or,
+    Not,
+}
+
+/// Which timestamp of a file a query compares
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TimeField {
+    /// When the contents were last modified (`mtime`)
+    Modified,
+    /// When the status, such as the permissions, was last changed (`ctime`)
+    Changed,
+    /// When the file was last read (`atime`)
+    Accessed,
+}
+
+/// How the timestamp of a file is compared to a date, e.g., `>=` for files
+/// whose timestamp is on or after the date
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TimeCmp(&'static [Ordering]);
+
+impl TimeCmp {
+    const AFTER: Self = Self(&[Ordering::Greater]);
+    const AT_OR_AFTER: Self = Self(&[Ordering::Greater, Ordering::Equal]);
+    const AT_OR_BEFORE: Self = Self(&[Ordering::Less, Ordering::Equal]);
+    const BEFORE: Self = Self(&[Ordering::Less]);
+    const ON: Self = Self(&[Ordering::Equal]);
+    /// The comparisons in the order they are tried when parsing, so that `<=`
+    /// is not read as `<`
+    const OPERATORS: [(&'static str, Self); 5] = [
+        ("<=", Self::AT_OR_BEFORE),
+        (">=", Self::AT_OR_AFTER),
+        ("<", Self::BEFORE),
+        (">", Self::AFTER),
+        ("=", Self::ON),
+    ];
+
+    /// Whether the day of a timestamp compares to `date` as required
+    fn matches(self, day: NaiveDate, date: NaiveDate) -> bool {
+        self.0.contains(&day.cmp(&date))
+    }
+}
+
+/// What a query matches files against
+#[derive(Debug, Clone)]
+enum Filter {
+    Tag(Regex),
+    Path(Regex),
+    Time(TimeField, TimeCmp, NaiveDate),
+}
+
+/// Parse a date given to `after()` or `before()`, which can leave out the day
+/// or the month and day
+fn parse_date(date: &str) -> Result<NaiveDate> {
+    let date = date.trim();
+    let full = match date.matches('-').count() {
+        0 => format!("{}-01-01", date),
+        1 => format!("{}-01", date),
+        _ => date.to_owned(),
+    };
+
+    NaiveDate::parse_from_str(&full, "%Y-%m-%d").map_err(|_| anyhow!("invalid date: {}", date))
+}
+
+/// Parse the argument of `mtime()`, `ctime()`, or `atime()`, such as `>=2023`
+fn parse_time_cmp(arg: &str) -> Result<(TimeCmp, NaiveDate)> {
+    let arg = arg.trim();
+    TimeCmp::OPERATORS
+        .iter()
+        .find_map(|(op, cmp)| arg.strip_prefix(op).map(|date| (*cmp, date)))
+        .ok_or_else(|| anyhow!("expected one of <, <=, =, >=, > before the date: {}", arg))
+        .and_then(|(cmp, date)| Ok((cmp, parse_date(date)?)))
+}
+
+/// The day of a timestamp of the file at `path`, in the local timezone
+fn file_day(path: &Path, field: TimeField) -> Option<NaiveDate> {
+    let meta = fs::metadata(path).ok()?;
+    let (secs, nsecs) = match field {
+        TimeField::Modified => (meta.mtime(), meta.mtime_nsec()),
+        TimeField::Changed => (meta.ctime(), meta.ctime_nsec()),
+        TimeField::Accessed => (meta.atime(), meta.atime_nsec()),
+    };
+
+    Local
+        .timestamp_opt(secs, u32::try_from(nsecs).unwrap_or(0))
+        .single()
+        .map(|time| time.date().naive_local())
+}
+
+impl App {
+    /// Parse a line of the prompt that is not a meta-command
+    fn parse_query(&self, line: &str) -> Result<(Combine, Filter)> {
+        let (combine, query) = match line.split_once(char::is_whitespace) {
+            Some(("and", rest)) => (Combine::And, rest.trim()),
+            Some(("or", rest)) => (Combine::Or, rest.trim()),
+            Some(("not", rest)) => (Combine::Not, rest.trim()),
+            _ => (Combine::And, line),
+        };
+
+        let glob = |pat: &str| -> Result<Regex> {
+            let glob = GlobEngine::current()
+                .regex(pat)
+                .map_err(|e| anyhow!("invalid glob: {}", e))?;
+            Ok(regex_builder(
+                &glob,
+                self.case_insensitive,
+                self.case_sensitive,
+            ))
+        };
+
+        let filter = match query.strip_suffix(')').and_then(|q| q.split_once('(')) {
+            Some(("tag", pat)) => Filter::Tag(glob(pat)?),
+            Some(("path", pat)) => Filter::Path(glob(pat)?),
+            Some(("after", date)) =>
+                Filter::Time(TimeField::Modified, TimeCmp::AT_OR_AFTER, parse_date(date)?),
+            Some(("before", date)) =>
+                Filter::Time(TimeField::Modified, TimeCmp::BEFORE, parse_date(date)?),
+            Some((func @ ("mtime" | "ctime" | "atime"), arg)) => {
+                let field = match func {
+                    "mtime" => TimeField::Modified,
+                    "ctime" => TimeField::Changed,
+                    _ => TimeField::Accessed,
+                };
+                let (cmp, date) = parse_time_cmp(arg)?;
+                Filter::Time(field, cmp, date)
+            },
+            _ => return Err(anyhow!("invalid query: {} (see :help)", query)),
+        };
+
+        Ok((combine, filter))
+    }
+
+    /// Whether the entry with the given `id` matches the `filter`
+    fn repl_matches(&self, id: EntryId, filter: &Filter) -> bool {
+        let entry = match self.registry.get_entry(id) {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        match filter {
+            Filter::Tag(re) => self
+                .registry
+                .list_entry_tags(id)
+                .unwrap_or_default()
+                .iter()
+                .any(|tag| re.is_match(tag.name().as_bytes())),
+            Filter::Path(re) => re.is_match(&osstr_to_bytes(entry.path().as_os_str())),
+            Filter::Time(field, cmp, date) => {
+                let day = match file_day(entry.path(), *field) {
+                    Some(day) => Some(day),
+                    None if *field == TimeField::Modified => Some(
+                        DateTime::<Local>::from(*entry.modtime())
+                            .date()
+                            .naive_local(),
+                    ),
+                    None => None,
+                };
+                day.map_or(false, |day| cmp.matches(day, *date))
+            },
+        }
+    }
+
+    /// Combine the `results` with the files that match a query
+    pub(crate) fn apply_query(
+        &self,
+        results: BTreeSet<EntryId>,
+        query: &str,
+    ) -> Result<BTreeSet<EntryId>> {
+        let (combine, filter) = self.parse_query(query.trim())?;
+
+        Ok(match combine {
+            Combine::And => results
+                .into_iter()
+                .filter(|id| self.repl_matches(*id, &filter))
+                .collect(),
+            Combine::Or => {
+                let matched = self
+                    .repl_all()
+                    .into_iter()
+                    .filter(|id| self.repl_matches(*id, &filter))
+                    .collect::<BTreeSet<_>>();
+                results.union(&matched).copied().collect()
+            },
+            Combine::Not => results
+                .into_iter()
+                .filter(|id| !self.repl_matches(*id, &filter))
+                .collect(),
+        })
+    }
+
+    /// The files that match the `queries`, which are applied in order as if
+    /// they were typed at the prompt
+    pub(crate) fn query_entries(&self, queries: &[String]) -> Result<BTreeSet<EntryId>> {
+        queries.iter().try_fold(self.repl_all(), |results, query| {
+            self.apply_query(results, query)
+        })
+    }
+
+    /// Every file in the registry
+    fn repl_all(&self) -> BTreeSet<EntryId> {
+        self.registry
+            .list_entries_and_ids()
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Paths of the files in the `results`
+    fn repl_paths(&self, results: &BTreeSet<EntryId>) -> Vec<PathBuf> {
+        results
+            .iter()
+            .filter_map(|id| self.registry.get_entry(*id))
+            .map(|entry| entry.path().to_path_buf())
+            .collect()
+    }
+
+    /// Display the files in the `results` and their tags
+    fn repl_list(&self, results: &BTreeSet<EntryId>) {
+        for id in results {
+            if let Some(entry) = self.registry.get_entry(*id) {
+                print!(
+                    "{}:",
+                    fmt_path(entry.path(), self.base_color, self.ls_colors)
+                );
+                for tag in self.registry.list_entry_tags(*id).unwrap_or_default() {
+                    print!(" {}", fmt_tag_icon(tag, self.tag_icon(tag)));
+                }
+                println!();
+            }
+        }
+    }
+
+    /// Run a meta-command, returning whether the prompt should exit
+    fn repl_meta(&self, line: &str, results: &mut BTreeSet<EntryId>) -> Result<bool> {
+        let (cmd, arg) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(cmd, arg)| (cmd, arg.trim()));
+
+        match cmd {
+            ":list" | ":ls" => self.repl_list(results),
+            ":exec" | ":x" => {
+                if arg.is_empty() {
+                    return Err(anyhow!(":exec requires a command"));
+                }
+                let command = CommandTemplate::new(arg.split_whitespace());
+                let out_perm = Arc::new(Mutex::new(()));
+                for path in self.repl_paths(results) {
+                    command.generate_and_execute(&path, &out_perm);
+                }
+            },
+            ":save" => {
+                if arg.is_empty() {
+                    return Err(anyhow!(":save requires a file"));
+                }
+                let paths = self
+                    .repl_paths(results)
+                    .iter()
+                    .map(|p| format!("{}\n", p.display()))
+                    .collect::<String>();
+                fs::write(arg, paths).map_err(|e| anyhow!("failed to save {}: {}", arg, e))?;
+            },
+            ":reset" => *results = self.repl_all(),
+            ":help" | ":h" => println!("{}", REPL_HELP),
+            ":quit" | ":q" | ":exit" => return Ok(true),
+            _ => return Err(anyhow!("unknown command: {} (see :help)", cmd)),
+        }
+
+        Ok(false)
+    }
+
+    pub(crate) fn repl(&self) {
+        log::debug!("Using registry: {}", self.registry.path.display());
+
+        let mut editor = Editor::<()>::new();
+        let mut results = self.repl_all();
+
+        if !self.quiet {
+            println!("{} files, type {} for help", results.len(), ":help".bold());
+        }
+
+        loop {
+            let line = match editor.readline(&format!("{} ", ">".green().bold())) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(e) => {
+                    wutag_error!("{}", e);
+                    break;
+                },
+            };
+
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            editor.add_history_entry(line);
+
+            if line.starts_with(':') {
+                match self.repl_meta(line, &mut results) {
+                    Ok(true) => break,
+                    Ok(false) => {},
+                    Err(e) => wutag_error!("{}", e),
+                }
+                continue;
+            }
+
+            match self.apply_query(results.clone(), line) {
+                Ok(matched) => {
+                    results = matched;
+                    println!("{} files", results.len());
+                },
+                Err(e) => wutag_error!("{}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn parses_time_comparisons() -> Result<()> {
+        let date = NaiveDate::from_ymd(2023, 6, 1);
+        assert_eq!(parse_time_cmp(">=2023-06")?, (TimeCmp::AT_OR_AFTER, date));
+        assert_eq!(parse_time_cmp("<= 2023-06-01")?, (TimeCmp::AT_OR_BEFORE, date));
+        assert_eq!(parse_time_cmp("<2023-06-01")?, (TimeCmp::BEFORE, date));
+        assert!(parse_time_cmp("2023").is_err());
+
+        let (before, after) = (date.pred(), date.succ());
+        assert!(TimeCmp::AT_OR_AFTER.matches(date, date));
+        assert!(TimeCmp::AFTER.matches(after, date));
+        assert!(!TimeCmp::AFTER.matches(date, date));
+        assert!(TimeCmp::BEFORE.matches(before, date));
+        assert!(TimeCmp::ON.matches(date, date));
+        Ok(())
+    }
+
+    #[test]
+    fn reads_each_timestamp() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("file");
+        let file = fs::File::create(&path)?;
+
+        let day = |secs: u64| {
+            DateTime::<Local>::from(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+                .date()
+                .naive_local()
+        };
+        let (accessed, modified) = (1_000_000_000, 1_300_000_000);
+        let today = || Local::now().date().naive_local();
+        let started = today();
+        file.set_times(
+            fs::FileTimes::new()
+                .set_accessed(SystemTime::UNIX_EPOCH + Duration::from_secs(accessed))
+                .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(modified)),
+        )?;
+
+        assert_eq!(file_day(&path, TimeField::Accessed), Some(day(accessed)));
+        assert_eq!(file_day(&path, TimeField::Modified), Some(day(modified)));
+        // The status changed when the other timestamps were set, which cannot be
+        // given a fixed time. The day may have changed in between
+        let changed = file_day(&path, TimeField::Changed).unwrap();
+        assert!(started <= changed && changed <= today());
+        assert_eq!(file_day(&dir.path().join("missing"), TimeField::Changed), None);
+        Ok(())
+    }
+}
@@ -1,13 +1,20 @@
 use super::{
     uses::{
-        err, fmt_err, fmt_path, fmt_tag, glob_builder, list_tags, osstr_to_bytes, reg_ok,
-        regex_builder, Arc, Args, Colorize, Cow, DirEntryExt, OsStr,
+        diagnostics, err, fmt_err, fmt_path, fmt_tag, glob_builder, list_tags, osstr_to_bytes,
+        reg_ok, regex_builder, Arc, Args, Colorize, Cow, DirEntryExt, OsStr,
     },
     App,
 };
 
 #[derive(Args, Clone, Debug, PartialEq)]
 pub(crate) struct RmOpts {
+    /// Use the closest tag for a tag that does not exist
+    #[clap(
+        long,
+        long_about = "When a tag does not exist, remove the existing tag whose name is the most \
+                      similar instead of failing with a list of suggestions"
+    )]
+    pub(crate) fuzzy:   bool,
     /// A glob pattern like "*.png" (or regex).
     pub(crate) pattern: String,
     pub(crate) tags:    Vec<String>,
@@ -27,14 +34,16 @@ impl App {
 
         let re = regex_builder(&pat, self.case_insensitive, self.case_sensitive);
         log::debug!("Compiled pattern: {}", re);
+        let tags = self.correct_tags(&opts.tags, opts.fuzzy);
 
         if self.global {
-            let ctags = opts.tags.iter().collect::<Vec<_>>();
+            let ctags = tags.iter().collect::<Vec<_>>();
             let exclude_pattern = regex_builder(
                 self.exclude.join("|").as_str(),
                 self.case_insensitive,
                 self.case_sensitive,
             );
+            diagnostics::matched(0);
             for (&id, entry) in self.registry.clone().list_entries_and_ids() {
                 let search_str: Cow<OsStr> = Cow::Owned(entry.path().as_os_str().to_os_string());
                 let search_bytes = osstr_to_bytes(search_str.as_ref());
@@ -49,6 +58,7 @@ impl App {
                 }
 
                 if re.is_match(&search_bytes) {
+                    diagnostics::matched(1);
                     list_tags(entry.path())
                         .map(|tags| {
                             tags.iter().fold(Vec::new(), |mut acc, tag| {
@@ -94,8 +104,7 @@ impl App {
                 |entry: &ignore::DirEntry| {
                     log::debug!("Using WalkParallel");
                     let id = self.registry.find_entry(entry.path());
-                    let tags = opts
-                        .tags
+                    let tags = tags
                         .iter()
                         .map(|tag| {
                             if let Some(id) = id {
@@ -1,11 +1,32 @@
+// TODO: once searches are written in a query language, add string functions
+//       that can be used in comparisons instead of the pattern, such as
+//       `lower()`, `basename() == "README.md"`, and `match_path("%2024%")`
+
 use super::{
     uses::{
-        channel, glob_builder, receiver, regex_builder, sender, Arc, Args, CommandTemplate,
-        ValueHint, WorkerResult, EXEC_BATCH_EXPL, EXEC_EXPL,
+        channel, color_matches, contained_path, diagnostics, fmt_path, fs, glob_builder,
+        highlight_builder, osstr_to_bytes, receiver, regex_builder, sender, wutag_error,
+        wutag_fatal, Arc, Args, Colorize, CommandTemplate, EncryptConfig, MetadataFilter, PathBuf,
+        TagRegistry, ValueHint, WorkerResult, EXEC_BATCH_EXPL, EXEC_EXPL,
     },
     App,
 };
 
+use crate::registry::{EntryId, ENTRY_PAGE_SIZE};
+use chrono::Local;
+use regex::bytes::Regex;
+use std::{
+    collections::BTreeSet,
+    fmt,
+    io::{self, Write},
+    path::Path,
+    slice, thread,
+    time::{Duration, Instant},
+};
+
+/// Number of seconds between each check of the registry with `--watch`
+const WATCH_POLL_SECS: f64 = 0.5;
+
 #[derive(Args, Clone, Debug, PartialEq)]
 pub(crate) struct SearchOpts {
     /// No colored output. Should be detected automatically on pipe
@@ -51,11 +72,79 @@ pub(crate) struct SearchOpts {
         value_hint = ValueHint::CommandName,
     )]
     pub(crate) execute_batch: Option<Vec<String>>,
+    /// Print the output of '--exec' as it is written
+    #[clap(
+        name = "no-buffer",
+        long = "no-buffer",
+        requires = "exec",
+        long_about = "Print the output of each command run with '--exec' as it is written, \
+                      instead of once the command exits. This is useful for commands that run \
+                      for a long time, but the output of commands that run at the same time can \
+                      be mixed together. Otherwise, each line of the output starts with the path \
+                      of the file when '--verbose' is used"
+    )]
+    pub(crate) no_buffer:     bool,
 
     /// Display tags and files on separate lines
     #[clap(name = "garrulous", long, short = 'G', conflicts_with = "only-files")]
     pub(crate) garrulous: bool,
 
+    /// Stop searching once this many files are found
+    #[clap(
+        name = "max-results",
+        long = "max-results",
+        value_name = "num",
+        long_about = "Stop searching once this many files are found, instead of going through \
+                      every file in the registry. Files are found in the order of the \
+                      'default_sort' configuration option (by path by default). With \
+                      '--all-registries', this is the number of files found in all of them"
+    )]
+    pub(crate) max_results: Option<usize>,
+
+    /// Follow symlinks, overriding 'follow_symlinks'
+    #[clap(
+        long = "follow",
+        conflicts_with = "no-follow",
+        long_about = "Filter a symlink in the results by the type, size, owner, and permissions \
+                      of its target, and read its encrypted tags from its target. This overrides \
+                      'follow_symlinks' in the configuration"
+    )]
+    pub(crate) follow:    bool,
+    /// Do not follow symlinks, overriding 'follow_symlinks'
+    #[clap(
+        name = "no-follow",
+        long = "no-follow",
+        long_about = "Filter a symlink in the results by its own type (e.g., '--type symlink'), \
+                      size, owner, and permissions, and read its encrypted tags from the symlink \
+                      itself. This overrides 'follow_symlinks' in the configuration, which is not \
+                      set by default"
+    )]
+    pub(crate) no_follow: bool,
+
+    /// Run the search again each time the registry changes
+    #[clap(
+        name = "watch",
+        long,
+        short = 'w',
+        conflicts_with_all = &["exec-batch", "all-registries"],
+        long_about = "Run the search again each time the registry is saved (e.g., while tagging \
+                      files in another terminal), clearing the screen before displaying the \
+                      new results like 'watch(1)'. Stop with Ctrl-C"
+    )]
+    pub(crate) watch:    bool,
+    /// Also run the search again after this many seconds with `--watch`
+    #[clap(
+        name = "interval",
+        long,
+        short = 'n',
+        value_name = "secs",
+        requires = "watch",
+        long_about = "Also run the search again after this many seconds when using '--watch', \
+                      even if the registry did not change. This picks up changes to the files \
+                      themselves, such as their names matching the pattern"
+    )]
+    pub(crate) interval: Option<f64>,
+
     /// Files matching all tags (instead of any)
     #[clap(
         name = "all",
@@ -67,6 +156,9 @@ pub(crate) struct SearchOpts {
     )]
     pub(crate) all: bool,
 
+    #[clap(flatten)]
+    pub(crate) tag_sets: TagSets,
+
     /// Files matching all and only all tags
     #[clap(
         name = "only-all",
@@ -86,17 +178,389 @@ pub(crate) struct SearchOpts {
         short,
         long_about = "\
         Limit search results even further by using a tag. To search just by tags use 'wutag search \
-                      '*' --tag <tag>'
+                      '*' --tag <tag>'. A tag group from the configuration can be used with \
+                      '<group>()' to match any tag in the group, or '<group>(<tag>)' to match a \
+                      tag that must be in the group. Tags can be matched by color with \
+                      'color(<color>)', where the color is either hex or the name of the closest \
+                      basic color (e.g., 'color(red)')
         "
     )]
     pub(crate) tags: Vec<String>,
 
+    /// Explain why the file at this path is not found by the search
+    #[clap(
+        name = "explain-misses",
+        long = "explain-misses",
+        value_name = "path",
+        value_hint = ValueHint::FilePath,
+        conflicts_with_all = &["watch", "all-registries", "exec", "exec-batch"],
+        long_about = "Instead of searching, check each part of the search against the file at \
+                      <path>, in the order that they are applied, and display the ones that it \
+                      does not match, such as a missing tag, a tag that has expired, or a pattern \
+                      that does not match its path"
+    )]
+    pub(crate) explain_misses: Option<PathBuf>,
+
+    /// Tag groups that were given with the `<group>()` accessor
+    #[clap(skip)]
+    pub(crate) groups: Vec<String>,
+
+    /// Colors that were given with the `color(<color>)` function
+    #[clap(skip)]
+    pub(crate) colors: Vec<String>,
+
+    /// Search every registry in the configuration file
+    #[clap(
+        name = "all-registries",
+        long = "all-registries",
+        long_about = "Search every registry that is listed under 'registries' in the \
+                      configuration file. The registries are only read, and the name of the \
+                      registry is displayed before each result"
+    )]
+    pub(crate) all_registries: bool,
+
+    /// Include the files inside of archives that were tagged with `set
+    /// --look-inside`
+    #[cfg(feature = "archives")]
+    #[clap(
+        name = "look-inside",
+        long = "look-inside",
+        long_about = "Include the files inside of zip and tar archives that were tagged with \
+                      'wutag set --look-inside'. They are displayed as '<archive>!/<file>'"
+    )]
+    pub(crate) look_inside: bool,
+
+    /// Name of the registry being searched when using `--all-registries`
+    #[clap(skip)]
+    pub(crate) registry_name: Option<String>,
+
+    /// Pattern matching the part of a path that is highlighted
+    #[clap(skip)]
+    pub(crate) highlight: Option<String>,
+
     /// Pattern to search tagged files
     #[clap(name = "pattern")]
     pub(crate) pattern: String,
 }
 
+/// Sets of tags that the files must have any of, all of, or none of, so that
+/// they can be searched for without writing a query. The `@filter` command of
+/// the TUI takes the same options
+#[derive(Args, Clone, Debug, Default, PartialEq)]
+pub(crate) struct TagSets {
+    /// Files that have any of the comma-separated tags
+    #[clap(
+        name = "any-of",
+        long = "any-of",
+        value_name = "tags",
+        use_delimiter = true,
+        multiple_occurrences = true,
+        long_about = "The files that result must have at least one of the tags, which are \
+                      separated by commas (e.g., '--any-of work,home'). Can be combined with \
+                      '--all-of' and '--none-of' without having to write a query"
+    )]
+    pub(crate) any_of:  Vec<String>,
+    /// Files that have all of the comma-separated tags
+    #[clap(
+        name = "all-of",
+        long = "all-of",
+        value_name = "tags",
+        use_delimiter = true,
+        multiple_occurrences = true,
+        long_about = "The files that result must have every one of the tags, which are separated \
+                      by commas (e.g., '--all-of work,urgent')"
+    )]
+    pub(crate) all_of:  Vec<String>,
+    /// Files that have none of the comma-separated tags
+    #[clap(
+        name = "none-of",
+        long = "none-of",
+        value_name = "tags",
+        use_delimiter = true,
+        multiple_occurrences = true,
+        long_about = "The files that result must not have any of the tags, which are separated \
+                      by commas (e.g., '--none-of done,archived')"
+    )]
+    pub(crate) none_of: Vec<String>,
+}
+
+impl TagSets {
+    /// Parse the arguments of the `@filter` command of the TUI, which are the
+    /// same as the options of `search` (e.g., `--all-of work,urgent`)
+    pub(crate) fn from_args(args: &[String]) -> anyhow::Result<Self> {
+        let mut sets = Self::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag, Some(value.to_owned())),
+                None => (arg.as_str(), None),
+            };
+            let set = match flag {
+                "--any-of" => &mut sets.any_of,
+                "--all-of" => &mut sets.all_of,
+                "--none-of" => &mut sets.none_of,
+                _ => return Err(anyhow::anyhow!("unknown argument: {}", arg)),
+            };
+            let value = value
+                .or_else(|| args.next().cloned())
+                .ok_or_else(|| anyhow::anyhow!("{} requires a list of tags", flag))?;
+            set.extend(
+                value
+                    .split(',')
+                    .filter(|t| !t.is_empty())
+                    .map(ToOwned::to_owned),
+            );
+        }
+
+        Ok(sets)
+    }
+
+    /// Whether no tags were given
+    pub(crate) fn is_empty(&self) -> bool {
+        self.any_of.is_empty() && self.all_of.is_empty() && self.none_of.is_empty()
+    }
+
+    /// Whether the file with the entry `id` has any of `any_of` (when it is
+    /// given), all of `all_of`, and none of `none_of`
+    pub(crate) fn matches(&self, registry: &TagRegistry, id: EntryId) -> bool {
+        (self.any_of.is_empty() || registry.entry_has_any_tags(id, &self.any_of))
+            && registry.entry_has_all_tags(id, &self.all_of)
+            && !registry.entry_has_any_tags(id, &self.none_of)
+    }
+}
+
+/// A part of a search that a file has to match. The files that are found by
+/// [`sender`] and the ones that `--explain-misses` displays are both checked
+/// with these, so that they cannot disagree
+#[derive(Clone, Debug)]
+pub(crate) enum Clause {
+    /// The file is under the directory, unless the search is global
+    Directory,
+    /// The file is not inside of an archive, unless using `--look-inside`
+    #[cfg(feature = "archives")]
+    LookInside,
+    /// The path matches none of the excluded patterns
+    Exclude,
+    /// The extension is one of `--ext`
+    Ext,
+    /// The file is one of `--type`
+    Type,
+    /// The metadata passes the filter of the flag (`--size`, `--owner`, or
+    /// `--perm`)
+    Metadata(&'static str, MetadataFilter),
+    /// The path matches the pattern
+    Pattern,
+    /// The tags match `--tags`, all of them with `--all`, or only them with
+    /// `--only-all`
+    Tags,
+    /// The file has any of `--any-of`
+    AnyOf,
+    /// The file has the tag of `--all-of`
+    AllOf(String),
+    /// The file does not have the tag of `--none-of`
+    NoneOf(String),
+    /// The file has a tag of the group of `<group>()`
+    Group(String),
+    /// One of the tags of the file has the color of `color()`
+    Color(String),
+}
+
+impl fmt::Display for Clause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Directory => write!(f, "directory"),
+            #[cfg(feature = "archives")]
+            Self::LookInside => write!(f, "--look-inside"),
+            Self::Exclude => write!(f, "exclude"),
+            Self::Ext => write!(f, "--ext"),
+            Self::Type => write!(f, "--type"),
+            Self::Metadata(flag, _) => write!(f, "{}", flag),
+            Self::Pattern => write!(f, "pattern"),
+            Self::Tags => write!(f, "--tags"),
+            Self::AnyOf => write!(f, "--any-of"),
+            Self::AllOf(tag) => write!(f, "--all-of {}", tag),
+            Self::NoneOf(tag) => write!(f, "--none-of {}", tag),
+            Self::Group(group) => write!(f, "{}()", group),
+            Self::Color(color) => write!(f, "color({})", color),
+        }
+    }
+}
+
+/// The clauses of a search, in the order that they are applied, and what they
+/// are checked with
+pub(crate) struct SearchFilter<'a> {
+    app:     &'a App,
+    opts:    &'a SearchOpts,
+    re:      &'a Regex,
+    exclude: Regex,
+    clauses: Vec<Clause>,
+}
+
+impl<'a> SearchFilter<'a> {
+    /// The clauses of the search of `opts` with the pattern `re`
+    pub(crate) fn new(app: &'a App, opts: &'a SearchOpts, re: &'a Regex) -> Self {
+        let mut clauses = vec![Clause::Directory];
+        #[cfg(feature = "archives")]
+        clauses.push(Clause::LookInside);
+        if !app.exclude.is_empty() {
+            clauses.push(Clause::Exclude);
+        }
+        if app.extension.is_some() {
+            clauses.push(Clause::Ext);
+        }
+        if app.file_type.is_some() {
+            clauses.push(Clause::Type);
+        }
+        if let Some(ref filter) = app.metadata_filter {
+            // Each filter is its own clause, so that the one that fails is known
+            let parts = [
+                ("--size", MetadataFilter {
+                    sizes: filter.sizes.clone(),
+                    ..MetadataFilter::default()
+                }),
+                ("--owner", MetadataFilter {
+                    owner: filter.owner,
+                    ..MetadataFilter::default()
+                }),
+                ("--perm", MetadataFilter {
+                    perm: filter.perm,
+                    ..MetadataFilter::default()
+                }),
+            ];
+            clauses.extend(
+                parts
+                    .iter()
+                    .filter(|(_, part)| !part.is_empty())
+                    .map(|(flag, part)| Clause::Metadata(*flag, part.clone())),
+            );
+        }
+        clauses.push(Clause::Pattern);
+
+        if !opts.tags.is_empty() {
+            clauses.push(Clause::Tags);
+        }
+        if !opts.tag_sets.any_of.is_empty() {
+            clauses.push(Clause::AnyOf);
+        }
+        clauses.extend(opts.tag_sets.all_of.iter().cloned().map(Clause::AllOf));
+        clauses.extend(opts.tag_sets.none_of.iter().cloned().map(Clause::NoneOf));
+        clauses.extend(opts.groups.iter().cloned().map(Clause::Group));
+        clauses.extend(opts.colors.iter().cloned().map(Clause::Color));
+
+        Self {
+            app,
+            opts,
+            re,
+            exclude: regex_builder(
+                app.exclude.join("|").as_str(),
+                app.case_insensitive,
+                app.case_sensitive,
+            ),
+            clauses,
+        }
+    }
+
+    /// The clauses of the search
+    pub(crate) fn clauses(&self) -> &[Clause] {
+        &self.clauses
+    }
+
+    /// Whether the file with the entry `id` at `path` matches the `clause`.
+    /// Encrypted tags are decrypted to be matched like the others
+    pub(crate) fn matches(&self, clause: &Clause, id: EntryId, path: &Path) -> bool {
+        let app = self.app;
+        let bytes = || osstr_to_bytes(path.as_os_str());
+        let has = |tag: &String| app.registry.entry_has_any_tags(id, slice::from_ref(tag));
+
+        match clause {
+            Clause::Directory => app.global || contained_path(path, &app.base_dir),
+            #[cfg(feature = "archives")]
+            Clause::LookInside =>
+                self.opts.look_inside || crate::archive::split_member(path).is_none(),
+            Clause::Exclude => !self.exclude.is_match(&bytes()),
+            Clause::Ext => app
+                .extension
+                .as_ref()
+                .map_or(true, |ext| ext.is_match(&bytes())),
+            Clause::Type => app
+                .file_type
+                .as_ref()
+                .map_or(true, |file_types| !file_types.should_ignore(&path)),
+            Clause::Metadata(_, filter) => filter.is_match(&path),
+            Clause::Pattern => self.re.is_match(&bytes()),
+            Clause::Tags => {
+                let names = app.tag_names(id, path);
+                let tags = &self.opts.tags;
+                if self.opts.only_all {
+                    names == tags.iter().cloned().collect::<BTreeSet<_>>()
+                } else if self.opts.all {
+                    tags.iter().all(|t| names.contains(t))
+                } else {
+                    tags.iter().any(|t| names.contains(t))
+                }
+            },
+            Clause::AnyOf => app
+                .registry
+                .entry_has_any_tags(id, &self.opts.tag_sets.any_of),
+            Clause::AllOf(tag) => has(tag),
+            Clause::NoneOf(tag) => !has(tag),
+            Clause::Group(group) => app
+                .exclusive_groups
+                .get(group)
+                .map_or(false, |tags| app.registry.entry_has_any_tags(id, tags)),
+            Clause::Color(color) => app
+                .registry
+                .list_entry_tags(id)
+                .unwrap_or_default()
+                .iter()
+                .any(|t| color_matches(*t.color(), color)),
+        }
+    }
+
+    /// Whether the file with the entry `id` at `path` matches every clause
+    pub(crate) fn is_match(&self, id: EntryId, path: &Path) -> bool {
+        self.clauses
+            .iter()
+            .all(|clause| self.matches(clause, id, path))
+    }
+}
+
 impl App {
+    /// Expand the `color(<color>)` function and the `<group>()` and
+    /// `<group>(<tag>)` accessors given to `--tags`
+    fn expand_accessors(&self, opts: &SearchOpts) -> SearchOpts {
+        let mut opts = opts.clone();
+        let mut tags = Vec::new();
+
+        for tag in &opts.tags {
+            match tag.strip_suffix(')').and_then(|t| t.split_once('(')) {
+                Some(("color", color)) => {
+                    if color.is_empty() {
+                        wutag_fatal!("{} requires a color", "color()".bold());
+                    }
+                    opts.colors.push(color.to_string());
+                },
+                Some((group, inner)) => {
+                    let members = self.exclusive_groups.get(group).unwrap_or_else(|| {
+                        wutag_fatal!("{} is not a tag group", group.bold());
+                    });
+                    if inner.is_empty() {
+                        opts.groups.push(group.to_string());
+                    } else if members.iter().any(|t| t == inner) {
+                        tags.push(inner.to_string());
+                    } else {
+                        wutag_fatal!("{} is not in the tag group {}", inner.bold(), group.bold());
+                    }
+                },
+                None => tags.push(tag.clone()),
+            }
+        }
+
+        opts.tags = tags;
+        opts
+    }
+
     pub(crate) fn search(&self, opts: &SearchOpts) {
         log::debug!("SearchOpts: {:#?}", opts);
         log::debug!("Using registry: {}", self.registry.path.display());
@@ -111,22 +575,379 @@ impl App {
 
         #[allow(clippy::manual_map)]
         let command = if let Some(cmd) = &opts.execute {
-            Some(CommandTemplate::new(cmd))
+            Some(CommandTemplate::new(cmd).with_output(!opts.no_buffer, self.verbose))
         } else if let Some(cmd) = &opts.execute_batch {
             Some(CommandTemplate::new_batch(cmd).expect("Invalid batch command"))
         } else {
             None
         };
 
-        let app = Arc::new(self.clone());
-        let opts = Arc::new(opts.clone());
+        let mut opts = self.expand_accessors(opts);
+        // The tags may only exist in the other registries
+        if !opts.all_registries {
+            let sets = &opts.tag_sets;
+            for tags in &[&opts.tags, &sets.any_of, &sets.all_of, &sets.none_of] {
+                self.warn_similar_tags(tags);
+            }
+        }
+        if self.highlight.is_some() {
+            opts.highlight = highlight_builder(&opts.pattern, self.pat_regex);
+        }
+        if let Some(ref path) = opts.explain_misses {
+            self.explain_misses(&opts, &re, path);
+            return;
+        }
         let re = Arc::new(re);
         let command = command.map(Arc::new);
 
-        let (tx, rx) = channel::unbounded::<WorkerResult>();
+        if opts.watch {
+            self.watch_search(&opts, &re, &command);
+        } else if opts.all_registries {
+            let mut remaining = opts.max_results;
+            for (name, registry) in self.load_registries() {
+                if remaining == Some(0) {
+                    break;
+                }
+
+                let mut app = self.clone();
+                app.registry = registry;
+                app.hide_expired();
+                let mut opts = opts.clone();
+                opts.registry_name = Some(name);
+                opts.max_results = remaining;
+
+                let found = run_search(&Arc::new(app), &Arc::new(opts), &re, command.clone());
+                diagnostics::matched(found);
+                remaining = remaining.map(|max| max.saturating_sub(found));
+            }
+        } else {
+            let mut app = self.clone();
+            app.hide_expired();
+            let found = run_search(&Arc::new(app), &Arc::new(opts), &re, command);
+            diagnostics::matched(found);
+        }
+    }
+
+    /// Check each part of the search against the file at `path` in the same
+    /// order as [`sender`], and display the ones that it does not match
+    fn explain_misses(&self, opts: &SearchOpts, re: &Regex, path: &Path) {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| self.base_dir.join(path));
+        println!("{}:", fmt_path(&path, self.base_color, self.ls_colors));
+
+        let id = match self.registry.find_entry(&path) {
+            Some(id) => id,
+            None => {
+                print_clause(
+                    "registry",
+                    Some(String::from("the file is not in the registry")),
+                );
+                diagnostics::matched(0);
+                return;
+            },
+        };
+        let entry = &self.registry.entries[&id];
+
+        let mut app = self.clone();
+        app.hide_expired();
+        let now = Local::now().naive_local();
+        let expired = self
+            .registry
+            .expired_tags(now)
+            .into_iter()
+            .find(|(expired_id, _)| *expired_id == id)
+            .map(|(_, tags)| tags)
+            .unwrap_or_default();
+
+        // Tags that expired are hidden from the search, as if they were missing
+        let describe = |name: &str| match expired.iter().find(|t| t.name() == name) {
+            Some(tag) => format!(
+                "'{}' (expired {})",
+                name,
+                self.registry.get_value(tag.name(), id).unwrap_or_default()
+            ),
+            None => format!("'{}'", name),
+        };
+        let none_of = |tags: &[String]| {
+            format!(
+                "it has none of {}",
+                tags.iter()
+                    .map(|t| describe(t))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        };
+        let reason = |clause: &Clause| match clause {
+            Clause::Directory => format!(
+                "it is outside of {} (use '--global')",
+                self.base_dir.display()
+            ),
+            #[cfg(feature = "archives")]
+            Clause::LookInside => String::from("it is inside of an archive"),
+            Clause::Exclude => format!("it matches one of {}", self.exclude.join(", ")),
+            Clause::Ext => String::from("its extension is not one of them"),
+            Clause::Type => String::from("it is not one of the types"),
+            Clause::Metadata(..) => String::from("its metadata is out of range or unreadable"),
+            Clause::Pattern => format!("its path does not match '{}'", opts.pattern),
+            Clause::Tags if opts.only_all =>
+                format!("it does not have only the tags {}", opts.tags.join(", ")),
+            Clause::Tags if opts.all => {
+                let names = app.tag_names(id, entry.path());
+                opts.tags
+                    .iter()
+                    .find(|t| !names.contains(*t))
+                    .map_or_else(String::new, |t| format!("it does not have {}", describe(t)))
+            },
+            Clause::Tags => none_of(&opts.tags),
+            Clause::AnyOf => none_of(&opts.tag_sets.any_of),
+            Clause::AllOf(tag) => format!("it does not have {}", describe(tag)),
+            Clause::NoneOf(tag) => format!("it has the tag '{}'", tag),
+            Clause::Group(group) => format!(
+                "it has none of the tags {}",
+                self.exclusive_groups
+                    .get(group)
+                    .cloned()
+                    .unwrap_or_default()
+                    .join(", ")
+            ),
+            Clause::Color(_) => String::from("none of its tags have the color"),
+        };
+
+        let filter = SearchFilter::new(&app, opts, re);
+        let clauses = filter
+            .clauses()
+            .iter()
+            .map(|clause| {
+                let miss = !filter.matches(clause, id, entry.path());
+                (clause.to_string(), miss.then(|| reason(clause)))
+            })
+            .collect::<Vec<_>>();
+
+        let misses = clauses.iter().filter(|(_, miss)| miss.is_some()).count();
+        for (name, miss) in clauses {
+            print_clause(&name, miss);
+        }
+        if misses == 0 {
+            println!("the file matches the search");
+        } else {
+            println!("{} part(s) of the search do not match the file", misses);
+        }
+        diagnostics::matched(usize::from(misses == 0));
+    }
+
+    /// Run the search each time the registry is saved, or after the interval of
+    /// `--interval`, clearing the screen before each run
+    fn watch_search(
+        &self,
+        opts: &SearchOpts,
+        re: &Arc<Regex>,
+        command: &Option<Arc<CommandTemplate>>,
+    ) -> ! {
+        let mut watch = self.watch_registry();
+        let poll = Duration::from_secs_f64(WATCH_POLL_SECS);
+        let interval = opts
+            .interval
+            .map(|secs| Duration::from_secs_f64(secs.max(0.1)));
+        let mut app = self.clone();
+
+        loop {
+            let header = format!(
+                "wutag search {}: {}",
+                opts.pattern,
+                self.registry.path.display()
+            );
+            let time = Local::now().format("%H:%M:%S").to_string();
+            // Clear the screen and move to the top left corner
+            print!("\x1b[2J\x1b[H");
+            if opts.raw {
+                println!("{}  {}\n", header, time);
+            } else {
+                println!("{}  {}\n", header.bold(), time.green());
+            }
+
+            // Tags can expire between two runs
+            app.hide_expired();
+            run_search(
+                &Arc::new(app.clone()),
+                &Arc::new(opts.clone()),
+                re,
+                command.clone(),
+            );
+            io::stdout().flush().ok();
+
+            let started = Instant::now();
+            loop {
+                thread::sleep(poll);
+
+                if let Some(registry) = watch.reload() {
+                    app.registry = registry;
+                    break;
+                }
+                if interval.map_or(false, |i| started.elapsed() >= i) {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Load every registry from the configuration file. Encrypted registries are
+    /// skipped, since decrypting them would modify the file. Each one is loaded
+    /// when it is reached, so only one is held in memory at a time
+    fn load_registries(&self) -> impl Iterator<Item = (String, TagRegistry)> + '_ {
+        if self.registries.is_empty() {
+            wutag_error!("no registries were found in the configuration file");
+        }
+
+        self.registries
+            .iter()
+            .filter_map(|(name, path)| {
+                let path = shellexpand::full(&path.display().to_string())
+                    .map_or_else(|_| path.clone(), |p| PathBuf::from(p.as_ref()));
+
+                #[cfg(feature = "encrypt-gpgme")]
+                if crate::registry::is_encrypted(&path) {
+                    wutag_error!("{}: registry is encrypted, skipping", name.bold());
+                    return None;
+                }
+
+                match TagRegistry::load(&path, &EncryptConfig::default()) {
+                    Ok(registry) => Some((name.clone(), registry)),
+                    Err(e) => {
+                        wutag_error!("{}: {} ({})", name.bold(), e, path.display());
+                        None
+                    },
+                }
+            })
+    }
+}
+
+/// Display whether the part of a search called `name` matched a file, or the
+/// reason that it did not
+fn print_clause(name: &str, miss: Option<String>) {
+    match miss {
+        Some(reason) => println!("  {} {}: {}", "miss".red().bold(), name.bold(), reason),
+        None => println!("  {}   {}", "ok".green().bold(), name),
+    }
+}
+
+/// Search a single registry, sending the results to the receiver. Returns the
+/// number of files that were found
+fn run_search(
+    app: &Arc<App>,
+    opts: &Arc<SearchOpts>,
+    re: &Arc<Regex>,
+    command: Option<Arc<CommandTemplate>>,
+) -> usize {
+    // Bounded so that the results wait for the printer or the command instead of
+    // piling up in memory
+    let (tx, rx) = channel::bounded::<WorkerResult>(ENTRY_PAGE_SIZE);
+
+    let rec = receiver(app, opts, command, rx);
+    let found = sender(app, opts, re, tx);
+    rec.join().unwrap();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        opt::{Command, Opts},
+        registry::EntryData,
+        storage::MEMORY,
+    };
+    use clap::Parser;
+    use colored::Color::Black;
+    use wutag_core::tag::Tag;
+
+    #[test]
+    fn matches_tag_sets() -> anyhow::Result<()> {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let sets = TagSets::from_args(&args(&[
+            "--any-of",
+            "a,b",
+            "--all-of=work,urgent",
+            "--none-of",
+            "done",
+        ]))?;
+        assert_eq!(sets.any_of, vec!["a", "b"]);
+        assert_eq!(sets.all_of, vec!["work", "urgent"]);
+        assert_eq!(sets.none_of, vec!["done"]);
+        assert!(TagSets::from_args(&args(&["--all", "work"])).is_err());
+        assert!(TagSets::from_args(&args(&["--none-of"])).is_err());
+
+        fn tagged(
+            registry: &mut TagRegistry,
+            path: &str,
+            tags: &[&str],
+        ) -> anyhow::Result<EntryId> {
+            let id = registry.add_or_update_entry(EntryData::new(path)?);
+            for tag in tags {
+                registry.tag_entry(&Tag::new(tag, Black), id);
+            }
+            Ok(id)
+        }
+
+        let mut registry = TagRegistry::new_in_memory();
+        let fst = tagged(&mut registry, "/tmp", &["a", "work", "urgent"])?;
+        let snd = tagged(&mut registry, "/", &["b", "work", "urgent", "done"])?;
+        let thd = tagged(&mut registry, "/usr", &["work", "urgent"])?;
+
+        assert!(sets.matches(&registry, fst));
+        assert!(!sets.matches(&registry, snd));
+        assert!(!sets.matches(&registry, thd));
+        assert!(TagSets::default().matches(&registry, thd));
+
+        Ok(())
+    }
+
+    #[test]
+    fn misses_only_the_changed_clause() -> anyhow::Result<()> {
+        let args = [
+            "wutag", "-g", "-R", MEMORY, "search", "*", "--all-of", "work", "--none-of", "done",
+            "-t", "urgent",
+        ];
+        let opts = Opts::try_parse_from(&args)?;
+        let search = match opts.cmd {
+            Command::Search(ref search) => search.clone(),
+            _ => unreachable!("parsed as a search"),
+        };
+        let mut app = App::new(&opts, Config::default())?;
+        let id = app.registry.add_or_update_entry(EntryData::new("/tmp")?);
+        app.registry.tag_entry(&Tag::new("work", Black), id);
+        app.registry.tag_entry(&Tag::new("urgent", Black), id);
+
+        let any = regex_builder(&glob_builder("*"), false, false);
+        let misses = |app: &App, search: &SearchOpts, re: &Regex| {
+            let filter = SearchFilter::new(app, search, re);
+            filter
+                .clauses()
+                .iter()
+                .filter(|clause| !filter.matches(clause, id, Path::new("/tmp")))
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert!(misses(&app, &search, &any).is_empty());
+
+        let md = regex_builder(&glob_builder("*.md"), false, false);
+        assert_eq!(misses(&app, &search, &md), vec!["pattern"]);
+
+        let mut changed = search.clone();
+        changed.tags = vec![String::from("later")];
+        assert_eq!(misses(&app, &changed, &any), vec!["--tags"]);
+
+        let mut changed = search.clone();
+        changed.tag_sets.all_of.push(String::from("later"));
+        assert_eq!(misses(&app, &changed, &any), vec!["--all-of later"]);
+
+        app.registry.tag_entry(&Tag::new("done", Black), id);
+        assert_eq!(misses(&app, &search, &any), vec!["--none-of done"]);
+
+        app.global = false;
+        app.registry.untag_by_name("done", id);
+        app.base_dir = PathBuf::from("/usr");
+        assert_eq!(misses(&app, &search, &any), vec!["directory"]);
 
-        let rec = receiver(&app, &opts, command, rx);
-        sender(&app, &opts, &re, tx);
-        rec.join().unwrap();
+        Ok(())
     }
 }
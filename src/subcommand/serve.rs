@@ -0,0 +1,70 @@
+use super::{
+    uses::{remote, wutag_fatal, wutag_warning, Args, Colorize, Config},
+    App,
+};
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct ServeOpts {
+    /// Serve the registry over HTTP on the given address
+    #[clap(
+        long = "http",
+        value_name = "addr",
+        min_values = 0,
+        require_equals = true,
+        default_value = remote::DEFAULT_ADDRESS,
+        default_missing_value = remote::DEFAULT_ADDRESS,
+        long_about = "Serve the registry over HTTP on the given address. Other machines can use \
+                      the registry by setting 'registry' in the configuration file, or \
+                      '--registry', to 'http://<addr>'"
+    )]
+    pub(crate) http: String,
+}
+
+impl App {
+    /// Share the registry with other machines. Runs until interrupted
+    pub(crate) fn serve(&self, opts: &ServeOpts, config: &Config) {
+        log::debug!("ServeOpts: {:#?}", opts);
+        log::debug!("Using registry: {}", self.registry.path.display());
+
+        if self.registry.remote().is_some() {
+            wutag_fatal!("a remote registry cannot be served");
+        }
+
+        #[cfg(feature = "encrypt-gpgme")]
+        if self.encrypt.to_encrypt {
+            wutag_fatal!("an encrypted registry cannot be served");
+        }
+
+        #[cfg(feature = "auth")]
+        let tokens = crate::auth::server_tokens().unwrap_or_else(|e| wutag_fatal!("{:#}", e));
+        #[cfg(not(feature = "auth"))]
+        let tokens = std::collections::BTreeMap::new();
+
+        if tokens.is_empty() {
+            wutag_warning!(
+                "there are no tokens, so anyone who can connect can read and replace the \
+                 registry. {}",
+                if cfg!(feature = "auth") {
+                    "Add one with 'wutag auth add <name>'"
+                } else {
+                    "Build wutag with the 'auth' feature to require them"
+                }
+            );
+        }
+
+        let acl = &config.serve;
+        for (tag, names) in &acl.tag_writers {
+            for name in names.iter().filter(|n| !tokens.contains_key(*n)) {
+                wutag_warning!(
+                    "there is no token named {}, which is allowed to change the tag {}",
+                    name.bold(),
+                    tag.bold()
+                );
+            }
+        }
+
+        if let Err(e) = remote::serve(&opts.http, &self.registry.path, &tokens, acl) {
+            wutag_fatal!("{}", e);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! User-level systemd units that maintain the registry on a schedule. The
+//! service expires tags whose date has passed and updates the hashes of
+//! modified files, and the timer runs it. It sends a desktop notification of
+//! the kinds of change that are enabled in the `notify` section of the
+//! configuration
+
+// TODO: add `--watch <dir>` once there is a watcher that can be run as a
+//       service
+
+use super::{
+    uses::{
+        fs, process, wutag_error, wutag_fatal, wutag_info, wutag_warning, Args, Colorize, Context,
+        PathBuf, Result, Subcommand,
+    },
+    App,
+};
+
+use anyhow::anyhow;
+
+/// Name of the units, without the extension
+const SERVICE_NAME: &str = "wutag-maintain";
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub(crate) enum ServiceObject {
+    /// Write the systemd units and enable the timer
+    Install {
+        /// How often the registry is maintained
+        #[clap(
+            long,
+            value_name = "when",
+            default_value = "weekly",
+            possible_values = &["daily", "weekly", "monthly"]
+        )]
+        maintain: String,
+    },
+    /// Disable the timer and remove the systemd units
+    Uninstall,
+    /// Display the status of the timer
+    Status,
+}
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct ServiceOpts {
+    /// The action to take. Valid values are: 'install', 'uninstall', 'status'
+    #[clap(subcommand)]
+    pub(crate) object: ServiceObject,
+}
+
+/// Directory that user-level systemd units are read from
+fn unit_dir() -> Result<PathBuf> {
+    dirs::config_dir()
+        .map(|d| d.join("systemd").join("user"))
+        .context("unable to find the configuration directory")
+}
+
+/// Send a desktop notification of a change made while maintaining the
+/// registry. The service has no terminal, so a failure is only warned about
+pub(crate) fn notify(summary: &str, body: &str) {
+    // `notify_rust` segfaults when it is built with musl
+    #[cfg(not(target_env = "musl"))]
+    if let Err(e) = notify_rust::Notification::new()
+        .appname("wutag")
+        .summary(summary)
+        .body(body)
+        .icon("emblem-system")
+        .show()
+    {
+        wutag_warning!("unable to send a notification: {}", e);
+    }
+
+    #[cfg(target_env = "musl")]
+    log::debug!(
+        "notifications are not sent with musl: {}: {}",
+        summary,
+        body
+    );
+}
+
+/// Run `systemctl --user` with the given `args`
+fn systemctl(args: &[&str]) -> Result<()> {
+    let status = process::Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .status()
+        .context("failed to run systemctl")?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!("'systemctl --user {}' failed", args.join(" ")))
+    }
+}
+
+impl App {
+    /// The service and timer units that maintain the registry `when` (e.g.,
+    /// `weekly`)
+    fn service_units(&self, when: &str) -> Result<(String, String)> {
+        let exe = std::env::current_exe().context("unable to find the wutag executable")?;
+        let command = |subcommand: &str| {
+            format!(
+                "ExecStart=\"{}\" --global --quiet --registry \"{}\" {}",
+                exe.display(),
+                self.registry.path.display(),
+                subcommand
+            )
+        };
+
+        let service = format!(
+            "[Unit]\nDescription=Maintain the wutag registry\n\n[Service]\nType=oneshot\n{}\n{}\n",
+            command("expire --notify"),
+            command("repair --notify")
+        );
+        let timer = format!(
+            "[Unit]\nDescription=Maintain the wutag registry {}\n\n[Timer]\nOnCalendar={}\n\
+             Persistent=true\n\n[Install]\nWantedBy=timers.target\n",
+            when, when
+        );
+
+        Ok((service, timer))
+    }
+
+    /// Write the units to the user's systemd directory and enable the timer
+    fn install_service(&self, when: &str) -> Result<()> {
+        let dir = unit_dir()?;
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("unable to create directory: {}", dir.display()))?;
+
+        let (service, timer) = self.service_units(when)?;
+        for (ext, unit) in &[("service", service), ("timer", timer)] {
+            let path = dir.join(format!("{}.{}", SERVICE_NAME, ext));
+            fs::write(&path, unit)
+                .with_context(|| format!("failed to write unit: {}", path.display()))?;
+            if !self.quiet {
+                wutag_info!("wrote {}", path.display());
+            }
+        }
+
+        systemctl(&["daemon-reload"])?;
+        systemctl(&["enable", "--now", &format!("{}.timer", SERVICE_NAME)])
+    }
+
+    /// Disable the timer and remove the units
+    fn uninstall_service(&self) -> Result<()> {
+        if let Err(e) = systemctl(&["disable", "--now", &format!("{}.timer", SERVICE_NAME)]) {
+            wutag_error!("{}", e);
+        }
+
+        let dir = unit_dir()?;
+        for ext in &["service", "timer"] {
+            let path = dir.join(format!("{}.{}", SERVICE_NAME, ext));
+            if path.exists() {
+                fs::remove_file(&path)
+                    .with_context(|| format!("failed to remove unit: {}", path.display()))?;
+                if !self.quiet {
+                    wutag_info!("removed {}", path.display());
+                }
+            }
+        }
+
+        systemctl(&["daemon-reload"])
+    }
+
+    pub(crate) fn service(&self, opts: &ServiceOpts) {
+        log::debug!("ServiceOpts: {:#?}", opts);
+
+        let result = match &opts.object {
+            ServiceObject::Install { maintain } => self.install_service(maintain),
+            ServiceObject::Uninstall => self.uninstall_service(),
+            ServiceObject::Status => systemctl(&[
+                "list-timers",
+                "--all",
+                "--no-pager",
+                &format!("{}.timer", SERVICE_NAME),
+            ]),
+        };
+
+        if let Err(e) = result {
+            wutag_fatal!("{}", e);
+        }
+
+        if !self.quiet {
+            match &opts.object {
+                ServiceObject::Install { maintain } => println!(
+                    "{} {}: the registry is maintained {}",
+                    "\u{2714}".green().bold(),
+                    "SERVICE INSTALLED".red().bold(),
+                    maintain.green().bold()
+                ),
+                ServiceObject::Uninstall => println!(
+                    "{} {}",
+                    "\u{2714}".green().bold(),
+                    "SERVICE UNINSTALLED".red().bold()
+                ),
+                ServiceObject::Status => {},
+            }
+        }
+    }
+}
@@ -1,22 +1,37 @@
 use super::{
     uses::{
-        bold_entry, collect_stdin_paths, err, fmt_err, fmt_path, fmt_tag, glob_builder,
-        parse_color, reg_ok, regex_builder, wutag_error, wutag_fatal, Arc, Args, Colorize,
-        DirEntryExt, EntryData, IntoParallelRefIterator, ParallelIterator, Result, Tag, ValueHint,
-        DEFAULT_COLOR,
+        bold_entry, collect_git_modified_paths, collect_stdin_paths, diagnostics, err,
+        expand_value, fmt_err, fmt_path, fmt_tag, glob_builder, io, parse_color, parse_duration,
+        reg_ok, regex_builder, wutag_error, wutag_fatal, wutag_warning, Arc, Args, BTreeMap,
+        Colorize, Context, DirEntryExt, EntryData, IntoParallelRefIterator, ParallelIterator,
+        PathBuf, Result, Tag, ValueHint, Write, XattrNamespace, DEFAULT_COLOR,
     },
     App,
 };
 
+use anyhow::anyhow;
+use chrono::Local;
+use lexiclean::Lexiclean;
+
+#[cfg(feature = "archives")]
+use crate::{archive, journal::Journal};
+#[cfg(feature = "encrypt-gpgme")]
+use crate::encryption::{payload::Payload, util, Recipients};
+use crate::{journal::PendingOp, mounts, util::EXPIRY_FORMAT};
+use std::path::Path;
+
 #[derive(Args, Clone, Debug, PartialEq)]
 pub(crate) struct SetOpts {
     // TODO: Implement/remove
     /// Do not show errors that tag already exists
     #[clap(name = "quiet", long, short = 'q')]
-    quiet:              bool,
+    quiet:                   bool,
     /// Clear all tags before setting them
     #[clap(long, short)]
-    pub(crate) clear:   bool,
+    pub(crate) clear:        bool,
+    /// Do not apply the default tags and values from the configuration file
+    #[clap(long)]
+    pub(crate) no_defaults:  bool,
     /// Explicitly select color for tag
     #[clap(long, short = 'C', takes_value = true,
         validator = |t| parse_color(t)
@@ -24,99 +39,876 @@ pub(crate) struct SetOpts {
                             .map(|_| ())
                             .map_err(|e| e.to_string())
     )]
-    pub(crate) color:   Option<String>,
+    pub(crate) color:        Option<String>,
     #[clap(name = "stdin", long, short = 's')]
-    pub(crate) stdin:   bool,
+    pub(crate) stdin:        bool,
+    /// Set the tags listed in a CSV or TSV file
+    #[clap(
+        name = "from-csv",
+        long = "from-csv",
+        value_name = "file",
+        conflicts_with = "stdin",
+        value_hint = ValueHint::FilePath,
+        long_about = "Set the tags listed in a CSV file, or a TSV file if it has a '.tsv' \
+                      extension. Each row is 'path,tag1 tag2,key=value,...', where the tags are \
+                      separated by spaces and each 'key=value' sets the value of a tag. Every \
+                      row is checked before any are applied, so nothing is changed if a row has \
+                      an error or if one of the files cannot be tagged. Each row also gets the default tags and values of the \
+                      configuration unless '--no-defaults' is given"
+    )]
+    pub(crate) from_csv:     Option<PathBuf>,
+    /// Tag the files that git reports as modified or untracked
+    #[clap(
+        name = "git-modified",
+        long = "git-modified",
+        conflicts_with_all = &["stdin", "from-csv"],
+        long_about = "Tag the files that the git repository in the current directory reports as \
+                      modified (staged or not) or untracked instead of the files matching a \
+                      pattern, so every argument is a tag. Ignored files are skipped, and only \
+                      the files under the current directory are tagged unless '--global' is given"
+    )]
+    pub(crate) git_modified: bool,
+    /// Follow symlinks, overriding 'follow_symlinks'
+    #[clap(
+        long = "follow",
+        conflicts_with = "no-follow",
+        long_about = "Walk into symlinked directories and tag the target of a symlink, which is \
+                      the file its entry in the registry points to. This overrides \
+                      'follow_symlinks' in the configuration"
+    )]
+    pub(crate) follow:       bool,
+    /// Do not follow symlinks, overriding 'follow_symlinks'
+    #[clap(
+        name = "no-follow",
+        long = "no-follow",
+        long_about = "Do not walk into symlinked directories, and tag a symlink itself instead \
+                      of its target. Some systems (e.g., Linux) do not allow symlinks to have \
+                      tags, so they are skipped with a warning. This overrides 'follow_symlinks' \
+                      in the configuration, which is not set by default"
+    )]
+    pub(crate) no_follow:    bool,
+    /// Tag the files inside of zip and tar archives instead of the archives
+    #[cfg(feature = "archives")]
+    #[clap(
+        name = "look-inside",
+        long = "look-inside",
+        long_about = "Tag each file inside of the zip and tar archives that match instead of the \
+                      archives themselves. A single file can be tagged by giving \
+                      '<archive>!/<file>' on stdin. Files inside of archives cannot have extended \
+                      attributes, so they are only tagged in the registry"
+    )]
+    pub(crate) look_inside:  bool,
+    /// Encrypt the tags so that only the recipients can read them
+    #[cfg(feature = "encrypt-gpgme")]
+    #[clap(
+        long,
+        short = 'E',
+        conflicts_with_all = &["from-csv", "clear", "temp"],
+        long_about = "Encrypt the tags to the 'public_key' of the 'encryption' configuration \
+                      before writing them to the files. They are not added to the registry, and \
+                      'list' and 'search' decrypt them when one of the private keys can, or \
+                      display '[locked]' otherwise. Encrypted tags cannot have values"
+    )]
+    pub(crate) encrypt:      bool,
+    /// Remove the tags once this much time has passed (e.g., '2d')
+    #[clap(
+        name = "temp",
+        long = "temp",
+        value_name = "duration",
+        conflicts_with = "from-csv",
+        validator = |d| parse_duration(d).map(|_| ()).map_err(|e| e.to_string()),
+        long_about = "Give each tag a value of 'expires=<time>' that is this long from now, such \
+                      as '30m', '12h', '2d', or '1w2d'. 'search' leaves out the files once the \
+                      time has passed, and 'wutag expire' (which the service from 'wutag \
+                      service install' runs) removes the tags. A tag that is given a value with \
+                      'tag=value' cannot also be temporary"
+    )]
+    pub(crate) temp:         Option<String>,
+    /// Namespace of the extended attributes to keep the tags in
+    #[clap(
+        long,
+        value_name = "namespace",
+        possible_values = &["user", "trusted", "security"],
+        long_about = "Keep the tags in the extended attributes of this namespace (e.g., \
+                      'trusted.wutag') instead of 'user'. Only root can use 'trusted', which can \
+                      tag files that 'user' does not allow, such as symlinks. The namespace is \
+                      recorded in the registry, so every command reading the tags of its files \
+                      uses it"
+    )]
+    pub(crate) namespace:    Option<XattrNamespace>,
     /// A glob pattern like "*.png".
     #[clap(
-        required_unless_present = "stdin", // Would be nice to have a default_value_if_present
+        // Would be nice to have a default_value_if_present
+        required_unless_present_any = &["stdin", "from-csv", "git-modified"],
         value_hint = ValueHint::FilePath,
     )]
-    pub(crate) pattern: String,
-    pub(crate) tags:    Vec<String>,
+    pub(crate) pattern:      Option<String>,
+    /// Tags to set. A value can be given to a tag with `tag=value`
+    pub(crate) tags:         Vec<String>,
+}
+
+/// A row of the file given to `--from-csv`
+#[derive(Debug, Clone, PartialEq)]
+struct CsvRow {
+    path:   PathBuf,
+    tags:   Vec<String>,
+    values: BTreeMap<String, String>,
+}
+
+/// Parse a row of the form `path,tag1 tag2,key=value,...`. A relative path is
+/// relative to `base_dir`, and each `key=value` also sets the tag `key`
+fn parse_csv_row(record: &csv::StringRecord, base_dir: &Path) -> Result<CsvRow> {
+    let mut fields = record.iter().map(str::trim);
+    let path = match fields.next() {
+        Some(path) if !path.is_empty() => base_dir.join(path).lexiclean(),
+        _ => return Err(anyhow!("missing path")),
+    };
+
+    let mut tags = fields
+        .next()
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+
+    let mut values = BTreeMap::new();
+    for field in fields.filter(|f| !f.is_empty()) {
+        match field.split_once('=') {
+            Some((tag, value)) if !tag.is_empty() => {
+                if !tags.iter().any(|t| t == tag) {
+                    tags.push(tag.to_owned());
+                }
+                values.insert(tag.to_owned(), value.to_owned());
+            },
+            _ => return Err(anyhow!("expected key=value: {}", field)),
+        }
+    }
+
+    if tags.is_empty() {
+        return Err(anyhow!("no tags for {}", path.display()));
+    }
+
+    Ok(CsvRow { path, tags, values })
+}
+
+/// Whether the files are given by `--git-modified` or on stdin instead of by
+/// the pattern, which is then another tag. The files of another machine are
+/// always given on stdin, since the pattern would find them on this one
+pub(super) fn paths_given(opts: &SetOpts) -> bool {
+    opts.git_modified
+        || wutag_core::xattr::remote_host().is_some()
+        || ((opts.stdin || atty::isnt(atty::Stream::Stdin)) && atty::is(atty::Stream::Stdout))
 }
 
 impl App {
+    /// The files that are given instead of by the pattern (see [`paths_given`])
+    fn given_paths(&self, opts: &SetOpts) -> Result<Vec<PathBuf>> {
+        let paths = if opts.git_modified {
+            collect_git_modified_paths(&self.base_dir, self.global)?
+        } else {
+            collect_stdin_paths(&self.base_dir)
+        };
+
+        // The other files are routed to the registries of their filesystems
+        Ok(paths
+            .into_iter()
+            .filter(|path| self.filesystems.contains(path))
+            .collect())
+    }
+
+    /// Set the tags listed in a CSV or TSV `file`. Every row is parsed and
+    /// checked first, so that nothing is changed if any row has an error, and
+    /// the files are given back their tags if one of them cannot be tagged
+    fn set_from_csv(&mut self, file: &Path, opts: &SetOpts) -> Result<()> {
+        let delimiter = match file.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("tsv") => b'\t',
+            _ => b',',
+        };
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .comment(Some(b'#'))
+            .delimiter(delimiter)
+            .from_path(file)
+            .with_context(|| format!("unable to open {}", file.display()))?;
+
+        let mut rows = Vec::new();
+        let mut failed = 0_usize;
+        for (idx, record) in reader.records().enumerate() {
+            let line = record
+                .as_ref()
+                .ok()
+                .and_then(csv::StringRecord::position)
+                .map_or(idx as u64 + 1, csv::Position::line);
+            let row = record
+                .map_err(|e| anyhow!(e))
+                .and_then(|r| parse_csv_row(&r, &self.base_dir))
+                .and_then(|row| {
+                    row.path
+                        .symlink_metadata()
+                        .with_context(|| format!("no such file: {}", row.path.display()))?;
+                    Ok(row)
+                });
+
+            match row {
+                Ok(row) => rows.push(row),
+                Err(e) => {
+                    failed += 1;
+                    wutag_error!("{}:{}: {}", file.display(), line, e);
+                },
+            }
+        }
+
+        if failed > 0 {
+            return Err(anyhow!(
+                "{} row(s) of {} have errors, no tags were set",
+                failed,
+                file.display()
+            ));
+        }
+
+        // The other files are routed to the registries of their filesystems
+        rows.retain(|row| self.filesystems.contains(&row.path));
+
+        // Each row gets the defaults of the configuration like any other `set`
+        let rows = rows
+            .iter()
+            .map(|row| {
+                let tags = row
+                    .tags
+                    .iter()
+                    .map(|name| self.resolve_tag(name, &opts.color))
+                    .collect::<Vec<_>>();
+                let (defaults, mut values) = self.defaults(&tags, opts);
+                values.extend(row.values.clone());
+                (&row.path, tags, defaults, values)
+            })
+            .collect::<Vec<_>>();
+
+        // The tags that each file has now are kept, so that the rows are set as
+        // one change and the files can be put back if any of them fails
+        let ops = rows
+            .iter()
+            .map(|(path, tags, defaults, values)| {
+                self.pending_op(path, opts.clear, tags, defaults, values)
+            })
+            .collect::<Vec<PendingOp>>();
+        let mut journal = self.begin_journal();
+        for op in &ops {
+            self.record_op(&mut journal, op);
+        }
+        self.commit_journal(&mut journal);
+
+        let registry = self.registry.clone();
+        let mut failure = None;
+        for (idx, (path, tags, defaults, values)) in rows.iter().enumerate() {
+            if !self.quiet {
+                println!("{}:", fmt_path(path, self.base_color, self.ls_colors));
+            }
+
+            if opts.clear {
+                if let Some(id) = self.registry.find_entry(path) {
+                    self.registry.clear_entry(id);
+                }
+                if let Err(e) = path.clear_tags() {
+                    failure = Some((idx, e.to_string()));
+                    break;
+                }
+            }
+
+            let missing = defaults
+                .iter()
+                .filter(|t| path.get_tag(t.name()).is_err())
+                .cloned()
+                .collect::<Vec<_>>();
+
+            for tag in tags.iter().chain(missing.iter()) {
+                if let Err(e) = self.write_tag(path, tag) {
+                    failure = Some((idx, mounts::describe(path, &e)));
+                    break;
+                }
+
+                let removed = self.remove_exclusive(path, tag);
+                let entry = match EntryData::new(path) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        failure = Some((idx, e.to_string()));
+                        break;
+                    },
+                };
+                let id = self.registry.add_or_update_entry(entry);
+                for other in &removed {
+                    self.registry.untag_entry(other, id);
+                }
+                self.registry.use_tag(tag, id);
+                if let Some(value) = values.get(tag.name()) {
+                    self.registry.set_value(tag, id, value.clone());
+                }
+                if !self.quiet {
+                    for other in &removed {
+                        print!("\t{} {}", "X".bold().red(), fmt_tag(other));
+                    }
+                    print!("\t{} {}", "+".bold().green(), fmt_tag(tag));
+                }
+            }
+            if !self.quiet {
+                println!();
+            }
+            if failure.is_some() {
+                break;
+            }
+        }
+
+        // The files of the rows so far get back their tags, and the registry is
+        // not saved
+        if let Some((idx, e)) = failure {
+            for op in ops[..=idx].iter().rev() {
+                if !self.quiet {
+                    println!("{}:", fmt_path(&op.path, self.base_color, self.ls_colors));
+                }
+                self.rollback_op(op);
+                if !self.quiet {
+                    println!();
+                }
+            }
+            self.registry = registry;
+            self.finish_journal(journal);
+
+            return Err(anyhow!(
+                "unable to tag {}: {}, the files before it were given back their tags",
+                rows[idx].0.display(),
+                e
+            ));
+        }
+
+        log::debug!("Saving registry...");
+        self.save_registry();
+        self.finish_journal(journal);
+
+        Ok(())
+    }
+
+    /// Tags and values from the configuration file, unless `--no-defaults` is
+    /// given. The tags are only added to an entry that does not already have
+    /// them, and none of them is one of the `tags` being set
+    fn defaults(&self, tags: &[Tag], opts: &SetOpts) -> (Vec<Tag>, BTreeMap<String, String>) {
+        if opts.no_defaults {
+            return (Vec::new(), BTreeMap::new());
+        }
+
+        let defaults = self
+            .default_tags
+            .iter()
+            .chain(self.default_values.keys())
+            .filter(|t| !tags.iter().any(|tag| tag.name() == t.as_str()))
+            .map(|t| self.resolve_tag(t, &opts.color))
+            .collect::<Vec<_>>();
+        let values = self
+            .default_values
+            .iter()
+            .map(|(t, v)| (t.clone(), expand_value(v)))
+            .collect::<BTreeMap<_, _>>();
+        (defaults, values)
+    }
+
+    /// With `check_plurals`, the name of the existing tag that the new tag
+    /// `name` only differs from in number (e.g., `photo` for `photos`) if the
+    /// user chooses to use it. Without a terminal to ask on, it is only a
+    /// warning
+    fn check_plural(&self, name: String) -> String {
+        let existing = match self.registry.inflected_tag(&name) {
+            Some(tag) if self.check_plurals => tag.name().to_owned(),
+            _ => return name,
+        };
+
+        if !atty::is(atty::Stream::Stdin) {
+            wutag_warning!(
+                "{} is a new tag, but {} already exists",
+                name.yellow().bold(),
+                existing.green().bold()
+            );
+            return name;
+        }
+
+        print!(
+            "{} is a new tag, but {} already exists. Use {} instead? [{}/{}] ",
+            name.yellow().bold(),
+            existing.green().bold(),
+            existing.green().bold(),
+            "Y".green().bold(),
+            "n".red().bold()
+        );
+        io::stdout().flush().ok();
+
+        let mut input = String::new();
+        if let Err(e) = io::stdin().read_line(&mut input) {
+            wutag_error!("{}", e);
+            return name;
+        }
+
+        match input.trim().to_lowercase().as_str() {
+            "" | "y" | "yes" => existing,
+            _ => name,
+        }
+    }
+
+    /// Resolve a tag name to an existing tag in the registry, or create a new one
+    pub(crate) fn resolve_tag(&self, name: &str, color: &Option<String>) -> Tag {
+        if let Some(t) = self.registry.get_tag(name) {
+            t.clone()
+        } else if let Some(color) = color {
+            Tag::new(
+                name,
+                parse_color(color).unwrap_or_else(|e| {
+                    wutag_error!("{}", e);
+                    DEFAULT_COLOR
+                }),
+            )
+        } else {
+            Tag::random(name, &self.colors)
+        }
+    }
+
+    /// Tag the `members` of an `archive` in the registry. Members cannot have
+    /// extended attributes, so they are only tagged in the registry
+    #[cfg(feature = "archives")]
+    #[allow(clippy::too_many_arguments)]
+    fn set_members(
+        &mut self,
+        archive: &Path,
+        members: &[String],
+        opts: &SetOpts,
+        tags: &[Tag],
+        defaults: &[Tag],
+        values: &BTreeMap<String, String>,
+        journal: &mut Option<Journal>,
+    ) {
+        let contents = match archive::read_members(archive, members) {
+            Ok(contents) => contents,
+            Err(e) => {
+                wutag_warning!("{} {}", e, bold_entry!(archive));
+                return;
+            },
+        };
+
+        let ops = members
+            .iter()
+            .map(|member| {
+                let path = archive::member_path(archive, member);
+                self.pending_op(&path, opts.clear, tags, defaults, values)
+            })
+            .collect::<Vec<_>>();
+        for op in &ops {
+            self.record_op(journal, op);
+        }
+        self.commit_journal(journal);
+
+        for (member, op) in members.iter().zip(&ops) {
+            if !self.quiet {
+                println!("{}:", fmt_path(&op.path, self.base_color, self.ls_colors));
+            }
+            match contents.get(member) {
+                Some(data) => self.tag_member(op, data, true),
+                None => wutag_warning!(
+                    "{} is not in the archive {}",
+                    member,
+                    bold_entry!(archive)
+                ),
+            }
+            if !self.quiet {
+                println!();
+            }
+        }
+    }
+
+    /// Tag a file inside of an archive, whose content is `data`, in the
+    /// registry with the tags of `op`. Only the uses of tags that are `count`ed
+    /// order them by how recently they were used
+    #[cfg(feature = "archives")]
+    pub(crate) fn tag_member(&mut self, op: &PendingOp, data: &[u8], count: bool) {
+        let path = &op.path;
+        let (archive, member) = match archive::split_member(path) {
+            Some(split) => split,
+            None => return,
+        };
+
+        if op.clear {
+            if let Some(id) = self.registry.find_entry(path) {
+                self.registry.clear_entry(id);
+            }
+        }
+
+        let entry = match EntryData::new_member(&archive, &member, data) {
+            Ok(data) => data,
+            Err(e) => {
+                wutag_warning!("{} {}", e, bold_entry!(path));
+                return;
+            },
+        };
+        let id = self.registry.add_or_update_entry(entry);
+        let current = self
+            .registry
+            .list_entry_tags(id)
+            .unwrap_or_default()
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for tag in op
+            .tags
+            .iter()
+            .chain(op.defaults.iter().filter(|t| !current.contains(t)))
+        {
+            let removed = self
+                .exclusive_tags(tag.name())
+                .into_iter()
+                .filter_map(|name| current.iter().find(|t| t.name() == name))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if count {
+                self.registry.use_tag(tag, id);
+            } else {
+                self.registry.tag_entry(tag, id);
+            }
+            for other in &removed {
+                self.registry.untag_entry(other, id);
+            }
+            if let Some(value) = op.values.get(tag.name()) {
+                self.registry.set_value(tag, id, value.clone());
+            }
+            if !self.quiet {
+                for other in &removed {
+                    print!("\t{} {}", "X".bold().red(), fmt_tag(other));
+                }
+                print!("\t{} {}", "+".bold().green(), fmt_tag(tag));
+            }
+        }
+    }
+
+    /// Encrypt the tags to the recipients of the configuration and write them to
+    /// the matching files. The files are added to the registry so that `list`
+    /// and `search` find them, but the tags are only on the files
+    #[cfg(feature = "encrypt-gpgme")]
+    fn set_encrypted(&mut self, opts: &SetOpts) -> Result<()> {
+        let from_stdin = paths_given(opts);
+        let mut names = opts.tags.clone();
+        if from_stdin {
+            names.extend(opts.pattern.clone());
+        }
+        if names.iter().any(|name| name.contains('=')) {
+            return Err(anyhow!("encrypted tags cannot have values"));
+        }
+
+        let mut ctx =
+            util::context(&self.encrypt).context("failed to get cryptography context")?;
+        let recipients = Recipients::configured(
+            &mut ctx,
+            &self.registry.path,
+            self.encrypt.public_key.as_deref(),
+        )?;
+        let sealed = names
+            .iter()
+            .map(|name| {
+                let tag = self.resolve_tag(name, &opts.color);
+                Payload::seal(&mut ctx, &recipients, &tag).map(|payload| (tag, payload))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let paths = if from_stdin {
+            self.given_paths(opts)?
+        } else {
+            let pattern = opts.pattern.clone().unwrap_or_default();
+            let pat = if self.pat_regex {
+                pattern
+            } else {
+                glob_builder(&pattern)
+            };
+            let re = regex_builder(&pat, self.case_insensitive, self.case_sensitive);
+
+            let mut paths = Vec::new();
+            reg_ok(&Arc::new(re), &Arc::new(self.clone()), |entry| {
+                paths.push(entry.path().to_path_buf());
+            });
+            paths
+        };
+
+        for path in &paths {
+            if !self.quiet {
+                println!("{}:", fmt_path(path, self.base_color, self.ls_colors));
+            }
+
+            for (tag, payload) in &sealed {
+                if let Err(e) = payload.write_to(path) {
+                    mounts::record_error(path, &e);
+                    wutag_warning!("{} {}", mounts::describe(path, &e), bold_entry!(path));
+                    continue;
+                }
+                if !self.quiet {
+                    print!(
+                        "\t{} {} {}",
+                        "+".bold().green(),
+                        fmt_tag(tag),
+                        "(encrypted)".dimmed()
+                    );
+                }
+            }
+
+            let entry = match self.remote_host {
+                Some(ref host) => EntryData::new_remote(host, path),
+                None => EntryData::new(path),
+            };
+            match entry {
+                Ok(data) => {
+                    self.registry.add_or_update_entry(data);
+                },
+                Err(e) => wutag_warning!("{} {}", e, bold_entry!(path)),
+            }
+            if !self.quiet {
+                println!();
+            }
+        }
+
+        log::debug!("Saving registry...");
+        self.save_registry();
+
+        Ok(())
+    }
+
+    /// Write the `tag` to an entry, reading it back afterwards when
+    /// `verify_writes` is set. A failure caused by the filesystem is recorded
+    /// in the table of mount points
+    pub(crate) fn write_tag<E: DirEntryExt>(&self, entry: &E, tag: &Tag) -> wutag_core::Result<()> {
+        let res = entry.tag(tag).and_then(|()| {
+            if self.verify_writes {
+                mounts::verify_write(entry.entry_path(), tag)
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(ref e) = res {
+            mounts::record_error(entry.entry_path(), e);
+        }
+        res
+    }
+
+    /// Remove the tags that are mutually exclusive with `tag` from an entry,
+    /// returning the tags that were removed
+    pub(crate) fn remove_exclusive<E: DirEntryExt>(&self, entry: &E, tag: &Tag) -> Vec<Tag> {
+        let mut removed = Vec::new();
+        for name in self.exclusive_tags(tag.name()) {
+            if let Ok(other) = entry.get_tag(name) {
+                if let Err(e) = entry.untag(&other) {
+                    diagnostics::warn();
+                    wutag_error!("{}", e);
+                } else {
+                    removed.push(other);
+                }
+            }
+        }
+        removed
+    }
+
     pub(crate) fn set(&mut self, opts: &SetOpts) -> Result<()> {
         log::debug!("SetOpts: {:#?}", opts);
         log::debug!("Using registry: {}", self.registry.path.display());
 
+        if let Some(ref file) = opts.from_csv {
+            return self.set_from_csv(file, opts);
+        }
+
+        #[cfg(feature = "encrypt-gpgme")]
+        if opts.encrypt {
+            return self.set_encrypted(opts);
+        }
+
         // Needed because it's not possible (as far as I know) to skip an argument if
         // another is present
         let mut tags = opts.tags.clone();
-        if (opts.stdin || atty::isnt(atty::Stream::Stdin)) && atty::is(atty::Stream::Stdout) {
-            tags.push(opts.pattern.clone());
+        let pattern = opts.pattern.clone().unwrap_or_default();
+        if paths_given(opts) {
+            tags.push(pattern.clone());
         }
 
+        // Tags can be given a value with `tag=value`
+        let mut user_values = BTreeMap::new();
         let tags = tags
-            .par_iter()
-            .map(|t| {
-                if let Some(t) = self.registry.get_tag(t) {
-                    t.clone()
-                } else if let Some(color) = &opts.color {
-                    Tag::new(
-                        t,
-                        parse_color(color).unwrap_or_else(|e| {
-                            wutag_error!("{}", e);
-                            DEFAULT_COLOR
-                        }),
-                    )
-                } else {
-                    Tag::random(t, &self.colors)
-                }
+            .iter()
+            .map(|t| match t.split_once('=') {
+                Some((name, value)) => {
+                    let name = self.check_plural(name.to_string());
+                    user_values.insert(name.clone(), value.to_string());
+                    name
+                },
+                None => self.check_plural(t.clone()),
             })
             .collect::<Vec<_>>();
 
+        // Temporary tags are given the time they expire at as their value
+        if let Some(ref duration) = opts.temp {
+            let expires = parse_duration(duration)
+                .ok()
+                .and_then(|d| Local::now().naive_local().checked_add_signed(d))
+                .unwrap_or_else(|| wutag_fatal!("invalid duration: {}", duration))
+                .format(EXPIRY_FORMAT)
+                .to_string();
+            for tag in &tags {
+                if user_values.contains_key(tag) {
+                    wutag_fatal!("{} has a value, so it cannot be temporary", tag.bold());
+                }
+                user_values.insert(tag.clone(), format!("expires={}", expires));
+            }
+        }
+
+        let tags = tags
+            .par_iter()
+            .map(|t| self.resolve_tag(t, &opts.color))
+            .collect::<Vec<_>>();
+
+        let (defaults, mut values) = self.defaults(&tags, opts);
+        values.extend(user_values);
+        log::debug!("Default tags: {:?}", defaults);
+
         let pat = if self.pat_regex {
-            String::from(&opts.pattern)
+            pattern
         } else {
-            glob_builder(&opts.pattern)
+            glob_builder(&pattern)
         };
 
         log::debug!("Is a TTY?: {}", atty::is(atty::Stream::Stdout));
         let re = regex_builder(&pat, self.case_insensitive, self.case_sensitive);
         log::debug!("Compiled pattern: {}", re);
 
-        if (opts.stdin || atty::isnt(atty::Stream::Stdin)) && atty::is(atty::Stream::Stdout) {
-            log::debug!("Using STDIN");
-            for entry in &collect_stdin_paths(&self.base_dir) {
+        // Collected before the journal is started so that an error leaves nothing
+        // to recover
+        let given_paths = if paths_given(opts) {
+            Some(self.given_paths(opts)?)
+        } else {
+            None
+        };
+
+        // Every file is journaled before any of them is changed. The members of
+        // an archive are journaled once their archive is read
+        #[cfg(feature = "archives")]
+        let by_members = |path: &Path| {
+            opts.look_inside && (archive::split_member(path).is_some() || archive::is_archive(path))
+        };
+        #[cfg(not(feature = "archives"))]
+        let by_members = |_: &Path| false;
+
+        let mut journal = self.begin_journal();
+        if let Some(paths) = given_paths {
+            log::debug!("Using STDIN or git");
+            for entry in paths.iter().filter(|p| !by_members(p)) {
+                self.journal_op(&mut journal, entry, opts.clear, &tags, &defaults, &values);
+            }
+            self.commit_journal(&mut journal);
+
+            for entry in &paths {
+                #[cfg(feature = "archives")]
+                if opts.look_inside {
+                    if let Some((archive, member)) = archive::split_member(entry) {
+                        self.set_members(
+                            &archive,
+                            &[member],
+                            opts,
+                            &tags,
+                            &defaults,
+                            &values,
+                            &mut journal,
+                        );
+                        continue;
+                    } else if archive::is_archive(entry) {
+                        match archive::list_members(entry) {
+                            Ok(members) => self.set_members(
+                                entry,
+                                &members,
+                                opts,
+                                &tags,
+                                &defaults,
+                                &values,
+                                &mut journal,
+                            ),
+                            Err(e) => wutag_warning!("{} {}", e, bold_entry!(entry)),
+                        }
+                        continue;
+                    }
+                }
+
                 if !self.quiet {
                     println!("{}:", fmt_path(entry, self.base_color, self.ls_colors));
                 }
 
-                for tag in &tags {
-                    if opts.clear {
-                        log::debug!(
-                            "Using registry in threads: {}",
-                            self.registry.path.display()
-                        );
-                        if let Some(id) = self.registry.find_entry(entry) {
-                            self.registry.clear_entry(id);
-                        }
-                        match entry.has_tags() {
-                            Ok(has_tags) =>
-                                if has_tags {
-                                    if let Err(e) = entry.clear_tags() {
-                                        wutag_error!("\t{} {}", e, bold_entry!(entry));
-                                    }
-                                },
-                            Err(e) => {
-                                wutag_error!("{} {}", e, bold_entry!(entry));
+                if opts.clear {
+                    log::debug!("Using registry in threads: {}", self.registry.path.display());
+                    let id = match self.remote_host {
+                        Some(ref host) => self
+                            .registry
+                            .find_entry(EntryData::remote_path(host, entry)),
+                        None => self.registry.find_entry(entry),
+                    };
+                    if let Some(id) = id {
+                        self.registry.clear_entry(id);
+                    }
+                    match entry.has_tags() {
+                        Ok(has_tags) =>
+                            if has_tags {
+                                if let Err(e) = entry.clear_tags() {
+                                    diagnostics::warn();
+                                    wutag_error!("\t{} {}", e, bold_entry!(entry));
+                                }
                             },
-                        }
+                        Err(e) => {
+                            diagnostics::warn();
+                            wutag_error!("{} {}", e, bold_entry!(entry));
+                        },
                     }
+                }
 
-                    if let Err(e) = entry.tag(tag) {
+                let missing = defaults
+                    .iter()
+                    .filter(|t| entry.get_tag(t.name()).is_err())
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                for tag in tags.iter().chain(missing.iter()) {
+                    if let Err(e) = self.write_tag(&entry, tag) {
                         log::debug!("Error setting tag for: {}", entry.display());
-                        if !opts.quiet {
-                            wutag_error!("{} {}", e, bold_entry!(entry));
+                        if opts.quiet {
+                            diagnostics::warn();
+                        } else {
+                            wutag_warning!(
+                                "{} {}",
+                                mounts::describe(entry, &e),
+                                bold_entry!(entry)
+                            );
                         }
                     } else {
                         log::debug!("Setting tag for new entry: {}", entry.display());
-                        let entry = EntryData::new(entry)?;
+                        let removed = self.remove_exclusive(&entry, tag);
+                        let entry = match self.remote_host {
+                            Some(ref host) => EntryData::new_remote(host, entry),
+                            None => EntryData::new(entry),
+                        };
+                        let entry = match entry {
+                            Ok(data) => data,
+                            Err(e) => {
+                                wutag_warning!("{} {}", e, bold_entry!(entry));
+                                continue;
+                            },
+                        };
                         let id = self.registry.add_or_update_entry(entry);
-                        self.registry.tag_entry(tag, id);
+                        for other in &removed {
+                            self.registry.untag_entry(other, id);
+                        }
+                        self.registry.use_tag(tag, id);
+                        if let Some(value) = values.get(tag.name()) {
+                            self.registry.set_value(tag, id, value.clone());
+                        }
                         if !self.quiet {
+                            for other in &removed {
+                                print!("\t{} {}", "X".bold().red(), fmt_tag(other));
+                            }
                             print!("\t{} {}", "+".bold().green(), fmt_tag(tag));
                         }
                     }
@@ -126,69 +918,191 @@ impl App {
                 }
             }
         } else {
+            let mut entries = vec![];
             reg_ok(
                 &Arc::new(re),
                 &Arc::new(self.clone()),
-                |entry: &ignore::DirEntry| {
-                    if !self.quiet {
-                        println!(
-                            "{}:",
-                            fmt_path(entry.path(), self.base_color, self.ls_colors)
-                        );
+                |entry: &ignore::DirEntry| entries.push(entry.clone()),
+            );
+
+            for entry in entries.iter().filter(|e| !by_members(e.path())) {
+                self.journal_op(&mut journal, entry.path(), opts.clear, &tags, &defaults, &values);
+            }
+            self.commit_journal(&mut journal);
+
+            for entry in &entries {
+                #[cfg(feature = "archives")]
+                if opts.look_inside && archive::is_archive(entry.path()) {
+                    match archive::list_members(entry.path()) {
+                        Ok(members) => self.set_members(
+                            entry.path(),
+                            &members,
+                            opts,
+                            &tags,
+                            &defaults,
+                            &values,
+                            &mut journal,
+                        ),
+                        Err(e) => wutag_warning!("{} {}", e, entry.path().display()),
                     }
-                    for tag in &tags {
-                        if opts.clear {
-                            log::debug!(
-                                "Using registry in threads: {}",
-                                self.registry.path.display()
-                            );
-                            if let Some(id) = self.registry.find_entry(entry.path()) {
-                                self.registry.clear_entry(id);
-                            }
-                            match entry.has_tags() {
-                                Ok(has_tags) =>
-                                    if has_tags {
-                                        if let Err(e) = entry.clear_tags() {
-                                            err!('\t', e, entry);
-                                        }
-                                    },
-                                Err(e) => {
-                                    err!(e, entry);
-                                },
-                            }
-                        }
+                    continue;
+                }
 
-                        if let Err(e) = entry.tag(tag) {
-                            log::debug!("Error setting tag for: {}", entry.path().display());
-                            // TODO: Make this skip printing path too
-                            if !self.quiet {
-                                err!('\t', e, entry);
-                            }
+                if !self.quiet {
+                    println!(
+                        "{}:",
+                        fmt_path(entry.path(), self.base_color, self.ls_colors)
+                    );
+                }
+
+                if opts.clear {
+                    log::debug!(
+                        "Using registry in threads: {}",
+                        self.registry.path.display()
+                    );
+                    if let Some(id) = self.registry.find_entry(entry.path()) {
+                        self.registry.clear_entry(id);
+                    }
+                    match entry.has_tags() {
+                        Ok(has_tags) =>
+                            if has_tags {
+                                if let Err(e) = entry.clear_tags() {
+                                    err!('\t', e, entry);
+                                }
+                            },
+                        Err(e) => {
+                            err!(e, entry);
+                        },
+                    }
+                }
+
+                let missing = defaults
+                    .iter()
+                    .filter(|t| entry.get_tag(t.name()).is_err())
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                for tag in tags.iter().chain(missing.iter()) {
+                    if let Err(e) = self.write_tag(entry, tag) {
+                        log::debug!("Error setting tag for: {}", entry.path().display());
+                        // TODO: Make this skip printing path too
+                        if self.quiet {
+                            diagnostics::warn();
                         } else {
-                            log::debug!("Setting tag for new entry: {}", entry.path().display());
-                            let entry = if let Ok(data) = EntryData::new(entry.path()) {
-                                data
-                            } else {
-                                wutag_fatal!(
-                                    "unable to create new entry: {}",
-                                    entry.path().display()
+                            let e = mounts::describe(entry.path(), &e);
+                            err!('\t', e, entry);
+                        }
+                    } else {
+                        log::debug!("Setting tag for new entry: {}", entry.path().display());
+                        let removed = self.remove_exclusive(entry, tag);
+                        let entry = match EntryData::new(entry.path()) {
+                            Ok(data) => data,
+                            Err(e) => {
+                                wutag_warning!(
+                                    "unable to create new entry: {} ({})",
+                                    entry.path().display(),
+                                    e
                                 );
-                            };
-                            let id = self.registry.add_or_update_entry(entry);
-                            self.registry.tag_entry(tag, id);
+                                continue;
+                            },
+                        };
+                        let id = self.registry.add_or_update_entry(entry);
+                        for other in &removed {
+                            self.registry.untag_entry(other, id);
+                        }
+                        self.registry.use_tag(tag, id);
+                        if let Some(value) = values.get(tag.name()) {
+                            self.registry.set_value(tag, id, value.clone());
+                        }
+                        if !self.quiet {
+                            for other in &removed {
+                                print!("\t{} {}", "X".bold().red(), fmt_tag(other));
+                            }
                             print!("\t{} {}", "+".bold().green(), fmt_tag(tag));
                         }
                     }
-                    if !self.quiet {
-                        println!();
-                    }
-                    // log::debug!("Saving registry...");
-                    // self.save_registry();
-                },
-            );
+                }
+                if !self.quiet {
+                    println!();
+                }
+            }
         }
         log::debug!("Saving registry...");
         self.save_registry();
+        self.finish_journal(journal);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        journal,
+        opt::{Command, Opts},
+        registry::TagRegistry,
+        storage::MEMORY,
+    };
+    use clap::Parser;
+    use colored::Color::Black;
+    use std::fs;
+
+    #[test]
+    fn parses_csv_rows() -> Result<()> {
+        let base = Path::new("/tmp");
+        let record = csv::StringRecord::from(vec!["a/../b.txt", "work  urgent", "status=draft"]);
+
+        assert_eq!(parse_csv_row(&record, base)?, CsvRow {
+            path:   PathBuf::from("/tmp/b.txt"),
+            tags:   vec!["work".to_owned(), "urgent".to_owned(), "status".to_owned()],
+            values: vec![("status".to_owned(), "draft".to_owned())]
+                .into_iter()
+                .collect(),
+        });
+
+        let record = csv::StringRecord::from(vec!["/srv/c.txt", "", "work=1"]);
+        assert_eq!(parse_csv_row(&record, base)?.tags, vec!["work".to_owned()]);
+
+        assert!(parse_csv_row(&csv::StringRecord::from(vec!["", "work"]), base).is_err());
+        assert!(parse_csv_row(&csv::StringRecord::from(vec!["b.txt", ""]), base).is_err());
+        assert!(parse_csv_row(&csv::StringRecord::from(vec!["b.txt", "a", "b"]), base).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn puts_back_the_rows_when_a_file_cannot_be_tagged() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("file");
+        fs::write(&file, "")?;
+        let old = Tag::new("old", Black);
+        // The filesystem may not support extended attributes
+        if file.as_path().tag(&old).is_err() {
+            return Ok(());
+        }
+        // A symlink to nothing cannot be tagged
+        std::os::unix::fs::symlink(dir.path().join("missing"), dir.path().join("link"))?;
+        let csv = dir.path().join("tags.csv");
+        fs::write(&csv, "file,new\nlink,new\n")?;
+
+        let (base, csv) = (dir.path().display().to_string(), csv.display().to_string());
+        let args = [
+            "wutag", "-q", "-R", MEMORY, "-d", &base, "set", "--clear", "--from-csv", &csv,
+        ];
+        let opts = Opts::try_parse_from(&args)?;
+        let set = match opts.cmd {
+            Command::Set(ref set) => set.clone(),
+            _ => unreachable!("parsed as a set"),
+        };
+        let mut app = App::new(&opts, Config::default())?;
+        app.registry = TagRegistry::new(dir.path().join("wutag.reg"));
+
+        assert!(app.set(&set).is_err());
+        assert_eq!(file.as_path().list_tags()?, vec![old]);
+        assert!(app.registry.find_entry(&file).is_none());
+        assert!(journal::pending(&app.registry.path).is_empty());
 
         Ok(())
     }
@@ -0,0 +1,291 @@
+use super::{
+    uses::{
+        fmt_path, fs, list_tags, wutag_error, wutag_fatal, wutag_info, Args, BTreeMap, Colorize,
+        Context, PathBuf, Result, Subcommand, TagRegistry,
+    },
+    App,
+};
+
+use chrono::Local;
+use std::{
+    collections::BTreeSet,
+    fs::OpenOptions,
+    io::{self, Write},
+};
+
+/// Name of the directory next to the registry that holds the snapshots
+const SNAPSHOT_DIR: &str = "snapshots";
+/// Extension of a snapshot file
+const SNAPSHOT_EXT: &str = "registry";
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub(crate) enum SnapshotObject {
+    /// Save a copy of the registry
+    Create {
+        /// Label that is appended to the name of the snapshot
+        label: Option<String>,
+    },
+    /// Replace the registry with a snapshot and re-tag the files to match
+    Restore {
+        /// Name of the snapshot (see 'snapshot list')
+        name: String,
+    },
+    /// List the snapshots, oldest first
+    List,
+    /// Display what has changed since a snapshot was created
+    Diff {
+        /// Name of the snapshot (see 'snapshot list')
+        name: String,
+    },
+}
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct SnapshotOpts {
+    /// The action to take. Valid values are: 'create', 'restore', 'list', 'diff'
+    #[clap(subcommand)]
+    pub(crate) object: SnapshotObject,
+}
+
+/// The tags on each file of the `registry`, including the tag's value if it
+/// has one (e.g., `tag=value`)
+pub(crate) fn tag_labels(registry: &TagRegistry) -> BTreeMap<PathBuf, BTreeSet<String>> {
+    registry
+        .list_entries_and_ids()
+        .map(|(id, entry)| {
+            let labels = registry
+                .list_entry_tags(*id)
+                .unwrap_or_default()
+                .iter()
+                .map(|tag| match registry.get_value(tag.name(), *id) {
+                    Some(value) => format!("{}={}", tag.name(), value),
+                    None => tag.name().to_owned(),
+                })
+                .collect();
+
+            (entry.path().to_path_buf(), labels)
+        })
+        .collect()
+}
+
+impl App {
+    /// Directory the snapshots are kept in, which is next to the registry
+    pub(crate) fn snapshot_dir(&self) -> PathBuf {
+        self.registry
+            .path
+            .parent()
+            .map_or_else(|| PathBuf::from(SNAPSHOT_DIR), |p| p.join(SNAPSHOT_DIR))
+    }
+
+    /// Path of the snapshot with the given `name`
+    fn snapshot_path(&self, name: &str) -> PathBuf {
+        let name = name.trim_end_matches(&format!(".{}", SNAPSHOT_EXT));
+        self.snapshot_dir().join(format!("{}.{}", name, SNAPSHOT_EXT))
+    }
+
+    /// Names of the snapshots, oldest first
+    pub(crate) fn list_snapshots(&self) -> Vec<String> {
+        let mut names = fs::read_dir(self.snapshot_dir())
+            .map(|dir| {
+                dir.filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|p| p.extension().map_or(false, |ext| ext == SNAPSHOT_EXT))
+                    .filter_map(|p| p.file_stem().map(|s| s.to_string_lossy().to_string()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        names.sort();
+        names
+    }
+
+    /// Load the snapshot with the given `name`
+    fn load_snapshot(&self, name: &str) -> Result<TagRegistry> {
+        let data = fs::read(self.snapshot_path(name))
+            .with_context(|| format!("failed to read snapshot: {}", name))?;
+        let mut registry: TagRegistry =
+            serde_yaml::from_slice(&data).context("failed to deserialize snapshot")?;
+        registry.path = self.registry.path.clone();
+        registry.set_storage(self.registry.storage());
+
+        Ok(registry)
+    }
+
+    /// Save a copy of the registry, removing the oldest snapshots when there
+    /// are more than the configured maximum. Returns the snapshot's name
+    pub(crate) fn create_snapshot(&self, label: Option<&str>) -> Result<String> {
+        let mut name = Local::now().format("%Y%m%d-%H%M%S").to_string();
+        if let Some(label) = label {
+            name.push('-');
+            name.extend(label.chars().map(|c| {
+                if c.is_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '-'
+                }
+            }));
+        }
+
+        let dir = self.snapshot_dir();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("unable to create snapshot directory: {}", dir.display()))?;
+
+        // Names only have a precision of a second, so a snapshot taken in the
+        // same second as another gets a suffix instead of replacing it
+        let mut n = 1;
+        let (name, mut file) = loop {
+            let candidate = match n {
+                1 => name.clone(),
+                n => format!("{}-{}", name, n),
+            };
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(self.snapshot_path(&candidate))
+            {
+                Ok(file) => break (candidate, file),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+                Err(e) => return Err(e).context("failed to save snapshot"),
+            }
+        };
+
+        let serialized =
+            serde_yaml::to_vec(&self.registry).context("failed to serialize registry")?;
+        file.write_all(&serialized)
+            .context("failed to save snapshot")?;
+
+        let snapshots = self.list_snapshots();
+        let excess = snapshots.len().saturating_sub(self.max_snapshots.max(1));
+        for old in snapshots.iter().take(excess) {
+            log::debug!("Removing old snapshot: {}", old);
+            fs::remove_file(self.snapshot_path(old))
+                .with_context(|| format!("failed to remove snapshot: {}", old))?;
+        }
+
+        Ok(name)
+    }
+
+    /// Replace the registry with the snapshot, adding and removing the tags on
+    /// each file so that they match. The current registry is saved as a
+    /// snapshot beforehand so that the restore can be undone
+    fn restore_snapshot(&mut self, name: &str) {
+        let snapshot = self
+            .load_snapshot(name)
+            .unwrap_or_else(|e| wutag_fatal!("{}", e));
+
+        match self.create_snapshot(Some("pre-restore")) {
+            Ok(saved) =>
+                if !self.quiet {
+                    wutag_info!("saved the current registry as {}", saved.bold());
+                },
+            Err(e) => wutag_fatal!("{}", e),
+        }
+
+        let paths = snapshot
+            .list_entries()
+            .chain(self.registry.list_entries())
+            .map(|entry| entry.path().to_path_buf())
+            .collect::<BTreeSet<_>>();
+
+        for path in paths.iter().filter(|p| p.exists()) {
+            let current = list_tags(path).unwrap_or_default();
+            let wanted = snapshot
+                .find_entry(path)
+                .and_then(|id| snapshot.list_entry_tags(id))
+                .unwrap_or_default();
+
+            for tag in current.iter().filter(|t| !wanted.contains(t)) {
+                if let Err(e) = tag.remove_from(path) {
+                    wutag_error!("{} {}", e, path.display());
+                }
+            }
+            for tag in wanted.iter().filter(|t| !current.contains(**t)) {
+                if let Err(e) = tag.save_to(path) {
+                    wutag_error!("{} {}", e, path.display());
+                }
+            }
+        }
+
+        self.registry = snapshot;
+        self.save_registry();
+
+        if !self.quiet {
+            println!(
+                "{} {}: {}",
+                "\u{2714}".green().bold(),
+                "SNAPSHOT RESTORED".red().bold(),
+                name.green().bold()
+            );
+        }
+    }
+
+    /// Display the tags that have been added (`+`) and removed (`X`) from each
+    /// file since the snapshot was created
+    fn diff_snapshot(&self, name: &str) {
+        let snapshot = self
+            .load_snapshot(name)
+            .unwrap_or_else(|e| wutag_fatal!("{}", e));
+
+        let (before, after) = (tag_labels(&snapshot), tag_labels(&self.registry));
+        let empty = BTreeSet::new();
+
+        for path in before.keys().chain(after.keys()).collect::<BTreeSet<_>>() {
+            let old = before.get(path).unwrap_or(&empty);
+            let new = after.get(path).unwrap_or(&empty);
+
+            if old == new {
+                continue;
+            }
+
+            println!("{}:", fmt_path(path, self.base_color, self.ls_colors));
+            for label in old.difference(new) {
+                print!("\t{} {}", "X".bold().red(), label.bold());
+            }
+            for label in new.difference(old) {
+                print!("\t{} {}", "+".bold().green(), label.bold());
+            }
+            println!();
+        }
+    }
+
+    pub(crate) fn snapshot(&mut self, opts: &SnapshotOpts) {
+        log::debug!("SnapshotOpts: {:#?}", opts);
+        log::debug!("Using registry: {}", self.registry.path.display());
+
+        if self.registry.remote().is_some() {
+            wutag_fatal!("snapshots of a remote registry must be taken where it is served");
+        }
+
+        #[cfg(feature = "encrypt-gpgme")]
+        if self.encrypt.to_encrypt {
+            wutag_fatal!("snapshots of an encrypted registry are not supported");
+        }
+
+        match &opts.object {
+            SnapshotObject::Create { label } => match self.create_snapshot(label.as_deref()) {
+                Ok(name) =>
+                    if !self.quiet {
+                        println!(
+                            "{} {}: {}",
+                            "\u{2714}".green().bold(),
+                            "SNAPSHOT CREATED".red().bold(),
+                            name.green().bold()
+                        );
+                    },
+                Err(e) => wutag_error!("{}", e),
+            },
+            SnapshotObject::Restore { name } => self.restore_snapshot(name),
+            SnapshotObject::List =>
+                for name in self.list_snapshots() {
+                    match self.load_snapshot(&name) {
+                        Ok(snapshot) => println!(
+                            "{} ({} files)",
+                            name.green().bold(),
+                            snapshot.list_entries().count()
+                        ),
+                        Err(e) => wutag_error!("{}: {}", name, e),
+                    }
+                },
+            SnapshotObject::Diff { name } => self.diff_snapshot(name),
+        }
+    }
+}
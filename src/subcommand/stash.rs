@@ -0,0 +1,225 @@
+//! Remove the tags from files while keeping a record of them in the registry,
+//! e.g., before sharing a directory or running a tool that does not handle
+//! extended attributes. The tags are put back with `stash pop <name>`
+
+use super::{
+    uses::{
+        clear_tags, contained_path, fmt_path, fmt_tag, glob_builder, osstr_to_bytes, regex_builder,
+        wutag_error, wutag_fatal, wutag_warning, Args, Colorize, EntryData, Subcommand,
+    },
+    App,
+};
+
+use crate::registry::StashedEntry;
+use chrono::Local;
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub(crate) enum StashObject {
+    /// Put the tags of a stash back on its files and remove the stash
+    Pop {
+        /// Name of the stash (see 'stash list')
+        name: String,
+    },
+    /// List the stashes and the number of files in each
+    List,
+}
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct StashOpts {
+    /// The action to take instead of stashing. Valid values are: 'pop', 'list'
+    #[clap(subcommand)]
+    pub(crate) object:  Option<StashObject>,
+    /// Name of the stash (default: the current date and time)
+    #[clap(long, short, value_name = "name")]
+    pub(crate) name:    Option<String>,
+    /// A glob pattern like "*.png"
+    pub(crate) pattern: Option<String>,
+}
+
+impl App {
+    /// Remove the tags from the files matching `pattern`, recording them
+    /// under the stash `name`
+    fn stash_push(&mut self, name: Option<&str>, pattern: &str) {
+        let name = name.map_or_else(
+            || Local::now().format("%Y%m%d-%H%M%S").to_string(),
+            ToOwned::to_owned,
+        );
+        if self.registry.stashes.contains_key(&name) {
+            wutag_fatal!("stash already exists: {}", name.bold());
+        }
+
+        let pat = if self.pat_regex {
+            String::from(pattern)
+        } else {
+            glob_builder(pattern)
+        };
+        let re = regex_builder(&pat, self.case_insensitive, self.case_sensitive);
+
+        let mut stashed = 0_usize;
+        for (id, entry) in self.registry.entries.clone() {
+            if !self.global && !contained_path(entry.path(), &self.base_dir) {
+                continue;
+            }
+            // The tags of a file inside of an archive are only in the registry
+            #[cfg(feature = "archives")]
+            if crate::archive::split_member(entry.path()).is_some() {
+                continue;
+            }
+            if !re.is_match(&osstr_to_bytes(entry.path().as_os_str())) {
+                continue;
+            }
+
+            let tags = self
+                .registry
+                .list_entry_tags(id)
+                .unwrap_or_default()
+                .into_iter()
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if !self.registry.stash_entry(&name, id) {
+                continue;
+            }
+            // The file keeps its tags when they cannot all be removed, so the
+            // ones that were are put back along with its entry
+            if let Err(e) = clear_tags(entry.path()) {
+                wutag_error!("{} {}, its tags are kept", e, entry.path().display());
+                let id = self.registry.add_or_update_entry(entry.clone());
+                if let Some(stashed) = self.registry.unstash_entry(&name, entry.path()) {
+                    for tag in &stashed.tags {
+                        tag.save_to(entry.path()).ok();
+                        self.registry.tag_entry(tag, id);
+                        if let Some(value) = stashed.values.get(tag.name()) {
+                            self.registry.set_value(tag, id, value.clone());
+                        }
+                    }
+                }
+                continue;
+            }
+            stashed += 1;
+
+            if !self.quiet {
+                println!(
+                    "{}:",
+                    fmt_path(entry.path(), self.base_color, self.ls_colors)
+                );
+                for tag in &tags {
+                    print!("\t{} {}", "X".bold().red(), fmt_tag(tag));
+                }
+                println!();
+            }
+        }
+
+        if stashed == 0 {
+            wutag_fatal!("no tagged files match {}", pattern.bold());
+        }
+
+        log::debug!("Saving registry...");
+        self.save_registry();
+
+        if !self.quiet {
+            println!(
+                "{} {}: {} file(s) in {}",
+                "\u{2714}".green().bold(),
+                "STASHED".red().bold(),
+                stashed,
+                name.green().bold()
+            );
+        }
+    }
+
+    /// Put the tags of the stash `name` back on its files. The tags that could
+    /// not be put back stay in the stash, which is removed once it is empty
+    fn stash_pop(&mut self, name: &str) {
+        let stash = self
+            .registry
+            .stashes
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| wutag_fatal!("no stash named {}", name.bold()));
+
+        let mut popped = 0_usize;
+        for (path, stashed) in &stash {
+            let entry = match EntryData::new(path) {
+                Ok(data) => data,
+                Err(e) => {
+                    wutag_warning!("{} {}, its tags are kept in the stash", e, path.display());
+                    continue;
+                },
+            };
+            let id = self.registry.add_or_update_entry(entry);
+
+            if !self.quiet {
+                println!("{}:", fmt_path(path, self.base_color, self.ls_colors));
+            }
+
+            let mut kept = vec![];
+            for tag in &stashed.tags {
+                match tag.save_to(path) {
+                    Ok(()) | Err(wutag_core::Error::TagExists(_)) => {},
+                    Err(e) => {
+                        wutag_error!("{} {}", e, path.display());
+                        kept.push(tag.clone());
+                        continue;
+                    },
+                }
+                self.registry.tag_entry(tag, id);
+                if let Some(value) = stashed.values.get(tag.name()) {
+                    self.registry.set_value(tag, id, value.clone());
+                }
+                if !self.quiet {
+                    print!("\t{} {}", "+".bold().green(), fmt_tag(tag));
+                }
+            }
+
+            if !self.quiet {
+                println!();
+            }
+
+            if kept.is_empty() {
+                self.registry.unstash_entry(name, path);
+                popped += 1;
+            } else if let Some(files) = self.registry.stashes.get_mut(name) {
+                files.insert(path.clone(), StashedEntry {
+                    tags:   kept,
+                    values: stashed.values.clone(),
+                });
+            }
+        }
+
+        log::debug!("Saving registry...");
+        self.save_registry();
+
+        if !self.quiet {
+            println!(
+                "{} {}: {} file(s) from {}",
+                "\u{2714}".green().bold(),
+                "STASH POPPED".red().bold(),
+                popped,
+                name.green().bold()
+            );
+            if popped < stash.len() {
+                println!(
+                    "{} file(s) were kept in {}",
+                    stash.len() - popped,
+                    name.green().bold()
+                );
+            }
+        }
+    }
+
+    pub(crate) fn stash(&mut self, opts: &StashOpts) {
+        log::debug!("StashOpts: {:#?}", opts);
+        log::debug!("Using registry: {}", self.registry.path.display());
+
+        match (&opts.object, &opts.pattern) {
+            (Some(StashObject::Pop { name }), _) => self.stash_pop(name),
+            (Some(StashObject::List), _) =>
+                for (name, files) in &self.registry.stashes {
+                    println!("{}\t{} file(s)", name.bold(), files.len());
+                },
+            (None, Some(pattern)) => self.stash_push(opts.name.as_deref(), pattern),
+            (None, None) => wutag_fatal!("a pattern is required to stash tags"),
+        }
+    }
+}
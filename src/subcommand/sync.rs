@@ -0,0 +1,157 @@
+use super::{
+    uses::{
+        contained_path, fmt_path, fmt_tag, remote, storage, wutag_error, wutag_fatal, Args,
+        Colorize, Context, DirEntryExt, EncryptConfig, EntryData, PathBuf, Result, SyncChange,
+        TagRegistry, ValueHint,
+    },
+    App,
+};
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct SyncOpts {
+    /// Display the changes without modifying any files or the registry
+    #[clap(long = "dry-run", short = 'd')]
+    pub(crate) dry_run: bool,
+
+    /// Registry to merge into the current one. Can be a path or the URL of a
+    /// registry shared with `wutag serve`
+    #[clap(name = "registry", value_hint = ValueHint::FilePath)]
+    pub(crate) registry: String,
+}
+
+impl App {
+    /// Load the registry that is being synced with
+    fn load_sync_registry(&self, registry: &str) -> Result<TagRegistry> {
+        if remote::is_remote(registry) {
+            return storage::open(registry, &EncryptConfig::default()).load();
+        }
+
+        let path = PathBuf::from(
+            shellexpand::full(registry)
+                .with_context(|| format!("failed to expand registry path: {}", registry))?
+                .as_ref(),
+        );
+
+        #[cfg(feature = "encrypt-gpgme")]
+        if crate::registry::is_encrypted(&path) {
+            return Err(anyhow::anyhow!("registry is encrypted: {}", path.display()));
+        }
+
+        TagRegistry::load(&path, &EncryptConfig::default())
+    }
+
+    /// Merge the tags and values of another registry into the current one. Tags
+    /// are unioned, and a conflicting value is taken from the other registry
+    /// only when its entry is newer
+    pub(crate) fn sync(&mut self, opts: &SyncOpts) {
+        log::debug!("SyncOpts: {:#?}", opts);
+        log::debug!("Using registry: {}", self.registry.path.display());
+
+        let other = self
+            .load_sync_registry(&opts.registry)
+            .unwrap_or_else(|e| wutag_fatal!("{}: {}", opts.registry, e));
+
+        let (mut tags, mut values) = (0_usize, 0_usize);
+
+        for (path, changes) in self.registry.sync_changes(&other) {
+            if !self.global && !contained_path(&path, &self.base_dir) {
+                continue;
+            }
+
+            if !path.exists() {
+                if !self.quiet {
+                    wutag_error!("{}: file does not exist, skipping", path.display());
+                }
+                continue;
+            }
+
+            let id = if opts.dry_run {
+                None
+            } else if let Some(id) = self.registry.find_entry(&path) {
+                Some(id)
+            } else {
+                match EntryData::new(&path) {
+                    Ok(entry) => Some(self.registry.add_or_update_entry(entry)),
+                    Err(e) => {
+                        wutag_error!("{} {}", e, path.display());
+                        continue;
+                    },
+                }
+            };
+
+            if !self.quiet {
+                println!("{}:", fmt_path(&path, self.base_color, self.ls_colors));
+            }
+
+            for change in &changes {
+                match change {
+                    SyncChange::Tag(tag) => {
+                        if let Some(id) = id {
+                            let entry = &path;
+                            if entry.get_tag(tag.name()).is_err() {
+                                if let Err(e) = entry.tag(tag) {
+                                    wutag_error!("{} {}", e, path.display());
+                                    continue;
+                                }
+                            }
+                            self.registry.tag_entry(tag, id);
+                        }
+                        tags += 1;
+                        if !self.quiet {
+                            print!("\t{} {}", "+".bold().green(), fmt_tag(tag));
+                        }
+                    },
+                    SyncChange::Value { tag, old, new } => {
+                        if let Some(id) = id {
+                            match self.registry.get_tag(tag).cloned() {
+                                Some(tag) => self.registry.set_value(&tag, id, new.clone()),
+                                None => continue,
+                            }
+                        }
+                        values += 1;
+                        if !self.quiet {
+                            if let Some(old) = old {
+                                print!(
+                                    "\t{} {}={} -> {}",
+                                    "~".bold().yellow(),
+                                    tag.bold(),
+                                    old,
+                                    new
+                                );
+                            } else {
+                                print!("\t{} {}={}", "+".bold().green(), tag.bold(), new);
+                            }
+                        }
+                    },
+                }
+            }
+
+            // An entry that was added for the file but none of whose tags could be
+            // written is not kept
+            if let Some(id) = id {
+                if self.registry.list_entry_tags(id).is_none() {
+                    self.registry.clear_entry(id);
+                }
+            }
+
+            if !self.quiet {
+                println!();
+            }
+        }
+
+        if !opts.dry_run {
+            log::debug!("Saving registry...");
+            self.save_registry();
+        }
+
+        if !self.quiet {
+            println!(
+                "{} {}: {} tag(s), {} value(s)",
+                "\u{2714}".green().bold(),
+                if opts.dry_run { "TO SYNC" } else { "SYNCED" }.red().bold(),
+                tags.to_string().green().bold(),
+                values.to_string().green().bold()
+            );
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//! Display the tokens that `search -x|--exec` and `-X|--exec-batch` expand
+
+use super::{uses::Colorize, App};
+use crate::exe::token::TOKENS;
+
+impl App {
+    /// Print each token and what it expands to
+    pub(crate) fn tokens(&self) {
+        let width = TOKENS
+            .iter()
+            .map(|(t, _)| t.len())
+            .max()
+            .unwrap_or_default();
+
+        for (token, expl) in TOKENS {
+            println!(
+                "{}{}  {}",
+                token.green(),
+                " ".repeat(width - token.len()),
+                expl
+            );
+        }
+    }
+}
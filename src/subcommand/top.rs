@@ -0,0 +1,223 @@
+//! A live view of the changes that other `wutag` processes make to the
+//! registry, such as the `service` or a script tagging files. The change feed
+//! of the registry only reaches the callbacks in the same process, so the
+//! registry is reloaded whenever it is saved and compared with what it was
+//! before. The view is redrawn in place instead of taking over the screen, so
+//! it can be left running in a split
+
+// TODO: display the rules fired and the drift repaired by the watcher, once
+//       there is a watcher that sends them to the change feed
+
+use super::{
+    snapshot::tag_labels,
+    uses::{
+        fmt_path, fs, wutag_fatal, Args, BTreeMap, Colorize, EncryptConfig, PathBuf, TagRegistry,
+    },
+    App,
+};
+
+use chrono::{DateTime, Duration, Local};
+use std::{collections::BTreeSet, collections::VecDeque, thread, time::SystemTime};
+
+/// The tags on each file of a registry
+type Labels = BTreeMap<PathBuf, BTreeSet<String>>;
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct TopOpts {
+    /// Number of seconds between each check of the registry
+    #[clap(long, short = 'n', value_name = "secs", default_value = "1")]
+    pub(crate) interval: f64,
+    /// Number of recent changes that are displayed
+    #[clap(long, short, value_name = "num", default_value = "10")]
+    pub(crate) lines:    usize,
+}
+
+/// The tags that were added to and removed from a file
+struct Change {
+    time:    DateTime<Local>,
+    path:    PathBuf,
+    added:   Vec<String>,
+    removed: Vec<String>,
+}
+
+/// The activity seen since `top` was started
+#[derive(Default)]
+struct Activity {
+    /// The most recent changes, newest first
+    recent:  VecDeque<Change>,
+    /// The times the registry was saved within the last minute
+    writes:  VecDeque<DateTime<Local>>,
+    /// The number of times the registry was saved
+    saves:   usize,
+    /// The number of tags added to files
+    added:   usize,
+    /// The number of tags removed from files
+    removed: usize,
+}
+
+impl Activity {
+    /// Record the changes between two versions of the registry, keeping the
+    /// `keep` most recent
+    fn record(&mut self, before: &Labels, after: &Labels, keep: usize) {
+        let now = Local::now();
+        let empty = BTreeSet::new();
+        self.saves += 1;
+        self.writes.push_back(now);
+
+        for path in before.keys().chain(after.keys()).collect::<BTreeSet<_>>() {
+            let old = before.get(path).unwrap_or(&empty);
+            let new = after.get(path).unwrap_or(&empty);
+            if old == new {
+                continue;
+            }
+
+            let change = Change {
+                time:    now,
+                path:    path.clone(),
+                added:   new.difference(old).cloned().collect(),
+                removed: old.difference(new).cloned().collect(),
+            };
+            self.added += change.added.len();
+            self.removed += change.removed.len();
+            self.recent.push_front(change);
+        }
+
+        self.recent.truncate(keep);
+    }
+
+    /// The number of times the registry was saved within the last minute
+    fn writes_per_minute(&mut self) -> usize {
+        let minute_ago = Local::now() - Duration::minutes(1);
+        while self.writes.front().map_or(false, |t| *t < minute_ago) {
+            self.writes.pop_front();
+        }
+        self.writes.len()
+    }
+}
+
+/// The registry of another process, which is loaded again each time it is
+/// saved. The change feed of a registry only reaches the callbacks of the
+/// process that changed it
+pub(crate) struct RegistryWatch {
+    path:     PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl RegistryWatch {
+    /// The time the registry was last saved
+    fn modified(&self) -> Option<SystemTime> {
+        fs::metadata(&self.path).and_then(|meta| meta.modified()).ok()
+    }
+
+    /// The registry if it was saved since it was last loaded. The registry can
+    /// be read while it is being written, which is tried again on the next call
+    pub(crate) fn reload(&mut self) -> Option<TagRegistry> {
+        let now = self.modified();
+        if now == self.modified {
+            return None;
+        }
+
+        let registry = TagRegistry::load(&self.path, &EncryptConfig::default()).ok()?;
+        self.modified = now;
+        Some(registry)
+    }
+}
+
+impl App {
+    /// Start watching the registry for the changes of other processes. A
+    /// registry that is shared with `wutag serve` or encrypted cannot be
+    /// watched, since it is not read from a file or reading it changes it
+    pub(crate) fn watch_registry(&self) -> RegistryWatch {
+        if self.registry.remote().is_some() {
+            wutag_fatal!("a registry shared with 'wutag serve' cannot be watched");
+        }
+        #[cfg(feature = "encrypt-gpgme")]
+        if crate::registry::is_encrypted(&self.registry.path) {
+            wutag_fatal!("an encrypted registry cannot be watched");
+        }
+
+        let mut watch = RegistryWatch {
+            path:     self.registry.path.clone(),
+            modified: None,
+        };
+        watch.modified = watch.modified();
+        watch
+    }
+
+    /// Draw the view, returning the number of lines that were printed
+    fn draw_top(&self, activity: &mut Activity, registry: &TagRegistry) -> usize {
+        let mut lines = vec![
+            format!(
+                "{} {} {}",
+                "wutag top:".bold(),
+                self.registry.path.display().to_string().green(),
+                "(Ctrl-C to quit)".dimmed()
+            ),
+            format!(
+                "{}: {}  {}: {}  {}: {} ({}/min)  {}: {}  {}: {}",
+                "Files".magenta(),
+                registry.entries.len(),
+                "Tags".magenta(),
+                registry.tags.len(),
+                "Writes".magenta(),
+                activity.saves,
+                activity.writes_per_minute(),
+                "Tagged".magenta(),
+                activity.added.to_string().green(),
+                "Untagged".magenta(),
+                activity.removed.to_string().red(),
+            ),
+            String::new(),
+        ];
+
+        if activity.recent.is_empty() {
+            lines.push("Waiting for changes...".dimmed().to_string());
+        }
+        for change in &activity.recent {
+            let mut line = format!(
+                "{} {}",
+                change.time.format("%H:%M:%S").to_string().dimmed(),
+                fmt_path(&change.path, self.base_color, self.ls_colors)
+            );
+            for label in &change.added {
+                line.push_str(&format!(" {} {}", "+".bold().green(), label.bold()));
+            }
+            for label in &change.removed {
+                line.push_str(&format!(" {} {}", "X".bold().red(), label.bold()));
+            }
+            lines.push(line);
+        }
+
+        for line in &lines {
+            println!("{}", line);
+        }
+        lines.len()
+    }
+
+    pub(crate) fn top(&self, opts: &TopOpts) {
+        log::debug!("TopOpts: {:#?}", opts);
+        log::debug!("Using registry: {}", self.registry.path.display());
+
+        let mut watch = self.watch_registry();
+        let interval = std::time::Duration::from_secs_f64(opts.interval.max(0.1));
+        let mut registry = self.registry.clone();
+        let mut labels = tag_labels(&registry);
+        let mut activity = Activity::default();
+        let mut drawn = self.draw_top(&mut activity, &registry);
+
+        loop {
+            thread::sleep(interval);
+
+            if let Some(reloaded) = watch.reload() {
+                let after = tag_labels(&reloaded);
+                activity.record(&labels, &after, opts.lines);
+                labels = after;
+                registry = reloaded;
+            }
+
+            // Move back to the start of the view and clear it
+            print!("\x1b[{}A\x1b[J", drawn);
+            drawn = self.draw_top(&mut activity, &registry);
+        }
+    }
+}
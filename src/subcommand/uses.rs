@@ -8,6 +8,7 @@ pub(crate) use cli_table::{
 };
 pub(crate) use colored::{Color, Colorize};
 pub(crate) use crossbeam_channel as channel;
+pub(crate) use indexmap::IndexMap;
 pub(crate) use lexiclean::Lexiclean;
 pub(crate) use rayon::prelude::*;
 pub(crate) use regex::{
@@ -29,27 +30,38 @@ pub(crate) use std::{
 
 pub(crate) use crate::{
     bold_entry, comp_helper,
-    config::{Config, EncryptConfig},
+    config::{
+        get_cache_path, get_config_path, get_data_path, Config, DirPolicy, EncryptConfig,
+        NotifyConfig, SortOrder, CONFIG_FILE, POLICY_FILE,
+    },
     consts::*,
-    err,
+    diagnostics, err,
     exe::{
         job::{receiver, sender, WorkerResult},
         CommandTemplate,
     },
-    filesystem::{contained_path, create_temp_path, osstr_to_bytes, FileTypes},
+    filesystem::{
+        contained_path, create_temp_path, osstr_to_bytes, FileTypes, MetadataFilter, SeenFiles,
+        StripPrefixes,
+    },
+    glob::GlobEngine,
     global_opts,
+    hashing::{Hashing, DEFAULT_QUICK_HASH_SIZE},
     opt::{Command, Opts},
-    registry::{self, EntryData, TagRegistry},
-    ternary, ui,
+    porcelain::{Porcelain, StyledTag},
+    registry::{self, EntryData, EntryId, SyncChange, TagRegistry},
+    remote, storage, ternary, ui,
     util::{
-        collect_stdin_paths, fmt_err, fmt_local_path, fmt_ok, fmt_path, fmt_tag, gen_completions,
-        glob_builder, parse_path, raw_local_path, reg_ok, regex_builder, replace,
-        systemtime_to_datetime,
+        collect_git_modified_paths, collect_stdin_paths, expand_value, fmt_err, fmt_local_path,
+        fmt_ok, fmt_path, fmt_tag, fmt_tag_icon, gen_completions, glob_builder, highlight_builder,
+        parse_duration, parse_path, raw_local_path, reg_ok, regex_builder, replace,
+        resolve_color_when, set_color_override, systemtime_to_datetime, Highlight,
     },
-    wutag_error, wutag_fatal, wutag_info,
+    wutag_error, wutag_fatal, wutag_info, wutag_warning,
 };
 
 pub(crate) use wutag_core::{
-    color::{parse_color, parse_color_cli_table},
+    color::{color_matches, parse_color, parse_color_cli_table},
     tag::{clear_tags, has_tags, list_tags, DirEntryExt, Tag, DEFAULT_COLOR},
+    xattr, XattrLayout, XattrNamespace,
 };
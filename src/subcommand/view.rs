@@ -4,7 +4,7 @@ use super::{
         glob_builder, osstr_to_bytes, process, raw_local_path, reg_ok, regex_builder, ternary,
         wutag_error, wutag_fatal, wutag_info, Arc, ArgSettings, Args, BTreeMap, Captures, Colorize,
         Cow, DirEntryExt, EntryData, IntoParallelRefIterator, Lexiclean, OsStr, ParallelIterator,
-        PathBuf, Regex, Result, Tag, Write, DEFAULT_EDITOR,
+        PathBuf, Regex, Result, Tag, ValueHint, Write, DEFAULT_EDITOR,
     },
     App,
 };
@@ -23,6 +23,28 @@ pub(crate) struct ViewOpts {
     pub(crate) editor:  String,
     #[clap(long, short = 'a')]
     pub(crate) all:     bool,
+    /// Print the results instead of opening them in an editor
+    #[clap(
+        long,
+        short = 's',
+        conflicts_with = "apply",
+        long_about = "Print the results to stdout in the selected format instead of opening them \
+                      in an editor. The output can be modified and applied afterwards with \
+                      '--apply'"
+    )]
+    pub(crate) stdout:  bool,
+    /// Apply the tags in a file printed with '--stdout'
+    #[clap(
+        name = "apply",
+        long,
+        value_name = "file",
+        value_hint = ValueHint::FilePath,
+        long_about = "Apply the tags in a file that was printed with '--stdout' instead of opening \
+                      the results in an editor. The file must be in the selected format, which is \
+                      detected by its extension if '--format' is not given. Only files matching \
+                      the same pattern and filters are modified"
+    )]
+    pub(crate) apply:   Option<PathBuf>,
     /// Format of file to view results (toml, yaml, json)
     #[clap(
         name = "format",
@@ -172,47 +194,60 @@ impl App {
             }
         };
 
-        let tag_file = if let Some(format) = &opts.format {
-            match_format(format)
-        } else {
-            match_format(&self.format)
-        };
+        // A file that is applied can have its format detected by its extension
+        let format = opts
+            .format
+            .clone()
+            .or_else(|| {
+                opts.apply
+                    .as_ref()
+                    .and_then(|p| p.extension())
+                    .and_then(OsStr::to_str)
+                    .filter(|ext| matches!(*ext, "toml" | "yaml" | "yml" | "json"))
+                    .map(ToOwned::to_owned)
+            })
+            .unwrap_or_else(|| self.format.clone());
 
-        let mut tmp_path = PathBuf::from(create_temp_path());
+        if opts.stdout {
+            print!("{}", match_format(&format));
+            return Ok(());
+        }
 
-        tmp_path.set_extension(if let Some(format) = &opts.format {
-            match format.as_str() {
-                f @ ("toml" | "yaml" | "yml" | "json") => f.to_string(),
-                _ => unreachable!(),
-            }
+        let edited = if let Some(path) = &opts.apply {
+            path.clone()
         } else {
-            match self.format.as_str() {
-                f @ ("toml" | "yaml" | "yml" | "json") => f.to_string(),
-                _ => unreachable!(),
-            }
-        });
+            let tag_file = match_format(&format);
+
+            let mut tmp_path = PathBuf::from(create_temp_path());
+            tmp_path.set_extension(&format);
 
-        let mut tmp_file: fs::File = fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(&tmp_path)
-            .unwrap_or_else(|_| {
-                wutag_fatal!("could not create tmp file: '{}'", tmp_path.display())
+            let mut tmp_file: fs::File = fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .open(&tmp_path)
+                .unwrap_or_else(|_| {
+                    wutag_fatal!("could not create tmp file: '{}'", tmp_path.display())
+                });
+
+            tmp_file.write_all(tag_file.as_bytes()).unwrap_or_else(|_| {
+                wutag_fatal!("could not write tmp file: '{}'", tmp_path.display())
             });
 
-        tmp_file
-            .write_all(tag_file.as_bytes())
-            .unwrap_or_else(|_| wutag_fatal!("could not write tmp file: '{}'", tmp_path.display()));
+            tmp_file.flush().unwrap_or_else(|_| {
+                wutag_fatal!("could not flush tmp file: '{}'", tmp_path.display())
+            });
 
-        tmp_file
-            .flush()
-            .unwrap_or_else(|_| wutag_fatal!("could not flush tmp file: '{}'", tmp_path.display()));
+            // Option does not need to be given, as it is read from an environment variable
+            process::Command::new(opts.editor.to_string())
+                .arg(&tmp_path)
+                .status()
+                .expect("could not spawn editor");
 
-        // Option does not need to be given, as it is read from an environment variable
-        process::Command::new(opts.editor.to_string())
-            .arg(&tmp_path)
-            .status()
-            .expect("could not spawn editor");
+            tmp_path
+        };
+
+        let data = fs::read(&edited)
+            .unwrap_or_else(|e| wutag_fatal!("failed to read {}: {}", edited.display(), e));
 
         // Used to help highlight errors for whatever reason
         let re = Regex::new(r"^(\b[[:word:].]+\b): (.*)$").unwrap();
@@ -239,29 +274,21 @@ impl App {
 
         let serialized_format = |format: &String| -> BTreeMap<String, Vec<String>> {
             match format.as_str() {
-                "toml" =>
-                    toml::from_slice(&fs::read(&tmp_path).expect("failed to read tagged file"))
-                        .unwrap_or_else(|e| {
-                            wutag_fatal!(
-                                "toml deserialization failed:\n\t{} {}",
-                                "+".red().bold(),
-                                color_file(e.to_string())
-                            )
-                        }),
-                "json" => serde_json::from_slice(
-                    &fs::read(&tmp_path).expect("failed to read tagged file"),
-                )
-                .unwrap_or_else(|e| {
+                "toml" => toml::from_slice(&data).unwrap_or_else(|e| {
+                    wutag_fatal!(
+                        "toml deserialization failed:\n\t{} {}",
+                        "+".red().bold(),
+                        color_file(e.to_string())
+                    )
+                }),
+                "json" => serde_json::from_slice(&data).unwrap_or_else(|e| {
                     wutag_fatal!(
                         "json deserialization failed:\n\t{} {}",
                         "+".red().bold(),
                         color_file(e.to_string())
                     )
                 }),
-                "yaml" | "yml" => serde_yaml::from_slice(
-                    &fs::read(&tmp_path).expect("failed to read tagged file"),
-                )
-                .unwrap_or_else(|e| {
+                "yaml" | "yml" => serde_yaml::from_slice(&data).unwrap_or_else(|e| {
                     wutag_fatal!(
                         "yaml deserialization failed:\n\t{} {}",
                         "+".red().bold(),
@@ -272,11 +299,7 @@ impl App {
             }
         };
 
-        let emap: BTreeMap<String, Vec<String>> = if let Some(format) = &opts.format {
-            serialized_format(format)
-        } else {
-            serialized_format(&self.format)
-        };
+        let emap: BTreeMap<String, Vec<String>> = serialized_format(&format);
 
         let diff = emap.into_iter().fold(BTreeMap::new(), |mut acc, path| {
             let (key, val) = path;
@@ -369,7 +392,7 @@ impl App {
                     } else {
                         let entry = EntryData::new(entry)?;
                         let id = self.registry.add_or_update_entry(entry);
-                        self.registry.tag_entry(&tag, id);
+                        self.registry.use_tag(&tag, id);
                         if !self.quiet {
                             println!("\t{} {}", "+".bold().green(), fmt_tag(&tag));
                         }
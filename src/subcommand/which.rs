@@ -0,0 +1,49 @@
+//! Quick lookup of the files that have a tag, like `command -v` for files. The
+//! paths are printed one per line without any color so they can be parsed
+
+use super::{
+    uses::{contained_path, diagnostics, Args, PathBuf},
+    App,
+};
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub(crate) struct WhichOpts {
+    /// Only print the first file
+    #[clap(long, short = '1')]
+    pub(crate) first: bool,
+    /// The exact name of the tag
+    #[clap(value_name = "tag")]
+    pub(crate) tag:   String,
+}
+
+impl App {
+    /// Print the files that have the tag, sorted. Exits with a non-zero status
+    /// if there are none
+    pub(crate) fn which(&self, opts: &WhichOpts) {
+        log::debug!("WhichOpts: {:#?}", opts);
+        log::debug!("Using registry: {}", self.registry.path.display());
+
+        let mut paths = self
+            .registry
+            .get_tag(&opts.tag)
+            .and_then(|tag| self.registry.tags.get(tag))
+            .map(|ids| {
+                ids.iter()
+                    .filter_map(|id| self.registry.get_entry(*id))
+                    .map(|entry| entry.path().to_path_buf())
+                    .filter(|path| self.global || contained_path(path, &self.base_dir))
+                    .collect::<Vec<PathBuf>>()
+            })
+            .unwrap_or_default();
+
+        if paths.is_empty() {
+            diagnostics::exit(1);
+        }
+
+        paths.sort();
+        paths.dedup();
+        for path in paths.iter().take(if opts.first { 1 } else { paths.len() }) {
+            println!("{}", path.display());
+        }
+    }
+}
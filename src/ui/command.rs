@@ -6,8 +6,16 @@
 //! the TUI are also defined within this file
 
 use super::ui_app::UiApp;
-use anyhow::Result;
-use std::{fmt, str::FromStr};
+use anyhow::{anyhow, Result};
+use gag::BufferRedirect;
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    mem,
+    path::PathBuf,
+    str::FromStr,
+};
+use wutag_core::tag::Tag;
 
 /// Specifies the commands available within the TUI
 ///
@@ -25,6 +33,21 @@ pub(crate) enum Command {
     Refresh,
     /// Show help menu
     ShowHelp,
+
+    // #### Actions on the marked files ####
+    /// Add tags to the marked files         (tag(s))
+    Add(Vec<String>),
+    /// Remove tags from the marked files    (tag(s))
+    Remove(Vec<String>),
+    /// Clear the tags of the marked files
+    Clear,
+    /// Copy the tags of the highlighted file to the marked files
+    Cp,
+
+    // #### Filtering ####
+    /// Only show the files with `--any-of`, `--all-of`, and `--none-of` the
+    /// tags, or every file when there are no arguments        (option(s))
+    Filter(Vec<String>),
     /* #### Movement ####
      * /// Select next tab
      * NextTab,
@@ -64,6 +87,11 @@ impl fmt::Display for Command {
             Command::Quit => String::from("quit application"),
             Command::Refresh => String::from("refresh application"),
             Command::ShowHelp => String::from("show help"),
+            Command::Add(tags) => format!("add {}", tags.join(", ")),
+            Command::Remove(tags) => format!("remove {}", tags.join(", ")),
+            Command::Clear => String::from("clear tags"),
+            Command::Cp => String::from("copy tags"),
+            Command::Filter(args) => format!("filter {}", args.join(" ")),
             // Command::NextTab => String::from("go to next tab"),
             // Command::PrevTab => String::from("go to previous tab"),
             // Command::Edit(path) => format!("edit the file {}", path),
@@ -80,19 +108,24 @@ impl FromStr for Command {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut full_cmd = s
-            .to_ascii_lowercase()
-            .split_whitespace()
-            .map(ToString::to_string)
-            .collect::<Vec<_>>();
+        // Only the command is case insensitive, since tags are not
+        let mut full_cmd = split_prompt(s).map_err(|_| ())?;
 
-        let command = full_cmd.first().cloned().unwrap_or_default();
+        let command = full_cmd
+            .first()
+            .map(|c| c.to_ascii_lowercase())
+            .unwrap_or_default();
         let args = full_cmd.drain(1..).collect::<Vec<String>>();
 
         match command.as_str() {
             "@help" => Ok(Command::ShowHelp),
             "@quit" => Ok(Command::Quit),
             "@refresh" => Ok(Command::Refresh),
+            "@add" => Ok(Command::Add(args)),
+            "@remove" | "@rm" => Ok(Command::Remove(args)),
+            "@clear" => Ok(Command::Clear),
+            "@copy" | "@cp" => Ok(Command::Cp),
+            "@filter" => Ok(Command::Filter(args)),
             "none" | "@none" => Ok(Command::None),
             _ => Err(()),
         }
@@ -133,8 +166,175 @@ impl FromStr for ListType {
     }
 }
 
+/// Split a line typed in the command prompt into arguments like a shell would.
+/// Quotes keep the whitespace within an argument, and a backslash escapes the
+/// next character outside of single quotes
+pub(crate) fn split_prompt(line: &str) -> Result<Vec<String>> {
+    let mut args = vec![];
+    let mut arg = String::new();
+    let mut in_arg = false;
+    let mut quote = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None | Some('"'), '\\') => {
+                arg.extend(chars.next());
+                in_arg = true;
+            },
+            (Some(_), c) => arg.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_arg = true;
+            },
+            (None, c) if c.is_whitespace() =>
+                if in_arg {
+                    args.push(mem::take(&mut arg));
+                    in_arg = false;
+                },
+            (None, c) => {
+                arg.push(c);
+                in_arg = true;
+            },
+        }
+    }
+
+    if quote.is_some() {
+        return Err(anyhow!("unterminated quote in the command"));
+    }
+    if in_arg {
+        args.push(arg);
+    }
+
+    Ok(args)
+}
+
+/// Run `f` with the standard output and error going to a buffer instead of the
+/// terminal, returning what was written to them. An error returned by `f` is
+/// added to the end
+pub(crate) fn capture_output<F: FnOnce() -> Result<()>>(f: F) -> Result<String> {
+    let mut stdout = BufferRedirect::stdout()?;
+    let mut stderr = BufferRedirect::stderr()?;
+    let res = f();
+    io::stdout().flush()?;
+
+    let mut output = String::new();
+    stdout.read_to_string(&mut output)?;
+    stderr.read_to_string(&mut output)?;
+    if let Err(e) = res {
+        output.push_str(&format!("error: {}\n", e));
+    }
+
+    Ok(output)
+}
+
+/// A change to the tags of several files, which is applied a few files at a
+/// time so that its progress can be displayed
+#[derive(Clone, Debug)]
+pub(crate) struct BulkJob {
+    /// The change that is made, which is one of the actions on marked files
+    pub(crate) command: Command,
+    /// The tags that are added or removed
+    pub(crate) tags:    Vec<Tag>,
+    /// The files that are changed
+    pub(crate) paths:   Vec<PathBuf>,
+    /// How many of the files have been changed so far
+    pub(crate) done:    usize,
+    /// The files that could not be changed, and why
+    pub(crate) failed:  Vec<(PathBuf, String)>,
+}
+
+impl BulkJob {
+    /// Create a new `BulkJob` that has not changed any file yet
+    pub(crate) fn new(command: Command, tags: Vec<Tag>, paths: Vec<PathBuf>) -> Self {
+        Self {
+            command,
+            tags,
+            paths,
+            done: 0,
+            failed: vec![],
+        }
+    }
+
+    /// Whether every file has been changed
+    pub(crate) fn is_finished(&self) -> bool {
+        self.done >= self.paths.len()
+    }
+
+    /// How many of the files have been changed, out of 100
+    pub(crate) fn percent(&self) -> u16 {
+        if self.paths.is_empty() {
+            100
+        } else {
+            (self.done * 100 / self.paths.len()) as u16
+        }
+    }
+
+    /// How many files succeeded and failed, followed by why each failure did.
+    /// Files that were not reached because the job was cancelled are skipped
+    pub(crate) fn summary(&self) -> String {
+        let mut summary = format!(
+            "{}: {} succeeded, {} failed",
+            self.command,
+            self.done - self.failed.len(),
+            self.failed.len()
+        );
+        if !self.is_finished() {
+            summary.push_str(&format!(", {} skipped", self.paths.len() - self.done));
+        }
+        summary.push('\n');
+
+        for (path, e) in &self.failed {
+            summary.push_str(&format!("\n{}: {}", path.display(), e));
+        }
+
+        summary
+    }
+}
+
 // #[allow(clippy::unnecessary_wraps)]
 // pub(crate) fn handle_command(app: &UiApp) -> Result<()> {
 //     let command = Command::from_str(app.command_buffer);
 //     Ok(())
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_prompt_like_a_shell() -> Result<()> {
+        assert_eq!(split_prompt("  set  '*.rs' rust ")?, vec!["set", "*.rs", "rust"]);
+        assert_eq!(split_prompt(r#"search "two words" a\ b ''"#)?, vec![
+            "search",
+            "two words",
+            "a b",
+            ""
+        ]);
+        assert!(split_prompt("rm 'open").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parses_actions_on_marked_files() {
+        assert_eq!("@ADD Rust 'two words'".parse::<Command>(), Ok(Command::Add(vec![
+            String::from("Rust"),
+            String::from("two words")
+        ])));
+        assert_eq!(
+            "@rm old".parse::<Command>(),
+            Ok(Command::Remove(vec![String::from("old")]))
+        );
+        assert_eq!("@clear".parse::<Command>(), Ok(Command::Clear));
+        assert_eq!("@cp".parse::<Command>(), Ok(Command::Cp));
+        assert_eq!(
+            "@filter --all-of work,urgent".parse::<Command>(),
+            Ok(Command::Filter(vec![
+                String::from("--all-of"),
+                String::from("work,urgent")
+            ]))
+        );
+        assert_eq!("set '*.rs' rust".parse::<Command>(), Err(()));
+    }
+}
@@ -3,7 +3,12 @@
 // Credit: idea and outline came from `kdheepak/taskwarrior-tui`
 //  * Using their work to help me learn how to code a TUI
 
-use std::{error::Error, fmt, io};
+use crate::registry::TagRegistry;
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    fmt, io,
+};
 use tui::{
     layout::{Constraint, Corner, Direction, Layout},
     style::{Color, Modifier, Style},
@@ -43,10 +48,71 @@ pub(crate) fn get_word_under_cursor(line: &str, cursor_pos: usize) -> usize {
     res
 }
 
+/// Names of the tags, values, and files in the registry that complete the
+/// arguments of a command. They are collected when they are first needed and
+/// kept until the registry changes
+#[derive(Debug, Default, Clone)]
+pub(crate) struct RegistryCandidates {
+    /// Names of the tags
+    pub(crate) tags:   Vec<String>,
+    /// Values of the tags, written as `tag=value` like `wutag set` takes them
+    pub(crate) values: Vec<String>,
+    /// Paths of the files and the shortened form that is displayed
+    pub(crate) paths:  Vec<(String, String)>,
+}
+
+impl RegistryCandidates {
+    /// Collect the candidates from the `registry`, where `shorten` gives the
+    /// form of a path that is displayed
+    pub(crate) fn collect<F: Fn(&str) -> String>(registry: &TagRegistry, shorten: F) -> Self {
+        let values = registry
+            .values
+            .values()
+            .flat_map(|values| {
+                values
+                    .iter()
+                    .map(|(tag, value)| format!("{}={}", tag, value))
+            })
+            .collect::<BTreeSet<_>>();
+
+        Self {
+            tags:   registry.list_tags().map(|t| t.name().to_owned()).collect(),
+            values: values.into_iter().collect(),
+            paths:  registry
+                .entries
+                .values()
+                .map(|entry| {
+                    let path = entry.path().display().to_string();
+                    let short = shorten(&path);
+                    (path, short)
+                })
+                .collect(),
+        }
+    }
+
+    /// The candidates for `word`, which are values once a tag is followed by
+    /// `=`, paths when it starts like one, and tags otherwise. Each is paired
+    /// with the text that is displayed when it differs
+    pub(crate) fn for_word(&self, word: &str) -> Vec<(String, Option<String>)> {
+        if word.contains('=') {
+            self.values.iter().map(|v| (v.clone(), None)).collect()
+        } else if word.starts_with('/') {
+            self.paths
+                .iter()
+                .map(|(path, short)| (path.clone(), (path != short).then(|| short.clone())))
+                .collect()
+        } else {
+            self.tags.iter().map(|t| (t.clone(), None)).collect()
+        }
+    }
+}
+
 /// Representation of completions options and the completer
 pub(crate) struct CompletionHelper {
     pub(crate) completer:  FilenameCompleter,
     pub(crate) candidates: Vec<String>,
+    /// Text displayed in place of a candidate, such as a path with an alias
+    pub(crate) displays:   HashMap<String, String>,
 }
 
 impl Completer for CompletionHelper {
@@ -70,7 +136,7 @@ impl Completer for CompletionHelper {
                         cand[pos..].to_string()
                     };
                     Some(Pair {
-                        display: cand.clone(),
+                        display: self.displays.get(cand).unwrap_or(cand).clone(),
                         replacement,
                     })
                 } else {
@@ -113,6 +179,7 @@ impl CompletionList {
             helper: CompletionHelper {
                 candidates: vec![],
                 completer:  FilenameCompleter::new(),
+                displays:   HashMap::new(),
             },
         }
     }
@@ -132,6 +199,7 @@ impl CompletionList {
             helper: CompletionHelper {
                 candidates,
                 completer: FilenameCompleter::new(),
+                displays: HashMap::new(),
             },
         }
     }
@@ -159,6 +227,12 @@ impl CompletionList {
         self.helper.candidates.sort();
     }
 
+    /// Insert an item into the completer that is displayed as `display`
+    pub(crate) fn insert_displayed(&mut self, item: String, display: String) {
+        self.helper.displays.insert(item.clone(), display);
+        self.insert(item);
+    }
+
     /// Get the next item in the completion list
     // TODO: fix a crash here while typing
     pub(crate) fn next(&mut self) {
@@ -196,6 +270,7 @@ impl CompletionList {
     /// Clear the completion menu
     pub(crate) fn clear(&mut self) {
         self.helper.candidates.clear();
+        self.helper.displays.clear();
         self.state.select(None);
     }
 
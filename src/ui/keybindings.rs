@@ -15,15 +15,12 @@ use crate::config::KeyConfig;
 use once_cell::sync::Lazy;
 use std::{collections::HashMap, fmt};
 use tui::{
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Span, Spans, Text},
     widgets::ListItem,
 };
 
-use super::{
-    event::Key,
-    ui_app::{GREEN, MAGENTA, PINK, YELLOW},
-};
+use super::{event::Key, theme::Palette};
 
 // Would be string slices, but I haven't figured out how to convert an enum
 // value to a string slice. An error is given about temporary variables being
@@ -39,6 +36,9 @@ pub(crate) struct Keybinding {
     pub(crate) action:      String,
     /// Description of the keybinding that is shown in the help screen
     pub(crate) description: String,
+    /// Key that runs the action from the main screen, which is how the command
+    /// palette runs it
+    pub(crate) input:       Option<Key>,
 }
 
 impl fmt::Display for Keybinding {
@@ -62,9 +62,25 @@ impl Keybinding {
             key,
             action,
             description,
+            input: None,
         }
     }
 
+    /// Set the key that runs the action from the main screen
+    pub(crate) fn with_input(mut self, input: Key) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    /// How well `query` matches the action, the key, or the description, which
+    /// is `None` if it matches none of them
+    pub(crate) fn score(&self, query: &str) -> Option<usize> {
+        [&self.action, &self.key, &self.description]
+            .iter()
+            .filter_map(|text| fuzzy_score(query, text))
+            .max()
+    }
+
     /// Return description of a keybinding
     pub(crate) fn get_description_text(&self, command_style: Style) -> Text {
         let mut lines = Vec::new();
@@ -80,16 +96,21 @@ impl Keybinding {
     }
 
     /// Returns [`Keybinding`] as a [`ListItem`]
-    pub(crate) fn as_list_item(&self, colored: bool, highlighted: bool) -> ListItem {
+    pub(crate) fn as_list_item(
+        &self,
+        palette: &Palette,
+        colored: bool,
+        highlighted: bool,
+    ) -> ListItem {
         // .fg(Color::Reset)
         // Current selection
 
         let highlight_style = if highlighted {
             Style::default()
-                .fg(Color::Rgb(PINK[0], PINK[1], PINK[2]))
+                .fg(palette.header)
                 .add_modifier(Modifier::BOLD)
         } else {
-            Style::default().fg(Color::Rgb(MAGENTA[0], MAGENTA[1], MAGENTA[2]))
+            Style::default().fg(palette.help)
         };
 
         ListItem::new(if colored {
@@ -100,11 +121,11 @@ impl Keybinding {
                         key,
                         if highlighted {
                             Style::default()
-                                .fg(Color::Rgb(YELLOW[0], YELLOW[1], YELLOW[2]))
+                                .fg(palette.key)
                                 .add_modifier(Modifier::BOLD)
                         } else {
                             Style::default()
-                                .fg(Color::Rgb(YELLOW[0], YELLOW[1], YELLOW[2]))
+                                .fg(palette.key)
                                 .add_modifier(Modifier::DIM)
                                 .add_modifier(Modifier::BOLD)
                         },
@@ -114,7 +135,7 @@ impl Keybinding {
                 })),
                 // └─
                 Spans::from(vec![
-                    Span::styled(" └─", Style::default().fg(Color::DarkGray)),
+                    Span::styled(" └─", Style::default().fg(palette.border)),
                     Span::styled(self.action.clone(), highlight_style),
                 ]),
                 Spans::default(),
@@ -124,3 +145,51 @@ impl Keybinding {
         })
     }
 }
+
+/// How well `query` matches `text` when its characters appear in `text` in the
+/// same order, ignoring case and whitespace. Characters that follow the one
+/// before them or start a word score higher, and gaps between them score lower
+pub(crate) fn fuzzy_score(query: &str, text: &str) -> Option<usize> {
+    let text = text.chars().collect::<Vec<_>>();
+    let same = |a: char, b: char| a.to_lowercase().eq(b.to_lowercase());
+
+    let mut pos = 0;
+    let mut prev = None;
+    let mut bonus = 0;
+    let mut gaps = 0;
+
+    for q in query.chars().filter(|c| !c.is_whitespace()) {
+        let idx = (pos..text.len()).find(|i| same(text[*i], q))?;
+
+        bonus += 1;
+        if prev.map_or(false, |p| p + 1 == idx) {
+            bonus += 4;
+        }
+        if idx == 0 || !text[idx - 1].is_alphanumeric() {
+            bonus += 2;
+        }
+        if prev.is_some() {
+            gaps += idx - pos;
+        }
+
+        prev = Some(idx);
+        pos = idx + 1;
+    }
+
+    Some((bonus * 16).saturating_sub(gaps))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_fuzzy_matches() {
+        assert!(fuzzy_score("gtt", "Go to top").is_some());
+        assert!(fuzzy_score("GTB", "go to bottom").is_some());
+        assert_eq!(fuzzy_score("tog", "go to top"), None);
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        assert!(fuzzy_score("tag", "tags column") > fuzzy_score("tag", "the anti gravity"));
+        assert!(fuzzy_score("prev", "preview") > fuzzy_score("prev", "page drive"));
+    }
+}
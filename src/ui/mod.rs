@@ -7,14 +7,17 @@ pub(crate) mod history;
 pub(crate) mod keybindings;
 pub(crate) mod list;
 pub(crate) mod mtui;
+pub(crate) mod preview;
 pub(crate) mod table;
+pub(crate) mod theme;
 pub(crate) mod ui_app;
 
 pub(crate) use event::{Event, EventConfig, EventHandler};
 pub(crate) use ui_app::AppMode;
 
-use crate::{config::Config, registry::TagRegistry, subcommand::App};
+use crate::{config::Config, registry::TagRegistry, subcommand::App, wutag_error};
 use anyhow::Result;
+use colored::Colorize;
 use crossterm::{
     cursor,
     event::DisableMouseCapture,
@@ -104,7 +107,13 @@ pub(crate) fn start_ui(cli_app: &App, config: Config, registry: TagRegistry) ->
     let mut toggle_pause = false;
     loop {
         tui.render(cli_app, &mut app).map_err(Error::UiRender)?;
-        match tui.events.next().map_err(Error::Recv)? {
+        // An action that was picked in the command palette runs as if its key
+        // had been pressed
+        let event = match app.pending_input.take() {
+            Some(input) => Event::Input(input),
+            None => tui.events.next().map_err(Error::Recv)?,
+        };
+        match event {
             Event::Input(input) => {
                 if input == app.config.keys.view && app.mode == AppMode::List {
                     // tui.leave_tui_mode().map_err(Error::UiStopFailure)?;
@@ -138,12 +147,24 @@ pub(crate) fn start_ui(cli_app: &App, config: Config, registry: TagRegistry) ->
                 },
         }
 
+        // A command from the prompt that uses the terminal is run while the TUI
+        // is paused
+        if let Some(opts) = app.terminal_command.take() {
+            tui.toggle_pause().map_err(Error::UiPause)?;
+            app.run_terminal_command(&opts);
+            tui.toggle_pause().map_err(Error::UiPause)?;
+        }
+
         if app.should_quit {
             tui.leave_tui_mode().map_err(Error::UiStopFailure)?;
             break;
         }
     }
 
+    if let Err(e) = app.save_layout() {
+        wutag_error!("unable to save the layout of the TUI: {}", e);
+    }
+
     Ok(())
 }
 
@@ -4,6 +4,7 @@
 //!     * the event signals
 //!     * an internal state on whether the application is paused
 //!         * This is used for leaving the TUI for the editor
+//!     * the image that is drawn over the preview, if any
 
 // Credit: idea and outline came from `orhun/gpg-tui`
 //  * Using his work to help me learn how to code a TUI
@@ -18,7 +19,7 @@ use crossterm::{
 use std::{io, sync::atomic::Ordering};
 use tui::{backend::Backend, Terminal};
 
-use super::{event::EventHandler, ui_app::UiApp};
+use super::{event::EventHandler, preview::PreviewImage, ui_app::UiApp};
 use crate::subcommand::App;
 
 /// The meta-tui wrapper, which sets up the user interface
@@ -30,6 +31,8 @@ pub(crate) struct Tui<B: Backend> {
     pub(crate) events: EventHandler,
     /// Paused state of interface
     pub(crate) paused: bool,
+    /// Image that is on the screen over the preview
+    image:             Option<PreviewImage>,
 }
 
 impl<B: Backend> Tui<B> {
@@ -39,6 +42,7 @@ impl<B: Backend> Tui<B> {
             terminal,
             events,
             paused: false,
+            image: None,
         }
     }
 
@@ -50,6 +54,8 @@ impl<B: Backend> Tui<B> {
         // execute!(stdout, Clear(ClearType::All)).unwrap();
         self.terminal.clear()?;
         self.terminal.resize(self.terminal.size()?)?;
+        // The screen was cleared, so the image is drawn again
+        self.image = None;
         Ok(())
     }
 
@@ -105,11 +111,34 @@ impl<B: Backend> Tui<B> {
         Ok(())
     }
 
-    /// Render the TUI envrionment
+    /// Render the TUI envrionment. An image in the preview is written after
+    /// the frame, and only when it changes, since `tui` does not draw over the
+    /// cells it thinks are unchanged
     pub(crate) fn render(&mut self, app: &App, uiapp: &mut UiApp) -> Result<()> {
         self.terminal
             .draw(|f| uiapp.draw(app, f))
             .context("failed to draw terminal")?;
+
+        if uiapp.preview_image == self.image {
+            return Ok(());
+        }
+
+        let mut stdout = io::stdout();
+        if let Some(image) = self.image.take() {
+            // Everything is drawn again to remove what the image covered
+            image.clear(&mut stdout)?;
+            self.terminal.clear()?;
+            self.terminal
+                .draw(|f| uiapp.draw(app, f))
+                .context("failed to draw terminal")?;
+        }
+        if let Some(image) = &uiapp.preview_image {
+            image
+                .draw(&mut stdout)
+                .context("failed to draw the preview image")?;
+        }
+        self.image = uiapp.preview_image.clone();
+
         Ok(())
     }
 }
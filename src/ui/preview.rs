@@ -0,0 +1,288 @@
+//! What the preview pane displays for a file. Text is piped through `bat` (or
+//! `cat`), small binary files are displayed as a hexdump, and images are drawn
+//! over the pane with the kitty or sixel graphics protocol when the terminal
+//! supports one of them. `tui` cannot hold an image in its buffer, so an image
+//! is written to the terminal after each frame that changes it (see
+//! [`Tui::render`](super::mtui::Tui::render))
+
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fmt,
+    fs::File,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    rc::Rc,
+    str,
+};
+use tui::layout::Rect;
+
+/// How much of a file is read to tell what kind of file it is
+const SNIFF_LEN: u64 = 8192;
+
+/// Largest binary file that is displayed as a hexdump
+pub(crate) const HEXDUMP_LIMIT: u64 = 16 * 1024;
+
+/// Deletes every image that was drawn with the kitty graphics protocol
+const KITTY_CLEAR: &[u8] = b"\x1b_Ga=d,q=2\x1b\\";
+
+/// What kind of content a file has
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FileKind {
+    Text,
+    Image,
+    Binary,
+}
+
+/// The graphics protocol that images are drawn with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ImageProtocol {
+    /// Detected from the environment of the terminal
+    Auto,
+    /// The protocol of kitty (also used by ghostty and konsole)
+    Kitty,
+    /// Sixel graphics (e.g., foot, mlterm, WezTerm, or xterm with `-ti vt340`)
+    Sixel,
+    /// Images are not drawn
+    None,
+}
+
+impl ImageProtocol {
+    /// The protocol that is used, detecting it when this is `Auto`
+    pub(crate) fn resolve(self) -> Self {
+        if self != Self::Auto {
+            return self;
+        }
+
+        let var = |name: &str| env::var(name).unwrap_or_default().to_ascii_lowercase();
+        let (term, program) = (var("TERM"), var("TERM_PROGRAM"));
+
+        if env::var_os("KITTY_WINDOW_ID").is_some()
+            || term.contains("kitty")
+            || term.contains("ghostty")
+            || env::var_os("KONSOLE_VERSION").is_some()
+        {
+            Self::Kitty
+        } else if term.starts_with("foot")
+            || term.starts_with("mlterm")
+            || term.contains("sixel")
+            || program == "wezterm"
+        {
+            Self::Sixel
+        } else {
+            Self::None
+        }
+    }
+}
+
+impl Default for ImageProtocol {
+    fn default() -> Self {
+        Self::Auto
+    }
+}
+
+impl fmt::Display for ImageProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Self::Auto => "auto",
+            Self::Kitty => "kitty",
+            Self::Sixel => "sixel",
+            Self::None => "none",
+        })
+    }
+}
+
+/// An image that is drawn over the preview pane
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PreviewImage {
+    /// The file that is displayed
+    pub(crate) path:     PathBuf,
+    /// Where the image is drawn, which is the inside of the preview pane
+    pub(crate) rect:     Rect,
+    /// Protocol that `data` is in
+    pub(crate) protocol: ImageProtocol,
+    /// Escape sequences that draw the image
+    pub(crate) data:     Rc<Vec<u8>>,
+}
+
+impl PreviewImage {
+    /// Render the image at `path` to fit in `rect` with `protocol`. Images are
+    /// converted with `chafa`, and without it only PNG files can be drawn, and
+    /// only with the kitty protocol, which reads the file itself
+    pub(crate) fn render(path: &Path, rect: Rect, protocol: ImageProtocol) -> Option<Self> {
+        let format = match protocol {
+            ImageProtocol::Kitty => "kitty",
+            ImageProtocol::Sixel => "sixels",
+            ImageProtocol::Auto | ImageProtocol::None => return None,
+        };
+
+        let data = if which::which("chafa").is_ok() {
+            let out = process::Command::new("chafa")
+                .arg(format!("--format={}", format))
+                .arg(format!("--size={}x{}", rect.width, rect.height))
+                .args(&["--animate=off", "--polite=on"])
+                .arg(path)
+                .output()
+                .ok()
+                .filter(|out| out.status.success())?;
+            out.stdout
+        } else if protocol == ImageProtocol::Kitty && sniff(path).ok()?.starts_with(b"\x89PNG") {
+            let path = path.canonicalize().ok()?;
+            format!(
+                "\x1b_Ga=T,f=100,t=f,q=2,c={},r={};{}\x1b\\",
+                rect.width,
+                rect.height,
+                base64::encode(path.to_string_lossy().as_bytes())
+            )
+            .into_bytes()
+        } else {
+            return None;
+        };
+
+        Some(Self {
+            path: path.to_path_buf(),
+            rect,
+            protocol,
+            data: Rc::new(data),
+        })
+    }
+
+    /// Write the image to the terminal at the top left of its `rect`
+    pub(crate) fn draw<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        crossterm::queue!(out, crossterm::cursor::MoveTo(self.rect.x, self.rect.y))?;
+        out.write_all(&self.data)?;
+        out.flush()
+    }
+
+    /// Remove the image from the terminal. Sixel images are part of the text,
+    /// so they are removed by drawing the cells under them again
+    pub(crate) fn clear<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        if self.protocol == ImageProtocol::Kitty {
+            out.write_all(KITTY_CLEAR)?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// The start of the file at `path`
+fn sniff(path: &Path) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    File::open(path)?.take(SNIFF_LEN).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// What kind of file starts with `bytes`
+pub(crate) fn kind_of(bytes: &[u8]) -> FileKind {
+    const IMAGE_MAGIC: &[&[u8]] = &[
+        b"\x89PNG\r\n\x1a\n",
+        b"\xff\xd8\xff",
+        b"GIF87a",
+        b"GIF89a",
+        b"BM",
+    ];
+
+    let webp = bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP";
+    if webp || IMAGE_MAGIC.iter().any(|magic| bytes.starts_with(magic)) {
+        return FileKind::Image;
+    }
+
+    // A character that was cut off at the end of `bytes` is still text
+    match str::from_utf8(bytes) {
+        Ok(text) if !text.contains('\0') => FileKind::Text,
+        Err(e) if e.error_len().is_none() && !bytes.contains(&0) => FileKind::Text,
+        _ => FileKind::Binary,
+    }
+}
+
+/// What kind of file is at `path`
+pub(crate) fn kind(path: &Path) -> io::Result<FileKind> {
+    sniff(path).map(|bytes| kind_of(&bytes))
+}
+
+/// Display `bytes` like `hexdump -C`: the offset, sixteen bytes in hex, and
+/// the same bytes as ASCII with a dot for those that cannot be printed
+pub(crate) fn hexdump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+
+    for (idx, line) in bytes.chunks(16).enumerate() {
+        let mut hex = String::new();
+        for (col, byte) in line.iter().enumerate() {
+            if col == 8 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x} ", byte));
+        }
+        let ascii = line
+            .iter()
+            .map(|b| {
+                if b.is_ascii_graphic() || *b == b' ' {
+                    char::from(*b)
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+
+        dump.push_str(&format!("{:08x}  {:<49} |{}|\n", idx * 16, hex, ascii));
+    }
+
+    dump
+}
+
+/// The text of the file at `path` as `bat` (or `cat`) displays it, wrapped to
+/// `width`
+pub(crate) fn text(path: &Path, width: u16) -> String {
+    let mut cmd = if which::which("bat").is_ok() {
+        let mut bat = process::Command::new("bat");
+        bat.arg("--paging=never");
+        bat.arg("--style=numbers");
+        bat.arg(format!("--terminal-width={}", width));
+        bat.arg("--color=always");
+        bat
+    } else {
+        process::Command::new("cat")
+    };
+
+    // This may not be needed since no pager is being opened
+    cmd.env("LESSCHARSET", "utf-8");
+    cmd.arg(path);
+
+    match cmd.output() {
+        Ok(out) =>
+            if out.status.success() {
+                String::from_utf8_lossy(&out.stdout).to_string()
+            } else {
+                format!("Error: {}", String::from_utf8_lossy(&out.stderr))
+            },
+        Err(e) => format!("Error: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_kinds_of_files_apart() {
+        assert_eq!(kind_of(b"fn main() {}\n"), FileKind::Text);
+        assert_eq!(kind_of(b""), FileKind::Text);
+        // An 'é' that is cut off after its first byte
+        assert_eq!(kind_of(b"caf\xc3"), FileKind::Text);
+        assert_eq!(kind_of(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), FileKind::Image);
+        assert_eq!(kind_of(b"RIFF\0\0\0\0WEBPVP8 "), FileKind::Image);
+        assert_eq!(kind_of(b"\x7fELF\x02\x01\x01\0"), FileKind::Binary);
+        assert_eq!(kind_of(b"\xff\xfe"), FileKind::Binary);
+    }
+
+    #[test]
+    fn dumps_bytes_like_hexdump() {
+        assert_eq!(
+            hexdump(b"Hello, world!\n\0\x01\xffA"),
+            "00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 01  |Hello, world!...|\n\
+             00000010  ff 41                                             |.A|\n"
+        );
+        assert!(hexdump(b"").is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+//! Colors of the TUI. Each part of it (e.g., the headers or the selection) is
+//! given a color in the `theme` section of the `ui` configuration, which is
+//! resolved for the terminal once when the TUI starts. Terminals that do not
+//! display truecolor are given the nearest of the 256 colors instead, or the
+//! fallback that was configured
+
+use crate::config::{ThemeColor, UiTheme};
+use std::env;
+use tui::style::Color;
+use wutag_core::color::TuiColor;
+
+/// Levels of red, green, and blue in the 6x6x6 cube of the 256 colors
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The colors of the theme, resolved for the terminal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Palette {
+    pub(crate) header:       Color,
+    pub(crate) text:         Color,
+    pub(crate) selection:    Color,
+    pub(crate) table_header: Color,
+    pub(crate) path:         Color,
+    pub(crate) tag_fallback: Color,
+    pub(crate) error:        Color,
+    pub(crate) border:       Color,
+    pub(crate) key:          Color,
+    pub(crate) help:         Color,
+    pub(crate) command:      Color,
+    pub(crate) info:         Color,
+}
+
+impl Palette {
+    /// Resolve the colors of `theme`. Paths are displayed with `paths_color`
+    /// when the theme does not have a color for them
+    pub(crate) fn new(theme: &UiTheme, paths_color: Color) -> Self {
+        let truecolor = theme.truecolor.unwrap_or_else(supports_truecolor);
+        let get = |color: &ThemeColor| color.resolve(truecolor);
+
+        Self {
+            header:       get(&theme.header),
+            text:         get(&theme.text),
+            selection:    get(&theme.selection),
+            table_header: get(&theme.table_header),
+            path:         theme.path.as_ref().map_or(paths_color, get),
+            tag_fallback: get(&theme.tag_fallback),
+            error:        get(&theme.error),
+            border:       get(&theme.border),
+            key:          get(&theme.key),
+            help:         get(&theme.help),
+            command:      get(&theme.command),
+            info:         get(&theme.info),
+        }
+    }
+}
+
+impl ThemeColor {
+    /// The color that is displayed, which is one of the 256 colors when the
+    /// terminal does not display truecolor
+    pub(crate) fn resolve(&self, truecolor: bool) -> Color {
+        match self {
+            Self::Color(color) => match parse_theme_color(color) {
+                Color::Rgb(r, g, b) if !truecolor => Color::Indexed(nearest_indexed(r, g, b)),
+                color => color,
+            },
+            Self::Fallback { color, fallback } =>
+                if truecolor {
+                    parse_theme_color(color)
+                } else {
+                    Color::Indexed(*fallback)
+                },
+        }
+    }
+}
+
+/// Whether the terminal displays truecolor, according to `$COLORTERM`
+fn supports_truecolor() -> bool {
+    env::var("COLORTERM").map_or(false, |term| term == "truecolor" || term == "24bit")
+}
+
+/// Parse a color of the theme, which is one of the 256 colors (`0`-`255`), a
+/// hex color, or the name of a basic color
+fn parse_theme_color(color: &str) -> Color {
+    color
+        .trim()
+        .parse::<u8>()
+        .map_or_else(|_| TuiColor::from(color).get(), Color::Indexed)
+}
+
+/// The one of the 256 colors that is nearest to the given RGB color, which is
+/// either in the 6x6x6 cube (16-231) or in the grayscale ramp (232-255)
+pub(crate) fn nearest_indexed(r: u8, g: u8, b: u8) -> u8 {
+    let distance = |x: u8, y: u8| (i32::from(x) - i32::from(y)).pow(2);
+    let level = |c: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|i| distance(CUBE_LEVELS[*i], c))
+            .unwrap_or_default()
+    };
+
+    let (ri, gi, bi) = (level(r), level(g), level(b));
+    let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+
+    // The grays go from 8 to 238 in steps of 10
+    let average = (u16::from(r) + u16::from(g) + u16::from(b)) / 3;
+    let step = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray = 8 + 10 * step;
+
+    let off = |(x, y, z): (u8, u8, u8)| distance(x, r) + distance(y, g) + distance(z, b);
+    if off((gray, gray, gray)) < off(cube) {
+        232 + step
+    } else {
+        (16 + 36 * ri + 6 * gi + bi) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_nearest_indexed_color() {
+        assert_eq!(nearest_indexed(0, 0, 0), 16);
+        assert_eq!(nearest_indexed(255, 0, 0), 196);
+        assert_eq!(nearest_indexed(255, 255, 255), 231);
+        assert_eq!(nearest_indexed(128, 128, 128), 244);
+        assert_eq!(nearest_indexed(239, 29, 85), 197);
+    }
+
+    #[test]
+    fn resolves_theme_colors() {
+        let hex = ThemeColor::from("#EF1D55");
+        assert_eq!(hex.resolve(true), Color::Rgb(239, 29, 85));
+        assert_eq!(hex.resolve(false), Color::Indexed(197));
+        assert_eq!(ThemeColor::from("red").resolve(false), Color::Red);
+        assert_eq!(ThemeColor::from("42").resolve(true), Color::Indexed(42));
+
+        let fallback = ThemeColor::Fallback {
+            color:    String::from("#EF1D55"),
+            fallback: 161,
+        };
+        assert_eq!(fallback.resolve(true), Color::Rgb(239, 29, 85));
+        assert_eq!(fallback.resolve(false), Color::Indexed(161));
+    }
+}
@@ -14,27 +14,34 @@
 // TODO: Use error mode
 // TODO: Encryption of database when leaving TUI
 
-// TODO: Change some hard coded colors
-
 // TODO: Command prompt in HelpMenu (?)
-// TODO: :exit :help commands
 
 // TODO: Use config if history is enabled
 
 use ansi_to_tui::ansi_to_text;
 use anyhow::{anyhow, Context, Result};
-use clap::IntoApp;
+use chrono::{Local, TimeZone};
+use clap::{IntoApp, Parser};
 use colored::{ColoredString, Colorize};
 use lexiclean::Lexiclean;
 use rand::seq::SliceRandom;
 use std::{
     borrow::Cow,
-    collections::{BTreeMap, HashMap, HashSet},
+    cmp,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     convert::{TryFrom, TryInto},
-    env, fmt, fs, io,
+    env, fmt, fs,
+    io::{self, Write},
+    iter,
+    os::unix::fs::MetadataExt,
     path::{Path, PathBuf},
     process,
     str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
     time::{Duration, SystemTime},
 };
 use thiserror::Error;
@@ -44,7 +51,9 @@ use tui::{
     style::{Color, Modifier, Style},
     terminal::Frame,
     text::{Span, Spans, Text},
-    widgets::{Block, BorderType, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
+    widgets::{
+        Block, BorderType, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph, Wrap,
+    },
     Terminal,
 };
 
@@ -57,26 +66,30 @@ use rustyline_derive::Helper;
 use unicode_segmentation::{Graphemes, UnicodeSegmentation};
 use unicode_width::UnicodeWidthStr;
 use wutag_core::{
-    color::{color_tui_from_fg_str, parse_color_tui, TuiColor},
-    tag::Tag,
+    color::{color_tui_from_fg_str, parse_color, parse_color_tui, TuiColor},
+    tag::{DirEntryExt, Tag},
 };
 
 use super::{
     banner::Banner,
-    command::{self, Command as TuiCommand},
-    completion::{self, CompletionList},
+    command::{self, BulkJob, Command as TuiCommand},
+    completion::{self, CompletionList, RegistryCandidates},
     event::Key,
     history::HistoryContext,
     keybindings::Keybinding,
     list::StatefulList,
+    preview::{self, FileKind, PreviewImage},
     table::{Row, Table, TableSelection, TableState},
+    theme::Palette,
 };
 
 use crate::{
-    config::{Config, HeaderAlignment},
+    config::{Config, HeaderAlignment, TableSort, UiLayout},
+    hashing::Hashing,
+    diagnostics, messages, mounts,
     opt::{Command, Opts},
-    registry::{EntryData, EntryId, TagRegistry},
-    subcommand::App,
+    registry::{self, EntryData, EntryId, TagRegistry},
+    subcommand::{search::TagSets, App},
     wutag_fatal,
 };
 
@@ -84,18 +97,15 @@ static RUN_ONCE: OnceCell<Result<()>> = OnceCell::new();
 
 const MAX_LINE: usize = 4096;
 
-pub(crate) const FG: [u8; 3] = [232, 192, 151];
-pub(crate) const FG2: [u8; 3] = [217, 174, 128];
-pub(crate) const PINK: [u8; 3] = [239, 29, 85];
-pub(crate) const DARK_PINK: [u8; 3] = [152, 103, 106];
-pub(crate) const DARK_PURPLE: [u8; 3] = [115, 62, 139];
-pub(crate) const MAGENTA: [u8; 3] = [160, 100, 105];
-pub(crate) const BLUE: [u8; 3] = [126, 178, 177];
-pub(crate) const DARK_BLUE: [u8; 3] = [76, 150, 168];
-pub(crate) const YELLOW: [u8; 3] = [255, 149, 0];
-pub(crate) const ORANGE: [u8; 3] = [255, 88, 19];
-pub(crate) const GREEN: [u8; 3] = [129, 156, 59];
-pub(crate) const BRIGHT_GREEN: [u8; 3] = [163, 185, 90];
+/// How much the tags column and the preview are resized by (out of 100)
+const LAYOUT_STEP: i32 = 5;
+
+/// How many files a change to the marked files is applied to on each tick
+const BULK_CHUNK: usize = 32;
+
+/// Subcommands whose arguments are completed with the tags and values of the
+/// registry in the command prompt
+const TAG_SUBCOMMANDS: &[&str] = &["set", "rm", "search"];
 
 /// Errors used within the UI module of this crate
 #[derive(Debug, Error)]
@@ -140,6 +150,16 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
 /// UI aspect of this App
 #[derive(Debug)]
 pub(crate) struct UiApp {
+    /// Actions of the command palette that match its query, best first
+    pub(crate) action_matches:          StatefulList<Keybinding>,
+    /// What is typed into the command palette
+    pub(crate) action_query:            String,
+    /// The change that is being made to the marked files
+    pub(crate) bulk:                    Option<BulkJob>,
+    /// Set when the registry changes, so the completions are collected again
+    pub(crate) candidates_stale:        Arc<AtomicBool>,
+    /// Set when the registry changes, so the paths are imported again
+    pub(crate) changed:                 Arc<AtomicBool>,
     pub(crate) command:                 TuiCommand,
     pub(crate) command_buffer:          LineBuffer,
     pub(crate) command_history_context: HistoryContext,
@@ -147,6 +167,9 @@ pub(crate) struct UiApp {
     pub(crate) completion_list:         CompletionList,
     pub(crate) completion_show:         bool,
     pub(crate) config:                  Config,
+    /// Whether the content of each file still has the hash in the registry,
+    /// which is checked in another thread
+    pub(crate) content_checks:          Arc<Mutex<HashMap<EntryId, String>>>,
     pub(crate) current_context:         String,
     pub(crate) current_context_command: String,
     pub(crate) current_directory:       String,
@@ -157,22 +180,45 @@ pub(crate) struct UiApp {
     pub(crate) current_selection_path:  Option<PathBuf>,
     pub(crate) dirty:                   bool,
     pub(crate) error:                   String,
-    pub(crate) file_details:            HashMap<EntryId, String>, // TODO: Show a stat command
+    /// Text of the detail pane of each file, until the registry changes
+    pub(crate) file_details:            HashMap<EntryId, String>,
     pub(crate) history_status:          Option<String>,
+    /// The image that was last rendered for the preview, until another file or
+    /// size is previewed
+    pub(crate) image_cache:             Option<PreviewImage>,
     pub(crate) keybindings:             StatefulList<Keybinding>,
     pub(crate) last_export:             Option<SystemTime>,
+    /// Set when the layout is changed with a keybinding, so it is saved on exit
+    pub(crate) layout_changed:          bool,
     pub(crate) list_height:             u16,
     pub(crate) list_state:              ListState,
     pub(crate) marked:                  HashSet<EntryId>,
     pub(crate) mode:                    AppMode,
+    /// What the last command that was run from the prompt printed
+    pub(crate) output:                  String,
+    pub(crate) output_scroll:           u16,
+    /// Colors of the theme, resolved for the terminal
+    pub(crate) palette:                 Palette,
     pub(crate) paths_color:             Color,
+    /// Key of the action that was picked in the command palette, which is
+    /// handled before the next event
+    pub(crate) pending_input:           Option<Key>,
     pub(crate) preview_file:            bool,
     pub(crate) preview_height:          u16,
+    /// The image that is drawn over the preview in this frame
+    pub(crate) preview_image:           Option<PreviewImage>,
     pub(crate) preview_scroll:          u16,
     pub(crate) registry:                TagRegistry,
+    pub(crate) registry_candidates:     Option<RegistryCandidates>,
     pub(crate) registry_paths:          Vec<PathBuf>,
     pub(crate) should_quit:             bool,
+    pub(crate) show_details:            bool,
     pub(crate) table_state:             TableState,
+    /// The tags that the files in the table must have, set with `@filter`
+    pub(crate) tag_filter:              TagSets,
+    /// A `wutag` command that uses the terminal, which is run once the TUI has
+    /// left it
+    pub(crate) terminal_command:        Option<Opts>,
     pub(crate) terminal_height:         u16,
     pub(crate) terminal_width:          u16,
 }
@@ -187,14 +233,18 @@ pub(crate) enum AppMode {
     /// Help menu for all other keybindings
     Help,
     /// Command buffer help
-    HelpPopup, /* Remove,
-                * Set,
-                * Clear,
-                * Search,
-                * Cp,
-                * Edit,
-                * View,
-                * Clear */
+    HelpPopup,
+    /// Output of a command that was run from the command prompt
+    Output,
+    /// Fuzzy finder over the actions of the keybindings
+    Palette, /* Remove,
+              * Set,
+              * Clear,
+              * Search,
+              * Cp,
+              * Edit,
+              * View,
+              * Clear */
 }
 
 impl fmt::Display for AppMode {
@@ -205,13 +255,22 @@ impl fmt::Display for AppMode {
             AppMode::Help => write!(f, "Help"),
             AppMode::HelpPopup => write!(f, "Help Popup"),
             AppMode::Command => write!(f, "Command"),
+            AppMode::Output => write!(f, "Output"),
+            AppMode::Palette => write!(f, "Palette"),
         }
     }
 }
 
 impl UiApp {
     /// Create a new instance of the `UiApp`
-    pub(crate) fn new(c: Config, reg: TagRegistry) -> Result<Self> {
+    pub(crate) fn new(mut c: Config, reg: TagRegistry) -> Result<Self> {
+        if let Some(layout) = UiLayout::load() {
+            layout.apply(&mut c.ui);
+        }
+        if c.ui.high_contrast {
+            c.ui.use_high_contrast();
+        }
+
         let (w, h) = crossterm::terminal::size()?;
         let mut state = ListState::default();
         if !reg.entries.is_empty() {
@@ -226,6 +285,8 @@ impl UiApp {
             }
         });
 
+        let palette = Palette::new(&c.ui.theme, parsed_color);
+
         let cwd = env::current_dir()
             .unwrap_or_else(|_| {
                 PathBuf::from(env::var("PWD").unwrap_or_else(|_| ".".to_string())).lexiclean()
@@ -234,6 +295,11 @@ impl UiApp {
             .to_string();
 
         let mut uiapp = Self {
+            action_matches:          StatefulList::default(),
+            action_query:            String::new(),
+            bulk:                    None,
+            candidates_stale:        Arc::new(AtomicBool::new(false)),
+            changed:                 Arc::new(AtomicBool::new(false)),
             command:                 TuiCommand::None,
             command_buffer:          LineBuffer::with_capacity(MAX_LINE),
             command_history_context: HistoryContext::new(&c.ui.history_filepath)?,
@@ -241,6 +307,7 @@ impl UiApp {
             completion_list:         CompletionList::with_items(vec![]),
             completion_show:         false,
             config:                  c.clone(),
+            content_checks:          Arc::new(Mutex::new(HashMap::new())),
             current_context:         String::from(""),
             current_context_command: String::from(""),
             current_directory:       cwd,
@@ -251,20 +318,31 @@ impl UiApp {
             error:                   String::from(""),
             file_details:            HashMap::new(),
             history_status:          None,
+            image_cache:             None,
             keybindings:             StatefulList::default(),
             last_export:             None,
+            layout_changed:          false,
             list_height:             0,
             list_state:              state,
             marked:                  HashSet::new(),
             mode:                    AppMode::List,
-            paths_color:             parsed_color,
+            output:                  String::new(),
+            output_scroll:           0,
+            palette,
+            paths_color:             palette.path,
+            pending_input:           None,
             preview_file:            false,
             preview_height:          0,
+            preview_image:           None,
             preview_scroll:          0,
             registry:                reg,
+            registry_candidates:     None,
             registry_paths:          Vec::new(),
             should_quit:             false,
+            show_details:            false,
             table_state:             TableState::default(),
+            tag_filter:              TagSets::default(),
+            terminal_command:        None,
             terminal_height:         h,
             terminal_width:          w,
         };
@@ -273,6 +351,7 @@ impl UiApp {
             uiapp.command_buffer.insert(ch, 1);
         }
 
+        uiapp.observe_registry();
         uiapp.get_context();
         uiapp.import_paths();
         uiapp.get_keybindings();
@@ -399,7 +478,7 @@ impl UiApp {
     fn get_keybindings(&mut self) {
         let keys = self.config.keys;
         let gen_key = |key: Key, alt: Option<&str>, desc: &str| -> Keybinding {
-            if let Some(alt) = alt {
+            let binding = if let Some(alt) = alt {
                 Keybinding::new(
                     format!("{},{}", key.name(), alt),
                     keys.fieldname(key),
@@ -407,7 +486,8 @@ impl UiApp {
                 )
             } else {
                 Keybinding::new(key.name(), keys.fieldname(key), desc.to_string())
-            }
+            };
+            binding.with_input(key)
         };
 
         let keybindings = vec![
@@ -415,6 +495,12 @@ impl UiApp {
                 ":".to_string(),
                 "command prompt".to_string(),
                 "Enter a command in the prompt".to_string(),
+            )
+            .with_input(Key::Char(':')),
+            gen_key(
+                keys.palette,
+                None,
+                "Search the actions of the keybindings and run one",
             ),
             Keybinding::new(
                 "M-.".to_string(),
@@ -441,19 +527,41 @@ impl UiApp {
             gen_key(keys.select_all, None, "Select all items"),
             gen_key(keys.select, None, "Select one item"),
             gen_key(keys.refresh, None, "Refresh the application\n:refresh"),
-            gen_key(keys.add, None, "Add tag(s) to file(s)\n:add"),
-            gen_key(keys.clear, None, "Clear tag(s) from file(s)\n:clear"),
-            gen_key(keys.remove, None, "Remove tag(s) from file(s)\n:remove"),
+            gen_key(keys.add, None, "Add tag(s) to the marked file(s)\n:@add"),
+            gen_key(
+                keys.clear,
+                None,
+                "Clear tag(s) from the marked file(s)\n:@clear",
+            ),
+            gen_key(
+                keys.remove,
+                None,
+                "Remove tag(s) from the marked file(s)\n:@remove",
+            ),
             gen_key(keys.edit, None, "Edit tag(s) on file(s)\n:edit"),
             gen_key(keys.view, None, "View tag(s) on file(s) in editor\n:view"),
             gen_key(keys.search, None, "Search for tag(s) or file(s)\n:search"),
             gen_key(
                 keys.copy,
                 None,
-                "Copy tag(s) from one file to another\n:copy",
+                "Copy the tag(s) of the selected file to the marked file(s)\n:@copy",
             ),
             // TODO:
             gen_key(keys.preview, None, "Preview a file in $PAGER\n:preview"),
+            gen_key(
+                keys.details,
+                None,
+                "Show the metadata, hash, values, and tags of the selected file",
+            ),
+            gen_key(
+                keys.sort,
+                None,
+                "Sort the table by path, tag count, or mtime",
+            ),
+            gen_key(keys.grow_tags, None, "Widen the tags column"),
+            gen_key(keys.shrink_tags, None, "Narrow the tags column"),
+            gen_key(keys.grow_preview, None, "Make the preview taller"),
+            gen_key(keys.shrink_preview, None, "Make the preview shorter"),
         ];
 
         self.keybindings = StatefulList::with_items(keybindings);
@@ -491,13 +599,16 @@ impl UiApp {
         let rect = f.size();
         self.terminal_width = rect.width;
         self.terminal_height = rect.height;
+        self.preview_image = None;
         // Use for whenever (if ever) a new mode is added
         match self.mode {
             AppMode::List
             | AppMode::Error
             | AppMode::Help
             | AppMode::HelpPopup
-            | AppMode::Command => self.draw_tag(app, f),
+            | AppMode::Command
+            | AppMode::Output
+            | AppMode::Palette => self.draw_tag(app, f),
         }
     }
 
@@ -561,7 +672,7 @@ impl UiApp {
             .map(|s| {
                 let style = Style::default().add_modifier(Modifier::ITALIC);
                 s.get_description_text(if self.is_colored() {
-                    style.fg(Color::Rgb(DARK_BLUE[0], DARK_BLUE[1], DARK_BLUE[2]))
+                    style.fg(self.palette.command)
                 } else {
                     style
                 })
@@ -602,6 +713,7 @@ impl UiApp {
                     .enumerate()
                     .map(|(i, v)| {
                         v.as_list_item(
+                            &self.palette,
                             self.is_colored(),
                             self.keybindings.state.selected() == Some(i),
                         )
@@ -611,7 +723,7 @@ impl UiApp {
             .block(
                 Block::default()
                     .borders(Borders::RIGHT)
-                    .border_style(Style::default().fg(Color::DarkGray)),
+                    .border_style(Style::default().fg(self.palette.border)),
             )
             .style(if self.is_colored() {
                 Style::default().fg(self.paths_color)
@@ -635,14 +747,14 @@ impl UiApp {
                     .block(
                         Block::default()
                             .borders(Borders::RIGHT)
-                            .border_style(Style::default().fg(Color::DarkGray)),
+                            .border_style(Style::default().fg(self.palette.border)),
                     )
                     .style(
                         Style::default()
                             .fg(if self.config.ui.flashy && self.is_colored() {
                                 self.gen_random_color()
                             } else if self.is_colored() {
-                                Color::Magenta
+                                self.palette.help
                             } else {
                                 Color::Reset
                             })
@@ -678,14 +790,14 @@ impl UiApp {
 
             f.render_widget(
                 Paragraph::new(if self.is_colored() {
-                    styled_context(&banner, Color::Magenta, self)
+                    styled_context(&banner, self.palette.help, self)
                 } else {
                     Text::raw(banner)
                 })
                 .block(
                     Block::default()
                         .borders(Borders::BOTTOM)
-                        .border_style(Style::default().fg(Color::DarkGray)),
+                        .border_style(Style::default().fg(self.palette.border)),
                 )
                 .style(if self.is_colored() {
                     Style::default().fg(self.paths_color)
@@ -699,14 +811,14 @@ impl UiApp {
 
             f.render_widget(
                 Paragraph::new(if self.is_colored() {
-                    styled_context(&self.current_context, Color::Cyan, self)
+                    styled_context(&self.current_context, self.palette.command, self)
                 } else {
                     Text::raw(&self.current_context)
                 })
                 .block(
                     Block::default()
                         .borders(Borders::NONE)
-                        .border_style(Style::default().fg(Color::DarkGray)),
+                        .border_style(Style::default().fg(self.palette.border)),
                 )
                 .style(if self.is_colored() {
                     Style::default().fg(self.paths_color)
@@ -743,17 +855,25 @@ impl UiApp {
             // FIX: Issues of returning value referencing function
             // Would be nice to use function above for this
             vec![
-                app.set_header_style::<PINK>("Wutag", match_mode(AppMode::List)),
-                app.set_header_style::<FG>("|", Modifier::SLOW_BLINK),
-                app.set_header_style::<PINK>("Other", match_mode(AppMode::Help)),
+                app.set_header_style(app.palette.header, "Wutag", match_mode(AppMode::List)),
+                app.set_header_style(
+                    app.palette.text,
+                    "|",
+                    if app.config.ui.high_contrast {
+                        Modifier::empty()
+                    } else {
+                        Modifier::SLOW_BLINK
+                    },
+                ),
+                app.set_header_style(app.palette.header, "Other", match_mode(AppMode::Help)),
                 Span::from("──("),
-                app.set_header_style::<FG>("Mode: ", Modifier::DIM),
+                app.set_header_style(app.palette.text, "Mode: ", Modifier::DIM),
                 if app.is_colored() {
                     Span::styled(
                         mode,
                         Style::default()
                             .add_modifier(Modifier::BOLD)
-                            .fg(Color::Rgb(ORANGE[0], ORANGE[1], ORANGE[2])),
+                            .fg(app.palette.selection),
                     )
                 } else {
                     Span::from(mode)
@@ -768,17 +888,29 @@ impl UiApp {
             .constraints([Constraint::Min(0), Constraint::Length(3)].as_ref())
             .split(rect);
 
+        let main = if self.show_details {
+            let details_layout = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+                .split(chunks[0]);
+
+            self.draw_details(f, details_layout[1]);
+            details_layout[0]
+        } else {
+            chunks[0]
+        };
+
         if self.preview_file {
             let split_layout = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints(
                     [
                         Constraint::Percentage(100 - self.config.ui.preview_height),
-                        Constraint::Percentage(self.preview_height),
+                        Constraint::Percentage(self.config.ui.preview_height),
                     ]
                     .as_ref(),
                 )
-                .split(chunks[0]);
+                .split(main);
 
             self.preview_height = split_layout[1].height;
             self.draw_table(
@@ -792,7 +924,7 @@ impl UiApp {
             let full_layout = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints([Constraint::Percentage(100)].as_ref())
-                .split(chunks[0]);
+                .split(main);
 
             self.preview_height = full_layout[0].height;
             self.draw_table(
@@ -840,7 +972,7 @@ impl UiApp {
                 f,
                 chunks[1],
                 self.command_buffer.as_str(),
-                self.set_header_style::<PINK>("Command Prompt", Modifier::DIM),
+                self.set_header_style(self.palette.header, "Command Prompt", Modifier::DIM),
                 self.get_position(&self.command_buffer),
                 false,
             ),
@@ -853,7 +985,7 @@ impl UiApp {
                     f,
                     chunks[1],
                     self.command_buffer.as_str(),
-                    self.set_header_style::<PINK>("Command Prompt", Modifier::BOLD),
+                    self.set_header_style(self.palette.header, "Command Prompt", Modifier::BOLD),
                     position,
                     true,
                 );
@@ -863,7 +995,7 @@ impl UiApp {
                     f,
                     chunks[1],
                     self.command_buffer.as_str(),
-                    self.set_header_style::<PINK>("Command Prompt", Modifier::BOLD),
+                    self.set_header_style(self.palette.header, "Command Prompt", Modifier::BOLD),
                     self.get_position(&self.command_buffer),
                     false,
                 );
@@ -879,7 +1011,7 @@ impl UiApp {
                     f,
                     chunks[1],
                     self.command_buffer.as_str(),
-                    self.set_header_style::<PINK>("Command Prompt", Modifier::BOLD),
+                    self.set_header_style(self.palette.header, "Command Prompt", Modifier::BOLD),
                     self.get_position(&self.command_buffer),
                     false,
                 );
@@ -887,15 +1019,172 @@ impl UiApp {
                     f,
                     80,
                     90,
-                    vec![self.set_header_style::<PINK>("Command Help", Modifier::BOLD)],
+                    vec![self.set_header_style(
+                        self.palette.header,
+                        "Command Help",
+                        Modifier::BOLD,
+                    )],
                     self.command_keybindings.clone(),
                 );
             },
-            AppMode::Error =>
-                self.draw_command(f, chunks[1], self.error.as_str(), "Error", 0, false),
+            AppMode::Error => self.draw_command(
+                f,
+                chunks[1],
+                self.error.as_str(),
+                self.set_header_style(self.palette.error, "Error", Modifier::BOLD),
+                0,
+                false,
+            ),
+            AppMode::Output => {
+                self.draw_command(
+                    f,
+                    chunks[1],
+                    self.command_buffer.as_str(),
+                    self.set_header_style(self.palette.header, "Command Prompt", Modifier::DIM),
+                    self.get_position(&self.command_buffer),
+                    false,
+                );
+                self.draw_output(f, centered_rect(80, 80, f.size()));
+            },
+            AppMode::Palette => {
+                self.draw_command(
+                    f,
+                    chunks[1],
+                    self.command_buffer.as_str(),
+                    self.set_header_style(self.palette.header, "Command Prompt", Modifier::DIM),
+                    self.get_position(&self.command_buffer),
+                    false,
+                );
+                self.draw_palette(f, centered_rect(60, 60, f.size()));
+            },
+        }
+
+        if self.bulk.is_some() {
+            let area = centered_rect(60, 100, f.size());
+            let height = cmp::min(3, area.height);
+            let top = area.y + (area.height - height) / 2;
+            self.draw_progress(f, Rect::new(area.x, top, area.width, height));
         }
     }
 
+    /// Draw the progress of the change that is being made to the marked files
+    fn draw_progress(&self, f: &mut Frame<impl Backend>, rect: Rect) {
+        if let Some(job) = &self.bulk {
+            let title = job.command.to_string();
+            let gauge = Gauge::default()
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_type(BorderType::Rounded)
+                        .title(self.set_header_style(self.palette.header, &title, Modifier::BOLD)),
+                )
+                .gauge_style(self.colored_style(self.palette.info, Modifier::BOLD))
+                .percent(job.percent())
+                .label(format!(
+                    "{}/{} ({} failed)",
+                    job.done,
+                    job.paths.len(),
+                    job.failed.len()
+                ));
+
+            f.render_widget(Clear, rect);
+            f.render_widget(gauge, rect);
+        }
+    }
+
+    /// Draw the output of the command that was run from the prompt
+    fn draw_output(&mut self, f: &mut Frame<impl Backend>, rect: Rect) {
+        let num_lines = self.output.lines().count() as u16;
+        self.output_scroll = std::cmp::min(
+            num_lines.saturating_sub(rect.height.saturating_sub(2)),
+            self.output_scroll,
+        );
+
+        let title = format!(
+            "Output──({}/{})",
+            self.output_scroll
+                .saturating_add(rect.height.saturating_sub(2))
+                .min(num_lines),
+            num_lines
+        );
+        let p = Paragraph::new(
+            ansi_to_text(self.output.as_bytes().iter().map(Clone::clone)).unwrap_or_else(|_| {
+                Text::from("Error: there was an error parsing ansi escape sequences")
+            }),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title(self.set_header_style(self.palette.header, &title, Modifier::BOLD)),
+        )
+        .scroll((self.output_scroll, 0));
+
+        f.render_widget(Clear, rect);
+        f.render_widget(p, rect);
+    }
+
+    /// Draw the command palette, which is the query above the actions that
+    /// match it
+    fn draw_palette(&self, f: &mut Frame<impl Backend>, rect: Rect) {
+        f.render_widget(Clear, rect);
+        f.render_widget(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(Style::default().fg(self.palette.border))
+                .title(self.set_header_style(
+                    self.palette.header,
+                    &format!(
+                        "Command Palette──({}/{})",
+                        self.action_matches.items.len(),
+                        self.keybindings.items.len()
+                    ),
+                    Modifier::BOLD,
+                )),
+            rect,
+        );
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(1)
+            .constraints([Constraint::Length(2), Constraint::Min(1)].as_ref())
+            .split(rect);
+
+        f.render_widget(
+            Paragraph::new(Spans::from(vec![
+                Span::styled("> ", self.colored_style(self.palette.key, Modifier::BOLD)),
+                Span::styled(
+                    self.action_query.as_str(),
+                    self.colored_style(self.palette.text, Modifier::empty()),
+                ),
+            ]))
+            .block(
+                Block::default()
+                    .borders(Borders::BOTTOM)
+                    .border_style(Style::default().fg(self.palette.border)),
+            ),
+            chunks[0],
+        );
+        f.set_cursor(
+            chunks[0].x + 2 + self.action_query.width() as u16,
+            chunks[0].y,
+        );
+
+        let selected = self.action_matches.state.selected();
+        let list = List::new(
+            self.action_matches
+                .items
+                .iter()
+                .enumerate()
+                .map(|(i, v)| v.as_list_item(&self.palette, self.is_colored(), selected == Some(i)))
+                .collect::<Vec<ListItem>>(),
+        )
+        .highlight_symbol(&self.config.ui.selection_indicator);
+
+        f.render_stateful_widget(list, chunks[1], &mut self.action_matches.state.clone());
+    }
+
     #[allow(single_use_lifetimes)]
     fn draw_command<'a, T>(
         &self,
@@ -924,7 +1213,7 @@ impl UiApp {
                 Block::default()
                     .borders(Borders::ALL)
                     .border_type(BorderType::Rounded)
-                    .style(Style::default().fg(Color::Rgb(FG[0], FG[1], FG[2])))
+                    .style(Style::default().fg(self.palette.text))
                     .title(title.into()),
             )
             .scroll((0, ((position + 3) as u16).saturating_sub(rect.width)));
@@ -946,34 +1235,8 @@ impl UiApp {
 
         let selected = self.selected();
         let path = self.registry_paths[selected].clone();
-
-        let mut cmd = if which::which("bat").is_ok() {
-            let mut bat = process::Command::new("bat");
-            bat.arg("--paging=never");
-            bat.arg("--style=numbers");
-            bat.arg(format!("--terminal-width={}", self.terminal_width - 2));
-            bat.arg("--color=always");
-            bat
-        } else {
-            process::Command::new("cat")
-        };
-
-        // This may not be needed since no pager is being opened
-        cmd.env("LESSCHARSET", "utf-8");
-        cmd.arg(path.display().to_string());
-
-        let output = cmd.output();
-        let preview = match output {
-            Ok(out) =>
-                if out.status.success() {
-                    String::from_utf8_lossy(&out.stdout).to_string()
-                } else {
-                    format!("Error: {}", String::from_utf8_lossy(&out.stderr))
-                },
-            Err(e) => {
-                format!("Error: {}", e)
-            },
-        };
+        let inner = Block::default().borders(Borders::ALL).inner(rect);
+        let preview = self.preview_content(&path, inner);
 
         // Rect height = 20
         // Bat preview lines = 18
@@ -994,16 +1257,16 @@ impl UiApp {
         let mut defstyle = Style::default();
         let title = if self.is_colored() {
             vec![
-                self.set_header_style::<PINK>("Entry", Modifier::BOLD),
+                self.set_header_style(self.palette.header, "Entry", Modifier::BOLD),
                 Span::from(": "),
                 Span::styled(
                     path.display().to_string(),
-                    defstyle.fg(Color::Rgb(ORANGE[0], ORANGE[1], ORANGE[2])),
+                    defstyle.fg(self.palette.selection),
                 ),
                 Span::from("──("),
-                self.set_header_style::<GREEN>(&current_line, Modifier::BOLD),
+                self.set_header_style(self.palette.info, &current_line, Modifier::BOLD),
                 Span::from("/"),
-                self.set_header_style::<GREEN>(&num_lines, Modifier::BOLD),
+                self.set_header_style(self.palette.info, &num_lines, Modifier::BOLD),
                 Span::from(")"),
             ]
         } else {
@@ -1031,6 +1294,180 @@ impl UiApp {
         f.render_widget(p, rect);
     }
 
+    /// The text of the preview of the file at `path`, which is empty when an
+    /// image is drawn over the `inner` part of the pane instead
+    fn preview_content(&mut self, path: &Path, inner: Rect) -> String {
+        let size = fs::metadata(path).map_or(0, |m| m.len());
+
+        match preview::kind(path) {
+            Ok(FileKind::Text) => preview::text(path, self.terminal_width - 2),
+            Ok(FileKind::Binary) if size <= preview::HEXDUMP_LIMIT =>
+                fs::read(path).map_or_else(|e| format!("Error: {}", e), |b| preview::hexdump(&b)),
+            Ok(FileKind::Binary) => format!("binary file, {} bytes", size),
+            Ok(FileKind::Image) => {
+                let cached = self
+                    .image_cache
+                    .as_ref()
+                    .filter(|image| image.path == path && image.rect == inner)
+                    .cloned();
+                let protocol = self.config.ui.image_protocol.resolve();
+                let image = cached.or_else(|| PreviewImage::render(path, inner, protocol));
+
+                self.image_cache = image.clone();
+                self.preview_image = image;
+                if self.preview_image.is_some() {
+                    String::new()
+                } else {
+                    format!("image file, {} bytes", size)
+                }
+            },
+            Err(e) => format!("Error: {}", e),
+        }
+    }
+
+    /// Draw the details of the selected file, which are only gathered again
+    /// once the registry changes. Whether its content changed is added once
+    /// the file has been hashed
+    fn draw_details(&mut self, f: &mut Frame<impl Backend>, rect: Rect) {
+        let id = self
+            .registry_paths
+            .get(self.current_selection)
+            .and_then(|path| self.registry.find_entry(path));
+
+        let text = match id {
+            Some(id) => {
+                if !self.file_details.contains_key(&id) {
+                    let details = self.file_detail(id);
+                    self.file_details.insert(id, details);
+                    self.check_content(id);
+                }
+
+                let content = self
+                    .content_checks
+                    .lock()
+                    .ok()
+                    .and_then(|checks| checks.get(&id).cloned())
+                    .unwrap_or_else(|| String::from("checking..."));
+                format!("{}\nContent:  {}", self.file_details[&id], content)
+            },
+            None => String::from("No tagged file is selected"),
+        };
+
+        let p = Paragraph::new(Text::from(text))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Rounded)
+                    .title(self.set_header_style(self.palette.header, "Details", Modifier::BOLD)),
+            )
+            .wrap(Wrap { trim: false });
+
+        f.render_widget(p, rect);
+    }
+
+    /// Check whether the content of the file with `id` still has the hash in
+    /// the registry. A large file takes a while to hash, so this is done in
+    /// another thread instead of while drawing
+    fn check_content(&self, id: EntryId) {
+        let entry = match self.registry.get_entry(id) {
+            Some(entry) => entry.clone(),
+            None => return,
+        };
+        let checks = Arc::clone(&self.content_checks);
+
+        thread::spawn(move || {
+            let content = match Hashing::of(entry.hash()).hash_file(entry.path()) {
+                Ok(hash) if hash == entry.hash() => String::from("unchanged since it was tagged"),
+                Ok(_) => String::from("changed since it was tagged"),
+                Err(e) => e.to_string(),
+            };
+            if let Ok(mut checks) = checks.lock() {
+                checks.insert(id, content);
+            }
+        });
+    }
+
+    /// Describe the file with `id`: its metadata, hash, values, and whether the
+    /// tags in its extended attributes agree with the registry
+    fn file_detail(&self, id: EntryId) -> String {
+        let entry = match self.registry.get_entry(id) {
+            Some(entry) => entry,
+            None => return String::from("The file is not in the registry"),
+        };
+        let path = entry.path();
+        let time = |secs: i64| {
+            Local
+                .timestamp(secs, 0)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        };
+
+        let mut lines = vec![format!(
+            "Path:     {}",
+            self.alias_replace(&path.display().to_string())
+        )];
+        match fs::metadata(path) {
+            Ok(meta) => {
+                lines.push(format!("Size:     {} bytes", meta.len()));
+                lines.push(format!("Mode:     {:o}", meta.mode() & 0o7777));
+                lines.push(format!("Owner:    {}:{}", meta.uid(), meta.gid()));
+                lines.push(format!("Modified: {}", time(meta.mtime())));
+                lines.push(format!("Accessed: {}", time(meta.atime())));
+                lines.push(format!("Changed:  {}", time(meta.ctime())));
+            },
+            Err(e) => lines.push(format!("Stat:     {}", e)),
+        }
+
+        lines.push(format!("Hash:     {}", entry.hash()));
+
+        if let Some(values) = self.registry.list_entry_values(id) {
+            lines.push(String::from("Values:"));
+            for (tag, value) in values {
+                lines.push(format!("  {}={}", tag, value));
+            }
+        }
+
+        // TODO: List the implied tags and the implication that produced each one
+        //       once tags can imply others
+
+        let registered = self
+            .registry
+            .list_entry_tags(id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|t| t.name().to_owned())
+            .collect::<BTreeSet<_>>();
+        match path.list_tags() {
+            Ok(tags) => {
+                let written = tags
+                    .iter()
+                    .map(|t| t.name().to_owned())
+                    .collect::<BTreeSet<_>>();
+                let join = |tags: Vec<&String>| {
+                    tags.into_iter()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                let only_written = written.difference(&registered).collect::<Vec<_>>();
+                let only_registered = registered.difference(&written).collect::<Vec<_>>();
+
+                if only_written.is_empty() && only_registered.is_empty() {
+                    lines.push(String::from("Tags:     the file agrees with the registry"));
+                }
+                if !only_written.is_empty() {
+                    lines.push(format!("Only on the file:     {}", join(only_written)));
+                }
+                if !only_registered.is_empty() {
+                    lines.push(format!("Only in the registry: {}", join(only_registered)));
+                }
+            },
+            Err(e) => lines.push(format!("Tags:     {}", e)),
+        }
+
+        lines.join("\n")
+    }
+
     /// Draw the tag table (filepaths tags)
     fn draw_table(&mut self, app: &App, f: &mut Frame<impl Backend>, rect: Rect, title: Vec<Span>) {
         let entries = self.get_full_tag_hash();
@@ -1052,16 +1489,17 @@ impl UiApp {
             return;
         }
 
-        let entries_name = entries.iter().fold(Vec::new(), |mut acc, (k, v)| {
-            acc.push(vec![
-                k.display().to_string(),
-                v.iter()
-                    .map(|tag| tag.name().to_string())
-                    .collect::<Vec<String>>()
-                    .join(" "),
-            ]);
-            acc
-        });
+        // The rows are in the order of `registry_paths`, which is how they are sorted
+        let entries_name = self
+            .registry_paths
+            .iter()
+            .map(|path| {
+                let tags: Vec<String> = entries.get(path).map_or_else(Vec::new, |tags| {
+                    tags.iter().map(|tag| tag.name().to_string()).collect()
+                });
+                vec![path.display().to_string(), tags.join(" ")]
+            })
+            .collect::<Vec<_>>();
 
         let maximum_column_width = rect.width;
         let widths = self.calculate_widths(&entries_name, &headers, maximum_column_width);
@@ -1133,12 +1571,12 @@ impl UiApp {
                 Block::default()
                     .borders(Borders::ALL)
                     .border_type(BorderType::Rounded)
-                    .style(Style::default().fg(Color::Rgb(FG[0], FG[1], FG[2])))
+                    .style(Style::default().fg(self.palette.text))
                     .title(Spans::from(title))
                     .title_alignment(Alignment::Left),
             )
             .header_style(if self.is_colored() {
-                header_style.fg(Color::Rgb(DARK_PINK[0], DARK_PINK[1], DARK_PINK[2]))
+                header_style.fg(self.palette.table_header)
             } else {
                 header_style
             })
@@ -1178,7 +1616,7 @@ impl UiApp {
             .iter()
             .map(|p| {
                 let lines = vec![Spans::from(p.display.clone())];
-                ListItem::new(lines).style(Style::default().fg(Color::Rgb(FG[0], FG[1], FG[2])))
+                ListItem::new(lines).style(Style::default().fg(self.palette.text))
             })
             .collect();
 
@@ -1187,11 +1625,11 @@ impl UiApp {
         // Create a List from all list items and highlight the currently selected one
         let items = List::new(items)
             .block(Block::default().borders(Borders::NONE).title(""))
-            .style(Style::default().fg(Color::Red))
+            .style(Style::default().fg(self.palette.text))
             .highlight_style(
                 Style::default()
                     .add_modifier(Modifier::BOLD)
-                    .fg(Color::Rgb(ORANGE[0], ORANGE[1], ORANGE[2])),
+                    .fg(self.palette.selection),
             )
             .highlight_symbol(&self.config.ui.selection_indicator);
 
@@ -1223,6 +1661,16 @@ impl UiApp {
 
     #[allow(clippy::unnecessary_wraps)]
     pub(crate) fn handle_input(&mut self, input: Key) -> Result<()> {
+        // Keys are ignored until the marked files are changed, except to cancel
+        if let Some(job) = self.bulk.take() {
+            if input == Key::Ctrl('c') {
+                self.finish_bulk(job);
+            } else {
+                self.bulk = Some(job);
+            }
+            return Ok(());
+        }
+
         match self.mode {
             AppMode::List =>
                 if input == self.config.keys.quit || input == Key::Ctrl('c') {
@@ -1252,11 +1700,31 @@ impl UiApp {
                 } else if input == self.config.keys.help {
                     self.mode = AppMode::Help;
                 } else if input == Key::Char(':') {
-                    self.mode = AppMode::Command;
-                    self.command_history_context.last();
-                    self.update_completion_list();
+                    self.open_prompt("");
+                } else if input == self.config.keys.palette {
+                    self.open_palette();
+                } else if input == self.config.keys.add {
+                    self.open_prompt("@add ");
+                } else if input == self.config.keys.remove {
+                    self.open_prompt("@remove ");
+                } else if input == self.config.keys.clear {
+                    self.open_prompt("@clear");
+                } else if input == self.config.keys.copy {
+                    self.open_prompt("@copy");
                 } else if input == self.config.keys.preview {
                     self.preview_file = !self.preview_file;
+                } else if input == self.config.keys.details {
+                    self.show_details = !self.show_details;
+                } else if input == self.config.keys.sort {
+                    self.cycle_sort();
+                } else if input == self.config.keys.grow_tags {
+                    self.resize_tags(LAYOUT_STEP);
+                } else if input == self.config.keys.shrink_tags {
+                    self.resize_tags(-LAYOUT_STEP);
+                } else if input == self.config.keys.grow_preview {
+                    self.resize_preview(LAYOUT_STEP);
+                } else if input == self.config.keys.shrink_preview {
+                    self.resize_preview(-LAYOUT_STEP);
                 } else if input == self.config.keys.preview_down {
                     self.preview_scroll_down();
                 } else if input == self.config.keys.preview_up {
@@ -1329,14 +1797,7 @@ impl UiApp {
                         self.completion_list.unselect();
                         self.dirty = true;
                     } else {
-                        // TODO: add error
-                        // TODO: Run commands here
-                        self.mode = AppMode::List;
-                        self.command_history_context
-                            .add(self.command_buffer.as_str());
-                        // command::handle_command(&self);
-                        self.command_buffer.update("", 0);
-                        self.update(true)?;
+                        self.run_prompt()?;
                     }
                 },
                 Key::Up =>
@@ -1401,6 +1862,52 @@ impl UiApp {
                 },
             },
             AppMode::Error => self.mode = AppMode::List,
+            AppMode::Palette => match input {
+                Key::Ctrl('c') => self.should_quit = true,
+                Key::Esc => self.mode = AppMode::List,
+                Key::Char('\n') => {
+                    self.pending_input = self.action_matches.selected().and_then(|k| k.input);
+                    self.mode = AppMode::List;
+                },
+                Key::Down | Key::Tab | Key::Ctrl('n') =>
+                    if !self.action_matches.items.is_empty() {
+                        self.action_matches.next();
+                    },
+                Key::Up | Key::BackTab | Key::Ctrl('p') =>
+                    if !self.action_matches.items.is_empty() {
+                        self.action_matches.previous();
+                    },
+                Key::Backspace => {
+                    self.action_query.pop();
+                    self.filter_actions();
+                },
+                Key::Ctrl('u') => {
+                    self.action_query.clear();
+                    self.filter_actions();
+                },
+                Key::Char(c) => {
+                    self.action_query.push(c);
+                    self.filter_actions();
+                },
+                _ => {},
+            },
+            AppMode::Output =>
+                if input == Key::Ctrl('c') {
+                    self.should_quit = true;
+                } else if input == self.config.keys.quit
+                    || input == Key::Esc
+                    || input == Key::Char('\n')
+                {
+                    self.mode = AppMode::List;
+                } else if input == Key::Down || input == self.config.keys.down {
+                    self.output_scroll = self.output_scroll.saturating_add(1);
+                } else if input == Key::Up || input == self.config.keys.up {
+                    self.output_scroll = self.output_scroll.saturating_sub(1);
+                } else if input == Key::PageDown || input == self.config.keys.page_down {
+                    self.output_scroll = self.output_scroll.saturating_add(self.list_height);
+                } else if input == Key::PageUp || input == self.config.keys.page_up {
+                    self.output_scroll = self.output_scroll.saturating_sub(self.list_height);
+                },
             /* } else if input == self.config.keys.go_to_bottom || input == Key::End {
              *     self.move_to_bottom();
              * } else if input == self.config.keys.go_to_top || input == Key::Home {
@@ -1438,6 +1945,10 @@ impl UiApp {
     /// path
     pub(crate) fn alias_replace(&self, replace: &str) -> String {
         let alias_hash = self.config.ui.clone().build_alias_hash();
+        if alias_hash.is_empty() {
+            return replace.to_owned();
+        }
+
         let re = Regex::new(
             format!(
                 r"({})",
@@ -1513,17 +2024,36 @@ impl UiApp {
             widths[index] -= 1;
         }
 
+        // A width chosen with the keybindings splits the room of both columns
+        let tags = headers.iter().position(|h| h == "Tag(s)");
+        let name = headers.iter().position(|h| h == "Filename");
+        if let (Some(tags), Some(name), width @ 1..=100) = (tags, name, self.config.ui.tags_width) {
+            let total = widths[tags] + widths[name];
+            widths[tags] = total * usize::from(width) / 100;
+            widths[name] = total - widths[tags];
+        }
+
         widths
     }
 
     // TODO: set correct functions
     /// Refresh the application state
     pub(crate) fn update(&mut self, force: bool) -> Result<()> {
-        if force || self.dirty || self.changed_since(self.last_export).unwrap_or(true) {
+        self.step_bulk();
+
+        if force
+            || self.dirty
+            || self.changed.swap(false, Ordering::Relaxed)
+            || self.changed_since(self.last_export).unwrap_or(true)
+        {
             super::notify("updatin", None);
             self.last_export = Some(SystemTime::now());
             self.import_paths();
             self.get_context();
+            self.file_details.clear();
+            if let Ok(mut checks) = self.content_checks.lock() {
+                checks.clear();
+            }
             self.dirty = false;
             self.save_history()?;
             // self.current_selection = 0;
@@ -1855,10 +2385,26 @@ impl UiApp {
                         ]
                         .choose(&mut rng)
                         .copied()
-                        .unwrap_or(Color::Blue)
+                        .unwrap_or(self.palette.tag_fallback)
                     })
             })
-            .unwrap()
+            .unwrap_or(self.palette.tag_fallback)
+    }
+
+    /// Returns the icon displayed before the `tag`, preferring the one in the
+    /// registry over the configuration
+    fn tag_icon(&self, tag: &Tag) -> Option<&str> {
+        if self.config.no_icons {
+            return None;
+        }
+
+        self.registry.get_tag_icon(tag.name()).or_else(|| {
+            self.config
+                .icons
+                .as_ref()
+                .and_then(|icons| icons.get(tag.name()))
+                .map(String::as_str)
+        })
     }
 
     /// Returns a `Text` object of every styled `Tag`
@@ -1887,6 +2433,9 @@ impl UiApp {
             }
 
             style = style.add_modifier(modifiers);
+            if let Some(icon) = self.tag_icon(tag) {
+                colored.push(Span::styled(format!("{} ", icon), style));
+            }
             colored.push(Span::styled(tag.clone().name().to_string(), style));
         }
 
@@ -1937,9 +2486,8 @@ impl UiApp {
         //     println!("PARSED FIRST");
         //     style = style.fg(color);
 
-        if let Some(color) = color_tui_from_fg_str(&tag.color().to_fg_str()) {
-            style = style.fg(color);
-        }
+        let color = color_tui_from_fg_str(&tag.color().to_fg_str());
+        style = style.fg(color.unwrap_or(self.palette.tag_fallback));
 
         modifiers |= Modifier::BOLD;
         style = style.add_modifier(modifiers);
@@ -1947,57 +2495,28 @@ impl UiApp {
     }
 
     /// Return a styled `Span` based on user configuration
-    fn set_header_style<'a, const COLOR: [u8; 3]>(
-        &self,
-        text: &'a str,
-        modif: Modifier,
-    ) -> Span<'a> {
-        Span::styled(text, self.colored_style::<COLOR>(modif))
+    fn set_header_style<'a>(&self, color: Color, text: &'a str, modif: Modifier) -> Span<'a> {
+        Span::styled(text, self.colored_style(color, modif))
     }
 
     // Would use this instead, however it requires two generic argumens when used
     /// Return a styled `Span` based on user configuration
-    fn set_header_style_alt<'a, const COLOR: [u8; 3], T>(
-        &self,
-        text: &'a T,
-        modif: Modifier,
-    ) -> Span<'a>
+    fn set_header_style_alt<'a, T>(&self, color: Color, text: &'a T, modif: Modifier) -> Span<'a>
     where
         T: AsRef<str>,
     {
-        Span::styled(text.as_ref(), self.colored_style::<COLOR>(modif))
+        Span::styled(text.as_ref(), self.colored_style(color, modif))
     }
 
     /// Return a `Style` depending on user configuration
-    fn colored_style<const COLOR: [u8; 3]>(&self, modif: Modifier) -> Style {
+    fn colored_style(&self, color: Color, modif: Modifier) -> Style {
         if self.is_colored() {
-            Style::default()
-                .add_modifier(modif)
-                .fg(Color::Rgb(COLOR[0], COLOR[1], COLOR[2]))
+            Style::default().add_modifier(modif).fg(color)
         } else {
             Style::default()
         }
     }
 
-    // INFO: Double const. Not used because can't use match statements
-    // fn set_header_style<'a, const COLOR: [u8; 3], const MOD: Modifier>(
-    //     &self,
-    //     text: &'a str,
-    // ) -> Span<'a> {
-    //     Span::styled(text, self.colored_style::<COLOR, MOD>())
-    // }
-    //
-    // /// Return a `Style` depending on user configuration
-    // fn colored_style<const COLOR: [u8; 3], const MOD: Modifier>(&self) -> Style {
-    //     if self.is_colored() {
-    //         Style::default()
-    //             .add_modifier(MOD)
-    //             .fg(Color::Rgb(COLOR[0], COLOR[1], COLOR[2]))
-    //     } else {
-    //         Style::default()
-    //     }
-    // }
-
     // #################### REGISTRY ####################
     //
 
@@ -2028,15 +2547,72 @@ impl UiApp {
     /// Import the paths from the registry
     pub(crate) fn import_paths(&mut self) {
         let entries = self.get_full_tag_hash();
-        let mut paths = vec![];
+        let mut paths = entries
+            .keys()
+            .filter(|p| {
+                self.registry
+                    .find_entry(p)
+                    .map_or(false, |id| self.tag_filter.matches(&self.registry, id))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
 
-        for entry in entries {
-            paths.push(entry.0);
+        // Paths that are equal otherwise stay in the order of their path
+        match self.config.ui.sort_by {
+            TableSort::Path => {},
+            TableSort::TagCount =>
+                paths.sort_by_key(|p| cmp::Reverse(entries.get(p).map_or(0, Vec::len))),
+            TableSort::Mtime => paths.sort_by_key(|p| {
+                cmp::Reverse(
+                    self.registry
+                        .find_entry(p)
+                        .and_then(|id| self.registry.get_entry(id))
+                        .map(|entry| *entry.modtime()),
+                )
+            }),
         }
 
         self.registry_paths = paths;
     }
 
+    /// Sort the table by the next order, keeping the same file selected
+    fn cycle_sort(&mut self) {
+        let selected = self.registry_paths.get(self.current_selection).cloned();
+        self.config.ui.sort_by = self.config.ui.sort_by.next();
+        self.layout_changed = true;
+        self.import_paths();
+
+        if let Some(idx) = selected.and_then(|s| self.registry_paths.iter().position(|p| *p == s)) {
+            self.current_selection = idx;
+        }
+    }
+
+    /// Change the width of the tags column by `step` out of 100, starting from
+    /// half of the table when it was fit to the tags
+    fn resize_tags(&mut self, step: i32) {
+        let width = match self.config.ui.tags_width {
+            0 => 50,
+            width => i32::from(width),
+        };
+        self.config.ui.tags_width = (width + step).clamp(10, 90) as u16;
+        self.layout_changed = true;
+    }
+
+    /// Change the height of the preview by `step` out of 100
+    fn resize_preview(&mut self, step: i32) {
+        self.config.ui.preview_height =
+            (i32::from(self.config.ui.preview_height) + step).clamp(10, 90) as u16;
+        self.layout_changed = true;
+    }
+
+    /// Save the layout if it was changed with a keybinding
+    pub(crate) fn save_layout(&self) -> Result<()> {
+        if self.layout_changed {
+            UiLayout::of(&self.config.ui).save()?;
+        }
+        Ok(())
+    }
+
     fn path_by_id(&self, id: EntryId) -> Option<&EntryData> {
         self.registry.get_entry(id)
 
@@ -2053,6 +2629,340 @@ impl UiApp {
             .position(|p| self.registry.find_entry(p).unwrap_or_default() == id)
     }
 
+    // #################### COMMANDS ####################
+    //
+
+    /// Mark the paths and the completions as stale whenever the registry changes
+    fn observe_registry(&self) {
+        let changed = Arc::clone(&self.changed);
+        self.registry
+            .on_change(move |_| changed.store(true, Ordering::Relaxed));
+        let stale = Arc::clone(&self.candidates_stale);
+        self.registry
+            .on_change(move |_| stale.store(true, Ordering::Relaxed));
+    }
+
+    /// Run the command in the prompt. Special commands starting with `@` are
+    /// handled by the TUI, and anything else is a `wutag` command
+    fn run_prompt(&mut self) -> Result<()> {
+        let line = self.command_buffer.as_str().to_owned();
+        self.command_history_context.add(&line);
+        self.command_buffer.update("", 0);
+        self.mode = AppMode::List;
+
+        match line.parse::<TuiCommand>() {
+            Ok(TuiCommand::Quit) => self.should_quit = true,
+            Ok(TuiCommand::ShowHelp) => self.mode = AppMode::Help,
+            Ok(TuiCommand::Refresh | TuiCommand::None) => {},
+            Ok(TuiCommand::Filter(args)) => match TagSets::from_args(&args) {
+                Ok(filter) => self.tag_filter = filter,
+                Err(e) => self.show_output(e.to_string()),
+            },
+            Ok(command) => self.start_bulk(command),
+            Err(_) if line.trim().is_empty() => {},
+            Err(_) => self.run_wutag_command(&line),
+        }
+
+        self.update(true)
+    }
+
+    /// Run a `wutag` command, displaying what it printed in the output pane. A
+    /// command that exits is stopped without exiting the TUI, and one that uses
+    /// the terminal is run once the TUI has left it
+    fn run_wutag_command(&mut self, line: &str) {
+        let args = match command::split_prompt(line) {
+            Ok(args) => args,
+            Err(e) => return self.show_output(e.to_string()),
+        };
+        let mut opts = match Opts::try_parse_from(iter::once(String::from("wutag")).chain(args)) {
+            Ok(opts) => opts,
+            Err(e) => return self.show_output(e.to_string()),
+        };
+
+        // These run until they are interrupted, which would also stop the TUI
+        let runs_forever = match &opts.cmd {
+            Command::Ui | Command::Repl | Command::Top(_) | Command::Serve(_) => true,
+            Command::Search(search) => search.watch,
+            _ => false,
+        };
+        if runs_forever {
+            return self.show_output(String::from(
+                "error: this command runs until it is stopped, run it outside of the TUI",
+            ));
+        }
+
+        // Commands use the same registry as the TUI unless another one is given
+        opts.reg.get_or_insert_with(|| self.registry.path.clone());
+
+        let uses_terminal = match &opts.cmd {
+            Command::View(_)
+            | Command::Edit(_)
+            | Command::Preserve(_)
+            | Command::GitSync(_)
+            | Command::CleanCache(_) => true,
+            #[cfg(feature = "auth")]
+            Command::Auth(_) => true,
+            Command::Search(search) =>
+                search.execute.is_some() || search.execute_batch.is_some(),
+            // The files are typed in the terminal
+            Command::Set(set) => set.stdin,
+            _ => false,
+        };
+        if uses_terminal {
+            self.terminal_command = Some(opts);
+            return;
+        }
+
+        let config = self.config.clone();
+        let output = command::capture_output(|| self.run_in_process(&opts, &config))
+            .unwrap_or_else(|e| format!("error: unable to capture the output: {}\n", e));
+
+        if self.reload_registry(&opts) {
+            self.show_output(output);
+        }
+    }
+
+    /// Run the `wutag` command that uses the terminal, after the TUI has left
+    /// it. What the command printed stays on the screen until `Enter` is pressed
+    pub(crate) fn run_terminal_command(&mut self, opts: &Opts) {
+        let config = self.config.clone();
+        let output = match self.run_in_process(opts, &config) {
+            Ok(()) => String::new(),
+            Err(e) => format!("error: {}\n", e),
+        };
+
+        print!("\nPress Enter to return to the TUI");
+        io::stdout().flush().ok();
+        io::stdin().read_line(&mut String::new()).ok();
+
+        if self.reload_registry(opts) {
+            self.show_output(output);
+        }
+    }
+
+    /// Run a `wutag` command in this process like it would be run from the
+    /// command-line. A command that exits is stopped with an error instead
+    fn run_in_process(&self, opts: &Opts, config: &Config) -> Result<()> {
+        diagnostics::catch_exit(|| App::run(opts.clone(), config))
+            .unwrap_or_else(|code| Err(anyhow!("wutag exited with {}", code)))
+    }
+
+    /// Load the registry again after a command was run, since the command
+    /// saved its own copy of it. Returns whether it was loaded
+    fn reload_registry(&mut self, opts: &Opts) -> bool {
+        match registry::load_registry(opts, &self.config.encryption) {
+            Ok(reg) => {
+                self.registry = reg;
+                self.observe_registry();
+                self.candidates_stale.store(true, Ordering::Relaxed);
+                true
+            },
+            Err(e) => {
+                self.mode = AppMode::Error;
+                self.error = format!("unable to load the registry again: {}", e);
+                false
+            },
+        }
+    }
+
+    /// Open the command palette with an empty query
+    fn open_palette(&mut self) {
+        self.mode = AppMode::Palette;
+        self.action_query.clear();
+        self.filter_actions();
+    }
+
+    /// Collect the actions that match the query of the command palette, best
+    /// first. Only actions that have a key on the main screen can be run
+    fn filter_actions(&mut self) {
+        let mut matches = self
+            .keybindings
+            .items
+            .iter()
+            .filter(|k| k.input.is_some())
+            .filter_map(|k| k.score(&self.action_query).map(|score| (score, k.clone())))
+            .collect::<Vec<_>>();
+        // The sort is stable, so ties stay in the order of the help menu
+        matches.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+        self.action_matches =
+            StatefulList::with_items(matches.into_iter().map(|(_, k)| k).collect());
+        if !self.action_matches.items.is_empty() {
+            self.action_matches.state.select(Some(0));
+        }
+    }
+
+    /// Open the command prompt with `text` in it, so that an action on the
+    /// marked files only needs its arguments and `Enter`
+    fn open_prompt(&mut self, text: &str) {
+        self.mode = AppMode::Command;
+        self.command_history_context.last();
+        self.command_buffer.update(text, text.len());
+        self.update_completion_list();
+    }
+
+    /// The tag named `name` in the registry, or a new one with one of the
+    /// colors of the configuration
+    fn resolve_tag(&self, name: &str) -> Tag {
+        self.registry.get_tag(name).cloned().unwrap_or_else(|| {
+            let colors = self
+                .config
+                .colors
+                .iter()
+                .flatten()
+                .filter_map(|c| parse_color(c).ok())
+                .collect::<Vec<_>>();
+            Tag::random(name, &colors)
+        })
+    }
+
+    /// Start applying `command` to the marked files, or to the highlighted one
+    /// when none are marked. The files are changed a few at a time by `update`,
+    /// so that the progress is displayed
+    fn start_bulk(&mut self, command: TuiCommand) {
+        let highlighted = self.registry_paths.get(self.current_selection).cloned();
+        let mut paths = self
+            .marked
+            .iter()
+            .filter_map(|id| self.registry.get_entry(*id))
+            .map(|entry| entry.path().to_path_buf())
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let tags = match &command {
+            TuiCommand::Add(names) | TuiCommand::Remove(names) =>
+                names.iter().map(|name| self.resolve_tag(name)).collect(),
+            TuiCommand::Cp => {
+                let source = match &highlighted {
+                    Some(source) => source,
+                    None => return,
+                };
+                paths.retain(|path| path != source);
+                match source.list_tags() {
+                    Ok(tags) => tags,
+                    Err(e) => {
+                        self.mode = AppMode::Error;
+                        self.error = format!("{}: {}", source.display(), messages::error_text(&e));
+                        return;
+                    },
+                }
+            },
+            _ => vec![],
+        };
+
+        if command != TuiCommand::Clear && tags.is_empty() {
+            self.mode = AppMode::Error;
+            self.error = String::from("there are no tags to change");
+            return;
+        }
+        if paths.is_empty() && command != TuiCommand::Cp {
+            paths.extend(highlighted);
+        }
+        if paths.is_empty() {
+            self.mode = AppMode::Error;
+            self.error = String::from("there are no marked files to change");
+            return;
+        }
+
+        self.bulk = Some(BulkJob::new(command, tags, paths));
+    }
+
+    /// Apply the change that is being made to the next few files, and display
+    /// a summary once every file is done
+    fn step_bulk(&mut self) {
+        let mut job = match self.bulk.take() {
+            Some(job) => job,
+            None => return,
+        };
+
+        let end = cmp::min(job.done + BULK_CHUNK, job.paths.len());
+        for path in &job.paths[job.done..end] {
+            if let Err(e) = self.apply_bulk(&job.command, &job.tags, path) {
+                job.failed.push((path.clone(), e));
+            }
+        }
+        job.done = end;
+
+        if job.is_finished() {
+            self.finish_bulk(job);
+        } else {
+            self.bulk = Some(job);
+        }
+    }
+
+    /// Make the change of `command` to the tags of the file at `path` and to
+    /// its entry in the registry
+    fn apply_bulk(
+        &mut self,
+        command: &TuiCommand,
+        tags: &[Tag],
+        path: &Path,
+    ) -> Result<(), String> {
+        let describe = |e: wutag_core::Error| messages::error_text(&e);
+
+        match command {
+            TuiCommand::Clear => {
+                path.clear_tags().map_err(describe)?;
+                if let Some(id) = self.registry.find_entry(path) {
+                    self.registry.clear_entry(id);
+                }
+            },
+            TuiCommand::Remove(_) => {
+                let id = self.registry.find_entry(path);
+                for tag in tags {
+                    // The color of the tag on the file may differ from the registry
+                    let tag = match path.get_tag(tag.name()) {
+                        Ok(tag) => tag,
+                        Err(wutag_core::Error::TagNotFound(_)) => continue,
+                        Err(e) => return Err(describe(e)),
+                    };
+                    path.untag(&tag).map_err(describe)?;
+                    if let Some(id) = id {
+                        self.registry.untag_entry(&tag, id);
+                    }
+                }
+            },
+            _ => {
+                for tag in tags {
+                    match path.tag(tag) {
+                        Ok(()) => mounts::verify_write(path, tag).map_err(describe)?,
+                        Err(wutag_core::Error::TagExists(_)) => {},
+                        Err(e) => return Err(describe(e)),
+                    }
+                }
+                let entry = EntryData::new(path).map_err(|e| e.to_string())?;
+                let id = self.registry.add_or_update_entry(entry);
+                for tag in tags {
+                    self.registry.use_tag(tag, id);
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Save the registry once the marked files are changed (or the change is
+    /// cancelled), and display how many files succeeded and failed
+    fn finish_bulk(&mut self, job: BulkJob) {
+        let mut summary = job.summary();
+        if let Err(e) = self.registry.save() {
+            summary.push_str(&format!("\nunable to save the registry: {}", e));
+        }
+
+        self.dirty = true;
+        self.show_output(summary);
+    }
+
+    /// Display `output` in the output pane, unless there is none
+    fn show_output(&mut self, output: String) {
+        if output.trim().is_empty() {
+            return;
+        }
+        self.output = output;
+        self.output_scroll = 0;
+        self.mode = AppMode::Output;
+    }
+
     // #################### COMPLETIONS ####################
     //
 
@@ -2097,6 +3007,11 @@ impl UiApp {
             .as_str()
             .split_whitespace()
             .collect::<Vec<_>>();
+        let subcommand = full_cmd
+            .iter()
+            .find(|cmd| !cmd.starts_with('-'))
+            .map(|cmd| (*cmd).to_owned())
+            .unwrap_or_default();
         let curr_cmd = if full_cmd.len() > 1 && full_cmd.last().unwrap_or(&"") == &"" {
             full_cmd.iter().rev().take(2).collect::<Vec<&&str>>()[1]
         } else {
@@ -2227,6 +3142,37 @@ impl UiApp {
                         match_args(sub, &mut self.completion_list);
                     }
                 }
+                self.complete_from_registry(&subcommand, &input);
+            }
+        }
+    }
+
+    /// Add the tags, values, and paths of the registry that complete the word
+    /// `input` of `subcommand` to the completion list. Paths are displayed
+    /// with the aliases of `alias_replace`
+    fn complete_from_registry(&mut self, subcommand: &str, input: &str) {
+        if input.starts_with('-') {
+            return;
+        }
+        if !input.starts_with('/') && !TAG_SUBCOMMANDS.contains(&subcommand) {
+            return;
+        }
+
+        if self.candidates_stale.swap(false, Ordering::Relaxed) {
+            self.registry_candidates = None;
+        }
+        if self.registry_candidates.is_none() {
+            let candidates =
+                RegistryCandidates::collect(&self.registry, |path| self.alias_replace(path));
+            self.registry_candidates = Some(candidates);
+        }
+
+        if let Some(candidates) = &self.registry_candidates {
+            for (item, display) in candidates.for_word(input) {
+                match display {
+                    Some(display) => self.completion_list.insert_displayed(item, display),
+                    None => self.completion_list.insert(item),
+                }
             }
         }
     }
@@ -2439,7 +3385,7 @@ fn styled_context<'a>(text: &'a str, color: Color, app: &UiApp) -> Text<'a> {
                 Spans::from(if values.len() >= 2 {
                     vec![
                         Span::styled(values[0], Style::default().fg(Color::Reset)),
-                        Span::styled(":", Style::default().fg(Color::DarkGray)),
+                        Span::styled(":", Style::default().fg(app.palette.border)),
                         Span::styled(
                             values.drain(1..).collect::<Vec<&str>>().join(":"),
                             Style::default()
@@ -1,5 +1,5 @@
 use anyhow::{anyhow, Result};
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime};
 use colored::{Color, ColoredString, Colorize};
 use ignore::{overrides::OverrideBuilder, WalkBuilder};
 use lexiclean::Lexiclean;
@@ -8,11 +8,14 @@ use once_cell::sync::Lazy;
 use regex::bytes::{Regex, RegexBuilder};
 use std::{
     borrow::Cow,
+    env,
     ffi::{OsStr, OsString},
     fmt::Display,
     fs,
     io::{self, BufRead, BufReader, Cursor, Write},
+    os::unix::ffi::OsStrExt,
     path::{Path, PathBuf},
+    str::FromStr,
     sync::{Arc, Once},
     time::SystemTime,
 };
@@ -26,13 +29,17 @@ use log::LevelFilter;
 // use crossbeam_utils::thread;
 // use rayon::prelude::*;
 
+#[cfg(feature = "archives")]
+use crate::archive;
 use crate::{
     consts::{APP_NAME, DEFAULT_MAX_DEPTH},
-    filesystem::{create_temp_ignore, delete_file, osstr_to_bytes, write_temp_ignore},
+    diagnostics,
+    filesystem::{create_temp_ignore, delete_file, osstr_to_bytes, write_temp_ignore, SeenFiles},
+    glob::GlobEngine,
     subcommand::App,
-    wutag_error, Opts,
+    wutag_error, wutag_warning, Opts,
 };
-use wutag_core::tag::Tag;
+use wutag_core::{color::parse_color, tag::Tag, xattr};
 
 static ONCE: Once = Once::new();
 static UPPER_REG: Lazy<Regex> = Lazy::new(|| Regex::new(r"[[:upper:]]").unwrap());
@@ -73,6 +80,32 @@ pub(crate) fn initialize_logging(args: &Opts) {
     });
 }
 
+/// Decide when output is colored. An explicit `--color` wins, followed by the
+/// `NO_COLOR` and `CLICOLOR_FORCE` environment variables. Porcelain output is
+/// never colored
+pub(crate) fn resolve_color_when(color: Option<&str>, porcelain: bool) -> &'static str {
+    let env_set = |var: &str| env::var_os(var).map_or(false, |v| !v.is_empty() && v != "0");
+
+    match color {
+        _ if porcelain => "never",
+        Some("always") => "always",
+        Some("never") => "never",
+        _ if env::var_os("NO_COLOR").map_or(false, |v| !v.is_empty()) => "never",
+        _ if env_set("CLICOLOR_FORCE") => "always",
+        _ if atty::is(atty::Stream::Stdout) => "auto",
+        _ => "never",
+    }
+}
+
+/// Make all colored output, including error messages, follow `color_when`
+pub(crate) fn set_color_override(color_when: &str) {
+    match color_when {
+        "never" => colored::control::SHOULD_COLORIZE.set_override(false),
+        "always" => colored::control::SHOULD_COLORIZE.set_override(true),
+        _ => colored::control::SHOULD_COLORIZE.unset_override(),
+    }
+}
+
 pub(crate) fn parse_path<P: AsRef<Path>>(path: P) -> Result<(), String> {
     fs::metadata(path)
         .map_err(|_| "must be a valid path")
@@ -89,6 +122,11 @@ pub(crate) fn fmt_ok<S: AsRef<str>>(msg: S) -> String {
 }
 
 pub(crate) fn fmt_path<P: AsRef<Path>>(path: P, base_color: Color, ls_colors: bool) -> String {
+    #[cfg(feature = "archives")]
+    if let Some(member) = archive::fmt_member(path.as_ref(), base_color, ls_colors) {
+        return member;
+    }
+
     // ls_colors implies forced coloring
     if ls_colors {
         LsColors::from_env()
@@ -131,6 +169,11 @@ pub(crate) fn fmt_local_path<P: AsRef<Path>>(
         .to_string()
         .replace(replaced.as_str(), "");
 
+    #[cfg(feature = "archives")]
+    if let Some(member) = archive::fmt_member(Path::new(&path), base_color, ls_colors) {
+        return member;
+    }
+
     if ls_colors {
         LsColors::from_env()
             .unwrap_or_default()
@@ -155,6 +198,129 @@ pub(crate) fn fmt_tag(tag: &Tag) -> ColoredString {
     tag.name().color(*tag.color()).bold()
 }
 
+/// Format the tag like [`fmt_tag`], with its `icon` in front of it
+pub(crate) fn fmt_tag_icon(tag: &Tag, icon: Option<&str>) -> String {
+    match icon {
+        Some(icon) => format!("{} {}", icon.color(*tag.color()), fmt_tag(tag)),
+        None => fmt_tag(tag).to_string(),
+    }
+}
+
+/// Style of the part of a path or tag that matched a search, which is written
+/// as words such as `underline` or `bold #FF5813`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct Highlight {
+    color:     Option<Color>,
+    bold:      bool,
+    italic:    bool,
+    underline: bool,
+    reversed:  bool,
+}
+
+impl Highlight {
+    /// Apply the style on top of the style `text` already has
+    pub(crate) fn paint(self, text: ColoredString) -> ColoredString {
+        let mut text = match self.color {
+            Some(color) => text.color(color),
+            None => text,
+        };
+        if self.bold {
+            text = text.bold();
+        }
+        if self.italic {
+            text = text.italic();
+        }
+        if self.underline {
+            text = text.underline();
+        }
+        if self.reversed {
+            text = text.reversed();
+        }
+        text
+    }
+}
+
+impl FromStr for Highlight {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut style = Self::default();
+        let mut words = s.split_whitespace();
+
+        while let Some(word) = words.next() {
+            match word.to_ascii_lowercase().as_str() {
+                "bold" => style.bold = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                "reverse" | "reversed" => style.reversed = true,
+                "bright" => {
+                    let color = words.next().unwrap_or_default();
+                    style.color = Some(
+                        format!("bright {}", color)
+                            .parse()
+                            .map_err(|_| format!("invalid color: bright {}", color))?,
+                    );
+                },
+                _ => {
+                    style.color = Some(
+                        parse_color(word)
+                            .ok()
+                            .or_else(|| word.parse().ok())
+                            .ok_or_else(|| format!("invalid highlight style: {}", word))?,
+                    );
+                },
+            }
+        }
+
+        Ok(style)
+    }
+}
+
+/// Format a path like [`fmt_path`] without `LS_COLORS`, highlighting the
+/// parts of it that match `re` with `style`
+pub(crate) fn fmt_highlighted_path(
+    path: &str,
+    re: &Regex,
+    base_color: Color,
+    style: Highlight,
+) -> String {
+    #[cfg(feature = "archives")]
+    if let Some(member) = archive::fmt_member(Path::new(path), base_color, false) {
+        return member;
+    }
+
+    let mut out = String::new();
+    let mut last = 0;
+    for m in re.find_iter(path.as_bytes()) {
+        let (start, end) = (m.start(), m.end());
+        // The pattern is matched against bytes, and can split a character
+        if start == end || !path.is_char_boundary(start) || !path.is_char_boundary(end) {
+            continue;
+        }
+        if last < start {
+            out.push_str(&path[last..start].color(base_color).bold().to_string());
+        }
+        let matched = path[start..end].color(base_color).bold();
+        out.push_str(&style.paint(matched).to_string());
+        last = end;
+    }
+    if last < path.len() {
+        out.push_str(&path[last..].color(base_color).bold().to_string());
+    }
+
+    out
+}
+
+/// Format the tag like [`fmt_tag_icon`], highlighted with `style` because it
+/// matched a search
+pub(crate) fn fmt_tag_highlight(tag: &Tag, icon: Option<&str>, style: Highlight) -> String {
+    let name = style.paint(fmt_tag(tag));
+    match icon {
+        Some(icon) => format!("{} {}", icon.color(*tag.color()), name),
+        None => name.to_string(),
+    }
+}
+
 /// Return a local path with no color, i.e., one in which /home/user/... is not
 /// used and it is relative to the current directory. The searching of the paths
 /// does not go above the folder in which this command is read and only searches
@@ -185,25 +351,139 @@ pub(crate) fn replace(haystack: &mut String, needle: &str, replacement: &str) ->
     }
 }
 
+/// The lines of `stdin`, which are read once so that a command that runs once
+/// for each registry (see `filesystem_registries`) is given all of them each time
+static STDIN_LINES: Lazy<Vec<String>> =
+    Lazy::new(|| BufReader::new(io::stdin()).lines().map(|p| p.unwrap()).collect());
+
 /// Collect the paths that are entered in through `stdin`. This can be achieved
 /// by doing something like: `fd <name> -tf | wutag set <tag>`
 pub(crate) fn collect_stdin_paths(base: &Path) -> Vec<PathBuf> {
-    BufReader::new(io::stdin())
-        .lines()
-        .map(|p| PathBuf::from(p.unwrap().as_str()).lexiclean())
+    STDIN_LINES
+        .iter()
+        .map(|p| PathBuf::from(p.as_str()).lexiclean())
         .filter(|path| {
+            // Files on another machine are checked when they are tagged
+            if xattr::remote_host().is_some() {
+                return true;
+            }
+
+            // A member of an archive exists if the archive does
+            #[cfg(feature = "archives")]
+            if let Some((archive, _)) = archive::split_member(path) {
+                return archive.is_file() || base.join(archive).is_file();
+            }
+
             fs::symlink_metadata(path).is_ok() || fs::symlink_metadata(base.join(path)).is_ok()
         })
         .map(|p| base.join(p))
         .collect::<Vec<_>>()
 }
 
+/// Collect the files that the git repository containing `base` reports as
+/// modified (staged or not) or untracked. Only the files under `base` are
+/// collected unless `global` is set, and ignored files never are
+pub(crate) fn collect_git_modified_paths(base: &Path, global: bool) -> Result<Vec<PathBuf>> {
+    let base = fs::canonicalize(base).unwrap_or_else(|_| base.to_path_buf());
+    let repo = git2::Repository::discover(&base)
+        .map_err(|e| anyhow!("{} is not inside of a git repository: {}", base.display(), e))?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| anyhow!("the git repository has no working directory"))?
+        .to_path_buf();
+
+    let mut options = git2::StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(false)
+        .exclude_submodules(true);
+    let modified = git2::Status::WT_NEW
+        | git2::Status::WT_MODIFIED
+        | git2::Status::WT_RENAMED
+        | git2::Status::WT_TYPECHANGE
+        | git2::Status::INDEX_NEW
+        | git2::Status::INDEX_MODIFIED
+        | git2::Status::INDEX_RENAMED
+        | git2::Status::INDEX_TYPECHANGE;
+
+    Ok(repo
+        .statuses(Some(&mut options))?
+        .iter()
+        .filter(|entry| entry.status().intersects(modified))
+        .map(|entry| workdir.join(OsStr::from_bytes(entry.path_bytes())))
+        // Deleted in the working tree, but still staged
+        .filter(|path| fs::symlink_metadata(path).is_ok())
+        .filter(|path| global || path.starts_with(&base))
+        .collect())
+}
+
 /// Convert a `SystemTime` to a [`DateTime`](chrono::DateTime)
 pub(crate) fn systemtime_to_datetime(t: SystemTime) -> String {
     let dt: DateTime<Local> = t.into();
     dt.format("%Y-%m-%d %H:%M:%S").to_string()
 }
 
+/// Expand the functions that are allowed within a tag's value in the
+/// configuration file (e.g., `now()` expands to the current date)
+pub(crate) fn expand_value(value: &str) -> String {
+    value.replace("now()", &Local::now().format("%Y-%m-%d").to_string())
+}
+
+/// Format of the expiration time that `set --temp` gives to a tag's value
+pub(crate) const EXPIRY_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// Parse the time a tag expires at out of its value. This is either a date
+/// (e.g., `expires=2024-07-01`), where the tag expires at the end of the day,
+/// or a local time as set by `set --temp` (e.g., `expires=2024-07-01T18:30:00`)
+pub(crate) fn parse_expiry(value: &str) -> Option<NaiveDateTime> {
+    let expiry = value.strip_prefix("expires=")?.trim();
+    NaiveDateTime::parse_from_str(expiry, EXPIRY_FORMAT)
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(expiry, "%Y-%m-%d")
+                .ok()
+                .and_then(NaiveDate::succ_opt)
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+}
+
+/// Parse a duration such as `2d`, `90m`, or `1w2d12h`, which is one or more
+/// numbers that are each followed by a unit (`s`, `m`, `h`, `d`, or `w`)
+pub(crate) fn parse_duration(duration: &str) -> Result<Duration> {
+    let invalid = || anyhow!("invalid duration (e.g., '2d' or '1w2d'): {}", duration);
+
+    let mut seconds = 0_i64;
+    let mut rest = duration.trim();
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let count = rest[..digits].parse::<i64>().map_err(|_| invalid())?;
+        let mut chars = rest[digits..].chars();
+        let unit = match chars.next() {
+            Some('s') => 1,
+            Some('m') => 60,
+            Some('h') => 60 * 60,
+            Some('d') => 24 * 60 * 60,
+            Some('w') => 7 * 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        seconds = count
+            .checked_mul(unit)
+            .and_then(|s| seconds.checked_add(s))
+            // The largest number of seconds a `Duration` can hold
+            .filter(|s| *s <= i64::MAX / 1000)
+            .ok_or_else(invalid)?;
+        rest = chars.as_str();
+    }
+
+    Ok(Duration::seconds(seconds))
+}
+
 /// Print completions to `stdout` or to a file
 pub(crate) fn gen_completions<G: Generator>(
     gen: G,
@@ -213,15 +493,34 @@ pub(crate) fn gen_completions<G: Generator>(
     generate(gen, app, APP_NAME, cursor);
 }
 
-/// Build a glob with [`GlobBuilder`](globset::GlobBuilder) and return a string
-/// to be compiled as a regular expression
+/// Build a glob with the [`GlobEngine`] of the configuration and return a
+/// string to be compiled as a regular expression
 pub(crate) fn glob_builder(pattern: &str) -> String {
-    let builder = globset::GlobBuilder::new(pattern);
-    builder
-        .build()
+    GlobEngine::current()
+        .regex(pattern)
         .expect("Invalid glob sequence")
-        .regex()
-        .to_owned()
+}
+
+/// Build the pattern that highlights the part of a path that a search pattern
+/// matched. A glob matches the whole path, so the wildcards at either end of it
+/// are dropped (e.g., `*.rs` highlights `.rs`). Returns `None` if nothing would
+/// be highlighted
+pub(crate) fn highlight_builder(pattern: &str, regex: bool) -> Option<String> {
+    if regex {
+        return Some(pattern.to_owned());
+    }
+
+    let trimmed = pattern.trim_matches('*');
+    if trimmed.is_empty() {
+        return None;
+    }
+
+    let glob = GlobEngine::current().glob(trimmed).ok()?;
+    let re = glob.regex();
+    let re = re.strip_prefix("(?-u)").unwrap_or(re);
+    let re = re.strip_prefix('^').unwrap_or(re);
+    let re = re.strip_suffix('$').unwrap_or(re);
+    Some(format!("(?-u){}", re))
 }
 
 /// Match uppercase characters against Unicode characters as well. Tags can also
@@ -256,7 +555,8 @@ pub(crate) fn regex_builder(pattern: &str, case_insensitive: bool, case_sensitiv
 }
 
 /// Returns an ignore::WalkParallel instance that uses `base_path`, and a
-/// pattern (both glob and regex) does not follow symlinks, respects hidden
+/// pattern (both glob and regex) follows symlinks only when `follow_symlinks`
+/// is set, respects hidden
 /// files, and uses max CPU's. If a `max_depth` is specified, the parallel
 /// walker will not traverse deeper than that, else if no `max_depth` is
 /// specified, it will use [DEFAULT_MAX_DEPTH](DEFAULT_MAX_DEPTH).
@@ -278,7 +578,7 @@ pub(crate) fn reg_walker(app: &Arc<App>) -> Result<ignore::WalkParallel> {
     let mut walker = WalkBuilder::new(&app.base_dir);
     walker
         .threads(num_cpus::get())
-        .follow_links(false)
+        .follow_links(app.follow_symlinks)
         .hidden(false)
         .ignore(false)
         .overrides(overrides)
@@ -313,6 +613,7 @@ where
     F: FnMut(&ignore::DirEntry) + Send + Sync,
 {
     let walker = reg_walker(app).unwrap();
+    diagnostics::matched(0);
 
     // TODO: Look into order of execution
     // Scope here does not require ownership of all the variables, or the use of a
@@ -322,7 +623,14 @@ where
 
         scope.spawn(|_| {
             let rx = rx;
-            rx.iter().for_each(|e| f(&e));
+            // A file that is reached through several paths is only used once
+            let mut seen = SeenFiles::default();
+            rx.iter()
+                .filter(|e| app.show_duplicates || !seen.is_duplicate(e.path()))
+                .for_each(|e| {
+                    diagnostics::matched(1);
+                    f(&e);
+                });
         });
 
         scope.spawn(|_| {
@@ -337,7 +645,7 @@ where
                     let entry = match res {
                         Ok(entry_) => entry_,
                         Err(err) => {
-                            wutag_error!("unable to access entry {}", err);
+                            wutag_warning!("unable to access entry {}", err);
                             return ignore::WalkState::Continue;
                         },
                     };
@@ -383,6 +691,18 @@ where
                         }
                     }
 
+                    // Filter out files by their size, owner and permissions
+                    if let Some(ref filter) = app.metadata_filter {
+                        if !filter.is_match(&entry) {
+                            return ignore::WalkState::Continue;
+                        }
+                    }
+
+                    // Filter out files that are routed to the registry of another filesystem
+                    if !app.filesystems.contains(entry_path) {
+                        return ignore::WalkState::Continue;
+                    }
+
                     // Using a match statement does not preserve output order for some reason
                     if let Err(e) = tx.send(entry) {
                         log::debug!("Sent quit: {:?}", e);
@@ -405,3 +725,67 @@ where
         app.base_dir.to_string_lossy().to_string()
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_highlight_styles() {
+        let style = "bold #FF5813".parse::<Highlight>().unwrap();
+        assert!(style.bold && !style.underline);
+        assert_eq!(style.color, Some(Color::TrueColor {
+            r: 255,
+            g: 88,
+            b: 19,
+        }));
+        assert_eq!(
+            "Underline bright red".parse::<Highlight>().unwrap().color,
+            Some(Color::BrightRed)
+        );
+        assert!("reverse".parse::<Highlight>().unwrap().reversed);
+        assert!("blinking".parse::<Highlight>().is_err());
+    }
+
+    #[test]
+    fn highlights_the_matching_part() {
+        let matched = |pattern: &str, regex: bool, path: &str| {
+            highlight_builder(pattern, regex).and_then(|re| {
+                Regex::new(&re)
+                    .unwrap()
+                    .find(path.as_bytes())
+                    .map(|m| path[m.start()..m.end()].to_owned())
+            })
+        };
+
+        assert_eq!(matched("*.rs", false, "src/main.rs"), Some(".rs".to_owned()));
+        assert_eq!(matched("*ma?n*", false, "src/main.rs"), Some("main".to_owned()));
+        assert_eq!(matched("m.*n", true, "src/main.rs"), Some("main".to_owned()));
+        assert_eq!(highlight_builder("**", false), None);
+    }
+
+    #[test]
+    fn parses_expiry_and_durations() {
+        let time = |s: &str| NaiveDateTime::parse_from_str(s, EXPIRY_FORMAT).ok();
+        assert_eq!(
+            parse_expiry("expires=2024-07-01"),
+            time("2024-07-02T00:00:00")
+        );
+        assert_eq!(
+            parse_expiry("expires=2024-07-01T18:30:00"),
+            time("2024-07-01T18:30:00")
+        );
+        assert_eq!(parse_expiry("2024-07-01"), None);
+        assert_eq!(parse_expiry("expires=soon"), None);
+
+        assert_eq!(parse_duration("2d").unwrap(), Duration::days(2));
+        assert_eq!(
+            parse_duration("1w2d12h").unwrap(),
+            Duration::days(9) + Duration::hours(12)
+        );
+        assert_eq!(parse_duration("90m").unwrap(), Duration::minutes(90));
+        for invalid in &["", "2", "d", "2y", "1d-", "99999999999999999w"] {
+            assert!(parse_duration(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! Keywords of XMP metadata, which is where photo managers such as Darktable,
+//! digiKam, and Lightroom keep their tags. The keywords are the `dc:subject`
+//! bag of either a sidecar next to the file (`photo.jpg.xmp` or `photo.xmp`)
+//! or the packet embedded in the file itself. Only sidecars are written, since
+//! rewriting the packet inside of an image depends on its format
+//!
+//! Elements are matched by the prefixes every tool writes them with (`rdf:`
+//! and `dc:`) instead of by resolving their namespaces
+
+use anyhow::{anyhow, Context, Result};
+use quick_xml::{
+    events::{BytesEnd, BytesStart, BytesText, Event},
+    Reader, Writer,
+};
+use std::{
+    fs::{self, File},
+    io::{Cursor, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// Extension of XMP sidecars
+pub(crate) const SIDECAR_EXT: &str = "xmp";
+
+/// Namespace of the Dublin Core elements, which `dc:subject` is one of
+const DC_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+
+/// Start of an XMP packet embedded in a file
+const PACKET_START: &[u8] = b"<x:xmpmeta";
+
+/// End of an XMP packet embedded in a file
+const PACKET_END: &[u8] = b"</x:xmpmeta>";
+
+/// Number of bytes at the start of a file that are searched for an embedded
+/// packet, so that large videos are not read whole
+const MAX_EMBEDDED_SCAN: u64 = 64 * 1024 * 1024;
+
+/// The sidecar that is written when a file does not have one
+const SIDECAR_TEMPLATE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/">
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+/// Whether the file at `path` is an XMP sidecar
+pub(crate) fn is_sidecar(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case(SIDECAR_EXT))
+}
+
+/// The sidecar that is written for the file at `path`, which is its name with
+/// `.xmp` appended (`photo.jpg.xmp`) like Darktable names them
+pub(crate) fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(SIDECAR_EXT);
+    PathBuf::from(name)
+}
+
+/// The existing sidecar of the file at `path`, which is either named like
+/// [`sidecar_path`] or has its extension replaced (`photo.xmp`)
+fn find_sidecar(path: &Path) -> Option<PathBuf> {
+    [sidecar_path(path), path.with_extension(SIDECAR_EXT)]
+        .iter()
+        .find(|p| p.is_file())
+        .cloned()
+}
+
+/// Position of `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// The XMP packet embedded in the `bytes` of a file. The packet is searched
+/// for instead of parsing the format of the file, which the specification
+/// allows for formats that are not known
+fn embedded_packet(bytes: &[u8]) -> Option<&str> {
+    let start = find(bytes, PACKET_START)?;
+    let end = start + find(&bytes[start..], PACKET_END)? + PACKET_END.len();
+    std::str::from_utf8(&bytes[start..end]).ok()
+}
+
+/// The keywords in the XMP `packet`, without duplicates
+pub(crate) fn parse_subjects(packet: &str) -> Result<Vec<String>> {
+    let mut reader = Reader::from_str(packet);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut subjects = Vec::new();
+    let (mut in_subject, mut in_item) = (false, false);
+    loop {
+        match reader.read_event(&mut buf).context("invalid XMP")? {
+            Event::Start(e) if e.name() == b"dc:subject" => in_subject = true,
+            Event::End(e) if e.name() == b"dc:subject" => in_subject = false,
+            Event::Start(e) if in_subject && e.name() == b"rdf:li" => in_item = true,
+            Event::End(e) if e.name() == b"rdf:li" => in_item = false,
+            Event::Text(e) if in_item => {
+                let subject = e.unescape_and_decode(&reader).context("invalid XMP")?;
+                if !subject.is_empty() && !subjects.contains(&subject) {
+                    subjects.push(subject);
+                }
+            },
+            Event::Eof => break,
+            _ => {},
+        }
+        buf.clear();
+    }
+
+    Ok(subjects)
+}
+
+/// The keywords of the file at `path`, from its sidecar or else from the
+/// packet embedded in it
+pub(crate) fn read_subjects(path: &Path) -> Result<Vec<String>> {
+    if let Some(sidecar) = find_sidecar(path) {
+        let packet = fs::read_to_string(&sidecar)
+            .with_context(|| format!("unable to read {}", sidecar.display()))?;
+        return parse_subjects(&packet).with_context(|| sidecar.display().to_string());
+    }
+
+    let mut bytes = Vec::new();
+    File::open(path)
+        .and_then(|f| f.take(MAX_EMBEDDED_SCAN).read_to_end(&mut bytes))
+        .with_context(|| format!("unable to read {}", path.display()))?;
+
+    embedded_packet(&bytes).map_or_else(|| Ok(vec![]), parse_subjects)
+}
+
+/// The start of an `rdf:Description` that declares the namespace of `dc:`
+fn with_dc_namespace(start: &BytesStart<'_>) -> BytesStart<'static> {
+    let mut start = start.to_owned();
+    if !start
+        .attributes()
+        .flatten()
+        .any(|attr| attr.key == b"xmlns:dc")
+    {
+        start.push_attribute(("xmlns:dc", DC_NAMESPACE));
+    }
+    start
+}
+
+/// Write the `subjects` as a `dc:subject` bag, or nothing if there are none
+fn write_subjects<W: Write>(writer: &mut Writer<W>, subjects: &[String]) -> Result<()> {
+    if subjects.is_empty() {
+        return Ok(());
+    }
+
+    writer.write_event(Event::Start(BytesStart::borrowed_name(b"dc:subject")))?;
+    writer.write_event(Event::Start(BytesStart::borrowed_name(b"rdf:Bag")))?;
+    for subject in subjects {
+        writer.write_event(Event::Start(BytesStart::borrowed_name(b"rdf:li")))?;
+        writer.write_event(Event::Text(BytesText::from_plain_str(subject)))?;
+        writer.write_event(Event::End(BytesEnd::borrowed(b"rdf:li")))?;
+    }
+    writer.write_event(Event::End(BytesEnd::borrowed(b"rdf:Bag")))?;
+    writer.write_event(Event::End(BytesEnd::borrowed(b"dc:subject")))?;
+
+    Ok(())
+}
+
+/// Replace the keywords of the XMP `packet` with `subjects`, keeping the rest
+/// of it as it is, such as the history of edits that Darktable keeps in its
+/// sidecars. The keywords are put in the first `rdf:Description`
+pub(crate) fn replace_subjects(packet: &str, subjects: &[String]) -> Result<String> {
+    let mut reader = Reader::from_str(packet);
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+
+    let mut buf = Vec::new();
+    // Depth inside of the `dc:subject` that is replaced
+    let mut skipped = 0_usize;
+    let mut written = false;
+    loop {
+        let event = reader.read_event(&mut buf).context("invalid XMP")?;
+        if skipped > 0 {
+            match event {
+                Event::Start(_) => skipped += 1,
+                Event::End(_) => skipped -= 1,
+                Event::Eof => return Err(anyhow!("invalid XMP: dc:subject is not closed")),
+                _ => {},
+            }
+            buf.clear();
+            continue;
+        }
+
+        match event {
+            Event::Start(e) if e.name() == b"dc:subject" => skipped = 1,
+            Event::Empty(e) if e.name() == b"dc:subject" => {},
+            Event::Start(e) if !written && e.name() == b"rdf:Description" => {
+                writer.write_event(Event::Start(with_dc_namespace(&e)))?;
+                write_subjects(&mut writer, subjects)?;
+                written = true;
+            },
+            Event::Empty(e) if !written && e.name() == b"rdf:Description" => {
+                writer.write_event(Event::Start(with_dc_namespace(&e)))?;
+                write_subjects(&mut writer, subjects)?;
+                writer.write_event(Event::End(BytesEnd::borrowed(b"rdf:Description")))?;
+                written = true;
+            },
+            Event::Eof => break,
+            event => writer.write_event(event)?,
+        }
+        buf.clear();
+    }
+
+    if !written {
+        return Err(anyhow!("no rdf:Description to put the keywords in"));
+    }
+
+    String::from_utf8(writer.into_inner().into_inner()).context("XMP is not UTF-8")
+}
+
+/// Write `tags` as the keywords of the sidecar of the file at `path`, which is
+/// created if it does not exist. Unless `replace`, the keywords the sidecar
+/// already has are kept. Returns the path of the sidecar
+pub(crate) fn write_sidecar(path: &Path, tags: &[String], replace: bool) -> Result<PathBuf> {
+    let (sidecar, packet) = match find_sidecar(path) {
+        Some(sidecar) => {
+            let packet = fs::read_to_string(&sidecar)
+                .with_context(|| format!("unable to read {}", sidecar.display()))?;
+            (sidecar, packet)
+        },
+        None => (sidecar_path(path), SIDECAR_TEMPLATE.to_owned()),
+    };
+
+    let mut subjects = if replace {
+        vec![]
+    } else {
+        parse_subjects(&packet).with_context(|| sidecar.display().to_string())?
+    };
+    for tag in tags {
+        if !subjects.contains(tag) {
+            subjects.push(tag.clone());
+        }
+    }
+
+    let packet =
+        replace_subjects(&packet, &subjects).with_context(|| sidecar.display().to_string())?;
+    fs::write(&sidecar, packet)
+        .with_context(|| format!("unable to write {}", sidecar.display()))?;
+
+    Ok(sidecar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DARKTABLE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/" x:xmptk="XMP Core 4.4.0-Exiv2">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:darktable="http://darktable.sf.net/"
+   darktable:xmp_version="4">
+   <dc:subject>
+    <rdf:Bag>
+     <rdf:li>travel</rdf:li>
+     <rdf:li>Rock &amp; Roll</rdf:li>
+     <rdf:li>travel</rdf:li>
+    </rdf:Bag>
+   </dc:subject>
+   <darktable:history>
+    <rdf:Seq>
+     <rdf:li darktable:operation="exposure"/>
+    </rdf:Seq>
+   </darktable:history>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+    #[test]
+    fn parses_subjects() -> Result<()> {
+        assert_eq!(parse_subjects(DARKTABLE)?, vec![
+            "travel".to_owned(),
+            "Rock & Roll".to_owned()
+        ]);
+        assert!(parse_subjects(SIDECAR_TEMPLATE)?.is_empty());
+        assert!(parse_subjects("<dc:subject></rdf:Bag>").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn replaces_subjects_and_keeps_the_rest() -> Result<()> {
+        let tags = vec!["work".to_owned(), "a<b".to_owned()];
+        let packet = replace_subjects(DARKTABLE, &tags)?;
+        assert_eq!(parse_subjects(&packet)?, tags);
+        assert!(packet.contains("a&lt;b"));
+        assert!(packet.contains(r#"<rdf:li darktable:operation="exposure"/>"#));
+        assert!(!packet.contains("travel"));
+
+        let packet = replace_subjects(
+            r#"<x:xmpmeta><rdf:RDF><rdf:Description rdf:about=""/></rdf:RDF></x:xmpmeta>"#,
+            &tags,
+        )?;
+        assert!(packet.contains(DC_NAMESPACE));
+        assert_eq!(parse_subjects(&packet)?, tags);
+
+        assert!(parse_subjects(&replace_subjects(DARKTABLE, &[])?)?.is_empty());
+        assert!(replace_subjects("<x:xmpmeta/>", &tags).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn finds_embedded_packets() {
+        let mut bytes = b"\xff\xd8\xff\xe1 http://ns.adobe.com/xap/1.0/\0".to_vec();
+        bytes.extend_from_slice(DARKTABLE.as_bytes());
+        bytes.extend_from_slice(b"\xff\xd9");
+
+        let packet = embedded_packet(&bytes).unwrap();
+        assert!(packet.starts_with("<x:xmpmeta"));
+        assert!(packet.ends_with("</x:xmpmeta>"));
+        assert_eq!(embedded_packet(b"\xff\xd8\xff\xd9"), None);
+    }
+
+    #[test]
+    fn writes_sidecars() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let photo = dir.path().join("photo.jpg");
+        fs::write(&photo, b"\xff\xd8\xff\xd9")?;
+        assert!(read_subjects(&photo)?.is_empty());
+
+        let sidecar = write_sidecar(&photo, &["work".to_owned()], false)?;
+        assert_eq!(sidecar, dir.path().join("photo.jpg.xmp"));
+        assert_eq!(read_subjects(&photo)?, vec!["work".to_owned()]);
+
+        write_sidecar(&photo, &["draft".to_owned()], false)?;
+        assert_eq!(read_subjects(&photo)?, vec![
+            "work".to_owned(),
+            "draft".to_owned()
+        ]);
+        write_sidecar(&photo, &["draft".to_owned()], true)?;
+        assert_eq!(read_subjects(&photo)?, vec!["draft".to_owned()]);
+        Ok(())
+    }
+}
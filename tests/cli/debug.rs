@@ -0,0 +1,36 @@
+use super::*;
+use std::fs;
+
+/// The paths, relative to `root`, and contents of the files under `dir`
+fn read_tree(root: &Path, dir: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(read_tree(root, &path));
+        } else {
+            let content = fs::read(&path).unwrap();
+            files.push((path.strip_prefix(root).unwrap().to_path_buf(), content));
+        }
+    }
+    files.sort();
+    files
+}
+
+#[test]
+fn generates_the_same_fixture_for_a_seed() {
+    let (one, two) = (tempdir().unwrap(), tempdir().unwrap());
+    for dir in &[&one, &two] {
+        wutag()
+            .args(&["debug", "generate-fixture", "--files", "20", "--tags", "4"])
+            .args(&["--seed", "7"])
+            .arg(dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("20 files with 4 tags"));
+    }
+
+    let tree = read_tree(one.path(), one.path());
+    assert_eq!(tree.len(), 20);
+    assert_eq!(tree, read_tree(two.path(), two.path()));
+}
@@ -1,4 +1,5 @@
 mod color;
+mod debug;
 mod list;
 mod rm;
 mod set;
@@ -30,7 +31,10 @@ static INIT: Once = Once::new();
 
 const FILE_DIR: &str = "tests/example_files";
 const ANOTHER_FILE_DIR: &str = "tests/sample_dir";
-const NEW_REGISTRY: &str = "tests/sample.reg";
+// The tests tag the same example files and share their extended attributes.
+// Local commands find the tags in those attributes, so they keep the registry
+// in memory, and the global ones give each test a registry of its own
+const IN_MEMORY_ENV: &str = "WUTAG_IN_MEMORY";
 
 static CWD: Lazy<PathBuf> = Lazy::new(|| {
     env::current_dir()
@@ -83,17 +87,24 @@ fn wutag_cmd_base_registry() -> Command {
     cmd
 }
 
-// BASE REGISTRY
-pub fn wutag_cmd_new_registry() -> Command {
+// IN-MEMORY REGISTRY
+pub fn wutag_cmd_in_memory() -> Command {
     let mut cmd = wutag_cmd_base_registry();
-    cmd.args(["--registry", NEW_REGISTRY, "--color=never"]);
+    cmd.env(IN_MEMORY_ENV, "1").arg("--color=never");
     cmd
 }
 
-// BASE REGISTRY - GLOBAL
-pub fn wutag_cmd_new_registry_global() -> Command {
+// GIVEN REGISTRY
+pub fn wutag_cmd_registry(reg: &str) -> Command {
     let mut cmd = wutag_cmd_base_registry();
-    cmd.args(["--registry", NEW_REGISTRY, "--global", "--color=never"]);
+    cmd.args(["--registry", reg, "--color=never"]);
+    cmd
+}
+
+// GIVEN REGISTRY - GLOBAL
+pub fn wutag_cmd_registry_global(reg: &str) -> Command {
+    let mut cmd = wutag_cmd_base_registry();
+    cmd.args(["--registry", reg, "--global", "--color=never"]);
     cmd
 }
 
@@ -105,30 +116,24 @@ pub fn wutag_cmd_random_registry() -> Command {
 }
 
 pub fn wutag() -> assert_cmd::Command {
-    assert_cmd::Command::from_std(wutag_cmd_new_registry())
+    assert_cmd::Command::from_std(wutag_cmd_in_memory())
 }
 
-pub fn wutag_global() -> assert_cmd::Command {
-    assert_cmd::Command::from_std(wutag_cmd_new_registry_global())
+pub fn wutag_global(reg: &str) -> assert_cmd::Command {
+    assert_cmd::Command::from_std(wutag_cmd_registry_global(reg))
 }
 
 pub fn wutag_rr() -> assert_cmd::Command {
     assert_cmd::Command::from_std(wutag_cmd_random_registry())
 }
 
-fn rm_registry() {
-    INIT.call_once(|| {
-        match assert_cmd::Command::new("rm")
-            .arg("-rf")
-            .arg(NEW_REGISTRY)
-            .ok()
-        {
-            Ok(_) => {},
-            Err(err) => {
-                eprintln!("Error removing registry {:?}", err);
-            },
-        };
-    });
+fn rm_registry(reg: &str) {
+    match assert_cmd::Command::new("rm").arg("-rf").arg(reg).ok() {
+        Ok(_) => {},
+        Err(err) => {
+            eprintln!("Error removing registry {:?}", err);
+        },
+    };
 }
 
 // ============================== SUBCMDS ===============================
@@ -136,7 +141,7 @@ fn rm_registry() {
 
 // CLEAR
 pub fn wutag_clear() {
-    wutag_cmd_new_registry()
+    wutag_cmd_in_memory()
         .args(&["-m", "5", "clear", "*"])
         .stdin(Stdio::null())
         .stdout(Stdio::null())
@@ -144,8 +149,8 @@ pub fn wutag_clear() {
         .expect("error removing tag");
 }
 
-pub fn wutag_clear_global() {
-    wutag_cmd_new_registry_global()
+pub fn wutag_clear_global(reg: &str) {
+    wutag_cmd_registry_global(reg)
         .args(&["-g", "clear", "*"])
         .stdin(Stdio::null())
         .stdout(Stdio::null())
@@ -154,8 +159,8 @@ pub fn wutag_clear_global() {
 }
 
 // CLEAN
-pub fn wutag_clean() {
-    wutag_cmd_new_registry()
+pub fn wutag_clean(reg: &str) {
+    wutag_cmd_registry(reg)
         .args(&["clean-cache"])
         .stdin(Stdio::null())
         .stdout(Stdio::null())
@@ -165,7 +170,16 @@ pub fn wutag_clean() {
 
 // SET
 pub fn wutag_set(pat: &str, tag: &str) {
-    wutag_cmd_new_registry()
+    wutag_cmd_in_memory()
+        .args(&["-m", "5", "set", pat, tag])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .status()
+        .expect("=== error setting tag ===");
+}
+
+pub fn wutag_set_registry(reg: &str, pat: &str, tag: &str) {
+    wutag_cmd_registry(reg)
         .args(&["-m", "5", "set", pat, tag])
         .stdin(Stdio::null())
         .stdout(Stdio::null())
@@ -175,7 +189,7 @@ pub fn wutag_set(pat: &str, tag: &str) {
 
 // RM
 pub fn wutag_rm(pat: &str, tag: &str) {
-    wutag_cmd_new_registry()
+    wutag_cmd_in_memory()
         .args(&["rm", pat, tag])
         .stdin(Stdio::null())
         .stdout(Stdio::null())
@@ -184,16 +198,16 @@ pub fn wutag_rm(pat: &str, tag: &str) {
 }
 
 // LIST FILES
-pub fn wutag_list_files() {
-    wutag_cmd_new_registry()
+pub fn wutag_list_files(reg: &str) {
+    wutag_cmd_registry(reg)
         .args(&["list", "files"])
         .status()
         .expect("=== error listing tag ===");
 }
 
 // LIST TAGS
-pub fn wutag_list_tags() {
-    wutag_cmd_new_registry()
+pub fn wutag_list_tags(reg: &str) {
+    wutag_cmd_registry(reg)
         .args(&["list", "tags"])
         .status()
         .expect("error removing tag");
@@ -101,12 +101,14 @@ fn local_exclude_glob() {
 #[test]
 fn global_glob() {
     INIT.call_once(|| {
-        wutag_set("*.c", "zzzk");
-        wutag_global()
+        let reg = create_temp_path();
+        wutag_set_registry(&reg, "*.c", "zzzk");
+        wutag_global(&reg)
             .args(&["rm", "*.c", "zzzk"])
             .assert()
             .success()
             .stdout(predicate::str::contains(expand_file!("hello.c")));
+        rm_registry(&reg);
     });
 }
 
@@ -114,8 +116,9 @@ fn global_glob() {
 #[test]
 fn global_case_sensitive_glob() {
     INIT.call_once(|| {
-        wutag_set("*.zsh", "zshz");
-        wutag_global()
+        let reg = create_temp_path();
+        wutag_set_registry(&reg, "*.zsh", "zshz");
+        wutag_global(&reg)
             .args(&["rm", "*eX*.zsh", "zshz"])
             .assert()
             .success()
@@ -126,14 +129,16 @@ fn global_case_sensitive_glob() {
             .stdout(predicate::function(|f: &str| {
                 !f.contains(expand_file!("sampd/exec.rs").as_str())
             }));
+        rm_registry(&reg);
     })
 }
 
 #[test]
 fn global_case_sensitive_flag_glob() {
     INIT.call_once(|| {
-        wutag_set("*.zsh", "zkz");
-        wutag_global()
+        let reg = create_temp_path();
+        wutag_set_registry(&reg, "*.zsh", "zkz");
+        wutag_global(&reg)
             .args(&["rm", "*.zsh", "zkz"])
             .assert()
             .success()
@@ -142,5 +147,6 @@ fn global_case_sensitive_flag_glob() {
             .stdout(predicate::str::contains(expand_file!("sampd/exec-not.zsh")))
             .stdout(predicate::str::contains(expand_file!("sampd/eXeC-cap.zsh")))
             .stdout(predicate::str::contains(expand_file!("sampd/pmas.zsh")));
+        rm_registry(&reg);
     });
 }
@@ -201,6 +201,74 @@ pub fn parse_color_tui<S: AsRef<str>>(color: S) -> Result<tui::Color> {
     Err(Error::InvalidColor(color.to_string()))
 }
 
+/// Names and approximate RGB values of the basic terminal colors
+const BASIC_COLORS: [(&str, (u8, u8, u8)); 8] = [
+    ("black", (0, 0, 0)),
+    ("red", (205, 0, 0)),
+    ("green", (0, 205, 0)),
+    ("yellow", (205, 205, 0)),
+    ("blue", (0, 0, 238)),
+    ("magenta", (205, 0, 205)),
+    ("cyan", (0, 205, 205)),
+    ("white", (229, 229, 229)),
+];
+
+/// Returns the name of the basic terminal color (e.g., `red`) that is closest
+/// to the given [Color](colored::Color)
+pub fn color_name(color: Color) -> &'static str {
+    let (r, g, b) = match color {
+        Color::TrueColor { r, g, b } => (r, g, b),
+        Color::Black | Color::BrightBlack => return "black",
+        Color::Red | Color::BrightRed => return "red",
+        Color::Green | Color::BrightGreen => return "green",
+        Color::Yellow | Color::BrightYellow => return "yellow",
+        Color::Blue | Color::BrightBlue => return "blue",
+        Color::Magenta | Color::BrightMagenta => return "magenta",
+        Color::Cyan | Color::BrightCyan => return "cyan",
+        Color::White | Color::BrightWhite => return "white",
+    };
+
+    let distance = |x: u8, y: u8| (i32::from(x) - i32::from(y)).pow(2);
+
+    BASIC_COLORS
+        .iter()
+        .min_by_key(|(_, (r1, g1, b1))| distance(r, *r1) + distance(g, *g1) + distance(b, *b1))
+        .map_or("white", |(name, _)| name)
+}
+
+/// Returns the `color` in hex notation (e.g., `#ef1d55`). The basic terminal
+/// colors are given the RGB values that xterm uses for them, since the actual
+/// values depend on the terminal's palette
+pub fn color_hex(color: Color) -> String {
+    let (r, g, b) = match color {
+        Color::TrueColor { r, g, b } => (r, g, b),
+        Color::BrightBlack => (127, 127, 127),
+        Color::BrightRed => (255, 0, 0),
+        Color::BrightGreen => (0, 255, 0),
+        Color::BrightYellow => (255, 255, 0),
+        Color::BrightBlue => (92, 92, 255),
+        Color::BrightMagenta => (255, 0, 255),
+        Color::BrightCyan => (0, 255, 255),
+        Color::BrightWhite => (255, 255, 255),
+        _ => BASIC_COLORS
+            .iter()
+            .find(|(name, _)| *name == color_name(color))
+            .map_or((229, 229, 229), |(_, rgb)| *rgb),
+    };
+
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Returns whether the `color` matches the `filter`, which is either a color in
+/// hex notation or the name of the basic terminal color closest to `color`
+pub fn color_matches<S: AsRef<str>>(color: Color, filter: S) -> bool {
+    let filter = filter.as_ref();
+    parse_color(filter).map_or_else(
+        |_| color_name(color) == filter.trim().to_ascii_lowercase(),
+        |c| c == color,
+    )
+}
+
 /// Wrapper for tui widget colors
 #[derive(Clone, Copy, Debug)]
 pub struct TuiColor {
@@ -256,7 +324,7 @@ impl Default for TuiColor {
 
 #[cfg(test)]
 mod tests {
-    use super::parse_color;
+    use super::{color_hex, color_matches, color_name, parse_color};
     use colored::Color::*;
     #[test]
     fn parses_colors() {
@@ -285,4 +353,23 @@ mod tests {
         assert!(parse_color("#1234567").is_err());
         assert!(parse_color("0x1234567").is_err());
     }
+
+    #[test]
+    fn matches_colors_by_name() {
+        let red = parse_color("#EF1D55").unwrap();
+        let cyan = parse_color("#4C96A8").unwrap();
+        assert_eq!(color_name(red), "red");
+        assert_eq!(color_name(cyan), "cyan");
+        assert_eq!(color_name(BrightBlue), "blue");
+        assert!(color_matches(red, "Red"));
+        assert!(color_matches(red, "#EF1D55"));
+        assert!(!color_matches(red, "green"));
+    }
+
+    #[test]
+    fn converts_colors_to_hex() {
+        assert_eq!(color_hex(parse_color("#EF1D55").unwrap()), "#ef1d55");
+        assert_eq!(color_hex(Red), "#cd0000");
+        assert_eq!(color_hex(BrightWhite), "#ffffff");
+    }
 }
@@ -0,0 +1,109 @@
+//! Events emitted when a registry is modified, so that the TUI and other
+//! integrations can react to a change as it happens instead of polling for it
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// A modification of a registry. Files are identified by the id of their entry
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeEvent {
+    /// A file was added
+    FileAdded(usize),
+    /// The path, hash, or modification time of a file changed
+    FileUpdated(usize),
+    /// A file was removed, along with its tags and values
+    FileRemoved(usize),
+    /// A tag was added to a file
+    Tagged { tag: String, file: usize },
+    /// A tag was removed from a file
+    Untagged { tag: String, file: usize },
+    /// The value of a tag on a file was set or replaced
+    ValueSet {
+        tag:   String,
+        file:  usize,
+        value: String,
+    },
+    /// The value of a tag on a file was removed
+    ValueRemoved { tag: String, file: usize },
+    /// A tag was renamed
+    TagRenamed { from: String, to: String },
+    /// The color of a tag changed
+    TagRecolored(String),
+    /// Every file and tag was removed
+    Cleared,
+}
+
+/// Callback that is called with each event. Each has a lock of its own, so
+/// that the list of callbacks is not locked while they run
+type Callback = Arc<Mutex<dyn FnMut(&ChangeEvent) + Send>>;
+
+/// The callbacks subscribed to the changes of a registry. Clones share the
+/// same callbacks
+#[derive(Clone, Default)]
+pub struct Observers(Arc<Mutex<Vec<Callback>>>);
+
+impl Observers {
+    /// Call `f` with every event that is emitted from now on
+    pub fn subscribe<F>(&self, f: F)
+    where
+        F: FnMut(&ChangeEvent) + Send + 'static,
+    {
+        if let Ok(mut callbacks) = self.0.lock() {
+            callbacks.push(Arc::new(Mutex::new(f)));
+        }
+    }
+
+    /// Call every subscribed callback with the `event`. The callbacks are
+    /// called after the list of them is unlocked, so a callback can subscribe
+    /// another one, and a slow callback does not keep another thread from
+    /// subscribing. A callback must not emit an event itself, since it is
+    /// locked while it runs
+    pub fn emit(&self, event: &ChangeEvent) {
+        let callbacks = match self.0.lock() {
+            Ok(callbacks) => callbacks.clone(),
+            Err(_) => return,
+        };
+
+        for callback in &callbacks {
+            if let Ok(mut callback) = callback.lock() {
+                (&mut *callback)(event);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = self.0.lock().map_or(0, |callbacks| callbacks.len());
+        write!(f, "Observers({})", count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscribes_from_a_callback() {
+        let observers = Observers::default();
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        let (inner, seen) = (observers.clone(), Arc::clone(&events));
+        observers.subscribe(move |event| {
+            if let ChangeEvent::FileAdded(id) = event {
+                let later = Arc::clone(&seen);
+                inner.subscribe(move |event| later.lock().unwrap().push(event.clone()));
+                seen.lock().unwrap().push(ChangeEvent::FileUpdated(*id));
+            }
+        });
+
+        observers.emit(&ChangeEvent::FileAdded(1));
+        observers.emit(&ChangeEvent::Cleared);
+        assert_eq!(*events.lock().unwrap(), vec![
+            ChangeEvent::FileUpdated(1),
+            ChangeEvent::Cleared
+        ]);
+    }
+}
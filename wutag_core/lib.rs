@@ -1,14 +1,264 @@
 pub mod color;
+pub mod event;
 pub mod tag;
 pub mod xattr;
 
+pub use tag::DirEntryExt;
+#[cfg(feature = "write-through")]
+pub use tag::TagStore;
+
 use colored::{ColoredString, Colorize};
-use std::{ffi, io, string};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, ffi, fmt, io, str::FromStr, string};
 use thiserror::Error;
 
-/// Prefix used to identify extra attributes added by wutag on files
+/// Prefix used to identify extra attributes added by wutag on files, when they
+/// are kept in the default `user` namespace
 pub const WUTAG_NAMESPACE: &str = "user.wutag";
 
+/// Part of the prefix that follows the namespace of extended attributes (e.g.,
+/// `trusted.wutag`)
+const WUTAG_KEY: &str = "wutag";
+
+/// Part of the key of an extended attribute that holds an encrypted tag, which
+/// is not valid base64 so it is never read as a tag
+pub const LOCKED_KEY: &str = "locked";
+
+/// Part of the key of the extended attribute that holds every tag of a file in
+/// the [single](XattrLayout::Single) layout (e.g., `user.wutag.tags`)
+pub const PAYLOAD_KEY: &str = "tags";
+
+/// Layout that tags are written in
+static XATTR_LAYOUT: OnceCell<XattrLayout> = OnceCell::new();
+
+/// How the tags of a file are laid out in its extended attributes. Tags are
+/// read from both layouts, so this only chooses the one they are written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum XattrLayout {
+    /// One attribute per tag whose key is the encoded tag (e.g.,
+    /// `user.wutag.<base64>`), which is the only layout older versions read
+    Legacy,
+    /// A single attribute (e.g., `user.wutag.tags`) whose value holds every
+    /// tag, so that files with many tags do not run out of attribute names
+    Single,
+    /// The legacy layout for files that already have a tag in it, and the
+    /// single layout otherwise
+    Auto,
+}
+
+impl Default for XattrLayout {
+    fn default() -> Self {
+        Self::Legacy
+    }
+}
+
+impl FromStr for XattrLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().trim() {
+            "legacy" => Ok(Self::Legacy),
+            "single" => Ok(Self::Single),
+            "auto" => Ok(Self::Auto),
+            v => Err(format!("unsupported layout: {}", v)),
+        }
+    }
+}
+
+impl fmt::Display for XattrLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Legacy => f.write_str("legacy"),
+            Self::Single => f.write_str("single"),
+            Self::Auto => f.write_str("auto"),
+        }
+    }
+}
+
+/// Write tags in the `layout` instead of the legacy one. Only the first call
+/// has an effect
+pub fn set_xattr_layout(layout: XattrLayout) {
+    if XATTR_LAYOUT.set(layout).is_err() {
+        log::debug!("xattr layout was already set");
+    }
+}
+
+/// Layout that tags are written in
+pub fn xattr_layout() -> XattrLayout {
+    XATTR_LAYOUT.get().copied().unwrap_or_default()
+}
+
+/// Namespace of the extended attributes that tags are kept in
+static XATTR_NAMESPACE: OnceCell<XattrNamespace> = OnceCell::new();
+
+/// Namespaces of extended attributes that tags can be kept in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum XattrNamespace {
+    /// Attributes that anyone who can write to a regular file can set
+    User,
+    /// Attributes that only root can read and set, so they can be set on files
+    /// that the user namespace does not allow (e.g., symlinks or devices)
+    Trusted,
+    /// Attributes that are read by security modules
+    Security,
+}
+
+impl Default for XattrNamespace {
+    fn default() -> Self {
+        Self::User
+    }
+}
+
+impl FromStr for XattrNamespace {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().trim() {
+            "user" => Ok(Self::User),
+            "trusted" => Ok(Self::Trusted),
+            "security" => Ok(Self::Security),
+            v => Err(format!("unsupported namespace: {}", v)),
+        }
+    }
+}
+
+impl fmt::Display for XattrNamespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::User => f.write_str("user"),
+            Self::Trusted => f.write_str("trusted"),
+            Self::Security => f.write_str("security"),
+        }
+    }
+}
+
+/// Keep tags in the extended attributes of the namespace `ns` instead of
+/// `user`. Only the first call has an effect
+pub fn set_xattr_namespace(ns: XattrNamespace) {
+    if XATTR_NAMESPACE.set(ns).is_err() {
+        log::debug!("xattr namespace was already set");
+    }
+}
+
+/// Namespace of the extended attributes that tags are kept in
+pub fn xattr_namespace() -> XattrNamespace {
+    XATTR_NAMESPACE.get().copied().unwrap_or_default()
+}
+
+/// Prefix of the extended attributes set by wutag in the current namespace,
+/// which are shared by every user
+fn shared_namespace() -> String {
+    format!("{}.{}", xattr_namespace(), WUTAG_KEY)
+}
+
+/// Id of the user whose own namespace (e.g., `user.wutag.<uid>`) tags are kept
+/// in, instead of the namespace shared by every user
+static USER_NAMESPACE: OnceCell<u32> = OnceCell::new();
+
+/// Keep tags in the namespace of the user with the id `uid`, so that users of
+/// a shared directory have their own tags. Only the first call has an effect
+pub fn set_user_namespace(uid: u32) {
+    if USER_NAMESPACE.set(uid).is_err() {
+        log::debug!("user namespace was already set");
+    }
+}
+
+/// Id of the user whose namespace tags are read from and written to, or `None`
+/// for the namespace shared by every user
+pub fn namespace_owner() -> Option<u32> {
+    USER_NAMESPACE.get().copied()
+}
+
+/// Id of the user running wutag
+#[cfg(unix)]
+pub fn current_uid() -> u32 {
+    // SAFETY: `getuid` always succeeds and has no side effects
+    unsafe { libc::getuid() }
+}
+
+/// Whether the process with the id `pid` is still running
+#[cfg(unix)]
+pub fn process_running(pid: u32) -> bool {
+    // 0 and negative ids would check a group of processes instead of one
+    let pid = match libc::pid_t::try_from(pid) {
+        Ok(pid) if pid > 0 => pid,
+        _ => return false,
+    };
+    // SAFETY: the signal 0 is never sent, it only checks that the process
+    // exists and can be signaled
+    let res = unsafe { libc::kill(pid, 0) };
+    res == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Prefix of the extended attributes that tags are read from and written to
+pub fn namespace() -> String {
+    match USER_NAMESPACE.get() {
+        Some(uid) => format!("{}.{}", shared_namespace(), uid),
+        None => shared_namespace(),
+    }
+}
+
+/// Split the key of an extended attribute set by wutag into the id of the user
+/// whose namespace it is in (`None` for the shared namespace) and the encoded
+/// tag. Returns `None` if wutag did not set it in the current namespace of
+/// extended attributes, or if it is the [payload](payload_key) of a file
+pub fn split_key(key: &str) -> Option<(Option<u32>, &str)> {
+    let rest = key
+        .strip_prefix(shared_namespace().as_str())?
+        .strip_prefix('.')?;
+    // The encoded tag is base64, which never contains a '.'
+    match rest.split_once('.') {
+        Some((uid, tag)) => uid.parse().ok().map(|uid| (Some(uid), tag)),
+        None => Some((None, rest)),
+    }
+    .filter(|(_, tag)| *tag != PAYLOAD_KEY)
+}
+
+/// Key of the extended attribute that holds every tag of a file in the current
+/// namespace when they are in the single layout
+pub fn payload_key() -> String {
+    format!("{}.{}", namespace(), PAYLOAD_KEY)
+}
+
+/// The id of the user whose namespace the payload with the given `key` is in
+/// (`None` for the shared namespace). Returns `None` if it is not a payload
+pub fn payload_owner(key: &str) -> Option<Option<u32>> {
+    let rest = key
+        .strip_prefix(shared_namespace().as_str())?
+        .strip_prefix('.')?
+        .strip_suffix(PAYLOAD_KEY)?;
+    match rest {
+        "" => Some(None),
+        uid => uid.strip_suffix('.')?.parse().ok().map(Some),
+    }
+}
+
+/// Whether the extended attribute with the given `key` is a tag in the current
+/// namespace
+pub fn in_namespace(key: &str) -> bool {
+    split_key(key).map_or(false, |(uid, _)| uid == namespace_owner())
+}
+
+/// Key of the extended attribute that holds the encrypted tag with the given
+/// `id` in the current namespace. Its value is the payload, since the key
+/// itself would reveal the name of the tag
+pub fn locked_key(id: &str) -> String {
+    format!("{}.{}.{}", namespace(), LOCKED_KEY, id)
+}
+
+/// The id of the encrypted tag held by the extended attribute with the given
+/// `key`, if it holds one in the current namespace
+pub fn locked_id(key: &str) -> Option<&str> {
+    key.strip_prefix(namespace().as_str())?
+        .strip_prefix('.')?
+        .strip_prefix(LOCKED_KEY)?
+        .strip_prefix('.')
+        .filter(|id| !id.is_empty())
+}
+
 #[derive(Debug, Error)]
 /// Default error used throughout this crate
 pub enum Error {
@@ -26,6 +276,14 @@ pub enum Error {
     Utf8ConversionFailed(#[from] string::FromUtf8Error),
     #[error("xattrs changed while getting their size")]
     AttrsChanged,
+    #[error("the filesystem does not support extended attributes")]
+    XattrsUnsupported,
+    #[error("extended attributes are not permitted on this file (e.g., a symlink or device)")]
+    XattrsNotPermitted,
+    #[error("the tag is larger than the filesystem allows an extended attribute to be")]
+    XattrTooLarge,
+    #[error("the filesystem did not keep the tag")]
+    XattrDropped,
     #[error("provided color `{0}` is not a valid hex color")]
     InvalidColor(String),
     #[error("failed to serialize or deserialize tag - `{0}`")]
@@ -44,6 +302,13 @@ impl From<io::Error> for Error {
             io::ErrorKind::AlreadyExists => Error::TagExists(err.to_string().green().bold()),
             _ => match err.raw_os_error() {
                 Some(61) => Error::TagNotFound("".to_string()),
+                #[cfg(unix)]
+                Some(code) if code == libc::EOPNOTSUPP || code == libc::ENOTSUP =>
+                    Error::XattrsUnsupported,
+                #[cfg(unix)]
+                Some(libc::EPERM) => Error::XattrsNotPermitted,
+                #[cfg(unix)]
+                Some(libc::E2BIG) => Error::XattrTooLarge,
                 _ => Error::Other(err.to_string()),
             },
         }
@@ -13,7 +13,8 @@ use std::{
 
 use crate::{
     xattr::{list_xattrs, remove_xattr, set_xattr, Xattr},
-    Error, Result, WUTAG_NAMESPACE,
+    in_namespace, locked_id, namespace, namespace_owner, payload_key, payload_owner, split_key,
+    xattr_layout, Error, Result, XattrLayout,
 };
 
 pub const DEFAULT_COLOR: Color = Color::BrightWhite;
@@ -31,6 +32,19 @@ impl Hash for Tag {
     }
 }
 
+/// A record of which files have which tags, such as wutag's registry, that is
+/// kept up to date by the `*_with` methods of [`DirEntryExt`]
+#[cfg(feature = "write-through")]
+pub trait TagStore {
+    /// The `tag` was added to the file at `path`
+    fn tagged(&mut self, path: &Path, tag: &Tag) -> Result<()>;
+    /// The `tag` was removed from the file at `path`
+    fn untagged(&mut self, path: &Path, tag: &Tag) -> Result<()>;
+    /// Every tag was removed from the file at `path`
+    fn cleared(&mut self, path: &Path) -> Result<()>;
+}
+
+/// Tag operations on a file, e.g., `path.tag(&tag)?`
 pub trait DirEntryExt {
     fn tag(&self, tag: &Tag) -> Result<()>;
     fn untag(&self, tag: &Tag) -> Result<()>;
@@ -39,6 +53,63 @@ pub trait DirEntryExt {
     fn list_tags_btree(&self) -> Result<BTreeSet<Tag>>;
     fn clear_tags(&self) -> Result<()>;
     fn has_tags(&self) -> Result<bool>;
+    /// Path of the file that is operated on
+    fn entry_path(&self) -> &Path;
+
+    /// Tag the file and record it in the `store`
+    #[cfg(feature = "write-through")]
+    fn tag_with<S: TagStore>(&self, tag: &Tag, store: &mut S) -> Result<()> {
+        self.tag(tag)?;
+        store.tagged(self.entry_path(), tag)
+    }
+
+    /// Untag the file and record it in the `store`
+    #[cfg(feature = "write-through")]
+    fn untag_with<S: TagStore>(&self, tag: &Tag, store: &mut S) -> Result<()> {
+        self.untag(tag)?;
+        store.untagged(self.entry_path(), tag)
+    }
+
+    /// Clear the file's tags and record it in the `store`
+    #[cfg(feature = "write-through")]
+    fn clear_tags_with<S: TagStore>(&self, store: &mut S) -> Result<()> {
+        self.clear_tags()?;
+        store.cleared(self.entry_path())
+    }
+}
+
+impl DirEntryExt for Path {
+    fn tag(&self, tag: &Tag) -> Result<()> {
+        tag.save_to(self)
+    }
+
+    fn untag(&self, tag: &Tag) -> Result<()> {
+        tag.remove_from(self)
+    }
+
+    fn get_tag<T: AsRef<str>>(&self, tag: T) -> Result<Tag> {
+        get_tag(self, tag)
+    }
+
+    fn list_tags(&self) -> Result<Vec<Tag>> {
+        list_tags(self)
+    }
+
+    fn list_tags_btree(&self) -> Result<BTreeSet<Tag>> {
+        list_tags_btree(self)
+    }
+
+    fn clear_tags(&self) -> Result<()> {
+        clear_tags(self)
+    }
+
+    fn has_tags(&self) -> Result<bool> {
+        has_tags(self)
+    }
+
+    fn entry_path(&self) -> &Path {
+        self
+    }
 }
 
 impl DirEntryExt for &std::path::PathBuf {
@@ -69,6 +140,10 @@ impl DirEntryExt for &std::path::PathBuf {
     fn has_tags(&self) -> Result<bool> {
         has_tags(self)
     }
+
+    fn entry_path(&self) -> &Path {
+        self
+    }
 }
 
 impl DirEntryExt for ignore::DirEntry {
@@ -99,6 +174,10 @@ impl DirEntryExt for ignore::DirEntry {
     fn has_tags(&self) -> Result<bool> {
         has_tags(self.path())
     }
+
+    fn entry_path(&self) -> &Path {
+        self.path()
+    }
 }
 
 impl Tag {
@@ -147,41 +226,112 @@ impl Tag {
 
     fn hash(&self) -> Result<String> {
         serde_cbor::to_vec(&self)
-            .map(|tag| format!("{}.{}", WUTAG_NAMESPACE, base64::encode(tag)))
+            .map(|tag| format!("{}.{}", namespace(), base64::encode(tag)))
             .map_err(Error::from)
     }
 
+    /// Decodes the tag held by the key of an extended attribute in the legacy
+    /// layout
+    fn from_key(key: &str) -> Result<Self> {
+        let (_, tag_bytes) = split_key(key).ok_or_else(|| {
+            Error::InvalidTagKey(format!(
+                "invalid namespace `{}`, valid namespace is `{}`",
+                key,
+                namespace()
+            ))
+        })?;
+        if tag_bytes.is_empty() {
+            return Err(Error::InvalidTagKey("missing tag".to_string()));
+        }
+        let tag = serde_cbor::from_slice(&base64::decode(tag_bytes.as_bytes())?)?;
+
+        Ok(tag)
+    }
+
     /// Tags the file at the given `path` with this tag. If the tag exists
     /// returns an error.
+    ///
+    /// In the single layout the payload is read and written again, so unlike
+    /// the legacy layout a tag that another process writes at the same time
+    /// can be lost
     pub fn save_to<P>(&self, path: P) -> Result<()>
     where
         P: AsRef<Path>,
     {
-        for tag in list_tags(path.as_ref())? {
-            if &tag == self {
-                return Err(Error::TagExists(tag.name.green().bold()));
-            }
+        let path = path.as_ref();
+        let attrs = list_xattrs(path)?;
+        if let Some(tag) = own_tags(&attrs).into_iter().find(|tag| tag == self) {
+            return Err(Error::TagExists(tag.name.green().bold()));
+        }
+
+        match write_layout(&attrs) {
+            XattrLayout::Single => {
+                let mut payload = read_payload(&attrs)?;
+                payload.push(self.clone());
+                write_payload(path, &payload)
+            },
+            _ => {
+                let hash = self.hash()?;
+                set_xattr(path, hash.as_str(), "")?;
+                self.resolve_duplicate(path, &hash)
+            },
+        }
+    }
+
+    /// Each tag is a separate extended attribute, so writing one cannot be
+    /// interleaved with another process writing a different tag. Another
+    /// process can however add a tag with the same name and a different color
+    /// between the check in [`Tag::save_to`] and the write of the attribute
+    /// `hash`. Only the copy with the lowest key is kept, so that every process
+    /// agrees on which one that is, and the others return
+    /// [Error::TagExists](wutag::Error::TagExists)
+    fn resolve_duplicate(&self, path: &Path, hash: &str) -> Result<()> {
+        let kept = list_xattrs(path)?
+            .into_iter()
+            .filter(|xattr| in_namespace(xattr.key()) && xattr.key() < hash)
+            .filter_map(|xattr| Tag::try_from(xattr).ok())
+            .find(|tag| tag == self);
+
+        match kept {
+            Some(tag) => {
+                remove_xattr(path, hash)?;
+                Err(Error::TagExists(tag.name.green().bold()))
+            },
+            None => Ok(()),
         }
-        set_xattr(path, self.hash()?.as_str(), "")
     }
 
-    /// Removes this tag from the file at the given `path`. If the tag doesn't
-    /// exists returns [Error::TagNotFound](wutag::Error::TagNotFound)
+    /// Removes this tag from the file at the given `path`, in whichever layout
+    /// it is in. If the tag doesn't exists returns
+    /// [Error::TagNotFound](wutag::Error::TagNotFound)
     pub fn remove_from<P>(&self, path: P) -> Result<()>
     where
         P: AsRef<Path>,
     {
+        let path = path.as_ref();
+        let attrs = list_xattrs(path)?;
         let hash = self.hash()?;
+        let mut removed = false;
 
-        for xattr in list_xattrs(path.as_ref())? {
-            let key = xattr.key();
-            // make sure to only remove attributes corresponding to this namespace
-            if key == hash {
-                return remove_xattr(path, key);
-            }
+        // make sure to only remove attributes corresponding to this namespace
+        if attrs.iter().any(|xattr| xattr.key() == hash) {
+            remove_xattr(path, hash.as_str())?;
+            removed = true;
+        }
+
+        let mut payload = read_payload(&attrs)?;
+        let len = payload.len();
+        payload.retain(|tag| tag != self);
+        if payload.len() != len {
+            write_payload(path, &payload)?;
+            removed = true;
         }
 
-        Err(Error::TagNotFound(self.name.clone()))
+        if removed {
+            Ok(())
+        } else {
+            Err(Error::TagNotFound(self.name.clone()))
+        }
     }
 }
 
@@ -209,33 +359,91 @@ impl PartialOrd for Tag {
     }
 }
 
-macro_rules! next_or_else {
-    ($it:ident, $msg:expr) => {
-        $it.next()
-            .ok_or_else(|| Error::InvalidTagKey($msg.to_string()))
-    };
-}
-
 impl TryFrom<Xattr> for Tag {
     type Error = Error;
 
     fn try_from(xattr: Xattr) -> Result<Self> {
-        let key = xattr.key();
+        Self::from_key(xattr.key())
+    }
+}
+
+/// Decodes the tags held by the `value` of the payload of the single layout
+fn decode_payload(value: &str) -> Result<Vec<Tag>> {
+    Ok(serde_cbor::from_slice(&base64::decode(value.as_bytes())?)?)
+}
 
-        let mut elems = key.split("wutag.");
+/// The tags held by the extended attributes `attrs` in either layout, along
+/// with the id of the user whose namespace each is in (`None` for the shared
+/// namespace). A tag that is in both layouts is only listed once
+fn decode_tags(attrs: &[Xattr]) -> Vec<(Option<u32>, Tag)> {
+    let mut tags: Vec<(Option<u32>, Tag)> = Vec::new();
+    for xattr in attrs {
+        let decoded = if let Some(owner) = payload_owner(xattr.key()) {
+            match decode_payload(xattr.val()) {
+                Ok(payload) => payload.into_iter().map(|tag| (owner, tag)).collect(),
+                Err(e) => {
+                    log::debug!("invalid payload `{}`: {}", xattr.key(), e);
+                    continue;
+                },
+            }
+        } else if let Some((owner, _)) = split_key(xattr.key()) {
+            match Tag::from_key(xattr.key()) {
+                Ok(tag) => vec![(owner, tag)],
+                Err(_) => continue,
+            }
+        } else {
+            continue;
+        };
 
-        let ns = next_or_else!(elems, "missing namespace `user`")?;
-        if ns != "user." {
-            return Err(Error::InvalidTagKey(format!(
-                "invalid namespace `{}`, valid namespace is `user`",
-                ns
-            )));
+        for (owner, tag) in decoded {
+            if !tags.iter().any(|(o, t)| *o == owner && *t == tag) {
+                tags.push((owner, tag));
+            }
         }
+    }
 
-        let tag_bytes = next_or_else!(elems, "missing tag")?;
-        let tag = serde_cbor::from_slice(&base64::decode(tag_bytes.as_bytes())?)?;
+    tags
+}
 
-        Ok(tag)
+/// The tags held by the extended attributes `attrs` in the current namespace
+fn own_tags(attrs: &[Xattr]) -> Vec<Tag> {
+    let owner = namespace_owner();
+    decode_tags(attrs)
+        .into_iter()
+        .filter(|(o, _)| *o == owner)
+        .map(|(_, tag)| tag)
+        .collect()
+}
+
+/// The tags held by the payload of the current namespace among `attrs`
+fn read_payload(attrs: &[Xattr]) -> Result<Vec<Tag>> {
+    let key = payload_key();
+    attrs
+        .iter()
+        .find(|xattr| xattr.key() == key)
+        .map_or_else(|| Ok(Vec::new()), |xattr| decode_payload(xattr.val()))
+}
+
+/// Writes the `tags` as the payload of the file at `path`, which is removed
+/// when there are none
+fn write_payload(path: &Path, tags: &[Tag]) -> Result<()> {
+    let key = payload_key();
+    if tags.is_empty() {
+        remove_xattr(path, key.as_str())
+    } else {
+        let value = base64::encode(serde_cbor::to_vec(tags)?);
+        set_xattr(path, key.as_str(), value.as_str())
+    }
+}
+
+/// The layout that a tag is written in on a file with the extended attributes
+/// `attrs`
+fn write_layout(attrs: &[Xattr]) -> XattrLayout {
+    match xattr_layout() {
+        XattrLayout::Auto if attrs.iter().any(|xattr| in_namespace(xattr.key())) =>
+            XattrLayout::Legacy,
+        XattrLayout::Auto => XattrLayout::Single,
+        layout => layout,
     }
 }
 
@@ -244,15 +452,11 @@ where
     P: AsRef<Path>,
     T: AsRef<str>,
 {
-    let path = path.as_ref();
     let tag = tag.as_ref();
-    for _tag in list_xattrs(path)?.into_iter().map(Tag::try_from).flatten() {
-        if _tag.name == tag {
-            return Ok(_tag);
-        }
-    }
-
-    Err(Error::TagNotFound(tag.to_string()))
+    list_tags(path)?
+        .into_iter()
+        .find(|t| t.name == tag)
+        .ok_or_else(|| Error::TagNotFound(tag.to_string()))
 }
 
 /// Lists tags of the file at the given `path`.
@@ -260,54 +464,102 @@ pub fn list_tags<P>(path: P) -> Result<Vec<Tag>>
 where
     P: AsRef<Path>,
 {
-    list_xattrs(path).map(|attrs| {
-        let mut tags = Vec::new();
-        let it = attrs
-            .into_iter()
-            .filter(|xattr| xattr.key().starts_with(WUTAG_NAMESPACE))
-            .map(Tag::try_from);
+    list_xattrs(path).map(|attrs| own_tags(&attrs))
+}
 
-        for tag in it.flatten() {
-            tags.push(tag);
-        }
-        tags
-    })
+/// Lists the tags of the file at the given `path` in every namespace, along
+/// with the id of the user whose namespace each is in (`None` for the shared
+/// namespace)
+pub fn list_tags_by_owner<P>(path: P) -> Result<Vec<(Option<u32>, Tag)>>
+where
+    P: AsRef<Path>,
+{
+    list_xattrs(path).map(|attrs| decode_tags(&attrs))
 }
 
 /// Lists tags of the file at the given `path` as a [BTreeSet](BTreeSet).
 pub fn list_tags_btree<P>(path: P) -> Result<BTreeSet<Tag>>
+where
+    P: AsRef<Path>,
+{
+    list_xattrs(path).map(|attrs| own_tags(&attrs).into_iter().collect())
+}
+
+/// Lists the extended attributes of the file at the given `path` that hold
+/// encrypted tags, which are decrypted by the caller
+pub fn list_locked<P>(path: P) -> Result<Vec<Xattr>>
 where
     P: AsRef<Path>,
 {
     list_xattrs(path).map(|attrs| {
-        let mut tags = BTreeSet::new();
-        let it = attrs
+        attrs
             .into_iter()
-            .filter(|xattr| xattr.key().starts_with(WUTAG_NAMESPACE))
-            .map(Tag::try_from);
-
-        for tag in it.flatten() {
-            tags.insert(tag);
-        }
-        tags
+            .filter(|xattr| locked_id(xattr.key()).is_some())
+            .collect()
     })
 }
 
-/// Clears all tags of the file at the given `path`.
+/// Clears all tags of the file at the given `path`, including the encrypted
+/// ones.
 pub fn clear_tags<P>(path: P) -> Result<()>
 where
     P: AsRef<Path>,
 {
-    for xattr in list_xattrs(path.as_ref())?
-        .iter()
-        .filter(|xattr| xattr.key().starts_with(WUTAG_NAMESPACE))
-    {
+    let owner = namespace_owner();
+    for xattr in list_xattrs(path.as_ref())?.iter().filter(|xattr| {
+        in_namespace(xattr.key())
+            || payload_owner(xattr.key()) == Some(owner)
+            || locked_id(xattr.key()).is_some()
+    }) {
         remove_xattr(path.as_ref(), xattr.key())?;
     }
 
     Ok(())
 }
 
+/// Rewrites the tags of the file at the given `path` in the `layout`, where
+/// [`XattrLayout::Auto`] is the single layout. The tags are written in the new
+/// layout before they are removed from the old one, so that none are lost if
+/// it fails. Returns whether the file had tags in the old layout
+pub fn convert_layout<P>(path: P, layout: XattrLayout) -> Result<bool>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let attrs = list_xattrs(path)?;
+    let tags = own_tags(&attrs);
+    let payload_key = payload_key();
+    let has_payload = attrs.iter().any(|xattr| xattr.key() == payload_key);
+    let legacy = attrs
+        .iter()
+        .map(Xattr::key)
+        .filter(|key| in_namespace(key))
+        .collect::<Vec<_>>();
+
+    if layout == XattrLayout::Legacy {
+        if !has_payload {
+            return Ok(false);
+        }
+        for tag in &tags {
+            let hash = tag.hash()?;
+            if !legacy.contains(&hash.as_str()) {
+                set_xattr(path, hash.as_str(), "")?;
+            }
+        }
+        remove_xattr(path, payload_key.as_str())?;
+    } else {
+        if legacy.is_empty() {
+            return Ok(false);
+        }
+        write_payload(path, &tags)?;
+        for key in legacy {
+            remove_xattr(path, key)?;
+        }
+    }
+
+    Ok(true)
+}
+
 /// Checks whether the given path has any tags.
 ///
 /// Returns an Error if the list of tags couldn't be aquired.
@@ -317,3 +569,209 @@ where
 {
     list_tags(path).map(|tags| !tags.is_empty())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{locked_key, XattrNamespace};
+    use proptest::prelude::*;
+    use std::{collections::BTreeMap, fs, path::PathBuf};
+
+    /// Linux limits the name of an extended attribute to this many bytes
+    const XATTR_NAME_MAX: usize = 255;
+
+    fn color() -> impl Strategy<Value = Color> {
+        prop_oneof![
+            Just(Color::Black),
+            Just(Color::Red),
+            Just(Color::Green),
+            Just(Color::Yellow),
+            Just(Color::Blue),
+            Just(Color::Magenta),
+            Just(Color::Cyan),
+            Just(Color::White),
+            Just(Color::BrightBlack),
+            Just(Color::BrightRed),
+            Just(Color::BrightGreen),
+            Just(Color::BrightYellow),
+            Just(Color::BrightBlue),
+            Just(Color::BrightMagenta),
+            Just(Color::BrightCyan),
+            Just(Color::BrightWhite),
+            any::<(u8, u8, u8)>().prop_map(|(r, g, b)| Color::TrueColor { r, g, b }),
+        ]
+    }
+
+    /// Unicode names, names with the separators used on the command-line, and
+    /// names too long to fit in the name of an extended attribute
+    fn name() -> impl Strategy<Value = String> {
+        prop_oneof![
+            "\\PC{1,32}",
+            "[a-z]{1,8}[.=,:/ ][a-z]{1,8}",
+            "[a-z]{200,400}",
+        ]
+    }
+
+    /// Creates an empty file in a temporary directory. Returns `None` if the
+    /// filesystem does not support extended attributes
+    fn tmp_file() -> Option<(tempfile::TempDir, PathBuf)> {
+        let dir = tempfile::tempdir().ok()?;
+        let path = dir.path().join("file");
+        fs::write(&path, "").ok()?;
+
+        let probe = format!("{}.probe", namespace());
+        set_xattr(&path, probe.as_str(), "").ok()?;
+        remove_xattr(&path, probe.as_str()).ok()?;
+
+        Some((dir, path))
+    }
+
+    #[test]
+    fn splits_namespaced_keys() {
+        assert_eq!(split_key("user.wutag.dGFn"), Some((None, "dGFn")));
+        assert_eq!(split_key("user.wutag.1000.dGFn"), Some((Some(1000), "dGFn")));
+        assert_eq!(split_key("user.wutag.bob.dGFn"), None);
+        assert_eq!(split_key("user.other.dGFn"), None);
+        assert_eq!(split_key("user.wutagdGFn"), None);
+        assert_eq!(split_key("trusted.wutag.dGFn"), None);
+
+        assert!(in_namespace("user.wutag.dGFn"));
+        assert!(!in_namespace("user.wutag.1000.dGFn"));
+
+        // Encrypted tags are never read as tags
+        assert_eq!(locked_key("0a1b"), "user.wutag.locked.0a1b");
+        assert_eq!(locked_id("user.wutag.locked.0a1b"), Some("0a1b"));
+        assert_eq!(locked_id("user.wutag.locked."), None);
+        assert_eq!(locked_id("user.wutag.dGFn"), None);
+        assert!(!in_namespace("user.wutag.locked.0a1b"));
+
+        // The payload of the single layout is not a tag of the legacy layout
+        assert_eq!(split_key("user.wutag.tags"), None);
+        assert_eq!(split_key("user.wutag.1000.tags"), None);
+        assert_eq!(payload_owner("user.wutag.tags"), Some(None));
+        assert_eq!(payload_owner("user.wutag.1000.tags"), Some(Some(1000)));
+        assert_eq!(payload_owner("user.wutag.bob.tags"), None);
+        assert_eq!(payload_owner("user.wutag.dGFn"), None);
+    }
+
+    #[test]
+    fn reads_and_converts_both_layouts() {
+        let (_dir, path) = match tmp_file() {
+            Some(file) => file,
+            None => return,
+        };
+
+        let keys = || {
+            list_xattrs(&path)
+                .unwrap()
+                .iter()
+                .map(|xattr| xattr.key().to_owned())
+                .collect::<Vec<_>>()
+        };
+        let (old, new) = (Tag::new("old", Color::Red), Tag::new("new", Color::Blue));
+        set_xattr(&path, old.hash().unwrap().as_str(), "").unwrap();
+        write_payload(&path, &[new.clone(), old.clone()]).unwrap();
+        let both = vec![new.clone(), old.clone()].into_iter().collect();
+        assert_eq!(list_tags(&path).unwrap().len(), 2);
+        assert_eq!(list_tags_btree(&path).unwrap(), both);
+
+        assert!(convert_layout(&path, XattrLayout::Single).unwrap());
+        assert!(!convert_layout(&path, XattrLayout::Single).unwrap());
+        assert!(keys().contains(&payload_key()));
+        assert!(!keys().iter().any(|key| in_namespace(key)));
+        assert_eq!(list_tags_btree(&path).unwrap(), both);
+
+        assert!(convert_layout(&path, XattrLayout::Legacy).unwrap());
+        assert!(!keys().contains(&payload_key()));
+        assert_eq!(list_tags_btree(&path).unwrap(), both);
+
+        write_payload(&path, &[Tag::new("payload", Color::Green)]).unwrap();
+        assert!(Tag::new("payload", Color::Green).remove_from(&path).is_ok());
+        assert!(new.remove_from(&path).is_ok());
+        assert_eq!(list_tags(&path).unwrap(), vec![old]);
+        assert!(!keys().contains(&payload_key()));
+    }
+
+    #[test]
+    fn parses_xattr_namespaces() {
+        for ns in &[
+            XattrNamespace::User,
+            XattrNamespace::Trusted,
+            XattrNamespace::Security,
+        ] {
+            assert_eq!(ns.to_string().parse::<XattrNamespace>(), Ok(*ns));
+        }
+        assert_eq!("Trusted".parse(), Ok(XattrNamespace::Trusted));
+        assert!("system".parse::<XattrNamespace>().is_err());
+        assert_eq!(XattrNamespace::default(), XattrNamespace::User);
+    }
+
+    #[test]
+    fn keeps_one_of_concurrent_tags() {
+        let (_dir, path) = match tmp_file() {
+            Some(file) => file,
+            None => return,
+        };
+
+        // Both processes saw that the file did not have the tag and wrote it
+        let mut tags = vec![Tag::new("tag", Color::Red), Tag::new("tag", Color::Blue)];
+        tags.sort_by_key(|tag| tag.hash().unwrap());
+        for tag in &tags {
+            set_xattr(&path, tag.hash().unwrap().as_str(), "").unwrap();
+        }
+
+        let (first, second) = (&tags[0], &tags[1]);
+        let (first_hash, second_hash) = (first.hash().unwrap(), second.hash().unwrap());
+        assert!(second.resolve_duplicate(&path, &second_hash).is_err());
+        assert!(first.resolve_duplicate(&path, &first_hash).is_ok());
+
+        let listed = list_tags(&path).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].color(), first.color());
+    }
+
+    proptest! {
+        #[test]
+        fn round_trips_tags(tags in prop::collection::btree_map(name(), color(), 1..8)) {
+            let (_dir, path) = match tmp_file() {
+                Some(file) => file,
+                None => return Ok(()),
+            };
+
+            let mut saved = BTreeMap::new();
+            for (name, color) in tags {
+                let tag = Tag::new(name, color);
+                match tag.save_to(&path) {
+                    Ok(()) => {
+                        prop_assert!(tag.save_to(&path).is_err());
+                        saved.insert(tag.name().to_owned(), tag);
+                    },
+                    Err(_) => {
+                        prop_assert!(tag.hash().unwrap().len() > XATTR_NAME_MAX);
+                        prop_assert!(get_tag(&path, tag.name()).is_err());
+                    },
+                }
+            }
+
+            let listed = list_tags_btree(&path).unwrap();
+            prop_assert_eq!(listed.len(), saved.len());
+            for tag in &listed {
+                let original = &saved[tag.name()];
+                prop_assert_eq!(tag.color(), original.color());
+                prop_assert_eq!(
+                    serde_cbor::to_vec(tag).unwrap(),
+                    serde_cbor::to_vec(original).unwrap()
+                );
+
+                let got = get_tag(&path, tag.name()).unwrap();
+                prop_assert_eq!(got.color(), original.color());
+            }
+
+            for tag in saved.values() {
+                prop_assert!(tag.remove_from(&path).is_ok());
+                prop_assert!(tag.remove_from(&path).is_err());
+            }
+            prop_assert!(!has_tags(&path).unwrap());
+        }
+    }
+}
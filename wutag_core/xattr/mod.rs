@@ -1,4 +1,5 @@
 //! Safe and os-agnostic(TODO) wrappers for manipulating extra attributes
+pub mod ssh;
 #[cfg(unix)]
 mod unix;
 #[cfg(windows)]
@@ -16,7 +17,44 @@ pub use windows::{
 };
 
 use crate::Result;
-use std::path::Path;
+use once_cell::sync::OnceCell;
+use std::{
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Machine that extended attributes are read and written on instead of this
+/// one (e.g., `user@host`)
+static REMOTE_HOST: OnceCell<String> = OnceCell::new();
+
+/// Read and write extended attributes on `host` over `ssh` instead of on this
+/// machine. Only the first call has an effect
+pub fn set_remote_host<S: Into<String>>(host: S) {
+    if REMOTE_HOST.set(host.into()).is_err() {
+        log::debug!("remote host was already set");
+    }
+}
+
+/// The machine that extended attributes are read and written on, if it is not
+/// this one
+pub fn remote_host() -> Option<&'static str> {
+    REMOTE_HOST.get().map(String::as_str)
+}
+
+/// Whether the extended attributes of a symlink are those of its target
+static FOLLOW_SYMLINKS: AtomicBool = AtomicBool::new(false);
+
+/// Read and write the extended attributes of a symlink on its target when
+/// `follow` is set, or on the symlink itself otherwise (the default is not to
+/// follow)
+pub fn set_follow_symlinks(follow: bool) {
+    FOLLOW_SYMLINKS.store(follow, Ordering::Relaxed);
+}
+
+/// Whether the extended attributes of a symlink are those of its target
+pub fn follow_symlinks() -> bool {
+    FOLLOW_SYMLINKS.load(Ordering::Relaxed)
+}
 
 pub struct Xattr {
     key: String,
@@ -55,7 +93,10 @@ where
     P: AsRef<Path>,
     S: AsRef<str>,
 {
-    _set_xattr(path, name, value)
+    match remote_host() {
+        Some(host) => ssh::set_xattr(host, path.as_ref(), name.as_ref(), value.as_ref()),
+        None => _set_xattr(path, name, value),
+    }
 }
 
 pub fn get_xattr<P, S>(path: P, name: S) -> Result<String>
@@ -63,14 +104,21 @@ where
     P: AsRef<Path>,
     S: AsRef<str>,
 {
-    _get_xattr(path, name)
+    match remote_host() {
+        Some(host) => ssh::get_xattr(host, path.as_ref(), name.as_ref()),
+        None => _get_xattr(path, name),
+    }
 }
 
 pub fn list_xattrs<P>(path: P) -> Result<Vec<Xattr>>
 where
     P: AsRef<Path>,
 {
-    _list_xattrs(path).map(|attrs| attrs.into_iter().map(From::from).collect())
+    match remote_host() {
+        Some(host) => ssh::list_xattrs(host, path.as_ref()),
+        None => _list_xattrs(path),
+    }
+    .map(|attrs| attrs.into_iter().map(From::from).collect())
 }
 
 pub fn remove_xattr<P, S>(path: P, name: S) -> Result<()>
@@ -78,5 +126,8 @@ where
     P: AsRef<Path>,
     S: AsRef<str>,
 {
-    _remove_xattr(path, name)
+    match remote_host() {
+        Some(host) => ssh::remove_xattr(host, path.as_ref(), name.as_ref()),
+        None => _remove_xattr(path, name),
+    }
 }
@@ -0,0 +1,114 @@
+//! Extended attributes of files on another machine, which are read and written
+//! by running `getfattr` and `setfattr` on it over `ssh`. The `attr` package
+//! has to be installed on the other machine
+use std::{path::Path, process::Command};
+
+use crate::{Error, Result};
+
+/// Quote an argument for a POSIX shell, such as the one on the other machine
+pub fn quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// Run a command on `host`, returning what it printed. A host that starts
+/// with a dash is refused, since `ssh` would take it as one of its options
+pub fn run(host: &str, args: &[&str]) -> Result<String> {
+    if host.is_empty() || host.starts_with('-') {
+        return Err(Error::Other(format!("invalid host: {}", host)));
+    }
+
+    let remote = args.iter().map(|a| quote(a)).collect::<Vec<_>>().join(" ");
+    let output = Command::new("ssh")
+        .args(&["-o", "BatchMode=yes", "--", host])
+        .arg(remote)
+        .output()
+        .map_err(|e| Error::Other(format!("failed to run ssh: {}", e)))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.success() {
+        Ok(String::from_utf8(output.stdout)?)
+    } else if stderr.contains("No such attribute") {
+        Err(Error::TagNotFound(String::new()))
+    } else {
+        Err(Error::Other(format!("{}: {}", host, stderr.trim())))
+    }
+}
+
+/// The path as a string, since it is sent to the other machine
+fn path_str(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| Error::Other(format!("path is not valid UTF-8: {}", path.display())))
+}
+
+pub fn set_xattr(host: &str, path: &Path, name: &str, value: &str) -> Result<()> {
+    let encoded = format!("0s{}", base64::encode(value));
+    let mut args = vec!["setfattr", "-h", "-n", name];
+    if !value.is_empty() {
+        args.extend(&["-v", encoded.as_str()]);
+    }
+    args.extend(&["--", path_str(path)?]);
+
+    run(host, &args).map(|_| ())
+}
+
+pub fn get_xattr(host: &str, path: &Path, name: &str) -> Result<String> {
+    run(host, &[
+        "getfattr",
+        "-h",
+        "--absolute-names",
+        "--only-values",
+        "-n",
+        name,
+        "--",
+        path_str(path)?,
+    ])
+}
+
+pub fn list_xattrs(host: &str, path: &Path) -> Result<Vec<(String, String)>> {
+    let output = run(host, &[
+        "getfattr",
+        "-h",
+        "--absolute-names",
+        "-d",
+        "-m",
+        "-",
+        "-e",
+        "base64",
+        "--",
+        path_str(path)?,
+    ])?;
+
+    let mut attrs = Vec::new();
+    for line in output.lines().filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        match line.split_once('=') {
+            Some((key, value)) => {
+                let value = base64::decode(value.trim_matches('"').trim_start_matches("0s"))?;
+                attrs.push((key.to_owned(), String::from_utf8(value)?));
+            },
+            None => attrs.push((line.to_owned(), String::new())),
+        }
+    }
+
+    Ok(attrs)
+}
+
+pub fn remove_xattr(host: &str, path: &Path, name: &str) -> Result<()> {
+    run(host, &["setfattr", "-h", "-x", name, "--", path_str(path)?]).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_arguments() {
+        assert_eq!(quote("/tmp/a file"), "'/tmp/a file'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn refuses_hosts_like_options() {
+        let err = run("-oProxyCommand=true", &["true"]).unwrap_err();
+        assert!(err.to_string().contains("invalid host"));
+    }
+}
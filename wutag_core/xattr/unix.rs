@@ -15,10 +15,13 @@ use std::{
     ptr,
 };
 
+use super::follow_symlinks;
 use crate::{Error, Result};
 
+/// Whether the attributes are read and written on the symlink at `path` itself
+/// instead of on its target
 fn is_symlink(path: &Path) -> bool {
-    fs::metadata(path).map_or(false, |f| f.file_type().is_symlink())
+    !follow_symlinks() && fs::symlink_metadata(path).map_or(false, |f| f.file_type().is_symlink())
 }
 
 /// Sets the value of the extended attribute identified by `name` and associated